@@ -1,27 +1,68 @@
-//! Benchmark on a large video file.
+//! Throughput benchmarks of start code scanning, RBSP decoding and parameter set and slice
+//! segment header parsing.
 //!
-//! Expects a copy of [Big Buck Bunny](https://peach.blender.org/download/):
+//! The bundled corpus is the "Intinor HW encode 720x576p" VPS, SPS and PPS followed by intra
+//! pictures whose slice segment data is pseudo-random, as entropy coded data looks, with
+//! emulation prevention applied.
+//!
+//! To also benchmark a real stream, set `HEVC_BENCH_FILE` to an Annex B file, e.g. made from
+//! [Big Buck Bunny](https://peach.blender.org/download/):
 //! ```text
 //! $ curl -OL https://download.blender.org/peach/bigbuckbunny_movies/big_buck_bunny_1080p_h264.mov
-//! $ ffmpeg -i big_buck_bunny_1080p_h264.mov -c copy big_buck_bunny_1080p.h264
+//! $ ffmpeg -i big_buck_bunny_1080p_h264.mov -c:v libx265 big_buck_bunny_1080p.h265
+//! $ HEVC_BENCH_FILE=big_buck_bunny_1080p.h265 cargo bench
 //! ```
 
 #[macro_use]
 extern crate criterion;
 
-use criterion::{Bencher, Criterion, Throughput};
-use h264_reader::annexb::AnnexBReader;
-use h264_reader::nal::sei::SeiReader;
-use h264_reader::nal::slice::{SliceHeader, SliceHeaderError};
-use h264_reader::nal::sps::SeqParameterSet;
-use h264_reader::nal::UnitType;
-use h264_reader::nal::{Nal, RefNal};
-use h264_reader::push::NalFragmentHandler;
-use h264_reader::push::NalInterest;
-use h264_reader::rbsp::{self, BitReaderError};
+use criterion::{Bencher, BenchmarkGroup, Criterion, Throughput};
+use hevc_reader::annexb::{nal_units, AnnexBReader};
+use hevc_reader::nal::slice::SliceSegmentHeader;
+use hevc_reader::nal::sps::{SeqParameterSet, SpsHeader};
+use hevc_reader::nal::{Nal, NalHeader, RefNal};
+use hevc_reader::push::parse::{NalParser, ParseDepth, ParsedNal};
+use hevc_reader::push::{NalFragmentHandler, NalInterest};
+use hevc_reader::rbsp::{self, decode_nal, encode_nal};
+use hevc_reader::Context;
 use hex_literal::hex;
-use std::convert::TryFrom;
-use std::io::{BufRead, ErrorKind};
+use std::io::BufRead;
+
+const VPS: [u8; 24] =
+    hex!("40 01 0c 01 ff ff 01 60 00 00 03 00 90 00 00 03 00 00 03 00 5d 95 98 09");
+const SPS: [u8; 59] = hex!(
+    "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46 d1
+     2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00 0b
+     b8 48"
+);
+const PPS: [u8; 7] = hex!("44 01 c1 72 b4 62 40");
+/// The NAL unit header and slice segment header of an IDR picture using the PPS.
+const IDR: [u8; 4] = hex!("26 01 ac 9c");
+
+/// An Annex B stream of `pictures` intra pictures of `picture_size` bytes of RBSP each.
+fn corpus(pictures: usize, picture_size: usize) -> Vec<u8> {
+    let mut out = vec![];
+    for nal in [&VPS[..], &SPS, &PPS] {
+        out.extend_from_slice(b"\x00\x00\x00\x01");
+        out.extend_from_slice(nal);
+    }
+    // xorshift64, so the corpus is the same on every run.
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    for _ in 0..pictures {
+        let mut rbsp = IDR[2..].to_vec();
+        rbsp.extend((0..picture_size).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            // Make zero bytes common enough to need emulation prevention now and then.
+            (state >> 56) as u8 & if state & 0xf == 0 { 0 } else { 0xff }
+        }));
+        rbsp.push(0x80);
+        out.extend_from_slice(b"\x00\x00\x01");
+        out.extend_from_slice(&encode_nal(&IDR[..2], &rbsp));
+    }
+    out
+}
 
 /// A NAL handler that does nothing, except maintain counters to limit optimization.
 #[derive(Default)]
@@ -38,7 +79,7 @@ impl NalFragmentHandler for NullNalReader {
     }
 }
 
-fn bench_annexb<'a, H, P>(mut r: AnnexBReader<H>, b: &mut Bencher, pushes: P)
+fn bench_annexb<'a, H, P>(mut r: AnnexBReader<H>, b: &mut Bencher<'_>, pushes: P)
 where
     H: NalFragmentHandler,
     P: Iterator<Item = &'a [u8]> + Clone,
@@ -51,157 +92,83 @@ where
     })
 }
 
-fn h264_reader(c: &mut Criterion) {
-    let buf = std::fs::read("big_buck_bunny_1080p.h264").expect("reading h264 file failed");
-    let mut rbsp_len = 0;
+/// Benchmarks pushing `buf` in one push (as when reading with a large buffer size), 184-byte
+/// pushes (like MPEG-TS) and 1440-byte pushes (~typical for RTP), through start code scanning
+/// alone, RBSP decoding, and parsing to the slice segment headers.
+fn bench_stream(group: &mut BenchmarkGroup<'_, criterion::measurement::WallTime>, buf: &[u8]) {
+    group.throughput(Throughput::Bytes(buf.len() as u64));
+    group.bench_function("nal_units", |b| b.iter(|| nal_units(buf).count()));
+    #[cfg(feature = "rayon")]
+    group.bench_function("par_nal_units", |b| {
+        b.iter(|| hevc_reader::par::nal_units(buf).len())
+    });
+    let chunkings: [(&str, usize); 3] = [
+        ("onepush", buf.len()),
+        ("chunksize184", 184),
+        ("chunksize1440", 1440),
+    ];
+    for (name, size) in chunkings {
+        group.bench_function(format!("{}_null", name), |b| {
+            bench_annexb(
+                AnnexBReader::for_fragment_handler(NullNalReader::default()),
+                b,
+                buf.chunks(size),
+            )
+        });
+    }
+    let mut rbsp_len = 0u64;
     let mut rbsp_len_nal_handler = |nal: RefNal<'_>| {
         if nal.is_complete() {
             let mut r = nal.rbsp_bytes();
             loop {
-                let buf = r.fill_buf().unwrap();
-                let len = buf.len();
+                let len = r.fill_buf().unwrap().len();
                 if len == 0 {
                     break;
                 }
-                rbsp_len += u64::try_from(buf.len()).unwrap();
+                rbsp_len += len as u64;
                 r.consume(len);
             }
         }
         NalInterest::Buffer
     };
-    let mut parsing_ctx = h264_reader::Context::default();
-    let mut scratch = Vec::new();
-    let mut parsing_nal_handler = |nal: RefNal<'_>| {
-        let nal_hdr = nal.header().unwrap();
-        match nal_hdr.nal_unit_type() {
-            UnitType::SeqParameterSet if nal.is_complete() => {
-                let sps =
-                    h264_reader::nal::sps::SeqParameterSet::from_bits(nal.rbsp_bits()).unwrap();
-                parsing_ctx.put_seq_param_set(sps);
-            }
-            UnitType::PicParameterSet if nal.is_complete() => {
-                let pps = h264_reader::nal::pps::PicParameterSet::from_bits(
-                    &parsing_ctx,
-                    nal.rbsp_bits(),
-                )
-                .unwrap();
-                parsing_ctx.put_pic_param_set(pps);
-            }
-            UnitType::SEI if nal.is_complete() => {
-                let mut r = SeiReader::from_rbsp_bytes(nal.rbsp_bytes(), &mut scratch);
-                while let Some(msg) = r.next().unwrap() {
-                    match msg.payload_type {
-                        h264_reader::nal::sei::HeaderType::BufferingPeriod => {} // todo
-                        h264_reader::nal::sei::HeaderType::UserDataUnregistered => {} // todo
-                        _ => panic!("unknown SEI payload type {:?}", msg.payload_type),
-                    }
-                }
-            }
-            UnitType::SliceLayerWithoutPartitioningIdr
-            | UnitType::SliceLayerWithoutPartitioningNonIdr => {
-                match SliceHeader::from_bits(
-                    &parsing_ctx,
-                    &mut nal.rbsp_bits(),
-                    nal.header().unwrap(),
-                ) {
-                    Err(SliceHeaderError::RbspError(BitReaderError::ReaderErrorFor(_, e))) => {
-                        assert_eq!(e.kind(), ErrorKind::WouldBlock);
+    for (name, size) in chunkings {
+        group.bench_function(format!("{}_rbsp", name), |b| {
+            bench_annexb(
+                AnnexBReader::accumulate(&mut rbsp_len_nal_handler),
+                b,
+                buf.chunks(size),
+            )
+        });
+    }
+    let mut slices = 0u64;
+    for (name, size) in chunkings {
+        group.bench_function(format!("{}_parse", name), |b| {
+            let parser = NalParser::new(
+                ParseDepth::SliceHeaders,
+                |_: &Context, _: NalHeader, parsed: ParsedNal<'_>| {
+                    if let ParsedNal::SliceSegment(r) = parsed {
+                        r.unwrap();
+                        slices += 1;
                     }
-                    Err(e) => panic!("{:?}", e),
-                    Ok(_) => return NalInterest::Ignore,
-                }
-            }
-            _ => {
-                if nal.is_complete() {
-                    panic!("unknown slice type {:?}", nal_hdr)
-                }
-            }
-        }
-        NalInterest::Buffer
-    };
-
-    let mut group = c.benchmark_group("parse_annexb");
-    group.throughput(Throughput::Bytes(u64::try_from(buf.len()).unwrap()));
+                },
+            );
+            bench_annexb(AnnexBReader::accumulate(parser), b, buf.chunks(size))
+        });
+    }
+}
 
-    // Benchmark parsing in one big push (as when reading H.264 with a large buffer size),
-    // 184-byte pushes (like MPEG-TS), and 1440-byte pushes (~typical for RTP). RTP doesn't
-    // use Annex B encoding, but it does use the RBSP decoding and NAL parsing layers, so this
-    // is still informative.
-    group.bench_function("onepush_null", |b| {
-        bench_annexb(
-            AnnexBReader::for_fragment_handler(NullNalReader::default()),
-            b,
-            std::iter::once(&buf[..]),
-        )
-    });
-    group.bench_function("chunksize184_null", |b| {
-        bench_annexb(
-            AnnexBReader::for_fragment_handler(NullNalReader::default()),
-            b,
-            buf.chunks(184),
-        )
-    });
-    group.bench_function("chunksize1440_null", |b| {
-        bench_annexb(
-            AnnexBReader::for_fragment_handler(NullNalReader::default()),
-            b,
-            buf.chunks(1440),
-        )
-    });
-    group.bench_function("onepush_rbsp", |b| {
-        bench_annexb(
-            AnnexBReader::accumulate(&mut rbsp_len_nal_handler),
-            b,
-            std::iter::once(&buf[..]),
-        )
-    });
-    group.bench_function("chunksize184_rbsp", |b| {
-        bench_annexb(
-            AnnexBReader::accumulate(&mut rbsp_len_nal_handler),
-            b,
-            buf.chunks(184),
-        )
-    });
-    group.bench_function("chunksize1440_rbsp", |b| {
-        bench_annexb(
-            AnnexBReader::accumulate(&mut rbsp_len_nal_handler),
-            b,
-            buf.chunks(1440),
-        )
-    });
-    group.bench_function("onepush_parse", |b| {
-        bench_annexb(
-            AnnexBReader::accumulate(&mut parsing_nal_handler),
-            b,
-            std::iter::once(&buf[..]),
-        )
-    });
-    group.bench_function("chunksize184_parse", |b| {
-        bench_annexb(
-            AnnexBReader::accumulate(&mut parsing_nal_handler),
-            b,
-            buf.chunks(184),
-        )
-    });
-    group.bench_function("chunksize1440_parse", |b| {
-        bench_annexb(
-            AnnexBReader::accumulate(&mut parsing_nal_handler),
-            b,
-            buf.chunks(1440),
-        )
-    });
+fn parse_annexb(c: &mut Criterion) {
+    let buf = corpus(50, 20_000);
+    bench_stream(&mut c.benchmark_group("parse_annexb"), &buf);
+    if let Some(path) = std::env::var_os("HEVC_BENCH_FILE") {
+        let buf = std::fs::read(path).expect("reading HEVC_BENCH_FILE failed");
+        bench_stream(&mut c.benchmark_group("parse_annexb_file"), &buf);
+    }
 }
 
 fn parse_nal(c: &mut Criterion) {
-    let sps = hex!(
-        "67 64 00 16 AC 1B 1A 80 B0 3D FF FF
-        00 28 00 21 6E 0C 0C 0C 80 00 01
-        F4 00 00 27 10 74 30 07 D0 00 07
-        A1 25 DE 5C 68 60 0F A0 00 0F 42
-        4B BC B8 50"
-    );
-    let rbsp = h264_reader::rbsp::decode_nal(&sps).unwrap();
-    let nal = RefNal::new(&sps[..], &[], true);
+    let rbsp = decode_nal(&SPS).unwrap();
+    let nal = RefNal::new(&SPS[..], &[], true);
     let mut group = c.benchmark_group("parse_nal");
     group.bench_function("rbsp_sps", |b| {
         b.iter(|| SeqParameterSet::from_bits(rbsp::BitReader::new(&*rbsp)).unwrap())
@@ -209,7 +176,25 @@ fn parse_nal(c: &mut Criterion) {
     group.bench_function("nal_sps", |b| {
         b.iter(|| SeqParameterSet::from_bits(nal.rbsp_bits()).unwrap())
     });
+    group.bench_function("nal_sps_header", |b| {
+        b.iter(|| SpsHeader::from_bits(nal.rbsp_bits()).unwrap())
+    });
+    group.bench_function("decode_nal_sps", |b| b.iter(|| decode_nal(&SPS).unwrap()));
+
+    let mut ctx = Context::new();
+    ctx.put_seq_param_set(SeqParameterSet::from_bits(nal.rbsp_bits()).unwrap());
+    let pps = RefNal::new(&PPS[..], &[], true);
+    ctx.put_pic_param_set(
+        hevc_reader::nal::pps::PicParameterSet::from_bits(&ctx, pps.rbsp_bits()).unwrap(),
+    );
+    let idr = corpus(1, 1000);
+    let idr = nal_units(&idr).last().unwrap().1;
+    let idr = RefNal::new(idr, &[], true);
+    let header = idr.header().unwrap();
+    group.bench_function("nal_slice_header", |b| {
+        b.iter(|| SliceSegmentHeader::from_bits(&ctx, &mut idr.rbsp_bits(), header).unwrap())
+    });
 }
 
-criterion_group!(benches, h264_reader, parse_nal);
+criterion_group!(benches, parse_annexb, parse_nal);
 criterion_main!(benches);
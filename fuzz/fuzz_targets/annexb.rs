@@ -2,19 +2,19 @@
 //! single push call and a pair of push split at each possible byte location.
 
 #![no_main]
-use h264_reader::annexb::AnnexBReader;
+use hevc_reader::annexb::AnnexBReader;
 use hex_slice::AsHex;
 use libfuzzer_sys::fuzz_target;
 use std::convert::TryFrom;
 
-/// Encodes the stream as (4-byte length prefix, NAL)*, as commonly seen in AVC files.
+/// Encodes the stream as (4-byte length prefix, NAL)*, as commonly seen in MP4 files.
 #[derive(Default)]
 struct AvcBuilder {
     cur: Vec<u8>,
     all: Vec<u8>,
 }
 
-impl h264_reader::push::NalFragmentHandler for AvcBuilder {
+impl hevc_reader::push::NalFragmentHandler for AvcBuilder {
     fn nal_fragment(&mut self, bufs: &[&[u8]], end: bool) {
         assert!(!bufs.is_empty() || (!self.cur.is_empty() || end));
         for buf in bufs {
@@ -47,15 +47,17 @@ fuzz_target!(|data: &[u8]| {
         split_push.reset();
         let split_avc = split_push.into_fragment_handler();
 
-        assert!(single_avc.all.as_slice() == split_avc.all.as_slice(),
-                "inconsistent output.\n\
+        assert!(
+            single_avc.all.as_slice() == split_avc.all.as_slice(),
+            "inconsistent output.\n\
                 split point: {}\n\
                 input:       {:02x}\n\
                 single push: {:02x}\n\
                 split push:  {:02x}",
-                i,
-                data.as_hex(),
-                single_avc.all.as_hex(),
-                split_avc.all.as_hex());
+            i,
+            data.as_hex(),
+            single_avc.all.as_hex(),
+            split_avc.all.as_hex()
+        );
     }
 });
@@ -0,0 +1,26 @@
+//! Parses a PPS against an arbitrary SPS, which needn't be one the SPS parser would produce.
+
+#![no_main]
+use arbitrary::Arbitrary;
+use hevc_reader::nal::pps::PicParameterSet;
+use hevc_reader::nal::sps::SeqParameterSet;
+use hevc_reader::rbsp::BitReader;
+use hevc_reader::Context;
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Arbitrary, Debug)]
+struct Input<'a> {
+    sps: SeqParameterSet,
+    pps: &'a [u8],
+}
+
+fuzz_target!(|input: Input<'_>| {
+    let mut ctx = Context::new();
+    ctx.put_seq_param_set(input.sps);
+    let Ok(pps) = PicParameterSet::from_bits(&ctx, BitReader::new(input.pps)) else {
+        return;
+    };
+    let sps = ctx.sps_by_id(pps.seq_parameter_set_id).unwrap();
+    let _ = pps.tile_scan(sps);
+    let _ = pps.screen_content_tools(sps);
+});
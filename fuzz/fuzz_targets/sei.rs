@@ -0,0 +1,35 @@
+//! Reads the messages of an SEI RBSP, and the payloads of the message types with parsers.
+
+#![no_main]
+use arbitrary::Arbitrary;
+use hevc_reader::nal::sei::content_light_level::ContentLightLevelInfo;
+use hevc_reader::nal::sei::mastering_display_colour_volume::MasteringDisplayColourVolume;
+use hevc_reader::nal::sei::pic_timing::PicTiming;
+use hevc_reader::nal::sei::{HeaderType, SeiReader};
+use hevc_reader::nal::sps::SeqParameterSet;
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Arbitrary, Debug)]
+struct Input<'a> {
+    /// The active SPS, which `pic_timing()` depends on.
+    sps: SeqParameterSet,
+    rbsp: &'a [u8],
+}
+
+fuzz_target!(|input: Input<'_>| {
+    let mut reader = SeiReader::from_rbsp_bytes(input.rbsp);
+    while let Ok(Some(msg)) = reader.next() {
+        match msg.payload_type {
+            HeaderType::PicTiming => {
+                let _ = PicTiming::read(&input.sps, &msg);
+            }
+            HeaderType::MasteringDisplayColourVolume => {
+                let _ = MasteringDisplayColourVolume::read(&msg);
+            }
+            HeaderType::ContentLightLevelInfo => {
+                let _ = ContentLightLevelInfo::read(&msg);
+            }
+            _ => {}
+        }
+    }
+});
@@ -0,0 +1,42 @@
+//! Parses a slice segment header against an arbitrary SPS and PPS. The SPS's short-term
+//! reference picture sets are arbitrary too, so slice headers predicting from them reach the
+//! RPS prediction path with untrusted indices.
+
+#![no_main]
+use arbitrary::Arbitrary;
+use hevc_reader::nal::pps::PicParameterSet;
+use hevc_reader::nal::slice::SliceSegmentHeader;
+use hevc_reader::nal::sps::SeqParameterSet;
+use hevc_reader::nal::NalHeader;
+use hevc_reader::rbsp::BitReader;
+use hevc_reader::Context;
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Arbitrary, Debug)]
+struct Input<'a> {
+    sps: SeqParameterSet,
+    pps: PicParameterSet,
+    /// The NAL unit header.
+    header: [u8; 2],
+    slice: &'a [u8],
+}
+
+fuzz_target!(|input: Input<'_>| {
+    let Ok(header) = NalHeader::new(input.header[0], Some(input.header[1])) else {
+        return;
+    };
+    if !header.nal_unit_type().is_vcl() {
+        return;
+    }
+    let mut ctx = Context::new();
+    let mut pps = input.pps;
+    pps.seq_parameter_set_id = input.sps.sps_seq_parameter_set_id;
+    ctx.put_seq_param_set(input.sps);
+    ctx.put_pic_param_set(pps);
+    let mut r = BitReader::new(input.slice);
+    if let Ok((slice, sps, pps)) = SliceSegmentHeader::from_bits(&ctx, &mut r, header) {
+        if let Some(header) = &slice.slice_header {
+            let _ = header.num_pic_total_curr(sps, pps);
+        }
+    }
+});
@@ -0,0 +1,19 @@
+//! Parses an SPS, and checks that what's derived from it doesn't panic and that the partial
+//! parse agrees with the full one.
+
+#![no_main]
+use hevc_reader::nal::sps::{SeqParameterSet, SpsHeader};
+use hevc_reader::rbsp::BitReader;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(sps) = SeqParameterSet::from_bits(BitReader::new(data)) else {
+        return;
+    };
+    let _ = sps.pixel_dimensions();
+    let _ = sps.derived();
+    let _ = sps.sub_layer_frame_durations();
+    let header = SpsHeader::from_bits(BitReader::new(data)).unwrap();
+    assert_eq!(header.profile_tier_level, sps.profile_tier_level);
+    assert_eq!(header.pixel_dimensions().ok(), sps.pixel_dimensions().ok());
+});
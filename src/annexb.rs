@@ -80,6 +80,8 @@ struct InUnitState {
 /// Annex B stream, regardless of boundaries of `AnnexBReader::push` calls.
 pub struct AnnexBReader<H: NalFragmentHandler> {
     state: ParseState,
+    /// True until the first start code, when starting mid-stream.
+    resyncing: bool,
     inner: H,
 }
 impl<H: AccumulatedNalHandler> AnnexBReader<NalAccumulator<H>> {
@@ -108,10 +110,18 @@ impl<H: NalFragmentHandler> AnnexBReader<H> {
     pub fn for_fragment_handler(inner: H) -> Self {
         AnnexBReader {
             state: ParseState::Start,
+            resyncing: false,
             inner,
         }
     }
 
+    /// Skips the data before the first start code without logging it as corrupt, for reading
+    /// from an arbitrary offset into a stream, where the data starts within a NAL unit.
+    pub fn starting_mid_stream(mut self) -> Self {
+        self.resyncing = true;
+        self
+    }
+
     /// Gets a reference to the underlying [NalFragmentHandler].
     pub fn fragment_handler_ref(&self) -> &H {
         &self.inner
@@ -151,6 +161,7 @@ impl<H: NalFragmentHandler> AnnexBReader<H> {
                         0x00 => (), // keep ignoring further 0x00 bytes
                         0x01 => {
                             fake_and_start = Some((0, i + 1));
+                            self.resyncing = false;
                             self.to(ParseState::InUnit);
                         }
                         _ => self.err(b),
@@ -158,7 +169,7 @@ impl<H: NalFragmentHandler> AnnexBReader<H> {
                 }
                 ParseState::InUnit => {
                     let remaining = &buf[i..];
-                    match memchr::memchr(0x00, remaining) {
+                    match find_start_code_candidate(remaining) {
                         Some(pos) => {
                             self.to(ParseState::InUnitOneZero);
                             i += pos;
@@ -251,6 +262,10 @@ impl<H: NalFragmentHandler> AnnexBReader<H> {
     }
 
     fn err(&mut self, b: u8) {
+        if self.resyncing {
+            self.state = ParseState::Start;
+            return;
+        }
         error!(
             "AnnexBReader: state={:?}, invalid byte {:#x}",
             self.state, b
@@ -259,6 +274,214 @@ impl<H: NalFragmentHandler> AnnexBReader<H> {
     }
 }
 
+/// Drives an [`AnnexBReader`] from an [`std::io::Read`] source such as a file, one buffer at a
+/// time, so the whole stream never needs to be in memory.
+///
+/// ```
+/// use hevc_reader::annexb::{AnnexBReader, ReadAdapter};
+/// use hevc_reader::nal::{Nal, RefNal};
+/// use hevc_reader::push::NalInterest;
+/// let data = &b"\x00\x00\x00\x01\x40\x01\x0c\x00\x00\x01\x42\x01\x01"[..];
+/// let mut types = vec![];
+/// let reader = AnnexBReader::accumulate(|nal: RefNal<'_>| {
+///     if nal.is_complete() {
+///         types.push(nal.header().unwrap().nal_unit_type().id());
+///     }
+///     NalInterest::Buffer
+/// });
+/// let mut adapter = ReadAdapter::with_capacity(data, reader, 4);
+/// assert_eq!(adapter.read_to_end().unwrap(), 13);
+/// drop(adapter);
+/// assert_eq!(types, [32, 33]);
+/// ```
+pub struct ReadAdapter<R: std::io::Read, H: NalFragmentHandler> {
+    inner: R,
+    reader: AnnexBReader<H>,
+    buf: Box<[u8]>,
+}
+impl<R: std::io::Read, H: NalFragmentHandler> ReadAdapter<R, H> {
+    /// Creates an adapter reading 64 KiB at a time.
+    pub fn new(inner: R, reader: AnnexBReader<H>) -> Self {
+        Self::with_capacity(inner, reader, 64 * 1024)
+    }
+
+    /// Creates an adapter reading up to `capacity` bytes at a time.
+    pub fn with_capacity(inner: R, reader: AnnexBReader<H>, capacity: usize) -> Self {
+        assert!(capacity > 0);
+        ReadAdapter {
+            inner,
+            reader,
+            buf: vec![0; capacity].into_boxed_slice(),
+        }
+    }
+
+    /// Reads and pushes the next buffer of data, returning the number of bytes read. At the end
+    /// of the input, returns 0 and completes the last NAL unit with [`AnnexBReader::reset`].
+    pub fn read_chunk(&mut self) -> std::io::Result<usize> {
+        let n = loop {
+            match self.inner.read(&mut self.buf) {
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                result => break result?,
+            }
+        };
+        if n == 0 {
+            self.reader.reset();
+        } else {
+            self.reader.push(&self.buf[..n]);
+        }
+        Ok(n)
+    }
+
+    /// Pushes everything up to the end of the input, returning the number of bytes read.
+    pub fn read_to_end(&mut self) -> std::io::Result<u64> {
+        let mut total = 0;
+        loop {
+            match self.read_chunk()? {
+                0 => return Ok(total),
+                n => total += n as u64,
+            }
+        }
+    }
+
+    /// Gets a reference to the [`AnnexBReader`].
+    pub fn reader_ref(&self) -> &AnnexBReader<H> {
+        &self.reader
+    }
+
+    /// Gets a mutable reference to the [`AnnexBReader`].
+    pub fn reader_mut(&mut self) -> &mut AnnexBReader<H> {
+        &mut self.reader
+    }
+
+    /// Unwraps the adapter, returning the input and the [`AnnexBReader`].
+    pub fn into_inner(self) -> (R, AnnexBReader<H>) {
+        (self.inner, self.reader)
+    }
+}
+
+/// Splits a complete, in-memory Annex B byte stream into its NAL units.
+///
+/// Yields the byte offset of each NAL unit within `data` (just past its start code) along
+/// with its bytes, excluding any trailing zero bytes. Bytes before the first start code are
+/// skipped.
+///
+/// ```
+/// use hevc_reader::annexb::nal_units;
+/// let data = b"\x00\x00\x00\x01\x40\x01\x0c\x00\x00\x01\x42\x01\x01\x00";
+/// let nals: Vec<_> = nal_units(&data[..]).collect();
+/// assert_eq!(nals, vec![(4, &b"\x40\x01\x0c"[..]), (10, &b"\x42\x01\x01"[..])]);
+/// ```
+pub fn nal_units(data: &[u8]) -> NalUnits<'_> {
+    NalUnits { data, pos: 0 }
+}
+
+/// Iterator returned by [`nal_units`].
+#[derive(Clone, Debug)]
+pub struct NalUnits<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+impl<'a> NalUnits<'a> {
+    /// Returns the index just past the next `00 00 01` start code at or after `from`.
+    fn find_start(&self, from: usize) -> Option<usize> {
+        let mut i = from;
+        while i < self.data.len() {
+            let pos = i + memchr::memchr(0x01, &self.data[i..])?;
+            if pos >= 2 && self.data[pos - 2] == 0x00 && self.data[pos - 1] == 0x00 {
+                return Some(pos + 1);
+            }
+            i = pos + 1;
+        }
+        None
+    }
+}
+impl<'a> Iterator for NalUnits<'a> {
+    type Item = (usize, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let start = self.find_start(self.pos)?;
+            let end = match self.find_start(start) {
+                Some(next) => next - 3,
+                None => self.data.len(),
+            };
+            self.pos = end;
+            let mut nal = &self.data[start..end];
+            while let [rest @ .., 0x00] = nal {
+                nal = rest;
+            }
+            if !nal.is_empty() {
+                return Some((start, nal));
+            }
+        }
+    }
+}
+
+/// How a NAL unit was framed in an Annex B byte stream, as found by [`framed_nal_units`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Framing {
+    /// True if the start code had four bytes (`zero_byte` followed by `00 00 01`), as required
+    /// before parameter sets and the first NAL unit of each access unit.
+    pub zero_byte: bool,
+    /// The number of zero bytes after the NAL unit, not counting the `zero_byte` of the next
+    /// start code (`trailing_zero_8bits`).
+    pub trailing_zeros: usize,
+}
+
+/// Like [`nal_units`], but also yields the [`Framing`] of each NAL unit.
+///
+/// A run of zero bytes before a start code is split between the trailing zeros of the NAL unit
+/// before and the `zero_byte` of the one after.
+///
+/// ```
+/// use hevc_reader::annexb::{framed_nal_units, Framing};
+/// let data = b"\x00\x00\x00\x01\x40\x01\x0c\x00\x00\x00\x00\x01\x42\x01\x01\x00\x00\x01\x44\x01";
+/// let framing: Vec<_> = framed_nal_units(&data[..]).map(|(_, _, f)| f).collect();
+/// assert_eq!(
+///     framing,
+///     [
+///         Framing { zero_byte: true, trailing_zeros: 1 },
+///         Framing { zero_byte: true, trailing_zeros: 0 },
+///         Framing { zero_byte: false, trailing_zeros: 0 },
+///     ]
+/// );
+/// ```
+pub fn framed_nal_units(data: &[u8]) -> impl Iterator<Item = (usize, &[u8], Framing)> {
+    let zero_byte = move |offset: usize| offset >= 4 && data[offset - 4] == 0x00;
+    let mut nals = nal_units(data).peekable();
+    std::iter::from_fn(move || {
+        let (offset, nal) = nals.next()?;
+        let end = match nals.peek() {
+            Some(&(next, _)) => next - 3 - usize::from(zero_byte(next)),
+            None => data.len(),
+        };
+        let framing = Framing {
+            zero_byte: zero_byte(offset),
+            trailing_zeros: end - offset - nal.len(),
+        };
+        Some((offset, nal, framing))
+    })
+}
+
+/// Returns the index of the first `0x00` in `buf` which may begin a start code or a trailing
+/// zero: one followed by `00 00` or `00 01`, or among the last two bytes, where what follows is
+/// in the next buffer.
+///
+/// Zero bytes are found with `memchr`, and the two bytes after each checked to skip past it and
+/// any second zero at once, so the scan runs at memory speed through slice segment data.
+fn find_start_code_candidate(buf: &[u8]) -> Option<usize> {
+    let mut i = 0;
+    while i < buf.len() {
+        let pos = i + memchr::memchr(0x00, &buf[i..])?;
+        match buf.get(pos + 1..pos + 3) {
+            None | Some([0x00, 0x00..=0x01]) => return Some(pos),
+            Some([0x00, _]) => i = pos + 3,
+            Some(_) => i = pos + 2,
+        }
+    }
+    None
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -281,11 +504,27 @@ mod tests {
         }
     }
 
+    #[test]
+    fn read_adapter() {
+        // Reads of at most three bytes split the start codes and NAL units every which way.
+        let data = hex!("00 00 00 01 40 01 0c 00 00 03 01 00 00 01 42 01 00 00 00 01 44");
+        for capacity in 1..4 {
+            let r = AnnexBReader::for_fragment_handler(MockFragmentHandler::default());
+            let mut adapter = ReadAdapter::with_capacity(&data[..], r, capacity);
+            assert_eq!(adapter.read_to_end().unwrap(), data.len() as u64);
+            let (rest, r) = adapter.into_inner();
+            assert!(rest.is_empty());
+            let mock = r.into_fragment_handler();
+            assert_eq!(mock.data, hex!("40 01 0c 00 00 03 01 42 01 44"));
+            assert_eq!(mock.ended, 3);
+        }
+    }
+
     #[test]
     fn short_nal() {
         let mock = MockFragmentHandler::default();
         let mut r = AnnexBReader::for_fragment_handler(mock);
-        let data = vec![
+        let data = [
             0, 0, 0, 1, // start-code
             3, // NAL data. Shorter than expected for a H265 NAL.
             0, 0, 1, // end-code
@@ -315,7 +554,7 @@ mod tests {
     fn short_start_code() {
         let mock = MockFragmentHandler::default();
         let mut r = AnnexBReader::for_fragment_handler(mock);
-        let data = vec![
+        let data = [
             0, 0, 1, // start-code -- only three bytes rather than the usual 4
             3, 4, // NAL data
             0, 0, 1, // end-code
@@ -390,7 +629,7 @@ mod tests {
     fn implicit_end() {
         let mock = MockFragmentHandler::default();
         let mut r = AnnexBReader::for_fragment_handler(mock);
-        let data = vec![
+        let data = [
             0, 0, 0, 1, // start-code
             3, 4, 0, // NAL data
         ];
@@ -420,6 +659,20 @@ mod tests {
         assert_eq!(1, mock.ended);
     }
 
+    #[test]
+    fn start_code_candidate() {
+        assert_eq!(
+            find_start_code_candidate(&hex!("12 00 34 00 00 03 00 00 01")),
+            Some(6)
+        );
+        assert_eq!(
+            find_start_code_candidate(&hex!("00 00 03 00 00 00")),
+            Some(3)
+        );
+        assert_eq!(find_start_code_candidate(&hex!("00 00 03 01 00")), Some(4));
+        assert_eq!(find_start_code_candidate(&hex!("00 80 00 00 03")), None);
+    }
+
     #[test]
     fn split_large() {
         let data = hex!(
@@ -508,8 +761,8 @@ mod tests {
             let mock = MockFragmentHandler::default();
             let mut r = AnnexBReader::for_fragment_handler(mock);
             let (head, tail) = data.split_at(i);
-            r.push(&head[..]);
-            r.push(&tail[..]);
+            r.push(head);
+            r.push(tail);
             r.reset();
             let mock = r.into_fragment_handler();
             assert_eq!(3, mock.ended);
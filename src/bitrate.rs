@@ -0,0 +1,169 @@
+//! Bit rate measurement over a sliding window of time.
+//!
+//! Level limits, HRD parameters and delivery capacity are all about the bits arriving within a
+//! window of time rather than the average over a whole stream. A [`BitRateMeter`] is given the
+//! size and timing of each access unit (or any other unit of data), and reports both.
+
+/// The slack allowed in comparing times, in seconds, so that e.g. 25 pictures at 25 Hz make up
+/// exactly one second.
+const EPSILON: f64 = 1e-9;
+
+/// What a [`BitRateMeter`] found.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BitRateReport {
+    /// The length of the windows in seconds: as configured, or the whole duration if shorter.
+    pub window: f64,
+    /// From the start of the first unit to the end of the last one, in seconds.
+    pub duration: f64,
+    pub total_bits: u64,
+    /// The bit rate over the whole duration, in bits per second.
+    pub average: f64,
+    /// The largest bit rate of any one window, in bits per second.
+    pub peak: f64,
+    /// The time at which the window with the peak bit rate starts, in seconds.
+    pub peak_start: f64,
+}
+
+/// Measures the bit rate of units of data each given a time and duration, in order of time.
+///
+/// Windows start at the start of each unit, and count the units starting within them.
+///
+/// ```
+/// use hevc_reader::bitrate::BitRateMeter;
+/// let mut meter = BitRateMeter::new(1.0);
+/// // Two seconds at 25 Hz, with the pictures of the second one twice as large.
+/// for i in 0..50 {
+///     meter.push(i as f64 / 25.0, 1.0 / 25.0, if i < 25 { 40_000 } else { 80_000 });
+/// }
+/// let report = meter.report().unwrap();
+/// assert_eq!(report.average, 1_500_000.0);
+/// assert_eq!(report.peak, 2_000_000.0);
+/// assert_eq!(report.peak_start, 1.0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct BitRateMeter {
+    window: f64,
+    /// The start time and size in bits of each unit.
+    units: Vec<(f64, u64)>,
+    end: f64,
+}
+impl Default for BitRateMeter {
+    /// One second windows.
+    fn default() -> Self {
+        Self::new(1.0)
+    }
+}
+impl BitRateMeter {
+    /// Measures over windows of `window` seconds.
+    pub fn new(window: f64) -> Self {
+        BitRateMeter {
+            window,
+            units: vec![],
+            end: 0.0,
+        }
+    }
+
+    /// Measures pictures at a constant rate, given their sizes in bits in decoding order.
+    pub fn with_picture_rate(
+        window: f64,
+        pictures_per_second: f64,
+        picture_bits: impl IntoIterator<Item = u64>,
+    ) -> Self {
+        let mut meter = Self::new(window);
+        for (i, bits) in picture_bits.into_iter().enumerate() {
+            meter.push(
+                i as f64 / pictures_per_second,
+                1.0 / pictures_per_second,
+                bits,
+            );
+        }
+        meter
+    }
+
+    /// Adds a unit of `bits` starting at `time` and lasting `duration` seconds. Units must be
+    /// added in order of time.
+    pub fn push(&mut self, time: f64, duration: f64, bits: u64) {
+        self.units.push((time, bits));
+        self.end = self.end.max(time + duration);
+    }
+
+    /// The measurement of the units added so far, or `None` if they take no time.
+    pub fn report(&self) -> Option<BitRateReport> {
+        let start = self.units.first()?.0;
+        let duration = self.end - start;
+        if duration <= 0.0 || self.window <= 0.0 {
+            return None;
+        }
+        let window = if duration > self.window - EPSILON {
+            self.window
+        } else {
+            duration
+        };
+        let total_bits: u64 = self.units.iter().map(|&(_, bits)| bits).sum();
+        let (mut peak_bits, mut peak_start) = (0, start);
+        let (mut end, mut bits) = (0, 0);
+        for (i, &(time, _)) in self.units.iter().enumerate() {
+            // Only whole windows count.
+            if time + window > self.end + EPSILON {
+                break;
+            }
+            // A window always holds the unit it starts with, however short it is.
+            while end < self.units.len()
+                && (end <= i || self.units[end].0 < time + window - EPSILON)
+            {
+                bits += self.units[end].1;
+                end += 1;
+            }
+            if bits > peak_bits {
+                (peak_bits, peak_start) = (bits, time);
+            }
+            bits -= self.units[i].1;
+        }
+        Some(BitRateReport {
+            window,
+            duration,
+            total_bits,
+            average: total_bits as f64 / duration,
+            peak: peak_bits as f64 / window,
+            peak_start,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn windows() {
+        // Half a second at 10 Hz is shorter than the window.
+        let meter = BitRateMeter::with_picture_rate(1.0, 10.0, [100; 5]);
+        let report = meter.report().unwrap();
+        assert_eq!(report.window, 0.5);
+        assert_eq!(report.peak, 1000.0);
+        assert_eq!(report.average, 1000.0);
+
+        // A burst straddling whole seconds, found by a window starting between them.
+        let mut meter = BitRateMeter::new(1.0);
+        for i in 0..30 {
+            let bits = if (12..18).contains(&i) { 1000 } else { 0 };
+            meter.push(f64::from(i) / 10.0, 0.1, bits);
+        }
+        let report = meter.report().unwrap();
+        assert_eq!(report.peak, 6000.0);
+        assert_eq!(report.peak_start, 0.8);
+        assert_eq!(report.total_bits, 6000);
+        assert_eq!(report.average, 2000.0);
+        assert_eq!(BitRateMeter::new(1.0).report(), None);
+    }
+
+    #[test]
+    fn tiny_window() {
+        // A window no longer than the slack in comparing times.
+        let mut meter = BitRateMeter::new(1.0);
+        meter.push(0.0, 1e-12, 8);
+        let report = meter.report().unwrap();
+        assert_eq!(report.total_bits, 8);
+        assert_eq!(report.peak, 8.0 / 1e-12);
+    }
+}
@@ -0,0 +1,507 @@
+//! Common Encryption (ISO/IEC 23001-7) support for samples of length-prefixed NAL units.
+//!
+//! With subsample encryption, each VCL NAL unit's length prefix, NAL header and slice segment
+//! header stay clear and only its slice segment data is protected. All other NAL units are
+//! entirely clear. [`SubsampleMapper`] computes such subsample maps when packaging, and
+//! [`ProtectedSampleParser`] uses them to parse the clear parts of encrypted samples.
+
+use crate::hvcc::SampleError;
+use crate::nal::slice::{SliceHeaderError, SliceSegmentHeader};
+use crate::nal::{NalHeader, NalHeaderError};
+use crate::rbsp::{BitReader, BitReaderError};
+use crate::Context;
+use std::io::ErrorKind;
+
+#[derive(Debug)]
+pub enum SubsampleError {
+    Sample(SampleError),
+    BadNalHeader(NalHeaderError),
+    /// The slice segment header had to be parsed to find where the slice segment data starts,
+    /// and that failed.
+    ///
+    /// This happens when the parameter sets it refers to haven't been given to the mapper.
+    BadSliceHeader(SliceHeaderError),
+    /// The subsample map doesn't cover exactly the sample.
+    MapLengthMismatch {
+        map_len: usize,
+        sample_len: usize,
+    },
+}
+impl SubsampleError {
+    /// See [`crate::error_code`].
+    pub fn code(&self) -> u32 {
+        crate::error_code::SUBSAMPLE
+            + match self {
+                SubsampleError::Sample(..) => 1,
+                SubsampleError::BadNalHeader(..) => 2,
+                SubsampleError::BadSliceHeader(..) => 3,
+                SubsampleError::MapLengthMismatch { .. } => 4,
+            }
+    }
+}
+impl From<SampleError> for SubsampleError {
+    fn from(e: SampleError) -> Self {
+        SubsampleError::Sample(e)
+    }
+}
+
+/// The protection scheme, as signalled by the `schm` box's `scheme_type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheme {
+    /// AES-CTR full sample encryption.
+    Cenc,
+    /// AES-CBC full sample encryption.
+    Cbc1,
+    /// AES-CTR pattern encryption.
+    Cens,
+    /// AES-CBC pattern encryption, as used by HLS.
+    Cbcs,
+}
+impl Scheme {
+    pub fn from_fourcc(fourcc: [u8; 4]) -> Option<Scheme> {
+        match &fourcc {
+            b"cenc" => Some(Scheme::Cenc),
+            b"cbc1" => Some(Scheme::Cbc1),
+            b"cens" => Some(Scheme::Cens),
+            b"cbcs" => Some(Scheme::Cbcs),
+            _ => None,
+        }
+    }
+
+    /// True if the protected range of each subsample must be a whole number of 16 byte blocks.
+    ///
+    /// In `cbcs`, a partial block at the end of the range is left unencrypted instead.
+    pub fn block_aligned(self) -> bool {
+        self != Scheme::Cbcs
+    }
+}
+
+/// One entry of a sample's subsample map, as in the `senc` box.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subsample {
+    pub bytes_of_clear_data: u16,
+    pub bytes_of_protected_data: u32,
+}
+
+/// Computes subsample maps for the samples of a track.
+///
+/// The parameter sets from the `hvcC` box must be given with [`SubsampleMapper::add_param_set`]
+/// before mapping samples; parameter sets within samples are picked up automatically.
+///
+/// ```
+/// use hevc_reader::cenc::{Scheme, Subsample, SubsampleMapper};
+/// # let sps = [
+/// #     0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03, 0x00, 0x00,
+/// #     0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46, 0xd1, 0x2e,
+/// #     0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10, 0x00, 0x00, 0x03,
+/// #     0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00, 0x0b, 0xb8, 0x48,
+/// # ];
+/// let mut mapper = SubsampleMapper::new(Scheme::Cenc, 4).unwrap();
+/// mapper.add_param_set(&sps);
+/// mapper.add_param_set(&[0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40]);
+/// // An IDR slice segment with a two byte header and 100 bytes of slice segment data.
+/// let mut sample = vec![0, 0, 0, 104, 0x26, 0x01, 0xac, 0x9c];
+/// sample.resize(108, 0xaa);
+/// assert_eq!(
+///     mapper.map_sample(&sample).unwrap(),
+///     [Subsample { bytes_of_clear_data: 12, bytes_of_protected_data: 96 }]
+/// );
+/// ```
+pub struct SubsampleMapper {
+    scheme: Scheme,
+    length_size: usize,
+    ctx: Context,
+}
+impl SubsampleMapper {
+    /// Creates a mapper for samples with `length_size` byte NAL unit lengths.
+    pub fn new(scheme: Scheme, length_size: usize) -> Result<Self, SampleError> {
+        // Validates the length size.
+        crate::hvcc::nal_units(&[], length_size)?;
+        Ok(SubsampleMapper {
+            scheme,
+            length_size,
+            ctx: Context::new(),
+        })
+    }
+
+    /// Keeps a VPS, SPS or PPS NAL unit, which slice segment headers need to be parsed. Other
+    /// NAL units, and parameter sets that fail to parse, are ignored.
+    pub fn add_param_set(&mut self, nal: &[u8]) {
+        track_param_set(&mut self.ctx, nal);
+    }
+
+    /// Returns the subsample map of one sample.
+    ///
+    /// Clear bytes are accumulated into the next protected range, so each entry but possibly
+    /// the last has protected data; runs of more than 65535 clear bytes are split over several
+    /// entries.
+    pub fn map_sample(&mut self, sample: &[u8]) -> Result<Vec<Subsample>, SubsampleError> {
+        let mut map = vec![];
+        let mut clear = 0;
+        for nal in crate::hvcc::nal_units(sample, self.length_size)? {
+            let nal = nal?;
+            clear += self.length_size;
+            let [b0, b1, ..] = *nal else {
+                return Err(SubsampleError::BadNalHeader(
+                    NalHeaderError::IncompleteHeader,
+                ));
+            };
+            let header = NalHeader::new(b0, Some(b1)).map_err(SubsampleError::BadNalHeader)?;
+            if !header.nal_unit_type().is_vcl() {
+                track_param_set(&mut self.ctx, nal);
+                clear += nal.len();
+                continue;
+            }
+            let header_len = slice_segment_header_len(&self.ctx, header, nal)?;
+            let mut protected = nal.len() - header_len;
+            if self.scheme.block_aligned() {
+                protected &= !15;
+            }
+            clear += nal.len() - protected;
+            if protected > 0 {
+                push_subsample(&mut map, clear, protected as u32);
+                clear = 0;
+            }
+        }
+        if clear > 0 {
+            push_subsample(&mut map, clear, 0);
+        }
+        Ok(map)
+    }
+}
+
+/// Parses the clear parts of encrypted samples, given their subsample maps, so that protected
+/// content can still be inspected and indexed.
+///
+/// As with [`SubsampleMapper`], the parameter sets from the `hvcC` box must be given first.
+///
+/// ```
+/// use hevc_reader::cenc::{ClearNalUnit, ProtectedSampleParser, Subsample};
+/// # let sps = [
+/// #     0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03, 0x00, 0x00,
+/// #     0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46, 0xd1, 0x2e,
+/// #     0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10, 0x00, 0x00, 0x03,
+/// #     0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00, 0x0b, 0xb8, 0x48,
+/// # ];
+/// let mut parser = ProtectedSampleParser::new(4).unwrap();
+/// parser.add_param_set(&sps);
+/// parser.add_param_set(&[0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40]);
+/// // An IDR slice segment whose data is encrypted.
+/// let mut sample = vec![0, 0, 0, 36, 0x26, 0x01, 0xac, 0x9c];
+/// sample.resize(40, 0x5a);
+/// let map = [Subsample { bytes_of_clear_data: 8, bytes_of_protected_data: 32 }];
+/// let nals = parser.parse_sample(&sample, &map).unwrap();
+/// let ClearNalUnit::SliceSegment { slice_header, .. } = &nals[0] else { panic!() };
+/// assert!(slice_header.first_slice_segment_in_pic_flag);
+/// ```
+pub struct ProtectedSampleParser {
+    length_size: usize,
+    ctx: Context,
+}
+impl ProtectedSampleParser {
+    /// Creates a parser for samples with `length_size` byte NAL unit lengths.
+    pub fn new(length_size: usize) -> Result<Self, SampleError> {
+        crate::hvcc::nal_units(&[], length_size)?;
+        Ok(ProtectedSampleParser {
+            length_size,
+            ctx: Context::new(),
+        })
+    }
+
+    /// Keeps a VPS, SPS or PPS NAL unit, see [`SubsampleMapper::add_param_set`].
+    pub fn add_param_set(&mut self, nal: &[u8]) {
+        track_param_set(&mut self.ctx, nal);
+    }
+
+    /// The parameter sets seen so far, including those in the clear within samples.
+    pub fn context(&self) -> &Context {
+        &self.ctx
+    }
+
+    /// Parses the NAL units of one sample, of which `map` describes the protected ranges.
+    ///
+    /// Slice segment headers are parsed from the clear bytes preceding the slice segment data;
+    /// NAL units whose clear part ends before the syntax that can be parsed are returned as
+    /// [`ClearNalUnit::Protected`].
+    pub fn parse_sample<'a>(
+        &mut self,
+        sample: &'a [u8],
+        map: &[Subsample],
+    ) -> Result<Vec<ClearNalUnit<'a>>, SubsampleError> {
+        let map_len: usize = map
+            .iter()
+            .map(|s| usize::from(s.bytes_of_clear_data) + s.bytes_of_protected_data as usize)
+            .sum();
+        if map_len != sample.len() {
+            return Err(SubsampleError::MapLengthMismatch {
+                map_len,
+                sample_len: sample.len(),
+            });
+        }
+        // The protected byte ranges, in ascending order.
+        let mut protected = vec![];
+        let mut pos = 0;
+        for s in map {
+            pos += usize::from(s.bytes_of_clear_data);
+            let end = pos + s.bytes_of_protected_data as usize;
+            if end > pos {
+                protected.push(pos..end);
+            }
+            pos = end;
+        }
+        let mut protected = protected.into_iter().peekable();
+        let mut nals = vec![];
+        let mut offset = 0;
+        for nal in crate::hvcc::nal_units(sample, self.length_size)? {
+            let nal = nal?;
+            let start = offset + self.length_size;
+            offset = start + nal.len();
+            while protected.next_if(|r| r.end <= start).is_some() {}
+            let clear_len = protected
+                .peek()
+                .map_or(nal.len(), |r| r.start.clamp(start, offset) - start);
+            let clear = &nal[..clear_len];
+            let [b0, b1, ..] = *clear else {
+                if nal.len() < 2 {
+                    return Err(SubsampleError::BadNalHeader(
+                        NalHeaderError::IncompleteHeader,
+                    ));
+                }
+                nals.push(ClearNalUnit::Protected {
+                    nal_header: None,
+                    clear,
+                    len: nal.len(),
+                });
+                continue;
+            };
+            let header = NalHeader::new(b0, Some(b1)).map_err(SubsampleError::BadNalHeader)?;
+            if header.nal_unit_type().is_vcl() {
+                let mut r = BitReader::from_nal(clear);
+                match SliceSegmentHeader::from_bits(&self.ctx, &mut r, header) {
+                    Ok((slice_header, _, _)) => nals.push(ClearNalUnit::SliceSegment {
+                        nal_header: header,
+                        slice_header: Box::new(slice_header),
+                        len: nal.len(),
+                    }),
+                    Err(SliceHeaderError::RbspError(BitReaderError::ReaderErrorFor(_, e)))
+                        if e.kind() == ErrorKind::UnexpectedEof && clear_len < nal.len() =>
+                    {
+                        nals.push(ClearNalUnit::Protected {
+                            nal_header: Some(header),
+                            clear,
+                            len: nal.len(),
+                        })
+                    }
+                    Err(e) => return Err(SubsampleError::BadSliceHeader(e)),
+                }
+            } else if clear_len == nal.len() {
+                track_param_set(&mut self.ctx, nal);
+                nals.push(ClearNalUnit::Clear(nal));
+            } else {
+                nals.push(ClearNalUnit::Protected {
+                    nal_header: Some(header),
+                    clear,
+                    len: nal.len(),
+                });
+            }
+        }
+        Ok(nals)
+    }
+}
+
+/// A NAL unit of an encrypted sample, as returned by [`ProtectedSampleParser::parse_sample`].
+#[derive(Debug)]
+pub enum ClearNalUnit<'a> {
+    /// A non-VCL NAL unit which is entirely clear, without length prefix.
+    Clear(&'a [u8]),
+    /// A slice segment with a clear header. Its data is usually protected.
+    SliceSegment {
+        nal_header: NalHeader,
+        slice_header: Box<SliceSegmentHeader>,
+        /// The length of the NAL unit, without length prefix.
+        len: usize,
+    },
+    /// A NAL unit which couldn't be parsed because of protected bytes; `clear` is the part of it
+    /// preceding the first protected byte.
+    Protected {
+        /// The NAL unit header, if clear.
+        nal_header: Option<NalHeader>,
+        clear: &'a [u8],
+        len: usize,
+    },
+}
+
+/// Keeps a VPS, SPS or PPS NAL unit in `ctx`, see [`Context::put_param_set_nal`].
+pub(crate) fn track_param_set(ctx: &mut Context, nal: &[u8]) {
+    ctx.put_param_set_nal(nal);
+}
+
+/// Returns the length in NAL bytes of the NAL header and slice segment header, which ends with
+/// `byte_alignment()`.
+fn slice_segment_header_len(
+    ctx: &Context,
+    header: NalHeader,
+    nal: &[u8],
+) -> Result<usize, SubsampleError> {
+    let mut r = BitReader::from_nal(nal);
+    SliceSegmentHeader::from_bits(ctx, &mut r, header).map_err(SubsampleError::BadSliceHeader)?;
+    let rest = r
+        .reader()
+        .expect("slice segment header ends byte aligned")
+        .get_ref();
+    Ok(nal.len() - rest.len())
+}
+
+fn push_subsample(map: &mut Vec<Subsample>, mut clear: usize, protected: u32) {
+    while clear > usize::from(u16::MAX) {
+        map.push(Subsample {
+            bytes_of_clear_data: u16::MAX,
+            bytes_of_protected_data: 0,
+        });
+        clear -= usize::from(u16::MAX);
+    }
+    map.push(Subsample {
+        bytes_of_clear_data: clear as u16,
+        bytes_of_protected_data: protected,
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hvcc::push_nal_unit;
+    use hex_literal::hex;
+
+    // "Intinor HW encode 720x576p" SPS and x265 PPS.
+    const SPS: [u8; 59] = hex!(
+        "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46 d1
+         2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00 0b
+         b8 48"
+    );
+    const PPS: [u8; 7] = hex!("44 01 c1 72 b4 62 40");
+
+    fn slice(header: &[u8], data_len: usize) -> Vec<u8> {
+        let mut nal = header.to_vec();
+        nal.resize(header.len() + data_len, 0xaa);
+        nal
+    }
+
+    #[test]
+    fn in_band_param_sets() {
+        let mut sample = vec![];
+        for nal in [&SPS[..], &PPS, &slice(&hex!("26 01 ac 9c"), 40)] {
+            push_nal_unit(&mut sample, nal, 4);
+        }
+        push_nal_unit(&mut sample, &hex!("4e 01 05 01 42 80"), 4);
+        // A slice segment too short to protect whole blocks.
+        push_nal_unit(&mut sample, &slice(&hex!("26 01 ac 9c"), 10), 4);
+
+        let clear_prefix = 4 + SPS.len() + 4 + PPS.len() + 4;
+        let mut mapper = SubsampleMapper::new(Scheme::Cenc, 4).unwrap();
+        assert_eq!(
+            mapper.map_sample(&sample).unwrap(),
+            [
+                Subsample {
+                    bytes_of_clear_data: (clear_prefix + 4 + 8) as u16,
+                    bytes_of_protected_data: 32,
+                },
+                Subsample {
+                    bytes_of_clear_data: 4 + 6 + 4 + 14,
+                    bytes_of_protected_data: 0,
+                },
+            ]
+        );
+        let mut mapper = SubsampleMapper::new(Scheme::Cbcs, 4).unwrap();
+        assert_eq!(
+            mapper.map_sample(&sample).unwrap(),
+            [
+                Subsample {
+                    bytes_of_clear_data: (clear_prefix + 4) as u16,
+                    bytes_of_protected_data: 40,
+                },
+                Subsample {
+                    bytes_of_clear_data: 4 + 6 + 4 + 4,
+                    bytes_of_protected_data: 10,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_param_sets() {
+        let mut sample = vec![];
+        push_nal_unit(&mut sample, &slice(&hex!("26 01 ac 9c"), 40), 4);
+        let mut mapper = SubsampleMapper::new(Scheme::Cenc, 4).unwrap();
+        assert!(matches!(
+            mapper.map_sample(&sample),
+            Err(SubsampleError::BadSliceHeader(
+                SliceHeaderError::UndefinedPicParamSetId(_)
+            ))
+        ));
+    }
+
+    #[test]
+    fn long_clear_run() {
+        let mut sample = vec![];
+        push_nal_unit(&mut sample, &slice(&hex!("4e 01"), 70000), 4);
+        let mut mapper = SubsampleMapper::new(Scheme::Cbcs, 4).unwrap();
+        let map = mapper.map_sample(&sample).unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(
+            map.iter()
+                .map(|s| usize::from(s.bytes_of_clear_data))
+                .sum::<usize>(),
+            sample.len()
+        );
+    }
+
+    #[test]
+    fn protected_header() {
+        let mut parser = ProtectedSampleParser::new(4).unwrap();
+        parser.add_param_set(&SPS);
+        let mut sample = vec![];
+        for nal in [
+            &PPS[..],
+            &slice(&hex!("26 01 ac 9c"), 40),
+            &hex!("4e 01 05 01 42 80"),
+        ] {
+            push_nal_unit(&mut sample, nal, 4);
+        }
+        let slice_start = 4 + PPS.len() + 4;
+        // Encrypted from within the slice segment header through the SEI.
+        let map = [Subsample {
+            bytes_of_clear_data: (slice_start + 3) as u16,
+            bytes_of_protected_data: (sample.len() - slice_start - 3) as u32,
+        }];
+        let nals = parser.parse_sample(&sample, &map).unwrap();
+        assert!(matches!(nals[0], ClearNalUnit::Clear(nal) if nal == PPS));
+        assert!(matches!(
+            nals[1],
+            ClearNalUnit::Protected { nal_header: Some(_), clear, len: 44 } if clear.len() == 3
+        ));
+        assert!(matches!(
+            nals[2],
+            ClearNalUnit::Protected {
+                nal_header: None,
+                len: 6,
+                ..
+            }
+        ));
+        // The PPS in the sample was kept for parsing later slice segment headers.
+        let map = [Subsample {
+            bytes_of_clear_data: (slice_start + 12) as u16,
+            bytes_of_protected_data: 32,
+        }];
+        let nals = parser
+            .parse_sample(&sample[..slice_start + 44], &map)
+            .unwrap();
+        assert!(matches!(
+            nals[1],
+            ClearNalUnit::SliceSegment { len: 44, .. }
+        ));
+        assert!(matches!(
+            parser.parse_sample(&sample, &map),
+            Err(SubsampleError::MapLengthMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,354 @@
+//! Extraction of what a CMAF or DASH initialization segment needs from an HEVC track, and
+//! checks for stream properties which CMAF (ISO/IEC 23000-19) doesn't allow within a track.
+
+use crate::hvcc::{nal_units, SampleError};
+use crate::nal::pps::PicParameterSet;
+use crate::nal::sei::content_light_level::ContentLightLevelInfo;
+use crate::nal::sei::mastering_display_colour_volume::MasteringDisplayColourVolume;
+use crate::nal::sei::{HeaderType, SeiReader};
+use crate::nal::sps::{ColourDescription, SeqParameterSet, SpsError};
+use crate::nal::vps::VideoParameterSet;
+use crate::nal::{NalHeader, UnitType};
+use crate::rbsp::{decode_nal, BitReader};
+use crate::rewrite::hvc1::is_sync_sample;
+use crate::rewrite::inject::param_set_id;
+use crate::{Context, FrameRate};
+
+#[derive(Debug)]
+pub enum InitError {
+    /// No SPS was given or seen in the samples.
+    NoSeqParamSet,
+    BadSeqParamSet(SpsError),
+}
+impl InitError {
+    /// See [`crate::error_code`].
+    pub fn code(&self) -> u32 {
+        crate::error_code::CMAF_INIT
+            + match self {
+                InitError::NoSeqParamSet => 1,
+                InitError::BadSeqParamSet(..) => 2,
+            }
+    }
+}
+
+/// A property of the track which violates CMAF.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CmafViolation {
+    /// The fragment starting at the given sample doesn't start with an IRAP picture.
+    FragmentNotRandomAccess { sample: u64 },
+    /// A parameter set in a sample differs from the one with the same id in the
+    /// initialization segment, and the sample doesn't start a fragment.
+    ParamSetChangedMidFragment { sample: u64, unit_type: UnitType },
+    /// As above, at the start of a fragment. The track needs a new CMAF header from there on.
+    ParamSetChanged { sample: u64, unit_type: UnitType },
+    /// The mastering display colour volume or content light level SEI differs from the first
+    /// one seen.
+    HdrMetadataChanged { sample: u64 },
+}
+
+/// The parameters of a CMAF header, see [`InitExtractor::init_params`].
+#[derive(Debug, Clone)]
+pub struct InitParams {
+    /// The VPS, SPS and PPS NAL units for the `hvcC` box, in that order.
+    pub param_sets: Vec<Vec<u8>>,
+    /// The RFC 6381 `codecs` parameter.
+    pub codecs: String,
+    /// The cropped picture size.
+    pub width: u32,
+    pub height: u32,
+    /// The sample aspect ratio, for the `pasp` box, if signalled and not unspecified.
+    pub sample_aspect_ratio: Option<(u16, u16)>,
+    pub frame_rate: Option<FrameRate>,
+    /// For the `colr` box, if signalled.
+    pub colour_description: Option<ColourDescription>,
+    pub video_full_range_flag: bool,
+    /// For the `mdcv` box.
+    pub mastering_display: Option<MasteringDisplayColourVolume>,
+    /// For the `clli` box.
+    pub content_light_level: Option<ContentLightLevelInfo>,
+}
+
+/// Collects the parameter sets and HDR metadata of a track's samples, checking them against
+/// CMAF constraints on the way.
+///
+/// ```
+/// use hevc_reader::cmaf::{CmafViolation, InitExtractor};
+/// use hevc_reader::nal::UnitType;
+/// # let sps = [
+/// #     0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03, 0x00, 0x00,
+/// #     0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46, 0xd1, 0x2e,
+/// #     0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10, 0x00, 0x00, 0x03,
+/// #     0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00, 0x0b, 0xb8, 0x48,
+/// # ];
+/// let mut extractor = InitExtractor::new(4).unwrap();
+/// extractor.add_param_set(&sps);
+/// extractor.add_param_set(&[0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40]);
+/// let idr = [0, 0, 0, 4, 0x26, 0x01, 0xac, 0x9c];
+/// extractor.push_sample(&idr, true).unwrap();
+/// // A trailing picture with a different in-band PPS.
+/// let trail = [
+///     0, 0, 0, 7, 0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0xc0, 0, 0, 0, 4, 0x02, 0x01, 0xd0, 0x6c,
+/// ];
+/// extractor.push_sample(&trail, false).unwrap();
+/// assert_eq!(
+///     extractor.violations(),
+///     [CmafViolation::ParamSetChangedMidFragment {
+///         sample: 1,
+///         unit_type: UnitType::PicParameterSet
+///     }]
+/// );
+/// let init = extractor.init_params("hvc1").unwrap();
+/// assert_eq!(init.codecs, "hvc1.1.6.L93.B0");
+/// assert_eq!((init.width, init.height), (720, 576));
+/// assert_eq!(init.frame_rate.unwrap().fps(), 25.0);
+/// ```
+pub struct InitExtractor {
+    length_size: usize,
+    /// The initialization segment's parameter sets, first seen per type and id.
+    param_sets: Vec<(UnitType, usize, Vec<u8>)>,
+    mastering_display: Option<MasteringDisplayColourVolume>,
+    content_light_level: Option<ContentLightLevelInfo>,
+    samples: u64,
+    violations: Vec<CmafViolation>,
+}
+impl InitExtractor {
+    /// Creates an extractor for samples with `length_size` byte NAL unit lengths.
+    pub fn new(length_size: usize) -> Result<Self, SampleError> {
+        // Validates the length size.
+        nal_units(&[], length_size)?;
+        Ok(InitExtractor {
+            length_size,
+            param_sets: vec![],
+            mastering_display: None,
+            content_light_level: None,
+            samples: 0,
+            violations: vec![],
+        })
+    }
+
+    /// Adds a parameter set NAL unit, e.g. from an existing `hvcC` box, unless one of the same
+    /// type and id was added or seen before. Returns false if the NAL unit isn't a parameter set.
+    pub fn add_param_set(&mut self, nal: &[u8]) -> bool {
+        let Some((unit_type, id)) = param_set_id(nal) else {
+            return false;
+        };
+        if !self
+            .param_sets
+            .iter()
+            .any(|(t, i, _)| (*t, *i) == (unit_type, id))
+        {
+            self.param_sets.push((unit_type, id, nal.to_vec()));
+        }
+        true
+    }
+
+    /// Examines the next sample, which starts a CMAF fragment if `starts_fragment`.
+    pub fn push_sample(&mut self, sample: &[u8], starts_fragment: bool) -> Result<(), SampleError> {
+        let n = self.samples;
+        self.samples += 1;
+        if starts_fragment && !is_sync_sample(sample, self.length_size)? {
+            self.violations
+                .push(CmafViolation::FragmentNotRandomAccess { sample: n });
+        }
+        for nal in nal_units(sample, self.length_size)? {
+            let nal = nal?;
+            if let Some((unit_type, id)) = param_set_id(nal) {
+                match self
+                    .param_sets
+                    .iter()
+                    .find(|(t, i, _)| (*t, *i) == (unit_type, id))
+                {
+                    Some((_, _, existing)) if existing[..] != nal[..] => {
+                        self.violations.push(if starts_fragment {
+                            CmafViolation::ParamSetChanged {
+                                sample: n,
+                                unit_type,
+                            }
+                        } else {
+                            CmafViolation::ParamSetChangedMidFragment {
+                                sample: n,
+                                unit_type,
+                            }
+                        });
+                    }
+                    Some(_) => {}
+                    None => self.param_sets.push((unit_type, id, nal.to_vec())),
+                }
+                continue;
+            }
+            let is_prefix_sei = nal
+                .first()
+                .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())
+                .is_some_and(|h| h.nal_unit_type() == UnitType::PrefixSEI);
+            if is_prefix_sei {
+                self.push_sei(nal, n);
+            }
+        }
+        Ok(())
+    }
+
+    fn push_sei(&mut self, nal: &[u8], sample: u64) {
+        let Ok(rbsp) = decode_nal(nal) else {
+            return;
+        };
+        let mut reader = SeiReader::from_rbsp_bytes(&rbsp);
+        let mut changed = false;
+        while let Ok(Some(msg)) = reader.next() {
+            match msg.payload_type {
+                HeaderType::MasteringDisplayColourVolume => {
+                    if let Ok(mdcv) = MasteringDisplayColourVolume::read(&msg) {
+                        changed |= self.mastering_display.get_or_insert(mdcv.clone()) != &mdcv;
+                    }
+                }
+                HeaderType::ContentLightLevelInfo => {
+                    if let Ok(cll) = ContentLightLevelInfo::read(&msg) {
+                        changed |= *self.content_light_level.get_or_insert(cll) != cll;
+                    }
+                }
+                _ => {}
+            }
+        }
+        if changed {
+            self.violations
+                .push(CmafViolation::HdrMetadataChanged { sample });
+        }
+    }
+
+    /// The violations found so far.
+    pub fn violations(&self) -> &[CmafViolation] {
+        &self.violations
+    }
+
+    /// Returns the CMAF header parameters for the first SPS, with `sample_entry_type` (`hvc1`
+    /// or `hev1`) for the codecs string.
+    pub fn init_params(&self, sample_entry_type: &str) -> Result<InitParams, InitError> {
+        let mut ctx = Context::new();
+        let mut param_sets = vec![];
+        let mut first_sps = None;
+        for unit_type in [
+            UnitType::VideoParameterSet,
+            UnitType::SeqParameterSet,
+            UnitType::PicParameterSet,
+        ] {
+            for (_, _, nal) in self.param_sets.iter().filter(|(t, _, _)| *t == unit_type) {
+                param_sets.push(nal.clone());
+                let Ok(rbsp) = decode_nal(nal) else {
+                    continue;
+                };
+                match unit_type {
+                    UnitType::VideoParameterSet => {
+                        if let Ok(vps) = VideoParameterSet::from_bits(BitReader::new(&*rbsp)) {
+                            ctx.put_video_param_set(vps);
+                        }
+                    }
+                    UnitType::SeqParameterSet => {
+                        let sps = SeqParameterSet::from_bits(BitReader::new(&*rbsp))
+                            .map_err(InitError::BadSeqParamSet)?;
+                        first_sps.get_or_insert(sps);
+                    }
+                    _ => {
+                        if let Ok(pps) = PicParameterSet::from_bits(&ctx, BitReader::new(&*rbsp)) {
+                            ctx.put_pic_param_set(pps);
+                        }
+                    }
+                }
+            }
+        }
+        let sps = first_sps.ok_or(InitError::NoSeqParamSet)?;
+        let (width, height) = sps.pixel_dimensions().map_err(InitError::BadSeqParamSet)?;
+        let vui = sps.vui_parameters.as_ref();
+        let signal = vui.and_then(|v| v.video_signal_type.as_ref());
+        Ok(InitParams {
+            param_sets,
+            codecs: sps.codecs_string(sample_entry_type),
+            width,
+            height,
+            sample_aspect_ratio: vui
+                .and_then(|v| v.aspect_ratio_info.as_ref())
+                .and_then(|a| a.get()),
+            frame_rate: ctx.frame_rate(&sps, None),
+            colour_description: signal.and_then(|s| s.colour_description.clone()),
+            video_full_range_flag: signal.is_some_and(|s| s.video_full_range_flag),
+            mastering_display: self.mastering_display.clone(),
+            content_light_level: self.content_light_level,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hvcc::push_nal_unit;
+    use crate::nal::sei::SeiMessage;
+    use crate::rbsp::encode_nal;
+    use hex_literal::hex;
+
+    const IDR: [u8; 4] = hex!("26 01 ac 9c");
+    const TRAIL: [u8; 6] = hex!("02 01 d0 6c 27 80");
+
+    fn cll_sei(max_content_light_level: u16) -> Vec<u8> {
+        let cll = ContentLightLevelInfo {
+            max_content_light_level,
+            max_pic_average_light_level: 400,
+        };
+        let payload = cll.to_payload();
+        let mut rbsp = vec![];
+        cll.to_message(&payload).write_to(&mut rbsp);
+        SeiMessage {
+            payload_type: HeaderType::RecoveryPoint,
+            payload: &[0xc4],
+        }
+        .write_to(&mut rbsp);
+        rbsp.push(0x80);
+        encode_nal(&[0x4e, 0x01], &rbsp)
+    }
+
+    fn sample(nals: &[&[u8]]) -> Vec<u8> {
+        let mut sample = vec![];
+        for nal in nals {
+            push_nal_unit(&mut sample, nal, 4);
+        }
+        sample
+    }
+
+    #[test]
+    fn fragments_and_hdr() {
+        let mut extractor = InitExtractor::new(4).unwrap();
+        extractor
+            .push_sample(&sample(&[&cll_sei(1000), &IDR]), true)
+            .unwrap();
+        extractor.push_sample(&sample(&[&TRAIL]), false).unwrap();
+        extractor.push_sample(&sample(&[&TRAIL]), true).unwrap();
+        extractor
+            .push_sample(&sample(&[&cll_sei(1000), &IDR]), true)
+            .unwrap();
+        extractor
+            .push_sample(&sample(&[&cll_sei(4000), &IDR]), true)
+            .unwrap();
+        assert_eq!(
+            extractor.violations(),
+            [
+                CmafViolation::FragmentNotRandomAccess { sample: 2 },
+                CmafViolation::HdrMetadataChanged { sample: 4 },
+            ]
+        );
+        assert!(matches!(
+            extractor.init_params("hvc1"),
+            Err(InitError::NoSeqParamSet)
+        ));
+        // "Haivision 1080i25" SPS, with 50 fields per second.
+        extractor.add_param_set(&hex!(
+            "42 01 01 01 40 00 00 03 00 40 00 00 03 00 00 03 00 7b a0 03 c0 80 22 1f 79 e9 6e
+             44 a1 7f f8 00 08 00 13 50 10 10 1e d0 00 00 03 00 10 00 00 03 03 25 08 ff de 10
+             00 16 e3 60 00 05 dd 77 df 08 04 10"
+        ));
+        let init = extractor.init_params("hev1").unwrap();
+        assert!(init.codecs.starts_with("hev1.1."));
+        assert_eq!(init.frame_rate.unwrap().fps(), 25.0);
+        assert_eq!(
+            init.content_light_level.unwrap().max_content_light_level,
+            1000
+        );
+        assert_eq!(init.mastering_display, None);
+    }
+}
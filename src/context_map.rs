@@ -0,0 +1,115 @@
+//! Separate [`Context`]s for independent streams carried together, such as the programs of an
+//! MPEG-TS multiplex or the SSRCs of an RTP session.
+//!
+//! Parameter set ids are only unique within a stream: independent encoders all start at SPS 0,
+//! so with one shared context, each stream's slices would be parsed with another stream's
+//! parameter sets whenever those arrived last.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::Context;
+
+/// A [`Context`] per stream, by a key chosen by the caller, e.g. a TS program number or an RTP
+/// SSRC. A context is created when the first NAL unit of its stream is pushed.
+///
+/// ```
+/// use hevc_reader::context_map::ContextMap;
+/// use hevc_reader::nal::sps::SeqParamSetId;
+/// # let sps = [
+/// #     0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03, 0x00, 0x00,
+/// #     0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46, 0xd1, 0x2e,
+/// #     0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10, 0x00, 0x00, 0x03,
+/// #     0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00, 0x0b, 0xb8, 0x48,
+/// # ];
+/// let mut contexts = ContextMap::new();
+/// // An SPS arrives in the stream of SSRC 0x1234.
+/// contexts.push_nal(0x1234u32, &sps);
+/// let id = SeqParamSetId::try_from(0).unwrap();
+/// assert!(contexts.get(&0x1234).unwrap().get(id).is_some());
+/// assert!(contexts.get(&0x5678).is_none());
+/// ```
+#[derive(Clone)]
+pub struct ContextMap<K> {
+    contexts: HashMap<K, Context>,
+}
+impl<K> Default for ContextMap<K> {
+    fn default() -> Self {
+        ContextMap {
+            contexts: HashMap::new(),
+        }
+    }
+}
+impl<K: Eq + Hash> ContextMap<K> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The context of a stream, if it has one.
+    pub fn get(&self, key: &K) -> Option<&Context> {
+        self.contexts.get(key)
+    }
+
+    /// The context of a stream, created empty if it has none yet.
+    pub fn context_mut(&mut self, key: K) -> &mut Context {
+        self.contexts.entry(key).or_default()
+    }
+
+    /// Keeps a NAL unit (without start code or length prefix) in the context of its stream if
+    /// it's a parameter set, as [`Context::put_param_set_nal`] does. Returns the context, to
+    /// parse the NAL unit further with.
+    pub fn push_nal(&mut self, key: K, nal: &[u8]) -> &Context {
+        let ctx = self.context_mut(key);
+        ctx.put_param_set_nal(nal);
+        ctx
+    }
+
+    /// Drops the context of a stream which has ended, returning it.
+    pub fn remove(&mut self, key: &K) -> Option<Context> {
+        self.contexts.remove(key)
+    }
+
+    /// The number of streams with a context.
+    pub fn len(&self) -> usize {
+        self.contexts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.contexts.is_empty()
+    }
+
+    /// The streams and their contexts, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &Context)> {
+        self.contexts.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::nal::pps::PicParamSetId;
+    use hex_literal::hex;
+
+    #[test]
+    fn isolation() {
+        // "Intinor HW encode 720x576p" SPS and its PPS.
+        let sps = hex!(
+            "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46
+             d1 2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00
+             0b b8 48"
+        );
+        let pps = hex!("44 01 c1 72 b4 62 40");
+        let mut contexts = ContextMap::new();
+        contexts.push_nal(1, &sps);
+        contexts.push_nal(1, &pps);
+        // Program 2's PPS arrives before its SPS, so doesn't parse, rather than parsing with
+        // program 1's SPS.
+        let id = PicParamSetId::try_from(0).unwrap();
+        assert!(contexts.push_nal(2, &pps).get(id).is_none());
+        assert!(contexts.get(&1).unwrap().get(id).is_some());
+        assert_eq!(contexts.len(), 2);
+
+        assert!(contexts.remove(&1).is_some());
+        assert_eq!(contexts.iter().map(|(&k, _)| k).collect::<Vec<_>>(), [2]);
+    }
+}
@@ -0,0 +1,341 @@
+//! Compares two H.265 Annex B elementary streams NAL unit by NAL unit, e.g. to validate that a
+//! remux or rewrite path leaves the bitstream intact.
+
+use crate::annexb::nal_units;
+use crate::nal::pps::PicParameterSet;
+use crate::nal::slice::SliceSegmentHeader;
+use crate::nal::sps::SeqParameterSet;
+use crate::nal::vps::VideoParameterSet;
+use crate::nal::{diff_fields, FieldDiff, NalHeader, UnitType};
+use crate::rbsp::{decode_nal, BitReader};
+use crate::Context;
+
+/// The first NAL unit where two streams differ, see [`first_divergence`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct NalDivergence {
+    /// Index of the NAL unit, counted from the start of both streams.
+    pub index: usize,
+    /// Byte offset of the NAL unit in the first stream, or `None` if that stream ended.
+    pub offset_a: Option<usize>,
+    /// Byte offset of the NAL unit in the second stream, or `None` if that stream ended.
+    pub offset_b: Option<usize>,
+    pub unit_type_a: Option<UnitType>,
+    pub unit_type_b: Option<UnitType>,
+    /// Offset of the first differing byte within the NAL units, if both are present.
+    pub first_differing_byte: Option<usize>,
+    /// Parse-level differences, when both NAL units are parameter sets or slice segments of
+    /// the same type that could be parsed.
+    pub fields: Vec<FieldDiff>,
+}
+
+/// Walks both streams in parallel and reports the first NAL unit that isn't byte-identical,
+/// or `None` if the streams contain the same NAL units.
+///
+/// Parameter sets seen before the divergence are tracked per stream, so that differing
+/// slice segment headers can be parsed and compared as well.
+pub fn first_divergence(a: &[u8], b: &[u8]) -> Option<NalDivergence> {
+    let mut ctx_a = Context::new();
+    let mut ctx_b = Context::new();
+    let mut nals_a = nal_units(a);
+    let mut nals_b = nal_units(b);
+    let mut index = 0;
+    loop {
+        match (nals_a.next(), nals_b.next()) {
+            (None, None) => return None,
+            (Some((_, nal_a)), Some((_, nal_b))) if nal_a == nal_b => {
+                track_param_set(&mut ctx_a, nal_a);
+                track_param_set(&mut ctx_b, nal_b);
+            }
+            (nal_a, nal_b) => {
+                let data_a = nal_a.map(|(_, nal)| nal);
+                let data_b = nal_b.map(|(_, nal)| nal);
+                let first_differing_byte = data_a.zip(data_b).map(|(a, b)| {
+                    a.iter()
+                        .zip(b.iter())
+                        .position(|(a, b)| a != b)
+                        .unwrap_or(a.len().min(b.len()))
+                });
+                let fields = match (data_a, data_b) {
+                    (Some(a), Some(b)) => field_diffs(&ctx_a, a, &ctx_b, b),
+                    _ => vec![],
+                };
+                return Some(NalDivergence {
+                    index,
+                    offset_a: nal_a.map(|(offset, _)| offset),
+                    offset_b: nal_b.map(|(offset, _)| offset),
+                    unit_type_a: data_a.and_then(unit_type),
+                    unit_type_b: data_b.and_then(unit_type),
+                    first_differing_byte,
+                    fields,
+                });
+            }
+        }
+        index += 1;
+    }
+}
+
+fn header(nal: &[u8]) -> Option<NalHeader> {
+    NalHeader::new(*nal.first()?, nal.get(1).copied()).ok()
+}
+
+fn unit_type(nal: &[u8]) -> Option<UnitType> {
+    header(nal).map(NalHeader::nal_unit_type)
+}
+
+fn track_param_set(ctx: &mut Context, nal: &[u8]) {
+    let Ok(rbsp) = decode_nal(nal) else {
+        return;
+    };
+    match unit_type(nal) {
+        Some(UnitType::VideoParameterSet) => {
+            if let Ok(vps) = VideoParameterSet::from_bits(BitReader::new(&*rbsp)) {
+                ctx.put_video_param_set(vps);
+            }
+        }
+        Some(UnitType::SeqParameterSet) => {
+            if let Ok(sps) = SeqParameterSet::from_bits(BitReader::new(&*rbsp)) {
+                ctx.put_seq_param_set(sps);
+            }
+        }
+        Some(UnitType::PicParameterSet) => {
+            if let Ok(pps) = PicParameterSet::from_bits(ctx, BitReader::new(&*rbsp)) {
+                ctx.put_pic_param_set(pps);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn field_diffs(ctx_a: &Context, a: &[u8], ctx_b: &Context, b: &[u8]) -> Vec<FieldDiff> {
+    let mut diffs = vec![];
+    let (Some(header_a), Some(header_b)) = (header(a), header(b)) else {
+        return diffs;
+    };
+    if header_a != header_b {
+        diffs.push(FieldDiff {
+            path: "nal_header".to_owned(),
+            old: format!("{:?}", header_a),
+            new: format!("{:?}", header_b),
+        });
+    }
+    let unit_type = header_a.nal_unit_type();
+    if unit_type != header_b.nal_unit_type() {
+        return diffs;
+    }
+    let (Ok(rbsp_a), Ok(rbsp_b)) = (decode_nal(a), decode_nal(b)) else {
+        return diffs;
+    };
+    match unit_type {
+        UnitType::VideoParameterSet => {
+            if let (Ok(a), Ok(b)) = (
+                VideoParameterSet::from_bits(BitReader::new(&*rbsp_a)),
+                VideoParameterSet::from_bits(BitReader::new(&*rbsp_b)),
+            ) {
+                diff_fields!(
+                    diffs,
+                    "",
+                    a,
+                    b,
+                    [
+                        vps_video_parameter_set_id,
+                        vps_base_layer_internal_flag,
+                        vps_base_layer_available_flag,
+                        vps_max_layers_minus1,
+                        vps_max_sub_layers_minus1,
+                        vps_temporal_id_nesting_flag,
+                        profile_tier_level,
+                        sub_layer_ordering_info,
+                        vps_max_layer_id,
+                        layer_sets,
+                        timing_info,
+                        vps_extension,
+                    ]
+                );
+            }
+        }
+        UnitType::SeqParameterSet => {
+            if let (Ok(a), Ok(b)) = (
+                SeqParameterSet::from_bits(BitReader::new(&*rbsp_a)),
+                SeqParameterSet::from_bits(BitReader::new(&*rbsp_b)),
+            ) {
+                diffs.extend(a.diff(&b));
+            }
+        }
+        UnitType::PicParameterSet => {
+            if let (Ok(a), Ok(b)) = (
+                PicParameterSet::from_bits(ctx_a, BitReader::new(&*rbsp_a)),
+                PicParameterSet::from_bits(ctx_b, BitReader::new(&*rbsp_b)),
+            ) {
+                diff_fields!(
+                    diffs,
+                    "",
+                    a,
+                    b,
+                    [
+                        pic_parameter_set_id,
+                        seq_parameter_set_id,
+                        dependent_slice_segments_enabled_flag,
+                        output_flag_present_flag,
+                        num_extra_slice_header_bits,
+                        sign_data_hiding_enabled_flag,
+                        cabac_init_present_flag,
+                        num_ref_idx_l0_default_active_minus1,
+                        num_ref_idx_l1_default_active_minus1,
+                        init_qp_minus26,
+                        constrained_intra_pred_flag,
+                        transform_skip_enabled_flag,
+                        diff_cu_qp_delta_depth,
+                        pps_cb_qp_offset,
+                        pps_cr_qp_offset,
+                        pps_slice_chroma_qp_offsets_present_flag,
+                        weighted_pred_flag,
+                        weighted_bipred_flag,
+                        transquant_bypass_enabled_flag,
+                        tiles,
+                        entropy_coding_sync_enabled_flag,
+                        pps_loop_filter_across_slices_enabled_flag,
+                        deblocking_filter_control,
+                        pps_scaling_list,
+                        lists_modification_present_flag,
+                        log2_parallel_merge_level_minus2,
+                        slice_segment_header_extension_present_flag,
+                        pps_extension,
+                    ]
+                );
+            }
+        }
+        t if t.is_vcl() => {
+            let mut r_a = BitReader::new(&*rbsp_a);
+            let mut r_b = BitReader::new(&*rbsp_b);
+            if let (Ok((a, _, _)), Ok((b, _, _))) = (
+                SliceSegmentHeader::from_bits(ctx_a, &mut r_a, header_a),
+                SliceSegmentHeader::from_bits(ctx_b, &mut r_b, header_b),
+            ) {
+                diff_fields!(
+                    diffs,
+                    "",
+                    a,
+                    b,
+                    [
+                        first_slice_segment_in_pic_flag,
+                        no_output_of_prior_pics_flag,
+                        slice_pic_parameter_set_id,
+                        dependent_slice_segment_flag,
+                        slice_segment_address,
+                    ]
+                );
+                match (&a.slice_header, &b.slice_header) {
+                    (Some(sa), Some(sb)) => diff_fields!(
+                        diffs,
+                        "slice_header.",
+                        sa,
+                        sb,
+                        [
+                            slice_reserved_flags,
+                            slice_type,
+                            pic_output_flag,
+                            colour_plane_id,
+                            slice_pic_order_cnt_lsb,
+                            short_term_ref_pic_set,
+                            long_term_ref_pics,
+                            slice_temporal_mvp_enabled_flag,
+                            slice_sao_luma_flag,
+                            slice_sao_chroma_flag,
+                            inter,
+                            slice_qp_delta,
+                            slice_cb_qp_offset,
+                            slice_cr_qp_offset,
+                            slice_act_y_qp_offset,
+                            slice_act_cb_qp_offset,
+                            slice_act_cr_qp_offset,
+                            cu_chroma_qp_offset_enabled_flag,
+                            deblocking_filter_override_flag,
+                            slice_deblocking_filter_disabled_flag,
+                            slice_beta_offset_div2,
+                            slice_tc_offset_div2,
+                            slice_loop_filter_across_slices_enabled_flag,
+                        ]
+                    ),
+                    _ => diff_fields!(diffs, "", a, b, [slice_header]),
+                }
+                diff_fields!(
+                    diffs,
+                    "",
+                    a,
+                    b,
+                    [
+                        entry_point_offset_minus1,
+                        slice_segment_header_extension_data,
+                    ]
+                );
+            }
+        }
+        _ => {}
+    }
+    diffs
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hex_literal::hex;
+
+    // "Intinor HW encode 720x576p" SPS, x265 PPS and an IDR slice segment from the slice tests.
+    const SPS: [u8; 59] = hex!(
+        "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46 d1
+         2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00 0b
+         b8 48"
+    );
+    const PPS: [u8; 7] = hex!("44 01 c1 72 b4 62 40");
+    const IDR: [u8; 4] = hex!("26 01 ac 9c");
+
+    fn stream(nals: &[&[u8]]) -> Vec<u8> {
+        let mut data = vec![];
+        for nal in nals {
+            data.extend_from_slice(&[0, 0, 0, 1]);
+            data.extend_from_slice(nal);
+        }
+        data
+    }
+
+    #[test]
+    fn identical() {
+        let a = stream(&[&SPS, &PPS, &IDR]);
+        assert_eq!(first_divergence(&a, &a), None);
+    }
+
+    #[test]
+    fn slice_header_field() {
+        let a = stream(&[&SPS, &PPS, &IDR]);
+        // The same slice with slice_qp_delta -1 rather than 2.
+        let idr = hex!("26 01 ad f0");
+        let b = stream(&[&SPS, &PPS, &idr]);
+        let divergence = first_divergence(&a, &b).unwrap();
+        assert_eq!(divergence.index, 2);
+        assert_eq!(divergence.offset_a, Some(4 + 59 + 4 + 7 + 4));
+        assert_eq!(
+            divergence.unit_type_a,
+            Some(UnitType::SliceSegmentLayerIdrWLp)
+        );
+        assert_eq!(divergence.first_differing_byte, Some(2));
+        assert_eq!(
+            divergence.fields,
+            vec![FieldDiff {
+                path: "slice_header.slice_qp_delta".to_owned(),
+                old: "2".to_owned(),
+                new: "-1".to_owned(),
+            }]
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let a = stream(&[&SPS, &PPS, &IDR]);
+        let b = stream(&[&SPS, &PPS]);
+        let divergence = first_divergence(&a, &b).unwrap();
+        assert_eq!(divergence.index, 2);
+        assert_eq!(divergence.offset_b, None);
+        assert_eq!(divergence.unit_type_b, None);
+        assert_eq!(divergence.first_differing_byte, None);
+    }
+}
@@ -0,0 +1,268 @@
+//! Frame counts and durations of whole streams, for media asset management and for checking
+//! that a file holds what its container claims.
+//!
+//! HEVC signals one clock tick of `num_units_in_tick / time_scale` seconds per coded picture,
+//! in the VUI of the SPS or in the VPS (see [`Context::frame_rate`]). The `pic_struct` of the
+//! `pic_timing()` SEI message of a picture may display it for longer: a frame shown as three
+//! fields for one and a half ticks, with frame doubling for two, with frame tripling for three.
+
+use std::io::Read;
+use std::sync::Arc;
+
+use crate::annexb::{AnnexBReader, ReadAdapter};
+use crate::hvcc::SampleError;
+use crate::nal::sei::pic_timing::{PicStruct, PicTiming};
+use crate::nal::sei::{HeaderType, SeiReader};
+use crate::nal::sps::SeqParameterSet;
+use crate::nal::{Nal, NalHeader, RefNal, UnitType};
+use crate::push::NalInterest;
+use crate::rbsp::{decode_nal, BitReader};
+use crate::{Context, FrameRate};
+
+/// The frame count and duration of a stream, as returned by [`DurationScanner::finish`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DurationReport {
+    /// The number of coded pictures of layer 0.
+    pub pictures: u64,
+    /// The number of frames, where two field pictures make up one frame.
+    pub frames: u64,
+    /// The frame rate signalled for the first picture.
+    pub frame_rate: Option<FrameRate>,
+    /// The total display duration in seconds, unless a picture had no signalled frame rate.
+    pub duration: Option<f64>,
+    /// True if every picture had the same frame rate and was displayed for the same time.
+    pub constant_frame_rate: bool,
+}
+
+/// Gathers a [`DurationReport`], fed one NAL unit at a time in decoding order.
+///
+/// Pictures are counted by their first slice segment, without parsing slice headers. Each
+/// `pic_timing()` SEI message is read with the last SPS received, which is the active one in
+/// all but streams mixing SPSs.
+///
+/// ```
+/// use hevc_reader::duration::DurationScanner;
+/// let mut scanner = DurationScanner::new();
+/// // "Haivision 1080i25" SPS, with 50 field pictures per second and no VPS.
+/// scanner.push_nal(&[
+///     0x42, 0x01, 0x01, 0x01, 0x40, 0x00, 0x00, 0x03, 0x00, 0x40, 0x00, 0x00, 0x03, 0x00, 0x00,
+///     0x03, 0x00, 0x7b, 0xa0, 0x03, 0xc0, 0x80, 0x22, 0x1f, 0x79, 0xe9, 0x6e, 0x44, 0xa1, 0x7f,
+///     0xf8, 0x00, 0x08, 0x00, 0x13, 0x50, 0x10, 0x10, 0x1e, 0xd0, 0x00, 0x00, 0x03, 0x00, 0x10,
+///     0x00, 0x00, 0x03, 0x03, 0x25, 0x08, 0xff, 0xde, 0x10, 0x00, 0x16, 0xe3, 0x60, 0x00, 0x05,
+///     0xdd, 0x77, 0xdf, 0x08, 0x04, 0x10,
+/// ]);
+/// // Two IDR pictures, i.e. one frame.
+/// scanner.push_nal(&[0x26, 0x01, 0xac, 0x9c]);
+/// scanner.push_nal(&[0x26, 0x01, 0xac, 0x9c]);
+/// let report = scanner.finish();
+/// assert_eq!((report.pictures, report.frames), (2, 1));
+/// assert_eq!(report.duration, Some(0.04));
+/// assert!(report.constant_frame_rate);
+/// ```
+#[derive(Default)]
+pub struct DurationScanner {
+    ctx: Context,
+    /// The last SPS received.
+    sps: Option<Arc<SeqParameterSet>>,
+    /// The `pic_timing()` SEI message of the access unit whose first slice segment is next.
+    pic_timing: Option<PicTiming>,
+    pictures: u64,
+    field_pictures: u64,
+    frame_rate: Option<FrameRate>,
+    /// The duration of the pictures so far, or `None` once one had no frame rate.
+    duration: Option<f64>,
+    /// The time base and the display time in half ticks of the first picture.
+    first_timing: Option<(u32, u32, u32)>,
+    constant_frame_rate: bool,
+}
+impl DurationScanner {
+    pub fn new() -> Self {
+        DurationScanner {
+            duration: Some(0.0),
+            constant_frame_rate: true,
+            ..Self::default()
+        }
+    }
+
+    /// Adds a NAL unit (without start code or length prefix). NAL units which can't be parsed
+    /// are ignored.
+    pub fn push_nal(&mut self, nal: &[u8]) {
+        let Some(header) = nal
+            .first()
+            .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())
+        else {
+            return;
+        };
+        if !matches!(header.nuh_layer_id(), Ok(0)) {
+            return;
+        }
+        match header.nal_unit_type() {
+            UnitType::SeqParameterSet => {
+                if let Ok(sps) = SeqParameterSet::from_bits(BitReader::from_nal(nal)) {
+                    let sps = Arc::new(sps);
+                    self.ctx.put_shared_seq_param_set(sps.clone());
+                    self.sps = Some(sps);
+                }
+            }
+            UnitType::VideoParameterSet | UnitType::PicParameterSet => {
+                self.ctx.put_param_set_nal(nal);
+            }
+            UnitType::PrefixSEI => self.push_sei(nal),
+            t if t.is_vcl() && nal.get(2).is_some_and(|b| b & 0x80 != 0) => self.push_picture(),
+            _ => {}
+        }
+    }
+
+    /// Adds the NAL units of an Annex B stream.
+    pub fn push_annexb(&mut self, data: &[u8]) {
+        for (_, nal) in crate::annexb::nal_units(data) {
+            self.push_nal(nal);
+        }
+    }
+
+    /// Adds the NAL units of a length-prefixed sample.
+    pub fn push_sample(&mut self, sample: &[u8], length_size: usize) -> Result<(), SampleError> {
+        for nal in crate::hvcc::nal_units(sample, length_size)? {
+            self.push_nal(nal?);
+        }
+        Ok(())
+    }
+
+    /// Adds an Annex B stream read from `reader`, e.g. a file, one buffer at a time. Returns the
+    /// number of bytes read.
+    pub fn read_annexb<R: Read>(&mut self, reader: R) -> std::io::Result<u64> {
+        let mut adapter = ReadAdapter::new(
+            reader,
+            AnnexBReader::accumulate(|nal: RefNal<'_>| {
+                if nal.is_complete() {
+                    let mut bytes = vec![];
+                    if nal.reader().read_to_end(&mut bytes).is_ok() {
+                        self.push_nal(&bytes);
+                    }
+                }
+                NalInterest::Buffer
+            }),
+        );
+        adapter.read_to_end()
+    }
+
+    pub fn finish(self) -> DurationReport {
+        DurationReport {
+            pictures: self.pictures,
+            frames: self.pictures - self.field_pictures / 2,
+            frame_rate: self.frame_rate,
+            duration: self.duration.filter(|_| self.pictures > 0),
+            constant_frame_rate: self.constant_frame_rate && self.first_timing.is_some(),
+        }
+    }
+
+    fn push_sei(&mut self, nal: &[u8]) {
+        let (Some(sps), Ok(rbsp)) = (&self.sps, decode_nal(nal)) else {
+            return;
+        };
+        let mut reader = SeiReader::from_rbsp_bytes(&rbsp);
+        while let Ok(Some(msg)) = reader.next() {
+            if msg.payload_type == HeaderType::PicTiming {
+                self.pic_timing = PicTiming::read(sps, &msg).ok();
+            }
+        }
+    }
+
+    fn push_picture(&mut self) {
+        let pic_timing = self.pic_timing.take();
+        self.pictures += 1;
+        let rate = self
+            .sps
+            .as_ref()
+            .and_then(|sps| self.ctx.frame_rate(sps, pic_timing.as_ref()));
+        if self.pictures == 1 {
+            self.frame_rate = rate;
+        }
+        let Some(rate) = rate else {
+            self.duration = None;
+            self.constant_frame_rate = false;
+            return;
+        };
+        if rate.field_pictures {
+            self.field_pictures += 1;
+        }
+        let half_ticks = pic_timing
+            .and_then(|p| p.frame_field_info)
+            .map_or(2, |info| half_ticks(info.pic_struct));
+        if let Some(duration) = &mut self.duration {
+            *duration += f64::from(half_ticks) / 2.0 / rate.pictures_per_second();
+        }
+        let timing = (rate.num_units_in_tick, rate.time_scale, half_ticks);
+        match self.first_timing {
+            None if self.pictures == 1 => self.first_timing = Some(timing),
+            Some(first) if first == timing => {}
+            _ => self.constant_frame_rate = false,
+        }
+    }
+}
+
+/// The display time of a picture in halves of a clock tick, see Table D.2.
+fn half_ticks(pic_struct: PicStruct) -> u32 {
+    match pic_struct {
+        PicStruct::TopBottomTop | PicStruct::BottomTopBottom => 3,
+        PicStruct::FrameDoubling => 4,
+        PicStruct::FrameTripling => 6,
+        _ => 2,
+    }
+}
+
+/// Scans an Annex B stream read from `reader`, e.g. a file, for its frame count and duration.
+///
+/// ```no_run
+/// let file = std::fs::File::open("stream.h265").unwrap();
+/// let report = hevc_reader::duration::scan_annexb(file).unwrap();
+/// println!("{} frames, {:?} s", report.frames, report.duration);
+/// ```
+pub fn scan_annexb<R: Read>(reader: R) -> std::io::Result<DurationReport> {
+    let mut scanner = DurationScanner::new();
+    scanner.read_annexb(reader)?;
+    Ok(scanner.finish())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hex_literal::hex;
+
+    /// A `pic_timing()` SEI NAL unit for the "Haivision 1080i25" SPS with the given
+    /// `pic_struct`, and `source_scan_type` 1 (progressive).
+    fn pic_timing(pic_struct: u8) -> Vec<u8> {
+        let mut nal = vec![0x4e, 0x01, 0x01, 0x09, pic_struct << 4 | 0x04];
+        nal.extend_from_slice(&hex!("00 00 03 00 00 03 00 00 03 00 14 80"));
+        nal
+    }
+
+    #[test]
+    fn pic_struct() {
+        let sps = hex!(
+            "42 01 01 01 40 00 00 03 00 40 00 00 03 00 00 03 00 7b a0 03 c0 80 22 1f 79 e9 6e
+             44 a1 7f f8 00 08 00 13 50 10 10 1e d0 00 00 03 00 10 00 00 03 03 25 08 ff de 10
+             00 16 e3 60 00 05 dd 77 df 08 04 10"
+        );
+        let idr = hex!("26 01 ac 9c");
+        let mut stream = vec![];
+        // A frame, then a frame shown twice, at 50 pictures per second.
+        for nal in [&sps[..], &pic_timing(0), &idr, &pic_timing(7), &idr] {
+            stream.extend_from_slice(&[0, 0, 1]);
+            stream.extend_from_slice(nal);
+        }
+        let report = scan_annexb(&stream[..]).unwrap();
+        assert_eq!((report.pictures, report.frames), (2, 2));
+        assert!(!report.frame_rate.unwrap().field_pictures);
+        assert!((report.duration.unwrap() - 0.06).abs() < 1e-9);
+        assert!(!report.constant_frame_rate);
+
+        // Without timing info, the duration is unknown.
+        let mut scanner = DurationScanner::new();
+        scanner.push_nal(&idr);
+        let report = scanner.finish();
+        assert_eq!(report.pictures, 1);
+        assert_eq!(report.duration, None);
+        assert!(!report.constant_frame_rate);
+    }
+}
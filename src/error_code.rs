@@ -0,0 +1,131 @@
+//! The registry of stable numeric error codes, for FFI and for logging pipelines which alert on
+//! specific parse failures.
+//!
+//! Every error type has a `code()` method, returning the stable number of the variant. Each type
+//! is given a range of 100 codes starting at its constant below, and each variant the code of
+//! the range's start plus its position in the enum, counting from 1. Codes are never reused:
+//! new variants are added at the end of their enum, and removed variants leave a gap.
+//!
+//! Variants wrapping another error, e.g. [`SpsError::RbspReaderError`], have a code of their
+//! own; the wrapped error's `code()` gives the underlying cause.
+//!
+//! ```
+//! use hevc_reader::nal::sps::SpsError;
+//! let e = SpsError::CpbCountOutOfRange(40);
+//! assert_eq!(e.code(), 605);
+//! assert_eq!(e.code() / 100 * 100, hevc_reader::error_code::SPS);
+//! ```
+//!
+//! [`SpsError::RbspReaderError`]: crate::nal::sps::SpsError::RbspReaderError
+
+/// [`BitReaderError`](crate::rbsp::BitReaderError).
+pub const BIT_READER: u32 = 100;
+/// [`NalHeaderError`](crate::nal::NalHeaderError).
+pub const NAL_HEADER: u32 = 200;
+/// [`UnitTypeError`](crate::nal::UnitTypeError).
+pub const UNIT_TYPE: u32 = 300;
+/// [`ParamSetIdError`](crate::nal::pps::ParamSetIdError).
+pub const PARAM_SET_ID: u32 = 400;
+/// [`VpsError`](crate::nal::vps::VpsError).
+pub const VPS: u32 = 500;
+/// [`SpsError`](crate::nal::sps::SpsError).
+pub const SPS: u32 = 600;
+/// [`PpsError`](crate::nal::pps::PpsError).
+pub const PPS: u32 = 700;
+/// `nal::slice::SliceHeaderError`, with the `slice` feature.
+pub const SLICE_HEADER: u32 = 800;
+/// `nal::sei::pic_timing::PicTimingError`, with the `sei` feature.
+pub const PIC_TIMING: u32 = 900;
+/// `nal::sei::mastering_display_colour_volume::MasteringDisplayError`, with the `sei` feature.
+pub const MASTERING_DISPLAY: u32 = 1000;
+/// `nal::sei::content_light_level::ContentLightLevelError`, with the `sei` feature.
+pub const CONTENT_LIGHT_LEVEL: u32 = 1100;
+/// [`SampleError`](crate::hvcc::SampleError).
+pub const SAMPLE: u32 = 1200;
+/// `cenc::SubsampleError`, with the `slice` feature.
+pub const SUBSAMPLE: u32 = 1300;
+/// [`PacketizeError`](crate::rtp::PacketizeError).
+pub const PACKETIZE: u32 = 1400;
+/// `sample_entry::SampleEntryError`, with the `sei` and `write` features.
+pub const SAMPLE_ENTRY: u32 = 1500;
+/// `cmaf::InitError`, with the `analysis` feature.
+pub const CMAF_INIT: u32 = 1600;
+/// `heif::ImageError`, with the `analysis` feature.
+pub const HEIF_IMAGE: u32 = 1700;
+/// `rewrite::FilterError`, with the `write` feature.
+pub const FILTER: u32 = 1800;
+/// `rewrite::remap::RemapError`, with the `write` and `slice` features.
+pub const REMAP: u32 = 1900;
+/// `rewrite::trim::TrimError`, with the `write` feature.
+pub const TRIM: u32 = 2000;
+/// `rewrite::vui::VuiEditError`, with the `write` feature.
+pub const VUI_EDIT: u32 = 2100;
+/// [`InspectError`](crate::inspect::InspectError).
+pub const INSPECT: u32 = 2200;
+/// [`PatchError`](crate::inspect::PatchError).
+pub const PATCH: u32 = 2300;
+/// `nal::sei::time_code::TimeCodeError`, with the `sei` feature.
+pub const TIME_CODE: u32 = 2400;
+/// `nal::sei::buffering_period::BufferingPeriodError`, with the `sei` feature.
+pub const BUFFERING_PERIOD: u32 = 2500;
+/// `index::IndexError`, with the `analysis` feature.
+pub const INDEX: u32 = 2600;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::nal::pps::{ParamSetIdError, ParamSetKind, PpsError};
+    use crate::nal::NalHeaderError;
+    use crate::rbsp::BitReaderError;
+
+    #[test]
+    fn codes() {
+        let ranges = [
+            BIT_READER,
+            NAL_HEADER,
+            UNIT_TYPE,
+            PARAM_SET_ID,
+            VPS,
+            SPS,
+            PPS,
+            SLICE_HEADER,
+            PIC_TIMING,
+            MASTERING_DISPLAY,
+            CONTENT_LIGHT_LEVEL,
+            SAMPLE,
+            SUBSAMPLE,
+            PACKETIZE,
+            SAMPLE_ENTRY,
+            CMAF_INIT,
+            HEIF_IMAGE,
+            FILTER,
+            REMAP,
+            TRIM,
+            VUI_EDIT,
+            INSPECT,
+            PATCH,
+            TIME_CODE,
+            BUFFERING_PERIOD,
+            INDEX,
+        ];
+        assert!(ranges.windows(2).all(|w| w[1] == w[0] + 100));
+        assert_eq!(BitReaderError::Unaligned.code(), 105);
+        assert_eq!(NalHeaderError::ForbiddenZeroBit.code(), 201);
+        assert_eq!(
+            ParamSetIdError::IdTooLarge(ParamSetKind::Seq, 99).code(),
+            401
+        );
+        let e = PpsError::BadSeqParamSetId(ParamSetIdError::IdTooLarge(ParamSetKind::Seq, 99));
+        assert_eq!(e.code(), 705);
+        #[cfg(feature = "slice")]
+        assert_eq!(
+            crate::nal::slice::SliceHeaderError::InvalidSliceType(9).code(),
+            802
+        );
+        #[cfg(feature = "write")]
+        assert_eq!(
+            crate::rewrite::FilterError::Custom(String::new()).code(),
+            1805
+        );
+    }
+}
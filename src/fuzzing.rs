@@ -0,0 +1,22 @@
+//! Helpers for deriving `arbitrary::Arbitrary`, with the `arbitrary` feature.
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+use arrayvec::ArrayVec;
+
+/// An `ArrayVec` of up to its capacity of arbitrary elements.
+pub(crate) fn array_vec<'a, T: Arbitrary<'a>, const N: usize>(
+    u: &mut Unstructured<'a>,
+) -> Result<ArrayVec<T, N>> {
+    let len = u.int_in_range(0..=N)?;
+    (0..len).map(|_| T::arbitrary(u)).collect()
+}
+
+pub(crate) fn option_array_vec<'a, T: Arbitrary<'a>, const N: usize>(
+    u: &mut Unstructured<'a>,
+) -> Result<Option<ArrayVec<T, N>>> {
+    Ok(if u.arbitrary()? {
+        Some(array_vec(u)?)
+    } else {
+        None
+    })
+}
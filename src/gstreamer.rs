@@ -0,0 +1,317 @@
+//! GStreamer caps for an HEVC stream, as `h265parse` would set them, for plugins which use this
+//! crate to parse streams themselves.
+//!
+//! The caps are produced as their string serialization, which `gst::Caps::from_str()` parses,
+//! so that this crate needn't depend on the GStreamer bindings.
+
+use std::fmt;
+
+use crate::nal::sps::{ChromaFormat, Level, Profile, Tier};
+use crate::sample_entry::{SampleEntry, SampleEntryConfig, SampleEntryError};
+use crate::Context;
+
+/// The `stream-format` field: how NAL units are delimited in buffers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamFormat {
+    /// Length-prefixed NAL units, with all parameter sets in `codec_data`.
+    Hvc1,
+    /// Length-prefixed NAL units, with parameter sets in `codec_data` and in the stream.
+    Hev1,
+    /// Annex B start codes, without `codec_data`.
+    ByteStream,
+}
+impl StreamFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StreamFormat::Hvc1 => "hvc1",
+            StreamFormat::Hev1 => "hev1",
+            StreamFormat::ByteStream => "byte-stream",
+        }
+    }
+}
+
+/// The `alignment` field: what a buffer holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alignment {
+    /// A whole access unit.
+    Au,
+    /// A single NAL unit.
+    Nal,
+}
+impl Alignment {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Alignment::Au => "au",
+            Alignment::Nal => "nal",
+        }
+    }
+}
+
+/// The fields of `video/x-h265` caps, taken from the first SPS of a [`Context`].
+///
+/// ```
+/// use hevc_reader::gstreamer::{Alignment, Caps, StreamFormat};
+/// use hevc_reader::Context;
+/// # let sps = [
+/// #     0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03, 0x00,
+/// #     0x00, 0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46,
+/// #     0xd1, 0x2e, 0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10,
+/// #     0x00, 0x00, 0x03, 0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00,
+/// #     0x0b, 0xb8, 0x48,
+/// # ];
+/// let mut ctx = Context::new();
+/// ctx.put_param_set_nal(&sps);
+/// let caps = Caps::new(&ctx, &[], StreamFormat::ByteStream, Alignment::Au).unwrap();
+/// assert_eq!(
+///     caps.to_string(),
+///     "video/x-h265, stream-format=(string)byte-stream, alignment=(string)au, \
+///      profile=(string)main, tier=(string)main, level=(string)3.1, width=(int)720, \
+///      height=(int)576, framerate=(fraction)25/1, pixel-aspect-ratio=(fraction)64/45, \
+///      chroma-format=(string)4:2:0, bit-depth-luma=(uint)8, bit-depth-chroma=(uint)8"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Caps {
+    pub stream_format: StreamFormat,
+    pub alignment: Alignment,
+    /// `None` if the profile has no GStreamer name.
+    pub profile: Option<&'static str>,
+    pub tier: &'static str,
+    /// `None` for reserved levels.
+    pub level: Option<&'static str>,
+    /// The cropped picture size.
+    pub width: u32,
+    pub height: u32,
+    /// Frames per second as a reduced fraction, if the VUI or VPS signal timing.
+    pub framerate: Option<(u32, u32)>,
+    pub pixel_aspect_ratio: Option<(u16, u16)>,
+    /// `None` for an invalid `chroma_format_idc`.
+    pub chroma_format: Option<&'static str>,
+    pub bit_depth_luma: u32,
+    pub bit_depth_chroma: u32,
+    /// The `HEVCDecoderConfigurationRecord`, for the `hvc1` and `hev1` stream formats.
+    pub codec_data: Option<Vec<u8>>,
+}
+impl Caps {
+    /// Describes the first SPS of `ctx`. `nals` are the parameter sets for `codec_data` as found
+    /// in the stream, see [`SampleEntry::new`]; they're unused for [`StreamFormat::ByteStream`].
+    pub fn new(
+        ctx: &Context,
+        nals: &[&[u8]],
+        stream_format: StreamFormat,
+        alignment: Alignment,
+    ) -> Result<Caps, SampleEntryError> {
+        let sps = ctx.sps().next().ok_or(SampleEntryError::NoSeqParamSet)?;
+        let (width, height) = sps.pixel_dimensions()?;
+        let codec_data = match stream_format {
+            StreamFormat::ByteStream => None,
+            StreamFormat::Hvc1 | StreamFormat::Hev1 => {
+                let config = SampleEntryConfig {
+                    sample_entry_type: if stream_format == StreamFormat::Hvc1 {
+                        *b"hvc1"
+                    } else {
+                        *b"hev1"
+                    },
+                    ..SampleEntryConfig::default()
+                };
+                Some(SampleEntry::new(ctx, nals, &config)?.hvcc)
+            }
+        };
+        let framerate = ctx.frame_rate(sps, None).map(|rate| {
+            let den = u64::from(rate.num_units_in_tick) * if rate.field_pictures { 2 } else { 1 };
+            reduce(u64::from(rate.time_scale), den)
+        });
+        Ok(Caps {
+            stream_format,
+            alignment,
+            profile: profile_name(sps.general_profile()),
+            tier: match sps.general_tier() {
+                Tier::Main => "main",
+                Tier::High => "high",
+            },
+            level: level_name(sps.general_level()),
+            width,
+            height,
+            framerate,
+            pixel_aspect_ratio: sps
+                .vui_parameters
+                .as_ref()
+                .and_then(|v| v.aspect_ratio_info.as_ref())
+                .and_then(|a| a.get()),
+            chroma_format: match sps.chroma_info.chroma_format {
+                ChromaFormat::Monochrome => Some("4:0:0"),
+                ChromaFormat::YUV420 => Some("4:2:0"),
+                ChromaFormat::YUV422 => Some("4:2:2"),
+                ChromaFormat::YUV444 => Some("4:4:4"),
+                ChromaFormat::Invalid(_) => None,
+            },
+            bit_depth_luma: sps.bit_depth_luma_minus8 + 8,
+            bit_depth_chroma: sps.bit_depth_chroma_minus8 + 8,
+            codec_data,
+        })
+    }
+}
+impl fmt::Display for Caps {
+    /// The caps in GStreamer's serialization, leaving out fields which are unknown.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "video/x-h265, stream-format=(string){}, alignment=(string){}",
+            self.stream_format.as_str(),
+            self.alignment.as_str()
+        )?;
+        if let Some(profile) = self.profile {
+            write!(f, ", profile=(string){}", profile)?;
+        }
+        write!(f, ", tier=(string){}", self.tier)?;
+        if let Some(level) = self.level {
+            write!(f, ", level=(string){}", level)?;
+        }
+        write!(
+            f,
+            ", width=(int){}, height=(int){}",
+            self.width, self.height
+        )?;
+        if let Some((num, den)) = self.framerate {
+            write!(f, ", framerate=(fraction){}/{}", num, den)?;
+        }
+        if let Some((num, den)) = self.pixel_aspect_ratio {
+            write!(f, ", pixel-aspect-ratio=(fraction){}/{}", num, den)?;
+        }
+        if let Some(chroma_format) = self.chroma_format {
+            write!(f, ", chroma-format=(string){}", chroma_format)?;
+        }
+        write!(
+            f,
+            ", bit-depth-luma=(uint){}, bit-depth-chroma=(uint){}",
+            self.bit_depth_luma, self.bit_depth_chroma
+        )?;
+        if let Some(codec_data) = &self.codec_data {
+            f.write_str(", codec_data=(buffer)")?;
+            for b in codec_data {
+                write!(f, "{:02x}", b)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `num / den` in lowest terms, saturating at `u32::MAX` if it still doesn't fit.
+fn reduce(num: u64, den: u64) -> (u32, u32) {
+    let (mut a, mut b) = (num, den);
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    let clamp = |v: u64| u32::try_from(v / a.max(1)).unwrap_or(u32::MAX);
+    (clamp(num), clamp(den))
+}
+
+/// The name GStreamer's `h265parse` gives `profile`, if it has one.
+pub fn profile_name(profile: Profile) -> Option<&'static str> {
+    Some(match profile {
+        Profile::Unknown(_) => return None,
+        Profile::Main => "main",
+        // GStreamer has no name of its own for Main 10 Still Picture.
+        Profile::Main10 | Profile::Main10StillPicture => "main-10",
+        Profile::MainStillPicture => "main-still-picture",
+        Profile::Monochrome => "monochrome",
+        Profile::Monochrome10 => "monochrome-10",
+        Profile::Monochrome12 => "monochrome-12",
+        Profile::Monochrome16 => "monochrome-16",
+        Profile::Main12 => "main-12",
+        Profile::Main422_10 => "main-422-10",
+        Profile::Main422_12 => "main-422-12",
+        Profile::Main444 => "main-444",
+        Profile::Main444_10 => "main-444-10",
+        Profile::Main444_12 => "main-444-12",
+        Profile::MainIntra => "main-intra",
+        Profile::Main10Intra => "main-10-intra",
+        Profile::Main12Intra => "main-12-intra",
+        Profile::Main422_10Intra => "main-422-10-intra",
+        Profile::Main422_12Intra => "main-422-12-intra",
+        Profile::Main444Intra => "main-444-intra",
+        Profile::Main444_10Intra => "main-444-10-intra",
+        Profile::Main444_12Intra => "main-444-12-intra",
+        Profile::Main444_16Intra => "main-444-16-intra",
+        Profile::Main444StillPicture => "main-444-still-picture",
+        Profile::Main444_16StillPicture => "main-444-16-still-picture",
+        Profile::HighThroughput444 => "high-throughput-444",
+        Profile::HighThroughput444_10 => "high-throughput-444-10",
+        Profile::HighThroughput444_14 => "high-throughput-444-14",
+        Profile::HighThroughput444_16Intra => "high-throughput-444-16-intra",
+        Profile::ScreenExtendedMain => "screen-extended-main",
+        Profile::ScreenExtendedMain10 => "screen-extended-main-10",
+        Profile::ScreenExtendedMain444 => "screen-extended-main-444",
+        Profile::ScreenExtendedMain444_10 => "screen-extended-main-444-10",
+        Profile::ScreenExtendedHighThroughput444 => "screen-extended-high-throughput-444",
+        Profile::ScreenExtendedHighThroughput444_10 => "screen-extended-high-throughput-444-10",
+        Profile::ScreenExtendedHighThroughput444_14 => "screen-extended-high-throughput-444-14",
+        Profile::ScalableMain => "scalable-main",
+        Profile::ScalableMain10 => "scalable-main-10",
+        Profile::ScalableMonochrome => "scalable-monochrome",
+        Profile::ScalableMonochrome12 => "scalable-monochrome-12",
+        Profile::ScalableMonochrome16 => "scalable-monochrome-16",
+        Profile::ScalableMain444 => "scalable-main-444",
+        Profile::MultiviewMain => "multiview-main",
+        Profile::ThreeDeeMain => "3d-main",
+    })
+}
+
+/// The name GStreamer gives `level`, which is `None` for reserved levels and level 8.5.
+pub fn level_name(level: Level) -> Option<&'static str> {
+    Some(match level {
+        Level::L1 => "1",
+        Level::L2 => "2",
+        Level::L2_1 => "2.1",
+        Level::L3 => "3",
+        Level::L3_1 => "3.1",
+        Level::L4 => "4",
+        Level::L4_1 => "4.1",
+        Level::L5 => "5",
+        Level::L5_1 => "5.1",
+        Level::L5_2 => "5.2",
+        Level::L6 => "6",
+        Level::L6_1 => "6.1",
+        Level::L6_2 => "6.2",
+        Level::L8_5 | Level::Reserved(_) => return None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hex_literal::hex;
+
+    #[test]
+    fn interlaced_hvc1() {
+        // "Haivision 1080i25" SPS: 50 field pictures per second make 25 frames.
+        let sps = hex!(
+            "42 01 01 01 40 00 00 03 00 40 00 00 03 00 00 03 00 7b a0 03 c0 80 22 1f 79 e9 6e
+             44 a1 7f f8 00 08 00 13 50 10 10 1e d0 00 00 03 00 10 00 00 03 03 25 08 ff de 10
+             00 16 e3 60 00 05 dd 77 df 08 04 10"
+        );
+        let mut ctx = Context::new();
+        ctx.put_param_set_nal(&sps);
+        let caps = Caps::new(&ctx, &[&sps], StreamFormat::Hvc1, Alignment::Au).unwrap();
+        assert_eq!(caps.framerate, Some((25, 1)));
+        assert_eq!(caps.profile, Some("main"));
+        let codec_data = caps.codec_data.as_ref().unwrap();
+        assert_eq!(codec_data[0], 1);
+        let s = caps.to_string();
+        assert!(s.starts_with("video/x-h265, stream-format=(string)hvc1, alignment=(string)au"));
+        let hex: String = codec_data.iter().map(|b| format!("{:02x}", b)).collect();
+        assert!(s.ends_with(&format!("codec_data=(buffer){}", hex)));
+
+        assert!(matches!(
+            Caps::new(&Context::new(), &[], StreamFormat::Hev1, Alignment::Nal),
+            Err(SampleEntryError::NoSeqParamSet)
+        ));
+    }
+
+    #[test]
+    fn reduced() {
+        assert_eq!(reduce(60000, 2002), (30000, 1001));
+        assert_eq!(reduce(50, 2), (25, 1));
+        assert_eq!(reduce(u64::from(u32::MAX) * 2, 1), (u32::MAX, 1));
+    }
+}
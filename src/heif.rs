@@ -0,0 +1,270 @@
+//! Support for HEVC image items in HEIF (ISO/IEC 23008-12) files such as HEIC images: reading
+//! an item's `hvcC` property and checking it against the still picture profiles.
+
+use crate::hvcc::{DecoderConfigRecord, SampleError};
+use crate::nal::pps::PicParameterSet;
+use crate::nal::sei::{HeaderType, SeiReader};
+use crate::nal::sps::{ChromaFormat, ColourDescription, Profile, SeqParameterSet, SpsError};
+use crate::nal::vps::VideoParameterSet;
+use crate::nal::{NalHeader, UnitType};
+use crate::rbsp::{decode_nal, BitReader};
+use crate::Context;
+
+#[derive(Debug)]
+pub enum ImageError {
+    BadConfig(SampleError),
+    /// The configuration has no SPS.
+    NoSeqParamSet,
+    BadSeqParamSet(SpsError),
+}
+impl ImageError {
+    /// See [`crate::error_code`].
+    pub fn code(&self) -> u32 {
+        crate::error_code::HEIF_IMAGE
+            + match self {
+                ImageError::BadConfig(..) => 1,
+                ImageError::NoSeqParamSet => 2,
+                ImageError::BadSeqParamSet(..) => 3,
+            }
+    }
+}
+impl From<SampleError> for ImageError {
+    fn from(e: SampleError) -> Self {
+        ImageError::BadConfig(e)
+    }
+}
+impl From<SpsError> for ImageError {
+    fn from(e: SpsError) -> Self {
+        ImageError::BadSeqParamSet(e)
+    }
+}
+
+/// A constraint of the signalled still picture profile which the SPS doesn't meet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StillPictureViolation {
+    BitDepth {
+        luma: u32,
+        chroma: u32,
+    },
+    ChromaFormat(ChromaFormat),
+    /// `sps_max_dec_pic_buffering_minus1`, which must be 0.
+    DecPicBuffering(u32),
+}
+
+/// What HEIF tooling needs to know about an image item.
+#[derive(Debug, Clone)]
+pub struct ImageInfo {
+    pub profile: Profile,
+    /// The cropped picture size, for the `ispe` property.
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth_luma: u32,
+    pub bit_depth_chroma: u32,
+    pub chroma_format: ChromaFormat,
+    /// For the `colr` property, if signalled.
+    pub colour_description: Option<ColourDescription>,
+    pub video_full_range_flag: bool,
+    /// The configuration carries an `alpha_channel_info()` SEI message, as the configuration of
+    /// an alpha auxiliary image item may.
+    pub alpha: bool,
+    /// Empty unless the SPS claims conformance to a still picture profile and doesn't meet its
+    /// constraints.
+    pub violations: Vec<StillPictureViolation>,
+}
+
+/// An image item's parameter sets, parsed from its `hvcC` property.
+///
+/// ```
+/// use hevc_reader::heif::ImageConfig;
+/// # let sps = [
+/// #     0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03, 0x00, 0x00,
+/// #     0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46, 0xd1, 0x2e,
+/// #     0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10, 0x00, 0x00, 0x03,
+/// #     0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00, 0x0b, 0xb8, 0x48,
+/// # ];
+/// let mut record = vec![1, 1, 0x60, 0, 0, 0, 0xb0, 0, 0, 0, 0, 0, 0x5d, 0xf0, 0, 0xfc];
+/// record.extend_from_slice(&[0xfd, 0xf8, 0xf8, 0, 0, 0x0f, 1, 0xa1, 0, 1, 0, sps.len() as u8]);
+/// record.extend_from_slice(&sps);
+/// let config = ImageConfig::parse(&record).unwrap();
+/// let info = config.info().unwrap();
+/// assert_eq!((info.width, info.height, info.bit_depth_luma), (720, 576, 8));
+/// assert!(!info.alpha);
+/// ```
+pub struct ImageConfig {
+    ctx: Context,
+    sps: Option<SeqParameterSet>,
+    alpha: bool,
+}
+impl ImageConfig {
+    /// Parses the `HEVCDecoderConfigurationRecord` of an `hvcC` item property. The first SPS is
+    /// the image's; VPS and PPS NAL units which don't parse are skipped.
+    pub fn parse(record: &[u8]) -> Result<Self, ImageError> {
+        let record = DecoderConfigRecord::parse(record)?;
+        let mut config = ImageConfig {
+            ctx: Context::new(),
+            sps: None,
+            alpha: false,
+        };
+        for nal in record.nal_units {
+            let Some(header) = nal
+                .first()
+                .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())
+            else {
+                continue;
+            };
+            let Ok(rbsp) = decode_nal(nal) else {
+                continue;
+            };
+            match header.nal_unit_type() {
+                UnitType::VideoParameterSet => {
+                    if let Ok(vps) = VideoParameterSet::from_bits(BitReader::new(&*rbsp)) {
+                        config.ctx.put_video_param_set(vps);
+                    }
+                }
+                UnitType::SeqParameterSet => {
+                    let sps = SeqParameterSet::from_bits(BitReader::new(&*rbsp))?;
+                    config.ctx.put_seq_param_set(sps.clone());
+                    config.sps.get_or_insert(sps);
+                }
+                UnitType::PicParameterSet => {
+                    if let Ok(pps) = PicParameterSet::from_bits(&config.ctx, BitReader::new(&*rbsp))
+                    {
+                        config.ctx.put_pic_param_set(pps);
+                    }
+                }
+                UnitType::PrefixSEI => {
+                    let mut reader = SeiReader::from_rbsp_bytes(&rbsp);
+                    while let Ok(Some(msg)) = reader.next() {
+                        config.alpha |= msg.payload_type == HeaderType::AlphaChannelInfo;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(config)
+    }
+
+    /// The parameter sets, for decoding the item's slice segments.
+    pub fn context(&self) -> &Context {
+        &self.ctx
+    }
+
+    pub fn info(&self) -> Result<ImageInfo, ImageError> {
+        let sps = self.sps.as_ref().ok_or(ImageError::NoSeqParamSet)?;
+        let (width, height) = sps.pixel_dimensions()?;
+        let signal = sps
+            .vui_parameters
+            .as_ref()
+            .and_then(|v| v.video_signal_type.as_ref());
+        Ok(ImageInfo {
+            profile: sps.general_profile(),
+            width,
+            height,
+            bit_depth_luma: sps.bit_depth_luma_minus8 + 8,
+            bit_depth_chroma: sps.bit_depth_chroma_minus8 + 8,
+            chroma_format: sps.chroma_info.chroma_format,
+            colour_description: signal.and_then(|s| s.colour_description.clone()),
+            video_full_range_flag: signal.is_some_and(|s| s.video_full_range_flag),
+            alpha: self.alpha,
+            violations: still_picture_violations(sps),
+        })
+    }
+}
+
+/// Checks the constraints of clause A.3 which the first still picture profile the SPS claims
+/// conformance to puts on it.
+fn still_picture_violations(sps: &SeqParameterSet) -> Vec<StillPictureViolation> {
+    let limits = sps
+        .general_layer_profile()
+        .compatible_profiles()
+        .into_iter()
+        .find_map(|profile| match profile {
+            Profile::MainStillPicture => Some((8, true)),
+            Profile::Main10StillPicture => Some((10, true)),
+            Profile::Main444StillPicture => Some((8, false)),
+            Profile::Main444_16StillPicture => Some((16, false)),
+            _ => None,
+        });
+    let Some((max_bit_depth, only_420)) = limits else {
+        return vec![];
+    };
+    let mut violations = vec![];
+    let (luma, chroma) = (
+        sps.bit_depth_luma_minus8 + 8,
+        sps.bit_depth_chroma_minus8 + 8,
+    );
+    if luma > max_bit_depth || chroma > max_bit_depth {
+        violations.push(StillPictureViolation::BitDepth { luma, chroma });
+    }
+    let chroma_format = sps.chroma_info.chroma_format;
+    if only_420 && chroma_format != ChromaFormat::YUV420
+        || matches!(chroma_format, ChromaFormat::Invalid(_))
+    {
+        violations.push(StillPictureViolation::ChromaFormat(chroma_format));
+    }
+    if let Some(info) = sps.sub_layering_ordering_info.last() {
+        if info.sps_max_dec_pic_buffering_minus1 != 0 {
+            violations.push(StillPictureViolation::DecPicBuffering(
+                info.sps_max_dec_pic_buffering_minus1,
+            ));
+        }
+    }
+    violations
+}
+
+/// Returns true if an `auxC` item property's `aux_type` marks an alpha plane.
+pub fn is_alpha_aux_type(aux_type: &str) -> bool {
+    matches!(
+        aux_type,
+        "urn:mpeg:mpegB:cicp:systems:auxiliary:alpha" | "urn:mpeg:hevc:2015:auxid:1"
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rbsp::encode_nal;
+    use hex_literal::hex;
+
+    // "Intinor HW encode 720x576p" SPS, also signalling compatibility with Main Still Picture.
+    const SPS: [u8; 59] = hex!(
+        "42 01 01 01 70 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46 d1
+         2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00 0b
+         b8 48"
+    );
+    const PPS: [u8; 7] = hex!("44 01 c1 72 b4 62 40");
+
+    fn record(nals: &[&[u8]]) -> Vec<u8> {
+        let mut record =
+            hex!("01 03 60 00 00 00 b0 00 00 00 00 00 5d f0 00 fc fd f8 f8 00 00 0f").to_vec();
+        record.push(nals.len() as u8);
+        for nal in nals {
+            record.extend_from_slice(&[nal[0] >> 1, 0, 1]);
+            record.extend_from_slice(&(nal.len() as u16).to_be_bytes());
+            record.extend_from_slice(nal);
+        }
+        record
+    }
+
+    #[test]
+    fn still_picture_with_alpha() {
+        // alpha_channel_info() with alpha_channel_cancel_flag set.
+        let sei = encode_nal(&[0x4e, 0x01], &[0xa5, 0x01, 0x80, 0x80]);
+        let config = ImageConfig::parse(&record(&[&SPS, &PPS, &sei])).unwrap();
+        assert_eq!(config.context().pps().count(), 1);
+        let info = config.info().unwrap();
+        assert_eq!(info.profile, Profile::Main);
+        assert!(info.alpha);
+        assert!(matches!(
+            info.violations[..],
+            [StillPictureViolation::DecPicBuffering(_)]
+        ));
+
+        let config = ImageConfig::parse(&record(&[&PPS])).unwrap();
+        assert!(matches!(config.info(), Err(ImageError::NoSeqParamSet)));
+        assert!(matches!(
+            ImageConfig::parse(&record(&[&SPS])[..30]),
+            Err(ImageError::BadConfig(SampleError::Truncated { offset: 26 }))
+        ));
+    }
+}
@@ -0,0 +1,220 @@
+//! Support for HEVC carried in ISO BMFF (MP4) files, where samples are sequences of NAL units
+//! each prefixed by its length, as configured by the `hvcC` box's `lengthSizeMinusOne`.
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SampleError {
+    /// The NAL unit length size was not 1, 2 or 4 bytes.
+    InvalidLengthSize(usize),
+    /// A length prefix or the NAL unit it describes extends beyond the end of the sample.
+    Truncated { offset: usize },
+    /// A NAL unit of this many bytes doesn't fit the NAL unit length size.
+    NalUnitTooLong { len: usize, length_size: usize },
+}
+impl SampleError {
+    /// See [`crate::error_code`].
+    pub fn code(&self) -> u32 {
+        crate::error_code::SAMPLE
+            + match self {
+                SampleError::InvalidLengthSize(..) => 1,
+                SampleError::Truncated { .. } => 2,
+                SampleError::NalUnitTooLong { .. } => 3,
+            }
+    }
+}
+
+pub(crate) fn check_length_size(length_size: usize) -> Result<(), SampleError> {
+    match length_size {
+        1 | 2 | 4 => Ok(()),
+        _ => Err(SampleError::InvalidLengthSize(length_size)),
+    }
+}
+
+/// Iterates the NAL units of a length-prefixed sample.
+///
+/// ```
+/// use hevc_reader::hvcc::nal_units;
+/// let sample = [0, 0, 0, 2, 0x46, 0x01, 0, 0, 0, 3, 0x26, 0x01, 0xac];
+/// let nals: Vec<_> = nal_units(&sample, 4).unwrap().collect::<Result<_, _>>().unwrap();
+/// assert_eq!(nals, [&[0x46, 0x01][..], &[0x26, 0x01, 0xac]]);
+/// ```
+pub fn nal_units(sample: &[u8], length_size: usize) -> Result<NalUnits<'_>, SampleError> {
+    check_length_size(length_size)?;
+    Ok(NalUnits {
+        sample,
+        offset: 0,
+        length_size,
+    })
+}
+
+/// Iterator returned by [`nal_units`].
+pub struct NalUnits<'a> {
+    sample: &'a [u8],
+    offset: usize,
+    length_size: usize,
+}
+impl<'a> Iterator for NalUnits<'a> {
+    type Item = Result<&'a [u8], SampleError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.sample.len() {
+            return None;
+        }
+        let offset = self.offset;
+        let start = offset + self.length_size;
+        let Some(prefix) = self.sample.get(offset..start) else {
+            self.offset = self.sample.len();
+            return Some(Err(SampleError::Truncated { offset }));
+        };
+        let len = prefix
+            .iter()
+            .fold(0usize, |len, &b| (len << 8) | usize::from(b));
+        let Some(nal) = self.sample.get(start..start + len) else {
+            self.offset = self.sample.len();
+            return Some(Err(SampleError::Truncated { offset }));
+        };
+        self.offset = start + len;
+        Some(Ok(nal))
+    }
+}
+
+/// Appends `nal` to `sample`, prefixed by its length in `length_size` bytes.
+///
+/// Panics if the length doesn't fit or `length_size` isn't 1, 2 or 4; use a `length_size` of 4
+/// for arbitrary NAL units.
+pub fn push_nal_unit(sample: &mut Vec<u8>, nal: &[u8], length_size: usize) {
+    if let Err(e) = try_push_nal_unit(sample, nal, length_size) {
+        panic!("{e:?}");
+    }
+}
+
+/// Appends `nal` to `sample` as [`push_nal_unit`] does, failing if the length doesn't fit or
+/// `length_size` is invalid.
+pub fn try_push_nal_unit(
+    sample: &mut Vec<u8>,
+    nal: &[u8],
+    length_size: usize,
+) -> Result<(), SampleError> {
+    check_length_size(length_size)?;
+    if (nal.len() as u64) >> (8 * length_size) != 0 {
+        return Err(SampleError::NalUnitTooLong {
+            len: nal.len(),
+            length_size,
+        });
+    }
+    sample.extend_from_slice(&(nal.len() as u64).to_be_bytes()[8 - length_size..]);
+    sample.extend_from_slice(nal);
+    Ok(())
+}
+
+/// The NAL unit length size and NAL units of an `HEVCDecoderConfigurationRecord`, the payload of
+/// an `hvcC` box or HEIF item property.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecoderConfigRecord<'a> {
+    /// `lengthSizeMinusOne + 1`, the size of the NAL unit lengths in samples.
+    pub length_size: usize,
+    /// The NAL units of every array, in the order of the record.
+    pub nal_units: Vec<&'a [u8]>,
+}
+impl<'a> DecoderConfigRecord<'a> {
+    /// Parses the record. The fields describing the stream's profile and format are skipped;
+    /// they repeat what the parameter sets say.
+    ///
+    /// ```
+    /// use hevc_reader::hvcc::DecoderConfigRecord;
+    /// let mut record = vec![1, 1, 0x60, 0, 0, 0, 0xb0, 0, 0, 0, 0, 0, 0x5d, 0xf0, 0, 0xfc];
+    /// record.extend_from_slice(&[0xfd, 0xf8, 0xf8, 0, 0, 0x0f]);
+    /// // One array, of one PPS.
+    /// record.extend_from_slice(&[1, 0xa2, 0, 1, 0, 7, 0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40]);
+    /// let config = DecoderConfigRecord::parse(&record).unwrap();
+    /// assert_eq!(config.length_size, 4);
+    /// assert_eq!(config.nal_units, [&[0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40][..]]);
+    /// ```
+    pub fn parse(record: &'a [u8]) -> Result<Self, SampleError> {
+        // 22 bytes of profile, level and format fields precede `numOfArrays`.
+        let (Some(&lengths), Some(&num_arrays)) = (record.get(21), record.get(22)) else {
+            return Err(SampleError::Truncated { offset: 0 });
+        };
+        let length_size = usize::from(lengths & 0x3) + 1;
+        check_length_size(length_size)?;
+        let (nal_units, end) = read_arrays(record, num_arrays);
+        end?;
+        Ok(DecoderConfigRecord {
+            length_size,
+            nal_units,
+        })
+    }
+}
+
+/// Reads `num_arrays` arrays of NAL units, starting after `numOfArrays`. Returns the NAL units
+/// read and the end of the last array, or the offset of the first which is truncated along with
+/// the NAL units before it.
+pub(crate) fn read_arrays(
+    record: &[u8],
+    num_arrays: u8,
+) -> (Vec<&[u8]>, Result<usize, SampleError>) {
+    let mut offset = 23;
+    let mut nal_units = vec![];
+    for _ in 0..num_arrays {
+        let Some(num_nalus) = record.get(offset + 1..offset + 3) else {
+            return (nal_units, Err(SampleError::Truncated { offset }));
+        };
+        let num_nalus = u16::from_be_bytes([num_nalus[0], num_nalus[1]]);
+        offset += 3;
+        for _ in 0..num_nalus {
+            let Some(len) = record.get(offset..offset + 2) else {
+                return (nal_units, Err(SampleError::Truncated { offset }));
+            };
+            let len = usize::from(u16::from_be_bytes([len[0], len[1]]));
+            let Some(nal) = record.get(offset + 2..offset + 2 + len) else {
+                return (nal_units, Err(SampleError::Truncated { offset }));
+            };
+            nal_units.push(nal);
+            offset += 2 + len;
+        }
+    }
+    (nal_units, Ok(offset))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        for length_size in [1, 2, 4] {
+            let mut sample = vec![];
+            push_nal_unit(&mut sample, &[0x40, 0x01, 0x0c], length_size);
+            push_nal_unit(&mut sample, &[0x26, 0x01], length_size);
+            let nals: Vec<_> = nal_units(&sample, length_size)
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+            assert_eq!(nals, [&[0x40, 0x01, 0x0c][..], &[0x26, 0x01]]);
+        }
+        assert_eq!(
+            nal_units(&[], 3).err(),
+            Some(SampleError::InvalidLengthSize(3))
+        );
+        for length_size in [0, 3, 8, 9] {
+            assert_eq!(
+                try_push_nal_unit(&mut vec![], &[0x26, 0x01], length_size),
+                Err(SampleError::InvalidLengthSize(length_size))
+            );
+        }
+        assert_eq!(
+            try_push_nal_unit(&mut vec![], &[0; 256], 1),
+            Err(SampleError::NalUnitTooLong {
+                len: 256,
+                length_size: 1
+            })
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let mut nals = nal_units(&[0, 2, 0x26, 0x01, 0, 5, 0x26], 2).unwrap();
+        assert_eq!(nals.next(), Some(Ok(&[0x26, 0x01][..])));
+        assert_eq!(nals.next(), Some(Err(SampleError::Truncated { offset: 4 })));
+        assert_eq!(nals.next(), None);
+    }
+}
@@ -0,0 +1,524 @@
+//! An index of the NAL units of an Annex B recording, which can be stored next to it and
+//! loaded instead of scanning the recording again.
+//!
+//! The index holds the byte range and type of each NAL unit, the access unit boundaries, and
+//! the POC of each picture. Its serialized form is compact, a few bytes per NAL unit:
+//!
+//! * the magic `HEVCNIDX` and a version byte, currently 1;
+//! * the length of the indexed stream and the number of entries, as LEB128 varints;
+//! * per entry, the NAL unit type, a byte of flags (1: starts an access unit, 2: starts a
+//!   picture, 4: has a POC), the bytes since the end of the previous NAL unit and the length of
+//!   the NAL unit as varints, and the POC as a zigzag varint if present.
+//!
+//! With a frame rate, the index also tells where to start decoding to present the picture at a
+//! given time, see [`NalIndex::seek`].
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::annexb::nal_units;
+use crate::nal::{AccessUnitTracker, CvsTracker, NalHeader, UnitType};
+use crate::rewrite::trim::RandomAccessPoint;
+use crate::validate::references::ReferenceTracker;
+use crate::FrameRate;
+
+const MAGIC: &[u8; 8] = b"HEVCNIDX";
+const VERSION: u8 = 1;
+
+const ACCESS_UNIT_START: u8 = 1;
+const PICTURE_START: u8 = 2;
+const HAS_POC: u8 = 4;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum IndexError {
+    /// The data doesn't start with the magic of an index.
+    BadMagic,
+    UnsupportedVersion(u8),
+    /// The data ends within the header or an entry.
+    Truncated,
+    /// An entry has an invalid NAL unit type or flags, or a byte range overlapping the previous
+    /// entry or beyond the stream.
+    InvalidEntry(usize),
+    /// The data continues after the last entry.
+    TrailingData,
+}
+impl IndexError {
+    /// See [`crate::error_code`].
+    pub fn code(&self) -> u32 {
+        crate::error_code::INDEX
+            + match self {
+                IndexError::BadMagic => 1,
+                IndexError::UnsupportedVersion(..) => 2,
+                IndexError::Truncated => 3,
+                IndexError::InvalidEntry(..) => 4,
+                IndexError::TrailingData => 5,
+            }
+    }
+}
+
+/// A NAL unit of a [`NalIndex`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// The offset of the NAL unit, just past its start code.
+    pub offset: u64,
+    /// The length of the NAL unit, without start code or trailing zero bytes.
+    pub len: u64,
+    pub unit_type: UnitType,
+    /// True for the first NAL unit of an access unit.
+    pub access_unit_start: bool,
+    /// True for the first slice segment of a picture.
+    pub picture_start: bool,
+    /// `PicOrderCntVal` of the picture, for the first slice segment of a picture of layer 0
+    /// whose slice header could be parsed.
+    pub poc: Option<i32>,
+}
+impl IndexEntry {
+    /// The byte range of the NAL unit within the stream.
+    pub fn range(&self) -> Range<u64> {
+        self.offset..self.offset + self.len
+    }
+
+    /// True for the first slice segment of an IRAP picture.
+    pub fn is_irap_picture_start(&self) -> bool {
+        self.picture_start && self.unit_type.is_irap()
+    }
+}
+
+/// Where to start decoding for a target time, as returned by [`NalIndex::seek`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeekPoint {
+    /// The offset of the access unit of the IRAP picture, just past its start code.
+    pub offset: u64,
+    /// The index of the entry of the first slice segment of the IRAP picture.
+    pub irap: usize,
+    /// The presentation time of the IRAP picture, in seconds from the first picture of the
+    /// stream.
+    pub time: f64,
+    /// The entries of the first slice segments of the leading pictures of the IRAP picture,
+    /// which are presented before it and so are to be discarded: RASL pictures can't even be
+    /// decoded without the pictures preceding the IRAP picture.
+    pub discard: Vec<usize>,
+}
+
+/// The NAL units of an Annex B stream, in stream order.
+///
+/// ```
+/// use hevc_reader::index::NalIndex;
+/// // An access unit delimiter and an IDR slice segment, without its parameter sets.
+/// let stream = b"\x00\x00\x00\x01\x46\x01\x10\x00\x00\x01\x26\x01\xac\x9c";
+/// let index = NalIndex::from_annexb(stream);
+/// assert_eq!(index.entries.len(), 2);
+/// assert_eq!(index.entries[1].range(), 10..14);
+/// assert!(index.entries[1].is_irap_picture_start());
+///
+/// let bytes = index.to_bytes().unwrap();
+/// assert_eq!(NalIndex::from_bytes(&bytes).unwrap(), index);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NalIndex {
+    /// The length of the indexed stream, to tell whether an index still matches its stream.
+    pub stream_len: u64,
+    /// The NAL units in stream order, none starting before the end of the previous one.
+    pub entries: Vec<IndexEntry>,
+}
+impl NalIndex {
+    /// Indexes an in-memory (or memory-mapped) Annex B stream.
+    pub fn from_annexb(data: &[u8]) -> Self {
+        let mut builder = IndexBuilder::new();
+        for (offset, nal) in nal_units(data) {
+            builder.push_nal(offset as u64, nal);
+        }
+        builder.finish(data.len() as u64)
+    }
+
+    /// The IRAP access units, to pass to [`crate::rewrite::trim::extract`].
+    pub fn random_access_points(&self) -> Vec<RandomAccessPoint> {
+        let mut raps = vec![];
+        let mut au_start = 0;
+        let mut pictures = 0;
+        for entry in &self.entries {
+            if entry.access_unit_start {
+                au_start = entry.offset;
+            }
+            if entry.picture_start {
+                if entry.unit_type.is_irap() {
+                    raps.push(RandomAccessPoint {
+                        offset: au_start as usize,
+                        picture: pictures,
+                        unit_type: entry.unit_type,
+                    });
+                }
+                pictures += 1;
+            }
+        }
+        raps
+    }
+
+    /// Finds the IRAP picture to start decoding at to present the picture at `time`, in seconds
+    /// from the first picture of the stream: the last one presented at or before `time`, or the
+    /// first one if `time` precedes it. Returns `None` if no IRAP picture has a POC.
+    ///
+    /// Presentation times follow from output order: each coded picture is presented for one
+    /// clock tick of `frame_rate`, in ascending POC order within each coded video sequence.
+    /// The parameter sets of the IRAP picture may precede its access unit, see
+    /// [`NalParser::resume`](crate::push::parse::NalParser::resume).
+    pub fn seek(&self, frame_rate: &FrameRate, time: f64) -> Option<SeekPoint> {
+        // The pictures with a POC, keyed by coded video sequence and POC.
+        let mut pictures = vec![];
+        let mut cvs = 0;
+        let mut cvs_tracker = CvsTracker::default();
+        for (i, entry) in self.entries.iter().enumerate() {
+            match entry.unit_type {
+                UnitType::EndOfSeq | UnitType::EndOfStream => cvs_tracker.end_sequence(),
+                t if entry.picture_start => {
+                    if cvs_tracker.push_picture(t) {
+                        cvs += 1;
+                    }
+                    if let Some(poc) = entry.poc {
+                        pictures.push(((cvs, poc), i));
+                    }
+                }
+                _ => {}
+            }
+        }
+        let mut output_order = pictures.clone();
+        output_order.sort_by_key(|&(key, _)| key);
+        let rank: HashMap<usize, usize> = output_order
+            .iter()
+            .enumerate()
+            .map(|(rank, &(_, i))| (i, rank))
+            .collect();
+        let tick = 1.0 / frame_rate.pictures_per_second();
+        let time_of = |i: usize| rank[&i] as f64 * tick;
+
+        let mut iraps = pictures
+            .iter()
+            .map(|&(_, i)| i)
+            .filter(|&i| self.entries[i].unit_type.is_irap());
+        let first = iraps.next()?;
+        let irap = iraps
+            .take_while(|&i| time_of(i) <= time)
+            .last()
+            .unwrap_or(first);
+        let offset = self.entries[..=irap]
+            .iter()
+            .rev()
+            .find(|e| e.access_unit_start)
+            .map_or(self.entries[irap].offset, |e| e.offset);
+        let discard = self.entries[irap + 1..]
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| e.picture_start)
+            .map(|(j, e)| (irap + 1 + j, e.unit_type))
+            .take_while(|&(_, t)| !t.is_irap())
+            .filter(|&(_, t)| {
+                matches!(
+                    t,
+                    UnitType::SliceSegmentLayerRadlN
+                        | UnitType::SliceSegmentLayerRadlR
+                        | UnitType::SliceSegmentLayerRaslN
+                        | UnitType::SliceSegmentLayerRaslR
+                )
+            })
+            .map(|(j, _)| j)
+            .collect();
+        Some(SeekPoint {
+            offset,
+            irap,
+            time: time_of(irap),
+            discard,
+        })
+    }
+
+    /// Serializes the index, in the format described in the [module docs](self).
+    ///
+    /// Returns [`IndexError::InvalidEntry`] for an entry starting before the end of the
+    /// previous one or ending beyond `stream_len`, which [`NalIndex::from_bytes`] would reject.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, IndexError> {
+        let mut out = Vec::with_capacity(MAGIC.len() + 1 + 20 + self.entries.len() * 6);
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        write_varint(&mut out, self.stream_len);
+        write_varint(&mut out, self.entries.len() as u64);
+        let mut end = 0;
+        for (i, entry) in self.entries.iter().enumerate() {
+            let gap = entry
+                .offset
+                .checked_sub(end)
+                .ok_or(IndexError::InvalidEntry(i))?;
+            end = entry
+                .offset
+                .checked_add(entry.len)
+                .filter(|&end| end <= self.stream_len)
+                .ok_or(IndexError::InvalidEntry(i))?;
+            let mut flags = 0;
+            if entry.access_unit_start {
+                flags |= ACCESS_UNIT_START;
+            }
+            if entry.picture_start {
+                flags |= PICTURE_START;
+            }
+            if entry.poc.is_some() {
+                flags |= HAS_POC;
+            }
+            out.push(entry.unit_type.id());
+            out.push(flags);
+            write_varint(&mut out, gap);
+            write_varint(&mut out, entry.len);
+            if let Some(poc) = entry.poc {
+                write_varint(&mut out, u64::from(((poc << 1) ^ (poc >> 31)) as u32));
+            }
+        }
+        Ok(out)
+    }
+
+    /// Loads an index serialized by [`NalIndex::to_bytes`].
+    pub fn from_bytes(data: &[u8]) -> Result<Self, IndexError> {
+        let rest = data.strip_prefix(MAGIC).ok_or(IndexError::BadMagic)?;
+        let (&version, mut rest) = rest.split_first().ok_or(IndexError::Truncated)?;
+        if version != VERSION {
+            return Err(IndexError::UnsupportedVersion(version));
+        }
+        let stream_len = read_varint(&mut rest)?;
+        let count = read_varint(&mut rest)?;
+        // Each entry takes at least four bytes.
+        let mut entries = Vec::with_capacity(count.min(rest.len() as u64 / 4) as usize);
+        let mut end = 0u64;
+        for i in 0..count {
+            let i = i as usize;
+            let [unit_type, flags, ..] = *rest else {
+                return Err(IndexError::Truncated);
+            };
+            rest = &rest[2..];
+            let unit_type = UnitType::for_id(unit_type).map_err(|_| IndexError::InvalidEntry(i))?;
+            if flags & !(ACCESS_UNIT_START | PICTURE_START | HAS_POC) != 0 {
+                return Err(IndexError::InvalidEntry(i));
+            }
+            let offset = end
+                .checked_add(read_varint(&mut rest)?)
+                .ok_or(IndexError::InvalidEntry(i))?;
+            let len = read_varint(&mut rest)?;
+            end = offset
+                .checked_add(len)
+                .filter(|&end| end <= stream_len)
+                .ok_or(IndexError::InvalidEntry(i))?;
+            let poc = if flags & HAS_POC != 0 {
+                let zigzag = u32::try_from(read_varint(&mut rest)?)
+                    .map_err(|_| IndexError::InvalidEntry(i))?;
+                Some((zigzag >> 1) as i32 ^ -((zigzag & 1) as i32))
+            } else {
+                None
+            };
+            entries.push(IndexEntry {
+                offset,
+                len,
+                unit_type,
+                access_unit_start: flags & ACCESS_UNIT_START != 0,
+                picture_start: flags & PICTURE_START != 0,
+                poc,
+            });
+        }
+        if !rest.is_empty() {
+            return Err(IndexError::TrailingData);
+        }
+        Ok(NalIndex {
+            stream_len,
+            entries,
+        })
+    }
+}
+
+/// Builds a [`NalIndex`], fed one NAL unit at a time in stream order with its offset, for
+/// streams which aren't in memory as a whole.
+#[derive(Default)]
+pub struct IndexBuilder {
+    tracker: AccessUnitTracker,
+    references: ReferenceTracker,
+    entries: Vec<IndexEntry>,
+}
+impl IndexBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a NAL unit (without start code) found at `offset`. NAL units with an invalid header,
+    /// or starting before the end of the previous NAL unit, are left out of the index.
+    pub fn push_nal(&mut self, offset: u64, nal: &[u8]) {
+        let Some(header) = nal
+            .first()
+            .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())
+        else {
+            return;
+        };
+        if self
+            .entries
+            .last()
+            .is_some_and(|e| offset < e.offset + e.len)
+        {
+            return;
+        }
+        let unit_type = header.nal_unit_type();
+        let access_unit_start = self.tracker.push(unit_type, nal);
+        let picture_start = unit_type.is_vcl() && nal.get(2).is_some_and(|b| b & 0x80 != 0);
+        let poc = self.references.push_nal(nal).map(|p| p.poc);
+        self.entries.push(IndexEntry {
+            offset,
+            len: nal.len() as u64,
+            unit_type,
+            access_unit_start,
+            picture_start,
+            poc,
+        });
+    }
+
+    /// Returns the index of a stream of `stream_len` bytes.
+    pub fn finish(self, stream_len: u64) -> NalIndex {
+        NalIndex {
+            stream_len,
+            entries: self.entries,
+        }
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &mut &[u8]) -> Result<u64, IndexError> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&b, rest) = data.split_first().ok_or(IndexError::Truncated)?;
+        *data = rest;
+        value |= u64::from(b & 0x7f) << shift;
+        if b & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(IndexError::Truncated)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hex_literal::hex;
+
+    #[test]
+    fn round_trip() {
+        // "Intinor HW encode 720x576p" SPS and its PPS, an IDR picture, and P pictures with
+        // POC 2 and 1.
+        let nals: [&[u8]; 5] = [
+            &hex!(
+                "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee
+                 46 d1 2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49
+                 3e 00 0b b8 48"
+            ),
+            &hex!("44 01 c1 72 b4 62 40"),
+            &hex!("26 01 ac 9c"),
+            &hex!("02 01 d0 ac 27 80"),
+            &hex!("02 01 d0 6c 27 80"),
+        ];
+        let mut stream = vec![];
+        for nal in nals {
+            stream.extend_from_slice(&[0, 0, 0, 1]);
+            stream.extend_from_slice(nal);
+        }
+        let index = NalIndex::from_annexb(&stream);
+        assert_eq!(
+            index
+                .entries
+                .iter()
+                .map(|e| (e.access_unit_start, e.poc))
+                .collect::<Vec<_>>(),
+            [
+                (true, None),
+                (false, None),
+                (false, Some(0)),
+                (true, Some(2)),
+                (true, Some(1)),
+            ]
+        );
+        assert_eq!(index.random_access_points()[0].offset, 4);
+
+        let bytes = index.to_bytes().unwrap();
+        assert_eq!(NalIndex::from_bytes(&bytes), Ok(index.clone()));
+        assert_eq!(
+            NalIndex::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(IndexError::Truncated)
+        );
+        // A one byte varint stream length, one short of the last NAL unit's end.
+        let mut short = bytes.clone();
+        assert!(short[MAGIC.len() + 1] < 0x80);
+        short[MAGIC.len() + 1] -= 1;
+        assert_eq!(
+            NalIndex::from_bytes(&short),
+            Err(IndexError::InvalidEntry(4))
+        );
+        let mut short = index.clone();
+        short.stream_len -= 1;
+        assert_eq!(short.to_bytes(), Err(IndexError::InvalidEntry(4)));
+        let mut overlapping = index;
+        overlapping.entries[2].offset = overlapping.entries[1].offset;
+        assert_eq!(overlapping.to_bytes(), Err(IndexError::InvalidEntry(2)));
+        assert_eq!(NalIndex::from_bytes(b"HEVC"), Err(IndexError::BadMagic));
+
+        // NAL units out of order are left out, rather than serialized with a negative gap.
+        let mut builder = IndexBuilder::new();
+        builder.push_nal(100, nals[2]);
+        builder.push_nal(102, nals[4]);
+        builder.push_nal(104, nals[4]);
+        let index = builder.finish(110);
+        assert_eq!(
+            index.entries.iter().map(|e| e.offset).collect::<Vec<_>>(),
+            [100, 104]
+        );
+        assert_eq!(NalIndex::from_bytes(&index.to_bytes().unwrap()), Ok(index));
+    }
+
+    #[test]
+    fn seek() {
+        // A CRA picture with POC 8 every 8 pictures, each followed by RASL pictures with POC
+        // 6 and 7, then trailing pictures; one NAL unit of 10 bytes per access unit.
+        let mut entries = vec![];
+        let mut push = |unit_type, poc| {
+            entries.push(IndexEntry {
+                offset: 4 + 14 * entries.len() as u64,
+                len: 10,
+                unit_type,
+                access_unit_start: true,
+                picture_start: true,
+                poc: Some(poc),
+            })
+        };
+        for cra_poc in [0, 8, 16] {
+            push(UnitType::SliceSegmentLayerCraNut, cra_poc);
+            if cra_poc > 0 {
+                push(UnitType::SliceSegmentLayerRaslN, cra_poc - 2);
+                push(UnitType::SliceSegmentLayerRaslN, cra_poc - 1);
+            }
+            for poc in cra_poc + 1..cra_poc + 6 {
+                push(UnitType::SliceSegmentLayerTrailR, poc);
+            }
+        }
+        let index = NalIndex {
+            stream_len: 4 + 14 * entries.len() as u64,
+            entries,
+        };
+        let rate = FrameRate {
+            num_units_in_tick: 1,
+            time_scale: 25,
+            source: crate::FrameRateSource::Vui,
+            field_pictures: false,
+        };
+        // POC 8 is presented at 0.32 s, POC 16 at 0.64 s.
+        let point = index.seek(&rate, 0.5).unwrap();
+        assert_eq!((point.irap, point.offset), (6, 88));
+        assert!((point.time - 0.32).abs() < 1e-9);
+        assert_eq!(point.discard, [7, 8]);
+        assert_eq!(index.seek(&rate, 0.7).unwrap().irap, 14);
+        assert_eq!(index.seek(&rate, -1.0).unwrap().irap, 0);
+    }
+}
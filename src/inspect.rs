@@ -0,0 +1,754 @@
+//! A generic tree of the syntax of a NAL unit, for analyzers which show every syntax element
+//! without depending on the parsed structs.
+//!
+//! [`inspect_nal`] parses a NAL unit through a [`VisitingReader`] and returns the elements read
+//! as a tree of [`SyntaxStructure`]s, named as in the syntax tables of the spec:
+//!
+//! ```
+//! use hevc_reader::inspect::inspect_nal;
+//! use hevc_reader::Context;
+//! let inspection = inspect_nal(&Context::new(), &[0x46, 0x01, 0x50]);
+//! assert!(inspection.error.is_none());
+//! assert_eq!(inspection.root.find("pic_type").unwrap().value, 2);
+//! assert_eq!(
+//!     inspection.root.to_string(),
+//!     "nal_unit 0..19
+//!   nal_unit_header 0..16
+//!     forbidden_zero_bit u(1) = 0
+//!     nal_unit_type u(6) = 35
+//!     nuh_layer_id u(6) = 0
+//!     nuh_temporal_id_plus1 u(3) = 1
+//!   access_unit_delimiter_rbsp 16..19
+//!     pic_type u(3) = 2
+//! "
+//! );
+//! ```
+//!
+//! [`measure_nal`] reads the same syntax into [`StructureSizes`] instead, totalling the bits of
+//! each kind of structure across many NAL units without building trees, and
+//! [`parse_with_offsets`] captures the position of each field alongside the parsed struct, for
+//! patching fields in place.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+
+use crate::nal::pps::{PicParameterSet, PpsError};
+#[cfg(feature = "slice")]
+use crate::nal::slice::{SliceHeaderError, SliceSegmentHeader};
+use crate::nal::sps::{SeqParameterSet, SpsError};
+use crate::nal::vps::{VideoParameterSet, VpsError};
+use crate::nal::{NalHeader, NalHeaderError, UnitType};
+use crate::rbsp::{
+    read_structure, BitRead, BitReader, BitReaderError, ByteReader, Descriptor, SyntaxElement,
+    SyntaxVisitor, VisitingReader,
+};
+use crate::Context;
+
+/// A node of a syntax tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyntaxNode {
+    Element(SyntaxElement),
+    Structure(SyntaxStructure),
+}
+impl SyntaxNode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SyntaxNode::Element(e) => e.name,
+            SyntaxNode::Structure(s) => s.name,
+        }
+    }
+
+    pub fn bits(&self) -> &Range<u64> {
+        match self {
+            SyntaxNode::Element(e) => &e.bits,
+            SyntaxNode::Structure(s) => &s.bits,
+        }
+    }
+}
+
+/// A syntax structure such as `vui_parameters()`, and the elements and structures within it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxStructure {
+    pub name: &'static str,
+    pub bits: Range<u64>,
+    pub children: Vec<SyntaxNode>,
+}
+impl SyntaxStructure {
+    pub fn bit_len(&self) -> u64 {
+        self.bits.end - self.bits.start
+    }
+
+    /// The first element called `name`, searching depth first.
+    pub fn find(&self, name: &str) -> Option<&SyntaxElement> {
+        self.children.iter().find_map(|c| match c {
+            SyntaxNode::Element(e) => (e.name == name).then_some(e),
+            SyntaxNode::Structure(s) => s.find(name),
+        })
+    }
+
+    /// The first structure called `name` within this one, searching depth first.
+    pub fn find_structure(&self, name: &str) -> Option<&SyntaxStructure> {
+        self.children.iter().find_map(|c| match c {
+            SyntaxNode::Element(_) => None,
+            SyntaxNode::Structure(s) if s.name == name => Some(s),
+            SyntaxNode::Structure(s) => s.find_structure(name),
+        })
+    }
+
+    fn fmt_indented(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        writeln!(
+            f,
+            "{:indent$}{} {}..{}",
+            "",
+            self.name,
+            self.bits.start,
+            self.bits.end,
+            indent = depth * 2
+        )?;
+        for child in &self.children {
+            match child {
+                SyntaxNode::Structure(s) => s.fmt_indented(f, depth + 1)?,
+                SyntaxNode::Element(e) => {
+                    write!(f, "{:indent$}{} ", "", e.name, indent = (depth + 1) * 2)?;
+                    match e.descriptor {
+                        Descriptor::U(n) => write!(f, "u({})", n)?,
+                        Descriptor::I(n) => write!(f, "i({})", n)?,
+                        Descriptor::Ue => f.write_str("ue(v)")?,
+                        Descriptor::Se => f.write_str("se(v)")?,
+                    }
+                    writeln!(f, " = {}", e.value)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+/// An indented listing, one node per line.
+impl fmt::Display for SyntaxStructure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+/// A [`SyntaxVisitor`] building a tree of what it's given.
+pub struct SyntaxTreeBuilder {
+    /// The structures entered, outermost first.
+    stack: Vec<SyntaxStructure>,
+}
+impl SyntaxTreeBuilder {
+    /// Starts a tree with a root structure called `name`.
+    pub fn new(name: &'static str) -> Self {
+        SyntaxTreeBuilder {
+            stack: vec![SyntaxStructure {
+                name,
+                bits: 0..0,
+                children: vec![],
+            }],
+        }
+    }
+
+    fn close(&mut self, end: u64) {
+        let mut s = self.stack.pop().unwrap();
+        s.bits.end = end;
+        self.stack
+            .last_mut()
+            .unwrap()
+            .children
+            .push(SyntaxNode::Structure(s));
+    }
+
+    /// Returns the root, closing any structures left open, e.g. by a visitor which stopped
+    /// reporting part way through.
+    pub fn finish(mut self) -> SyntaxStructure {
+        let end_of = |s: &SyntaxStructure| s.children.last().map_or(s.bits.start, |c| c.bits().end);
+        while self.stack.len() > 1 {
+            let end = end_of(self.stack.last().unwrap());
+            self.close(end);
+        }
+        let mut root = self.stack.pop().unwrap();
+        root.bits.end = end_of(&root);
+        root
+    }
+}
+impl SyntaxVisitor for SyntaxTreeBuilder {
+    fn element(&mut self, element: &SyntaxElement) {
+        self.stack
+            .last_mut()
+            .unwrap()
+            .children
+            .push(SyntaxNode::Element(element.clone()));
+    }
+    fn enter(&mut self, name: &'static str, position: u64) {
+        self.stack.push(SyntaxStructure {
+            name,
+            bits: position..position,
+            children: vec![],
+        });
+    }
+    fn exit(&mut self, position: u64) {
+        if self.stack.len() > 1 {
+            self.close(position);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum InspectError {
+    NalHeader(NalHeaderError),
+    Vps(VpsError),
+    Sps(SpsError),
+    Pps(PpsError),
+    RbspReaderError(BitReaderError),
+    #[cfg(feature = "slice")]
+    SliceHeader(SliceHeaderError),
+}
+impl InspectError {
+    /// See [`crate::error_code`].
+    pub fn code(&self) -> u32 {
+        crate::error_code::INSPECT
+            + match self {
+                InspectError::NalHeader(..) => 1,
+                InspectError::Vps(..) => 2,
+                InspectError::Sps(..) => 3,
+                InspectError::Pps(..) => 4,
+                InspectError::RbspReaderError(..) => 5,
+                #[cfg(feature = "slice")]
+                InspectError::SliceHeader(..) => 6,
+            }
+    }
+}
+impl From<NalHeaderError> for InspectError {
+    fn from(e: NalHeaderError) -> Self {
+        InspectError::NalHeader(e)
+    }
+}
+impl From<BitReaderError> for InspectError {
+    fn from(e: BitReaderError) -> Self {
+        InspectError::RbspReaderError(e)
+    }
+}
+impl From<VpsError> for InspectError {
+    fn from(e: VpsError) -> Self {
+        InspectError::Vps(e)
+    }
+}
+impl From<SpsError> for InspectError {
+    fn from(e: SpsError) -> Self {
+        InspectError::Sps(e)
+    }
+}
+impl From<PpsError> for InspectError {
+    fn from(e: PpsError) -> Self {
+        InspectError::Pps(e)
+    }
+}
+#[cfg(feature = "slice")]
+impl From<SliceHeaderError> for InspectError {
+    fn from(e: SliceHeaderError) -> Self {
+        InspectError::SliceHeader(e)
+    }
+}
+
+/// The result of [`inspect_nal`].
+#[derive(Debug)]
+pub struct Inspection {
+    /// The syntax read, up to the error if there was one.
+    pub root: SyntaxStructure,
+    pub error: Option<InspectError>,
+}
+
+/// Parses a complete NAL unit, including its header, into a tree rooted at `nal_unit`.
+///
+/// Positions count bits from the start of the NAL unit, excluding emulation prevention bytes.
+/// The RBSP of parameter sets, SEI, access unit delimiters and (with the `slice` feature) the
+/// slice segment header of slice segments is parsed; of other NAL units, only the header.
+/// Slice segments are parsed with the parameter sets in `ctx`.
+pub fn inspect_nal(ctx: &Context, nal: &[u8]) -> Inspection {
+    let mut builder = SyntaxTreeBuilder::new("nal_unit");
+    let error = read_nal(ctx, nal, &mut EndTracking::new(&mut builder)).err();
+    Inspection {
+        root: builder.finish(),
+        error,
+    }
+}
+
+/// The number of bits of one kind of syntax structure, see [`StructureSizes`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StructureSize {
+    pub count: u64,
+    pub bits: u64,
+}
+
+/// A [`SyntaxVisitor`] tallying the bits of each kind of syntax structure, e.g. to tell how much
+/// of a stream's bit rate goes on VUI or reference picture sets.
+///
+/// A structure's bits include those of the structures within it, so `vui_parameters` counts
+/// its `hrd_parameters` too.
+///
+/// ```
+/// use hevc_reader::inspect::{measure_nal, StructureSizes};
+/// use hevc_reader::Context;
+/// let mut sizes = StructureSizes::new();
+/// for nal in [&[0x46, 0x01, 0x50][..], &[0x46, 0x01, 0x10]] {
+///     measure_nal(&Context::new(), nal, &mut sizes).unwrap();
+/// }
+/// assert_eq!(sizes.get("nal_unit").count, 2);
+/// assert_eq!(sizes.get("nal_unit_header").bits, 32);
+/// assert_eq!(sizes.get("access_unit_delimiter_rbsp").bits, 6);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct StructureSizes {
+    sizes: BTreeMap<&'static str, StructureSize>,
+    /// The structures entered and not yet exited, with their start positions.
+    open: Vec<(&'static str, u64)>,
+}
+impl StructureSizes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The tally of structures called `name`, zero if there were none.
+    pub fn get(&self, name: &str) -> StructureSize {
+        self.sizes.get(name).copied().unwrap_or_default()
+    }
+
+    /// The tallies by structure name, in alphabetical order.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, StructureSize)> + '_ {
+        self.sizes.iter().map(|(&name, &size)| (name, size))
+    }
+}
+impl SyntaxVisitor for StructureSizes {
+    fn element(&mut self, _element: &SyntaxElement) {}
+    fn enter(&mut self, name: &'static str, position: u64) {
+        self.open.push((name, position));
+    }
+    fn exit(&mut self, position: u64) {
+        if let Some((name, start)) = self.open.pop() {
+            let size = self.sizes.entry(name).or_default();
+            size.count += 1;
+            size.bits += position - start;
+        }
+    }
+}
+
+/// Adds the structures of a complete NAL unit to `sizes`, including `nal_unit` for the whole.
+/// What's parsed is as for [`inspect_nal`].
+///
+/// On error the structures read up to the error are still counted.
+pub fn measure_nal(
+    ctx: &Context,
+    nal: &[u8],
+    sizes: &mut StructureSizes,
+) -> Result<(), InspectError> {
+    sizes.enter("nal_unit", 0);
+    let mut tracking = EndTracking::new(sizes);
+    let result = read_nal(ctx, nal, &mut tracking);
+    let end = tracking.end;
+    sizes.open.truncate(1);
+    sizes.exit(end);
+    result
+}
+
+/// The positions of the fields of a NAL unit, captured by [`parse_with_offsets`], for patching
+/// single fields in place without re-serializing the NAL unit.
+///
+/// ```
+/// use hevc_reader::inspect::parse_with_offsets;
+/// use hevc_reader::nal::sps::SeqParameterSet;
+/// # let mut nal = hex_literal::hex!(
+/// #     "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46 d1
+/// #      2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00 0b
+/// #      b8 48");
+/// let (sps, offsets) = parse_with_offsets(&nal, |r| SeqParameterSet::from_bits(r)).unwrap();
+/// assert_eq!(sps.profile_tier_level.general_level_idc, 93);
+/// let level = offsets.get("general_level_idc").unwrap();
+/// offsets.patch(&mut nal, level, 120).unwrap();
+/// let (sps, _) = parse_with_offsets(&nal, |r| SeqParameterSet::from_bits(r)).unwrap();
+/// assert_eq!(sps.profile_tier_level.general_level_idc, 120);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct FieldOffsets {
+    /// Positioned as in [`inspect_nal`], in bits from the start of the NAL unit, excluding
+    /// emulation prevention bytes.
+    fields: Vec<SyntaxElement>,
+    /// The indices of the emulation prevention bytes in the NAL unit.
+    emulation_prevention: Vec<usize>,
+}
+impl FieldOffsets {
+    /// The first field called `name`.
+    pub fn get(&self, name: &str) -> Option<&SyntaxElement> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+
+    /// Every field called `name`, e.g. one per sub-layer, in the order read.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a SyntaxElement> + 'a {
+        self.fields.iter().filter(move |f| f.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &SyntaxElement> {
+        self.fields.iter()
+    }
+
+    /// The position in the bytes of the NAL unit, counting emulation prevention bytes, of bit
+    /// `position` as given in a field's `bits`.
+    pub fn nal_bit(&self, position: u64) -> u64 {
+        let byte = position / 8;
+        if byte < 2 {
+            return position;
+        }
+        // The emulation prevention byte at index `e`, the `j`th, precedes RBSP byte `e - 2 - j`.
+        let rbsp_byte = byte as usize - 2;
+        let preceding = self
+            .emulation_prevention
+            .iter()
+            .enumerate()
+            .take_while(|&(j, &e)| e - 2 - j <= rbsp_byte)
+            .count();
+        position + 8 * preceding as u64
+    }
+
+    /// Overwrites the bits of a fixed length field of `nal`, which must be the NAL unit the
+    /// offsets were captured from.
+    ///
+    /// Fails, leaving `nal` unchanged, if the new value would need emulation prevention bytes
+    /// added or removed.
+    pub fn patch(
+        &self,
+        nal: &mut [u8],
+        field: &SyntaxElement,
+        value: u32,
+    ) -> Result<(), PatchError> {
+        let (Descriptor::U(n) | Descriptor::I(n)) = field.descriptor else {
+            return Err(PatchError::VariableLength(field.name));
+        };
+        if n < 32 && value >> n != 0 {
+            return Err(PatchError::ValueTooLarge {
+                field: field.name,
+                value,
+            });
+        }
+        let original = nal.to_vec();
+        for (i, position) in field.bits.clone().enumerate() {
+            let bit = self.nal_bit(position);
+            let byte = nal
+                .get_mut((bit / 8) as usize)
+                .ok_or(PatchError::OutOfRange(field.name))?;
+            let mask = 0x80 >> (bit % 8);
+            if value >> (n as usize - 1 - i) & 1 != 0 {
+                *byte |= mask;
+            } else {
+                *byte &= !mask;
+            }
+        }
+        if emulation_prevention_bytes(nal).as_ref() != Some(&self.emulation_prevention) {
+            nal.copy_from_slice(&original);
+            return Err(PatchError::EmulationPrevention(field.name));
+        }
+        Ok(())
+    }
+}
+impl SyntaxVisitor for FieldOffsets {
+    fn element(&mut self, element: &SyntaxElement) {
+        self.fields.push(element.clone());
+    }
+}
+
+/// The indices of the emulation prevention bytes in a NAL unit, or `None` if it contains a
+/// sequence which must not occur within one.
+fn emulation_prevention_bytes(nal: &[u8]) -> Option<Vec<usize>> {
+    let mut indices = vec![];
+    let mut zeros = 0;
+    for (i, &b) in nal.iter().enumerate().skip(2) {
+        match b {
+            0 if zeros >= 2 => return None,
+            0 => zeros += 1,
+            1 | 2 if zeros >= 2 => return None,
+            3 if zeros >= 2 => {
+                indices.push(i);
+                zeros = 0;
+            }
+            _ => zeros = 0,
+        }
+    }
+    Some(indices)
+}
+
+#[derive(Debug)]
+pub enum PatchError {
+    /// The field is `ue(v)` or `se(v)` coded, so a new value may need a different length.
+    VariableLength(&'static str),
+    /// The value doesn't fit the field.
+    ValueTooLarge { field: &'static str, value: u32 },
+    /// The field lies beyond the end of the NAL unit given.
+    OutOfRange(&'static str),
+    /// The new value would change where emulation prevention bytes are needed.
+    EmulationPrevention(&'static str),
+}
+impl PatchError {
+    /// See [`crate::error_code`].
+    pub fn code(&self) -> u32 {
+        crate::error_code::PATCH
+            + match self {
+                PatchError::VariableLength(..) => 1,
+                PatchError::ValueTooLarge { .. } => 2,
+                PatchError::OutOfRange(..) => 3,
+                PatchError::EmulationPrevention(..) => 4,
+            }
+    }
+}
+
+/// A reader of the RBSP of a NAL unit, reporting to a [`FieldOffsets`].
+pub type OffsetsReader<'v, 'a> = VisitingReader<'v, BitReader<ByteReader<&'a [u8]>>, FieldOffsets>;
+
+/// Parses the RBSP of a complete NAL unit with `parse`, e.g. `SeqParameterSet::from_bits`,
+/// capturing the position of each field read.
+pub fn parse_with_offsets<'a, T, E>(
+    nal: &'a [u8],
+    parse: impl FnOnce(OffsetsReader<'_, 'a>) -> Result<T, E>,
+) -> Result<(T, FieldOffsets), E> {
+    let mut offsets = FieldOffsets {
+        fields: vec![],
+        emulation_prevention: emulation_prevention_bytes(nal).unwrap_or_default(),
+    };
+    let parsed =
+        parse(VisitingReader::new(BitReader::from_nal(nal), &mut offsets).starting_at(16))?;
+    Ok((parsed, offsets))
+}
+
+/// Passes on what a [`VisitingReader`] reports, noting where the last syntax read ended.
+struct EndTracking<'a, V: SyntaxVisitor> {
+    visitor: &'a mut V,
+    end: u64,
+}
+impl<'a, V: SyntaxVisitor> EndTracking<'a, V> {
+    fn new(visitor: &'a mut V) -> Self {
+        EndTracking { visitor, end: 0 }
+    }
+}
+impl<V: SyntaxVisitor> SyntaxVisitor for EndTracking<'_, V> {
+    fn element(&mut self, element: &SyntaxElement) {
+        self.end = element.bits.end;
+        self.visitor.element(element);
+    }
+    fn enter(&mut self, name: &'static str, position: u64) {
+        self.visitor.enter(name, position);
+    }
+    fn exit(&mut self, position: u64) {
+        self.end = position;
+        self.visitor.exit(position);
+    }
+}
+
+fn read_nal<V: SyntaxVisitor>(
+    ctx: &Context,
+    nal: &[u8],
+    visitor: &mut EndTracking<'_, V>,
+) -> Result<(), InspectError> {
+    let first = *nal.first().ok_or(NalHeaderError::IncompleteHeader)?;
+    let header = NalHeader::new(first, nal.get(1).copied())?;
+    let fields = [
+        ("forbidden_zero_bit", 0, 0..1),
+        ("nal_unit_type", header.nal_unit_type().id(), 1..7),
+        ("nuh_layer_id", header.nuh_layer_id()?, 7..13),
+        ("nuh_temporal_id_plus1", nal[1] & 0b111, 13..16),
+    ];
+    visitor.enter("nal_unit_header", 0);
+    for (name, value, bits) in fields {
+        visitor.element(&SyntaxElement {
+            name,
+            descriptor: Descriptor::U((bits.end - bits.start) as u32),
+            value: value.into(),
+            bits,
+        });
+    }
+    visitor.exit(16);
+
+    // The parameter set parsers take the reader by value, so their structure is exited after
+    // the reader is gone, at the end of the last element read.
+    let mut r = VisitingReader::new(BitReader::from_nal(nal), visitor).starting_at(16);
+    match header.nal_unit_type() {
+        UnitType::VideoParameterSet => {
+            r.enter_structure("video_parameter_set_rbsp");
+            let result = VideoParameterSet::from_bits(r);
+            visitor.exit(visitor.end);
+            result?;
+        }
+        UnitType::SeqParameterSet => {
+            r.enter_structure("seq_parameter_set_rbsp");
+            let result = SeqParameterSet::from_bits(r);
+            visitor.exit(visitor.end);
+            result?;
+        }
+        UnitType::PicParameterSet => {
+            r.enter_structure("pic_parameter_set_rbsp");
+            let result = PicParameterSet::from_bits(ctx, r);
+            visitor.exit(visitor.end);
+            result?;
+        }
+        UnitType::AccessUnitDelimiter => {
+            read_structure(&mut r, "access_unit_delimiter_rbsp", |r| {
+                r.read_u8(3, "pic_type")
+            })?;
+        }
+        UnitType::PrefixSEI | UnitType::SuffixSEI => {
+            read_structure(&mut r, "sei_rbsp", read_sei)?;
+        }
+        #[cfg(feature = "slice")]
+        t if t.is_vcl() => {
+            read_structure(&mut r, "slice_segment_header", |r| {
+                SliceSegmentHeader::from_bits(ctx, r, header)
+            })?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Reads the `sei_message()`s of an `sei_rbsp()`, with their payloads as bytes.
+fn read_sei<R: BitRead>(r: &mut R) -> Result<(), BitReaderError> {
+    loop {
+        read_structure(r, "sei_message", |r| {
+            while r.read_u8(8, "payload_type_byte")? == 0xff {}
+            let mut payload_size = 0u32;
+            loop {
+                let byte = r.read_u8(8, "payload_size_byte")?;
+                // As `SeiReader` has it, a size past 32 bits is malformed.
+                payload_size = payload_size
+                    .checked_add(u32::from(byte))
+                    .ok_or(BitReaderError::ExpGolombTooLarge("payload_size"))?;
+                if byte != 0xff {
+                    break;
+                }
+            }
+            read_structure(r, "sei_payload", |r| {
+                for _ in 0..payload_size {
+                    r.read_u8(8, "payload_byte")?;
+                }
+                Ok(())
+            })
+        })?;
+        if !r.has_more_rbsp_data("sei_message")? {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hex_literal::hex;
+
+    // "Intinor HW encode 720x576p"
+    const SPS: [u8; 59] = hex!(
+        "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46 d1
+         2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00 0b
+         b8 48"
+    );
+
+    #[test]
+    fn param_sets() {
+        let sps = inspect_nal(&Context::new(), &SPS);
+        assert!(sps.error.is_none());
+        let rbsp = sps.root.find_structure("seq_parameter_set_rbsp").unwrap();
+        assert_eq!(rbsp.bits.start, 16);
+        assert_eq!(rbsp.bits, 16..sps.root.bits.end);
+        assert_eq!(
+            sps.root.find("pic_width_in_luma_samples").unwrap().value,
+            736
+        );
+        let ptl = rbsp.find_structure("profile_tier_level").unwrap();
+        // 88 bits of general profile and tier, then general_level_idc.
+        assert_eq!(ptl.bits, 24..120);
+        let vui = rbsp.find_structure("vui_parameters").unwrap();
+        assert!(vui.find("time_scale").is_some());
+        assert!(vui.bits.end <= rbsp.bits.end);
+
+        // Parsing fails in the VUI, but everything before it is there.
+        let truncated = inspect_nal(&Context::new(), &SPS[..40]);
+        assert!(matches!(truncated.error, Some(InspectError::Sps(_))));
+        assert!(truncated
+            .root
+            .find("strong_intra_smoothing_enabled")
+            .is_some());
+        assert!(truncated.root.find_structure("vui_parameters").is_some());
+
+        let sei = inspect_nal(&Context::new(), &hex!("4e 01 06 01 c4 80"));
+        assert!(sei.error.is_none());
+        let payload = sei.root.find_structure("sei_payload").unwrap();
+        assert_eq!(payload.bits, 32..40);
+    }
+
+    #[test]
+    fn sizes() {
+        let mut sizes = StructureSizes::new();
+        measure_nal(&Context::new(), &SPS, &mut sizes).unwrap();
+        let tree = inspect_nal(&Context::new(), &SPS).root;
+        assert_eq!(sizes.get("nal_unit").bits, tree.bit_len());
+        assert_eq!(sizes.get("profile_tier_level").bits, 96);
+        let vui = tree.find_structure("vui_parameters").unwrap();
+        assert_eq!(sizes.get("vui_parameters").bits, vui.bit_len());
+        assert_eq!(sizes.get("hrd_parameters").bits, 84);
+        assert_eq!(
+            sizes.get("st_ref_pic_set"),
+            StructureSize { count: 1, bits: 6 }
+        );
+        assert_eq!(sizes.get("pred_weight_table"), StructureSize::default());
+
+        // A failed parse still counts what was read.
+        let mut sizes = StructureSizes::new();
+        assert!(measure_nal(&Context::new(), &SPS[..40], &mut sizes).is_err());
+        assert_eq!(sizes.get("profile_tier_level").bits, 96);
+        assert_eq!(sizes.get("nal_unit").count, 1);
+    }
+
+    #[test]
+    fn patch() {
+        let mut nal = SPS;
+        let (_, offsets) = parse_with_offsets(&nal, |r| SeqParameterSet::from_bits(r)).unwrap();
+        // The first emulation prevention byte is at index 7, before RBSP byte 5.
+        assert_eq!(offsets.nal_bit(55), 55);
+        assert_eq!(offsets.nal_bit(56), 64);
+        let width = offsets.get("pic_width_in_luma_samples").unwrap();
+        assert!(matches!(
+            offsets.patch(&mut nal, width, 720),
+            Err(PatchError::VariableLength(_))
+        ));
+
+        // Clearing the set compatibility flags, 1 and 2, would leave `00 00 00 03`.
+        let mut flags = offsets.get_all("profile_compatibility_flag[j]");
+        let flag1 = flags.nth(1).unwrap();
+        let flag2 = flags.next().unwrap();
+        offsets.patch(&mut nal, flag1, 0).unwrap();
+        assert_eq!(nal[4], 0x20);
+        let patched = nal;
+        assert!(matches!(
+            offsets.patch(&mut nal, flag2, 0),
+            Err(PatchError::EmulationPrevention(_))
+        ));
+        assert_eq!(nal, patched);
+        assert!(matches!(
+            offsets.patch(&mut nal, flag2, 2),
+            Err(PatchError::ValueTooLarge { .. })
+        ));
+    }
+
+    #[cfg(feature = "slice")]
+    #[test]
+    fn slice_segment_header() {
+        let mut ctx = Context::new();
+        ctx.put_seq_param_set(SeqParameterSet::from_bits(BitReader::from_nal(&SPS)).unwrap());
+        let pps = hex!("44 01 c1 72 b4 62 40");
+        ctx.put_pic_param_set(PicParameterSet::from_bits(&ctx, BitReader::from_nal(&pps)).unwrap());
+        let idr = inspect_nal(&ctx, &hex!("26 01 ac 9c ff ff"));
+        assert!(idr.error.is_none());
+        let header = idr.root.find_structure("slice_segment_header").unwrap();
+        assert_eq!(header.children[0].name(), "first_slice_segment_in_pic_flag");
+        assert!(header.find("slice_qp_delta").is_some());
+
+        // Without the PPS, the slice segment header can't be parsed past its id.
+        let idr = inspect_nal(&Context::new(), &hex!("26 01 ac 9c ff ff"));
+        assert!(matches!(idr.error, Some(InspectError::SliceHeader(_))));
+        assert!(idr.root.find("slice_pic_parameter_set_id").is_some());
+    }
+}
@@ -1,18 +1,68 @@
 //! Parser for HEVC / H265 bitstream syntax.  Not a video decoder.
 
-#![forbid(unsafe_code)]
+#![cfg_attr(not(any(feature = "wasm", feature = "python")), forbid(unsafe_code))]
+// The code wasm-bindgen and pyo3 generate for the `wasm` and `python` modules is unsafe.
+#![cfg_attr(any(feature = "wasm", feature = "python"), deny(unsafe_code))]
 #![deny(rust_2018_idioms)]
 
 pub mod annexb;
+pub mod bitrate;
+#[cfg(feature = "slice")]
+pub mod cenc;
+#[cfg(feature = "analysis")]
+pub mod cmaf;
+pub mod context_map;
+#[cfg(feature = "analysis")]
+pub mod diff;
+#[cfg(feature = "sei")]
+pub mod duration;
+pub mod error_code;
+#[cfg(feature = "arbitrary")]
+mod fuzzing;
+#[cfg(all(feature = "sei", feature = "write"))]
+pub mod gstreamer;
+#[cfg(feature = "analysis")]
+pub mod heif;
+pub mod hvcc;
+#[cfg(feature = "analysis")]
+pub mod index;
+pub mod inspect;
+#[cfg(all(feature = "sei", feature = "write"))]
+pub mod matroska;
 pub mod nal;
+#[cfg(feature = "rayon")]
+pub mod par;
+pub mod pes;
 pub mod push;
+#[cfg(feature = "python")]
+#[allow(unsafe_code)]
+pub mod python;
 pub mod rbsp;
+#[cfg(feature = "write")]
+pub mod rewrite;
+pub mod rtp;
+#[cfg(all(feature = "sei", feature = "write"))]
+pub mod sample_entry;
+#[cfg(feature = "async")]
+pub mod stream;
+#[cfg(feature = "analysis")]
+pub mod validate;
+#[cfg(feature = "wasm")]
+#[allow(unsafe_code)]
+pub mod wasm;
+
+use std::sync::Arc;
 
 /// Contextual data that needs to be tracked between evaluations of different portions of H265
 /// syntax.
+///
+/// The parameter sets are shared behind [`Arc`]s, so cloning a `Context` is cheap, and a clone
+/// sent to another thread is unaffected by later `put_*` calls on the original.
+#[derive(Clone)]
 pub struct Context {
-    seq_param_sets: Vec<Option<nal::sps::SeqParameterSet>>,
-    pic_param_sets: Vec<Option<nal::pps::PicParameterSet>>,
+    video_param_sets: Vec<Option<Arc<nal::vps::VideoParameterSet>>>,
+    seq_param_sets: Vec<Option<Arc<nal::sps::SeqParameterSet>>>,
+    pic_param_sets: Vec<Option<Arc<nal::pps::PicParameterSet>>>,
 }
 impl Default for Context {
     fn default() -> Self {
@@ -21,47 +71,285 @@ impl Default for Context {
 }
 impl Context {
     pub fn new() -> Self {
-        let mut seq_param_sets = vec![];
-        for _ in 0..32 {
-            seq_param_sets.push(None);
-        }
-        let mut pic_param_sets = vec![];
-        for _ in 0..32 {
-            pic_param_sets.push(None);
-        }
         Context {
-            seq_param_sets,
-            pic_param_sets,
+            video_param_sets: vec![None; 16],
+            seq_param_sets: vec![None; 32],
+            pic_param_sets: vec![None; 64],
         }
     }
 }
 impl Context {
+    pub fn vps_by_id(&self, id: nal::sps::VideoParamSetId) -> Option<&nal::vps::VideoParameterSet> {
+        self.shared_vps_by_id(id).map(|vps| &**vps)
+    }
+    /// The VPS with the given id, to keep beyond the lifetime of the context.
+    pub fn shared_vps_by_id(
+        &self,
+        id: nal::sps::VideoParamSetId,
+    ) -> Option<&Arc<nal::vps::VideoParameterSet>> {
+        self.video_param_sets.get(id.id() as usize)?.as_ref()
+    }
+    pub fn vps(&self) -> impl Iterator<Item = &nal::vps::VideoParameterSet> {
+        self.video_param_sets
+            .iter()
+            .filter_map(|vps| vps.as_deref())
+    }
+    pub fn put_video_param_set(&mut self, vps: nal::vps::VideoParameterSet) {
+        self.put_shared_video_param_set(Arc::new(vps));
+    }
+    /// Adds a VPS without copying it, e.g. one shared with another context.
+    pub fn put_shared_video_param_set(&mut self, vps: Arc<nal::vps::VideoParameterSet>) {
+        let i = vps.vps_video_parameter_set_id.id() as usize;
+        self.video_param_sets[i] = Some(vps);
+    }
     pub fn sps_by_id(&self, id: nal::sps::SeqParamSetId) -> Option<&nal::sps::SeqParameterSet> {
-        if id.id() > 31 {
-            None
-        } else {
-            self.seq_param_sets[id.id() as usize].as_ref()
-        }
+        self.shared_sps_by_id(id).map(|sps| &**sps)
+    }
+    /// The SPS with the given id, to keep beyond the lifetime of the context.
+    pub fn shared_sps_by_id(
+        &self,
+        id: nal::sps::SeqParamSetId,
+    ) -> Option<&Arc<nal::sps::SeqParameterSet>> {
+        self.seq_param_sets.get(id.id() as usize)?.as_ref()
     }
     pub fn sps(&self) -> impl Iterator<Item = &nal::sps::SeqParameterSet> {
-        self.seq_param_sets.iter().filter_map(Option::as_ref)
+        self.seq_param_sets.iter().filter_map(|sps| sps.as_deref())
     }
     pub fn put_seq_param_set(&mut self, sps: nal::sps::SeqParameterSet) {
+        self.put_shared_seq_param_set(Arc::new(sps));
+    }
+    /// Adds an SPS without copying it, e.g. one shared with another context.
+    pub fn put_shared_seq_param_set(&mut self, sps: Arc<nal::sps::SeqParameterSet>) {
         let i = sps.sps_seq_parameter_set_id.id() as usize;
         self.seq_param_sets[i] = Some(sps);
     }
-    pub fn pps_by_id(&self, id: nal::pps::SeqParamSetId) -> Option<&nal::pps::PicParameterSet> {
-        if id.id() > 31 {
-            None
-        } else {
-            self.pic_param_sets[id.id() as usize].as_ref()
-        }
+    pub fn pps_by_id(&self, id: nal::pps::PicParamSetId) -> Option<&nal::pps::PicParameterSet> {
+        self.shared_pps_by_id(id).map(|pps| &**pps)
+    }
+    /// The PPS with the given id, to keep beyond the lifetime of the context.
+    pub fn shared_pps_by_id(
+        &self,
+        id: nal::pps::PicParamSetId,
+    ) -> Option<&Arc<nal::pps::PicParameterSet>> {
+        self.pic_param_sets.get(id.id() as usize)?.as_ref()
     }
     pub fn pps(&self) -> impl Iterator<Item = &nal::pps::PicParameterSet> {
-        self.pic_param_sets.iter().filter_map(Option::as_ref)
+        self.pic_param_sets.iter().filter_map(|pps| pps.as_deref())
     }
     pub fn put_pic_param_set(&mut self, pps: nal::pps::PicParameterSet) {
+        self.put_shared_pic_param_set(Arc::new(pps));
+    }
+    /// Adds a PPS without copying it, e.g. one shared with another context.
+    pub fn put_shared_pic_param_set(&mut self, pps: Arc<nal::pps::PicParameterSet>) {
         let i = pps.pic_parameter_set_id.id() as usize;
         self.pic_param_sets[i] = Some(pps);
     }
+
+    /// Parses a VPS, SPS or PPS NAL unit (without start code or length prefix) and keeps it,
+    /// returning true if it was one and parsed. Other NAL units are ignored.
+    pub fn put_param_set_nal(&mut self, nal: &[u8]) -> bool {
+        let Some(header) = nal
+            .first()
+            .and_then(|&b| nal::NalHeader::new(b, nal.get(1).copied()).ok())
+        else {
+            return false;
+        };
+        let r = rbsp::BitReader::from_nal(nal);
+        match header.nal_unit_type() {
+            nal::UnitType::VideoParameterSet => nal::vps::VideoParameterSet::from_bits(r)
+                .map(|vps| self.put_video_param_set(vps))
+                .is_ok(),
+            nal::UnitType::SeqParameterSet => nal::sps::SeqParameterSet::from_bits(r)
+                .map(|sps| self.put_seq_param_set(sps))
+                .is_ok(),
+            nal::UnitType::PicParameterSet => nal::pps::PicParameterSet::from_bits(self, r)
+                .map(|pps| self.put_pic_param_set(pps))
+                .is_ok(),
+            _ => false,
+        }
+    }
+
+    /// The VPS, SPS or PPS with the given id, by the type of the id. Indexing with the id does
+    /// the same, panicking if there is none.
+    ///
+    /// ```
+    /// use hevc_reader::nal::pps::PicParamSetId;
+    /// use hevc_reader::Context;
+    /// let ctx = Context::new();
+    /// assert!(ctx.get(PicParamSetId::try_from(0).unwrap()).is_none());
+    /// ```
+    pub fn get<T: nal::pps::ParamSetType>(
+        &self,
+        id: nal::pps::ParamSetId<T>,
+    ) -> Option<&T::ParamSet> {
+        T::get(self, id).map(|ps| &**ps)
+    }
+}
+impl<T: nal::pps::ParamSetType> std::ops::Index<nal::pps::ParamSetId<T>> for Context {
+    type Output = T::ParamSet;
+
+    fn index(&self, id: nal::pps::ParamSetId<T>) -> &T::ParamSet {
+        self.get(id)
+            .unwrap_or_else(|| panic!("no {} with id {}", T::KIND, id))
+    }
+}
+
+/// Where the time base of a [`FrameRate`] was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameRateSource {
+    /// The `vui_parameters()` timing info of the SPS
+    Vui,
+    /// The timing info of the VPS referenced by the SPS
+    Vps,
+}
+
+/// A picture rate as signalled in the bitstream, see [`Context::frame_rate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameRate {
+    pub num_units_in_tick: u32,
+    pub time_scale: u32,
+    pub source: FrameRateSource,
+    /// Each coded picture is a single field, so two pictures make up a frame.
+    pub field_pictures: bool,
+}
+impl FrameRate {
+    /// Coded pictures per second, i.e. fields per second for field coded video.
+    pub fn pictures_per_second(&self) -> f64 {
+        (self.time_scale as f64) / (self.num_units_in_tick as f64)
+    }
+
+    /// Frames per second, where a field pair counts as one frame.
+    pub fn fps(&self) -> f64 {
+        if self.field_pictures {
+            self.pictures_per_second() / 2.0
+        } else {
+            self.pictures_per_second()
+        }
+    }
+}
+
+#[cfg(feature = "sei")]
+impl Context {
+    /// The frame rate of pictures using `sps`.
+    ///
+    /// Many encoders signal timing only in the VPS, so when the SPS VUI has no timing info
+    /// the referenced VPS is consulted next. The `pic_timing()` SEI message is consulted last:
+    /// it carries no time base in HEVC, but its `pic_struct` tells whether pictures are fields,
+    /// which takes precedence over the VUI `field_seq_flag`.
+    ///
+    /// Returns `None` if neither the VUI nor the VPS signal a (non-zero) time base.
+    pub fn frame_rate(
+        &self,
+        sps: &nal::sps::SeqParameterSet,
+        pic_timing: Option<&nal::sei::pic_timing::PicTiming>,
+    ) -> Option<FrameRate> {
+        let vui = sps.vui_parameters.as_ref();
+        let (num_units_in_tick, time_scale, source) =
+            if let Some(t) = vui.and_then(|v| v.timing_info.as_ref()) {
+                (t.num_units_in_tick, t.time_scale, FrameRateSource::Vui)
+            } else {
+                let vps = self.vps_by_id(sps.sps_video_parameter_set_id)?;
+                let t = vps.timing_info.as_ref()?;
+                (t.num_units_in_tick, t.time_scale, FrameRateSource::Vps)
+            };
+        if num_units_in_tick == 0 || time_scale == 0 {
+            return None;
+        }
+        let field_pictures = match pic_timing.and_then(|p| p.frame_field_info) {
+            Some(info) => info.pic_struct.is_field(),
+            None => vui.is_some_and(|v| v.field_seq_flag),
+        };
+        Some(FrameRate {
+            num_units_in_tick,
+            time_scale,
+            source,
+            field_pictures,
+        })
+    }
+}
+
+#[cfg(all(test, feature = "sei"))]
+mod test {
+    use super::*;
+    use crate::nal::sei::pic_timing::{PicStruct, PicTiming};
+    use crate::nal::sps::SeqParameterSet;
+    use crate::nal::vps::VideoParameterSet;
+    use crate::rbsp::{decode_nal, BitReader};
+    use hex_literal::hex;
+
+    fn haivision_sps() -> SeqParameterSet {
+        // "Haivision 1080i25" from the SPS tests: 50 fields/s with field_seq_flag.
+        let sps = hex!(
+            "42 01 01 01 40 00 00 03 00 40 00 00 03 00 00 03 00 7b a0 03 c0 80 22 1f 79 e9 6e
+             44 a1 7f f8 00 08 00 13 50 10 10 1e d0 00 00 03 00 10 00 00 03 03 25 08 ff de 10
+             00 16 e3 60 00 05 dd 77 df 08 04 10"
+        );
+        SeqParameterSet::from_bits(BitReader::new(&*decode_nal(&sps).unwrap())).unwrap()
+    }
+
+    #[test]
+    fn frame_rate_fallback() {
+        let mut ctx = Context::new();
+        let mut sps = haivision_sps();
+        let rate = ctx.frame_rate(&sps, None).unwrap();
+        assert_eq!(rate.source, FrameRateSource::Vui);
+        assert_eq!(rate.pictures_per_second(), 50.0);
+        assert_eq!(rate.fps(), 25.0);
+
+        let mut frame = PicTiming {
+            frame_field_info: None,
+            delays: None,
+        };
+        assert_eq!(ctx.frame_rate(&sps, Some(&frame)).unwrap().fps(), 25.0);
+        frame.frame_field_info = Some(nal::sei::pic_timing::FrameFieldInfo {
+            pic_struct: PicStruct::Frame,
+            source_scan_type: 1,
+            duplicate_flag: false,
+        });
+        assert_eq!(ctx.frame_rate(&sps, Some(&frame)).unwrap().fps(), 50.0);
+
+        // Without VUI timing, fall back to the VPS: 60000/1001 with timing info.
+        sps.vui_parameters.as_mut().unwrap().timing_info = None;
+        assert_eq!(ctx.frame_rate(&sps, None), None);
+        let vps = hex!(
+            "40 01 0c 01 ff ff 01 60 00 00 03 00 90 00 00 03 00 00 03 00 5d 95 c0 c0 00 00 fa
+             40 00 3a 98 14"
+        );
+        let vps =
+            VideoParameterSet::from_bits(BitReader::new(&*decode_nal(&vps).unwrap())).unwrap();
+        ctx.put_video_param_set(vps);
+        let rate = ctx.frame_rate(&sps, None).unwrap();
+        assert_eq!(rate.source, FrameRateSource::Vps);
+        assert_eq!((rate.num_units_in_tick, rate.time_scale), (1001, 60000));
+        assert!(rate.field_pictures);
+    }
+
+    #[test]
+    fn shared_param_sets() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Context>();
+        assert_send_sync::<nal::sps::SpsError>();
+        assert_send_sync::<nal::sei::SeiReader<'_>>();
+        #[cfg(feature = "slice")]
+        assert_send_sync::<push::parse::ParsedNal<'_>>();
+
+        let mut ctx = Context::new();
+        ctx.put_seq_param_set(haivision_sps());
+        let id = ctx.sps().next().unwrap().id();
+        let shared = ctx.clone();
+        assert!(Arc::ptr_eq(
+            ctx.shared_sps_by_id(id).unwrap(),
+            shared.shared_sps_by_id(id).unwrap()
+        ));
+        // Replacing the SPS leaves the clone as it was.
+        let mut sps = haivision_sps();
+        sps.pic_width_in_luma_samples = 1280;
+        ctx.put_seq_param_set(sps);
+        let width =
+            std::thread::spawn(move || shared.sps_by_id(id).unwrap().pic_width_in_luma_samples)
+                .join()
+                .unwrap();
+        assert_eq!(width, 1920);
+        assert_eq!(ctx.sps_by_id(id).unwrap().pic_width_in_luma_samples, 1280);
+    }
 }
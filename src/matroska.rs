@@ -0,0 +1,221 @@
+//! The `CodecPrivate` element of HEVC tracks in Matroska and WebM files, whose codec id is
+//! [`CODEC_ID`].
+//!
+//! It's specified to hold an `HEVCDecoderConfigurationRecord`, as the `hvcC` box of MP4 does, but
+//! muxers have written several variants: Annex B parameter sets, `configurationVersion` 0 from
+//! drafts of ISO/IEC 14496-15, the whole `hvcC` box including its header, and records whose
+//! arrays are cut short or followed by padding. [`CodecPrivate::parse`] accepts all of these,
+//! and [`CodecPrivate::to_hvcc`] rewrites them as a well-formed record.
+
+use crate::hvcc::{check_length_size, read_arrays, SampleError};
+use crate::nal::{NalHeader, UnitType};
+use crate::sample_entry::{SampleEntry, SampleEntryConfig, SampleEntryError};
+use crate::Context;
+
+/// The Matroska codec id of HEVC tracks.
+pub const CODEC_ID: &str = "V_MPEGH/ISO/HEVC";
+
+/// A deviation from a well-formed `HEVCDecoderConfigurationRecord` which
+/// [`CodecPrivate::parse`] worked around.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quirk {
+    /// The element holds Annex B NAL units with start codes rather than a record. The length
+    /// size is then assumed to be 4.
+    AnnexB,
+    /// The element holds the whole `hvcC` box, starting with its 8 byte header.
+    BoxHeader,
+    /// `configurationVersion` isn't 1; early muxers wrote 0.
+    ConfigurationVersion(u8),
+    /// The arrays end early, at this offset in the record; the NAL units before it are kept.
+    Truncated { offset: usize },
+    /// This many bytes follow the last array.
+    TrailingData(usize),
+}
+
+/// The NAL unit length size and NAL units of a `CodecPrivate` element.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodecPrivate<'a> {
+    /// The size of the NAL unit lengths in blocks.
+    pub length_size: usize,
+    /// The NAL units of every array, in the order of the element.
+    pub nal_units: Vec<&'a [u8]>,
+    /// The variants found, in the order they were detected, or none if the element is a
+    /// well-formed record.
+    pub quirks: Vec<Quirk>,
+}
+impl<'a> CodecPrivate<'a> {
+    /// Parses the element, tolerating the variants described by [`Quirk`]. Fails only if the
+    /// length size is invalid or the fixed fields of the record are missing.
+    ///
+    /// ```
+    /// use hevc_reader::matroska::{CodecPrivate, Quirk};
+    /// // A PPS with a start code rather than a record.
+    /// let data = [0, 0, 0, 1, 0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40];
+    /// let codec_private = CodecPrivate::parse(&data).unwrap();
+    /// assert_eq!(codec_private.length_size, 4);
+    /// assert_eq!(codec_private.nal_units, [&data[4..]]);
+    /// assert_eq!(codec_private.quirks, [Quirk::AnnexB]);
+    /// ```
+    pub fn parse(data: &'a [u8]) -> Result<Self, SampleError> {
+        if data.starts_with(&[0, 0, 1]) || data.starts_with(&[0, 0, 0, 1]) {
+            return Ok(CodecPrivate {
+                length_size: 4,
+                nal_units: crate::annexb::nal_units(data).map(|(_, nal)| nal).collect(),
+                quirks: vec![Quirk::AnnexB],
+            });
+        }
+        let mut quirks = vec![];
+        let mut record = data;
+        if data.get(4..8) == Some(b"hvcC") {
+            quirks.push(Quirk::BoxHeader);
+            record = &data[8..];
+        }
+        let (Some(&version), Some(&lengths), Some(&num_arrays)) =
+            (record.first(), record.get(21), record.get(22))
+        else {
+            return Err(SampleError::Truncated { offset: 0 });
+        };
+        if version != 1 {
+            quirks.push(Quirk::ConfigurationVersion(version));
+        }
+        let length_size = usize::from(lengths & 0x3) + 1;
+        check_length_size(length_size)?;
+        let (nal_units, end) = read_arrays(record, num_arrays);
+        match end {
+            Ok(end) if end < record.len() => quirks.push(Quirk::TrailingData(record.len() - end)),
+            Ok(_) => {}
+            Err(SampleError::Truncated { offset }) => quirks.push(Quirk::Truncated { offset }),
+            Err(e) => return Err(e),
+        }
+        Ok(CodecPrivate {
+            length_size,
+            nal_units,
+            quirks,
+        })
+    }
+
+    /// A well-formed record for the parameter sets and SEI NAL units of the element, with the
+    /// same length size, fit to be written back as `CodecPrivate` or as an `hvcC` box. Other
+    /// NAL units, such as access unit delimiters in Annex B variants, are dropped.
+    pub fn to_hvcc(&self) -> Result<Vec<u8>, SampleEntryError> {
+        let mut ctx = Context::new();
+        let mut nals = vec![];
+        for &nal in &self.nal_units {
+            let Some(header) = nal
+                .first()
+                .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())
+            else {
+                continue;
+            };
+            if matches!(
+                header.nal_unit_type(),
+                UnitType::VideoParameterSet
+                    | UnitType::SeqParameterSet
+                    | UnitType::PicParameterSet
+                    | UnitType::PrefixSEI
+                    | UnitType::SuffixSEI
+            ) {
+                ctx.put_param_set_nal(nal);
+                nals.push(nal);
+            }
+        }
+        codec_private(&ctx, &nals, self.length_size)
+    }
+}
+
+/// The `CodecPrivate` element for the first SPS of `ctx`: an `HEVCDecoderConfigurationRecord`
+/// with the NAL units `nals`, as found in the stream, and blocks using `length_size` byte NAL
+/// unit lengths. See [`SampleEntry::new`].
+pub fn codec_private(
+    ctx: &Context,
+    nals: &[&[u8]],
+    length_size: usize,
+) -> Result<Vec<u8>, SampleEntryError> {
+    let config = SampleEntryConfig {
+        length_size,
+        ..SampleEntryConfig::default()
+    };
+    Ok(SampleEntry::new(ctx, nals, &config)?.hvcc)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hex_literal::hex;
+
+    // "Intinor HW encode 720x576p" SPS and its PPS.
+    const SPS: [u8; 59] = hex!(
+        "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46
+         d1 2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00
+         0b b8 48"
+    );
+    const PPS: [u8; 7] = hex!("44 01 c1 72 b4 62 40");
+
+    #[test]
+    fn round_trip() {
+        let mut ctx = Context::new();
+        ctx.put_param_set_nal(&SPS);
+        ctx.put_param_set_nal(&PPS);
+        let record = codec_private(&ctx, &[&SPS, &PPS], 2).unwrap();
+        let parsed = CodecPrivate::parse(&record).unwrap();
+        assert_eq!(parsed.length_size, 2);
+        assert_eq!(parsed.nal_units, [&SPS[..], &PPS]);
+        assert_eq!(parsed.quirks, []);
+        assert_eq!(parsed.to_hvcc().unwrap(), record);
+
+        // Annex B, with an access unit delimiter which the record can't hold.
+        let mut annexb = vec![];
+        for nal in [&hex!("46 01 50")[..], &SPS, &PPS] {
+            annexb.extend_from_slice(&[0, 0, 0, 1]);
+            annexb.extend_from_slice(nal);
+        }
+        let parsed = CodecPrivate::parse(&annexb).unwrap();
+        assert_eq!(parsed.quirks, [Quirk::AnnexB]);
+        assert_eq!(parsed.nal_units.len(), 3);
+        assert_eq!(
+            parsed.to_hvcc().unwrap(),
+            codec_private(&ctx, &[&SPS, &PPS], 4).unwrap()
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        let mut ctx = Context::new();
+        ctx.put_param_set_nal(&SPS);
+        ctx.put_param_set_nal(&PPS);
+        let record = codec_private(&ctx, &[&SPS, &PPS], 4).unwrap();
+
+        // A draft record inside its box, with padding.
+        let mut data = ((record.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(b"hvcC");
+        data.extend_from_slice(&record);
+        data[8] = 0;
+        data.extend_from_slice(&[0, 0]);
+        let parsed = CodecPrivate::parse(&data).unwrap();
+        assert_eq!(
+            parsed.quirks,
+            [
+                Quirk::BoxHeader,
+                Quirk::ConfigurationVersion(0),
+                Quirk::TrailingData(2)
+            ]
+        );
+        assert_eq!(parsed.to_hvcc().unwrap(), record);
+
+        // Cut short in the PPS array: the SPS is still found.
+        let cut = &record[..record.len() - 3];
+        let parsed = CodecPrivate::parse(cut).unwrap();
+        assert_eq!(parsed.nal_units, [&SPS[..]]);
+        assert_eq!(
+            parsed.quirks,
+            [Quirk::Truncated {
+                offset: record.len() - 9
+            }]
+        );
+
+        assert_eq!(
+            CodecPrivate::parse(&record[..20]),
+            Err(SampleError::Truncated { offset: 0 })
+        );
+    }
+}
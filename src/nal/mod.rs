@@ -4,14 +4,37 @@
 //! [`RbspDecoder`](../rbsp/struct.RbspDecoder.html)), where it has been encoded with
 //! 'emulation prevention bytes'.
 
+/// Compares the listed fields of two values, pushing a [`FieldDiff`] for each that differs.
+macro_rules! diff_fields {
+    ($diffs:expr, $prefix:expr, $a:expr, $b:expr, [$($field:ident),* $(,)?]) => {{
+        $(
+            if $a.$field != $b.$field {
+                $diffs.push($crate::nal::FieldDiff {
+                    path: format!("{}{}", $prefix, stringify!($field)),
+                    old: format!("{:?}", $a.$field),
+                    new: format!("{:?}", $b.$field),
+                });
+            }
+        )*
+    }};
+}
+#[cfg(feature = "analysis")]
+pub(crate) use diff_fields;
+
 pub mod pps;
+#[cfg(feature = "sei")]
+pub mod sei;
+#[cfg(feature = "slice")]
+pub mod slice;
 pub mod sps;
+pub mod vps;
 
 use crate::rbsp;
 use hex_slice::AsHex;
 use std::fmt;
 
-#[derive(PartialEq, Hash, Debug, Copy, Clone)]
+#[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum UnitType {
     /// VCL class
     /// TODO: better naming (if ever used)
@@ -124,6 +147,44 @@ impl UnitType {
             UnitType::Reserved(v) => v,
         }
     }
+
+    /// True for the _Video Coding Layer_ types `0`-`31`, which carry slice segments.
+    pub fn is_vcl(self) -> bool {
+        self.id() <= 31
+    }
+
+    /// True for _intra random access point_ types `16`-`23` (`BLA_W_LP`-`RSV_IRAP_VCL23`).
+    pub fn is_irap(self) -> bool {
+        (16..=23).contains(&self.id())
+    }
+
+    /// True for `IDR_W_RADL` and `IDR_N_LP`.
+    pub fn is_idr(self) -> bool {
+        matches!(
+            self,
+            UnitType::SliceSegmentLayerIdrWLp | UnitType::SliceSegmentLayerIdrNLp
+        )
+    }
+
+    /// True if a picture of this type starts a coded video sequence: IDR and BLA pictures, and
+    /// CRA pictures which are the first in the bitstream or follow an end of sequence, as
+    /// `sequence_ended` tells.
+    pub fn starts_cvs(self, sequence_ended: bool) -> bool {
+        (16..=20).contains(&self.id())
+            || (self == UnitType::SliceSegmentLayerCraNut && sequence_ended)
+    }
+}
+
+/// A field that differs between two parsed syntax structures, see e.g.
+/// [`sps::SeqParameterSet::diff`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldDiff {
+    /// Path of the field, e.g. `vui_parameters.timing_info`.
+    pub path: String,
+    /// The `Debug` representation of the old value.
+    pub old: String,
+    /// The `Debug` representation of the new value.
+    pub new: String,
 }
 
 #[derive(Debug)]
@@ -131,8 +192,18 @@ pub enum UnitTypeError {
     /// if the value was outside the range `0` - `31`.
     ValueOutOfRange(u8),
 }
+impl UnitTypeError {
+    /// See [`crate::error_code`].
+    pub fn code(&self) -> u32 {
+        crate::error_code::UNIT_TYPE
+            + match self {
+                UnitTypeError::ValueOutOfRange(..) => 1,
+            }
+    }
+}
 
 #[derive(Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct NalHeader(u8, Option<u8>);
 
 #[derive(Debug)]
@@ -142,6 +213,16 @@ pub enum NalHeaderError {
     /// Only one byte received of the two required
     IncompleteHeader,
 }
+impl NalHeaderError {
+    /// See [`crate::error_code`].
+    pub fn code(&self) -> u32 {
+        crate::error_code::NAL_HEADER
+            + match self {
+                NalHeaderError::ForbiddenZeroBit => 1,
+                NalHeaderError::IncompleteHeader => 2,
+            }
+    }
+}
 impl NalHeader {
     /// Create a new header from one or two bytes.
     /// A one-byte header is considered incomplete,
@@ -171,6 +252,75 @@ impl NalHeader {
         let byte2 = self.1.ok_or(NalHeaderError::IncompleteHeader)?;
         Ok(byte2 & 0b0000_0111)
     }
+
+    /// `TemporalId`, one less than the `nuh_temporal_id_plus1` of the header, or 0 if the header
+    /// is incomplete or `nuh_temporal_id_plus1` is the forbidden 0.
+    pub fn temporal_id(self) -> u8 {
+        self.nuh_temporal_id().map_or(0, |t| t.saturating_sub(1))
+    }
+}
+
+/// The first bit of a slice segment header, read without parsing it.
+pub(crate) fn first_slice_segment_in_pic_flag(nal: &[u8]) -> bool {
+    nal.get(2).is_some_and(|b| b & 0x80 != 0)
+}
+
+/// Finds the first NAL unit of each access unit, following clause 7.4.2.4.4.
+#[derive(Default)]
+pub(crate) struct AccessUnitTracker {
+    started: bool,
+    /// True if a VCL NAL unit was seen since the start of the current access unit.
+    seen_vcl: bool,
+}
+impl AccessUnitTracker {
+    /// Returns true if `nal` is the first NAL unit of an access unit.
+    pub(crate) fn push(&mut self, unit_type: UnitType, nal: &[u8]) -> bool {
+        let first = if unit_type.is_vcl() {
+            let first = self.seen_vcl && first_slice_segment_in_pic_flag(nal);
+            self.seen_vcl = true;
+            first
+        } else if matches!(unit_type.id(), 32..=35 | 39 | 41..=44 | 48..=55) {
+            // Parameter sets, access unit delimiters, prefix SEI and some reserved and
+            // unspecified types start the next access unit when following a VCL NAL unit.
+            std::mem::replace(&mut self.seen_vcl, false)
+        } else {
+            false
+        };
+        first || !std::mem::replace(&mut self.started, true)
+    }
+}
+
+/// Finds the pictures which start a coded video sequence, which for a CRA picture depends on
+/// whether an end of sequence preceded it.
+#[cfg(feature = "analysis")]
+pub(crate) struct CvsTracker {
+    /// True after an end of sequence or end of bitstream NAL unit, or before the first picture.
+    sequence_ended: bool,
+}
+#[cfg(feature = "analysis")]
+impl Default for CvsTracker {
+    fn default() -> Self {
+        CvsTracker {
+            sequence_ended: true,
+        }
+    }
+}
+#[cfg(feature = "analysis")]
+impl CvsTracker {
+    /// Notes an end of sequence or end of bitstream NAL unit.
+    pub(crate) fn end_sequence(&mut self) {
+        self.sequence_ended = true;
+    }
+
+    /// Returns true if a picture of type `unit_type` starts a coded video sequence. Called once
+    /// per picture, in decoding order.
+    pub(crate) fn push_picture(&mut self, unit_type: UnitType) -> bool {
+        let starts = unit_type.starts_cvs(self.sequence_ended);
+        if unit_type.is_irap() {
+            self.sequence_ended = false;
+        }
+        starts
+    }
 }
 
 impl fmt::Debug for NalHeader {
@@ -184,7 +334,6 @@ impl fmt::Debug for NalHeader {
 }
 
 /// A partially- or completely-buffered encoded NAL.
-
 /// Must have at least one byte (the header). Partially-encoded NALs are *prefixes*
 /// of a complete NAL. They can always be parsed from the beginning.
 ///
@@ -1,18 +1,46 @@
-use crate::rbsp::BitRead;
-use crate::{rbsp, Context};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::sync::Arc;
 
-// TODO: this is unchanged from original H264 parser, so it is completely incorrect for H265
+use crate::nal::sps::{DerivedSpsParams, ScalingList, SeqParameterSet};
+use crate::nal::vps::VideoParameterSet;
+use crate::rbsp::{read_structure, BitRead, RawBits};
+use crate::{rbsp, Context};
 
 #[derive(Debug)]
 pub enum PpsError {
     RbspReaderError(rbsp::BitReaderError),
-    InvalidSliceGroupMapType(u32),
-    InvalidNumSliceGroupsMinus1(u32),
     InvalidNumRefIdx(&'static str, u32),
-    InvalidSliceGroupChangeType(u32),
-    UnknownSeqParamSetId(ParamSetId<15>),
+    UnknownSeqParamSetId(SeqParamSetId),
     BadPicParamSetId(ParamSetIdError),
     BadSeqParamSetId(ParamSetIdError),
+    /// A field in the bitstream had a value too large for a subsequent calculation
+    FieldValueTooLarge {
+        name: &'static str,
+        value: u32,
+    },
+    /// The referenced SPS has values that don't allow the PPS to be interpreted.
+    BadSeqParamSet(crate::nal::sps::SpsError),
+    /// An unimplemented part of the PPS syntax was encountered
+    /// TODO: These errors should be removed before serious release
+    Unimplemented(&'static str),
+}
+impl PpsError {
+    /// See [`crate::error_code`].
+    pub fn code(&self) -> u32 {
+        crate::error_code::PPS
+            + match self {
+                PpsError::RbspReaderError(..) => 1,
+                PpsError::InvalidNumRefIdx(..) => 2,
+                PpsError::UnknownSeqParamSetId(..) => 3,
+                PpsError::BadPicParamSetId(..) => 4,
+                PpsError::BadSeqParamSetId(..) => 5,
+                PpsError::FieldValueTooLarge { .. } => 6,
+                PpsError::BadSeqParamSet(..) => 7,
+                PpsError::Unimplemented(..) => 8,
+            }
+    }
 }
 
 impl From<rbsp::BitReaderError> for PpsError {
@@ -23,95 +51,1374 @@ impl From<rbsp::BitReaderError> for PpsError {
 
 #[derive(Debug, PartialEq)]
 pub enum ParamSetIdError {
-    IdTooLarge(u32),
+    /// The id is larger than ids of this kind of parameter set may be.
+    IdTooLarge(ParamSetKind, u32),
+}
+impl ParamSetIdError {
+    /// See [`crate::error_code`].
+    pub fn code(&self) -> u32 {
+        crate::error_code::PARAM_SET_ID
+            + match self {
+                ParamSetIdError::IdTooLarge(..) => 1,
+            }
+    }
 }
 
+/// The kinds of parameter set.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct ParamSetId<const MAX: u32>(u8);
-impl<const MAX: u32> ParamSetId<MAX> {
+pub enum ParamSetKind {
+    Video,
+    Seq,
+    Pic,
+}
+impl fmt::Display for ParamSetKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ParamSetKind::Video => "VPS",
+            ParamSetKind::Seq => "SPS",
+            ParamSetKind::Pic => "PPS",
+        })
+    }
+}
+
+/// A kind of parameter set at the type level, so that e.g. a [`VideoParamSetId`] can't be used
+/// as a [`SeqParamSetId`], although both range up to 15.
+///
+/// [`VideoParamSetId`]: crate::nal::sps::VideoParamSetId
+pub trait ParamSetType: 'static {
+    const KIND: ParamSetKind;
+    /// The largest valid id.
+    const MAX: u32;
+    type ParamSet;
+
+    /// The parameter set with the given id in `ctx`, see [`Context::get`].
+    fn get(ctx: &Context, id: ParamSetId<Self>) -> Option<&Arc<Self::ParamSet>>
+    where
+        Self: Sized;
+}
+
+/// The [`ParamSetType`] of VPSs.
+#[derive(Debug)]
+pub enum Video {}
+impl ParamSetType for Video {
+    const KIND: ParamSetKind = ParamSetKind::Video;
+    const MAX: u32 = 15;
+    type ParamSet = VideoParameterSet;
+
+    fn get(ctx: &Context, id: ParamSetId<Self>) -> Option<&Arc<Self::ParamSet>> {
+        ctx.shared_vps_by_id(id)
+    }
+}
+
+/// The [`ParamSetType`] of SPSs.
+#[derive(Debug)]
+pub enum Seq {}
+impl ParamSetType for Seq {
+    const KIND: ParamSetKind = ParamSetKind::Seq;
+    const MAX: u32 = 15;
+    type ParamSet = SeqParameterSet;
+
+    fn get(ctx: &Context, id: ParamSetId<Self>) -> Option<&Arc<Self::ParamSet>> {
+        ctx.shared_sps_by_id(id)
+    }
+}
+
+/// The [`ParamSetType`] of PPSs.
+#[derive(Debug)]
+pub enum Pic {}
+impl ParamSetType for Pic {
+    const KIND: ParamSetKind = ParamSetKind::Pic;
+    const MAX: u32 = 63;
+    type ParamSet = PicParameterSet;
+
+    fn get(ctx: &Context, id: ParamSetId<Self>) -> Option<&Arc<Self::ParamSet>> {
+        ctx.shared_pps_by_id(id)
+    }
+}
+
+/// The id of a parameter set of type `T`.
+///
+/// ```
+/// use hevc_reader::nal::pps::{ParamSetIdError, ParamSetKind, PicParamSetId};
+/// let id = PicParamSetId::try_from(5).unwrap();
+/// assert_eq!(id.to_string(), "5");
+/// assert_eq!(
+///     PicParamSetId::try_from(64),
+///     Err(ParamSetIdError::IdTooLarge(ParamSetKind::Pic, 64))
+/// );
+/// ```
+pub struct ParamSetId<T: ParamSetType>(u8, PhantomData<T>);
+impl<T: ParamSetType> ParamSetId<T> {
     pub fn from_u32(id: u32) -> Result<Self, ParamSetIdError> {
-        if id > MAX {
-            Err(ParamSetIdError::IdTooLarge(id))
+        if id > T::MAX {
+            Err(ParamSetIdError::IdTooLarge(T::KIND, id))
         } else {
-            Ok(Self(id as u8))
+            Ok(Self(id as u8, PhantomData))
         }
     }
     pub fn id(self) -> u8 {
         self.0
     }
 }
+// Implemented by hand, as derives would require `T` to implement the traits too.
+impl<T: ParamSetType> Clone for ParamSetId<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<T: ParamSetType> Copy for ParamSetId<T> {}
+impl<T: ParamSetType> PartialEq for ParamSetId<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+impl<T: ParamSetType> Eq for ParamSetId<T> {}
+impl<T: ParamSetType> Hash for ParamSetId<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+impl<T: ParamSetType> fmt::Debug for ParamSetId<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ParamSetId").field(&self.0).finish()
+    }
+}
+impl<T: ParamSetType> fmt::Display for ParamSetId<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+impl<T: ParamSetType> TryFrom<u32> for ParamSetId<T> {
+    type Error = ParamSetIdError;
+
+    fn try_from(id: u32) -> Result<Self, ParamSetIdError> {
+        Self::from_u32(id)
+    }
+}
+#[cfg(feature = "arbitrary")]
+impl<'a, T: ParamSetType> arbitrary::Arbitrary<'a> for ParamSetId<T> {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Self(u.int_in_range(0..=T::MAX as u8)?, PhantomData))
+    }
+}
+
+pub type PicParamSetId = ParamSetId<Pic>;
+pub type SeqParamSetId = ParamSetId<Seq>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum TileSpacing {
+    Uniform,
+    Explicit {
+        column_width_minus1: Vec<u32>,
+        row_height_minus1: Vec<u32>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Tiles {
+    pub num_tile_columns_minus1: u32,
+    pub num_tile_rows_minus1: u32,
+    pub spacing: TileSpacing,
+    pub loop_filter_across_tiles_enabled_flag: bool,
+}
+impl Tiles {
+    fn read<R: BitRead>(r: &mut R, derived: &DerivedSpsParams) -> Result<Tiles, PpsError> {
+        let num_tile_columns_minus1 = r.read_ue("num_tile_columns_minus1")?;
+        if num_tile_columns_minus1 >= derived.pic_width_in_ctbs_y {
+            return Err(PpsError::FieldValueTooLarge {
+                name: "num_tile_columns_minus1",
+                value: num_tile_columns_minus1,
+            });
+        }
+        let num_tile_rows_minus1 = r.read_ue("num_tile_rows_minus1")?;
+        if num_tile_rows_minus1 >= derived.pic_height_in_ctbs_y {
+            return Err(PpsError::FieldValueTooLarge {
+                name: "num_tile_rows_minus1",
+                value: num_tile_rows_minus1,
+            });
+        }
+        let spacing = if r.read_bool("uniform_spacing_flag")? {
+            TileSpacing::Uniform
+        } else {
+            let column_width_minus1 = (0..num_tile_columns_minus1)
+                .map(|_| r.read_ue("column_width_minus1"))
+                .collect::<Result<Vec<_>, _>>()?;
+            let row_height_minus1 = (0..num_tile_rows_minus1)
+                .map(|_| r.read_ue("row_height_minus1"))
+                .collect::<Result<Vec<_>, _>>()?;
+            TileSpacing::Explicit {
+                column_width_minus1,
+                row_height_minus1,
+            }
+        };
+        Ok(Tiles {
+            num_tile_columns_minus1,
+            num_tile_rows_minus1,
+            spacing,
+            loop_filter_across_tiles_enabled_flag: r
+                .read_bool("loop_filter_across_tiles_enabled_flag")?,
+        })
+    }
+
+    pub fn num_tiles(&self) -> u32 {
+        (self.num_tile_columns_minus1 + 1) * (self.num_tile_rows_minus1 + 1)
+    }
+}
 
-pub type PicParamSetId = ParamSetId<63>;
-pub type SeqParamSetId = ParamSetId<15>;
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct DeblockingFilterControl {
+    pub deblocking_filter_override_enabled_flag: bool,
+    pub pps_deblocking_filter_disabled_flag: bool,
+    /// Only present in the bitstream if `!pps_deblocking_filter_disabled_flag`, otherwise 0.
+    pub pps_beta_offset_div2: i32,
+    /// Only present in the bitstream if `!pps_deblocking_filter_disabled_flag`, otherwise 0.
+    pub pps_tc_offset_div2: i32,
+}
+impl DeblockingFilterControl {
+    fn read<R: BitRead>(r: &mut R) -> Result<Option<DeblockingFilterControl>, PpsError> {
+        Ok(if r.read_bool("deblocking_filter_control_present_flag")? {
+            let deblocking_filter_override_enabled_flag =
+                r.read_bool("deblocking_filter_override_enabled_flag")?;
+            let pps_deblocking_filter_disabled_flag =
+                r.read_bool("pps_deblocking_filter_disabled_flag")?;
+            let (pps_beta_offset_div2, pps_tc_offset_div2) = if !pps_deblocking_filter_disabled_flag
+            {
+                (
+                    r.read_se("pps_beta_offset_div2")?,
+                    r.read_se("pps_tc_offset_div2")?,
+                )
+            } else {
+                (0, 0)
+            };
+            Some(DeblockingFilterControl {
+                deblocking_filter_override_enabled_flag,
+                pps_deblocking_filter_disabled_flag,
+                pps_beta_offset_div2,
+                pps_tc_offset_div2,
+            })
+        } else {
+            None
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct ChromaQpOffsetList {
+    pub diff_cu_chroma_qp_offset_depth: u32,
+    pub cb_qp_offset_list: Vec<i32>,
+    pub cr_qp_offset_list: Vec<i32>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct PpsRangeExtension {
+    /// Only present if `transform_skip_enabled_flag`.
+    pub log2_max_transform_skip_block_size_minus2: Option<u32>,
+    pub cross_component_prediction_enabled_flag: bool,
+    pub chroma_qp_offset_list: Option<ChromaQpOffsetList>,
+    pub log2_sao_offset_scale_luma: u32,
+    pub log2_sao_offset_scale_chroma: u32,
+}
+impl PpsRangeExtension {
+    fn read<R: BitRead>(
+        r: &mut R,
+        transform_skip_enabled_flag: bool,
+    ) -> Result<PpsRangeExtension, PpsError> {
+        let log2_max_transform_skip_block_size_minus2 = if transform_skip_enabled_flag {
+            Some(r.read_ue("log2_max_transform_skip_block_size_minus2")?)
+        } else {
+            None
+        };
+        let cross_component_prediction_enabled_flag =
+            r.read_bool("cross_component_prediction_enabled_flag")?;
+        let chroma_qp_offset_list = if r.read_bool("chroma_qp_offset_list_enabled_flag")? {
+            let diff_cu_chroma_qp_offset_depth = r.read_ue("diff_cu_chroma_qp_offset_depth")?;
+            let chroma_qp_offset_list_len_minus1 = r.read_ue("chroma_qp_offset_list_len_minus1")?;
+            // "The value of chroma_qp_offset_list_len_minus1 shall be in the range of 0 to 5"
+            if chroma_qp_offset_list_len_minus1 > 5 {
+                return Err(PpsError::FieldValueTooLarge {
+                    name: "chroma_qp_offset_list_len_minus1",
+                    value: chroma_qp_offset_list_len_minus1,
+                });
+            }
+            let mut cb_qp_offset_list = Vec::new();
+            let mut cr_qp_offset_list = Vec::new();
+            for _ in 0..=chroma_qp_offset_list_len_minus1 {
+                cb_qp_offset_list.push(r.read_se("cb_qp_offset_list")?);
+                cr_qp_offset_list.push(r.read_se("cr_qp_offset_list")?);
+            }
+            Some(ChromaQpOffsetList {
+                diff_cu_chroma_qp_offset_depth,
+                cb_qp_offset_list,
+                cr_qp_offset_list,
+            })
+        } else {
+            None
+        };
+        Ok(PpsRangeExtension {
+            log2_max_transform_skip_block_size_minus2,
+            cross_component_prediction_enabled_flag,
+            chroma_qp_offset_list,
+            log2_sao_offset_scale_luma: r.read_ue("log2_sao_offset_scale_luma")?,
+            log2_sao_offset_scale_chroma: r.read_ue("log2_sao_offset_scale_chroma")?,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct ActQpOffsets {
+    pub pps_slice_act_qp_offsets_present_flag: bool,
+    pub pps_act_y_qp_offset_plus5: i32,
+    pub pps_act_cb_qp_offset_plus5: i32,
+    pub pps_act_cr_qp_offset_plus3: i32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct PalettePredictorInitializers {
+    pub monochrome_palette_flag: bool,
+    pub luma_bit_depth_entry_minus8: u32,
+    /// Zero if `monochrome_palette_flag`.
+    pub chroma_bit_depth_entry_minus8: u32,
+    /// `pps_palette_predictor_initializer[ comp ][ i ]`, with one component if
+    /// `monochrome_palette_flag`, otherwise three.
+    pub initializers: Vec<Vec<u16>>,
+}
+impl PalettePredictorInitializers {
+    fn read<R: BitRead>(r: &mut R) -> Result<Option<PalettePredictorInitializers>, PpsError> {
+        let num = r.read_ue("pps_num_palette_predictor_initializers")?;
+        // PaletteMaxPredictorSize is at most 128.
+        if num > 128 {
+            return Err(PpsError::FieldValueTooLarge {
+                name: "pps_num_palette_predictor_initializers",
+                value: num,
+            });
+        }
+        if num == 0 {
+            return Ok(None);
+        }
+        let monochrome_palette_flag = r.read_bool("monochrome_palette_flag")?;
+        let luma_bit_depth_entry_minus8 = read_bit_depth_entry(r, "luma_bit_depth_entry_minus8")?;
+        let chroma_bit_depth_entry_minus8 = if monochrome_palette_flag {
+            0
+        } else {
+            read_bit_depth_entry(r, "chroma_bit_depth_entry_minus8")?
+        };
+        let num_comps = if monochrome_palette_flag { 1 } else { 3 };
+        let mut initializers = Vec::with_capacity(num_comps);
+        for comp in 0..num_comps {
+            let bits = 8 + if comp == 0 {
+                luma_bit_depth_entry_minus8
+            } else {
+                chroma_bit_depth_entry_minus8
+            };
+            let values: Result<Vec<_>, _> = (0..num)
+                .map(|_| r.read_u16(bits, "pps_palette_predictor_initializer"))
+                .collect();
+            initializers.push(values?);
+        }
+        Ok(Some(PalettePredictorInitializers {
+            monochrome_palette_flag,
+            luma_bit_depth_entry_minus8,
+            chroma_bit_depth_entry_minus8,
+            initializers,
+        }))
+    }
+}
+
+fn read_bit_depth_entry<R: BitRead>(r: &mut R, name: &'static str) -> Result<u32, PpsError> {
+    let value = r.read_ue(name)?;
+    if value > 8 {
+        return Err(PpsError::FieldValueTooLarge { name, value });
+    }
+    Ok(value)
+}
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct PpsSccExtension {
+    pub pps_curr_pic_ref_enabled_flag: bool,
+    /// Present iff `residual_adaptive_colour_transform_enabled_flag`.
+    pub residual_adaptive_colour_transform: Option<ActQpOffsets>,
+    /// `None` if `pps_palette_predictor_initializers_present_flag` is unset or
+    /// `pps_num_palette_predictor_initializers` is zero.
+    pub pps_palette_predictor_initializers: Option<PalettePredictorInitializers>,
+    /// `pps_palette_predictor_initializers_present_flag`; if set with no initializers, the
+    /// predictor is initialized to be empty rather than from the SPS.
+    pub pps_palette_predictor_initializers_present_flag: bool,
+}
+impl PpsSccExtension {
+    fn read<R: BitRead>(r: &mut R) -> Result<PpsSccExtension, PpsError> {
+        let pps_curr_pic_ref_enabled_flag = r.read_bool("pps_curr_pic_ref_enabled_flag")?;
+        let residual_adaptive_colour_transform =
+            if r.read_bool("residual_adaptive_colour_transform_enabled_flag")? {
+                Some(ActQpOffsets {
+                    pps_slice_act_qp_offsets_present_flag: r
+                        .read_bool("pps_slice_act_qp_offsets_present_flag")?,
+                    pps_act_y_qp_offset_plus5: r.read_se("pps_act_y_qp_offset_plus5")?,
+                    pps_act_cb_qp_offset_plus5: r.read_se("pps_act_cb_qp_offset_plus5")?,
+                    pps_act_cr_qp_offset_plus3: r.read_se("pps_act_cr_qp_offset_plus3")?,
+                })
+            } else {
+                None
+            };
+        let pps_palette_predictor_initializers_present_flag =
+            r.read_bool("pps_palette_predictor_initializers_present_flag")?;
+        let pps_palette_predictor_initializers = if pps_palette_predictor_initializers_present_flag
+        {
+            PalettePredictorInitializers::read(r)?
+        } else {
+            None
+        };
+        Ok(PpsSccExtension {
+            pps_curr_pic_ref_enabled_flag,
+            residual_adaptive_colour_transform,
+            pps_palette_predictor_initializers,
+            pps_palette_predictor_initializers_present_flag,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct PpsExtension {
+    pub pps_range_extension: Option<PpsRangeExtension>,
+    pub pps_multilayer_extension_flag: bool,
+    pub pps_3d_extension_flag: bool,
+    /// `pps_scc_extension_flag`. The extension is only parsed into `pps_scc_extension` if
+    /// neither of the extensions preceding it is present.
+    pub pps_scc_extension_flag: bool,
+    pub pps_scc_extension: Option<PpsSccExtension>,
+    pub pps_extension_4bits: u8,
+    /// The syntax following the extensions parsed above, up to the `rbsp_trailing_bits()`:
+    /// from `pps_multilayer_extension()` or `pps_3d_extension()` if either is present, or
+    /// else the `pps_extension_data_flag` bits if `pps_extension_4bits` is nonzero.
+    pub extension_data: Option<RawBits>,
+}
+impl PpsExtension {
+    fn read<R: BitRead>(
+        r: &mut R,
+        transform_skip_enabled_flag: bool,
+    ) -> Result<Option<PpsExtension>, PpsError> {
+        Ok(if r.read_bool("pps_extension_present_flag")? {
+            let pps_range_extension_flag = r.read_bool("pps_range_extension_flag")?;
+            let pps_multilayer_extension_flag = r.read_bool("pps_multilayer_extension_flag")?;
+            let pps_3d_extension_flag = r.read_bool("pps_3d_extension_flag")?;
+            let pps_scc_extension_flag = r.read_bool("pps_scc_extension_flag")?;
+            let pps_extension_4bits = r.read_u8(4, "pps_extension_4bits")?;
+
+            let pps_range_extension = if pps_range_extension_flag {
+                Some(read_structure(r, "pps_range_extension", |r| {
+                    PpsRangeExtension::read(r, transform_skip_enabled_flag)
+                })?)
+            } else {
+                None
+            };
+            let mut pps_scc_extension = None;
+            let extension_data = if pps_multilayer_extension_flag {
+                Some(RawBits::read_to_trailing_bits(
+                    r,
+                    "pps_multilayer_extension",
+                )?)
+            } else if pps_3d_extension_flag {
+                Some(RawBits::read_to_trailing_bits(r, "pps_3d_extension")?)
+            } else {
+                if pps_scc_extension_flag {
+                    pps_scc_extension = Some(read_structure(
+                        r,
+                        "pps_scc_extension",
+                        PpsSccExtension::read,
+                    )?);
+                }
+                if pps_extension_4bits != 0 {
+                    Some(RawBits::read_to_trailing_bits(
+                        r,
+                        "pps_extension_data_flag",
+                    )?)
+                } else {
+                    None
+                }
+            };
+
+            Some(PpsExtension {
+                pps_range_extension,
+                pps_multilayer_extension_flag,
+                pps_3d_extension_flag,
+                pps_scc_extension_flag,
+                pps_scc_extension,
+                pps_extension_4bits,
+                extension_data,
+            })
+        } else {
+            None
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct PicParameterSet {
     pub pic_parameter_set_id: PicParamSetId,
     pub seq_parameter_set_id: SeqParamSetId,
-    // TODO...
+    pub dependent_slice_segments_enabled_flag: bool,
+    pub output_flag_present_flag: bool,
+    pub num_extra_slice_header_bits: u8,
+    pub sign_data_hiding_enabled_flag: bool,
+    pub cabac_init_present_flag: bool,
+    pub num_ref_idx_l0_default_active_minus1: u32,
+    pub num_ref_idx_l1_default_active_minus1: u32,
+    pub init_qp_minus26: i32,
+    pub constrained_intra_pred_flag: bool,
+    pub transform_skip_enabled_flag: bool,
+    /// `diff_cu_qp_delta_depth`, present iff `cu_qp_delta_enabled_flag`.
+    pub diff_cu_qp_delta_depth: Option<u32>,
+    pub pps_cb_qp_offset: i32,
+    pub pps_cr_qp_offset: i32,
+    pub pps_slice_chroma_qp_offsets_present_flag: bool,
+    pub weighted_pred_flag: bool,
+    pub weighted_bipred_flag: bool,
+    pub transquant_bypass_enabled_flag: bool,
+    /// Present iff `tiles_enabled_flag`.
+    pub tiles: Option<Tiles>,
+    pub entropy_coding_sync_enabled_flag: bool,
+    pub pps_loop_filter_across_slices_enabled_flag: bool,
+    pub deblocking_filter_control: Option<DeblockingFilterControl>,
+    pub pps_scaling_list: Option<ScalingList>,
+    pub lists_modification_present_flag: bool,
+    pub log2_parallel_merge_level_minus2: u32,
+    pub slice_segment_header_extension_present_flag: bool,
+    pub pps_extension: Option<PpsExtension>,
 }
 impl PicParameterSet {
-    pub fn from_bits<R: BitRead>(_ctx: &Context, mut _r: R) -> Result<PicParameterSet, PpsError> {
-        unimplemented!("Not implemented yet");
+    pub fn from_bits<R: BitRead>(ctx: &Context, mut r: R) -> Result<PicParameterSet, PpsError> {
+        let pic_parameter_set_id = PicParamSetId::from_u32(r.read_ue("pps_pic_parameter_set_id")?)
+            .map_err(PpsError::BadPicParamSetId)?;
+        let seq_parameter_set_id = SeqParamSetId::from_u32(r.read_ue("pps_seq_parameter_set_id")?)
+            .map_err(PpsError::BadSeqParamSetId)?;
+        let sps = ctx
+            .sps_by_id(seq_parameter_set_id)
+            .ok_or(PpsError::UnknownSeqParamSetId(seq_parameter_set_id))?;
+        let derived = sps.derived().map_err(PpsError::BadSeqParamSet)?;
+
+        let dependent_slice_segments_enabled_flag =
+            r.read_bool("dependent_slice_segments_enabled_flag")?;
+        let output_flag_present_flag = r.read_bool("output_flag_present_flag")?;
+        let num_extra_slice_header_bits = r.read_u8(3, "num_extra_slice_header_bits")?;
+        let sign_data_hiding_enabled_flag = r.read_bool("sign_data_hiding_enabled_flag")?;
+        let cabac_init_present_flag = r.read_bool("cabac_init_present_flag")?;
+        let num_ref_idx_l0_default_active_minus1 =
+            read_num_ref_idx(&mut r, "num_ref_idx_l0_default_active_minus1")?;
+        let num_ref_idx_l1_default_active_minus1 =
+            read_num_ref_idx(&mut r, "num_ref_idx_l1_default_active_minus1")?;
+        let init_qp_minus26 = r.read_se("init_qp_minus26")?;
+        let constrained_intra_pred_flag = r.read_bool("constrained_intra_pred_flag")?;
+        let transform_skip_enabled_flag = r.read_bool("transform_skip_enabled_flag")?;
+        let diff_cu_qp_delta_depth = if r.read_bool("cu_qp_delta_enabled_flag")? {
+            Some(r.read_ue("diff_cu_qp_delta_depth")?)
+        } else {
+            None
+        };
+        let pps_cb_qp_offset = r.read_se("pps_cb_qp_offset")?;
+        let pps_cr_qp_offset = r.read_se("pps_cr_qp_offset")?;
+        let pps_slice_chroma_qp_offsets_present_flag =
+            r.read_bool("pps_slice_chroma_qp_offsets_present_flag")?;
+        let weighted_pred_flag = r.read_bool("weighted_pred_flag")?;
+        let weighted_bipred_flag = r.read_bool("weighted_bipred_flag")?;
+        let transquant_bypass_enabled_flag = r.read_bool("transquant_bypass_enabled_flag")?;
+        let tiles_enabled_flag = r.read_bool("tiles_enabled_flag")?;
+        let entropy_coding_sync_enabled_flag = r.read_bool("entropy_coding_sync_enabled_flag")?;
+        let tiles = if tiles_enabled_flag {
+            Some(Tiles::read(&mut r, &derived)?)
+        } else {
+            None
+        };
+        let pps = PicParameterSet {
+            pic_parameter_set_id,
+            seq_parameter_set_id,
+            dependent_slice_segments_enabled_flag,
+            output_flag_present_flag,
+            num_extra_slice_header_bits,
+            sign_data_hiding_enabled_flag,
+            cabac_init_present_flag,
+            num_ref_idx_l0_default_active_minus1,
+            num_ref_idx_l1_default_active_minus1,
+            init_qp_minus26,
+            constrained_intra_pred_flag,
+            transform_skip_enabled_flag,
+            diff_cu_qp_delta_depth,
+            pps_cb_qp_offset,
+            pps_cr_qp_offset,
+            pps_slice_chroma_qp_offsets_present_flag,
+            weighted_pred_flag,
+            weighted_bipred_flag,
+            transquant_bypass_enabled_flag,
+            tiles,
+            entropy_coding_sync_enabled_flag,
+            pps_loop_filter_across_slices_enabled_flag: r
+                .read_bool("pps_loop_filter_across_slices_enabled_flag")?,
+            deblocking_filter_control: DeblockingFilterControl::read(&mut r)?,
+            pps_scaling_list: if r.read_bool("pps_scaling_list_data_present_flag")? {
+                Some(read_structure(
+                    &mut r,
+                    "scaling_list_data",
+                    ScalingList::read_scaling_list,
+                )?)
+            } else {
+                None
+            },
+            lists_modification_present_flag: r.read_bool("lists_modification_present_flag")?,
+            log2_parallel_merge_level_minus2: r.read_ue("log2_parallel_merge_level_minus2")?,
+            slice_segment_header_extension_present_flag: r
+                .read_bool("slice_segment_header_extension_present_flag")?,
+            pps_extension: PpsExtension::read(&mut r, transform_skip_enabled_flag)?,
+        };
+        r.finish_rbsp()?;
+        Ok(pps)
+    }
+
+    pub fn id(&self) -> PicParamSetId {
+        self.pic_parameter_set_id
+    }
+
+    pub fn tiles_enabled_flag(&self) -> bool {
+        self.tiles.is_some()
+    }
+
+    pub fn cu_qp_delta_enabled_flag(&self) -> bool {
+        self.diff_cu_qp_delta_depth.is_some()
+    }
+
+    /// Summarizes the decoder parallelism this PPS allows, given the SPS it refers to.
+    ///
+    /// `slices_per_picture` is the number of independent slices per picture observed in the
+    /// stream, if known.
+    pub fn parallelism(
+        &self,
+        sps: &SeqParameterSet,
+        slices_per_picture: Option<u32>,
+    ) -> Result<ParallelismInfo, PpsError> {
+        let derived = sps.derived().map_err(PpsError::BadSeqParamSet)?;
+        let wavefront_rows = if self.entropy_coding_sync_enabled_flag {
+            // Each CTB row may start once two CTBs of the row above are decoded.
+            derived
+                .pic_height_in_ctbs_y
+                .min(derived.pic_width_in_ctbs_y.div_ceil(2))
+                .max(1)
+        } else {
+            1
+        };
+        Ok(ParallelismInfo {
+            num_tiles: self.tiles.as_ref().map_or(1, Tiles::num_tiles),
+            wavefront_rows,
+            entropy_coding_sync_enabled_flag: self.entropy_coding_sync_enabled_flag,
+            min_spatial_segmentation_idc: sps
+                .vui_parameters
+                .as_ref()
+                .and_then(|v| v.bitstream_restrictions.as_ref())
+                .map_or(0, |b| b.min_spatial_segmentation_idc),
+            slices_per_picture,
+        })
+    }
+
+    /// Returns `SliceQpY` for a slice with the given `slice_qp_delta`, or `None` if the
+    /// unbounded fields of a malformed stream overflow it.
+    pub fn slice_qp_y(&self, slice_qp_delta: i32) -> Option<i32> {
+        self.init_qp_minus26
+            .checked_add(26)?
+            .checked_add(slice_qp_delta)
+    }
+
+    /// Reports which lossless coding tools pictures using this PPS may use.
+    ///
+    /// `slice_qp_delta` should be given if known; otherwise the PPS initial QP is assumed.
+    pub fn lossless_info(
+        &self,
+        sps: &SeqParameterSet,
+        slice_qp_delta: Option<i32>,
+    ) -> Result<LosslessInfo, PpsError> {
+        let derived = sps.derived().map_err(PpsError::BadSeqParamSet)?;
+        let qp_prime_y = self
+            .slice_qp_y(slice_qp_delta.unwrap_or(0))
+            .and_then(|qp| qp.checked_add(derived.qp_bd_offset_y as i32));
+        Ok(LosslessInfo {
+            transquant_bypass_enabled: self.transquant_bypass_enabled_flag,
+            transform_skip_at_qp4: self.transform_skip_enabled_flag && qp_prime_y == Some(4),
+            // There are no chroma PCM samples when ChromaArrayType is 0.
+            lossless_pcm: sps.pcm.as_ref().is_some_and(|pcm| {
+                u32::from(pcm.pcm_sample_bit_depth_luma_minus1) + 1 == derived.bit_depth_y
+                    && (sps.chroma_array_type() == 0
+                        || u32::from(pcm.pcm_sample_bit_depth_chroma_minus1) + 1
+                            == derived.bit_depth_c)
+            }),
+        })
+    }
+
+    /// The PPS screen content coding extension, if present.
+    pub fn pps_scc_extension(&self) -> Option<&PpsSccExtension> {
+        self.pps_extension
+            .as_ref()
+            .and_then(|e| e.pps_scc_extension.as_ref())
+    }
+
+    /// Reports which screen content coding tools pictures using this PPS and the given SPS
+    /// may use.
+    pub fn screen_content_tools(&self, sps: &SeqParameterSet) -> ScreenContentTools {
+        let sps_scc = sps
+            .sps_extension
+            .as_ref()
+            .and_then(|e| e.sps_scc_extension.as_ref());
+        let pps_scc = self.pps_scc_extension();
+        ScreenContentTools {
+            intra_block_copy: pps_scc.is_some_and(|e| e.pps_curr_pic_ref_enabled_flag),
+            palette_mode: sps_scc.is_some_and(|e| e.palette_mode.is_some()),
+            adaptive_colour_transform: pps_scc
+                .is_some_and(|e| e.residual_adaptive_colour_transform.is_some()),
+        }
+    }
+
+    /// Derives the CTB raster and tile scan conversion tables of clause 6.5.1 for pictures
+    /// using this PPS and the given SPS.
+    pub fn tile_scan(&self, sps: &SeqParameterSet) -> Result<TileScan, PpsError> {
+        TileScan::new(sps, self.tiles.as_ref())
+    }
+}
+
+/// A rectangle in luma samples.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Conversions between CTB addresses in raster scan (as used by `slice_segment_address`),
+/// CTB addresses in tile scan (the order CTBs are coded in), CTB coordinates and pixel
+/// rectangles. See [`PicParameterSet::tile_scan`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TileScan {
+    pic_width_in_luma_samples: u32,
+    pic_height_in_luma_samples: u32,
+    ctb_size_y: u32,
+    pic_width_in_ctbs_y: u32,
+    /// `colBd`, with a final entry of `PicWidthInCtbsY`.
+    pub col_bd: Vec<u32>,
+    /// `rowBd`, with a final entry of `PicHeightInCtbsY`.
+    pub row_bd: Vec<u32>,
+    /// `CtbAddrRsToTs`
+    pub ctb_addr_rs_to_ts: Vec<u32>,
+    /// `CtbAddrTsToRs`
+    pub ctb_addr_ts_to_rs: Vec<u32>,
+    /// `TileId`, indexed by tile scan address.
+    pub tile_id: Vec<u32>,
+}
+impl TileScan {
+    pub fn new(sps: &SeqParameterSet, tiles: Option<&Tiles>) -> Result<TileScan, PpsError> {
+        let derived = sps.derived().map_err(PpsError::BadSeqParamSet)?;
+        let width = derived.pic_width_in_ctbs_y;
+        let height = derived.pic_height_in_ctbs_y;
+        let (col_width, row_height) = match tiles {
+            None => (vec![width], vec![height]),
+            Some(tiles) => match &tiles.spacing {
+                TileSpacing::Uniform => (
+                    uniform_spacing(width, tiles.num_tile_columns_minus1 + 1),
+                    uniform_spacing(height, tiles.num_tile_rows_minus1 + 1),
+                ),
+                TileSpacing::Explicit {
+                    column_width_minus1,
+                    row_height_minus1,
+                } => (
+                    explicit_spacing(width, column_width_minus1, "column_width_minus1")?,
+                    explicit_spacing(height, row_height_minus1, "row_height_minus1")?,
+                ),
+            },
+        };
+        let boundaries = |sizes: &[u32]| {
+            let mut bd = Vec::with_capacity(sizes.len() + 1);
+            bd.push(0);
+            for size in sizes {
+                bd.push(bd.last().unwrap() + size);
+            }
+            bd
+        };
+        let col_bd = boundaries(&col_width);
+        let row_bd = boundaries(&row_height);
+
+        // (6-5)
+        // Grown as it fills rather than sized up front from the untrusted picture size.
+        let mut ctb_addr_rs_to_ts = vec![];
+        for ctb_addr_rs in 0..derived.pic_size_in_ctbs_y {
+            let tb_x = ctb_addr_rs % width;
+            let tb_y = ctb_addr_rs / width;
+            let tile_x = col_bd[1..].iter().position(|&bd| tb_x < bd).unwrap();
+            let tile_y = row_bd[1..].iter().position(|&bd| tb_y < bd).unwrap();
+            let preceding_rows = row_bd[tile_y] * width;
+            let preceding_cols = row_height[tile_y] * col_bd[tile_x];
+            ctb_addr_rs_to_ts.push(
+                preceding_rows
+                    + preceding_cols
+                    + (tb_y - row_bd[tile_y]) * col_width[tile_x]
+                    + tb_x
+                    - col_bd[tile_x],
+            );
+        }
+        // (6-6)
+        let pic_size = ctb_addr_rs_to_ts.len();
+        let mut ctb_addr_ts_to_rs = vec![0; pic_size];
+        for (rs, &ts) in ctb_addr_rs_to_ts.iter().enumerate() {
+            ctb_addr_ts_to_rs[ts as usize] = rs as u32;
+        }
+        // (6-7)
+        let mut tile_id = vec![0; pic_size];
+        let mut t = 0;
+        for j in 0..row_height.len() {
+            for i in 0..col_width.len() {
+                for y in row_bd[j]..row_bd[j + 1] {
+                    for x in col_bd[i]..col_bd[i + 1] {
+                        tile_id[ctb_addr_rs_to_ts[(y * width + x) as usize] as usize] = t;
+                    }
+                }
+                t += 1;
+            }
+        }
+
+        Ok(TileScan {
+            pic_width_in_luma_samples: sps.pic_width_in_luma_samples,
+            pic_height_in_luma_samples: sps.pic_height_in_luma_samples,
+            ctb_size_y: derived.ctb_size_y,
+            pic_width_in_ctbs_y: width,
+            col_bd,
+            row_bd,
+            ctb_addr_rs_to_ts,
+            ctb_addr_ts_to_rs,
+            tile_id,
+        })
+    }
+
+    /// Returns the CTB coordinates `(x, y)` of a CTB raster scan address, such as a
+    /// `slice_segment_address`.
+    pub fn ctb_xy(&self, ctb_addr_rs: u32) -> Option<(u32, u32)> {
+        if ctb_addr_rs as usize >= self.ctb_addr_rs_to_ts.len() {
+            return None;
+        }
+        Some((
+            ctb_addr_rs % self.pic_width_in_ctbs_y,
+            ctb_addr_rs / self.pic_width_in_ctbs_y,
+        ))
+    }
+
+    /// Returns the luma sample rectangle covered by the CTB at the given raster scan address,
+    /// clipped to the picture boundaries.
+    pub fn ctb_rect(&self, ctb_addr_rs: u32) -> Option<Rect> {
+        let (ctb_x, ctb_y) = self.ctb_xy(ctb_addr_rs)?;
+        let x = ctb_x * self.ctb_size_y;
+        let y = ctb_y * self.ctb_size_y;
+        Some(Rect {
+            x,
+            y,
+            width: self.ctb_size_y.min(self.pic_width_in_luma_samples - x),
+            height: self.ctb_size_y.min(self.pic_height_in_luma_samples - y),
+        })
+    }
+
+    /// Returns the raster scan addresses of the CTBs in a slice segment, in decoding order.
+    ///
+    /// The slice segment starts at `slice_segment_address` and continues up to (but not
+    /// including) `next_slice_segment_address`, or to the end of the picture if `None`.
+    pub fn slice_segment_ctbs(
+        &self,
+        slice_segment_address: u32,
+        next_slice_segment_address: Option<u32>,
+    ) -> impl Iterator<Item = u32> + '_ {
+        let to_ts = |addr: u32| {
+            self.ctb_addr_rs_to_ts
+                .get(addr as usize)
+                .map_or(self.ctb_addr_ts_to_rs.len(), |&ts| ts as usize)
+        };
+        let start = to_ts(slice_segment_address);
+        let end = next_slice_segment_address.map_or(self.ctb_addr_ts_to_rs.len(), to_ts);
+        self.ctb_addr_ts_to_rs[start..end.max(start)]
+            .iter()
+            .copied()
+    }
+
+    /// Returns the luma sample rectangles of the CTBs in a slice segment; see
+    /// [`TileScan::slice_segment_ctbs`].
+    pub fn slice_segment_rects(
+        &self,
+        slice_segment_address: u32,
+        next_slice_segment_address: Option<u32>,
+    ) -> impl Iterator<Item = Rect> + '_ {
+        self.slice_segment_ctbs(slice_segment_address, next_slice_segment_address)
+            .filter_map(|addr| self.ctb_rect(addr))
+    }
+}
+
+/// `colWidth` or `rowHeight` when `uniform_spacing_flag` is set, per (6-3) and (6-4).
+fn uniform_spacing(size_in_ctbs: u32, num: u32) -> Vec<u32> {
+    // In u64, as the products overflow u32 for huge pictures with many tiles.
+    let (size, n) = (u64::from(size_in_ctbs), u64::from(num));
+    (0..n)
+        .map(|i| ((i + 1) * size / n - i * size / n) as u32)
+        .collect()
+}
+
+/// `colWidth` or `rowHeight` from explicitly signalled sizes, per (6-3) and (6-4).
+fn explicit_spacing(
+    size_in_ctbs: u32,
+    sizes_minus1: &[u32],
+    name: &'static str,
+) -> Result<Vec<u32>, PpsError> {
+    let mut sizes = Vec::with_capacity(sizes_minus1.len() + 1);
+    let mut remaining = size_in_ctbs;
+    for &size_minus1 in sizes_minus1 {
+        // The last tile must be at least one CTB in size.
+        remaining = match remaining.checked_sub(size_minus1.saturating_add(1)) {
+            Some(r) if r > 0 => r,
+            _ => {
+                return Err(PpsError::FieldValueTooLarge {
+                    name,
+                    value: size_minus1,
+                })
+            }
+        };
+        sizes.push(size_minus1 + 1);
+    }
+    sizes.push(remaining);
+    Ok(sizes)
+}
+
+fn read_num_ref_idx<R: BitRead>(r: &mut R, name: &'static str) -> Result<u32, PpsError> {
+    let num_ref_idx = r.read_ue(name)?;
+    if num_ref_idx > 14 {
+        return Err(PpsError::InvalidNumRefIdx(name, num_ref_idx));
+    }
+    Ok(num_ref_idx)
+}
+
+/// Lossless coding tools available to pictures, from [`PicParameterSet::lossless_info`].
+///
+/// These indicate that lossless coding is *possible*; whether it's actually used is signalled
+/// per coding unit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LosslessInfo {
+    /// `transquant_bypass_enabled_flag`: coding units may bypass transform, quantization and
+    /// in-loop filtering via `cu_transquant_bypass_flag`.
+    pub transquant_bypass_enabled: bool,
+    /// Transform skip is enabled and `Qp′Y` is 4, i.e. a quantization step size of 1.
+    pub transform_skip_at_qp4: bool,
+    /// PCM is enabled with the full luma and chroma bit depths, so PCM coding units are
+    /// lossless.
+    pub lossless_pcm: bool,
+}
+impl LosslessInfo {
+    /// Returns true if any lossless coding tool is available.
+    pub fn may_be_lossless(&self) -> bool {
+        self.transquant_bypass_enabled || self.transform_skip_at_qp4 || self.lossless_pcm
+    }
+}
+
+/// Screen content coding tools available to pictures, from
+/// [`PicParameterSet::screen_content_tools`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ScreenContentTools {
+    /// Intra block copy, i.e. `pps_curr_pic_ref_enabled_flag`: the current picture may be
+    /// used as a reference.
+    pub intra_block_copy: bool,
+    /// `palette_mode_enabled_flag`.
+    pub palette_mode: bool,
+    /// `residual_adaptive_colour_transform_enabled_flag`.
+    pub adaptive_colour_transform: bool,
+}
+impl ScreenContentTools {
+    /// Returns true if any screen content coding tool is enabled.
+    pub fn any(&self) -> bool {
+        self.intra_block_copy || self.palette_mode || self.adaptive_colour_transform
+    }
+}
+
+/// The `parallelismType` of an `HEVCDecoderConfigurationRecord` (ISO/IEC 14496-15).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParallelismType {
+    /// Mixed types of parallel decoding, or unknown.
+    Mixed,
+    /// Slice-based parallel decoding.
+    Slice,
+    /// Tile-based parallel decoding.
+    Tile,
+    /// Entropy coding synchronization (wavefront) based parallel decoding.
+    Wavefront,
+}
+impl ParallelismType {
+    /// Determines the type from every PPS in the stream.
+    pub fn for_pps<'a>(pps: impl IntoIterator<Item = &'a PicParameterSet>) -> ParallelismType {
+        let mut result = None;
+        for pps in pps {
+            let t = match (
+                pps.tiles_enabled_flag(),
+                pps.entropy_coding_sync_enabled_flag,
+            ) {
+                (false, false) => ParallelismType::Slice,
+                (true, false) => ParallelismType::Tile,
+                (false, true) => ParallelismType::Wavefront,
+                (true, true) => return ParallelismType::Mixed,
+            };
+            match result {
+                None => result = Some(t),
+                Some(prev) if prev != t => return ParallelismType::Mixed,
+                Some(_) => {}
+            }
+        }
+        result.unwrap_or(ParallelismType::Mixed)
+    }
+
+    /// The value of the two-bit `parallelismType` field.
+    pub fn id(self) -> u8 {
+        match self {
+            ParallelismType::Mixed => 0,
+            ParallelismType::Slice => 1,
+            ParallelismType::Tile => 2,
+            ParallelismType::Wavefront => 3,
+        }
+    }
+}
+
+/// Decoder parallelism capabilities of a picture, from [`PicParameterSet::parallelism`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParallelismInfo {
+    /// Number of tiles per picture; `1` if tiles aren't enabled.
+    pub num_tiles: u32,
+    pub entropy_coding_sync_enabled_flag: bool,
+    /// Number of CTB rows that can be decoded concurrently with wavefront parallel processing;
+    /// `1` if entropy coding sync isn't enabled.
+    pub wavefront_rows: u32,
+    /// From the VUI bitstream restrictions, or `0` if absent.
+    pub min_spatial_segmentation_idc: u32,
+    pub slices_per_picture: Option<u32>,
+}
+impl ParallelismInfo {
+    /// The parallelism type this picture supports, as used by `parallelismType`.
+    pub fn parallelism_type(&self) -> ParallelismType {
+        match (self.num_tiles > 1, self.entropy_coding_sync_enabled_flag) {
+            (false, false) => ParallelismType::Slice,
+            (true, false) => ParallelismType::Tile,
+            (false, true) => ParallelismType::Wavefront,
+            (true, true) => ParallelismType::Mixed,
+        }
+    }
+
+    /// The minimum number of spatial segments (slices, tiles or CTB rows) per picture which is
+    /// guaranteed by `min_spatial_segmentation_idc`, per clause E.3.1.
+    pub fn min_spatial_segments(&self) -> u32 {
+        // No segment contains more than ( 4 * PicSizeInSamplesY ) / ( idc + 4 ) luma samples.
+        self.min_spatial_segmentation_idc
+            .saturating_add(4)
+            .div_ceil(4)
+    }
+
+    /// An estimate of how many threads a decoder can usefully employ for a single picture.
+    pub fn max_useful_threads(&self) -> u32 {
+        let tiles_and_rows = if self.num_tiles > 1 && self.entropy_coding_sync_enabled_flag {
+            self.num_tiles.saturating_mul(self.wavefront_rows)
+        } else {
+            self.num_tiles.max(self.wavefront_rows)
+        };
+        tiles_and_rows
+            .max(self.slices_per_picture.unwrap_or(1))
+            .max(self.min_spatial_segments())
     }
 }
 
-/* TODO: tests are not updated for H265
 #[cfg(test)]
 mod test {
     use super::*;
-    use hex_literal::*;
+    use crate::rbsp::{decode_nal, BitReader};
+    use hex_literal::hex;
 
-    #[test]
-    fn test_it() {
-        let data = hex!(
-            "64 00 0A AC 72 84 44 26 84 00 00
-            00 04 00 00 00 CA 3C 48 96 11 80"
+    fn ctx_with_sps() -> Context {
+        // "Intinor HW encode 720x576p" from the SPS tests: 736x576 with 32x32 CTBs.
+        let sps = hex!(
+            "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87
+             ee 46 d1 2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0
+             00 49 3e 00 0b b8 48"
         );
-        let sps = super::sps::SeqParameterSet::from_bits(rbsp::BitReader::new(&data[..]))
+        let sps = SeqParameterSet::from_bits(BitReader::new(&*decode_nal(&sps).unwrap()))
             .expect("unexpected test data");
         let mut ctx = Context::default();
         ctx.put_seq_param_set(sps);
-        let data = hex!("E8 43 8F 13 21 30");
-        match PicParameterSet::from_bits(&ctx, rbsp::BitReader::new(&data[..])) {
-            Err(e) => panic!("failed: {:?}", e),
-            Ok(pps) => {
-                println!("pps: {:#?}", pps);
-                assert_eq!(pps.pic_parameter_set_id.id(), 0);
-                assert_eq!(pps.seq_parameter_set_id.id(), 0);
-            }
+        ctx
+    }
+
+    #[test]
+    fn x265_pps() {
+        let ctx = ctx_with_sps();
+        let pps = hex!("44 01 c1 72 b4 62 40");
+        let pps =
+            PicParameterSet::from_bits(&ctx, BitReader::new(&*decode_nal(&pps).unwrap())).unwrap();
+        assert_eq!(pps.pic_parameter_set_id.id(), 0);
+        assert_eq!(pps.seq_parameter_set_id.id(), 0);
+        assert!(pps.sign_data_hiding_enabled_flag);
+        assert_eq!(pps.diff_cu_qp_delta_depth, Some(1));
+        assert!(pps.weighted_pred_flag);
+        assert!(!pps.tiles_enabled_flag());
+        assert!(pps.entropy_coding_sync_enabled_flag);
+        assert!(pps.pps_loop_filter_across_slices_enabled_flag);
+        assert_eq!(pps.pps_extension, None);
+
+        let sps = ctx.sps().next().unwrap();
+        let info = pps.parallelism(sps, Some(1)).unwrap();
+        assert_eq!(info.parallelism_type(), ParallelismType::Wavefront);
+        // 23x18 CTBs
+        assert_eq!(info.wavefront_rows, 12);
+        assert_eq!(info.max_useful_threads(), 12);
+        assert_eq!(ParallelismType::for_pps([&pps]), ParallelismType::Wavefront);
+    }
+
+    #[test]
+    fn context_index() {
+        let mut ctx = ctx_with_sps();
+        let pps = hex!("44 01 c1 72 b4 62 40");
+        let pps =
+            PicParameterSet::from_bits(&ctx, BitReader::new(&*decode_nal(&pps).unwrap())).unwrap();
+        ctx.put_pic_param_set(pps);
+        let pps = &ctx[PicParamSetId::try_from(0).unwrap()];
+        let sps = &ctx[pps.seq_parameter_set_id];
+        assert_eq!(sps.sps_video_parameter_set_id.to_string(), "0");
+        // No VPS was added, and VPS ids have their own type although they share the range.
+        assert!(ctx.get(sps.sps_video_parameter_set_id).is_none());
+        assert_eq!(
+            crate::nal::sps::VideoParamSetId::try_from(16),
+            Err(ParamSetIdError::IdTooLarge(ParamSetKind::Video, 16))
+        );
+    }
+
+    #[test]
+    fn extension_data() {
+        let ctx = ctx_with_sps();
+        let read = |nal: &[u8]| {
+            PicParameterSet::from_bits(&ctx, BitReader::new(&*decode_nal(nal).unwrap()))
+                .unwrap()
+                .pps_extension
+                .unwrap()
+        };
+        // The x265 PPS with pps_extension_4bits 1 and pps_extension_data_flag 1, 0, 1.
+        let ext = read(&hex!("44 01 c1 72 b4 62 80 d8"));
+        assert_eq!(ext.pps_extension_4bits, 1);
+        let data = ext.extension_data.unwrap();
+        assert_eq!(
+            (0..data.len())
+                .map(|i| data.get(i).unwrap())
+                .collect::<Vec<_>>(),
+            [true, false, true]
+        );
+
+        // With pps_multilayer_extension_flag and 4 bits of it, which are kept as they are.
+        let nal = hex!("44 01 c1 72 b4 62 a0 5c");
+        let ext = read(&nal);
+        assert!(ext.pps_multilayer_extension_flag);
+        let data = ext.extension_data.unwrap();
+        assert_eq!((data.len(), data.as_bytes()), (4, &[0xb0][..]));
+        #[cfg(feature = "write")]
+        {
+            let mut w = crate::rbsp::BitWriter::new();
+            w.write_bits_from(&nal[2..], 0, 32);
+            w.write_bool(true);
+            w.write_u8(8, 0b0100_0000);
+            w.write_raw_bits(&data);
+            w.write_rbsp_trailing_bits();
+            assert_eq!(w.into_bytes(), &nal[2..]);
         }
     }
 
     #[test]
-    fn test_transform_8x8_mode_with_scaling_matrix() {
-        let sps = hex!(
-            "64 00 29 ac 1b 1a 50 1e 00 89 f9 70 11 00 00 03 e9 00 00 bb 80 e2 60 00 04 c3 7a 00 00
-             72 70 e8 c4 b8 c4 c0 00 09 86 f4 00 00 e4 e1 d1 89 70 f8 e1 85 2c"
+    fn lossless() {
+        let ctx = ctx_with_sps();
+        let sps = ctx.sps().next().unwrap();
+        let mut pps = PicParameterSet::from_bits(
+            &ctx,
+            BitReader::new(&*decode_nal(&hex!("44 01 c1 72 b4 62 40")).unwrap()),
+        )
+        .unwrap();
+        let info = pps.lossless_info(sps, None).unwrap();
+        assert!(!info.may_be_lossless());
+
+        pps.transquant_bypass_enabled_flag = true;
+        let info = pps.lossless_info(sps, None).unwrap();
+        assert!(info.transquant_bypass_enabled);
+        assert!(info.may_be_lossless());
+
+        pps.transquant_bypass_enabled_flag = false;
+        pps.transform_skip_enabled_flag = true;
+        assert!(!pps.lossless_info(sps, None).unwrap().transform_skip_at_qp4);
+        assert!(
+            pps.lossless_info(sps, Some(-22))
+                .unwrap()
+                .transform_skip_at_qp4
         );
-        let pps = hex!(
-            "ea 8d ce 50 94 8d 18 b2 5a 55 28 4a 46 8c 59 2d 2a 50 c9 1a 31 64 b4 aa 85 48 d2 75 d5
-             25 1d 23 49 d2 7a 23 74 93 7a 49 be 95 da ad d5 3d 7a 6b 54 22 9a 4e 93 d6 ea 9f a4 ee
-             aa fd 6e bf f5 f7"
+
+        // Unbounded deltas of a malformed stream.
+        assert_eq!(pps.slice_qp_y(-22), Some(4));
+        assert_eq!(pps.slice_qp_y(i32::MAX), None);
+        assert!(
+            !pps.lossless_info(sps, Some(i32::MAX))
+                .unwrap()
+                .transform_skip_at_qp4
         );
-        let sps = super::sps::SeqParameterSet::from_bits(rbsp::BitReader::new(&sps[..]))
-            .expect("unexpected test data");
-        let mut ctx = Context::default();
-        ctx.put_seq_param_set(sps);
+    }
+
+    #[test]
+    fn unknown_sps() {
+        let ctx = Context::default();
+        let pps = hex!("44 01 c1 72 b4 62 40");
+        assert!(matches!(
+            PicParameterSet::from_bits(&ctx, BitReader::new(&*decode_nal(&pps).unwrap())),
+            Err(PpsError::UnknownSeqParamSetId(_))
+        ));
+    }
+
+    #[test]
+    fn uniform_spacing_huge() {
+        // (i + 1) * 2^29 overflows u32 from the eighth column on.
+        let widths = uniform_spacing(1 << 29, 20);
+        assert_eq!(widths.iter().map(|&w| u64::from(w)).sum::<u64>(), 1 << 29);
+        assert!(widths.iter().all(|&w| w.abs_diff((1 << 29) / 20) <= 1));
+    }
+
+    #[test]
+    fn tile_scan() {
+        let ctx = ctx_with_sps();
+        let sps = ctx.sps().next().unwrap();
+        let mut pps = PicParameterSet::from_bits(
+            &ctx,
+            BitReader::new(&*decode_nal(&hex!("44 01 c1 72 b4 62 40")).unwrap()),
+        )
+        .unwrap();
+
+        // Without tiles, raster and tile scans are the same.
+        let scan = pps.tile_scan(sps).unwrap();
+        assert_eq!(scan.ctb_addr_rs_to_ts[100], 100);
+        assert_eq!(scan.ctb_xy(47), Some((1, 2)));
+        assert_eq!(scan.ctb_xy(414), None);
+        // The picture is 736x576, 23 by 18 whole CTBs, so CTB 22 ends the first row unclipped.
+        assert_eq!(
+            scan.ctb_rect(22),
+            Some(Rect {
+                x: 704,
+                y: 0,
+                width: 32,
+                height: 32
+            })
+        );
+        assert_eq!(scan.slice_segment_ctbs(20, Some(24)).count(), 4);
 
-        let pps = PicParameterSet::from_bits(&ctx, rbsp::BitReader::new(&pps[..]))
-            .expect("we mis-parsed pic_scaling_matrix when transform_8x8_mode_flag is active");
+        // 23x18 CTBs in 2x2 uniformly spaced tiles: 11+12 columns, 9+9 rows.
+        pps.tiles = Some(Tiles {
+            num_tile_columns_minus1: 1,
+            num_tile_rows_minus1: 1,
+            spacing: TileSpacing::Uniform,
+            loop_filter_across_tiles_enabled_flag: true,
+        });
+        let scan = pps.tile_scan(sps).unwrap();
+        assert_eq!(scan.col_bd, [0, 11, 23]);
+        assert_eq!(scan.row_bd, [0, 9, 18]);
+        assert_eq!(scan.ctb_addr_rs_to_ts[11], 99);
+        assert_eq!(scan.ctb_addr_ts_to_rs[99], 11);
+        assert_eq!(scan.tile_id[99], 1);
+        // A slice segment starting at the second tile covers the whole tile first.
+        let ctbs: Vec<_> = scan.slice_segment_ctbs(11, Some(9 * 23)).collect();
+        assert_eq!(ctbs.len(), 12 * 9);
+        assert_eq!(&ctbs[..2], &[11, 12]);
+        assert_eq!(ctbs[12], 23 + 11);
 
-        // if transform_8x8_mode_flag were false or pic_scaling_matrix were None then we wouldn't
-        // be recreating the required conditions for the test
+        pps.tiles = Some(Tiles {
+            num_tile_columns_minus1: 1,
+            num_tile_rows_minus1: 0,
+            spacing: TileSpacing::Explicit {
+                column_width_minus1: vec![22],
+                row_height_minus1: vec![],
+            },
+            loop_filter_across_tiles_enabled_flag: true,
+        });
         assert!(matches!(
-            pps.extension,
-            Some(PicParameterSetExtra {
-                transform_8x8_mode_flag: true,
-                pic_scaling_matrix: Some(_),
-                ..
+            pps.tile_scan(sps),
+            Err(PpsError::FieldValueTooLarge {
+                name: "column_width_minus1",
+                value: 22
             })
         ));
     }
+
+    #[test]
+    fn parallelism_threads() {
+        let info = ParallelismInfo {
+            num_tiles: 4,
+            entropy_coding_sync_enabled_flag: false,
+            wavefront_rows: 1,
+            min_spatial_segmentation_idc: 0,
+            slices_per_picture: Some(2),
+        };
+        assert_eq!(info.parallelism_type(), ParallelismType::Tile);
+        assert_eq!(info.max_useful_threads(), 4);
+        let info = ParallelismInfo {
+            num_tiles: 1,
+            min_spatial_segmentation_idc: 28,
+            ..info
+        };
+        assert_eq!(info.parallelism_type(), ParallelismType::Slice);
+        assert_eq!(info.min_spatial_segments(), 8);
+        assert_eq!(info.max_useful_threads(), 8);
+        // A partial segment's worth of samples still needs a segment of its own.
+        let segments = |min_spatial_segmentation_idc| {
+            ParallelismInfo {
+                min_spatial_segmentation_idc,
+                ..info
+            }
+            .min_spatial_segments()
+        };
+        assert_eq!([1, 4, 5].map(segments), [2, 2, 3]);
+    }
+
+    #[test]
+    fn screen_content_tools() {
+        let ctx = ctx_with_sps();
+        let sps = ctx.sps().next().unwrap();
+        let pps = PicParameterSet::from_bits(
+            &ctx,
+            BitReader::new(&*decode_nal(&hex!("44 01 c1 72 b4 62 40")).unwrap()),
+        )
+        .unwrap();
+        assert!(!pps.screen_content_tools(sps).any());
+
+        // The same PPS with a pps_scc_extension enabling intra block copy and adaptive colour
+        // transform.
+        let pps = PicParameterSet::from_bits(
+            &ctx,
+            BitReader::new(&*decode_nal(&hex!("44 01 c1 72 b4 62 88 6e 80")).unwrap()),
+        )
+        .unwrap();
+        let scc = pps.pps_scc_extension().unwrap();
+        assert_eq!(
+            scc.residual_adaptive_colour_transform,
+            Some(ActQpOffsets {
+                pps_slice_act_qp_offsets_present_flag: false,
+                pps_act_y_qp_offset_plus5: 0,
+                pps_act_cb_qp_offset_plus5: 0,
+                pps_act_cr_qp_offset_plus3: 0,
+            })
+        );
+        assert_eq!(scc.pps_palette_predictor_initializers, None);
+        assert_eq!(
+            pps.screen_content_tools(sps),
+            ScreenContentTools {
+                intra_block_copy: true,
+                palette_mode: false,
+                adaptive_colour_transform: true,
+            }
+        );
+    }
 }
-*/
@@ -0,0 +1,249 @@
+//! The `buffering_period()` SEI message, as specified in clause D.2.2.
+//!
+//! It anchors the CPB removal times of the following access units: their `pic_timing()`
+//! removal delays count from the removal of the access unit carrying this message.
+
+use super::{HeaderType, SeiMessage};
+use crate::nal::pps::ParamSetIdError;
+use crate::nal::sps::{HrdParametersCommonInf, SeqParamSetId, SeqParameterSet};
+#[cfg(feature = "write")]
+use crate::rbsp::BitWriter;
+use crate::rbsp::{BitRead, BitReader, BitReaderError};
+use crate::Context;
+
+#[derive(Debug)]
+pub enum BufferingPeriodError {
+    RbspError(BitReaderError),
+    /// The message was not a `buffering_period()` message
+    WrongPayloadType(HeaderType),
+    BadSeqParamSetId(ParamSetIdError),
+    UndefinedSeqParamSetId(SeqParamSetId),
+    /// The SPS has no HRD parameters, so the lengths of the fields aren't known
+    NoHrdParameters,
+}
+impl BufferingPeriodError {
+    /// See [`crate::error_code`].
+    pub fn code(&self) -> u32 {
+        crate::error_code::BUFFERING_PERIOD
+            + match self {
+                BufferingPeriodError::RbspError(..) => 1,
+                BufferingPeriodError::WrongPayloadType(..) => 2,
+                BufferingPeriodError::BadSeqParamSetId(..) => 3,
+                BufferingPeriodError::UndefinedSeqParamSetId(..) => 4,
+                BufferingPeriodError::NoHrdParameters => 5,
+            }
+    }
+}
+impl From<BitReaderError> for BufferingPeriodError {
+    fn from(e: BitReaderError) -> Self {
+        BufferingPeriodError::RbspError(e)
+    }
+}
+
+/// The initial CPB removal delay and offset of one CPB specification, in units of a 90 kHz
+/// clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct InitialCpbRemoval {
+    pub initial_cpb_removal_delay: u32,
+    pub initial_cpb_removal_offset: u32,
+    /// `initial_alt_cpb_removal_delay` and `initial_alt_cpb_removal_offset`, present with
+    /// sub-picture HRD parameters or `irap_cpb_params_present_flag`.
+    pub alt: Option<(u32, u32)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct BufferingPeriod {
+    pub bp_seq_parameter_set_id: SeqParamSetId,
+    /// `cpb_delay_offset` and `dpb_delay_offset`, if `irap_cpb_params_present_flag`.
+    pub irap_cpb_params: Option<(u32, u32)>,
+    pub concatenation_flag: bool,
+    pub au_cpb_removal_delay_delta_minus1: u32,
+    /// One per CPB specification, if `NalHrdBpPresentFlag`.
+    pub nal_initial_cpb_removal: Vec<InitialCpbRemoval>,
+    /// One per CPB specification, if `VclHrdBpPresentFlag`.
+    pub vcl_initial_cpb_removal: Vec<InitialCpbRemoval>,
+}
+impl BufferingPeriod {
+    /// Reads the message with the lengths of the HRD parameters of the SPS it names. The
+    /// `use_alt_cpb_params_flag` payload extension isn't supported.
+    pub fn read(ctx: &Context, msg: &SeiMessage<'_>) -> Result<Self, BufferingPeriodError> {
+        if msg.payload_type != HeaderType::BufferingPeriod {
+            return Err(BufferingPeriodError::WrongPayloadType(msg.payload_type));
+        }
+        let mut r = BitReader::new(msg.payload);
+        let id = SeqParamSetId::from_u32(r.read_ue("bp_seq_parameter_set_id")?)
+            .map_err(BufferingPeriodError::BadSeqParamSetId)?;
+        let sps = ctx
+            .sps_by_id(id)
+            .ok_or(BufferingPeriodError::UndefinedSeqParamSetId(id))?;
+        let (common, cpb_cnt) = hrd(sps).ok_or(BufferingPeriodError::NoHrdParameters)?;
+        let params = common.parameters.as_ref().expect("checked by hrd()");
+        let sub_pic = params.sub_pic_hrd_params.is_some();
+        let irap_cpb_params_present_flag =
+            !sub_pic && r.read_bool("irap_cpb_params_present_flag")?;
+        let delay_len = u32::from(params.au_cpb_removal_delay_length_minus1) + 1;
+        let irap_cpb_params = if irap_cpb_params_present_flag {
+            Some((
+                r.read_u32(delay_len, "cpb_delay_offset")?,
+                r.read_u32(
+                    u32::from(params.dpb_output_delay_length_minus1) + 1,
+                    "dpb_delay_offset",
+                )?,
+            ))
+        } else {
+            None
+        };
+        let concatenation_flag = r.read_bool("concatenation_flag")?;
+        let au_cpb_removal_delay_delta_minus1 =
+            r.read_u32(delay_len, "au_cpb_removal_delay_delta_minus1")?;
+        let initial_len = u32::from(params.initial_cpb_removal_delay_length_minus1) + 1;
+        let alt = sub_pic || irap_cpb_params_present_flag;
+        let mut read_initial = |present: bool| -> Result<_, BitReaderError> {
+            let mut entries = vec![];
+            if !present {
+                return Ok(entries);
+            }
+            for _ in 0..cpb_cnt {
+                entries.push(InitialCpbRemoval {
+                    initial_cpb_removal_delay: r
+                        .read_u32(initial_len, "initial_cpb_removal_delay")?,
+                    initial_cpb_removal_offset: r
+                        .read_u32(initial_len, "initial_cpb_removal_offset")?,
+                    alt: if alt {
+                        Some((
+                            r.read_u32(initial_len, "initial_alt_cpb_removal_delay")?,
+                            r.read_u32(initial_len, "initial_alt_cpb_removal_offset")?,
+                        ))
+                    } else {
+                        None
+                    },
+                });
+            }
+            Ok(entries)
+        };
+        let nal_initial_cpb_removal = read_initial(common.nal_hrd_parameters_present_flag)?;
+        let vcl_initial_cpb_removal = read_initial(common.vcl_hrd_parameters_present_flag)?;
+        r.finish_sei_payload()?;
+        Ok(BufferingPeriod {
+            bp_seq_parameter_set_id: id,
+            irap_cpb_params,
+            concatenation_flag,
+            au_cpb_removal_delay_delta_minus1,
+            nal_initial_cpb_removal,
+            vcl_initial_cpb_removal,
+        })
+    }
+
+    /// Returns the `sei_payload()` bytes of this message, with the field lengths of `sps`, or
+    /// `None` if it has no HRD parameters.
+    #[cfg(feature = "write")]
+    pub fn to_payload(&self, sps: &SeqParameterSet) -> Option<Vec<u8>> {
+        let (common, _) = hrd(sps)?;
+        let params = common.parameters.as_ref()?;
+        let mut w = BitWriter::new();
+        w.write_ue(u32::from(self.bp_seq_parameter_set_id.id()));
+        if params.sub_pic_hrd_params.is_none() {
+            w.write_bool(self.irap_cpb_params.is_some());
+        }
+        let delay_len = u32::from(params.au_cpb_removal_delay_length_minus1) + 1;
+        if let Some((cpb_delay_offset, dpb_delay_offset)) = self.irap_cpb_params {
+            w.write_u32(delay_len, cpb_delay_offset);
+            w.write_u32(
+                u32::from(params.dpb_output_delay_length_minus1) + 1,
+                dpb_delay_offset,
+            );
+        }
+        w.write_bool(self.concatenation_flag);
+        w.write_u32(delay_len, self.au_cpb_removal_delay_delta_minus1);
+        let initial_len = u32::from(params.initial_cpb_removal_delay_length_minus1) + 1;
+        for entry in self
+            .nal_initial_cpb_removal
+            .iter()
+            .chain(&self.vcl_initial_cpb_removal)
+        {
+            w.write_u32(initial_len, entry.initial_cpb_removal_delay);
+            w.write_u32(initial_len, entry.initial_cpb_removal_offset);
+            if let Some((delay, offset)) = entry.alt {
+                w.write_u32(initial_len, delay);
+                w.write_u32(initial_len, offset);
+            }
+        }
+        if !w.byte_aligned() {
+            // payload_bit_equal_to_one and payload_bit_equal_to_zero
+            w.write_byte_alignment();
+        }
+        Some(w.into_bytes())
+    }
+
+    /// Wraps a payload from [`Self::to_payload`] as an SEI message.
+    #[cfg(feature = "write")]
+    pub fn to_message<'a>(&self, payload: &'a [u8]) -> SeiMessage<'a> {
+        SeiMessage {
+            payload_type: HeaderType::BufferingPeriod,
+            payload,
+        }
+    }
+}
+
+/// The HRD common info of the SPS, if it has HRD parameters, and `CpbCnt` of its highest
+/// sub-layer.
+fn hrd(sps: &SeqParameterSet) -> Option<(&HrdParametersCommonInf, u32)> {
+    let hrd = sps
+        .vui_parameters
+        .as_ref()?
+        .timing_info
+        .as_ref()?
+        .hrd_parameters
+        .as_ref()?;
+    let common = hrd.common.as_ref().filter(|c| c.parameters.is_some())?;
+    Some((common, hrd.sub_layers.last()?.cpb_cnt()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rbsp::decode_nal;
+    use hex_literal::hex;
+
+    #[test]
+    fn round_trip() {
+        // "Haivision 1080i25" SPS: VCL HRD parameters with one CPB and 32 bit initial delays.
+        let sps = hex!(
+            "42 01 01 01 40 00 00 03 00 40 00 00 03 00 00 03 00 7b a0 03 c0 80 22 1f 79 e9 6e
+             44 a1 7f f8 00 08 00 13 50 10 10 1e d0 00 00 03 00 10 00 00 03 03 25 08 ff de 10
+             00 16 e3 60 00 05 dd 77 df 08 04 10"
+        );
+        let sps = SeqParameterSet::from_bits(BitReader::new(&*decode_nal(&sps).unwrap())).unwrap();
+        let mut ctx = Context::new();
+        ctx.put_seq_param_set(sps.clone());
+        // SPS 0, no IRAP parameters, a delta of 1 and an initial delay of 90000, offset 0.
+        let msg = SeiMessage {
+            payload_type: HeaderType::BufferingPeriod,
+            payload: &hex!("80 00 00 00 00 00 57 e4 00 00 00 00 20"),
+        };
+        let bp = BufferingPeriod::read(&ctx, &msg).unwrap();
+        assert_eq!(
+            bp,
+            BufferingPeriod {
+                bp_seq_parameter_set_id: SeqParamSetId::from_u32(0).unwrap(),
+                irap_cpb_params: None,
+                concatenation_flag: false,
+                au_cpb_removal_delay_delta_minus1: 0,
+                nal_initial_cpb_removal: vec![],
+                vcl_initial_cpb_removal: vec![InitialCpbRemoval {
+                    initial_cpb_removal_delay: 90000,
+                    initial_cpb_removal_offset: 0,
+                    alt: None,
+                }],
+            }
+        );
+        #[cfg(feature = "write")]
+        assert_eq!(bp.to_payload(&sps).unwrap(), msg.payload);
+        assert!(matches!(
+            BufferingPeriod::read(&Context::new(), &msg),
+            Err(BufferingPeriodError::UndefinedSeqParamSetId(_))
+        ));
+    }
+}
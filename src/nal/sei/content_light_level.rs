@@ -0,0 +1,92 @@
+//! The `content_light_level_info()` SEI message, as specified in clause D.2.35.
+
+use super::{HeaderType, SeiMessage};
+#[cfg(feature = "write")]
+use crate::rbsp::BitWriter;
+use crate::rbsp::{BitRead, BitReader, BitReaderError};
+
+#[derive(Debug)]
+pub enum ContentLightLevelError {
+    RbspError(BitReaderError),
+    /// The message was not a `content_light_level_info()` message
+    WrongPayloadType(HeaderType),
+}
+impl ContentLightLevelError {
+    /// See [`crate::error_code`].
+    pub fn code(&self) -> u32 {
+        crate::error_code::CONTENT_LIGHT_LEVEL
+            + match self {
+                ContentLightLevelError::RbspError(..) => 1,
+                ContentLightLevelError::WrongPayloadType(..) => 2,
+            }
+    }
+}
+impl From<BitReaderError> for ContentLightLevelError {
+    fn from(e: BitReaderError) -> Self {
+        ContentLightLevelError::RbspError(e)
+    }
+}
+
+/// The upper bounds of the light level of the content, in candelas per square metre (MaxCLL and
+/// MaxFALL of HDR10). Zero means the bound is unknown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct ContentLightLevelInfo {
+    pub max_content_light_level: u16,
+    pub max_pic_average_light_level: u16,
+}
+impl ContentLightLevelInfo {
+    pub fn read(msg: &SeiMessage<'_>) -> Result<Self, ContentLightLevelError> {
+        if msg.payload_type != HeaderType::ContentLightLevelInfo {
+            return Err(ContentLightLevelError::WrongPayloadType(msg.payload_type));
+        }
+        let mut r = BitReader::new(msg.payload);
+        let info = ContentLightLevelInfo {
+            max_content_light_level: r.read_u16(16, "max_content_light_level")?,
+            max_pic_average_light_level: r.read_u16(16, "max_pic_average_light_level")?,
+        };
+        r.finish_sei_payload()?;
+        Ok(info)
+    }
+
+    /// Returns the `sei_payload()` bytes of this message.
+    #[cfg(feature = "write")]
+    pub fn to_payload(&self) -> Vec<u8> {
+        let mut w = BitWriter::new();
+        w.write_u16(16, self.max_content_light_level);
+        w.write_u16(16, self.max_pic_average_light_level);
+        w.into_bytes()
+    }
+
+    /// Wraps a payload from [`Self::to_payload`] as an SEI message.
+    #[cfg(feature = "write")]
+    pub fn to_message<'a>(&self, payload: &'a [u8]) -> SeiMessage<'a> {
+        SeiMessage {
+            payload_type: HeaderType::ContentLightLevelInfo,
+            payload,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn read() {
+        let msg = SeiMessage {
+            payload_type: HeaderType::ContentLightLevelInfo,
+            payload: &[0x03, 0xe8, 0x01, 0x90],
+        };
+        let info = ContentLightLevelInfo::read(&msg).unwrap();
+        assert_eq!(info.max_content_light_level, 1000);
+        assert_eq!(info.max_pic_average_light_level, 400);
+        #[cfg(feature = "write")]
+        assert_eq!(info.to_payload(), msg.payload);
+        let msg = SeiMessage {
+            payload_type: HeaderType::ContentLightLevelInfo,
+            payload: &[0x03, 0xe8, 0x01],
+        };
+        assert!(ContentLightLevelInfo::read(&msg).is_err());
+    }
+}
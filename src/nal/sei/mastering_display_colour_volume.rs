@@ -0,0 +1,107 @@
+//! The `mastering_display_colour_volume()` SEI message, as specified in clause D.2.28.
+
+use super::{HeaderType, SeiMessage};
+#[cfg(feature = "write")]
+use crate::rbsp::BitWriter;
+use crate::rbsp::{BitRead, BitReader, BitReaderError};
+
+#[derive(Debug)]
+pub enum MasteringDisplayError {
+    RbspError(BitReaderError),
+    /// The message was not a `mastering_display_colour_volume()` message
+    WrongPayloadType(HeaderType),
+}
+impl MasteringDisplayError {
+    /// See [`crate::error_code`].
+    pub fn code(&self) -> u32 {
+        crate::error_code::MASTERING_DISPLAY
+            + match self {
+                MasteringDisplayError::RbspError(..) => 1,
+                MasteringDisplayError::WrongPayloadType(..) => 2,
+            }
+    }
+}
+impl From<BitReaderError> for MasteringDisplayError {
+    fn from(e: BitReaderError) -> Self {
+        MasteringDisplayError::RbspError(e)
+    }
+}
+
+/// The colour volume of the display used when mastering the content, e.g. the SMPTE ST 2086
+/// metadata of HDR10.
+///
+/// Chromaticity coordinates are in units of 0.00002, and luminances in units of 0.0001
+/// candelas per square metre.
+///
+/// ```
+/// use hevc_reader::nal::sei::mastering_display_colour_volume::MasteringDisplayColourVolume;
+/// // BT.2020 primaries and D65 white point, 1000 to 0.0050 cd/m^2.
+/// let mdcv = MasteringDisplayColourVolume {
+///     display_primaries: [(8500, 39850), (6550, 2300), (35400, 14600)],
+///     white_point: (15635, 16450),
+///     max_display_mastering_luminance: 10_000_000,
+///     min_display_mastering_luminance: 50,
+/// };
+/// let payload = mdcv.to_payload();
+/// assert_eq!(payload.len(), 24);
+/// assert_eq!(MasteringDisplayColourVolume::read(&mdcv.to_message(&payload)).unwrap(), mdcv);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct MasteringDisplayColourVolume {
+    /// `(display_primaries_x, display_primaries_y)` for each primary, which should be given in
+    /// green, blue, red order.
+    pub display_primaries: [(u16, u16); 3],
+    /// `(white_point_x, white_point_y)`.
+    pub white_point: (u16, u16),
+    pub max_display_mastering_luminance: u32,
+    pub min_display_mastering_luminance: u32,
+}
+impl MasteringDisplayColourVolume {
+    pub fn read(msg: &SeiMessage<'_>) -> Result<Self, MasteringDisplayError> {
+        if msg.payload_type != HeaderType::MasteringDisplayColourVolume {
+            return Err(MasteringDisplayError::WrongPayloadType(msg.payload_type));
+        }
+        let mut r = BitReader::new(msg.payload);
+        let mut display_primaries = [(0, 0); 3];
+        for (x, y) in &mut display_primaries {
+            *x = r.read_u16(16, "display_primaries_x")?;
+            *y = r.read_u16(16, "display_primaries_y")?;
+        }
+        let mdcv = MasteringDisplayColourVolume {
+            display_primaries,
+            white_point: (
+                r.read_u16(16, "white_point_x")?,
+                r.read_u16(16, "white_point_y")?,
+            ),
+            max_display_mastering_luminance: r.read_u32(32, "max_display_mastering_luminance")?,
+            min_display_mastering_luminance: r.read_u32(32, "min_display_mastering_luminance")?,
+        };
+        r.finish_sei_payload()?;
+        Ok(mdcv)
+    }
+
+    /// Returns the `sei_payload()` bytes of this message.
+    #[cfg(feature = "write")]
+    pub fn to_payload(&self) -> Vec<u8> {
+        let mut w = BitWriter::new();
+        for &(x, y) in &self.display_primaries {
+            w.write_u16(16, x);
+            w.write_u16(16, y);
+        }
+        w.write_u16(16, self.white_point.0);
+        w.write_u16(16, self.white_point.1);
+        w.write_u32(32, self.max_display_mastering_luminance);
+        w.write_u32(32, self.min_display_mastering_luminance);
+        w.into_bytes()
+    }
+
+    /// Wraps a payload from [`Self::to_payload`] as an SEI message.
+    #[cfg(feature = "write")]
+    pub fn to_message<'a>(&self, payload: &'a [u8]) -> SeiMessage<'a> {
+        SeiMessage {
+            payload_type: HeaderType::MasteringDisplayColourVolume,
+            payload,
+        }
+    }
+}
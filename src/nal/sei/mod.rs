@@ -0,0 +1,479 @@
+//! _Supplemental Enhancement Information_ messages, carried in prefix and suffix SEI NAL units.
+//!
+//! [`SeiReader`] splits an `sei_rbsp()` into its `sei_message()`s; the payloads of individual
+//! message types are parsed by the submodules. [`SeiNalReader`] does the same straight from the
+//! NAL unit, borrowing each payload as it is there, so that SEI passed through without looking
+//! inside is never copied.
+
+pub mod buffering_period;
+pub mod content_light_level;
+pub mod mastering_display_colour_volume;
+pub mod pic_timing;
+pub mod time_code;
+
+use std::borrow::Cow;
+
+use crate::rbsp::BitReaderError;
+
+/// The `payloadType` of an SEI message, as listed in clause 7.4.6.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum HeaderType {
+    BufferingPeriod,
+    PicTiming,
+    PanScanRect,
+    FillerPayload,
+    UserDataRegisteredItuTT35,
+    UserDataUnregistered,
+    RecoveryPoint,
+    SceneInfo,
+    PictureSnapshot,
+    ProgressiveRefinementSegmentStart,
+    ProgressiveRefinementSegmentEnd,
+    FilmGrainCharacteristics,
+    PostFilterHint,
+    ToneMappingInfo,
+    FramePackingArrangement,
+    DisplayOrientation,
+    GreenMetadata,
+    StructureOfPicturesInfo,
+    ActiveParameterSets,
+    DecodingUnitInfo,
+    TemporalSubLayerZeroIndex,
+    DecodedPictureHash,
+    ScalableNesting,
+    RegionRefreshInfo,
+    NoDisplay,
+    TimeCode,
+    MasteringDisplayColourVolume,
+    SegmentedRectFramePackingArrangement,
+    TemporalMotionConstrainedTileSets,
+    ChromaResamplingFilterHint,
+    KneeFunctionInfo,
+    ColourRemappingInfo,
+    DeinterlacedFieldIdentification,
+    ContentLightLevelInfo,
+    DependentRapIndication,
+    CodedRegionCompletion,
+    AlternativeTransferCharacteristics,
+    AmbientViewingEnvironment,
+    ContentColourVolume,
+    AlphaChannelInfo,
+    /// A payload type not (yet) known to this crate
+    Unknown(u32),
+}
+impl HeaderType {
+    pub fn from_id(id: u32) -> HeaderType {
+        match id {
+            0 => HeaderType::BufferingPeriod,
+            1 => HeaderType::PicTiming,
+            2 => HeaderType::PanScanRect,
+            3 => HeaderType::FillerPayload,
+            4 => HeaderType::UserDataRegisteredItuTT35,
+            5 => HeaderType::UserDataUnregistered,
+            6 => HeaderType::RecoveryPoint,
+            9 => HeaderType::SceneInfo,
+            15 => HeaderType::PictureSnapshot,
+            16 => HeaderType::ProgressiveRefinementSegmentStart,
+            17 => HeaderType::ProgressiveRefinementSegmentEnd,
+            19 => HeaderType::FilmGrainCharacteristics,
+            22 => HeaderType::PostFilterHint,
+            23 => HeaderType::ToneMappingInfo,
+            45 => HeaderType::FramePackingArrangement,
+            47 => HeaderType::DisplayOrientation,
+            56 => HeaderType::GreenMetadata,
+            128 => HeaderType::StructureOfPicturesInfo,
+            129 => HeaderType::ActiveParameterSets,
+            130 => HeaderType::DecodingUnitInfo,
+            131 => HeaderType::TemporalSubLayerZeroIndex,
+            132 => HeaderType::DecodedPictureHash,
+            133 => HeaderType::ScalableNesting,
+            134 => HeaderType::RegionRefreshInfo,
+            135 => HeaderType::NoDisplay,
+            136 => HeaderType::TimeCode,
+            137 => HeaderType::MasteringDisplayColourVolume,
+            138 => HeaderType::SegmentedRectFramePackingArrangement,
+            139 => HeaderType::TemporalMotionConstrainedTileSets,
+            140 => HeaderType::ChromaResamplingFilterHint,
+            141 => HeaderType::KneeFunctionInfo,
+            142 => HeaderType::ColourRemappingInfo,
+            143 => HeaderType::DeinterlacedFieldIdentification,
+            144 => HeaderType::ContentLightLevelInfo,
+            145 => HeaderType::DependentRapIndication,
+            146 => HeaderType::CodedRegionCompletion,
+            147 => HeaderType::AlternativeTransferCharacteristics,
+            148 => HeaderType::AmbientViewingEnvironment,
+            149 => HeaderType::ContentColourVolume,
+            165 => HeaderType::AlphaChannelInfo,
+            _ => HeaderType::Unknown(id),
+        }
+    }
+
+    pub fn id(self) -> u32 {
+        match self {
+            HeaderType::BufferingPeriod => 0,
+            HeaderType::PicTiming => 1,
+            HeaderType::PanScanRect => 2,
+            HeaderType::FillerPayload => 3,
+            HeaderType::UserDataRegisteredItuTT35 => 4,
+            HeaderType::UserDataUnregistered => 5,
+            HeaderType::RecoveryPoint => 6,
+            HeaderType::SceneInfo => 9,
+            HeaderType::PictureSnapshot => 15,
+            HeaderType::ProgressiveRefinementSegmentStart => 16,
+            HeaderType::ProgressiveRefinementSegmentEnd => 17,
+            HeaderType::FilmGrainCharacteristics => 19,
+            HeaderType::PostFilterHint => 22,
+            HeaderType::ToneMappingInfo => 23,
+            HeaderType::FramePackingArrangement => 45,
+            HeaderType::DisplayOrientation => 47,
+            HeaderType::GreenMetadata => 56,
+            HeaderType::StructureOfPicturesInfo => 128,
+            HeaderType::ActiveParameterSets => 129,
+            HeaderType::DecodingUnitInfo => 130,
+            HeaderType::TemporalSubLayerZeroIndex => 131,
+            HeaderType::DecodedPictureHash => 132,
+            HeaderType::ScalableNesting => 133,
+            HeaderType::RegionRefreshInfo => 134,
+            HeaderType::NoDisplay => 135,
+            HeaderType::TimeCode => 136,
+            HeaderType::MasteringDisplayColourVolume => 137,
+            HeaderType::SegmentedRectFramePackingArrangement => 138,
+            HeaderType::TemporalMotionConstrainedTileSets => 139,
+            HeaderType::ChromaResamplingFilterHint => 140,
+            HeaderType::KneeFunctionInfo => 141,
+            HeaderType::ColourRemappingInfo => 142,
+            HeaderType::DeinterlacedFieldIdentification => 143,
+            HeaderType::ContentLightLevelInfo => 144,
+            HeaderType::DependentRapIndication => 145,
+            HeaderType::CodedRegionCompletion => 146,
+            HeaderType::AlternativeTransferCharacteristics => 147,
+            HeaderType::AmbientViewingEnvironment => 148,
+            HeaderType::ContentColourVolume => 149,
+            HeaderType::AlphaChannelInfo => 165,
+            HeaderType::Unknown(id) => id,
+        }
+    }
+}
+
+/// A single `sei_message()`, with its payload still in RBSP form.
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct SeiMessage<'a> {
+    pub payload_type: HeaderType,
+    pub payload: &'a [u8],
+}
+#[cfg(feature = "write")]
+impl SeiMessage<'_> {
+    /// Appends the `sei_message()` syntax (type, size and payload) to an SEI RBSP being built.
+    pub fn write_to(&self, rbsp: &mut Vec<u8>) {
+        write_ff_coded(rbsp, self.payload_type.id() as usize);
+        write_ff_coded(rbsp, self.payload.len());
+        rbsp.extend_from_slice(self.payload);
+    }
+}
+
+#[cfg(feature = "write")]
+fn write_ff_coded(rbsp: &mut Vec<u8>, mut value: usize) {
+    while value >= 0xff {
+        rbsp.push(0xff);
+        value -= 0xff;
+    }
+    rbsp.push(value as u8);
+}
+
+/// Iterates the `sei_message()`s of an `sei_rbsp()`.
+///
+/// ```
+/// use hevc_reader::nal::sei::{HeaderType, SeiReader};
+/// // A single recovery_point() message, followed by rbsp_trailing_bits().
+/// let mut reader = SeiReader::from_rbsp_bytes(&[0x06, 0x01, 0xc4, 0x80]);
+/// let msg = reader.next().unwrap().unwrap();
+/// assert_eq!(msg.payload_type, HeaderType::RecoveryPoint);
+/// assert_eq!(msg.payload, &[0xc4]);
+/// assert!(reader.next().unwrap().is_none());
+/// ```
+#[derive(Debug)]
+pub struct SeiReader<'a> {
+    rbsp: &'a [u8],
+}
+impl<'a> SeiReader<'a> {
+    /// Wraps the RBSP of an SEI NAL unit, i.e. the bytes following the two byte NAL header
+    /// with emulation prevention already removed.
+    pub fn from_rbsp_bytes(rbsp: &'a [u8]) -> Self {
+        SeiReader { rbsp }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<SeiMessage<'a>>, BitReaderError> {
+        // more_rbsp_data(): only the rbsp_trailing_bits() byte remains
+        if self.rbsp.is_empty() || self.rbsp == [0x80] {
+            return Ok(None);
+        }
+        let payload_type = self.read_ff_coded("payload_type")?;
+        let payload_size = self.read_ff_coded("payload_size")? as usize;
+        if payload_size > self.rbsp.len() {
+            return Err(BitReaderError::ReaderErrorFor(
+                "sei_payload",
+                std::io::ErrorKind::UnexpectedEof.into(),
+            ));
+        }
+        let (payload, rest) = self.rbsp.split_at(payload_size);
+        self.rbsp = rest;
+        Ok(Some(SeiMessage {
+            payload_type: HeaderType::from_id(payload_type),
+            payload,
+        }))
+    }
+
+    /// Reads a value coded as a run of `0xFF` bytes followed by a final byte.
+    fn read_ff_coded(&mut self, name: &'static str) -> Result<u32, BitReaderError> {
+        let mut value = 0u32;
+        loop {
+            let Some((&b, rest)) = self.rbsp.split_first() else {
+                return Err(BitReaderError::ReaderErrorFor(
+                    name,
+                    std::io::ErrorKind::UnexpectedEof.into(),
+                ));
+            };
+            self.rbsp = rest;
+            value = value
+                .checked_add(u32::from(b))
+                .ok_or(BitReaderError::ExpGolombTooLarge(name))?;
+            if b != 0xff {
+                return Ok(value);
+            }
+        }
+    }
+}
+
+/// An `sei_message()` found by [`SeiNalReader`], whose payload still has its emulation
+/// prevention bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeiPayloadRef<'a> {
+    pub payload_type: HeaderType,
+    /// `payloadSize`, the length of the payload in RBSP form.
+    pub size: usize,
+    /// The payload as it is in the NAL unit.
+    escaped: &'a [u8],
+    /// The number of zero bytes just before the payload, which an emulation prevention byte at
+    /// its start follows, e.g. the last byte of a `payloadSize` of 255.
+    zeros: usize,
+}
+impl<'a> SeiPayloadRef<'a> {
+    /// The payload as it is in the NAL unit, with emulation prevention bytes.
+    pub fn escaped(&self) -> &'a [u8] {
+        self.escaped
+    }
+
+    /// The payload in RBSP form, borrowed unless it contains emulation prevention bytes.
+    pub fn payload(&self) -> Cow<'a, [u8]> {
+        let mut zeros = self.zeros;
+        let mut escapes = vec![];
+        for (i, &b) in self.escaped.iter().enumerate() {
+            if zeros >= 2 && b == 3 {
+                escapes.push(i);
+                zeros = 0;
+            } else {
+                zeros = if b == 0 { zeros + 1 } else { 0 };
+            }
+        }
+        if escapes.is_empty() {
+            return Cow::Borrowed(self.escaped);
+        }
+        let mut payload = Vec::with_capacity(self.size);
+        let mut start = 0;
+        for i in escapes {
+            payload.extend_from_slice(&self.escaped[start..i]);
+            start = i + 1;
+        }
+        payload.extend_from_slice(&self.escaped[start..]);
+        Cow::Owned(payload)
+    }
+
+    /// Calls `f` with the message, e.g. to parse the payload with one of the submodules.
+    pub fn with_message<T>(&self, f: impl FnOnce(&SeiMessage<'_>) -> T) -> T {
+        let payload = self.payload();
+        f(&SeiMessage {
+            payload_type: self.payload_type,
+            payload: &payload,
+        })
+    }
+}
+
+/// Iterates the `sei_message()`s of an SEI NAL unit without removing its emulation prevention
+/// bytes, borrowing each payload from the NAL unit.
+///
+/// ```
+/// use hevc_reader::nal::sei::{HeaderType, SeiNalReader};
+/// // A prefix SEI NAL unit with a user_data_unregistered() message whose payload needs an
+/// // emulation prevention byte, then a recovery_point() message.
+/// let nal = [0x4e, 0x01, 0x05, 0x04, 0x00, 0x00, 0x03, 0x01, 0x02, 0x06, 0x01, 0xc4, 0x80];
+/// let mut reader = SeiNalReader::new(&nal);
+/// let msg = reader.next().unwrap().unwrap();
+/// assert_eq!(msg.payload_type, HeaderType::UserDataUnregistered);
+/// assert_eq!(msg.escaped(), &nal[4..9]);
+/// assert_eq!(&*msg.payload(), &[0x00, 0x00, 0x01, 0x02]);
+/// let msg = reader.next().unwrap().unwrap();
+/// assert_eq!(msg.payload_type, HeaderType::RecoveryPoint);
+/// assert_eq!(msg.escaped(), &[0xc4]);
+/// assert!(reader.next().unwrap().is_none());
+/// ```
+#[derive(Debug)]
+pub struct SeiNalReader<'a> {
+    nal: &'a [u8],
+    pos: usize,
+    /// The number of zero bytes just before `pos`.
+    zeros: usize,
+}
+impl<'a> SeiNalReader<'a> {
+    /// Wraps an SEI NAL unit, without start code or length prefix.
+    pub fn new(nal: &'a [u8]) -> Self {
+        SeiNalReader {
+            nal,
+            pos: nal.len().min(2),
+            zeros: 0,
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<SeiPayloadRef<'a>>, BitReaderError> {
+        self.skip_escape();
+        // more_rbsp_data(): only the rbsp_trailing_bits() byte remains
+        let rest = &self.nal[self.pos..];
+        if rest.is_empty() || rest == [0x80] {
+            return Ok(None);
+        }
+        let payload_type = self.read_ff_coded("payload_type")?;
+        let size = self.read_ff_coded("payload_size")? as usize;
+        self.skip_escape();
+        let (start, zeros) = (self.pos, self.zeros);
+        for _ in 0..size {
+            self.read_byte("sei_payload")?;
+        }
+        Ok(Some(SeiPayloadRef {
+            payload_type: HeaderType::from_id(payload_type),
+            size,
+            escaped: &self.nal[start..self.pos],
+            zeros,
+        }))
+    }
+
+    /// Steps over an emulation prevention byte at the current position.
+    fn skip_escape(&mut self) {
+        if self.zeros >= 2 && self.nal.get(self.pos) == Some(&3) {
+            self.pos += 1;
+            self.zeros = 0;
+        }
+    }
+
+    fn read_byte(&mut self, name: &'static str) -> Result<u8, BitReaderError> {
+        self.skip_escape();
+        let Some(&b) = self.nal.get(self.pos) else {
+            return Err(BitReaderError::ReaderErrorFor(
+                name,
+                std::io::ErrorKind::UnexpectedEof.into(),
+            ));
+        };
+        self.pos += 1;
+        self.zeros = if b == 0 { self.zeros + 1 } else { 0 };
+        Ok(b)
+    }
+
+    /// Reads a value coded as a run of `0xFF` bytes followed by a final byte.
+    fn read_ff_coded(&mut self, name: &'static str) -> Result<u32, BitReaderError> {
+        let mut value = 0u32;
+        loop {
+            let b = self.read_byte(name)?;
+            value = value
+                .checked_add(u32::from(b))
+                .ok_or(BitReaderError::ExpGolombTooLarge(name))?;
+            if b != 0xff {
+                return Ok(value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn multiple_messages() {
+        // A 300 byte user_data_unregistered() followed by a pic_timing() message.
+        let mut rbsp = vec![0x05, 0xff, 0x2d];
+        rbsp.extend_from_slice(&[0xaa; 300]);
+        rbsp.extend_from_slice(&[0x01, 0x01, 0x10, 0x80]);
+        let mut reader = SeiReader::from_rbsp_bytes(&rbsp);
+        let msg = reader.next().unwrap().unwrap();
+        assert_eq!(msg.payload_type, HeaderType::UserDataUnregistered);
+        assert_eq!(msg.payload.len(), 300);
+        let msg = reader.next().unwrap().unwrap();
+        assert_eq!(msg.payload_type, HeaderType::PicTiming);
+        assert_eq!(msg.payload, &[0x10]);
+        assert!(reader.next().unwrap().is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "write")]
+    fn write_round_trip() {
+        let payload = [0xaa; 300];
+        let msg = SeiMessage {
+            payload_type: HeaderType::Unknown(600),
+            payload: &payload,
+        };
+        let mut rbsp = vec![];
+        msg.write_to(&mut rbsp);
+        rbsp.push(0x80);
+        assert_eq!(&rbsp[..5], &[0xff, 0xff, 0x5a, 0xff, 0x2d]);
+        let mut reader = SeiReader::from_rbsp_bytes(&rbsp);
+        assert_eq!(reader.next().unwrap().unwrap(), msg);
+        assert!(reader.next().unwrap().is_none());
+    }
+
+    #[test]
+    fn nal_reader_escapes() {
+        // An empty buffering_period()-typed message, whose zero type and size bytes are followed
+        // by an emulation prevention byte, then a user_data_unregistered() message with one in
+        // its payload; and a message cut short.
+        let nal = [
+            0x4e, 0x01, 0x00, 0x00, 0x03, 0x05, 0x04, 0x00, 0x00, 0x03, 0x01, 0x02, 0x01, 0x05,
+            0x80,
+        ];
+        let mut reader = SeiNalReader::new(&nal);
+        let msg = reader.next().unwrap().unwrap();
+        assert_eq!(
+            (msg.payload_type, msg.size),
+            (HeaderType::BufferingPeriod, 0)
+        );
+        let msg = reader.next().unwrap().unwrap();
+        assert_eq!(msg.size, 4);
+        assert_eq!(msg.escaped(), &nal[7..12]);
+        assert_eq!(&*msg.payload(), &[0x00, 0x00, 0x01, 0x02]);
+        assert!(reader.next().is_err());
+    }
+
+    #[test]
+    fn nal_reader_size_255() {
+        // A 255 byte user_data_unregistered() payload, whose size is coded as `ff 00`, starting
+        // with a zero byte and so an emulation prevention byte.
+        let mut nal = vec![0x4e, 0x01, 0x05, 0xff, 0x00, 0x00, 0x03, 0x01];
+        nal.extend_from_slice(&[0xaa; 253]);
+        nal.push(0x80);
+        let mut reader = SeiNalReader::new(&nal);
+        let msg = reader.next().unwrap().unwrap();
+        assert_eq!(msg.size, 255);
+        assert_eq!(msg.escaped().len(), 256);
+        let payload = msg.payload();
+        assert_eq!(payload.len(), 255);
+        assert_eq!(payload[..3], [0x00, 0x01, 0xaa]);
+        assert!(reader.next().unwrap().is_none());
+    }
+
+    #[test]
+    fn truncated() {
+        let mut reader = SeiReader::from_rbsp_bytes(&[0x01, 0x05, 0x10, 0x80]);
+        assert!(reader.next().is_err());
+    }
+}
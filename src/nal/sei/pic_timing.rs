@@ -0,0 +1,276 @@
+//! The `pic_timing()` SEI message, as specified in clause D.2.3.
+//!
+//! Unlike H.264, the HEVC picture timing message carries no clock timestamps; the time base
+//! lives in the VUI or VPS timing info, and this message only tells how each picture is to be
+//! displayed and when it leaves the CPB and DPB in clock ticks.
+
+use super::{HeaderType, SeiMessage};
+use crate::nal::sps::{HrdParametersCommonInfParameters, SeqParameterSet, SpsError};
+use crate::rbsp::{BitRead, BitReader, BitReaderError};
+
+#[derive(Debug)]
+pub enum PicTimingError {
+    RbspError(BitReaderError),
+    /// The message was not a `pic_timing()` message
+    WrongPayloadType(HeaderType),
+    /// The SPS has no VUI, so the message syntax is empty and can't be present
+    NoVui,
+    BadSeqParamSet(SpsError),
+    /// A field in the bitstream had a value too large for a subsequent calculation
+    FieldValueTooLarge {
+        name: &'static str,
+        value: u32,
+    },
+}
+impl PicTimingError {
+    /// See [`crate::error_code`].
+    pub fn code(&self) -> u32 {
+        crate::error_code::PIC_TIMING
+            + match self {
+                PicTimingError::RbspError(..) => 1,
+                PicTimingError::WrongPayloadType(..) => 2,
+                PicTimingError::NoVui => 3,
+                PicTimingError::BadSeqParamSet(..) => 4,
+                PicTimingError::FieldValueTooLarge { .. } => 5,
+            }
+    }
+}
+impl From<BitReaderError> for PicTimingError {
+    fn from(e: BitReaderError) -> Self {
+        PicTimingError::RbspError(e)
+    }
+}
+
+/// `pic_struct`, see Table D.2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum PicStruct {
+    Frame,
+    TopField,
+    BottomField,
+    TopBottom,
+    BottomTop,
+    TopBottomTop,
+    BottomTopBottom,
+    FrameDoubling,
+    FrameTripling,
+    TopFieldPairedWithPreviousBottom,
+    BottomFieldPairedWithPreviousTop,
+    TopFieldPairedWithNextBottom,
+    BottomFieldPairedWithNextTop,
+    Reserved(u8),
+}
+impl PicStruct {
+    pub fn from_id(id: u8) -> PicStruct {
+        match id {
+            0 => PicStruct::Frame,
+            1 => PicStruct::TopField,
+            2 => PicStruct::BottomField,
+            3 => PicStruct::TopBottom,
+            4 => PicStruct::BottomTop,
+            5 => PicStruct::TopBottomTop,
+            6 => PicStruct::BottomTopBottom,
+            7 => PicStruct::FrameDoubling,
+            8 => PicStruct::FrameTripling,
+            9 => PicStruct::TopFieldPairedWithPreviousBottom,
+            10 => PicStruct::BottomFieldPairedWithPreviousTop,
+            11 => PicStruct::TopFieldPairedWithNextBottom,
+            12 => PicStruct::BottomFieldPairedWithNextTop,
+            _ => PicStruct::Reserved(id),
+        }
+    }
+
+    /// True if the picture is a single field rather than a frame.
+    pub fn is_field(self) -> bool {
+        matches!(
+            self,
+            PicStruct::TopField
+                | PicStruct::BottomField
+                | PicStruct::TopFieldPairedWithPreviousBottom
+                | PicStruct::BottomFieldPairedWithPreviousTop
+                | PicStruct::TopFieldPairedWithNextBottom
+                | PicStruct::BottomFieldPairedWithNextTop
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct FrameFieldInfo {
+    pub pic_struct: PicStruct,
+    pub source_scan_type: u8,
+    pub duplicate_flag: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct DecodingUnits {
+    pub num_nalus_in_du_minus1: Vec<u32>,
+    /// `du_common_cpb_removal_delay_increment_minus1`, if `du_common_cpb_removal_delay_flag`
+    pub du_common_cpb_removal_delay_increment_minus1: Option<u32>,
+    /// `du_cpb_removal_delay_increment_minus1[i]`, for all but the last decoding unit, if not
+    /// using the common increment
+    pub du_cpb_removal_delay_increment_minus1: Vec<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct CpbDpbDelays {
+    pub au_cpb_removal_delay_minus1: u32,
+    pub pic_dpb_output_delay: u32,
+    pub pic_dpb_output_du_delay: Option<u32>,
+    pub decoding_units: Option<DecodingUnits>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct PicTiming {
+    pub frame_field_info: Option<FrameFieldInfo>,
+    pub delays: Option<CpbDpbDelays>,
+}
+impl PicTiming {
+    pub fn read(sps: &SeqParameterSet, msg: &SeiMessage<'_>) -> Result<PicTiming, PicTimingError> {
+        if msg.payload_type != HeaderType::PicTiming {
+            return Err(PicTimingError::WrongPayloadType(msg.payload_type));
+        }
+        let vui = sps.vui_parameters.as_ref().ok_or(PicTimingError::NoVui)?;
+        let mut r = BitReader::new(msg.payload);
+        let frame_field_info = if vui.frame_field_info_present_flag {
+            Some(FrameFieldInfo {
+                pic_struct: PicStruct::from_id(r.read_u8(4, "pic_struct")?),
+                source_scan_type: r.read_u8(2, "source_scan_type")?,
+                duplicate_flag: r.read_bool("duplicate_flag")?,
+            })
+        } else {
+            None
+        };
+        // CpbDpbDelaysPresentFlag is set when the HRD common info carries its parameters.
+        let hrd = vui
+            .timing_info
+            .as_ref()
+            .and_then(|t| t.hrd_parameters.as_ref())
+            .and_then(|h| h.common.as_ref())
+            .and_then(|c| c.parameters.as_ref());
+        let delays = match hrd {
+            Some(hrd) => Some(Self::read_delays(&mut r, sps, hrd)?),
+            None => None,
+        };
+        r.finish_sei_payload()?;
+        Ok(PicTiming {
+            frame_field_info,
+            delays,
+        })
+    }
+
+    fn read_delays<R: BitRead>(
+        r: &mut R,
+        sps: &SeqParameterSet,
+        hrd: &HrdParametersCommonInfParameters,
+    ) -> Result<CpbDpbDelays, PicTimingError> {
+        let au_cpb_removal_delay_minus1 = r.read_u32(
+            u32::from(hrd.au_cpb_removal_delay_length_minus1) + 1,
+            "au_cpb_removal_delay_minus1",
+        )?;
+        let pic_dpb_output_delay = r.read_u32(
+            u32::from(hrd.dpb_output_delay_length_minus1) + 1,
+            "pic_dpb_output_delay",
+        )?;
+        let mut pic_dpb_output_du_delay = None;
+        let mut decoding_units = None;
+        if let Some(sub_pic) = &hrd.sub_pic_hrd_params {
+            pic_dpb_output_du_delay = Some(r.read_u32(
+                u32::from(sub_pic.dpb_output_delay_du_length_minus1) + 1,
+                "pic_dpb_output_du_delay",
+            )?);
+            if sub_pic.sub_pic_cpb_params_in_pic_timing_sei_flag {
+                let increment_len =
+                    u32::from(sub_pic.du_cpb_removal_delay_increment_length_minus1) + 1;
+                let num_decoding_units_minus1 = r.read_ue("num_decoding_units_minus1")?;
+                let pic_size_in_ctbs_y = sps
+                    .derived()
+                    .map_err(PicTimingError::BadSeqParamSet)?
+                    .pic_size_in_ctbs_y;
+                if num_decoding_units_minus1 >= pic_size_in_ctbs_y {
+                    return Err(PicTimingError::FieldValueTooLarge {
+                        name: "num_decoding_units_minus1",
+                        value: num_decoding_units_minus1,
+                    });
+                }
+                let du_common_cpb_removal_delay_increment_minus1 =
+                    if r.read_bool("du_common_cpb_removal_delay_flag")? {
+                        Some(r.read_u32(
+                            increment_len,
+                            "du_common_cpb_removal_delay_increment_minus1",
+                        )?)
+                    } else {
+                        None
+                    };
+                let mut num_nalus_in_du_minus1 =
+                    Vec::with_capacity(num_decoding_units_minus1 as usize + 1);
+                let mut du_cpb_removal_delay_increment_minus1 = vec![];
+                for i in 0..=num_decoding_units_minus1 {
+                    num_nalus_in_du_minus1.push(r.read_ue("num_nalus_in_du_minus1")?);
+                    if du_common_cpb_removal_delay_increment_minus1.is_none()
+                        && i < num_decoding_units_minus1
+                    {
+                        du_cpb_removal_delay_increment_minus1.push(
+                            r.read_u32(increment_len, "du_cpb_removal_delay_increment_minus1")?,
+                        );
+                    }
+                }
+                decoding_units = Some(DecodingUnits {
+                    num_nalus_in_du_minus1,
+                    du_common_cpb_removal_delay_increment_minus1,
+                    du_cpb_removal_delay_increment_minus1,
+                });
+            }
+        }
+        Ok(CpbDpbDelays {
+            au_cpb_removal_delay_minus1,
+            pic_dpb_output_delay,
+            pic_dpb_output_du_delay,
+            decoding_units,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rbsp::decode_nal;
+    use hex_literal::hex;
+
+    #[test]
+    fn haivision_field() {
+        // "Haivision 1080i25" from the SPS tests: frame_field_info_present_flag, and VCL HRD
+        // parameters with 31 bit CPB removal and DPB output delays.
+        let sps = hex!(
+            "42 01 01 01 40 00 00 03 00 40 00 00 03 00 00 03 00 7b a0 03 c0 80 22 1f 79 e9 6e
+             44 a1 7f f8 00 08 00 13 50 10 10 1e d0 00 00 03 00 10 00 00 03 03 25 08 ff de 10
+             00 16 e3 60 00 05 dd 77 df 08 04 10"
+        );
+        let sps = SeqParameterSet::from_bits(BitReader::new(&*decode_nal(&sps).unwrap())).unwrap();
+        let msg = SeiMessage {
+            payload_type: HeaderType::PicTiming,
+            payload: &hex!("10 00 00 00 00 00 00 00 14"),
+        };
+        let pic_timing = PicTiming::read(&sps, &msg).unwrap();
+        assert_eq!(
+            pic_timing,
+            PicTiming {
+                frame_field_info: Some(FrameFieldInfo {
+                    pic_struct: PicStruct::TopField,
+                    source_scan_type: 0,
+                    duplicate_flag: false,
+                }),
+                delays: Some(CpbDpbDelays {
+                    au_cpb_removal_delay_minus1: 0,
+                    pic_dpb_output_delay: 2,
+                    pic_dpb_output_du_delay: None,
+                    decoding_units: None,
+                }),
+            }
+        );
+        assert!(pic_timing.frame_field_info.unwrap().pic_struct.is_field());
+    }
+}
@@ -0,0 +1,214 @@
+//! The `time_code()` SEI message, as specified in clause D.2.27.
+
+use super::{HeaderType, SeiMessage};
+#[cfg(feature = "write")]
+use crate::rbsp::BitWriter;
+use crate::rbsp::{BitRead, BitReader, BitReaderError};
+
+#[derive(Debug)]
+pub enum TimeCodeError {
+    RbspError(BitReaderError),
+    /// The message was not a `time_code()` message
+    WrongPayloadType(HeaderType),
+    /// `num_clock_ts` was 0, which the spec doesn't allow
+    NoClockTimestamps,
+}
+impl TimeCodeError {
+    /// See [`crate::error_code`].
+    pub fn code(&self) -> u32 {
+        crate::error_code::TIME_CODE
+            + match self {
+                TimeCodeError::RbspError(..) => 1,
+                TimeCodeError::WrongPayloadType(..) => 2,
+                TimeCodeError::NoClockTimestamps => 3,
+            }
+    }
+}
+impl From<BitReaderError> for TimeCodeError {
+    fn from(e: BitReaderError) -> Self {
+        TimeCodeError::RbspError(e)
+    }
+}
+
+/// `counting_type` 4, which drops `n_frames` counts 0 and 1 at the start of each minute but
+/// every tenth, as in SMPTE drop-frame time code for 29.97 Hz.
+pub const COUNTING_TYPE_DROP_FRAME: u8 = 4;
+
+/// One clock timestamp, e.g. of one field of a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct ClockTimestamp {
+    pub units_field_based_flag: bool,
+    pub counting_type: u8,
+    pub full_timestamp_flag: bool,
+    pub discontinuity_flag: bool,
+    pub cnt_dropped_flag: bool,
+    pub n_frames: u16,
+    /// Present if `full_timestamp_flag` or `seconds_flag`; when absent the value of the
+    /// previous picture in output order applies.
+    pub seconds_value: Option<u8>,
+    /// Present if `full_timestamp_flag` or `minutes_flag`.
+    pub minutes_value: Option<u8>,
+    /// Present if `full_timestamp_flag` or `hours_flag`.
+    pub hours_value: Option<u8>,
+    pub time_offset_length: u8,
+    /// Zero unless `time_offset_length` is non-zero.
+    pub time_offset_value: i32,
+}
+impl ClockTimestamp {
+    fn read<R: BitRead>(r: &mut R) -> Result<Self, BitReaderError> {
+        let units_field_based_flag = r.read_bool("units_field_based_flag")?;
+        let counting_type = r.read_u8(5, "counting_type")?;
+        let full_timestamp_flag = r.read_bool("full_timestamp_flag")?;
+        let discontinuity_flag = r.read_bool("discontinuity_flag")?;
+        let cnt_dropped_flag = r.read_bool("cnt_dropped_flag")?;
+        let n_frames = r.read_u16(9, "n_frames")?;
+        let (mut seconds_value, mut minutes_value, mut hours_value) = (None, None, None);
+        if full_timestamp_flag {
+            seconds_value = Some(r.read_u8(6, "seconds_value")?);
+            minutes_value = Some(r.read_u8(6, "minutes_value")?);
+            hours_value = Some(r.read_u8(5, "hours_value")?);
+        } else if r.read_bool("seconds_flag")? {
+            seconds_value = Some(r.read_u8(6, "seconds_value")?);
+            if r.read_bool("minutes_flag")? {
+                minutes_value = Some(r.read_u8(6, "minutes_value")?);
+                if r.read_bool("hours_flag")? {
+                    hours_value = Some(r.read_u8(5, "hours_value")?);
+                }
+            }
+        }
+        let time_offset_length = r.read_u8(5, "time_offset_length")?;
+        let time_offset_value = r.read_i32(time_offset_length.into(), "time_offset_value")?;
+        Ok(ClockTimestamp {
+            units_field_based_flag,
+            counting_type,
+            full_timestamp_flag,
+            discontinuity_flag,
+            cnt_dropped_flag,
+            n_frames,
+            seconds_value,
+            minutes_value,
+            hours_value,
+            time_offset_length,
+            time_offset_value,
+        })
+    }
+
+    #[cfg(feature = "write")]
+    fn write(&self, w: &mut BitWriter) {
+        w.write_bool(self.units_field_based_flag);
+        w.write_u8(5, self.counting_type);
+        w.write_bool(self.full_timestamp_flag);
+        w.write_bool(self.discontinuity_flag);
+        w.write_bool(self.cnt_dropped_flag);
+        w.write_u16(9, self.n_frames);
+        if self.full_timestamp_flag {
+            w.write_u8(6, self.seconds_value.unwrap_or(0));
+            w.write_u8(6, self.minutes_value.unwrap_or(0));
+            w.write_u8(5, self.hours_value.unwrap_or(0));
+        } else {
+            w.write_bool(self.seconds_value.is_some());
+            if let Some(seconds) = self.seconds_value {
+                w.write_u8(6, seconds);
+                w.write_bool(self.minutes_value.is_some());
+                if let Some(minutes) = self.minutes_value {
+                    w.write_u8(6, minutes);
+                    w.write_bool(self.hours_value.is_some());
+                    if let Some(hours) = self.hours_value {
+                        w.write_u8(5, hours);
+                    }
+                }
+            }
+        }
+        w.write_u8(5, self.time_offset_length);
+        w.write_i32(self.time_offset_length.into(), self.time_offset_value);
+    }
+}
+
+/// The time codes of a picture: one per field or frame, up to three, each of which may be
+/// absent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct TimeCode {
+    pub clock_timestamps: Vec<Option<ClockTimestamp>>,
+}
+impl TimeCode {
+    pub fn read(msg: &SeiMessage<'_>) -> Result<Self, TimeCodeError> {
+        if msg.payload_type != HeaderType::TimeCode {
+            return Err(TimeCodeError::WrongPayloadType(msg.payload_type));
+        }
+        let mut r = BitReader::new(msg.payload);
+        let num_clock_ts = r.read_u8(2, "num_clock_ts")?;
+        if num_clock_ts == 0 {
+            return Err(TimeCodeError::NoClockTimestamps);
+        }
+        let mut clock_timestamps = Vec::with_capacity(num_clock_ts.into());
+        for _ in 0..num_clock_ts {
+            clock_timestamps.push(if r.read_bool("clock_timestamp_flag")? {
+                Some(ClockTimestamp::read(&mut r)?)
+            } else {
+                None
+            });
+        }
+        r.finish_sei_payload()?;
+        Ok(TimeCode { clock_timestamps })
+    }
+
+    /// The first clock timestamp present.
+    pub fn first(&self) -> Option<&ClockTimestamp> {
+        self.clock_timestamps.iter().flatten().next()
+    }
+
+    /// Returns the `sei_payload()` bytes of this message.
+    #[cfg(feature = "write")]
+    pub fn to_payload(&self) -> Vec<u8> {
+        let mut w = BitWriter::new();
+        w.write_u8(2, self.clock_timestamps.len() as u8);
+        for ts in &self.clock_timestamps {
+            w.write_bool(ts.is_some());
+            if let Some(ts) = ts {
+                ts.write(&mut w);
+            }
+        }
+        if !w.byte_aligned() {
+            // payload_bit_equal_to_one and payload_bit_equal_to_zero
+            w.write_byte_alignment();
+        }
+        w.into_bytes()
+    }
+
+    /// Wraps a payload from [`Self::to_payload`] as an SEI message.
+    #[cfg(feature = "write")]
+    pub fn to_message<'a>(&self, payload: &'a [u8]) -> SeiMessage<'a> {
+        SeiMessage {
+            payload_type: HeaderType::TimeCode,
+            payload,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn read() {
+        // One full timestamp, 01:02:03:04 with drop-frame counting.
+        let msg = SeiMessage {
+            payload_type: HeaderType::TimeCode,
+            payload: &[0x62, 0x40, 0x20, 0x61, 0x04, 0x10],
+        };
+        let tc = TimeCode::read(&msg).unwrap();
+        assert_eq!(tc.clock_timestamps.len(), 1);
+        let ts = tc.first().unwrap();
+        assert_eq!(ts.counting_type, COUNTING_TYPE_DROP_FRAME);
+        assert!(ts.full_timestamp_flag);
+        assert_eq!(ts.n_frames, 4);
+        assert_eq!(
+            (ts.hours_value, ts.minutes_value, ts.seconds_value),
+            (Some(1), Some(2), Some(3))
+        );
+        #[cfg(feature = "write")]
+        assert_eq!(tc.to_payload(), msg.payload);
+    }
+}
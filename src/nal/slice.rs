@@ -0,0 +1,1160 @@
+//! Parser for the slice segment header, as specified in clause 7.3.6.
+//!
+//! Only the header is parsed; the slice segment data which follows it is left unread.
+
+use crate::nal::pps::{ParamSetIdError, PicParamSetId, PicParameterSet};
+use crate::nal::sps::{
+    LongTermRefPicSps, SeqParamSetId, SeqParameterSet, ShortTermRefPicSet, SpsError,
+    VideoParamSetId,
+};
+use crate::nal::vps::VideoParameterSet;
+use crate::nal::NalHeader;
+use crate::rbsp::{read_structure, BitRead, BitReaderError};
+use crate::Context;
+
+#[derive(Debug)]
+pub enum SliceHeaderError {
+    RbspError(BitReaderError),
+    InvalidSliceType(u32),
+    BadPicParamSetId(ParamSetIdError),
+    UndefinedPicParamSetId(PicParamSetId),
+    UndefinedSeqParamSetId(SeqParamSetId),
+    /// The referenced SPS has values that don't allow the slice header to be interpreted.
+    BadSeqParamSet(SpsError),
+    /// The `slice_segment_address` was not less than `PicSizeInCtbsY`.
+    InvalidSliceSegmentAddress(u32),
+    InvalidNumRefIdx(&'static str, u32),
+    /// The `st_ref_pic_set()` signalled in the slice header was invalid.
+    InvalidShortTermRefPicSet(SpsError),
+    /// A field in the bitstream had a value too large for a subsequent calculation
+    FieldValueTooLarge {
+        name: &'static str,
+        value: u32,
+    },
+    UndefinedVideoParamSetId(VideoParamSetId),
+}
+impl SliceHeaderError {
+    /// See [`crate::error_code`].
+    pub fn code(&self) -> u32 {
+        crate::error_code::SLICE_HEADER
+            + match self {
+                SliceHeaderError::RbspError(..) => 1,
+                SliceHeaderError::InvalidSliceType(..) => 2,
+                SliceHeaderError::BadPicParamSetId(..) => 3,
+                SliceHeaderError::UndefinedPicParamSetId(..) => 4,
+                SliceHeaderError::UndefinedSeqParamSetId(..) => 5,
+                SliceHeaderError::BadSeqParamSet(..) => 6,
+                SliceHeaderError::InvalidSliceSegmentAddress(..) => 7,
+                SliceHeaderError::InvalidNumRefIdx(..) => 8,
+                SliceHeaderError::InvalidShortTermRefPicSet(..) => 9,
+                SliceHeaderError::FieldValueTooLarge { .. } => 10,
+                SliceHeaderError::UndefinedVideoParamSetId(..) => 11,
+            }
+    }
+}
+impl From<BitReaderError> for SliceHeaderError {
+    fn from(e: BitReaderError) -> Self {
+        SliceHeaderError::RbspError(e)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum SliceType {
+    B,
+    P,
+    I,
+}
+impl SliceType {
+    pub fn from_id(id: u32) -> Result<SliceType, SliceHeaderError> {
+        match id {
+            0 => Ok(SliceType::B),
+            1 => Ok(SliceType::P),
+            2 => Ok(SliceType::I),
+            _ => Err(SliceHeaderError::InvalidSliceType(id)),
+        }
+    }
+
+    pub fn id(self) -> u32 {
+        match self {
+            SliceType::B => 0,
+            SliceType::P => 1,
+            SliceType::I => 2,
+        }
+    }
+}
+
+/// The short-term reference picture set used by a slice.
+// Not boxed, so parsing a slice header doesn't allocate.
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum SliceShortTermRefPicSet {
+    /// One of the sets in the SPS, selected by `short_term_ref_pic_set_idx`.
+    Sps { short_term_ref_pic_set_idx: u32 },
+    /// A set signalled in the slice header itself.
+    Explicit(ShortTermRefPicSet),
+}
+impl SliceShortTermRefPicSet {
+    /// Returns the selected set, given the SPS the slice header was parsed against.
+    pub fn get<'a>(&'a self, sps: &'a SeqParameterSet) -> Option<&'a ShortTermRefPicSet> {
+        match self {
+            SliceShortTermRefPicSet::Sps {
+                short_term_ref_pic_set_idx,
+            } => sps
+                .st_ref_pic_sets
+                .get(*short_term_ref_pic_set_idx as usize),
+            SliceShortTermRefPicSet::Explicit(set) => Some(set),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct LongTermRefPic {
+    /// `lt_idx_sps[ i ]`, present for entries which refer to a candidate in the SPS.
+    pub lt_idx_sps: Option<u32>,
+    /// `PocLsbLt[ i ]`, taken from the SPS candidate when `lt_idx_sps` is present.
+    pub poc_lsb_lt: u32,
+    /// `UsedByCurrPicLt[ i ]`, taken from the SPS candidate when `lt_idx_sps` is present.
+    pub used_by_curr_pic_lt: bool,
+    /// `delta_poc_msb_cycle_lt[ i ]`, present iff `delta_poc_msb_present_flag[ i ]`.
+    pub delta_poc_msb_cycle_lt: Option<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct LongTermRefPics {
+    pub num_long_term_sps: u32,
+    /// The `num_long_term_sps` entries selected from the SPS followed by the
+    /// `num_long_term_pics` entries signalled explicitly.
+    pub pics: Vec<LongTermRefPic>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct RefPicListsModification {
+    /// `list_entry_l0`, present iff `ref_pic_list_modification_flag_l0`.
+    pub list_entry_l0: Option<Vec<u32>>,
+    /// `list_entry_l1`, present iff `ref_pic_list_modification_flag_l1`.
+    pub list_entry_l1: Option<Vec<u32>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct WeightOffset {
+    pub delta_weight: i32,
+    pub offset: i32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct PredWeight {
+    /// `delta_luma_weight` and `luma_offset`, present iff `luma_weight_flag`.
+    pub luma: Option<WeightOffset>,
+    /// `delta_chroma_weight` and `delta_chroma_offset` for Cb and Cr, present iff
+    /// `chroma_weight_flag`.
+    pub chroma: Option<[WeightOffset; 2]>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct PredWeightTable {
+    pub luma_log2_weight_denom: u32,
+    /// Zero when `ChromaArrayType` is 0.
+    pub delta_chroma_log2_weight_denom: i32,
+    pub l0: Vec<PredWeight>,
+    /// Empty for P slices.
+    pub l1: Vec<PredWeight>,
+}
+
+/// Slice header fields only present in P and B slices.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct InterSliceHeader {
+    pub num_ref_idx_active_override_flag: bool,
+    /// Inferred from the PPS unless `num_ref_idx_active_override_flag`.
+    pub num_ref_idx_l0_active_minus1: u32,
+    /// Inferred from the PPS unless `num_ref_idx_active_override_flag`. Unused in P slices.
+    pub num_ref_idx_l1_active_minus1: u32,
+    pub ref_pic_lists_modification: Option<RefPicListsModification>,
+    pub mvd_l1_zero_flag: bool,
+    pub cabac_init_flag: bool,
+    /// Inferred to be `true` when absent.
+    pub collocated_from_l0_flag: bool,
+    pub collocated_ref_idx: u32,
+    pub pred_weight_table: Option<PredWeightTable>,
+    pub five_minus_max_num_merge_cand: u32,
+    /// Inferred from `motion_vector_resolution_control_idc` unless it is 2.
+    pub use_integer_mv_flag: bool,
+}
+
+/// The slice header fields of an independent slice segment, which dependent slice segments
+/// of the same slice share.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct SliceHeader {
+    /// The `num_extra_slice_header_bits` `slice_reserved_flag` bits, with the first flag in
+    /// the most significant position.
+    pub slice_reserved_flags: u8,
+    pub slice_type: SliceType,
+    /// Inferred to be `true` when `output_flag_present_flag` is unset.
+    pub pic_output_flag: bool,
+    /// Present iff `separate_colour_plane_flag`.
+    pub colour_plane_id: Option<u8>,
+    /// Zero for IDR pictures.
+    pub slice_pic_order_cnt_lsb: u32,
+    /// Absent for IDR pictures.
+    pub short_term_ref_pic_set: Option<SliceShortTermRefPicSet>,
+    /// Present iff not IDR and `long_term_ref_pics_present_flag`.
+    pub long_term_ref_pics: Option<LongTermRefPics>,
+    pub slice_temporal_mvp_enabled_flag: bool,
+    pub slice_sao_luma_flag: bool,
+    pub slice_sao_chroma_flag: bool,
+    /// Present iff `slice_type` is P or B.
+    pub inter: Option<InterSliceHeader>,
+    pub slice_qp_delta: i32,
+    pub slice_cb_qp_offset: i32,
+    pub slice_cr_qp_offset: i32,
+    pub slice_act_y_qp_offset: i32,
+    pub slice_act_cb_qp_offset: i32,
+    pub slice_act_cr_qp_offset: i32,
+    pub cu_chroma_qp_offset_enabled_flag: bool,
+    pub deblocking_filter_override_flag: bool,
+    /// Inferred from the PPS unless `deblocking_filter_override_flag`.
+    pub slice_deblocking_filter_disabled_flag: bool,
+    /// Inferred from the PPS unless `deblocking_filter_override_flag`.
+    pub slice_beta_offset_div2: i32,
+    /// Inferred from the PPS unless `deblocking_filter_override_flag`.
+    pub slice_tc_offset_div2: i32,
+    /// Inferred from `pps_loop_filter_across_slices_enabled_flag` when absent.
+    pub slice_loop_filter_across_slices_enabled_flag: bool,
+}
+impl SliceHeader {
+    /// `NumPicTotalCurr` (7-55), the number of pictures usable for reference by the current
+    /// picture.
+    pub fn num_pic_total_curr(&self, sps: &SeqParameterSet, pps: &PicParameterSet) -> u32 {
+        let st = self
+            .short_term_ref_pic_set
+            .as_ref()
+            .and_then(|s| s.get(sps))
+            .map_or(0, ShortTermRefPicSet::num_used_by_curr_pic);
+        let lt = self.long_term_ref_pics.as_ref().map_or(0, |lt| {
+            lt.pics.iter().filter(|p| p.used_by_curr_pic_lt).count()
+        });
+        let curr = pps
+            .pps_scc_extension()
+            .is_some_and(|e| e.pps_curr_pic_ref_enabled_flag);
+        (st + lt + usize::from(curr)) as u32
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct SliceSegmentHeader {
+    pub first_slice_segment_in_pic_flag: bool,
+    /// Only present in IRAP pictures, otherwise `false`.
+    pub no_output_of_prior_pics_flag: bool,
+    pub slice_pic_parameter_set_id: PicParamSetId,
+    pub dependent_slice_segment_flag: bool,
+    pub slice_segment_address: u32,
+    /// Absent for dependent slice segments, which use the values from the preceding
+    /// independent slice segment.
+    pub slice_header: Option<SliceHeader>,
+    /// `entry_point_offset_minus1`, present iff tiles or entropy coding sync are enabled.
+    pub entry_point_offset_minus1: Option<Vec<u32>>,
+    /// `slice_segment_header_extension_data_byte`, present iff
+    /// `slice_segment_header_extension_present_flag`. Copied, as the bytes needn't be byte
+    /// aligned in the NAL unit.
+    pub slice_segment_header_extension_data: Option<Vec<u8>>,
+}
+impl SliceSegmentHeader {
+    /// Reads a slice segment header, returning it along with the SPS and PPS it refers to.
+    ///
+    /// `r` is left positioned at the start of the slice segment data.
+    pub fn from_bits<'a, R: BitRead>(
+        ctx: &'a Context,
+        r: &mut R,
+        header: NalHeader,
+    ) -> Result<(SliceSegmentHeader, &'a SeqParameterSet, &'a PicParameterSet), SliceHeaderError>
+    {
+        let nal_unit_type = header.nal_unit_type();
+        let first_slice_segment_in_pic_flag = r.read_bool("first_slice_segment_in_pic_flag")?;
+        let no_output_of_prior_pics_flag = if nal_unit_type.is_irap() {
+            r.read_bool("no_output_of_prior_pics_flag")?
+        } else {
+            false
+        };
+        let slice_pic_parameter_set_id =
+            PicParamSetId::from_u32(r.read_ue("slice_pic_parameter_set_id")?)
+                .map_err(SliceHeaderError::BadPicParamSetId)?;
+        let (pps, sps) = pps_and_sps(ctx, slice_pic_parameter_set_id)?;
+        let derived = sps.derived().map_err(SliceHeaderError::BadSeqParamSet)?;
+
+        let mut dependent_slice_segment_flag = false;
+        let mut slice_segment_address = 0;
+        if !first_slice_segment_in_pic_flag {
+            if pps.dependent_slice_segments_enabled_flag {
+                dependent_slice_segment_flag = r.read_bool("dependent_slice_segment_flag")?;
+            }
+            slice_segment_address = read_bits(
+                r,
+                ceil_log2(derived.pic_size_in_ctbs_y),
+                "slice_segment_address",
+            )?;
+            if slice_segment_address >= derived.pic_size_in_ctbs_y {
+                return Err(SliceHeaderError::InvalidSliceSegmentAddress(
+                    slice_segment_address,
+                ));
+            }
+        }
+        let slice_header = if dependent_slice_segment_flag {
+            None
+        } else {
+            Some(SliceHeader::read(r, header, sps, pps)?)
+        };
+
+        let entry_point_offset_minus1 = if pps.tiles_enabled_flag()
+            || pps.entropy_coding_sync_enabled_flag
+        {
+            let num_entry_point_offsets = r.read_ue("num_entry_point_offsets")?;
+            if num_entry_point_offsets >= derived.pic_size_in_ctbs_y {
+                return Err(SliceHeaderError::FieldValueTooLarge {
+                    name: "num_entry_point_offsets",
+                    value: num_entry_point_offsets,
+                });
+            }
+            // Grown as the offsets are read, as the count is only bounded by the picture size.
+            let mut offsets = vec![];
+            if num_entry_point_offsets > 0 {
+                let offset_len_minus1 = r.read_ue("offset_len_minus1")?;
+                if offset_len_minus1 > 31 {
+                    return Err(SliceHeaderError::FieldValueTooLarge {
+                        name: "offset_len_minus1",
+                        value: offset_len_minus1,
+                    });
+                }
+                for _ in 0..num_entry_point_offsets {
+                    offsets.push(r.read_u32(offset_len_minus1 + 1, "entry_point_offset_minus1")?);
+                }
+            }
+            Some(offsets)
+        } else {
+            None
+        };
+
+        let slice_segment_header_extension_data = if pps.slice_segment_header_extension_present_flag
+        {
+            let len = r.read_ue("slice_segment_header_extension_length")?;
+            if len > 256 {
+                return Err(SliceHeaderError::FieldValueTooLarge {
+                    name: "slice_segment_header_extension_length",
+                    value: len,
+                });
+            }
+            let mut data = Vec::with_capacity(len as usize);
+            for _ in 0..len {
+                data.push(r.read_u8(8, "slice_segment_header_extension_data_byte")?);
+            }
+            Some(data)
+        } else {
+            None
+        };
+        r.read_byte_alignment()?;
+
+        let hdr = SliceSegmentHeader {
+            first_slice_segment_in_pic_flag,
+            no_output_of_prior_pics_flag,
+            slice_pic_parameter_set_id,
+            dependent_slice_segment_flag,
+            slice_segment_address,
+            slice_header,
+            entry_point_offset_minus1,
+            slice_segment_header_extension_data,
+        };
+        Ok((hdr, sps, pps))
+    }
+
+    /// Looks up the PPS this slice segment refers to, and the SPS and VPS that PPS refers to.
+    ///
+    /// ```
+    /// # use hevc_reader::Context;
+    /// # use hevc_reader::nal::slice::{SliceHeaderError, SliceSegmentHeader};
+    /// # use hevc_reader::nal::NalHeader;
+    /// # use hevc_reader::rbsp::BitReader;
+    /// # let mut ctx = Context::new();
+    /// # ctx.put_param_set_nal(&[
+    /// #     0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03, 0x00,
+    /// #     0x00, 0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46,
+    /// #     0xd1, 0x2e, 0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10,
+    /// #     0x00, 0x00, 0x03, 0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00,
+    /// #     0x0b, 0xb8, 0x48,
+    /// # ]);
+    /// # ctx.put_param_set_nal(&[0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40]);
+    /// // `ctx` has an SPS and PPS, but not the VPS.
+    /// let nal = [0x26, 0x01, 0xac, 0x9c];
+    /// let header = NalHeader::new(nal[0], Some(nal[1])).unwrap();
+    /// let (slice, _, _) =
+    ///     SliceSegmentHeader::from_bits(&ctx, &mut BitReader::from_nal(&nal), header).unwrap();
+    /// assert!(matches!(
+    ///     slice.active_param_sets(&ctx),
+    ///     Err(SliceHeaderError::UndefinedVideoParamSetId(_))
+    /// ));
+    /// ```
+    pub fn active_param_sets<'a>(
+        &self,
+        ctx: &'a Context,
+    ) -> Result<ActiveParamSets<'a>, SliceHeaderError> {
+        let (pps, sps) = pps_and_sps(ctx, self.slice_pic_parameter_set_id)?;
+        let vps = ctx.get(sps.sps_video_parameter_set_id).ok_or(
+            SliceHeaderError::UndefinedVideoParamSetId(sps.sps_video_parameter_set_id),
+        )?;
+        Ok(ActiveParamSets { vps, sps, pps })
+    }
+}
+
+/// The parameter sets active for a slice segment, see
+/// [`SliceSegmentHeader::active_param_sets`].
+#[derive(Debug, Clone, Copy)]
+pub struct ActiveParamSets<'a> {
+    pub vps: &'a VideoParameterSet,
+    pub sps: &'a SeqParameterSet,
+    pub pps: &'a PicParameterSet,
+}
+
+fn pps_and_sps(
+    ctx: &Context,
+    id: PicParamSetId,
+) -> Result<(&PicParameterSet, &SeqParameterSet), SliceHeaderError> {
+    let pps = ctx
+        .get(id)
+        .ok_or(SliceHeaderError::UndefinedPicParamSetId(id))?;
+    let sps = ctx
+        .get(pps.seq_parameter_set_id)
+        .ok_or(SliceHeaderError::UndefinedSeqParamSetId(
+            pps.seq_parameter_set_id,
+        ))?;
+    Ok((pps, sps))
+}
+
+impl SliceHeader {
+    fn read<R: BitRead>(
+        r: &mut R,
+        header: NalHeader,
+        sps: &SeqParameterSet,
+        pps: &PicParameterSet,
+    ) -> Result<SliceHeader, SliceHeaderError> {
+        let nal_unit_type = header.nal_unit_type();
+        let chroma_array_type_nonzero = sps.chroma_array_type() != 0;
+
+        let slice_reserved_flags = r.read_u8(
+            u32::from(pps.num_extra_slice_header_bits),
+            "slice_reserved_flag",
+        )?;
+        let slice_type = SliceType::from_id(r.read_ue("slice_type")?)?;
+        let pic_output_flag = if pps.output_flag_present_flag {
+            r.read_bool("pic_output_flag")?
+        } else {
+            true
+        };
+        let colour_plane_id = if sps.chroma_info.separate_colour_plane_flag {
+            Some(r.read_u8(2, "colour_plane_id")?)
+        } else {
+            None
+        };
+
+        let mut slice_pic_order_cnt_lsb = 0;
+        let mut short_term_ref_pic_set = None;
+        let mut long_term_ref_pics = None;
+        let mut slice_temporal_mvp_enabled_flag = false;
+        if !nal_unit_type.is_idr() {
+            slice_pic_order_cnt_lsb =
+                r.read_u32(sps.log2_max_pic_order_cnt_lsb(), "slice_pic_order_cnt_lsb")?;
+            let num_short_term_ref_pic_sets = sps.st_ref_pic_sets.len() as u32;
+            short_term_ref_pic_set = Some(if !r.read_bool("short_term_ref_pic_set_sps_flag")? {
+                SliceShortTermRefPicSet::Explicit(
+                    read_structure(r, "st_ref_pic_set", |r| {
+                        ShortTermRefPicSet::read(
+                            r,
+                            num_short_term_ref_pic_sets,
+                            num_short_term_ref_pic_sets,
+                            &sps.st_ref_pic_sets,
+                        )
+                    })
+                    .map_err(SliceHeaderError::InvalidShortTermRefPicSet)?,
+                )
+            } else {
+                let short_term_ref_pic_set_idx = read_bits(
+                    r,
+                    ceil_log2(num_short_term_ref_pic_sets),
+                    "short_term_ref_pic_set_idx",
+                )?;
+                if short_term_ref_pic_set_idx >= num_short_term_ref_pic_sets {
+                    return Err(SliceHeaderError::FieldValueTooLarge {
+                        name: "short_term_ref_pic_set_idx",
+                        value: short_term_ref_pic_set_idx,
+                    });
+                }
+                SliceShortTermRefPicSet::Sps {
+                    short_term_ref_pic_set_idx,
+                }
+            });
+            if let Some(lt_sps) = &sps.long_term_ref_pics_sps {
+                long_term_ref_pics = Some(LongTermRefPics::read(r, sps, lt_sps)?);
+            }
+            if sps.sps_termporal_mvp_enabled {
+                slice_temporal_mvp_enabled_flag = r.read_bool("slice_temporal_mvp_enabled_flag")?;
+            }
+        }
+
+        let mut slice_sao_luma_flag = false;
+        let mut slice_sao_chroma_flag = false;
+        if sps.sample_adaptive_offset_enabled {
+            slice_sao_luma_flag = r.read_bool("slice_sao_luma_flag")?;
+            if chroma_array_type_nonzero {
+                slice_sao_chroma_flag = r.read_bool("slice_sao_chroma_flag")?;
+            }
+        }
+
+        let mut slice_header = SliceHeader {
+            slice_reserved_flags,
+            slice_type,
+            pic_output_flag,
+            colour_plane_id,
+            slice_pic_order_cnt_lsb,
+            short_term_ref_pic_set,
+            long_term_ref_pics,
+            slice_temporal_mvp_enabled_flag,
+            slice_sao_luma_flag,
+            slice_sao_chroma_flag,
+            inter: None,
+            slice_qp_delta: 0,
+            slice_cb_qp_offset: 0,
+            slice_cr_qp_offset: 0,
+            slice_act_y_qp_offset: 0,
+            slice_act_cb_qp_offset: 0,
+            slice_act_cr_qp_offset: 0,
+            cu_chroma_qp_offset_enabled_flag: false,
+            deblocking_filter_override_flag: false,
+            slice_deblocking_filter_disabled_flag: false,
+            slice_beta_offset_div2: 0,
+            slice_tc_offset_div2: 0,
+            slice_loop_filter_across_slices_enabled_flag: false,
+        };
+        if slice_type != SliceType::I {
+            let num_pic_total_curr = slice_header.num_pic_total_curr(sps, pps);
+            slice_header.inter = Some(InterSliceHeader::read(
+                r,
+                sps,
+                pps,
+                slice_type,
+                num_pic_total_curr,
+                slice_temporal_mvp_enabled_flag,
+                chroma_array_type_nonzero,
+            )?);
+        }
+        slice_header.slice_qp_delta = r.read_se("slice_qp_delta")?;
+        if pps.pps_slice_chroma_qp_offsets_present_flag {
+            slice_header.slice_cb_qp_offset = r.read_se("slice_cb_qp_offset")?;
+            slice_header.slice_cr_qp_offset = r.read_se("slice_cr_qp_offset")?;
+        }
+        let pps_slice_act_qp_offsets_present_flag = pps
+            .pps_scc_extension()
+            .and_then(|e| e.residual_adaptive_colour_transform.as_ref())
+            .is_some_and(|act| act.pps_slice_act_qp_offsets_present_flag);
+        if pps_slice_act_qp_offsets_present_flag {
+            slice_header.slice_act_y_qp_offset = r.read_se("slice_act_y_qp_offset")?;
+            slice_header.slice_act_cb_qp_offset = r.read_se("slice_act_cb_qp_offset")?;
+            slice_header.slice_act_cr_qp_offset = r.read_se("slice_act_cr_qp_offset")?;
+        }
+        let chroma_qp_offset_list_enabled_flag = pps
+            .pps_extension
+            .as_ref()
+            .and_then(|e| e.pps_range_extension.as_ref())
+            .is_some_and(|e| e.chroma_qp_offset_list.is_some());
+        if chroma_qp_offset_list_enabled_flag {
+            slice_header.cu_chroma_qp_offset_enabled_flag =
+                r.read_bool("cu_chroma_qp_offset_enabled_flag")?;
+        }
+        if let Some(deblocking) = &pps.deblocking_filter_control {
+            if deblocking.deblocking_filter_override_enabled_flag {
+                slice_header.deblocking_filter_override_flag =
+                    r.read_bool("deblocking_filter_override_flag")?;
+            }
+            if slice_header.deblocking_filter_override_flag {
+                slice_header.slice_deblocking_filter_disabled_flag =
+                    r.read_bool("slice_deblocking_filter_disabled_flag")?;
+                if !slice_header.slice_deblocking_filter_disabled_flag {
+                    slice_header.slice_beta_offset_div2 = r.read_se("slice_beta_offset_div2")?;
+                    slice_header.slice_tc_offset_div2 = r.read_se("slice_tc_offset_div2")?;
+                }
+            } else {
+                slice_header.slice_deblocking_filter_disabled_flag =
+                    deblocking.pps_deblocking_filter_disabled_flag;
+                slice_header.slice_beta_offset_div2 = deblocking.pps_beta_offset_div2;
+                slice_header.slice_tc_offset_div2 = deblocking.pps_tc_offset_div2;
+            }
+        }
+        slice_header.slice_loop_filter_across_slices_enabled_flag =
+            pps.pps_loop_filter_across_slices_enabled_flag;
+        if pps.pps_loop_filter_across_slices_enabled_flag
+            && (slice_header.slice_sao_luma_flag
+                || slice_header.slice_sao_chroma_flag
+                || !slice_header.slice_deblocking_filter_disabled_flag)
+        {
+            slice_header.slice_loop_filter_across_slices_enabled_flag =
+                r.read_bool("slice_loop_filter_across_slices_enabled_flag")?;
+        }
+        Ok(slice_header)
+    }
+}
+
+impl LongTermRefPics {
+    fn read<R: BitRead>(
+        r: &mut R,
+        sps: &SeqParameterSet,
+        lt_sps: &[LongTermRefPicSps],
+    ) -> Result<LongTermRefPics, SliceHeaderError> {
+        let num_long_term_ref_pics_sps = lt_sps.len() as u32;
+        let num_long_term_sps = if num_long_term_ref_pics_sps > 0 {
+            r.read_ue("num_long_term_sps")?
+        } else {
+            0
+        };
+        if num_long_term_sps > num_long_term_ref_pics_sps {
+            return Err(SliceHeaderError::FieldValueTooLarge {
+                name: "num_long_term_sps",
+                value: num_long_term_sps,
+            });
+        }
+        let num_long_term_pics = r.read_ue("num_long_term_pics")?;
+        // Bounded by sps_max_dec_pic_buffering_minus1; use the largest possible DPB size.
+        if num_long_term_pics > 16 - num_long_term_sps.min(16) {
+            return Err(SliceHeaderError::FieldValueTooLarge {
+                name: "num_long_term_pics",
+                value: num_long_term_pics,
+            });
+        }
+        let mut pics = Vec::with_capacity((num_long_term_sps + num_long_term_pics) as usize);
+        for i in 0..num_long_term_sps + num_long_term_pics {
+            let (lt_idx_sps, poc_lsb_lt, used_by_curr_pic_lt) = if i < num_long_term_sps {
+                let lt_idx_sps = read_bits(r, ceil_log2(num_long_term_ref_pics_sps), "lt_idx_sps")?;
+                let candidate = lt_sps.get(lt_idx_sps as usize).ok_or(
+                    SliceHeaderError::FieldValueTooLarge {
+                        name: "lt_idx_sps",
+                        value: lt_idx_sps,
+                    },
+                )?;
+                (
+                    Some(lt_idx_sps),
+                    candidate.lt_ref_pic_poc_lsb_sps,
+                    candidate.used_by_curr_pic_lt_sps_flag,
+                )
+            } else {
+                (
+                    None,
+                    r.read_u32(sps.log2_max_pic_order_cnt_lsb(), "poc_lsb_lt")?,
+                    r.read_bool("used_by_curr_pic_lt_flag")?,
+                )
+            };
+            let delta_poc_msb_cycle_lt = if r.read_bool("delta_poc_msb_present_flag")? {
+                Some(r.read_ue("delta_poc_msb_cycle_lt")?)
+            } else {
+                None
+            };
+            pics.push(LongTermRefPic {
+                lt_idx_sps,
+                poc_lsb_lt,
+                used_by_curr_pic_lt,
+                delta_poc_msb_cycle_lt,
+            });
+        }
+        Ok(LongTermRefPics {
+            num_long_term_sps,
+            pics,
+        })
+    }
+}
+
+impl InterSliceHeader {
+    fn read<R: BitRead>(
+        r: &mut R,
+        sps: &SeqParameterSet,
+        pps: &PicParameterSet,
+        slice_type: SliceType,
+        num_pic_total_curr: u32,
+        slice_temporal_mvp_enabled_flag: bool,
+        chroma_array_type_nonzero: bool,
+    ) -> Result<InterSliceHeader, SliceHeaderError> {
+        let is_b = slice_type == SliceType::B;
+        let num_ref_idx_active_override_flag = r.read_bool("num_ref_idx_active_override_flag")?;
+        let mut num_ref_idx_l0_active_minus1 = pps.num_ref_idx_l0_default_active_minus1;
+        let mut num_ref_idx_l1_active_minus1 = pps.num_ref_idx_l1_default_active_minus1;
+        if num_ref_idx_active_override_flag {
+            num_ref_idx_l0_active_minus1 = read_num_ref_idx(r, "num_ref_idx_l0_active_minus1")?;
+            if is_b {
+                num_ref_idx_l1_active_minus1 = read_num_ref_idx(r, "num_ref_idx_l1_active_minus1")?;
+            }
+        }
+        let ref_pic_lists_modification = if pps.lists_modification_present_flag
+            && num_pic_total_curr > 1
+        {
+            let bits = ceil_log2(num_pic_total_curr);
+            Some(read_structure(r, "ref_pic_lists_modification", |r| {
+                let list_entry_l0 = if r.read_bool("ref_pic_list_modification_flag_l0")? {
+                    Some(read_list_entries(
+                        r,
+                        num_ref_idx_l0_active_minus1,
+                        bits,
+                        "list_entry_l0",
+                    )?)
+                } else {
+                    None
+                };
+                let list_entry_l1 = if is_b && r.read_bool("ref_pic_list_modification_flag_l1")? {
+                    Some(read_list_entries(
+                        r,
+                        num_ref_idx_l1_active_minus1,
+                        bits,
+                        "list_entry_l1",
+                    )?)
+                } else {
+                    None
+                };
+                Ok::<_, SliceHeaderError>(RefPicListsModification {
+                    list_entry_l0,
+                    list_entry_l1,
+                })
+            })?)
+        } else {
+            None
+        };
+        let mvd_l1_zero_flag = is_b && r.read_bool("mvd_l1_zero_flag")?;
+        let cabac_init_flag = pps.cabac_init_present_flag && r.read_bool("cabac_init_flag")?;
+        let mut collocated_from_l0_flag = true;
+        let mut collocated_ref_idx = 0;
+        if slice_temporal_mvp_enabled_flag {
+            if is_b {
+                collocated_from_l0_flag = r.read_bool("collocated_from_l0_flag")?;
+            }
+            let num_ref_idx_active_minus1 = if collocated_from_l0_flag {
+                num_ref_idx_l0_active_minus1
+            } else {
+                num_ref_idx_l1_active_minus1
+            };
+            if num_ref_idx_active_minus1 > 0 {
+                collocated_ref_idx = r.read_ue("collocated_ref_idx")?;
+                if collocated_ref_idx > num_ref_idx_active_minus1 {
+                    return Err(SliceHeaderError::FieldValueTooLarge {
+                        name: "collocated_ref_idx",
+                        value: collocated_ref_idx,
+                    });
+                }
+            }
+        }
+        let pred_weight_table = if (pps.weighted_pred_flag && slice_type == SliceType::P)
+            || (pps.weighted_bipred_flag && is_b)
+        {
+            Some(read_structure(r, "pred_weight_table", |r| {
+                PredWeightTable::read(
+                    r,
+                    is_b,
+                    num_ref_idx_l0_active_minus1,
+                    num_ref_idx_l1_active_minus1,
+                    chroma_array_type_nonzero,
+                )
+            })?)
+        } else {
+            None
+        };
+        let five_minus_max_num_merge_cand = r.read_ue("five_minus_max_num_merge_cand")?;
+        if five_minus_max_num_merge_cand > 4 {
+            return Err(SliceHeaderError::FieldValueTooLarge {
+                name: "five_minus_max_num_merge_cand",
+                value: five_minus_max_num_merge_cand,
+            });
+        }
+        let motion_vector_resolution_control_idc = sps
+            .sps_extension
+            .as_ref()
+            .and_then(|e| e.sps_scc_extension.as_ref())
+            .map_or(0, |e| e.motion_vector_resolution_control_idc);
+        let use_integer_mv_flag = if motion_vector_resolution_control_idc == 2 {
+            r.read_bool("use_integer_mv_flag")?
+        } else {
+            motion_vector_resolution_control_idc != 0
+        };
+        Ok(InterSliceHeader {
+            num_ref_idx_active_override_flag,
+            num_ref_idx_l0_active_minus1,
+            num_ref_idx_l1_active_minus1,
+            ref_pic_lists_modification,
+            mvd_l1_zero_flag,
+            cabac_init_flag,
+            collocated_from_l0_flag,
+            collocated_ref_idx,
+            pred_weight_table,
+            five_minus_max_num_merge_cand,
+            use_integer_mv_flag,
+        })
+    }
+}
+
+impl PredWeightTable {
+    fn read<R: BitRead>(
+        r: &mut R,
+        is_b: bool,
+        num_ref_idx_l0_active_minus1: u32,
+        num_ref_idx_l1_active_minus1: u32,
+        chroma_array_type_nonzero: bool,
+    ) -> Result<PredWeightTable, SliceHeaderError> {
+        let luma_log2_weight_denom = r.read_ue("luma_log2_weight_denom")?;
+        if luma_log2_weight_denom > 7 {
+            return Err(SliceHeaderError::FieldValueTooLarge {
+                name: "luma_log2_weight_denom",
+                value: luma_log2_weight_denom,
+            });
+        }
+        let delta_chroma_log2_weight_denom = if chroma_array_type_nonzero {
+            r.read_se("delta_chroma_log2_weight_denom")?
+        } else {
+            0
+        };
+        let l0 = Self::read_list(r, num_ref_idx_l0_active_minus1, chroma_array_type_nonzero)?;
+        let l1 = if is_b {
+            Self::read_list(r, num_ref_idx_l1_active_minus1, chroma_array_type_nonzero)?
+        } else {
+            Vec::new()
+        };
+        Ok(PredWeightTable {
+            luma_log2_weight_denom,
+            delta_chroma_log2_weight_denom,
+            l0,
+            l1,
+        })
+    }
+
+    // Assumes every reference picture is a different picture from the current one, which holds
+    // unless the current picture is a reference (`pps_curr_pic_ref_enabled_flag`) or the
+    // (unsupported) multi-layer extensions are in use.
+    fn read_list<R: BitRead>(
+        r: &mut R,
+        num_ref_idx_active_minus1: u32,
+        chroma_array_type_nonzero: bool,
+    ) -> Result<Vec<PredWeight>, SliceHeaderError> {
+        let n = num_ref_idx_active_minus1 as usize + 1;
+        let mut luma_weight_flags = [false; 15];
+        let mut chroma_weight_flags = [false; 15];
+        for f in &mut luma_weight_flags[..n] {
+            *f = r.read_bool("luma_weight_flag")?;
+        }
+        if chroma_array_type_nonzero {
+            for f in &mut chroma_weight_flags[..n] {
+                *f = r.read_bool("chroma_weight_flag")?;
+            }
+        }
+        let mut weights = Vec::with_capacity(n);
+        for i in 0..n {
+            let luma = if luma_weight_flags[i] {
+                Some(WeightOffset {
+                    delta_weight: r.read_se("delta_luma_weight")?,
+                    offset: r.read_se("luma_offset")?,
+                })
+            } else {
+                None
+            };
+            let chroma = if chroma_weight_flags[i] {
+                let mut read_one = || -> Result<WeightOffset, SliceHeaderError> {
+                    Ok(WeightOffset {
+                        delta_weight: r.read_se("delta_chroma_weight")?,
+                        offset: r.read_se("delta_chroma_offset")?,
+                    })
+                };
+                Some([read_one()?, read_one()?])
+            } else {
+                None
+            };
+            weights.push(PredWeight { luma, chroma });
+        }
+        Ok(weights)
+    }
+}
+
+/// Whether a stream uses inter prediction, as classified by [`IntraClassifier`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IntraClass {
+    /// A single intra-coded picture, e.g. an image item in a HEIF file.
+    StillPicture,
+    /// Only intra-coded (I) slices.
+    AllIntra,
+    /// P or B slices may be present.
+    Inter,
+}
+
+/// Classifies a stream as a still picture, all-intra or inter-coded, from the profile
+/// constraint flags of its SPS and the slice types observed so far.
+///
+/// ```
+/// use hevc_reader::nal::slice::{IntraClass, IntraClassifier};
+/// let classifier = IntraClassifier::default();
+/// // Nothing is known until an SPS or slice has been observed.
+/// assert_eq!(classifier.classify(), None);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct IntraClassifier {
+    signalled: Option<IntraClass>,
+    pictures: u64,
+    inter_slices: u64,
+}
+impl IntraClassifier {
+    /// Records the constraints signalled in the SPS's general profile.
+    pub fn observe_sps(&mut self, sps: &SeqParameterSet) {
+        let profile = sps.general_layer_profile();
+        // The Main Still Picture profile has no constraint flags of its own.
+        let signalled = if profile.one_picture_only_constraint_flag || profile.profile_idc == 3 {
+            IntraClass::StillPicture
+        } else if profile.intra_constraint_flag {
+            IntraClass::AllIntra
+        } else {
+            IntraClass::Inter
+        };
+        // Keep the least restrictive signalling if several SPSs are in use.
+        self.signalled = Some(match self.signalled {
+            Some(prev) => Self::least_restrictive(prev, signalled),
+            None => signalled,
+        });
+    }
+
+    /// Records the slice type of a slice segment. Dependent slice segments are ignored, as
+    /// they share the slice type of the preceding independent slice segment.
+    pub fn observe_slice(&mut self, header: &SliceSegmentHeader) {
+        if header.first_slice_segment_in_pic_flag {
+            self.pictures += 1;
+        }
+        if let Some(slice_header) = &header.slice_header {
+            if slice_header.slice_type != SliceType::I {
+                self.inter_slices += 1;
+            }
+        }
+    }
+
+    /// The classification implied by the profile constraint flags, if an SPS was observed.
+    pub fn signalled(&self) -> Option<IntraClass> {
+        self.signalled
+    }
+
+    /// The classification implied by the slices observed, if any pictures were observed.
+    pub fn observed(&self) -> Option<IntraClass> {
+        match (self.pictures, self.inter_slices) {
+            (0, 0) => None,
+            (_, 1..) => Some(IntraClass::Inter),
+            (1, 0) => Some(IntraClass::StillPicture),
+            (_, 0) => Some(IntraClass::AllIntra),
+        }
+    }
+
+    /// The number of pictures observed.
+    pub fn pictures(&self) -> u64 {
+        self.pictures
+    }
+
+    /// Combines the signalled and observed classifications.
+    ///
+    /// The signalled constraints are a promise about the whole stream, so take precedence
+    /// over what has been observed so far, unless the observed slices contradict them.
+    pub fn classify(&self) -> Option<IntraClass> {
+        match (self.signalled, self.observed()) {
+            (_, Some(IntraClass::Inter)) => Some(IntraClass::Inter),
+            (Some(IntraClass::StillPicture), _) if self.pictures > 1 => Some(IntraClass::AllIntra),
+            (Some(IntraClass::Inter), observed) => observed.or(Some(IntraClass::Inter)),
+            (Some(signalled), _) => Some(signalled),
+            (None, observed) => observed,
+        }
+    }
+
+    fn least_restrictive(a: IntraClass, b: IntraClass) -> IntraClass {
+        match (a, b) {
+            (IntraClass::Inter, _) | (_, IntraClass::Inter) => IntraClass::Inter,
+            (IntraClass::AllIntra, _) | (_, IntraClass::AllIntra) => IntraClass::AllIntra,
+            _ => IntraClass::StillPicture,
+        }
+    }
+}
+
+fn read_num_ref_idx<R: BitRead>(r: &mut R, name: &'static str) -> Result<u32, SliceHeaderError> {
+    let num = r.read_ue(name)?;
+    if num > 14 {
+        return Err(SliceHeaderError::InvalidNumRefIdx(name, num));
+    }
+    Ok(num)
+}
+
+fn read_list_entries<R: BitRead>(
+    r: &mut R,
+    num_ref_idx_active_minus1: u32,
+    bits: u32,
+    name: &'static str,
+) -> Result<Vec<u32>, SliceHeaderError> {
+    (0..=num_ref_idx_active_minus1)
+        .map(|_| Ok(read_bits(r, bits, name)?))
+        .collect()
+}
+
+/// Reads a `u(v)` field whose length may be zero, in which case the value is zero.
+fn read_bits<R: BitRead>(r: &mut R, bits: u32, name: &'static str) -> Result<u32, BitReaderError> {
+    if bits == 0 {
+        Ok(0)
+    } else {
+        r.read_u32(bits, name)
+    }
+}
+
+/// `Ceil( Log2( n ) )`, with zero for `n <= 1`.
+fn ceil_log2(n: u32) -> u32 {
+    if n <= 1 {
+        0
+    } else {
+        32 - (n - 1).leading_zeros()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rbsp::{decode_nal, BitReader};
+    use hex_literal::hex;
+
+    fn ctx() -> Context {
+        // "Intinor HW encode 720x576p" SPS (736x576, 32x32 CTBs, 5-bit POC LSB, one
+        // short-term RPS) and an x265 PPS (WPP, weighted prediction).
+        let sps = hex!(
+            "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87
+             ee 46 d1 2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0
+             00 49 3e 00 0b b8 48"
+        );
+        let sps = SeqParameterSet::from_bits(BitReader::new(&*decode_nal(&sps).unwrap()))
+            .expect("unexpected test data");
+        let mut ctx = Context::default();
+        ctx.put_seq_param_set(sps);
+        let pps = hex!("44 01 c1 72 b4 62 40");
+        let pps = PicParameterSet::from_bits(&ctx, BitReader::new(&*decode_nal(&pps).unwrap()))
+            .expect("unexpected test data");
+        ctx.put_pic_param_set(pps);
+        ctx
+    }
+
+    fn parse(ctx: &Context, nal: &[u8]) -> SliceSegmentHeader {
+        let header = NalHeader::new(nal[0], Some(nal[1])).unwrap();
+        let mut r = BitReader::new(&nal[2..]);
+        SliceSegmentHeader::from_bits(ctx, &mut r, header)
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn idr_slice() {
+        let ctx = ctx();
+        let hdr = parse(&ctx, &hex!("26 01 ac 9c"));
+        assert!(hdr.first_slice_segment_in_pic_flag);
+        assert!(!hdr.no_output_of_prior_pics_flag);
+        assert_eq!(hdr.slice_pic_parameter_set_id.id(), 0);
+        assert_eq!(hdr.entry_point_offset_minus1, Some(vec![]));
+        let slice = hdr.slice_header.unwrap();
+        assert_eq!(slice.slice_type, SliceType::I);
+        assert_eq!(slice.short_term_ref_pic_set, None);
+        assert_eq!(slice.inter, None);
+        assert_eq!(slice.slice_qp_delta, 2);
+        assert!(slice.slice_loop_filter_across_slices_enabled_flag);
+    }
+
+    #[test]
+    fn p_slice() {
+        let ctx = ctx();
+        let hdr = parse(&ctx, &hex!("02 01 d0 6c 27 80"));
+        let slice = hdr.slice_header.unwrap();
+        assert_eq!(slice.slice_type, SliceType::P);
+        assert_eq!(slice.slice_pic_order_cnt_lsb, 1);
+        assert_eq!(
+            slice.short_term_ref_pic_set,
+            Some(SliceShortTermRefPicSet::Sps {
+                short_term_ref_pic_set_idx: 0
+            })
+        );
+        assert_eq!(
+            slice.num_pic_total_curr(ctx.sps().next().unwrap(), ctx.pps().next().unwrap()),
+            1
+        );
+        let inter = slice.inter.unwrap();
+        assert_eq!(inter.num_ref_idx_l0_active_minus1, 0);
+        let weights = inter.pred_weight_table.unwrap();
+        assert_eq!(weights.l0.len(), 1);
+        assert_eq!(weights.l0[0].luma, None);
+        assert!(weights.l1.is_empty());
+        assert_eq!(inter.five_minus_max_num_merge_cand, 3);
+        assert_eq!(slice.slice_qp_delta, 0);
+    }
+
+    #[test]
+    fn undefined_pps() {
+        let ctx = Context::default();
+        let header = NalHeader::new(0x26, Some(0x01)).unwrap();
+        let mut r = BitReader::new(&hex!("ac 9c")[..]);
+        assert!(matches!(
+            SliceSegmentHeader::from_bits(&ctx, &mut r, header),
+            Err(SliceHeaderError::UndefinedPicParamSetId(_))
+        ));
+    }
+
+    #[test]
+    fn active_param_sets() {
+        let mut ctx = ctx();
+        let hdr = parse(&ctx, &hex!("26 01 ac 9c"));
+        assert!(matches!(
+            hdr.active_param_sets(&ctx),
+            Err(SliceHeaderError::UndefinedVideoParamSetId(_))
+        ));
+        assert!(ctx.put_param_set_nal(&hex!(
+            "40 01 0c 01 ff ff 01 60 00 00 03 00 90 00 00 03 00 00 03 00 5d 95 98 09"
+        )));
+        let active = hdr.active_param_sets(&ctx).unwrap();
+        assert_eq!(active.vps.vps_video_parameter_set_id.id(), 0);
+        assert_eq!(active.pps.seq_parameter_set_id, active.sps.id());
+
+        let mut other = hdr;
+        other.slice_pic_parameter_set_id = 1.try_into().unwrap();
+        assert!(matches!(
+            other.active_param_sets(&ctx),
+            Err(SliceHeaderError::UndefinedPicParamSetId(id)) if id.id() == 1
+        ));
+    }
+
+    #[test]
+    fn intra_classifier() {
+        let ctx = ctx();
+        let sps = ctx.sps().next().unwrap();
+        let idr = parse(&ctx, &hex!("26 01 ac 9c"));
+        let p = parse(&ctx, &hex!("02 01 d0 6c 27 80"));
+
+        let mut c = IntraClassifier::default();
+        c.observe_sps(sps);
+        assert_eq!(c.signalled(), Some(IntraClass::Inter));
+        assert_eq!(c.classify(), Some(IntraClass::Inter));
+        c.observe_slice(&idr);
+        assert_eq!(c.classify(), Some(IntraClass::StillPicture));
+        c.observe_slice(&idr);
+        assert_eq!(c.classify(), Some(IntraClass::AllIntra));
+        c.observe_slice(&p);
+        assert_eq!(c.classify(), Some(IntraClass::Inter));
+        assert_eq!(c.pictures(), 3);
+
+        // A Main Still Picture profile SPS.
+        let mut still = sps.clone();
+        let profile = still.profile_tier_level.general_profile.as_mut().unwrap();
+        profile.profile_idc = 3;
+        let mut c = IntraClassifier::default();
+        c.observe_sps(&still);
+        assert_eq!(c.classify(), Some(IntraClass::StillPicture));
+        c.observe_slice(&idr);
+        assert_eq!(c.classify(), Some(IntraClass::StillPicture));
+    }
+}
@@ -1,7 +1,13 @@
+#[cfg(feature = "write")]
+use crate::rbsp::BitWriter;
 use crate::{
-    nal::pps::{ParamSetId, ParamSetIdError},
-    rbsp::{BitRead, BitReaderError},
+    nal::{
+        pps::{ParamSetId, ParamSetIdError},
+        FieldDiff,
+    },
+    rbsp::{read_structure, BitRead, BitReaderError, RawBits},
 };
+use arrayvec::ArrayVec;
 use std::fmt::Debug;
 
 // TODO: more really specific errors after adding more constraints...
@@ -20,11 +26,31 @@ pub enum SpsError {
         value: u32,
     },
     /// The `cpb_cnt_minus1` field must be between 0 and 31 inclusive.
-    // CpbCountOutOfRange(u32),
+    CpbCountOutOfRange(u32),
 
     /// An unimplemented part of the SPS syntax was encountered
     /// TODO: These errors should be removed before serious release
     Unimplemented(&'static str),
+    /// A field in the bitstream had a value too small for the range the spec allows
+    FieldValueTooSmall {
+        name: &'static str,
+        value: u32,
+    },
+}
+impl SpsError {
+    /// See [`crate::error_code`].
+    pub fn code(&self) -> u32 {
+        crate::error_code::SPS
+            + match self {
+                SpsError::RbspReaderError(..) => 1,
+                SpsError::BadSeqParamSetId(..) => 2,
+                SpsError::BadVideoParamSetId(..) => 3,
+                SpsError::FieldValueTooLarge { .. } => 4,
+                SpsError::CpbCountOutOfRange(..) => 5,
+                SpsError::Unimplemented(..) => 6,
+                SpsError::FieldValueTooSmall { .. } => 7,
+            }
+    }
 }
 
 impl From<BitReaderError> for SpsError {
@@ -33,7 +59,9 @@ impl From<BitReaderError> for SpsError {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Ordered so that `Tier::Main < Tier::High`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum Tier {
     Main,
     High,
@@ -49,6 +77,7 @@ impl Tier {
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum Profile {
     Unknown(u8),
 
@@ -119,7 +148,24 @@ pub enum Profile {
     ThreeDeeMain,
 }
 
+/// How the profile of a layer was signalled, see [`LayerProfile::profile_signal`].
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProfileSignal {
+    /// A profile known to this crate, the "lowest" one if several are indicated.
+    Known(Profile),
+    /// `profile_space` is non-zero, which is reserved for future use. The meaning of
+    /// `profile_idc` is then unspecified, and decoders shall ignore the CVS.
+    ReservedProfileSpace { profile_space: u8, profile_idc: u8 },
+    /// `profile_idc` is a value reserved for future profiles, and no compatibility flag
+    /// indicates a known profile.
+    ReservedProfileIdc(u8),
+    /// `profile_idc` is a defined profile, but the constraint flags don't match any profile
+    /// of it.
+    UnrecognizedCombination { profile_idc: u8 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum Level {
     L1,
     L2,
@@ -162,9 +208,156 @@ impl Level {
             n => Level::Reserved(n),
         }
     }
+
+    /// The `level_idc` value, i.e. 30 times the level number.
+    pub fn level_idc(self) -> u8 {
+        match self {
+            Level::L1 => 30,
+            Level::L2 => 60,
+            Level::L2_1 => 63,
+            Level::L3 => 90,
+            Level::L3_1 => 93,
+            Level::L4 => 120,
+            Level::L4_1 => 123,
+            Level::L5 => 150,
+            Level::L5_1 => 153,
+            Level::L5_2 => 156,
+            Level::L6 => 180,
+            Level::L6_1 => 183,
+            Level::L6_2 => 186,
+            Level::L8_5 => 255,
+            Level::Reserved(n) => n,
+        }
+    }
+
+    /// The defined levels, lowest first.
+    pub const ALL: [Level; 14] = [
+        Level::L1,
+        Level::L2,
+        Level::L2_1,
+        Level::L3,
+        Level::L3_1,
+        Level::L4,
+        Level::L4_1,
+        Level::L5,
+        Level::L5_1,
+        Level::L5_2,
+        Level::L6,
+        Level::L6_1,
+        Level::L6_2,
+        Level::L8_5,
+    ];
+
+    /// The general tier and level limits of Tables A.8 and A.9, or `None` for reserved levels
+    /// and level 8.5, which is unconstrained.
+    pub fn limits(self) -> Option<LevelLimits> {
+        #[rustfmt::skip]
+        let (max_luma_ps, max_cpb, max_slice_segments, max_tile_rows, max_tile_cols, max_luma_sr, max_br) =
+            match self {
+                Level::L1 => (36_864, (350, None), 16, 1, 1, 552_960, (128, None)),
+                Level::L2 => (122_880, (1_500, None), 16, 1, 1, 3_686_400, (1_500, None)),
+                Level::L2_1 => (245_760, (3_000, None), 20, 1, 1, 7_372_800, (3_000, None)),
+                Level::L3 => (552_960, (6_000, None), 30, 2, 2, 16_588_800, (6_000, None)),
+                Level::L3_1 => (983_040, (10_000, None), 40, 3, 3, 33_177_600, (10_000, None)),
+                Level::L4 => (2_228_224, (12_000, Some(30_000)), 75, 5, 5, 66_846_720, (12_000, Some(30_000))),
+                Level::L4_1 => (2_228_224, (20_000, Some(50_000)), 75, 5, 5, 133_693_440, (20_000, Some(50_000))),
+                Level::L5 => (8_912_896, (25_000, Some(100_000)), 200, 11, 10, 267_386_880, (25_000, Some(100_000))),
+                Level::L5_1 => (8_912_896, (40_000, Some(160_000)), 200, 11, 10, 534_773_760, (40_000, Some(160_000))),
+                Level::L5_2 => (8_912_896, (60_000, Some(240_000)), 200, 11, 10, 1_069_547_520, (60_000, Some(240_000))),
+                Level::L6 => (35_651_584, (60_000, Some(240_000)), 600, 22, 20, 1_069_547_520, (60_000, Some(240_000))),
+                Level::L6_1 => (35_651_584, (120_000, Some(480_000)), 600, 22, 20, 2_139_095_040, (120_000, Some(480_000))),
+                Level::L6_2 => (35_651_584, (240_000, Some(800_000)), 600, 22, 20, 4_278_190_080, (240_000, Some(800_000))),
+                Level::L8_5 | Level::Reserved(_) => return None,
+            };
+        Some(LevelLimits {
+            max_luma_ps,
+            max_cpb_main: max_cpb.0,
+            max_cpb_high: max_cpb.1,
+            max_slice_segments_per_picture: max_slice_segments,
+            max_tile_rows,
+            max_tile_cols,
+            max_luma_sr,
+            max_br_main: max_br.0,
+            max_br_high: max_br.1,
+        })
+    }
+
+    /// True if `self` is the same level as `other` or higher.
+    pub fn at_least(self, other: Level) -> bool {
+        self >= other
+    }
+
+    /// True if `self` is the same level as `other` or lower.
+    ///
+    /// ```
+    /// # use hevc_reader::nal::sps::{Level, Tier};
+    /// let accept = |tier: Tier, level: Level| tier == Tier::Main && level.at_most(Level::L4_1);
+    /// assert!(accept(Tier::Main, Level::L4));
+    /// assert!(!accept(Tier::Main, Level::L5));
+    /// assert!(!accept(Tier::High, Level::L4_1));
+    /// ```
+    pub fn at_most(self, other: Level) -> bool {
+        self <= other
+    }
+}
+
+/// The limits a level places on a bitstream, see [`Level::limits`].
+///
+/// The CPB sizes are in units of `CpbVclFactor` bits and the bit rates in units of
+/// `BrVclFactor` bits per second, which are 1000 for the Main and Main 10 profiles (Table A.10);
+/// the NAL HRD allows 1.1 times these. The high tier values are `None` below level 4.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LevelLimits {
+    /// `MaxLumaPs`, the maximum luma picture size in samples.
+    pub max_luma_ps: u32,
+    pub max_cpb_main: u32,
+    pub max_cpb_high: Option<u32>,
+    pub max_slice_segments_per_picture: u32,
+    pub max_tile_rows: u32,
+    pub max_tile_cols: u32,
+    /// `MaxLumaSr`, the maximum luma sample rate in samples per second.
+    pub max_luma_sr: u64,
+    pub max_br_main: u32,
+    pub max_br_high: Option<u32>,
+}
+impl LevelLimits {
+    /// The maximum CPB size for `tier`, if the tier exists at this level.
+    pub fn max_cpb(&self, tier: Tier) -> Option<u32> {
+        match tier {
+            Tier::Main => Some(self.max_cpb_main),
+            Tier::High => self.max_cpb_high,
+        }
+    }
+
+    /// The maximum bit rate for `tier`, if the tier exists at this level.
+    pub fn max_br(&self, tier: Tier) -> Option<u32> {
+        match tier {
+            Tier::Main => Some(self.max_br_main),
+            Tier::High => self.max_br_high,
+        }
+    }
+
+    /// The maximum picture width or height, `Sqrt(MaxLumaPs * 8)`.
+    pub fn max_dimension(&self) -> u32 {
+        (f64::from(self.max_luma_ps) * 8.0).sqrt() as u32
+    }
+}
+
+/// Levels are ordered by `level_idc`, so reserved values fall in between the defined levels
+/// according to the level number they encode.
+impl PartialOrd for Level {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Level {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.level_idc().cmp(&other.level_idc())
+    }
 }
 
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum ChromaFormat {
     Monochrome,
     #[default]
@@ -183,9 +376,21 @@ impl ChromaFormat {
             _ => ChromaFormat::Invalid(chroma_format_idc),
         }
     }
+
+    /// The `chroma_format_idc` value.
+    pub fn id(self) -> u32 {
+        match self {
+            ChromaFormat::Monochrome => 0,
+            ChromaFormat::YUV420 => 1,
+            ChromaFormat::YUV422 => 2,
+            ChromaFormat::YUV444 => 3,
+            ChromaFormat::Invalid(chroma_format_idc) => chroma_format_idc,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ChromaInfo {
     pub chroma_format: ChromaFormat,
     pub separate_colour_plane_flag: bool,
@@ -202,9 +407,21 @@ impl ChromaInfo {
             },
         })
     }
+
+    /// `ChromaArrayType`, which is `chroma_format_idc` unless `separate_colour_plane_flag` is
+    /// set, in which case it's 0: the three colour planes are then coded separately, each as
+    /// a monochrome picture.
+    pub fn chroma_array_type(&self) -> u32 {
+        if self.separate_colour_plane_flag {
+            0
+        } else {
+            self.chroma_format.id()
+        }
+    }
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum AspectRatioInfo {
     #[default]
     Unspecified,
@@ -261,6 +478,43 @@ impl AspectRatioInfo {
         })
     }
 
+    #[cfg(feature = "write")]
+    fn aspect_ratio_idc(&self) -> u8 {
+        match self {
+            AspectRatioInfo::Unspecified => 0,
+            AspectRatioInfo::Ratio1_1 => 1,
+            AspectRatioInfo::Ratio12_11 => 2,
+            AspectRatioInfo::Ratio10_11 => 3,
+            AspectRatioInfo::Ratio16_11 => 4,
+            AspectRatioInfo::Ratio40_33 => 5,
+            AspectRatioInfo::Ratio24_11 => 6,
+            AspectRatioInfo::Ratio20_11 => 7,
+            AspectRatioInfo::Ratio32_11 => 8,
+            AspectRatioInfo::Ratio80_33 => 9,
+            AspectRatioInfo::Ratio18_11 => 10,
+            AspectRatioInfo::Ratio15_11 => 11,
+            AspectRatioInfo::Ratio64_33 => 12,
+            AspectRatioInfo::Ratio160_99 => 13,
+            AspectRatioInfo::Ratio4_3 => 14,
+            AspectRatioInfo::Ratio3_2 => 15,
+            AspectRatioInfo::Ratio2_1 => 16,
+            AspectRatioInfo::Reserved(idc) => *idc,
+            AspectRatioInfo::Extended(..) => 255,
+        }
+    }
+
+    #[cfg(feature = "write")]
+    fn write(info: Option<&AspectRatioInfo>, w: &mut BitWriter) {
+        w.write_bool(info.is_some());
+        if let Some(info) = info {
+            w.write_u8(8, info.aspect_ratio_idc());
+            if let AspectRatioInfo::Extended(width, height) = info {
+                w.write_u16(16, *width);
+                w.write_u16(16, *height);
+            }
+        }
+    }
+
     /// Returns the aspect ratio as `(width, height)`, if specified.
     pub fn get(&self) -> Option<(u16, u16)> {
         match self {
@@ -297,6 +551,7 @@ impl AspectRatioInfo {
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum OverscanAppropriate {
     #[default]
     Unspecified,
@@ -317,9 +572,18 @@ impl OverscanAppropriate {
             OverscanAppropriate::Unspecified
         })
     }
+
+    #[cfg(feature = "write")]
+    fn write(&self, w: &mut BitWriter) {
+        w.write_bool(*self != OverscanAppropriate::Unspecified);
+        if *self != OverscanAppropriate::Unspecified {
+            w.write_bool(*self == OverscanAppropriate::Appropriate);
+        }
+    }
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum VideoFormat {
     #[default]
     Component,
@@ -344,9 +608,23 @@ impl VideoFormat {
             _ => panic!("unsupported video_format value {}", video_format),
         }
     }
+
+    #[cfg(feature = "write")]
+    fn id(&self) -> u8 {
+        match self {
+            VideoFormat::Component => 0,
+            VideoFormat::PAL => 1,
+            VideoFormat::NTSC => 2,
+            VideoFormat::SECAM => 3,
+            VideoFormat::MAC => 4,
+            VideoFormat::Unspecified => 5,
+            VideoFormat::Reserved(video_format) => *video_format,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ColourDescription {
     pub colour_primaries: u8,
     pub transfer_characteristics: u8,
@@ -365,9 +643,20 @@ impl ColourDescription {
             None
         })
     }
+
+    #[cfg(feature = "write")]
+    fn write(desc: Option<&ColourDescription>, w: &mut BitWriter) {
+        w.write_bool(desc.is_some());
+        if let Some(desc) = desc {
+            w.write_u8(8, desc.colour_primaries);
+            w.write_u8(8, desc.transfer_characteristics);
+            w.write_u8(8, desc.matrix_coeffs);
+        }
+    }
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct VideoSignalType {
     pub video_format: VideoFormat,
     pub video_full_range_flag: bool,
@@ -386,9 +675,20 @@ impl VideoSignalType {
             None
         })
     }
+
+    #[cfg(feature = "write")]
+    fn write(signal: Option<&VideoSignalType>, w: &mut BitWriter) {
+        w.write_bool(signal.is_some());
+        if let Some(signal) = signal {
+            w.write_u8(3, signal.video_format.id());
+            w.write_bool(signal.video_full_range_flag);
+            ColourDescription::write(signal.colour_description.as_ref(), w);
+        }
+    }
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ChromaLocInfo {
     pub chroma_sample_loc_type_top_field: u32,
     pub chroma_sample_loc_type_bottom_field: u32,
@@ -406,9 +706,19 @@ impl ChromaLocInfo {
             None
         })
     }
+
+    #[cfg(feature = "write")]
+    fn write(info: Option<&ChromaLocInfo>, w: &mut BitWriter) {
+        w.write_bool(info.is_some());
+        if let Some(info) = info {
+            w.write_ue(info.chroma_sample_loc_type_top_field);
+            w.write_ue(info.chroma_sample_loc_type_bottom_field);
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Window {
     pub win_left_offset: u32,
     pub win_right_offset: u32,
@@ -428,10 +738,22 @@ impl Window {
             None
         })
     }
+
+    #[cfg(feature = "write")]
+    fn write(window: Option<&Self>, w: &mut BitWriter) {
+        w.write_bool(window.is_some());
+        if let Some(window) = window {
+            w.write_ue(window.win_left_offset);
+            w.write_ue(window.win_right_offset);
+            w.write_ue(window.win_top_offset);
+            w.write_ue(window.win_bottom_offset);
+        }
+    }
 }
 
 // TODO: Check if this is generalizable with Vui && Vps
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct TimingInfo {
     pub num_units_in_tick: u32,
     pub time_scale: u32,
@@ -443,7 +765,7 @@ impl TimingInfo {
         r: &mut R,
         hrd_common_inf_present: bool,
         max_sub_layers_minus1: u8,
-    ) -> Result<Option<TimingInfo>, BitReaderError> {
+    ) -> Result<Option<TimingInfo>, SpsError> {
         let timing_info_present_flag = r.read_bool("timing_info_present_flag")?;
         Ok(if timing_info_present_flag {
             Some(TimingInfo {
@@ -461,6 +783,41 @@ impl TimingInfo {
         })
     }
 
+    /// The output duration of each picture of temporal sub-layer `sub_layer`, i.e. when
+    /// decoding with `HighestTid` equal to `sub_layer`.
+    ///
+    /// This is `DpbOutputElementalInterval` from clause E.3.3, and is only known when the HRD
+    /// parameters signal a fixed picture rate for the sub-layer. For field coded video it is the
+    /// duration of a field.
+    pub fn sub_layer_frame_duration(&self, sub_layer: usize) -> Option<FrameDuration> {
+        let sub_layer = self.hrd_parameters.as_ref()?.sub_layers.get(sub_layer)?;
+        if !sub_layer.fixed_pic_rate_within_cvs_flag || self.time_scale == 0 {
+            return None;
+        }
+        Some(FrameDuration {
+            duration: u64::from(self.num_units_in_tick)
+                * (u64::from(sub_layer.elemental_duration_in_tc_minus1) + 1),
+            time_scale: self.time_scale,
+        })
+    }
+
+    #[cfg(feature = "write")]
+    fn write(info: Option<&TimingInfo>, w: &mut BitWriter) {
+        w.write_bool(info.is_some());
+        if let Some(info) = info {
+            w.write_u32(32, info.num_units_in_tick);
+            w.write_u32(32, info.time_scale);
+            w.write_bool(info.num_ticks_poc_diff_one_minus1.is_some());
+            if let Some(num_ticks) = info.num_ticks_poc_diff_one_minus1 {
+                w.write_ue(num_ticks);
+            }
+            w.write_bool(info.hrd_parameters.is_some());
+            if let Some(hrd) = &info.hrd_parameters {
+                hrd.write_body(w, true);
+            }
+        }
+    }
+
     fn read_num_ticks<R: BitRead>(r: &mut R) -> Result<Option<u32>, BitReaderError> {
         let vui_poc_proportional_timing_flag = r.read_bool("vui_poc_proportional_timing_flag")?;
         Ok(if vui_poc_proportional_timing_flag {
@@ -471,7 +828,24 @@ impl TimingInfo {
     }
 }
 
+/// The output duration of a picture, in units of `1 / time_scale` seconds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameDuration {
+    pub duration: u64,
+    pub time_scale: u32,
+}
+impl FrameDuration {
+    pub fn as_secs_f64(&self) -> f64 {
+        self.duration as f64 / self.time_scale as f64
+    }
+
+    pub fn fps(&self) -> f64 {
+        self.time_scale as f64 / self.duration as f64
+    }
+}
+
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct SubPicHrdParams {
     pub tick_divisor_minus2: u8,
     pub du_cpb_removal_delay_increment_length_minus1: u8,
@@ -494,6 +868,7 @@ impl SubPicHrdParams {
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct HrdParametersCommonInfParameters {
     pub sub_pic_hrd_params: Option<SubPicHrdParams>,
     pub bit_rate_scale: u8,
@@ -526,9 +901,29 @@ impl HrdParametersCommonInfParameters {
             dpb_output_delay_length_minus1: r.read_u8(5, "dpb_output_delay_length_minus1")?,
         })
     }
+
+    #[cfg(feature = "write")]
+    fn write(&self, w: &mut BitWriter) {
+        w.write_bool(self.sub_pic_hrd_params.is_some());
+        if let Some(subpic) = &self.sub_pic_hrd_params {
+            w.write_u8(8, subpic.tick_divisor_minus2);
+            w.write_u8(5, subpic.du_cpb_removal_delay_increment_length_minus1);
+            w.write_bool(subpic.sub_pic_cpb_params_in_pic_timing_sei_flag);
+            w.write_u8(5, subpic.dpb_output_delay_du_length_minus1);
+        }
+        w.write_u8(4, self.bit_rate_scale);
+        w.write_u8(4, self.cpb_size_scale);
+        if let Some(subpic) = &self.sub_pic_hrd_params {
+            w.write_u8(4, subpic.cpb_size_du_scale);
+        }
+        w.write_u8(5, self.initial_cpb_removal_delay_length_minus1);
+        w.write_u8(5, self.au_cpb_removal_delay_length_minus1);
+        w.write_u8(5, self.dpb_output_delay_length_minus1);
+    }
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct HrdParametersCommonInf {
     pub nal_hrd_parameters_present_flag: bool,
     pub vcl_hrd_parameters_present_flag: bool,
@@ -548,14 +943,25 @@ impl HrdParametersCommonInf {
             },
         })
     }
+
+    #[cfg(feature = "write")]
+    fn write(&self, w: &mut BitWriter) {
+        w.write_bool(self.nal_hrd_parameters_present_flag);
+        w.write_bool(self.vcl_hrd_parameters_present_flag);
+        if let Some(parameters) = &self.parameters {
+            parameters.write(w);
+        }
+    }
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct SubLayerSubPicHrdParams {
     pub cpb_size_du_value_minus1: u32,
     pub bit_rate_du_value_minus1: u32,
 }
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct SubLayerHrdParameters {
     pub bit_rate_value_minus1: u32,
     pub cpb_size_value_minus1: u32,
@@ -581,19 +987,33 @@ impl SubLayerHrdParameters {
             cbr_flag: r.read_bool("cbr_flag")?,
         })
     }
+
+    #[cfg(feature = "write")]
+    fn write(&self, w: &mut BitWriter) {
+        w.write_ue(self.bit_rate_value_minus1);
+        w.write_ue(self.cpb_size_value_minus1);
+        if let Some(subpic) = &self.sub_pic_hrd_params {
+            w.write_ue(subpic.cpb_size_du_value_minus1);
+            w.write_ue(subpic.bit_rate_du_value_minus1);
+        }
+        w.write_bool(self.cbr_flag);
+    }
 }
 
 // The syntax here is a bit messy, so initial version doesn't
 // split optional fields in subtypes. Make better types if needed.
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct SubLayerHrdParametersContainer {
     pub fixed_pic_rate_general_flag: bool,
-    pub fixed_pic_rate_within_cvs_flag: bool, // valid iff !fixed_pic_rate_general_flag
+    pub fixed_pic_rate_within_cvs_flag: bool, // inferred 1 if fixed_pic_rate_general_flag
     pub elemental_duration_in_tc_minus1: u32, // valid iff fixed_pic_rate_within_cvs_flag
-    pub low_delay_hrd_flag: bool,             // valid iff !fixed_pic_rate_within_cvs_flag
-    pub cpb_cnt_minus1: u32,                  // valid iff !low_delay_hrd_flag
-    pub nal_hrd_parameters: Option<Vec<SubLayerHrdParameters>>,
-    pub vcl_hrd_parameters: Option<Vec<SubLayerHrdParameters>>,
+    pub low_delay_hrd_flag: bool,             // inferred 0 if fixed_pic_rate_within_cvs_flag
+    pub cpb_cnt_minus1: u32,                  // inferred 0 if low_delay_hrd_flag
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzzing::option_array_vec))]
+    pub nal_hrd_parameters: Option<ArrayVec<SubLayerHrdParameters, MAX_CPB_CNT>>,
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzzing::option_array_vec))]
+    pub vcl_hrd_parameters: Option<ArrayVec<SubLayerHrdParameters, MAX_CPB_CNT>>,
 }
 impl SubLayerHrdParametersContainer {
     fn read<R: BitRead>(
@@ -601,7 +1021,7 @@ impl SubLayerHrdParametersContainer {
         nal_hrd_parameters_present: bool,
         vcl_hrd_parameters_present: bool,
         sub_pic_hrd_parameters_present: bool,
-    ) -> Result<Self, BitReaderError> {
+    ) -> Result<Self, SpsError> {
         let fixed_pic_rate_general_flag = r.read_bool("fixed_pic_rate_general_flag")?;
         let fixed_pic_rate_within_cvs_flag = if !fixed_pic_rate_general_flag {
             r.read_bool("fixed_pic_rate_within_cvs_flag")?
@@ -614,28 +1034,33 @@ impl SubLayerHrdParametersContainer {
             } else {
                 (0, r.read_bool("low_delay_hrd_flag")?)
             };
+        // E.3.2: cpb_cnt_minus1 is inferred to be 0 when absent, i.e. a low delay HRD has a
+        // single CPB specification.
         let cpb_cnt_minus1 = if !low_delay_hrd_flag {
             r.read_ue("cpb_cnt_minus1")?
         } else {
             0
         };
-        // TODO: default value for cpb_cnt_minus1? (ie if low_delay_hrd_flag)
-        let nal_hrd_parameters = if nal_hrd_parameters_present {
-            let params: Result<Vec<_>, _> = (0..=cpb_cnt_minus1)
-                .map(|_| SubLayerHrdParameters::read(r, sub_pic_hrd_parameters_present))
-                .collect();
-            Some(params?)
-        } else {
-            None
-        };
-        let vcl_hrd_parameters = if vcl_hrd_parameters_present {
-            let params: Result<Vec<_>, _> = (0..=cpb_cnt_minus1)
-                .map(|_| SubLayerHrdParameters::read(r, sub_pic_hrd_parameters_present))
-                .collect();
-            Some(params?)
-        } else {
-            None
+        if cpb_cnt_minus1 as usize >= MAX_CPB_CNT {
+            return Err(SpsError::CpbCountOutOfRange(cpb_cnt_minus1));
+        }
+        let mut read_params = |present: bool| -> Result<_, SpsError> {
+            if !present {
+                return Ok(None);
+            }
+            read_structure(r, "sub_layer_hrd_parameters", |r| {
+                let mut params = ArrayVec::new();
+                for _ in 0..=cpb_cnt_minus1 {
+                    params.push(SubLayerHrdParameters::read(
+                        r,
+                        sub_pic_hrd_parameters_present,
+                    )?);
+                }
+                Ok(Some(params))
+            })
         };
+        let nal_hrd_parameters = read_params(nal_hrd_parameters_present)?;
+        let vcl_hrd_parameters = read_params(vcl_hrd_parameters_present)?;
 
         Ok(SubLayerHrdParametersContainer {
             fixed_pic_rate_general_flag,
@@ -647,55 +1072,125 @@ impl SubLayerHrdParametersContainer {
             vcl_hrd_parameters,
         })
     }
+
+    #[cfg(feature = "write")]
+    fn write(&self, w: &mut BitWriter) {
+        w.write_bool(self.fixed_pic_rate_general_flag);
+        if !self.fixed_pic_rate_general_flag {
+            w.write_bool(self.fixed_pic_rate_within_cvs_flag);
+        }
+        if self.fixed_pic_rate_within_cvs_flag {
+            w.write_ue(self.elemental_duration_in_tc_minus1);
+        } else {
+            w.write_bool(self.low_delay_hrd_flag);
+        }
+        if !self.low_delay_hrd_flag {
+            w.write_ue(self.cpb_cnt_minus1);
+        }
+        for params in self
+            .nal_hrd_parameters
+            .iter()
+            .chain(&self.vcl_hrd_parameters)
+        {
+            for p in params {
+                p.write(w);
+            }
+        }
+    }
+
+    /// `CpbCnt`, the number of alternative CPB specifications, taking the inferred value of
+    /// `cpb_cnt_minus1` into account when `low_delay_hrd_flag` is set.
+    pub fn cpb_cnt(&self) -> u32 {
+        self.cpb_cnt_minus1 + 1
+    }
 }
 
-// TODO: most or all vecs can be replace with ArrayVec to reduce allocations and indirections
+/// The largest `CpbCnt`, as `cpb_cnt_minus1` is at most 31.
+const MAX_CPB_CNT: usize = 32;
+
+/// The largest number of temporal sub-layers, as `sps_max_sub_layers_minus1` and
+/// `vps_max_sub_layers_minus1` are 3 bit fields.
+pub(crate) const MAX_SUB_LAYERS: usize = 8;
+
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct HrdParameters {
+    /// The common information, either as signalled or as inferred from the preceding
+    /// `hrd_parameters()` when `commonInfPresentFlag` is 0. `None` only if it was neither
+    /// signalled nor inferable, in which case the flags it carries are inferred to be 0.
     pub common: Option<HrdParametersCommonInf>,
-    pub sub_layers: Vec<SubLayerHrdParametersContainer>,
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzzing::array_vec))]
+    pub sub_layers: ArrayVec<SubLayerHrdParametersContainer, MAX_SUB_LAYERS>,
 }
 impl HrdParameters {
     fn read<R: BitRead>(
         r: &mut R,
         common_inf_present_flag: bool,
         max_num_sub_layers_minus1: u8,
-    ) -> Result<Option<Self>, BitReaderError> {
+    ) -> Result<Option<Self>, SpsError> {
         let hrd_parameters_present_flag = r.read_bool("hrd_parameters_present_flag")?;
         Ok(if hrd_parameters_present_flag {
-            let common = if common_inf_present_flag {
-                Some(HrdParametersCommonInf::read(r)?)
-            } else {
-                None
-            };
-            let mut sub_layers = Vec::with_capacity(usize::from(max_num_sub_layers_minus1) + 1);
-            let nal_hrd_params = common
-                .as_ref()
-                .map_or(false, |c| c.nal_hrd_parameters_present_flag);
-            let vcl_hrd_params = common
-                .as_ref()
-                .map_or(false, |c| c.vcl_hrd_parameters_present_flag);
-            let sub_pic_hrd_params = common
-                .as_ref()
-                .and_then(|c| c.parameters.as_ref())
-                .map(|p| p.sub_pic_hrd_params.is_some())
-                .unwrap_or(false);
-            for _ in 0..=max_num_sub_layers_minus1 {
-                sub_layers.push(SubLayerHrdParametersContainer::read(
-                    r,
-                    nal_hrd_params,
-                    vcl_hrd_params,
-                    sub_pic_hrd_params, // TODO: default values?
-                )?);
-            }
-            Some(Self { common, sub_layers })
+            Some(read_structure(r, "hrd_parameters", |r| {
+                Self::read_body(r, common_inf_present_flag, None, max_num_sub_layers_minus1)
+            })?)
         } else {
             None
         })
     }
+
+    /// Reads the `hrd_parameters( commonInfPresentFlag, maxNumSubLayersMinus1 )` syntax
+    /// itself, without the preceding presence flag used in the VUI.
+    ///
+    /// When `common_inf_present_flag` is false, the common information is inferred to be equal
+    /// to `previous_common` (7.4.3.1), which sizes the sub-layer and decoding unit fields.
+    pub(crate) fn read_body<R: BitRead>(
+        r: &mut R,
+        common_inf_present_flag: bool,
+        previous_common: Option<&HrdParametersCommonInf>,
+        max_num_sub_layers_minus1: u8,
+    ) -> Result<Self, SpsError> {
+        let common = if common_inf_present_flag {
+            Some(HrdParametersCommonInf::read(r)?)
+        } else {
+            previous_common.cloned()
+        };
+        let mut sub_layers = ArrayVec::new();
+        let nal_hrd_params = common
+            .as_ref()
+            .is_some_and(|c| c.nal_hrd_parameters_present_flag);
+        let vcl_hrd_params = common
+            .as_ref()
+            .is_some_and(|c| c.vcl_hrd_parameters_present_flag);
+        // sub_pic_hrd_params_present_flag is inferred to be 0 when not present (E.3.2)
+        let sub_pic_hrd_params = common
+            .as_ref()
+            .and_then(|c| c.parameters.as_ref())
+            .is_some_and(|p| p.sub_pic_hrd_params.is_some());
+        for _ in 0..=max_num_sub_layers_minus1 {
+            sub_layers.push(SubLayerHrdParametersContainer::read(
+                r,
+                nal_hrd_params,
+                vcl_hrd_params,
+                sub_pic_hrd_params,
+            )?);
+        }
+        Ok(Self { common, sub_layers })
+    }
+
+    /// Writes the `hrd_parameters()` syntax, the inverse of [`Self::read_body`].
+    #[cfg(feature = "write")]
+    pub(crate) fn write_body(&self, w: &mut BitWriter, common_inf_present_flag: bool) {
+        if common_inf_present_flag {
+            self.common.clone().unwrap_or_default().write(w);
+        }
+        for sub_layer in &self.sub_layers {
+            sub_layer.write(w);
+        }
+    }
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct BitstreamRestrictions {
     pub tiles_fixed_structure_flag: bool,
     pub motion_vectors_over_pic_boundaries_flag: bool,
@@ -725,9 +1220,25 @@ impl BitstreamRestrictions {
             None
         })
     }
+
+    #[cfg(feature = "write")]
+    fn write(restrictions: Option<&BitstreamRestrictions>, w: &mut BitWriter) {
+        w.write_bool(restrictions.is_some());
+        if let Some(r) = restrictions {
+            w.write_bool(r.tiles_fixed_structure_flag);
+            w.write_bool(r.motion_vectors_over_pic_boundaries_flag);
+            w.write_bool(r.restricted_ref_pic_lists_flag);
+            w.write_ue(r.min_spatial_segmentation_idc);
+            w.write_ue(r.max_bytes_per_pic_denom);
+            w.write_ue(r.max_bits_per_mb_denom);
+            w.write_ue(r.log2_max_mv_length_horizontal);
+            w.write_ue(r.log2_max_mv_length_vertical);
+        }
+    }
 }
 
 #[derive(Default, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct LayerProfile {
     pub profile_space: u8,
     pub tier_flag: bool,
@@ -815,21 +1326,17 @@ impl LayerProfile {
                 || profile_compatibility_flag[11]
             {
                 profile.max_14bit_constraint_flag = r.read_bool("max_14bit_constraint_flag")?;
-                let _zero_bits = r.read_u32(32, "reserved_zero_33bits")?;
-                let _zero_bits = r.read_u32(1, "reserved_zero_33bits")?;
+                r.skip_bits(33, "reserved_zero_33bits")?;
             } else {
-                let _zero_bits = r.read_u32(32, "reserved_zero_34bits")?;
-                let _zero_bits = r.read_u32(2, "reserved_zero_34bits")?;
+                r.skip_bits(34, "reserved_zero_34bits")?;
             }
         } else if profile_idc == 2 || profile_compatibility_flag[2] {
             let _zero_bits = r.read_u8(7, "reserved_zero_7bits")?; // TODO: check zeroness
             profile.one_picture_only_constraint_flag =
                 r.read_bool("one_picture_only_constraint_flag")?;
-            let _zero_bits = r.read_u32(32, "reserved_zero_35bits")?;
-            let _zero_bits = r.read_u32(3, "reserved_zero_35bits")?;
+            r.skip_bits(35, "reserved_zero_35bits")?;
         } else {
-            let _zero_bits = r.read_u32(32, "reserved_zero_43bits")?;
-            let _zero_bits = r.read_u32(11, "reserved_zero_43bits")?;
+            r.skip_bits(43, "reserved_zero_43bits")?;
         }
         if profile_idc == 1
             || profile_compatibility_flag[1]
@@ -858,190 +1365,285 @@ impl LayerProfile {
         Tier::from_tier_flag(self.tier_flag)
     }
 
-    /// Return the "lowest" compatible profile
-    // TODO: this returns the "lowest" profile indicated by any profile_compatibility_flag
-    // but in reality a (sub)stream can conform to multiple profiles by setting multiple flags.
+    /// The 48 bits from `progressive_source_flag` through `inbld_flag`, as stored in the
+    /// `hvcC` box's `general_constraint_indicator_flags`. Reserved bits are zero.
+    pub fn constraint_indicator_flags(&self) -> [u8; 6] {
+        let bits = [
+            (0, self.progressive_source_flag),
+            (1, self.interlaced_source_flag),
+            (2, self.non_packed_constraint_flag),
+            (3, self.frame_only_constraint_flag),
+            (4, self.max_12bit_constraint_flag),
+            (5, self.max_10bit_constraint_flag),
+            (6, self.max_8bit_constraint_flag),
+            (7, self.max_422chroma_constraint_flag),
+            (8, self.max_420chroma_constraint_flag),
+            (9, self.max_monochrome_constraint_flag),
+            (10, self.intra_constraint_flag),
+            (11, self.one_picture_only_constraint_flag),
+            (12, self.lower_bit_rate_constraint_flag),
+            (13, self.max_14bit_constraint_flag),
+            (47, self.inbld_flag),
+        ];
+        let mut flags = [0; 6];
+        for (i, set) in bits {
+            if set {
+                flags[i / 8] |= 0x80 >> (i % 8);
+            }
+        }
+        flags
+    }
+
+    /// The `profile_compatibility_flag`s as a 32 bit value, with flag `j` in bit `31 - j`.
+    pub fn compatibility_flags(&self) -> u32 {
+        self.profile_compatibility_flag
+            .iter()
+            .fold(0, |acc, &f| (acc << 1) | u32::from(f))
+    }
+
+    /// Return the "lowest" compatible profile, i.e. the first of [`Self::compatible_profiles`]
+    /// or `Unknown` if there is none.
     pub fn profile(&self) -> Profile {
+        self.compatible_profiles()
+            .into_iter()
+            .next()
+            .unwrap_or(Profile::Unknown(self.profile_idc))
+    }
+
+    /// Classifies the signalled profile, distinguishing values reserved for future use from
+    /// combinations of constraint flags that this crate doesn't recognize.
+    pub fn profile_signal(&self) -> ProfileSignal {
+        if self.profile_space != 0 {
+            return ProfileSignal::ReservedProfileSpace {
+                profile_space: self.profile_space,
+                profile_idc: self.profile_idc,
+            };
+        }
+        if let Some(&profile) = self.compatible_profiles().first() {
+            return ProfileSignal::Known(profile);
+        }
+        match self.profile_idc {
+            1..=11 => ProfileSignal::UnrecognizedCombination {
+                profile_idc: self.profile_idc,
+            },
+            idc => ProfileSignal::ReservedProfileIdc(idc),
+        }
+    }
+
+    /// All profiles the stream conforms to, as indicated by `general_profile_idc` and every
+    /// set `general_profile_compatibility_flag[j]`, in order of increasing `j`.
+    ///
+    /// A stream can conform to several profiles, e.g. Main profile streams typically also
+    /// signal compatibility with Main 10. Flags for which the constraint flags don't match a
+    /// known profile are left out, and the list is empty for a non-zero `profile_space`.
+    pub fn compatible_profiles(&self) -> Vec<Profile> {
+        let mut profiles = vec![];
+        if self.profile_space != 0 {
+            return profiles;
+        }
+        for j in 1..32u8 {
+            if self.profile_idc != j && !self.profile_compatibility_flag[usize::from(j)] {
+                continue;
+            }
+            let profile = self.profile_for_idc(j);
+            if !matches!(profile, Profile::Unknown(_)) && !profiles.contains(&profile) {
+                profiles.push(profile);
+            }
+        }
+        profiles
+    }
+
+    /// The profile indicated by `general_profile_idc` or `general_profile_compatibility_flag`
+    /// being `profile_idc`, given the constraint flags (A.3).
+    fn profile_for_idc(&self, profile_idc: u8) -> Profile {
         use Profile::*;
 
-        if self.profile_idc == 1 || self.profile_compatibility_flag[1] {
-            Main
-        } else if self.profile_idc == 2 || self.profile_compatibility_flag[2] {
-            if self.one_picture_only_constraint_flag {
-                Main10StillPicture
-            } else {
-                Main10
+        match profile_idc {
+            1 => Main,
+            2 => {
+                if self.one_picture_only_constraint_flag {
+                    Main10StillPicture
+                } else {
+                    Main10
+                }
             }
-        } else if self.profile_idc == 3 || self.profile_compatibility_flag[3] {
-            MainStillPicture
-        } else if self.profile_idc == 4 || self.profile_compatibility_flag[4] {
-            match (
-                self.max_12bit_constraint_flag as u8,
-                self.max_10bit_constraint_flag as u8,
-                self.max_8bit_constraint_flag as u8,
-                self.max_422chroma_constraint_flag as u8,
-                self.max_420chroma_constraint_flag as u8,
-                self.max_monochrome_constraint_flag as u8,
-                self.intra_constraint_flag as u8,
-                self.one_picture_only_constraint_flag as u8,
-                self.lower_bit_rate_constraint_flag as u8,
-            ) {
-                (1, 1, 1, 1, 1, 1, 0, 0, 1) => Monochrome,
-                (1, 1, 0, 1, 1, 1, 0, 0, 1) => Monochrome10,
-                (1, 0, 0, 1, 1, 1, 0, 0, 1) => Monochrome12,
-                (0, 0, 0, 1, 1, 1, 0, 0, 1) => Monochrome16,
-                (1, 0, 0, 1, 1, 0, 0, 0, 1) => Main12,
-                (1, 1, 0, 1, 0, 0, 0, 0, 1) => Main422_10,
-                (1, 0, 0, 1, 0, 0, 0, 0, 1) => Main422_12,
-                (1, 1, 1, 0, 0, 0, 0, 0, 1) => Main444,
-                (1, 1, 0, 0, 0, 0, 0, 0, 1) => Main444_10,
-                (1, 0, 0, 0, 0, 0, 0, 0, 1) => Main444_12,
-                (1, 1, 1, 1, 1, 0, 1, 0, _) => MainIntra,
-                (1, 1, 0, 1, 1, 0, 1, 0, _) => Main10Intra,
-                (1, 0, 0, 1, 1, 0, 1, 0, _) => Main12Intra,
-                (1, 1, 0, 1, 0, 0, 1, 0, _) => Main422_10Intra,
-                (1, 0, 0, 1, 0, 0, 1, 0, _) => Main422_12Intra,
-                (1, 1, 1, 0, 0, 0, 1, 0, _) => Main444Intra,
-                (1, 1, 0, 0, 0, 0, 1, 0, _) => Main444_10Intra,
-                (1, 0, 0, 0, 0, 0, 1, 0, _) => Main444_12Intra,
-                (0, 0, 0, 0, 0, 0, 1, 0, _) => Main444_16Intra,
-                (1, 1, 1, 0, 0, 0, 1, 1, _) => Main444StillPicture,
-                (0, 0, 0, 0, 0, 0, 1, 1, _) => Main444_16StillPicture,
-
-                _ => Unknown(self.profile_idc),
+            3 => MainStillPicture,
+            4 => {
+                match (
+                    self.max_12bit_constraint_flag as u8,
+                    self.max_10bit_constraint_flag as u8,
+                    self.max_8bit_constraint_flag as u8,
+                    self.max_422chroma_constraint_flag as u8,
+                    self.max_420chroma_constraint_flag as u8,
+                    self.max_monochrome_constraint_flag as u8,
+                    self.intra_constraint_flag as u8,
+                    self.one_picture_only_constraint_flag as u8,
+                    self.lower_bit_rate_constraint_flag as u8,
+                ) {
+                    (1, 1, 1, 1, 1, 1, 0, 0, 1) => Monochrome,
+                    (1, 1, 0, 1, 1, 1, 0, 0, 1) => Monochrome10,
+                    (1, 0, 0, 1, 1, 1, 0, 0, 1) => Monochrome12,
+                    (0, 0, 0, 1, 1, 1, 0, 0, 1) => Monochrome16,
+                    (1, 0, 0, 1, 1, 0, 0, 0, 1) => Main12,
+                    (1, 1, 0, 1, 0, 0, 0, 0, 1) => Main422_10,
+                    (1, 0, 0, 1, 0, 0, 0, 0, 1) => Main422_12,
+                    (1, 1, 1, 0, 0, 0, 0, 0, 1) => Main444,
+                    (1, 1, 0, 0, 0, 0, 0, 0, 1) => Main444_10,
+                    (1, 0, 0, 0, 0, 0, 0, 0, 1) => Main444_12,
+                    (1, 1, 1, 1, 1, 0, 1, 0, _) => MainIntra,
+                    (1, 1, 0, 1, 1, 0, 1, 0, _) => Main10Intra,
+                    (1, 0, 0, 1, 1, 0, 1, 0, _) => Main12Intra,
+                    (1, 1, 0, 1, 0, 0, 1, 0, _) => Main422_10Intra,
+                    (1, 0, 0, 1, 0, 0, 1, 0, _) => Main422_12Intra,
+                    (1, 1, 1, 0, 0, 0, 1, 0, _) => Main444Intra,
+                    (1, 1, 0, 0, 0, 0, 1, 0, _) => Main444_10Intra,
+                    (1, 0, 0, 0, 0, 0, 1, 0, _) => Main444_12Intra,
+                    (0, 0, 0, 0, 0, 0, 1, 0, _) => Main444_16Intra,
+                    (1, 1, 1, 0, 0, 0, 1, 1, _) => Main444StillPicture,
+                    (0, 0, 0, 0, 0, 0, 1, 1, _) => Main444_16StillPicture,
+
+                    _ => Unknown(self.profile_idc),
+                }
             }
-        } else if self.profile_idc == 5 || self.profile_compatibility_flag[5] {
-            match (
-                self.max_14bit_constraint_flag as u8,
-                self.max_12bit_constraint_flag as u8,
-                self.max_10bit_constraint_flag as u8,
-                self.max_8bit_constraint_flag as u8,
-                self.max_422chroma_constraint_flag as u8,
-                self.max_420chroma_constraint_flag as u8,
-                self.max_monochrome_constraint_flag as u8,
-                self.intra_constraint_flag as u8,
-                self.one_picture_only_constraint_flag as u8,
-                self.lower_bit_rate_constraint_flag as u8,
-            ) {
-                (1, 1, 1, 1, 0, 0, 0, 0, 0, 1) => HighThroughput444,
-                (1, 1, 1, 0, 0, 0, 0, 0, 0, 1) => HighThroughput444_10,
-                (1, 0, 0, 0, 0, 0, 0, 0, 0, 1) => HighThroughput444_14,
-                (0, 0, 0, 0, 0, 0, 0, 1, 0, _) => HighThroughput444_16Intra,
+            5 => {
+                match (
+                    self.max_14bit_constraint_flag as u8,
+                    self.max_12bit_constraint_flag as u8,
+                    self.max_10bit_constraint_flag as u8,
+                    self.max_8bit_constraint_flag as u8,
+                    self.max_422chroma_constraint_flag as u8,
+                    self.max_420chroma_constraint_flag as u8,
+                    self.max_monochrome_constraint_flag as u8,
+                    self.intra_constraint_flag as u8,
+                    self.one_picture_only_constraint_flag as u8,
+                    self.lower_bit_rate_constraint_flag as u8,
+                ) {
+                    (1, 1, 1, 1, 0, 0, 0, 0, 0, 1) => HighThroughput444,
+                    (1, 1, 1, 0, 0, 0, 0, 0, 0, 1) => HighThroughput444_10,
+                    (1, 0, 0, 0, 0, 0, 0, 0, 0, 1) => HighThroughput444_14,
+                    (0, 0, 0, 0, 0, 0, 0, 1, 0, _) => HighThroughput444_16Intra,
 
-                _ => Unknown(self.profile_idc),
+                    _ => Unknown(self.profile_idc),
+                }
             }
-        } else if self.profile_idc == 6 || self.profile_compatibility_flag[6] {
-            match (
-                self.max_12bit_constraint_flag as u8,
-                self.max_10bit_constraint_flag as u8,
-                self.max_8bit_constraint_flag as u8,
-                self.max_422chroma_constraint_flag as u8,
-                self.max_420chroma_constraint_flag as u8,
-                self.max_monochrome_constraint_flag as u8,
-                self.intra_constraint_flag as u8,
-                self.one_picture_only_constraint_flag as u8,
-                self.lower_bit_rate_constraint_flag as u8,
-            ) {
-                (1, 1, 1, 1, 1, 0, 0, 0, 1) => MultiviewMain,
-                _ => Unknown(self.profile_idc),
+            6 => {
+                match (
+                    self.max_12bit_constraint_flag as u8,
+                    self.max_10bit_constraint_flag as u8,
+                    self.max_8bit_constraint_flag as u8,
+                    self.max_422chroma_constraint_flag as u8,
+                    self.max_420chroma_constraint_flag as u8,
+                    self.max_monochrome_constraint_flag as u8,
+                    self.intra_constraint_flag as u8,
+                    self.one_picture_only_constraint_flag as u8,
+                    self.lower_bit_rate_constraint_flag as u8,
+                ) {
+                    (1, 1, 1, 1, 1, 0, 0, 0, 1) => MultiviewMain,
+                    _ => Unknown(self.profile_idc),
+                }
             }
-        } else if self.profile_idc == 7 || self.profile_compatibility_flag[7] {
-            match (
-                self.max_12bit_constraint_flag as u8,
-                self.max_10bit_constraint_flag as u8,
-                self.max_8bit_constraint_flag as u8,
-                self.max_422chroma_constraint_flag as u8,
-                self.max_420chroma_constraint_flag as u8,
-                self.max_monochrome_constraint_flag as u8,
-                self.intra_constraint_flag as u8,
-                self.one_picture_only_constraint_flag as u8,
-                self.lower_bit_rate_constraint_flag as u8,
-            ) {
-                (1, 1, 1, 1, 1, 0, 0, 0, 1) => ScalableMain,
-                (1, 1, 0, 1, 1, 0, 0, 0, 1) => ScalableMain10,
-                _ => Unknown(self.profile_idc),
+            7 => {
+                match (
+                    self.max_12bit_constraint_flag as u8,
+                    self.max_10bit_constraint_flag as u8,
+                    self.max_8bit_constraint_flag as u8,
+                    self.max_422chroma_constraint_flag as u8,
+                    self.max_420chroma_constraint_flag as u8,
+                    self.max_monochrome_constraint_flag as u8,
+                    self.intra_constraint_flag as u8,
+                    self.one_picture_only_constraint_flag as u8,
+                    self.lower_bit_rate_constraint_flag as u8,
+                ) {
+                    (1, 1, 1, 1, 1, 0, 0, 0, 1) => ScalableMain,
+                    (1, 1, 0, 1, 1, 0, 0, 0, 1) => ScalableMain10,
+                    _ => Unknown(self.profile_idc),
+                }
             }
-        } else if self.profile_idc == 8 || self.profile_compatibility_flag[8] {
-            match (
-                self.max_12bit_constraint_flag as u8,
-                self.max_10bit_constraint_flag as u8,
-                self.max_8bit_constraint_flag as u8,
-                self.max_422chroma_constraint_flag as u8,
-                self.max_420chroma_constraint_flag as u8,
-                self.max_monochrome_constraint_flag as u8,
-                self.intra_constraint_flag as u8,
-                self.one_picture_only_constraint_flag as u8,
-                self.lower_bit_rate_constraint_flag as u8,
-            ) {
-                (1, 1, 1, 1, 1, 0, 0, 0, 1) => ThreeDeeMain,
-                _ => Unknown(self.profile_idc),
+            8 => {
+                match (
+                    self.max_12bit_constraint_flag as u8,
+                    self.max_10bit_constraint_flag as u8,
+                    self.max_8bit_constraint_flag as u8,
+                    self.max_422chroma_constraint_flag as u8,
+                    self.max_420chroma_constraint_flag as u8,
+                    self.max_monochrome_constraint_flag as u8,
+                    self.intra_constraint_flag as u8,
+                    self.one_picture_only_constraint_flag as u8,
+                    self.lower_bit_rate_constraint_flag as u8,
+                ) {
+                    (1, 1, 1, 1, 1, 0, 0, 0, 1) => ThreeDeeMain,
+                    _ => Unknown(self.profile_idc),
+                }
             }
-        } else if self.profile_idc == 9 || self.profile_compatibility_flag[9] {
-            match (
-                self.max_14bit_constraint_flag as u8,
-                self.max_12bit_constraint_flag as u8,
-                self.max_10bit_constraint_flag as u8,
-                self.max_8bit_constraint_flag as u8,
-                self.max_422chroma_constraint_flag as u8,
-                self.max_420chroma_constraint_flag as u8,
-                self.max_monochrome_constraint_flag as u8,
-                self.intra_constraint_flag as u8,
-                self.one_picture_only_constraint_flag as u8,
-                self.lower_bit_rate_constraint_flag as u8,
-            ) {
-                (1, 1, 1, 1, 1, 1, 0, 0, 0, 1) => ScreenExtendedMain,
-                (1, 1, 1, 0, 1, 1, 0, 0, 0, 1) => ScreenExtendedMain10,
-                (1, 1, 1, 1, 0, 0, 0, 0, 0, 1) => ScreenExtendedMain444,
-                (1, 1, 1, 0, 0, 0, 0, 0, 0, 1) => ScreenExtendedMain444_10,
+            9 => {
+                match (
+                    self.max_14bit_constraint_flag as u8,
+                    self.max_12bit_constraint_flag as u8,
+                    self.max_10bit_constraint_flag as u8,
+                    self.max_8bit_constraint_flag as u8,
+                    self.max_422chroma_constraint_flag as u8,
+                    self.max_420chroma_constraint_flag as u8,
+                    self.max_monochrome_constraint_flag as u8,
+                    self.intra_constraint_flag as u8,
+                    self.one_picture_only_constraint_flag as u8,
+                    self.lower_bit_rate_constraint_flag as u8,
+                ) {
+                    (1, 1, 1, 1, 1, 1, 0, 0, 0, 1) => ScreenExtendedMain,
+                    (1, 1, 1, 0, 1, 1, 0, 0, 0, 1) => ScreenExtendedMain10,
+                    (1, 1, 1, 1, 0, 0, 0, 0, 0, 1) => ScreenExtendedMain444,
+                    (1, 1, 1, 0, 0, 0, 0, 0, 0, 1) => ScreenExtendedMain444_10,
 
-                _ => Unknown(self.profile_idc),
+                    _ => Unknown(self.profile_idc),
+                }
             }
-        } else if self.profile_idc == 10 || self.profile_compatibility_flag[10] {
-            match (
-                self.max_14bit_constraint_flag as u8,
-                self.max_12bit_constraint_flag as u8,
-                self.max_10bit_constraint_flag as u8,
-                self.max_8bit_constraint_flag as u8,
-                self.max_422chroma_constraint_flag as u8,
-                self.max_420chroma_constraint_flag as u8,
-                self.max_monochrome_constraint_flag as u8,
-                self.intra_constraint_flag as u8,
-                self.one_picture_only_constraint_flag as u8,
-                self.lower_bit_rate_constraint_flag as u8,
-            ) {
-                (1, 1, 1, 1, 1, 1, 1, 0, 0, 1) => ScalableMonochrome,
-                (1, 1, 0, 0, 1, 1, 1, 0, 0, 1) => ScalableMonochrome12,
-                (0, 0, 0, 0, 1, 1, 1, 0, 0, 1) => ScalableMonochrome16,
-                (1, 1, 1, 1, 0, 0, 0, 0, 0, 1) => ScalableMain444,
+            10 => {
+                match (
+                    self.max_14bit_constraint_flag as u8,
+                    self.max_12bit_constraint_flag as u8,
+                    self.max_10bit_constraint_flag as u8,
+                    self.max_8bit_constraint_flag as u8,
+                    self.max_422chroma_constraint_flag as u8,
+                    self.max_420chroma_constraint_flag as u8,
+                    self.max_monochrome_constraint_flag as u8,
+                    self.intra_constraint_flag as u8,
+                    self.one_picture_only_constraint_flag as u8,
+                    self.lower_bit_rate_constraint_flag as u8,
+                ) {
+                    (1, 1, 1, 1, 1, 1, 1, 0, 0, 1) => ScalableMonochrome,
+                    (1, 1, 0, 0, 1, 1, 1, 0, 0, 1) => ScalableMonochrome12,
+                    (0, 0, 0, 0, 1, 1, 1, 0, 0, 1) => ScalableMonochrome16,
+                    (1, 1, 1, 1, 0, 0, 0, 0, 0, 1) => ScalableMain444,
 
-                _ => Unknown(self.profile_idc),
+                    _ => Unknown(self.profile_idc),
+                }
             }
-        } else if self.profile_idc == 11 || self.profile_compatibility_flag[11] {
-            match (
-                self.max_14bit_constraint_flag as u8,
-                self.max_12bit_constraint_flag as u8,
-                self.max_10bit_constraint_flag as u8,
-                self.max_8bit_constraint_flag as u8,
-                self.max_422chroma_constraint_flag as u8,
-                self.max_420chroma_constraint_flag as u8,
-                self.max_monochrome_constraint_flag as u8,
-                self.intra_constraint_flag as u8,
-                self.one_picture_only_constraint_flag as u8,
-                self.lower_bit_rate_constraint_flag as u8,
-            ) {
-                (1, 1, 1, 1, 0, 0, 0, 0, 0, 1) => ScreenExtendedHighThroughput444,
-                (1, 1, 1, 0, 0, 0, 0, 0, 0, 1) => ScreenExtendedHighThroughput444_10,
-                (1, 0, 0, 0, 0, 0, 0, 0, 0, 1) => ScreenExtendedHighThroughput444_14,
+            11 => {
+                match (
+                    self.max_14bit_constraint_flag as u8,
+                    self.max_12bit_constraint_flag as u8,
+                    self.max_10bit_constraint_flag as u8,
+                    self.max_8bit_constraint_flag as u8,
+                    self.max_422chroma_constraint_flag as u8,
+                    self.max_420chroma_constraint_flag as u8,
+                    self.max_monochrome_constraint_flag as u8,
+                    self.intra_constraint_flag as u8,
+                    self.one_picture_only_constraint_flag as u8,
+                    self.lower_bit_rate_constraint_flag as u8,
+                ) {
+                    (1, 1, 1, 1, 0, 0, 0, 0, 0, 1) => ScreenExtendedHighThroughput444,
+                    (1, 1, 1, 0, 0, 0, 0, 0, 0, 1) => ScreenExtendedHighThroughput444_10,
+                    (1, 0, 0, 0, 0, 0, 0, 0, 0, 1) => ScreenExtendedHighThroughput444_14,
 
-                _ => Unknown(self.profile_idc),
+                    _ => Unknown(self.profile_idc),
+                }
             }
-        } else {
-            Unknown(self.profile_idc)
+            _ => Unknown(self.profile_idc),
         }
     }
 }
 
 #[derive(Default, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct SubLayerProfileLevel {
     pub profile: Option<LayerProfile>,
     pub level_idc: Option<u8>,
@@ -1069,6 +1671,7 @@ impl SubLayerProfileLevel {
 // TODO: used in both vps and pps. break out to "common_syntax" module and add custom errors?
 /// Profile, Tier and Level
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ProfileTierLevel {
     pub general_profile: Option<LayerProfile>,
     pub general_level_idc: u8,
@@ -1120,29 +1723,36 @@ impl ProfileTierLevel {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct LayerInfo {
     pub sps_max_dec_pic_buffering_minus1: u32,
     pub sps_max_num_reorder_pics: u32,
     pub sps_max_latency_increase_plus1: u32,
 }
 impl LayerInfo {
+    /// Reads the sub-layer ordering info, returning one entry per sub-layer indexed by
+    /// `TemporalId`.
     pub fn read<R: BitRead>(
         r: &mut R,
         sps_max_sub_layers_minus1: u8,
-    ) -> Result<Vec<LayerInfo>, SpsError> {
+    ) -> Result<ArrayVec<LayerInfo, MAX_SUB_LAYERS>, SpsError> {
         SeqParameterSet::validate_max_num_sub_layers_minus1(sps_max_sub_layers_minus1)?;
 
         let sub_layer_ordering_info_present =
             r.read_bool("sps_sub_layer_ordering_info_present_flag")?;
 
+        let num_sub_layers = usize::from(sps_max_sub_layers_minus1) + 1;
         Ok(if sub_layer_ordering_info_present {
-            let mut layers = Vec::with_capacity((sps_max_sub_layers_minus1 + 1).into());
-            for _ in 0..=sps_max_sub_layers_minus1 {
+            let mut layers = ArrayVec::new();
+            for _ in 0..num_sub_layers {
                 layers.push(Self::read_layer(r)?);
             }
             layers
         } else {
-            vec![Self::read_layer(r)?] // NOTE: index is wrong if sps_max_sub_layers_minus1 > 0
+            // Only the values for the highest sub-layer are signalled, and the values for the
+            // lower sub-layers are inferred to be equal to them (7.4.3.2.1).
+            let layer = Self::read_layer(r)?;
+            std::iter::repeat_n(layer, num_sub_layers).collect()
         })
     }
 
@@ -1156,12 +1766,17 @@ impl LayerInfo {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ScalingList; // TODO: store list contents
 impl ScalingList {
     pub fn read<R: BitRead>(r: &mut R) -> Result<Option<ScalingList>, SpsError> {
         Ok(if r.read_bool("scaling_list_enabled_flag")? {
             if r.read_bool("sps_scaling_list_data_present_flag")? {
-                Some(Self::read_scaling_list(r)?)
+                Some(read_structure(
+                    r,
+                    "scaling_list_data",
+                    Self::read_scaling_list,
+                )?)
             } else {
                 Some(ScalingList) // Enabled but empty
             }
@@ -1170,7 +1785,7 @@ impl ScalingList {
         })
     }
 
-    fn read_scaling_list<R: BitRead>(r: &mut R) -> Result<ScalingList, SpsError> {
+    pub(crate) fn read_scaling_list<R: BitRead>(r: &mut R) -> Result<ScalingList, BitReaderError> {
         for size_id in 0..4 {
             for _matrix_id in (0..6).step_by(if size_id == 3 { 3 } else { 1 }) {
                 if !r.read_bool("scaling_list_pred_mode_flag")? {
@@ -1196,6 +1811,7 @@ impl ScalingList {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Pcm {
     pub pcm_sample_bit_depth_luma_minus1: u8,
     pub pcm_sample_bit_depth_chroma_minus1: u8,
@@ -1225,6 +1841,7 @@ impl Pcm {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ShortTermRef {
     /// Value read from NAL when inter_ref_pic_set_prediction_flag == 0
     pub delta_poc_minus1: Option<u32>,
@@ -1233,10 +1850,25 @@ pub struct ShortTermRef {
     pub used_by_curr_pic_flag: bool,
 }
 
+/// The largest `MaxDpbSize` allowed by any level, see clause A.4.2.
+const MAX_DPB_SIZE: u32 = 16;
+
+/// The entries of one list of a short-term RPS, of which there are fewer than `MaxDpbSize`.
+pub type ShortTermRefList = ArrayVec<ShortTermRef, { MAX_DPB_SIZE as usize }>;
+
+/// The largest `num_short_term_ref_pic_sets`.
+const MAX_SHORT_TERM_REF_PIC_SETS: usize = 64;
+
+/// The largest `num_long_term_ref_pics_sps`.
+const MAX_LONG_TERM_REF_PICS_SPS: usize = 32;
+
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ShortTermRefPicSet {
-    pub negative_pics_s0: Vec<ShortTermRef>,
-    pub positive_pics_s1: Vec<ShortTermRef>,
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzzing::array_vec))]
+    pub negative_pics_s0: ShortTermRefList,
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzzing::array_vec))]
+    pub positive_pics_s1: ShortTermRefList,
 }
 impl ShortTermRefPicSet {
     fn num_negative_pics(&self) -> usize {
@@ -1249,7 +1881,21 @@ impl ShortTermRefPicSet {
         self.num_negative_pics() + self.num_positive_pics()
     }
 
-    fn read<R: BitRead>(
+    /// The number of entries in the RPS with `used_by_curr_pic_flag` set, i.e. this set's
+    /// contribution to `NumPicTotalCurr`.
+    pub fn num_used_by_curr_pic(&self) -> usize {
+        self.negative_pics_s0
+            .iter()
+            .chain(self.positive_pics_s1.iter())
+            .filter(|r| r.used_by_curr_pic_flag)
+            .count()
+    }
+
+    /// Reads the `st_ref_pic_set( stRpsIdx )` syntax.
+    ///
+    /// `prev_sets` holds the sets already read from the SPS. When reading the set in a
+    /// slice header, `st_rps_idx` equals `num_short_term_ref_pic_sets`.
+    pub(crate) fn read<R: BitRead>(
         r: &mut R,
         st_rps_idx: u32,
         num_short_term_ref_pic_sets: u32,
@@ -1263,27 +1909,37 @@ impl ShortTermRefPicSet {
             r.read_bool("inter_ref_pic_set_prediction_flag")?
         };
         if inter_ref_pic_set_prediction_flag {
-            // TODO: "The value of delta_idx_minus1 shall be in the range of 0 to stRpsIdx − 1, inclusive."
+            // "The value of delta_idx_minus1 shall be in the range of 0 to stRpsIdx − 1, inclusive."
             let delta_idx_minus1 = if st_rps_idx == num_short_term_ref_pic_sets {
                 r.read_ue("delta_idx_minus1")?
             } else {
                 0
             };
+            if delta_idx_minus1 >= st_rps_idx {
+                return Err(SpsError::FieldValueTooLarge {
+                    name: "delta_idx_minus1",
+                    value: delta_idx_minus1,
+                });
+            }
             let delta_rps_sign = i32::from(r.read_bool("delta_rps_sign")?);
-            let abs_delta_rps_minus1 = i32::try_from(r.read_ue("abs_delta_rps_minus1")?)
-                .expect("abs_delta_rps_minus1 out of range");
-            // TODO: "The value of abs_delta_rps_minus1 shall be in the range of 0 to 2^15 − 1,"
+            // "The value of abs_delta_rps_minus1 shall be in the range of 0 to 2^15 − 1, inclusive."
+            let abs_delta_rps_minus1 = r.read_ue("abs_delta_rps_minus1")?;
+            if abs_delta_rps_minus1 > (1 << 15) - 1 {
+                return Err(SpsError::FieldValueTooLarge {
+                    name: "abs_delta_rps_minus1",
+                    value: abs_delta_rps_minus1,
+                });
+            }
+            let abs_delta_rps_minus1 = abs_delta_rps_minus1 as i32;
 
             let ref_rps_idx = st_rps_idx - (delta_idx_minus1 + 1);
             let delta_rps = (1 - 2 * delta_rps_sign) * (abs_delta_rps_minus1 + 1);
             // ref_rps.xyz here is equivalent to Xyz[ RefRpsIdx ] in spec
-            let ref_rps = &prev_sets
-                .get(usize::try_from(ref_rps_idx).unwrap())
-                .unwrap();
+            let ref_rps = &prev_sets[ref_rps_idx as usize];
 
             // Read used_by_curr_pic_flag[j] and use_delta_flag[j]
-            let mut used_by_curr_pic = Vec::with_capacity(ref_rps.num_delta_pocs() + 1);
-            let mut use_delta = Vec::with_capacity(ref_rps.num_delta_pocs() + 1);
+            let mut used_by_curr_pic = ArrayVec::<bool, { 2 * MAX_DPB_SIZE as usize + 1 }>::new();
+            let mut use_delta = ArrayVec::<bool, { 2 * MAX_DPB_SIZE as usize + 1 }>::new();
             for _j in 0..=ref_rps.num_delta_pocs() {
                 let used_by_curr_pic_flag = r.read_bool("used_by_curr_pic_flag")?;
                 let use_delta_flag = if !used_by_curr_pic_flag {
@@ -1295,9 +1951,8 @@ impl ShortTermRefPicSet {
                 use_delta.push(use_delta_flag);
             }
 
-            // This algorithm is translated from the spec
-            // TODO: a lot of (indexing) validation is missing here,
-            // so we are much more panicky than we should be.
+            // This algorithm is translated from the spec. `used_by_curr_pic` and `use_delta` have
+            // `NumDeltaPocs[ RefRpsIdx ] + 1` entries, so all indexing below is in bounds.
             //
             // i=0
             // for( j = NumPositivePics[ RefRpsIdx ] − 1; j >= 0; j−− ) {
@@ -1307,15 +1962,29 @@ impl ShortTermRefPicSet {
             //     UsedByCurrPicS0[ stRpsIdx ][ i++ ] =
             //     used_by_curr_pic_flag[ NumNegativePics[ RefRpsIdx ] + j ]
             //   }
-            let mut negative_pics_s0 = Vec::new();
+            // Each entry of the reference set and deltaRps itself may yield one entry, so the
+            // lists may overflow when sets are predicted from predicted sets.
+            let push = |list: &mut ShortTermRefList, entry, name| {
+                list.try_push(entry)
+                    .map_err(|_| SpsError::FieldValueTooLarge {
+                        name,
+                        value: MAX_DPB_SIZE,
+                    })
+            };
+            let mut negative_pics_s0 = ShortTermRefList::new();
             for j in (0..ref_rps.num_positive_pics()).rev() {
                 let d_poc = ref_rps.positive_pics_s1[j].delta_poc + delta_rps;
                 if d_poc < 0 && use_delta[ref_rps.num_negative_pics() + j] {
-                    negative_pics_s0.push(ShortTermRef {
-                        delta_poc_minus1: None,
-                        delta_poc: d_poc,
-                        used_by_curr_pic_flag: used_by_curr_pic[ref_rps.num_negative_pics() + j],
-                    });
+                    push(
+                        &mut negative_pics_s0,
+                        ShortTermRef {
+                            delta_poc_minus1: None,
+                            delta_poc: d_poc,
+                            used_by_curr_pic_flag: used_by_curr_pic
+                                [ref_rps.num_negative_pics() + j],
+                        },
+                        "NumNegativePics",
+                    )?;
                 }
             }
             // if( deltaRps < 0 && use_delta_flag[ NumDeltaPocs[ RefRpsIdx ] ] ) { // (7-61)
@@ -1323,11 +1992,15 @@ impl ShortTermRefPicSet {
             //    UsedByCurrPicS0[ stRpsIdx ][ i++ ] = used_by_curr_pic_flag[ NumDeltaPocs[ RefRpsIdx ] ]
             // }
             if delta_rps < 0 && use_delta[ref_rps.num_delta_pocs()] {
-                negative_pics_s0.push(ShortTermRef {
-                    delta_poc_minus1: None,
-                    delta_poc: delta_rps,
-                    used_by_curr_pic_flag: used_by_curr_pic[ref_rps.num_delta_pocs()],
-                });
+                push(
+                    &mut negative_pics_s0,
+                    ShortTermRef {
+                        delta_poc_minus1: None,
+                        delta_poc: delta_rps,
+                        used_by_curr_pic_flag: used_by_curr_pic[ref_rps.num_delta_pocs()],
+                    },
+                    "NumNegativePics",
+                )?;
             }
             // for( j = 0; j < NumNegativePics[ RefRpsIdx ]; j++ ) {
             //   dPoc = DeltaPocS0[ RefRpsIdx ][ j ] + deltaRps
@@ -1340,11 +2013,15 @@ impl ShortTermRefPicSet {
             for j in 0..ref_rps.num_negative_pics() {
                 let d_poc = ref_rps.negative_pics_s0[j].delta_poc + delta_rps;
                 if d_poc < 0 && use_delta[j] {
-                    negative_pics_s0.push(ShortTermRef {
-                        delta_poc_minus1: None,
-                        delta_poc: d_poc,
-                        used_by_curr_pic_flag: used_by_curr_pic[j],
-                    });
+                    push(
+                        &mut negative_pics_s0,
+                        ShortTermRef {
+                            delta_poc_minus1: None,
+                            delta_poc: d_poc,
+                            used_by_curr_pic_flag: used_by_curr_pic[j],
+                        },
+                        "NumNegativePics",
+                    )?;
                 }
             }
 
@@ -1356,15 +2033,19 @@ impl ShortTermRefPicSet {
             //     UsedByCurrPicS1[ stRpsIdx ][ i++ ] = used_by_curr_pic_flag[ j ]
             //   }
             // }
-            let mut positive_pics_s1 = Vec::new();
+            let mut positive_pics_s1 = ShortTermRefList::new();
             for j in (0..ref_rps.num_negative_pics()).rev() {
                 let d_poc = ref_rps.negative_pics_s0[j].delta_poc + delta_rps;
                 if d_poc > 0 && use_delta[j] {
-                    positive_pics_s1.push(ShortTermRef {
-                        delta_poc_minus1: None,
-                        delta_poc: d_poc,
-                        used_by_curr_pic_flag: used_by_curr_pic[j],
-                    });
+                    push(
+                        &mut positive_pics_s1,
+                        ShortTermRef {
+                            delta_poc_minus1: None,
+                            delta_poc: d_poc,
+                            used_by_curr_pic_flag: used_by_curr_pic[j],
+                        },
+                        "NumPositivePics",
+                    )?;
                 }
             }
             // if( deltaRps > 0 && use_delta_flag[ NumDeltaPocs[ RefRpsIdx ] ] ) { ( // 7-62)
@@ -1372,11 +2053,15 @@ impl ShortTermRefPicSet {
             //   UsedByCurrPicS1[ stRpsIdx ][ i++ ] = used_by_curr_pic_flag[ NumDeltaPocs[ RefRpsIdx ] ]
             // }
             if delta_rps > 0 && use_delta[ref_rps.num_delta_pocs()] {
-                positive_pics_s1.push(ShortTermRef {
-                    delta_poc_minus1: None,
-                    delta_poc: delta_rps,
-                    used_by_curr_pic_flag: used_by_curr_pic[ref_rps.num_delta_pocs()],
-                });
+                push(
+                    &mut positive_pics_s1,
+                    ShortTermRef {
+                        delta_poc_minus1: None,
+                        delta_poc: delta_rps,
+                        used_by_curr_pic_flag: used_by_curr_pic[ref_rps.num_delta_pocs()],
+                    },
+                    "NumPositivePics",
+                )?;
             }
             // for( j = 0; j < NumPositivePics[ RefRpsIdx ]; j++) {
             //   dPoc = DeltaPocS1[ RefRpsIdx ][ j ] + deltaRps
@@ -1390,11 +2075,16 @@ impl ShortTermRefPicSet {
             for j in 0..ref_rps.num_positive_pics() {
                 let d_poc = ref_rps.positive_pics_s1[j].delta_poc + delta_rps;
                 if d_poc > 0 && use_delta[ref_rps.num_negative_pics() + j] {
-                    positive_pics_s1.push(ShortTermRef {
-                        delta_poc_minus1: None,
-                        delta_poc: d_poc,
-                        used_by_curr_pic_flag: used_by_curr_pic[ref_rps.num_negative_pics() + j],
-                    });
+                    push(
+                        &mut positive_pics_s1,
+                        ShortTermRef {
+                            delta_poc_minus1: None,
+                            delta_poc: d_poc,
+                            used_by_curr_pic_flag: used_by_curr_pic
+                                [ref_rps.num_negative_pics() + j],
+                        },
+                        "NumPositivePics",
+                    )?;
                 }
             }
 
@@ -1404,9 +2094,22 @@ impl ShortTermRefPicSet {
             })
         } else {
             // TODO: "the value of num_negative_pics shall be in the range of 0 to sps_max_dec_pic_buffering_minus1[ sps_max_sub_layers_minus1 ], inclusive."
+            // For now only the bound implied by the maximum DPB size is enforced.
             let num_negative_pics = r.read_ue("num_negative_pics")?;
+            if num_negative_pics >= MAX_DPB_SIZE {
+                return Err(SpsError::FieldValueTooLarge {
+                    name: "num_negative_pics",
+                    value: num_negative_pics,
+                });
+            }
             let num_positive_pics = r.read_ue("num_positive_pics")?;
-            let mut negative_pics_s0: Vec<ShortTermRef> = Vec::new();
+            if num_positive_pics >= MAX_DPB_SIZE - num_negative_pics {
+                return Err(SpsError::FieldValueTooLarge {
+                    name: "num_positive_pics",
+                    value: num_positive_pics,
+                });
+            }
+            let mut negative_pics_s0 = ShortTermRefList::new();
             for _ in 0..num_negative_pics {
                 let delta_poc_s0_minus1 = r.read_ue("delta_poc_s0_minus1")?;
                 let used_by_curr_pic_s0_flag = r.read_bool("used_by_curr_pic_s0_flag")?;
@@ -1417,7 +2120,7 @@ impl ShortTermRefPicSet {
                     used_by_curr_pic_flag: used_by_curr_pic_s0_flag,
                 });
             }
-            let mut positive_pics_s1: Vec<ShortTermRef> = Vec::new();
+            let mut positive_pics_s1 = ShortTermRefList::new();
             for _ in 0..num_positive_pics {
                 let delta_poc_s1_minus1 = r.read_ue("delta_poc_s1_minus1")?;
                 let used_by_curr_pic_s1_flag = r.read_bool("used_by_curr_pic_s1_flag")?;
@@ -1436,13 +2139,20 @@ impl ShortTermRefPicSet {
         }
     }
 
-    pub fn read_with_count<R: BitRead>(r: &mut R) -> Result<Vec<Self>, SpsError> {
-        // TODO: "The value of num_short_term_ref_pic_sets shall be in the range of 0 to 64, inclusive."
-        //       (so we can use arrayvec here)
+    pub fn read_with_count<R: BitRead>(
+        r: &mut R,
+    ) -> Result<ArrayVec<Self, MAX_SHORT_TERM_REF_PIC_SETS>, SpsError> {
+        // "The value of num_short_term_ref_pic_sets shall be in the range of 0 to 64, inclusive."
         let num = r.read_ue("num_short_term_ref_pic_sets")?;
-        let mut sets = Vec::new();
+        if num as usize > MAX_SHORT_TERM_REF_PIC_SETS {
+            return Err(SpsError::FieldValueTooLarge {
+                name: "num_short_term_ref_pic_sets",
+                value: num,
+            });
+        }
+        let mut sets = ArrayVec::new();
         for i in 0..num {
-            let next_set = Self::read(r, i, num, &sets)?;
+            let next_set = read_structure(r, "st_ref_pic_set", |r| Self::read(r, i, num, &sets))?;
             sets.push(next_set);
         }
         Ok(sets)
@@ -1450,28 +2160,48 @@ impl ShortTermRefPicSet {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
-pub struct LongTermRefPicSps; // TODO: store content
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct LongTermRefPicSps {
+    pub lt_ref_pic_poc_lsb_sps: u32,
+    pub used_by_curr_pic_lt_sps_flag: bool,
+}
 impl LongTermRefPicSps {
-    fn read_one<R: BitRead>(r: &mut R) -> Result<Self, SpsError> {
-        let _lt_ref_pic_pic_lsb_sps = r.read_ue("lt_ref_pic_pic_lsb_sps")?;
-        let _used_by_curr_pic_lt_sps_flag = r.read_ue("used_by_curr_pic_lt_sps_flag")?;
-
-        Ok(LongTermRefPicSps)
+    fn read_one<R: BitRead>(r: &mut R, log2_max_pic_order_cnt_lsb: u32) -> Result<Self, SpsError> {
+        Ok(LongTermRefPicSps {
+            lt_ref_pic_poc_lsb_sps: r
+                .read_u32(log2_max_pic_order_cnt_lsb, "lt_ref_pic_poc_lsb_sps")?,
+            used_by_curr_pic_lt_sps_flag: r.read_bool("used_by_curr_pic_lt_sps_flag")?,
+        })
     }
 
-    pub fn read<R: BitRead>(r: &mut R) -> Result<Option<Vec<Self>>, SpsError> {
+    pub fn read<R: BitRead>(
+        r: &mut R,
+        log2_max_pic_order_cnt_lsb: u32,
+    ) -> Result<Option<ArrayVec<Self, MAX_LONG_TERM_REF_PICS_SPS>>, SpsError> {
         let present = r.read_bool("long_term_ref_pics_present_flag")?;
         if present {
+            // "The value of num_long_term_ref_pics_sps shall be in the range of 0 to 32, inclusive."
             let num = r.read_ue("num_long_term_ref_pics_sps")?;
-            let refs: Result<Vec<_>, _> = (0..num).map(|_| Self::read_one(r)).collect();
-            Ok(Some(refs?))
-        } else {
+            if num as usize > MAX_LONG_TERM_REF_PICS_SPS {
+                return Err(SpsError::FieldValueTooLarge {
+                    name: "num_long_term_ref_pics_sps",
+                    value: num,
+                });
+            }
+            let mut refs = ArrayVec::new();
+            for _ in 0..num {
+                refs.push(Self::read_one(r, log2_max_pic_order_cnt_lsb)?);
+            }
+            Ok(Some(refs))
+        } else {
             Ok(None)
         }
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+/// The `Default` value has every optional part absent.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct VuiParameters {
     pub aspect_ratio_info: Option<AspectRatioInfo>,
     pub overscan_appropriate: OverscanAppropriate,
@@ -1509,22 +2239,301 @@ impl VuiParameters {
         hrd_common_inf_present: bool,
         max_sub_layers_minus1: u8,
     ) -> Result<Option<Self>, SpsError> {
-        Ok(if r.read_bool("vui_parameeters_present")? {
-            Some(Self::read_one(
+        Ok(if r.read_bool("vui_parameters_present_flag")? {
+            Some(read_structure(r, "vui_parameters", |r| {
+                Self::read_one(r, hrd_common_inf_present, max_sub_layers_minus1)
+            })?)
+        } else {
+            None
+        })
+    }
+
+    /// Writes `vui_parameters_present_flag` followed by the `vui_parameters()` syntax of an
+    /// SPS, the inverse of [`Self::read`].
+    #[cfg(feature = "write")]
+    pub(crate) fn write(vui: Option<&Self>, w: &mut BitWriter) {
+        w.write_bool(vui.is_some());
+        let Some(vui) = vui else {
+            return;
+        };
+        AspectRatioInfo::write(vui.aspect_ratio_info.as_ref(), w);
+        vui.overscan_appropriate.write(w);
+        VideoSignalType::write(vui.video_signal_type.as_ref(), w);
+        ChromaLocInfo::write(vui.chroma_loc_info.as_ref(), w);
+        w.write_bool(vui.neutral_chroma_indication_flag);
+        w.write_bool(vui.field_seq_flag);
+        w.write_bool(vui.frame_field_info_present_flag);
+        Window::write(vui.default_display_window.as_ref(), w);
+        TimingInfo::write(vui.timing_info.as_ref(), w);
+        BitstreamRestrictions::write(vui.bitstream_restrictions.as_ref(), w);
+    }
+}
+
+/// [`VuiParameters`] with every absent syntax element replaced by the value inferred by
+/// clause E.3.1, see [`VuiParameters::effective`].
+///
+/// The `Default` derives of the individual VUI types don't always match the spec inference
+/// (e.g. `video_format` is inferred to be 5, unspecified), so prefer this view when
+/// interpreting a stream.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EffectiveVui<'a> {
+    pub aspect_ratio_info: AspectRatioInfo,
+    pub overscan_appropriate: OverscanAppropriate,
+    pub video_format: VideoFormat,
+    pub video_full_range_flag: bool,
+    pub colour_description: ColourDescription,
+    pub chroma_loc_info: ChromaLocInfo,
+    pub neutral_chroma_indication_flag: bool,
+    pub field_seq_flag: bool,
+    pub frame_field_info_present_flag: bool,
+    pub default_display_window: Window,
+    /// Timing info has no inferred value; it is simply absent.
+    pub timing_info: Option<&'a TimingInfo>,
+    pub bitstream_restrictions: BitstreamRestrictions,
+}
+impl Default for EffectiveVui<'_> {
+    /// The inferred values when no `vui_parameters()` are present at all.
+    fn default() -> Self {
+        EffectiveVui {
+            aspect_ratio_info: AspectRatioInfo::Unspecified,
+            overscan_appropriate: OverscanAppropriate::Unspecified,
+            video_format: VideoFormat::Unspecified,
+            video_full_range_flag: false,
+            colour_description: ColourDescription {
+                colour_primaries: 2,
+                transfer_characteristics: 2,
+                matrix_coeffs: 2,
+            },
+            chroma_loc_info: ChromaLocInfo::default(),
+            neutral_chroma_indication_flag: false,
+            field_seq_flag: false,
+            frame_field_info_present_flag: false,
+            default_display_window: Window::default(),
+            timing_info: None,
+            bitstream_restrictions: BitstreamRestrictions {
+                tiles_fixed_structure_flag: false,
+                motion_vectors_over_pic_boundaries_flag: true,
+                restricted_ref_pic_lists_flag: false,
+                min_spatial_segmentation_idc: 0,
+                max_bytes_per_pic_denom: 2,
+                max_bits_per_mb_denom: 1,
+                log2_max_mv_length_horizontal: 15,
+                log2_max_mv_length_vertical: 15,
+            },
+        }
+    }
+}
+
+impl VuiParameters {
+    /// The VUI with spec inferred defaults applied for absent syntax elements.
+    pub fn effective(&self) -> EffectiveVui<'_> {
+        let defaults = EffectiveVui::default();
+        let video_signal_type = self.video_signal_type.as_ref();
+        EffectiveVui {
+            aspect_ratio_info: self
+                .aspect_ratio_info
+                .clone()
+                .unwrap_or(defaults.aspect_ratio_info),
+            overscan_appropriate: self.overscan_appropriate.clone(),
+            video_format: video_signal_type
+                .map(|v| v.video_format.clone())
+                .unwrap_or(defaults.video_format),
+            video_full_range_flag: video_signal_type
+                .map(|v| v.video_full_range_flag)
+                .unwrap_or(defaults.video_full_range_flag),
+            colour_description: video_signal_type
+                .and_then(|v| v.colour_description.clone())
+                .unwrap_or(defaults.colour_description),
+            chroma_loc_info: self
+                .chroma_loc_info
+                .clone()
+                .unwrap_or(defaults.chroma_loc_info),
+            neutral_chroma_indication_flag: self.neutral_chroma_indication_flag,
+            field_seq_flag: self.field_seq_flag,
+            frame_field_info_present_flag: self.frame_field_info_present_flag,
+            default_display_window: self
+                .default_display_window
+                .clone()
+                .unwrap_or(defaults.default_display_window),
+            timing_info: self.timing_info.as_ref(),
+            bitstream_restrictions: self
+                .bitstream_restrictions
+                .clone()
+                .unwrap_or(defaults.bitstream_restrictions),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct SpsRangeExtension {
+    pub transform_skip_rotation_enabled_flag: bool,
+    pub transform_skip_context_enabled_flag: bool,
+    pub implicit_rdpcm_enabled_flag: bool,
+    pub explicit_rdpcm_enabled_flag: bool,
+    pub extended_precision_processing_flag: bool,
+    pub intra_smoothing_disabled_flag: bool,
+    pub high_precision_offsets_enabled_flag: bool,
+    pub persistent_rice_adaptation_enabled_flag: bool,
+    pub cabac_bypass_alignment_enabled_flag: bool,
+}
+impl SpsRangeExtension {
+    fn read<R: BitRead>(r: &mut R) -> Result<SpsRangeExtension, SpsError> {
+        Ok(SpsRangeExtension {
+            transform_skip_rotation_enabled_flag: r
+                .read_bool("transform_skip_rotation_enabled_flag")?,
+            transform_skip_context_enabled_flag: r
+                .read_bool("transform_skip_context_enabled_flag")?,
+            implicit_rdpcm_enabled_flag: r.read_bool("implicit_rdpcm_enabled_flag")?,
+            explicit_rdpcm_enabled_flag: r.read_bool("explicit_rdpcm_enabled_flag")?,
+            extended_precision_processing_flag: r
+                .read_bool("extended_precision_processing_flag")?,
+            intra_smoothing_disabled_flag: r.read_bool("intra_smoothing_disabled_flag")?,
+            high_precision_offsets_enabled_flag: r
+                .read_bool("high_precision_offsets_enabled_flag")?,
+            persistent_rice_adaptation_enabled_flag: r
+                .read_bool("persistent_rice_adaptation_enabled_flag")?,
+            cabac_bypass_alignment_enabled_flag: r
+                .read_bool("cabac_bypass_alignment_enabled_flag")?,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct PaletteMode {
+    pub palette_max_size: u32,
+    pub delta_palette_max_predictor_size: u32,
+    /// `sps_palette_predictor_initializer[ comp ][ i ]`, present iff
+    /// `sps_palette_predictor_initializers_present_flag`. Has one component for monochrome
+    /// streams, otherwise three.
+    pub sps_palette_predictor_initializers: Option<Vec<Vec<u16>>>,
+}
+impl PaletteMode {
+    fn read<R: BitRead>(
+        r: &mut R,
+        chroma_array_type: u32,
+        bit_depth_luma_minus8: u32,
+        bit_depth_chroma_minus8: u32,
+    ) -> Result<PaletteMode, SpsError> {
+        // "The value of palette_max_size shall be in the range of 0 to 64, inclusive."
+        let palette_max_size = r.read_ue("palette_max_size")?;
+        if palette_max_size > 64 {
+            return Err(SpsError::FieldValueTooLarge {
+                name: "palette_max_size",
+                value: palette_max_size,
+            });
+        }
+        // "The value of delta_palette_max_predictor_size shall be in the range of 0 to
+        // 128 − palette_max_size, inclusive."
+        let delta_palette_max_predictor_size = r.read_ue("delta_palette_max_predictor_size")?;
+        if delta_palette_max_predictor_size > 128 - palette_max_size {
+            return Err(SpsError::FieldValueTooLarge {
+                name: "delta_palette_max_predictor_size",
+                value: delta_palette_max_predictor_size,
+            });
+        }
+        let sps_palette_predictor_initializers =
+            if r.read_bool("sps_palette_predictor_initializers_present_flag")? {
+                let num_minus1 = r.read_ue("sps_num_palette_predictor_initializers_minus1")?;
+                if num_minus1 >= palette_max_size + delta_palette_max_predictor_size {
+                    return Err(SpsError::FieldValueTooLarge {
+                        name: "sps_num_palette_predictor_initializers_minus1",
+                        value: num_minus1,
+                    });
+                }
+                let num_comps = if chroma_array_type == 0 { 1 } else { 3 };
+                let mut initializers = Vec::with_capacity(num_comps);
+                for comp in 0..num_comps {
+                    let bit_depth = 8 + if comp == 0 {
+                        bit_depth_luma_minus8
+                    } else {
+                        bit_depth_chroma_minus8
+                    };
+                    if bit_depth > 16 {
+                        return Err(SpsError::FieldValueTooLarge {
+                            name: "bit_depth_minus8",
+                            value: bit_depth - 8,
+                        });
+                    }
+                    let values: Result<Vec<_>, _> = (0..=num_minus1)
+                        .map(|_| r.read_u16(bit_depth, "sps_palette_predictor_initializer"))
+                        .collect();
+                    initializers.push(values?);
+                }
+                Some(initializers)
+            } else {
+                None
+            };
+        Ok(PaletteMode {
+            palette_max_size,
+            delta_palette_max_predictor_size,
+            sps_palette_predictor_initializers,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct SpsSccExtension {
+    pub sps_curr_pic_ref_enabled_flag: bool,
+    /// Present iff `palette_mode_enabled_flag`.
+    pub palette_mode: Option<PaletteMode>,
+    pub motion_vector_resolution_control_idc: u8,
+    pub intra_boundary_filtering_disabled_flag: bool,
+}
+impl SpsSccExtension {
+    fn read<R: BitRead>(
+        r: &mut R,
+        chroma_array_type: u32,
+        bit_depth_luma_minus8: u32,
+        bit_depth_chroma_minus8: u32,
+    ) -> Result<SpsSccExtension, SpsError> {
+        let sps_curr_pic_ref_enabled_flag = r.read_bool("sps_curr_pic_ref_enabled_flag")?;
+        let palette_mode = if r.read_bool("palette_mode_enabled_flag")? {
+            Some(PaletteMode::read(
                 r,
-                hrd_common_inf_present,
-                max_sub_layers_minus1,
+                chroma_array_type,
+                bit_depth_luma_minus8,
+                bit_depth_chroma_minus8,
             )?)
         } else {
             None
+        };
+        Ok(SpsSccExtension {
+            sps_curr_pic_ref_enabled_flag,
+            palette_mode,
+            motion_vector_resolution_control_idc: r
+                .read_u8(2, "motion_vector_resolution_control_idc")?,
+            intra_boundary_filtering_disabled_flag: r
+                .read_bool("intra_boundary_filtering_disabled_flag")?,
         })
     }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
-pub struct SpsExtension; // TODO: contents
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct SpsExtension {
+    pub sps_range_extension: Option<SpsRangeExtension>,
+    pub sps_multilayer_extension_flag: bool,
+    pub sps_3d_extension_flag: bool,
+    /// `sps_scc_extension_flag`. The extension is only parsed into `sps_scc_extension` if
+    /// neither of the extensions preceding it is present.
+    pub sps_scc_extension_flag: bool,
+    pub sps_scc_extension: Option<SpsSccExtension>,
+    pub sps_extension_4bits: u8,
+    /// The syntax following the extensions parsed above, up to the `rbsp_trailing_bits()`:
+    /// from `sps_multilayer_extension()` or `sps_3d_extension()` if either is present, or
+    /// else the `sps_extension_data_flag` bits if `sps_extension_4bits` is nonzero.
+    pub extension_data: Option<RawBits>,
+}
 impl SpsExtension {
-    fn read<R: BitRead>(r: &mut R) -> Result<Option<Self>, SpsError> {
+    fn read<R: BitRead>(
+        r: &mut R,
+        chroma_array_type: u32,
+        bit_depth_luma_minus8: u32,
+        bit_depth_chroma_minus8: u32,
+    ) -> Result<Option<Self>, SpsError> {
         Ok(if r.read_bool("sps_extension_present_flag")? {
             let sps_range_extension_flag = r.read_bool("sps_range_extension_flag")?;
             let sps_multilayer_extension_flag = r.read_bool("sps_multilayer_extension_flag")?;
@@ -1532,36 +2541,196 @@ impl SpsExtension {
             let sps_scc_extension_flag = r.read_bool("sps_scc_extension_flag")?;
             let sps_extension_4bits = r.read_u8(4, "sps_extension_4bits")?;
 
-            // TODO
-            if sps_range_extension_flag {
-                return Err(SpsError::Unimplemented("sps_range_extension"));
-            }
-            if sps_multilayer_extension_flag {
-                return Err(SpsError::Unimplemented("sps_multilayer_extension"));
-            }
-            if sps_3d_extension_flag {
-                return Err(SpsError::Unimplemented("sps_3d_extension"));
-            }
-            if sps_scc_extension_flag {
-                return Err(SpsError::Unimplemented("sps_scc_extension"));
-            }
-            if sps_extension_4bits != 0 {
-                while r.has_more_rbsp_data("sps_extension_data_flag")? {
-                    r.read_bool("sps_extension_data_flag")?;
+            let sps_range_extension = if sps_range_extension_flag {
+                Some(read_structure(
+                    r,
+                    "sps_range_extension",
+                    SpsRangeExtension::read,
+                )?)
+            } else {
+                None
+            };
+            let mut sps_scc_extension = None;
+            let extension_data = if sps_multilayer_extension_flag {
+                Some(RawBits::read_to_trailing_bits(
+                    r,
+                    "sps_multilayer_extension",
+                )?)
+            } else if sps_3d_extension_flag {
+                Some(RawBits::read_to_trailing_bits(r, "sps_3d_extension")?)
+            } else {
+                if sps_scc_extension_flag {
+                    sps_scc_extension = Some(read_structure(r, "sps_scc_extension", |r| {
+                        SpsSccExtension::read(
+                            r,
+                            chroma_array_type,
+                            bit_depth_luma_minus8,
+                            bit_depth_chroma_minus8,
+                        )
+                    })?);
                 }
-            }
+                if sps_extension_4bits != 0 {
+                    Some(RawBits::read_to_trailing_bits(
+                        r,
+                        "sps_extension_data_flag",
+                    )?)
+                } else {
+                    None
+                }
+            };
 
-            Some(SpsExtension)
+            Some(SpsExtension {
+                sps_range_extension,
+                sps_multilayer_extension_flag,
+                sps_3d_extension_flag,
+                sps_scc_extension_flag,
+                sps_scc_extension,
+                sps_extension_4bits,
+                extension_data,
+            })
         } else {
             None
         })
     }
 }
 
-pub type VideoParamSetId = ParamSetId<15>;
-pub type SeqParamSetId = ParamSetId<15>;
+/// Variables derived from the SPS, as specified in clause 7.4.3.2.1.
+///
+/// Field names follow the spec variable names, e.g. `ctb_size_y` is `CtbSizeY`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DerivedSpsParams {
+    pub min_cb_log2_size_y: u32,
+    pub ctb_log2_size_y: u32,
+    pub min_cb_size_y: u32,
+    pub ctb_size_y: u32,
+    pub pic_width_in_min_cbs_y: u32,
+    pub pic_height_in_min_cbs_y: u32,
+    pub pic_size_in_min_cbs_y: u32,
+    pub pic_width_in_ctbs_y: u32,
+    pub pic_height_in_ctbs_y: u32,
+    pub pic_size_in_ctbs_y: u32,
+    pub pic_size_in_samples_y: u32,
+    pub pic_width_in_samples_c: u32,
+    pub pic_height_in_samples_c: u32,
+    /// Zero when `ChromaArrayType` is 0 (monochrome or separate colour planes).
+    pub ctb_width_c: u32,
+    /// Zero when `ChromaArrayType` is 0 (monochrome or separate colour planes).
+    pub ctb_height_c: u32,
+    pub min_tb_log2_size_y: u32,
+    pub max_tb_log2_size_y: u32,
+    pub bit_depth_y: u32,
+    pub bit_depth_c: u32,
+    pub qp_bd_offset_y: u32,
+    pub qp_bd_offset_c: u32,
+    pub max_pic_order_cnt_lsb: u32,
+}
+
+pub type VideoParamSetId = ParamSetId<crate::nal::pps::Video>;
+pub type SeqParamSetId = ParamSetId<crate::nal::pps::Seq>;
+
+/// The leading fields of an SPS, up to the bit depths: enough to probe a stream's profile, tier,
+/// level, picture size and format without parsing the rest of the SPS.
+///
+/// ```
+/// use hevc_reader::nal::sps::{Profile, SpsHeader};
+/// use hevc_reader::rbsp::BitReader;
+/// # let sps = [
+/// #     0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03, 0x00, 0x00,
+/// #     0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46, 0xd1, 0x2e,
+/// #     0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10, 0x00, 0x00, 0x03,
+/// #     0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00, 0x0b, 0xb8, 0x48,
+/// # ];
+/// let header = SpsHeader::from_bits(BitReader::from_nal(&sps)).unwrap();
+/// assert_eq!(header.general_profile(), Profile::Main);
+/// assert_eq!(header.pixel_dimensions().unwrap(), (720, 576));
+/// assert_eq!(header.bit_depth_luma_minus8, 0);
+/// ```
+///
+/// The frame rate is signalled in the VUI, after the reference picture sets, so isn't cheap to
+/// reach; use [`SeqParameterSet`] for it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct SpsHeader {
+    pub sps_video_parameter_set_id: VideoParamSetId,
+    pub sps_max_sub_layers_minus1: u8,
+    pub sps_temporal_id_nesting: bool,
+    pub profile_tier_level: ProfileTierLevel,
+    pub sps_seq_parameter_set_id: SeqParamSetId,
+    pub chroma_info: ChromaInfo,
+    pub pic_width_in_luma_samples: u32,
+    pub pic_height_in_luma_samples: u32,
+    pub conformance_window: Option<Window>,
+    pub bit_depth_luma_minus8: u32,
+    pub bit_depth_chroma_minus8: u32,
+}
+impl SpsHeader {
+    /// Reads the leading fields, ignoring the rest of the SPS.
+    pub fn from_bits<R: BitRead>(mut r: R) -> Result<SpsHeader, SpsError> {
+        Self::read(&mut r)
+    }
+
+    fn read<R: BitRead>(r: &mut R) -> Result<SpsHeader, SpsError> {
+        let sps_video_parameter_set_id = r.read_u8(4, "sps_video_parameter_set_id")?;
+        let sps_max_sub_layers_minus1 = r.read_u8(3, "sps_max_sub_layers_minus1")?;
+        let sps_temporal_id_nesting = r.read_bool("sps_temporal_id_nesting_flag")?;
+        let profile_tier_level = read_structure(r, "profile_tier_level", |r| {
+            ProfileTierLevel::read(r, true, sps_max_sub_layers_minus1)
+        })?;
+        Ok(SpsHeader {
+            sps_video_parameter_set_id: ParamSetId::from_u32(sps_video_parameter_set_id.into())
+                .map_err(SpsError::BadVideoParamSetId)?,
+            sps_max_sub_layers_minus1,
+            sps_temporal_id_nesting,
+            profile_tier_level,
+            sps_seq_parameter_set_id: ParamSetId::from_u32(r.read_ue("seq_parameter_set_id")?)
+                .map_err(SpsError::BadSeqParamSetId)?,
+            chroma_info: ChromaInfo::read(r)?,
+            pic_width_in_luma_samples: r.read_ue("pic_width_in_luma_samples")?,
+            pic_height_in_luma_samples: r.read_ue("pic_height_in_luma_samples")?,
+            conformance_window: Window::read(r)?,
+            bit_depth_luma_minus8: r.read_ue("bit_depth_luma_minus8")?,
+            bit_depth_chroma_minus8: r.read_ue("bit_depth_chroma_minus8")?,
+        })
+    }
+
+    pub fn id(&self) -> SeqParamSetId {
+        self.sps_seq_parameter_set_id
+    }
+
+    pub fn general_level(&self) -> Level {
+        Level::from_level_idc(self.profile_tier_level.general_level_idc)
+    }
+
+    pub fn general_layer_profile(&self) -> &LayerProfile {
+        self.profile_tier_level
+            .general_profile
+            .as_ref()
+            .expect("SPS always has general profile")
+    }
+
+    pub fn general_tier(&self) -> Tier {
+        self.general_layer_profile().tier()
+    }
+
+    /// Return the "lowest" compatible profile. A stream may conform to multiple profiles.
+    pub fn general_profile(&self) -> Profile {
+        self.general_layer_profile().profile()
+    }
+
+    /// The picture size after cropping to the conformance window, as
+    /// [`SeqParameterSet::pixel_dimensions`].
+    pub fn pixel_dimensions(&self) -> Result<(u32, u32), SpsError> {
+        cropped_dimensions(
+            &self.chroma_info,
+            self.pic_width_in_luma_samples,
+            self.pic_height_in_luma_samples,
+            self.conformance_window.as_ref(),
+        )
+    }
+}
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct SeqParameterSet {
     pub sps_video_parameter_set_id: VideoParamSetId,
     pub sps_max_sub_layers_minus1: u8,
@@ -1575,7 +2744,8 @@ pub struct SeqParameterSet {
     pub bit_depth_luma_minus8: u32,
     pub bit_depth_chroma_minus8: u32,
     pub log2_max_pic_order_cnt_lsb_minus4: u32,
-    pub sub_layering_ordering_info: Vec<LayerInfo>,
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzzing::array_vec))]
+    pub sub_layering_ordering_info: ArrayVec<LayerInfo, MAX_SUB_LAYERS>,
     pub log2_min_luma_coding_block_size_minus3: u32,
     pub log2_diff_max_min_luma_coding_block_size: u32,
     pub log2_min_luma_transform_block_size_minus2: u32,
@@ -1586,8 +2756,10 @@ pub struct SeqParameterSet {
     pub amp_enabled: bool,
     pub sample_adaptive_offset_enabled: bool,
     pub pcm: Option<Pcm>,
-    pub st_ref_pic_sets: Vec<ShortTermRefPicSet>,
-    pub long_term_ref_pics_sps: Option<Vec<LongTermRefPicSps>>,
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzzing::array_vec))]
+    pub st_ref_pic_sets: ArrayVec<ShortTermRefPicSet, MAX_SHORT_TERM_REF_PIC_SETS>,
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzzing::option_array_vec))]
+    pub long_term_ref_pics_sps: Option<ArrayVec<LongTermRefPicSps, MAX_LONG_TERM_REF_PICS_SPS>>,
     pub sps_termporal_mvp_enabled: bool,
     pub strong_intra_smoothing_enabled: bool,
     pub vui_parameters: Option<VuiParameters>,
@@ -1595,27 +2767,38 @@ pub struct SeqParameterSet {
 }
 impl SeqParameterSet {
     pub fn from_bits<R: BitRead>(mut r: R) -> Result<SeqParameterSet, SpsError> {
-        let sps_video_parameter_set_id = r.read_u8(4, "sps_video_parameter_set_id")?;
-        let sps_max_sub_layers_minus1 = r.read_u8(3, "sps_max_sub_layers_minus1")?;
+        let SpsHeader {
+            sps_video_parameter_set_id,
+            sps_max_sub_layers_minus1,
+            sps_temporal_id_nesting,
+            profile_tier_level,
+            sps_seq_parameter_set_id,
+            chroma_info,
+            pic_width_in_luma_samples,
+            pic_height_in_luma_samples,
+            conformance_window,
+            bit_depth_luma_minus8,
+            bit_depth_chroma_minus8,
+        } = SpsHeader::read(&mut r)?;
+        let chroma_array_type = chroma_info.chroma_array_type();
+        let log2_max_pic_order_cnt_lsb_minus4 =
+            Self::read_log2_max_pic_order_cnt_lsb_minus4(&mut r)?;
+        let log2_max_pic_order_cnt_lsb = log2_max_pic_order_cnt_lsb_minus4 + 4;
 
         // TODO: should apply more max/min validations to many of those parameters
         let sps = SeqParameterSet {
-            sps_video_parameter_set_id: ParamSetId::from_u32(sps_video_parameter_set_id.into())
-                .map_err(SpsError::BadVideoParamSetId)?,
+            sps_video_parameter_set_id,
             sps_max_sub_layers_minus1,
-            sps_temporal_id_nesting: r.read_bool("sps_temporal_id_nesting_flag")?,
-
-            profile_tier_level: ProfileTierLevel::read(&mut r, true, sps_max_sub_layers_minus1)?, // check
-
-            sps_seq_parameter_set_id: ParamSetId::from_u32(r.read_ue("seq_parameter_set_id")?)
-                .map_err(SpsError::BadSeqParamSetId)?,
-            chroma_info: ChromaInfo::read(&mut r)?,
-            pic_width_in_luma_samples: r.read_ue("pic_width_in_luma_samples")?,
-            pic_height_in_luma_samples: r.read_ue("pic_height_in_luma_samples")?,
-            conformance_window: Window::read(&mut r)?,
-            bit_depth_luma_minus8: r.read_ue("bit_depth_luma_minus8")?,
-            bit_depth_chroma_minus8: r.read_ue("bit_depth_chroma_minus8")?,
-            log2_max_pic_order_cnt_lsb_minus4: r.read_ue("log2_max_pic_order_cnt_lsb_minus4")?,
+            sps_temporal_id_nesting,
+            profile_tier_level,
+            sps_seq_parameter_set_id,
+            chroma_info,
+            pic_width_in_luma_samples,
+            pic_height_in_luma_samples,
+            conformance_window,
+            bit_depth_luma_minus8,
+            bit_depth_chroma_minus8,
+            log2_max_pic_order_cnt_lsb_minus4,
             sub_layering_ordering_info: LayerInfo::read(&mut r, sps_max_sub_layers_minus1)?,
             log2_min_luma_coding_block_size_minus3: r
                 .read_ue("log2_min_luma_coding_block_size_minus3")?,
@@ -1634,11 +2817,16 @@ impl SeqParameterSet {
             sample_adaptive_offset_enabled: r.read_bool("sample_adaptive_offset_enabled")?,
             pcm: Pcm::read(&mut r)?,
             st_ref_pic_sets: ShortTermRefPicSet::read_with_count(&mut r)?,
-            long_term_ref_pics_sps: LongTermRefPicSps::read(&mut r)?,
+            long_term_ref_pics_sps: LongTermRefPicSps::read(&mut r, log2_max_pic_order_cnt_lsb)?,
             sps_termporal_mvp_enabled: r.read_bool("sps_termporal_mvp_enabled")?,
             strong_intra_smoothing_enabled: r.read_bool("strong_intra_smoothing_enabled")?,
             vui_parameters: VuiParameters::read(&mut r, true, sps_max_sub_layers_minus1)?,
-            sps_extension: SpsExtension::read(&mut r)?,
+            sps_extension: SpsExtension::read(
+                &mut r,
+                chroma_array_type,
+                bit_depth_luma_minus8,
+                bit_depth_chroma_minus8,
+            )?,
         };
         r.finish_rbsp()?;
         Ok(sps)
@@ -1668,77 +2856,74 @@ impl SeqParameterSet {
         self.general_layer_profile().profile()
     }
 
-    /*
-    fn read_log2_max_frame_num_minus4<R: BitRead>(r: &mut R) -> Result<u8, SpsError> {
-        let val = r.read_ue("log2_max_frame_num_minus4")?;
+    /// The RFC 6381 `codecs` parameter for this SPS, as specified in ISO/IEC 14496-15 Annex E,
+    /// with `sample_entry_type` being e.g. `hvc1` or `hev1`.
+    ///
+    /// ```
+    /// use hevc_reader::nal::sps::SeqParameterSet;
+    /// use hevc_reader::rbsp::{decode_nal, BitReader};
+    /// # let nal = [
+    /// #     0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03, 0x00, 0x00,
+    /// #     0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46, 0xd1, 0x2e,
+    /// #     0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10, 0x00, 0x00, 0x03,
+    /// #     0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00, 0x0b, 0xb8, 0x48,
+    /// # ];
+    /// let sps = SeqParameterSet::from_bits(BitReader::new(&*decode_nal(&nal).unwrap())).unwrap();
+    /// assert_eq!(sps.codecs_string("hvc1"), "hvc1.1.6.L93.B0");
+    /// ```
+    pub fn codecs_string(&self, sample_entry_type: &str) -> String {
+        let profile = self.general_layer_profile();
+        let profile_space = match profile.profile_space {
+            0 => "",
+            1 => "A",
+            2 => "B",
+            _ => "C",
+        };
+        // The compatibility flags are in reverse bit order, i.e. flag j in bit j.
+        let mut codecs = format!(
+            "{}.{}{}.{:X}.{}{}",
+            sample_entry_type,
+            profile_space,
+            profile.profile_idc,
+            profile.compatibility_flags().reverse_bits(),
+            if profile.tier_flag { 'H' } else { 'L' },
+            self.profile_tier_level.general_level_idc,
+        );
+        let flags = profile.constraint_indicator_flags();
+        let len = flags.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+        for b in &flags[..len] {
+            codecs.push_str(&format!(".{:X}", b));
+        }
+        codecs
+    }
+
+    fn read_log2_max_pic_order_cnt_lsb_minus4<R: BitRead>(r: &mut R) -> Result<u32, SpsError> {
+        let val = r.read_ue("log2_max_pic_order_cnt_lsb_minus4")?;
         if val > 12 {
-            Err(SpsError::Log2MaxFrameNumMinus4OutOfRange(val))
+            Err(SpsError::FieldValueTooLarge {
+                name: "log2_max_pic_order_cnt_lsb_minus4",
+                value: val,
+            })
         } else {
-            Ok(val as u8)
+            Ok(val)
         }
     }
 
-    /// returned value will be in the range 4 to 16 inclusive
-    pub fn log2_max_frame_num(&self) -> u8 {
-        self.log2_max_frame_num_minus4 + 4
+    /// `log2_max_pic_order_cnt_lsb_minus4 + 4`, the length in bits of `slice_pic_order_cnt_lsb`.
+    /// The returned value will be in the range 4 to 16 inclusive.
+    pub fn log2_max_pic_order_cnt_lsb(&self) -> u32 {
+        self.log2_max_pic_order_cnt_lsb_minus4 + 4
     }
-    */
 
     /// Helper to calculate the pixel-dimensions of the video image specified by this SPS, taking
     /// into account cropping (but not interlacing - yet).
     pub fn pixel_dimensions(&self) -> Result<(u32, u32), SpsError> {
-        let win = self.conformance_window.clone().unwrap_or_default();
-
-        let (sub_width_c, sub_height_c) = match self.chroma_info.chroma_format {
-            ChromaFormat::Monochrome => (1, 1),
-            ChromaFormat::YUV420 => (2, 2),
-            ChromaFormat::YUV422 => (2, 1),
-            ChromaFormat::YUV444 => (1, 1),
-            ChromaFormat::Invalid(idc) => {
-                return Err(SpsError::FieldValueTooLarge {
-                    name: "chroma_format_idc",
-                    value: idc,
-                });
-            }
-        };
-
-        let mut width = self.pic_width_in_luma_samples;
-        width = win
-            .win_left_offset
-            .checked_mul(sub_width_c)
-            .and_then(|offset| width.checked_sub(offset))
-            .ok_or(SpsError::FieldValueTooLarge {
-                name: "win_left_offset",
-                value: win.win_left_offset,
-            })?;
-        width = win
-            .win_right_offset
-            .checked_mul(sub_width_c)
-            .and_then(|offset| width.checked_sub(offset))
-            .ok_or(SpsError::FieldValueTooLarge {
-                name: "win_right_offset",
-                value: win.win_right_offset,
-            })?;
-
-        let mut height = self.pic_height_in_luma_samples;
-        height = win
-            .win_top_offset
-            .checked_mul(sub_height_c)
-            .and_then(|offset| height.checked_sub(offset))
-            .ok_or(SpsError::FieldValueTooLarge {
-                name: "win_top_offset",
-                value: win.win_top_offset,
-            })?;
-        height = win
-            .win_bottom_offset
-            .checked_mul(sub_height_c)
-            .and_then(|offset| height.checked_sub(offset))
-            .ok_or(SpsError::FieldValueTooLarge {
-                name: "win_bottom_offset",
-                value: win.win_bottom_offset,
-            })?;
-
-        Ok((width, height))
+        cropped_dimensions(
+            &self.chroma_info,
+            self.pic_width_in_luma_samples,
+            self.pic_height_in_luma_samples,
+            self.conformance_window.as_ref(),
+        )
     }
 
     pub fn fps(&self) -> Option<f64> {
@@ -1752,6 +2937,149 @@ impl SeqParameterSet {
         Some((timing_info.time_scale as f64) / (timing_info.num_units_in_tick as f64))
     }
 
+    /// The output frame duration of each temporal sub-layer, indexed by `TemporalId`, see
+    /// [`TimingInfo::sub_layer_frame_duration`].
+    ///
+    /// Decoding only the sub-layers up to and including index `i` gives pictures at the rate
+    /// of entry `i`, which describes the ladder available through temporal scalability.
+    pub fn sub_layer_frame_durations(&self) -> Vec<Option<FrameDuration>> {
+        let timing_info = self
+            .vui_parameters
+            .as_ref()
+            .and_then(|v| v.timing_info.as_ref());
+        (0..=usize::from(self.sps_max_sub_layers_minus1))
+            .map(|i| timing_info.and_then(|t| t.sub_layer_frame_duration(i)))
+            .collect()
+    }
+
+    /// The VUI with spec inferred defaults applied, also when the SPS has no VUI at all.
+    pub fn effective_vui(&self) -> EffectiveVui<'_> {
+        self.vui_parameters
+            .as_ref()
+            .map(VuiParameters::effective)
+            .unwrap_or_default()
+    }
+
+    /// `ChromaArrayType`, see [`ChromaInfo::chroma_array_type`].
+    pub fn chroma_array_type(&self) -> u32 {
+        self.chroma_info.chroma_array_type()
+    }
+
+    /// `SubWidthC` and `SubHeightC` from table 6-1.
+    ///
+    /// Monochrome and 4:4:4 are both 1x1, whether or not the colour planes are coded
+    /// separately.
+    pub fn sub_width_height_c(&self) -> Result<(u32, u32), SpsError> {
+        sub_width_height_c(&self.chroma_info)
+    }
+
+    /// Calculates the variables derived from this SPS in clause 7.4.3.2.1, which slice
+    /// address and tile calculations depend on.
+    ///
+    /// Returns an error if the coding block or transform block sizes are outside the
+    /// ranges allowed by the spec, as the derivations would otherwise overflow.
+    pub fn derived(&self) -> Result<DerivedSpsParams, SpsError> {
+        fn check(name: &'static str, value: u32, max: u32) -> Result<u32, SpsError> {
+            if value > max {
+                Err(SpsError::FieldValueTooLarge { name, value })
+            } else {
+                Ok(value)
+            }
+        }
+
+        // CtbLog2SizeY shall be in the range of 4 to 6, inclusive.
+        let min_cb_log2_size_y = check(
+            "log2_min_luma_coding_block_size_minus3",
+            self.log2_min_luma_coding_block_size_minus3,
+            3,
+        )? + 3;
+        let ctb_log2_size_y = check(
+            "log2_diff_max_min_luma_coding_block_size",
+            self.log2_diff_max_min_luma_coding_block_size,
+            6 - min_cb_log2_size_y,
+        )? + min_cb_log2_size_y;
+        if ctb_log2_size_y < 4 {
+            return Err(SpsError::FieldValueTooSmall {
+                name: "log2_diff_max_min_luma_coding_block_size",
+                value: self.log2_diff_max_min_luma_coding_block_size,
+            });
+        }
+        // MinTbLog2SizeY shall be less than MinCbLog2SizeY, and MaxTbLog2SizeY shall be
+        // less than or equal to Min( CtbLog2SizeY, 5 ).
+        let min_tb_log2_size_y = check(
+            "log2_min_luma_transform_block_size_minus2",
+            self.log2_min_luma_transform_block_size_minus2,
+            min_cb_log2_size_y - 3,
+        )? + 2;
+        let max_tb_log2_size_y = check(
+            "log2_diff_max_min_luma_transform_block_size",
+            self.log2_diff_max_min_luma_transform_block_size,
+            ctb_log2_size_y.min(5) - min_tb_log2_size_y,
+        )? + min_tb_log2_size_y;
+        let bit_depth_luma_minus8 = check("bit_depth_luma_minus8", self.bit_depth_luma_minus8, 8)?;
+        let bit_depth_chroma_minus8 =
+            check("bit_depth_chroma_minus8", self.bit_depth_chroma_minus8, 8)?;
+        let log2_max_pic_order_cnt_lsb_minus4 = check(
+            "log2_max_pic_order_cnt_lsb_minus4",
+            self.log2_max_pic_order_cnt_lsb_minus4,
+            12,
+        )?;
+
+        let (sub_width_c, sub_height_c) = self.sub_width_height_c()?;
+        let min_cb_size_y = 1 << min_cb_log2_size_y;
+        let ctb_size_y = 1 << ctb_log2_size_y;
+        let width = self.pic_width_in_luma_samples;
+        let height = self.pic_height_in_luma_samples;
+        let pic_width_in_min_cbs_y = width / min_cb_size_y;
+        let pic_height_in_min_cbs_y = height / min_cb_size_y;
+        let pic_width_in_ctbs_y = width.div_ceil(ctb_size_y);
+        let pic_height_in_ctbs_y = height.div_ceil(ctb_size_y);
+        let too_large = || SpsError::FieldValueTooLarge {
+            name: "pic_height_in_luma_samples",
+            value: height,
+        };
+        let pic_size_in_min_cbs_y = pic_width_in_min_cbs_y
+            .checked_mul(pic_height_in_min_cbs_y)
+            .ok_or_else(too_large)?;
+        let pic_size_in_ctbs_y = pic_width_in_ctbs_y
+            .checked_mul(pic_height_in_ctbs_y)
+            .ok_or_else(too_large)?;
+        let pic_size_in_samples_y = width.checked_mul(height).ok_or_else(too_large)?;
+        let no_chroma_arrays = self.chroma_array_type() == 0;
+        Ok(DerivedSpsParams {
+            min_cb_log2_size_y,
+            ctb_log2_size_y,
+            min_cb_size_y,
+            ctb_size_y,
+            pic_width_in_min_cbs_y,
+            pic_height_in_min_cbs_y,
+            pic_size_in_min_cbs_y,
+            pic_width_in_ctbs_y,
+            pic_height_in_ctbs_y,
+            pic_size_in_ctbs_y,
+            pic_size_in_samples_y,
+            pic_width_in_samples_c: width / sub_width_c,
+            pic_height_in_samples_c: height / sub_height_c,
+            ctb_width_c: if no_chroma_arrays {
+                0
+            } else {
+                ctb_size_y / sub_width_c
+            },
+            ctb_height_c: if no_chroma_arrays {
+                0
+            } else {
+                ctb_size_y / sub_height_c
+            },
+            min_tb_log2_size_y,
+            max_tb_log2_size_y,
+            bit_depth_y: 8 + bit_depth_luma_minus8,
+            bit_depth_c: 8 + bit_depth_chroma_minus8,
+            qp_bd_offset_y: 6 * bit_depth_luma_minus8,
+            qp_bd_offset_c: 6 * bit_depth_chroma_minus8,
+            max_pic_order_cnt_lsb: 1 << (log2_max_pic_order_cnt_lsb_minus4 + 4),
+        })
+    }
+
     fn validate_max_num_sub_layers_minus1(max_num_sub_layers_minus1: u8) -> Result<(), SpsError> {
         if max_num_sub_layers_minus1 > 7 {
             Err(SpsError::FieldValueTooLarge {
@@ -1764,6 +3092,231 @@ impl SeqParameterSet {
     }
 }
 
+impl SeqParameterSet {
+    /// Lists the fields that differ between `self` and `other`.
+    ///
+    /// Nested syntax structures present in both are compared field by field, so e.g. a
+    /// changed frame rate is reported as `vui_parameters.timing_info` rather than the VUI as a
+    /// whole. This answers why an encoder re-sent an SPS with the same id mid-stream.
+    ///
+    /// ```
+    /// # use hevc_reader::nal::sps::SeqParameterSet;
+    /// # use hevc_reader::rbsp::{decode_nal, BitReader};
+    /// # let byts = [
+    /// #     0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03, 0x00,
+    /// #     0x00, 0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46,
+    /// #     0xd1, 0x2e, 0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10,
+    /// #     0x00, 0x00, 0x03, 0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00,
+    /// #     0x0b, 0xb8, 0x48,
+    /// # ];
+    /// let old = SeqParameterSet::from_bits(BitReader::new(&*decode_nal(&byts).unwrap())).unwrap();
+    /// let mut new = old.clone();
+    /// new.pic_height_in_luma_samples = 480;
+    /// let diff = old.diff(&new);
+    /// assert_eq!(diff.len(), 1);
+    /// assert_eq!(diff[0].path, "pic_height_in_luma_samples");
+    /// assert_eq!((diff[0].old.as_str(), diff[0].new.as_str()), ("576", "480"));
+    /// ```
+    pub fn diff(&self, other: &SeqParameterSet) -> Vec<FieldDiff> {
+        let mut diffs = vec![];
+        diff_fields!(
+            diffs,
+            "",
+            self,
+            other,
+            [
+                sps_video_parameter_set_id,
+                sps_max_sub_layers_minus1,
+                sps_temporal_id_nesting,
+            ]
+        );
+        Self::diff_profile_tier_level(
+            &mut diffs,
+            &self.profile_tier_level,
+            &other.profile_tier_level,
+        );
+        diff_fields!(
+            diffs,
+            "",
+            self,
+            other,
+            [
+                sps_seq_parameter_set_id,
+                chroma_info,
+                pic_width_in_luma_samples,
+                pic_height_in_luma_samples,
+                conformance_window,
+                bit_depth_luma_minus8,
+                bit_depth_chroma_minus8,
+                log2_max_pic_order_cnt_lsb_minus4,
+                sub_layering_ordering_info,
+                log2_min_luma_coding_block_size_minus3,
+                log2_diff_max_min_luma_coding_block_size,
+                log2_min_luma_transform_block_size_minus2,
+                log2_diff_max_min_luma_transform_block_size,
+                max_transform_hierarchy_depth_inter,
+                max_transform_hierarchy_depth_intra,
+                scaling_list,
+                amp_enabled,
+                sample_adaptive_offset_enabled,
+                pcm,
+                st_ref_pic_sets,
+                long_term_ref_pics_sps,
+                sps_termporal_mvp_enabled,
+                strong_intra_smoothing_enabled,
+            ]
+        );
+        match (&self.vui_parameters, &other.vui_parameters) {
+            (Some(a), Some(b)) => diff_fields!(
+                diffs,
+                "vui_parameters.",
+                a,
+                b,
+                [
+                    aspect_ratio_info,
+                    overscan_appropriate,
+                    video_signal_type,
+                    chroma_loc_info,
+                    neutral_chroma_indication_flag,
+                    field_seq_flag,
+                    frame_field_info_present_flag,
+                    default_display_window,
+                    timing_info,
+                    bitstream_restrictions,
+                ]
+            ),
+            _ => diff_fields!(diffs, "", self, other, [vui_parameters]),
+        }
+        match (&self.sps_extension, &other.sps_extension) {
+            (Some(a), Some(b)) => diff_fields!(
+                diffs,
+                "sps_extension.",
+                a,
+                b,
+                [
+                    sps_range_extension,
+                    sps_multilayer_extension_flag,
+                    sps_3d_extension_flag,
+                    sps_scc_extension_flag,
+                    sps_scc_extension,
+                    sps_extension_4bits,
+                    extension_data
+                ]
+            ),
+            _ => diff_fields!(diffs, "", self, other, [sps_extension]),
+        }
+        diffs
+    }
+
+    fn diff_profile_tier_level(
+        diffs: &mut Vec<FieldDiff>,
+        a: &ProfileTierLevel,
+        b: &ProfileTierLevel,
+    ) {
+        match (&a.general_profile, &b.general_profile) {
+            (Some(pa), Some(pb)) => diff_fields!(
+                diffs,
+                "profile_tier_level.general_profile.",
+                pa,
+                pb,
+                [
+                    profile_space,
+                    tier_flag,
+                    profile_idc,
+                    profile_compatibility_flag,
+                    progressive_source_flag,
+                    interlaced_source_flag,
+                    non_packed_constraint_flag,
+                    frame_only_constraint_flag,
+                    max_14bit_constraint_flag,
+                    max_12bit_constraint_flag,
+                    max_10bit_constraint_flag,
+                    max_8bit_constraint_flag,
+                    max_422chroma_constraint_flag,
+                    max_420chroma_constraint_flag,
+                    max_monochrome_constraint_flag,
+                    intra_constraint_flag,
+                    one_picture_only_constraint_flag,
+                    lower_bit_rate_constraint_flag,
+                    inbld_flag,
+                ]
+            ),
+            _ => diff_fields!(diffs, "profile_tier_level.", a, b, [general_profile]),
+        }
+        diff_fields!(
+            diffs,
+            "profile_tier_level.",
+            a,
+            b,
+            [general_level_idc, sub_layers]
+        );
+    }
+}
+
+/// `SubWidthC` and `SubHeightC` from table 6-1.
+fn sub_width_height_c(chroma_info: &ChromaInfo) -> Result<(u32, u32), SpsError> {
+    match chroma_info.chroma_format {
+        ChromaFormat::Monochrome => Ok((1, 1)),
+        ChromaFormat::YUV420 => Ok((2, 2)),
+        ChromaFormat::YUV422 => Ok((2, 1)),
+        ChromaFormat::YUV444 => Ok((1, 1)),
+        ChromaFormat::Invalid(idc) => Err(SpsError::FieldValueTooLarge {
+            name: "chroma_format_idc",
+            value: idc,
+        }),
+    }
+}
+
+/// The picture size after cropping to the conformance window.
+fn cropped_dimensions(
+    chroma_info: &ChromaInfo,
+    pic_width_in_luma_samples: u32,
+    pic_height_in_luma_samples: u32,
+    window: Option<&Window>,
+) -> Result<(u32, u32), SpsError> {
+    let win = window.cloned().unwrap_or_default();
+
+    let (sub_width_c, sub_height_c) = sub_width_height_c(chroma_info)?;
+
+    let mut width = pic_width_in_luma_samples;
+    width = win
+        .win_left_offset
+        .checked_mul(sub_width_c)
+        .and_then(|offset| width.checked_sub(offset))
+        .ok_or(SpsError::FieldValueTooLarge {
+            name: "win_left_offset",
+            value: win.win_left_offset,
+        })?;
+    width = win
+        .win_right_offset
+        .checked_mul(sub_width_c)
+        .and_then(|offset| width.checked_sub(offset))
+        .ok_or(SpsError::FieldValueTooLarge {
+            name: "win_right_offset",
+            value: win.win_right_offset,
+        })?;
+
+    let mut height = pic_height_in_luma_samples;
+    height = win
+        .win_top_offset
+        .checked_mul(sub_height_c)
+        .and_then(|offset| height.checked_sub(offset))
+        .ok_or(SpsError::FieldValueTooLarge {
+            name: "win_top_offset",
+            value: win.win_top_offset,
+        })?;
+    height = win
+        .win_bottom_offset
+        .checked_mul(sub_height_c)
+        .and_then(|offset| height.checked_sub(offset))
+        .ok_or(SpsError::FieldValueTooLarge {
+            name: "win_bottom_offset",
+            value: win.win_bottom_offset,
+        })?;
+
+    Ok((width, height))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -1958,13 +3511,13 @@ mod test {
             bit_depth_luma_minus8: 0,
             bit_depth_chroma_minus8: 0,
             log2_max_pic_order_cnt_lsb_minus4: 1,
-            sub_layering_ordering_info: vec![
+            sub_layering_ordering_info: [
                 LayerInfo {
                     sps_max_dec_pic_buffering_minus1: 6,
                     sps_max_num_reorder_pics: 0,
                     sps_max_latency_increase_plus1: 0,
                 },
-            ],
+            ].into_iter().collect(),
             log2_min_luma_coding_block_size_minus3: 0,
             log2_diff_max_min_luma_coding_block_size: 2,
             log2_min_luma_transform_block_size_minus2: 0,
@@ -1975,16 +3528,16 @@ mod test {
             amp_enabled: true,
             sample_adaptive_offset_enabled: false,
             pcm: None,
-            st_ref_pic_sets: vec![
+            st_ref_pic_sets: [
                 ShortTermRefPicSet {
-                    negative_pics_s0: vec![ShortTermRef {
+                    negative_pics_s0: [ShortTermRef {
                         delta_poc_minus1: Some(0),
                         delta_poc: -1,
                         used_by_curr_pic_flag: true
-                    }],
-                    positive_pics_s1: vec![],
+                    }].into_iter().collect(),
+                    positive_pics_s1: ArrayVec::new(),
                 },
-            ],
+            ].into_iter().collect(),
             long_term_ref_pics_sps: None,
             sps_termporal_mvp_enabled: false,
             strong_intra_smoothing_enabled: false,
@@ -2026,7 +3579,7 @@ mod test {
                                             ),
                                         },
                                     ),
-                                    sub_layers: vec![
+                                    sub_layers: [
                                         SubLayerHrdParametersContainer {
                                             fixed_pic_rate_general_flag: true,
                                             fixed_pic_rate_within_cvs_flag: true,
@@ -2034,18 +3587,18 @@ mod test {
                                             low_delay_hrd_flag: false,
                                             cpb_cnt_minus1: 0,
                                             nal_hrd_parameters: Some(
-                                                vec![
+                                                [
                                                     SubLayerHrdParameters {
                                                         bit_rate_value_minus1: 18749,
                                                         cpb_size_value_minus1: 5999,
                                                         sub_pic_hrd_params: None,
                                                         cbr_flag: true,
                                                     },
-                                                ],
+                                                ].into_iter().collect(),
                                             ),
                                             vcl_hrd_parameters: None,
                                         },
-                                    ],
+                                    ].into_iter().collect(),
                                 },
                             ),
                         },
@@ -2177,13 +3730,13 @@ mod test {
             bit_depth_luma_minus8: 0,
             bit_depth_chroma_minus8: 0,
             log2_max_pic_order_cnt_lsb_minus4: 6,
-            sub_layering_ordering_info: vec![
+            sub_layering_ordering_info: [
                 LayerInfo {
                     sps_max_dec_pic_buffering_minus1: 1,
                     sps_max_num_reorder_pics: 1,
                     sps_max_latency_increase_plus1: 0,
                 },
-            ],
+            ].into_iter().collect(),
             log2_min_luma_coding_block_size_minus3: 0,
             log2_diff_max_min_luma_coding_block_size: 2,
             log2_min_luma_transform_block_size_minus2: 0,
@@ -2196,7 +3749,7 @@ mod test {
             amp_enabled: false,
             sample_adaptive_offset_enabled: false,
             pcm: None,
-            st_ref_pic_sets: vec![],
+            st_ref_pic_sets: ArrayVec::new(),
             long_term_ref_pics_sps: None,
             sps_termporal_mvp_enabled: true,
             strong_intra_smoothing_enabled: true,
@@ -2252,7 +3805,7 @@ mod test {
                                             ),
                                         },
                                     ),
-                                    sub_layers: vec![
+                                    sub_layers: [
                                         SubLayerHrdParametersContainer {
                                             fixed_pic_rate_general_flag: false,
                                             fixed_pic_rate_within_cvs_flag: false,
@@ -2261,17 +3814,17 @@ mod test {
                                             cpb_cnt_minus1: 0,
                                             nal_hrd_parameters: None,
                                             vcl_hrd_parameters: Some(
-                                                vec![
+                                                [
                                                     SubLayerHrdParameters {
                                                         bit_rate_value_minus1: 46874,
                                                         cpb_size_value_minus1: 384374,
                                                         sub_pic_hrd_params: None,
                                                         cbr_flag: true,
                                                     },
-                                                ],
+                                                ].into_iter().collect(),
                                             ),
                                         },
-                                    ],
+                                    ].into_iter().collect(),
                                 },
                             ),
                         },
@@ -2406,13 +3959,13 @@ mod test {
             bit_depth_luma_minus8: 0,
             bit_depth_chroma_minus8: 0,
             log2_max_pic_order_cnt_lsb_minus4: 8,
-            sub_layering_ordering_info: vec![
+            sub_layering_ordering_info: [
                 LayerInfo {
                     sps_max_dec_pic_buffering_minus1: 3,
                     sps_max_num_reorder_pics: 0,
                     sps_max_latency_increase_plus1: 0,
                 },
-            ],
+            ].into_iter().collect(),
             log2_min_luma_coding_block_size_minus3: 0,
             log2_diff_max_min_luma_coding_block_size: 3,
             log2_min_luma_transform_block_size_minus2: 0,
@@ -2423,9 +3976,9 @@ mod test {
             amp_enabled: false,
             sample_adaptive_offset_enabled: true,
             pcm: None,
-            st_ref_pic_sets: vec![
+            st_ref_pic_sets: [
                 ShortTermRefPicSet {
-                    negative_pics_s0: vec![
+                    negative_pics_s0: [
                         ShortTermRef {
                             delta_poc_minus1: Some(3),
                             delta_poc: -4,
@@ -2441,11 +3994,11 @@ mod test {
                             delta_poc: -12,
                             used_by_curr_pic_flag: true,
                         },
-                    ],
-                    positive_pics_s1: vec![],
+                    ].into_iter().collect(),
+                    positive_pics_s1: ArrayVec::new(),
                 },
                 ShortTermRefPicSet {
-                    negative_pics_s0: vec![
+                    negative_pics_s0: [
                         ShortTermRef {
                             delta_poc_minus1: None,
                             delta_poc: -1,
@@ -2461,11 +4014,11 @@ mod test {
                             delta_poc: -9,
                             used_by_curr_pic_flag: false,
                         },
-                    ],
-                    positive_pics_s1: vec![],
+                    ].into_iter().collect(),
+                    positive_pics_s1: ArrayVec::new(),
                 },
                 ShortTermRefPicSet {
-                    negative_pics_s0: vec![
+                    negative_pics_s0: [
                         ShortTermRef {
                             delta_poc_minus1: None,
                             delta_poc: -2,
@@ -2481,11 +4034,11 @@ mod test {
                             delta_poc: -10,
                             used_by_curr_pic_flag: false,
                         },
-                    ],
-                    positive_pics_s1: vec![],
+                    ].into_iter().collect(),
+                    positive_pics_s1: ArrayVec::new(),
                 },
                 ShortTermRefPicSet {
-                    negative_pics_s0: vec![
+                    negative_pics_s0: [
                         ShortTermRef {
                             delta_poc_minus1: None,
                             delta_poc: -1,
@@ -2506,10 +4059,10 @@ mod test {
                             delta_poc: -11,
                             used_by_curr_pic_flag: false,
                         },
-                    ],
-                    positive_pics_s1: vec![],
+                    ].into_iter().collect(),
+                    positive_pics_s1: ArrayVec::new(),
                 },
-            ],
+            ].into_iter().collect(),
             long_term_ref_pics_sps: None,
             sps_termporal_mvp_enabled: true,
             strong_intra_smoothing_enabled: true,
@@ -2548,7 +4101,7 @@ mod test {
                                             ),
                                         },
                                     ),
-                                    sub_layers: vec![
+                                    sub_layers: [
                                         SubLayerHrdParametersContainer {
                                             fixed_pic_rate_general_flag: true,
                                             fixed_pic_rate_within_cvs_flag: true,
@@ -2556,18 +4109,18 @@ mod test {
                                             low_delay_hrd_flag: false,
                                             cpb_cnt_minus1: 0,
                                             nal_hrd_parameters: Some(
-                                                vec![
+                                                [
                                                     SubLayerHrdParameters {
                                                         bit_rate_value_minus1: 15624,
                                                         cpb_size_value_minus1: 15624,
                                                         sub_pic_hrd_params: None,
                                                         cbr_flag: false,
                                                     },
-                                                ],
+                                                ].into_iter().collect(),
                                             ),
                                             vcl_hrd_parameters: None,
                                         },
-                                    ],
+                                    ].into_iter().collect(),
                                 },
                             ),
                         },
@@ -2591,5 +4144,736 @@ mod test {
         assert_eq!(width, width2);
         assert_eq!(height, height2);
         assert_eq!(fps, sps2.fps().unwrap());
+        // Writing back the parsed VUI reproduces the NAL unit exactly.
+        #[cfg(feature = "write")]
+        assert_eq!(
+            crate::rewrite::vui::SpsVuiEditor::new(&byts)
+                .unwrap()
+                .to_nal(),
+            byts
+        );
+    }
+
+    #[test]
+    fn derived_params() {
+        // "Intinor HW encode 720x576p" from above.
+        let byts = [
+            0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03, 0x00,
+            0x00, 0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46,
+            0xd1, 0x2e, 0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10,
+            0x00, 0x00, 0x03, 0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00,
+            0x0b, 0xb8, 0x48,
+        ];
+        let sps_rbsp = decode_nal(&byts).unwrap();
+        let sps = SeqParameterSet::from_bits(BitReader::new(&*sps_rbsp)).unwrap();
+        let d = sps.derived().unwrap();
+        assert_eq!(d.min_cb_size_y, 8);
+        assert_eq!(d.ctb_size_y, 32);
+        assert_eq!(
+            (d.pic_width_in_min_cbs_y, d.pic_height_in_min_cbs_y),
+            (92, 72)
+        );
+        assert_eq!((d.pic_width_in_ctbs_y, d.pic_height_in_ctbs_y), (23, 18));
+        assert_eq!(d.pic_size_in_ctbs_y, 414);
+        assert_eq!(
+            (d.pic_width_in_samples_c, d.pic_height_in_samples_c),
+            (368, 288)
+        );
+        assert_eq!((d.ctb_width_c, d.ctb_height_c), (16, 16));
+        assert_eq!((d.min_tb_log2_size_y, d.max_tb_log2_size_y), (2, 5));
+        assert_eq!((d.bit_depth_y, d.qp_bd_offset_y), (8, 0));
+        assert_eq!(d.max_pic_order_cnt_lsb, 32);
+
+        let mut bad = sps.clone();
+        bad.log2_diff_max_min_luma_coding_block_size = 4;
+        assert!(matches!(
+            bad.derived(),
+            Err(SpsError::FieldValueTooLarge {
+                name: "log2_diff_max_min_luma_coding_block_size",
+                value: 4
+            })
+        ));
+        // 8x8 CTBs are below the minimum of 16x16.
+        let mut bad = sps.clone();
+        bad.log2_diff_max_min_luma_coding_block_size = 0;
+        assert!(matches!(
+            bad.derived(),
+            Err(SpsError::FieldValueTooSmall {
+                name: "log2_diff_max_min_luma_coding_block_size",
+                value: 0
+            })
+        ));
+        // 32x32 transform blocks don't fit in 16x16 CTBs.
+        let mut bad = sps.clone();
+        bad.log2_diff_max_min_luma_coding_block_size = 1;
+        assert!(matches!(
+            bad.derived(),
+            Err(SpsError::FieldValueTooLarge {
+                name: "log2_diff_max_min_luma_transform_block_size",
+                value: 3
+            })
+        ));
+
+        // Too many minimum coding blocks for u32.
+        let mut bad = sps;
+        bad.pic_width_in_luma_samples = 0xffff_fff8;
+        bad.pic_height_in_luma_samples = 0xffff_fff8;
+        assert!(matches!(
+            bad.derived(),
+            Err(SpsError::FieldValueTooLarge {
+                name: "pic_height_in_luma_samples",
+                value: 0xffff_fff8
+            })
+        ));
+    }
+
+    #[test]
+    fn sub_layer_frame_durations() {
+        // "Intinor HW encode 720x576p" from above: a single sub-layer at a fixed 25 fps.
+        let byts = [
+            0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03, 0x00,
+            0x00, 0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46,
+            0xd1, 0x2e, 0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10,
+            0x00, 0x00, 0x03, 0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00,
+            0x0b, 0xb8, 0x48,
+        ];
+        let sps_rbsp = decode_nal(&byts).unwrap();
+        let mut sps = SeqParameterSet::from_bits(BitReader::new(&*sps_rbsp)).unwrap();
+        let durations = sps.sub_layer_frame_durations();
+        assert_eq!(
+            durations,
+            vec![Some(FrameDuration {
+                duration: 1,
+                time_scale: 25
+            })]
+        );
+        assert_eq!(durations[0].unwrap().fps(), 25.0);
+
+        // Three sub-layers, where the upper two have a fixed rate of 60 and 120 fps.
+        sps.sps_max_sub_layers_minus1 = 2;
+        let timing_info = sps
+            .vui_parameters
+            .as_mut()
+            .unwrap()
+            .timing_info
+            .as_mut()
+            .unwrap();
+        timing_info.time_scale = 120;
+        let hrd = timing_info.hrd_parameters.as_mut().unwrap();
+        let fixed = |elemental_duration_in_tc_minus1| SubLayerHrdParametersContainer {
+            fixed_pic_rate_general_flag: true,
+            fixed_pic_rate_within_cvs_flag: true,
+            elemental_duration_in_tc_minus1,
+            ..Default::default()
+        };
+        hrd.sub_layers = [
+            SubLayerHrdParametersContainer::default(),
+            fixed(1),
+            fixed(0),
+        ]
+        .into_iter()
+        .collect();
+        let fps: Vec<_> = sps
+            .sub_layer_frame_durations()
+            .iter()
+            .map(|d| d.map(|d| d.fps()))
+            .collect();
+        assert_eq!(fps, vec![None, Some(60.0), Some(120.0)]);
+    }
+
+    #[test]
+    fn header_of_truncated_sps() {
+        // "Intinor HW encode 720x576p" from above, cut off before the sub-layer ordering info.
+        let byts = [
+            0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03, 0x00,
+            0x00, 0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46,
+            0xd1, 0x2e, 0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10,
+            0x00, 0x00, 0x03, 0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00,
+            0x0b, 0xb8, 0x48,
+        ];
+        let sps = SeqParameterSet::from_bits(BitReader::from_nal(&byts)).unwrap();
+        let header = SpsHeader::from_bits(BitReader::from_nal(&byts[..28])).unwrap();
+        assert_eq!(header.id(), sps.id());
+        assert_eq!(header.profile_tier_level, sps.profile_tier_level);
+        assert_eq!(header.chroma_info, sps.chroma_info);
+        assert_eq!(header.general_level(), Level::L3_1);
+        assert_eq!(
+            header.pixel_dimensions().unwrap(),
+            sps.pixel_dimensions().unwrap()
+        );
+        assert!(SeqParameterSet::from_bits(BitReader::from_nal(&byts[..28])).is_err());
+    }
+
+    #[test]
+    fn effective_vui() {
+        // "Intinor HW encode 720x576p" from above: VUI with timing but no video signal type
+        // nor bitstream restrictions.
+        let byts = [
+            0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03, 0x00,
+            0x00, 0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46,
+            0xd1, 0x2e, 0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10,
+            0x00, 0x00, 0x03, 0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00,
+            0x0b, 0xb8, 0x48,
+        ];
+        let sps_rbsp = decode_nal(&byts).unwrap();
+        let mut sps = SeqParameterSet::from_bits(BitReader::new(&*sps_rbsp)).unwrap();
+        let vui = sps.effective_vui();
+        assert_eq!(vui.video_format, VideoFormat::Unspecified);
+        assert_eq!(vui.colour_description.colour_primaries, 2);
+        assert!(
+            vui.bitstream_restrictions
+                .motion_vectors_over_pic_boundaries_flag
+        );
+        assert_eq!(vui.bitstream_restrictions.max_bytes_per_pic_denom, 2);
+        assert_eq!(vui.timing_info.unwrap().time_scale, 25);
+
+        sps.vui_parameters = None;
+        assert_eq!(sps.effective_vui(), EffectiveVui::default());
+    }
+
+    #[test]
+    #[cfg(feature = "write")]
+    fn low_delay_hrd_cpb_cnt() {
+        // fixed_pic_rate_general_flag 0, fixed_pic_rate_within_cvs_flag 0, low_delay_hrd_flag 1,
+        // then a single NAL CPB specification without cpb_cnt_minus1.
+        let data = [0b0011_1100];
+        let mut r = BitReader::new(&data[..]);
+        let sub_layer = SubLayerHrdParametersContainer::read(&mut r, true, false, false).unwrap();
+        assert!(sub_layer.low_delay_hrd_flag);
+        assert!(!sub_layer.fixed_pic_rate_within_cvs_flag);
+        assert_eq!(sub_layer.cpb_cnt(), 1);
+        assert_eq!(sub_layer.nal_hrd_parameters.unwrap().len(), 1);
+
+        // fixed_pic_rate_general_flag 0, fixed_pic_rate_within_cvs_flag 0, low_delay_hrd_flag 0
+        // and cpb_cnt_minus1 32.
+        let mut w = BitWriter::new();
+        w.write_u8(3, 0);
+        w.write_ue(32);
+        w.write_rbsp_trailing_bits();
+        let data = w.into_bytes();
+        assert!(matches!(
+            SubLayerHrdParametersContainer::read(
+                &mut BitReader::new(&data[..]),
+                true,
+                false,
+                false
+            ),
+            Err(SpsError::CpbCountOutOfRange(32))
+        ));
+    }
+
+    #[test]
+    fn multiple_sub_layers() {
+        // 64x64 Main profile with sps_max_sub_layers_minus1 2, and
+        // sps_sub_layer_ordering_info_present_flag 0.
+        let byts = [
+            0x42, 0x01, 0x05, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03, 0x00,
+            0x00, 0x03, 0x00, 0x5d, 0x00, 0x00, 0xa0, 0x20, 0x81, 0x05, 0x94, 0x57, 0x92, 0x4c,
+            0x20, 0x80,
+        ];
+        let sps_rbsp = decode_nal(&byts).unwrap();
+        let sps = SeqParameterSet::from_bits(BitReader::new(&*sps_rbsp)).unwrap();
+        assert_eq!(sps.sps_max_sub_layers_minus1, 2);
+        assert_eq!(sps.pixel_dimensions().unwrap(), (64, 64));
+        let highest = LayerInfo {
+            sps_max_dec_pic_buffering_minus1: 4,
+            sps_max_num_reorder_pics: 2,
+            sps_max_latency_increase_plus1: 0,
+        };
+        assert_eq!(
+            sps.sub_layering_ordering_info[..],
+            [highest.clone(), highest.clone(), highest]
+        );
+    }
+
+    #[test]
+    fn sub_layer_ordering_info_present() {
+        // sps_sub_layer_ordering_info_present_flag 1, then (0, 0, 0), (1, 0, 0) and (2, 1, 0)
+        let data = [0b1111_0101, 0b1011_0101];
+        let mut r = BitReader::new(&data[..]);
+        let layers = LayerInfo::read(&mut r, 2).unwrap();
+        let dpb: Vec<_> = layers
+            .iter()
+            .map(|l| {
+                (
+                    l.sps_max_dec_pic_buffering_minus1,
+                    l.sps_max_num_reorder_pics,
+                )
+            })
+            .collect();
+        assert_eq!(dpb, vec![(0, 0), (1, 0), (2, 1)]);
+    }
+
+    #[test]
+    fn compatible_profiles() {
+        let mut profile = LayerProfile {
+            profile_idc: 1,
+            ..Default::default()
+        };
+        profile.profile_compatibility_flag[1] = true;
+        profile.profile_compatibility_flag[2] = true;
+        assert_eq!(
+            profile.compatible_profiles(),
+            vec![Profile::Main, Profile::Main10]
+        );
+        assert_eq!(profile.profile(), Profile::Main);
+
+        // Format range extensions with constraint flags matching no known profile are left out.
+        profile.profile_compatibility_flag[4] = true;
+        assert_eq!(
+            profile.compatible_profiles(),
+            vec![Profile::Main, Profile::Main10]
+        );
+
+        let unknown = LayerProfile {
+            profile_idc: 31,
+            ..Default::default()
+        };
+        assert_eq!(unknown.compatible_profiles(), vec![]);
+        assert_eq!(unknown.profile(), Profile::Unknown(31));
+    }
+
+    #[test]
+    fn profile_signal() {
+        let mut profile = LayerProfile {
+            profile_idc: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            profile.profile_signal(),
+            ProfileSignal::Known(Profile::Main)
+        );
+
+        // A future profile, compatible with Main
+        profile.profile_idc = 20;
+        assert_eq!(
+            profile.profile_signal(),
+            ProfileSignal::ReservedProfileIdc(20)
+        );
+        profile.profile_compatibility_flag[1] = true;
+        assert_eq!(
+            profile.profile_signal(),
+            ProfileSignal::Known(Profile::Main)
+        );
+
+        profile.profile_space = 1;
+        assert_eq!(
+            profile.profile_signal(),
+            ProfileSignal::ReservedProfileSpace {
+                profile_space: 1,
+                profile_idc: 20
+            }
+        );
+        assert_eq!(profile.profile(), Profile::Unknown(20));
+
+        // Format range extensions without constraint flags set
+        let rext = LayerProfile {
+            profile_idc: 4,
+            ..Default::default()
+        };
+        assert_eq!(
+            rext.profile_signal(),
+            ProfileSignal::UnrecognizedCombination { profile_idc: 4 }
+        );
+    }
+
+    #[test]
+    fn level_ordering() {
+        assert!(Level::L4_1 > Level::L4);
+        assert!(Level::L5 > Level::L4_1);
+        assert!(Level::L8_5 > Level::L6_2);
+        assert!(Level::Reserved(96) > Level::L3_1);
+        assert!(Level::Reserved(96) < Level::L4);
+        assert!(Level::L4_1.at_least(Level::L4_1));
+        assert!(!Level::L4.at_least(Level::L4_1));
+        for idc in 0..=255 {
+            assert_eq!(Level::from_level_idc(idc).level_idc(), idc);
+        }
+        assert!(Tier::Main < Tier::High);
+    }
+
+    #[test]
+    fn diff() {
+        // "Intinor HW encode 720x576p" from above.
+        let byts = [
+            0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03, 0x00,
+            0x00, 0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46,
+            0xd1, 0x2e, 0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10,
+            0x00, 0x00, 0x03, 0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00,
+            0x0b, 0xb8, 0x48,
+        ];
+        let sps_rbsp = decode_nal(&byts).unwrap();
+        let old = SeqParameterSet::from_bits(BitReader::new(&*sps_rbsp)).unwrap();
+        assert_eq!(old.diff(&old), vec![]);
+
+        let mut new = old.clone();
+        new.profile_tier_level.general_level_idc = 120;
+        new.profile_tier_level
+            .general_profile
+            .as_mut()
+            .unwrap()
+            .tier_flag = true;
+        new.vui_parameters.as_mut().unwrap().field_seq_flag = true;
+        new.sps_extension = Some(SpsExtension {
+            sps_range_extension: None,
+            sps_multilayer_extension_flag: false,
+            sps_3d_extension_flag: false,
+            sps_scc_extension_flag: false,
+            sps_scc_extension: None,
+            sps_extension_4bits: 0,
+            extension_data: None,
+        });
+        let paths: Vec<_> = old.diff(&new).into_iter().map(|d| d.path).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "profile_tier_level.general_profile.tier_flag",
+                "profile_tier_level.general_level_idc",
+                "vui_parameters.field_seq_flag",
+                "sps_extension",
+            ]
+        );
+    }
+
+    #[test]
+    fn scc_extension() {
+        // palette_mode_enabled_flag with palette_max_size 2, and
+        // motion_vector_resolution_control_idc 2.
+        let data = [0x5d, 0x00];
+        let ext = SpsSccExtension::read(&mut BitReader::new(&data[..]), 1, 0, 0).unwrap();
+        assert!(!ext.sps_curr_pic_ref_enabled_flag);
+        assert_eq!(
+            ext.palette_mode,
+            Some(PaletteMode {
+                palette_max_size: 2,
+                delta_palette_max_predictor_size: 0,
+                sps_palette_predictor_initializers: None,
+            })
+        );
+        assert_eq!(ext.motion_vector_resolution_control_idc, 2);
+        assert!(!ext.intra_boundary_filtering_disabled_flag);
+    }
+
+    #[test_case(ChromaFormat::Monochrome, false => 0; "monochrome")]
+    #[test_case(ChromaFormat::YUV420, false => 1; "4:2:0")]
+    #[test_case(ChromaFormat::YUV422, false => 2; "4:2:2")]
+    #[test_case(ChromaFormat::YUV444, false => 3; "4:4:4")]
+    #[test_case(ChromaFormat::YUV444, true => 0; "4:4:4 separate planes")]
+    fn chroma_array_type(chroma_format: ChromaFormat, separate_colour_plane_flag: bool) -> u32 {
+        ChromaInfo {
+            chroma_format,
+            separate_colour_plane_flag,
+        }
+        .chroma_array_type()
+    }
+
+    #[test]
+    fn separate_colour_planes() {
+        // "Intinor HW encode 720x576p" from above, made 4:4:4.
+        let byts = [
+            0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03, 0x00,
+            0x00, 0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46,
+            0xd1, 0x2e, 0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10,
+            0x00, 0x00, 0x03, 0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00,
+            0x0b, 0xb8, 0x48,
+        ];
+        let mut sps =
+            SeqParameterSet::from_bits(BitReader::new(&*decode_nal(&byts).unwrap())).unwrap();
+        sps.chroma_info.chroma_format = ChromaFormat::YUV444;
+        // The conformance window offsets are in chroma sample units, so the 8-unit right
+        // offset which crops 736 to 720 at 4:2:0 only crops 8 luma samples at 4:4:4.
+        assert_eq!(sps.pixel_dimensions().unwrap(), (728, 576));
+        let d = sps.derived().unwrap();
+        assert_eq!((d.ctb_width_c, d.ctb_height_c), (32, 32));
+        assert_eq!(
+            (d.pic_width_in_samples_c, d.pic_height_in_samples_c),
+            (736, 576)
+        );
+
+        // With separate colour planes, each plane is a monochrome picture at full resolution.
+        sps.chroma_info.separate_colour_plane_flag = true;
+        assert_eq!(sps.chroma_array_type(), 0);
+        assert_eq!(sps.sub_width_height_c().unwrap(), (1, 1));
+        assert_eq!(sps.pixel_dimensions().unwrap(), (728, 576));
+        let d = sps.derived().unwrap();
+        assert_eq!((d.ctb_width_c, d.ctb_height_c), (0, 0));
+        assert_eq!(
+            (d.pic_width_in_samples_c, d.pic_height_in_samples_c),
+            (736, 576)
+        );
+    }
+
+    /// Generated `vui_parameters()`, valid as far as the reader checks, are written and read
+    /// back unchanged.
+    #[cfg(feature = "write")]
+    mod vui_round_trip {
+        use super::*;
+        use crate::rbsp::BitWriter;
+        use proptest::collection::vec;
+        use proptest::option;
+        use proptest::prelude::*;
+
+        fn ue() -> std::ops::Range<u32> {
+            0..u32::MAX
+        }
+
+        fn aspect_ratio_info() -> impl Strategy<Value = AspectRatioInfo> {
+            (any::<u8>(), any::<u16>(), any::<u16>()).prop_map(|(idc, width, height)| {
+                let mut w = BitWriter::new();
+                w.write_bool(true);
+                w.write_u8(8, idc);
+                w.write_u16(16, width);
+                w.write_u16(16, height);
+                let bytes = w.into_bytes();
+                AspectRatioInfo::read(&mut BitReader::new(&bytes[..]))
+                    .unwrap()
+                    .unwrap()
+            })
+        }
+
+        fn overscan_appropriate() -> impl Strategy<Value = OverscanAppropriate> {
+            prop_oneof![
+                Just(OverscanAppropriate::Unspecified),
+                Just(OverscanAppropriate::Appropriate),
+                Just(OverscanAppropriate::Inappropriate),
+            ]
+        }
+
+        fn video_signal_type() -> impl Strategy<Value = VideoSignalType> {
+            let colour_description = (any::<u8>(), any::<u8>(), any::<u8>()).prop_map(
+                |(colour_primaries, transfer_characteristics, matrix_coeffs)| ColourDescription {
+                    colour_primaries,
+                    transfer_characteristics,
+                    matrix_coeffs,
+                },
+            );
+            (0..8u8, any::<bool>(), option::of(colour_description)).prop_map(
+                |(video_format, video_full_range_flag, colour_description)| VideoSignalType {
+                    video_format: VideoFormat::from(video_format),
+                    video_full_range_flag,
+                    colour_description,
+                },
+            )
+        }
+
+        fn window() -> impl Strategy<Value = Window> {
+            (ue(), ue(), ue(), ue()).prop_map(|(left, right, top, bottom)| Window {
+                win_left_offset: left,
+                win_right_offset: right,
+                win_top_offset: top,
+                win_bottom_offset: bottom,
+            })
+        }
+
+        fn common_inf() -> impl Strategy<Value = HrdParametersCommonInf> {
+            let sub_pic = (any::<u8>(), 0..32u8, any::<bool>(), 0..32u8, 0..16u8).prop_map(
+                |(tick, du_delay, in_pic_timing, du_output_delay, du_scale)| SubPicHrdParams {
+                    tick_divisor_minus2: tick,
+                    du_cpb_removal_delay_increment_length_minus1: du_delay,
+                    sub_pic_cpb_params_in_pic_timing_sei_flag: in_pic_timing,
+                    dpb_output_delay_du_length_minus1: du_output_delay,
+                    cpb_size_du_scale: du_scale,
+                },
+            );
+            let parameters = (
+                option::of(sub_pic),
+                0..16u8,
+                0..16u8,
+                0..32u8,
+                0..32u8,
+                0..32u8,
+            )
+                .prop_map(|(sub_pic, bit_rate, cpb_size, initial, au, dpb)| {
+                    HrdParametersCommonInfParameters {
+                        sub_pic_hrd_params: sub_pic,
+                        bit_rate_scale: bit_rate,
+                        cpb_size_scale: cpb_size,
+                        initial_cpb_removal_delay_length_minus1: initial,
+                        au_cpb_removal_delay_length_minus1: au,
+                        dpb_output_delay_length_minus1: dpb,
+                    }
+                });
+            (any::<bool>(), any::<bool>(), parameters).prop_map(|(nal, vcl, parameters)| {
+                HrdParametersCommonInf {
+                    nal_hrd_parameters_present_flag: nal,
+                    vcl_hrd_parameters_present_flag: vcl,
+                    parameters: (nal || vcl).then_some(parameters),
+                }
+            })
+        }
+
+        fn sub_layer(
+            common: &HrdParametersCommonInf,
+        ) -> impl Strategy<Value = SubLayerHrdParametersContainer> {
+            let sub_pic = common
+                .parameters
+                .as_ref()
+                .is_some_and(|p| p.sub_pic_hrd_params.is_some());
+            let params = (ue(), ue(), ue(), ue(), any::<bool>()).prop_map(
+                move |(bit_rate, cpb_size, cpb_size_du, bit_rate_du, cbr_flag)| {
+                    SubLayerHrdParameters {
+                        bit_rate_value_minus1: bit_rate,
+                        cpb_size_value_minus1: cpb_size,
+                        sub_pic_hrd_params: sub_pic.then_some(SubLayerSubPicHrdParams {
+                            cpb_size_du_value_minus1: cpb_size_du,
+                            bit_rate_du_value_minus1: bit_rate_du,
+                        }),
+                        cbr_flag,
+                    }
+                },
+            );
+            let nal = common.nal_hrd_parameters_present_flag;
+            let vcl = common.vcl_hrd_parameters_present_flag;
+            (
+                any::<bool>(),
+                any::<bool>(),
+                ue(),
+                any::<bool>(),
+                vec(params.clone(), 1..=MAX_CPB_CNT),
+                vec(params, MAX_CPB_CNT),
+            )
+                .prop_map(
+                    move |(
+                        general,
+                        within_cvs,
+                        elemental_duration,
+                        low_delay,
+                        nal_params,
+                        vcl_params,
+                    )| {
+                        let within_cvs = general || within_cvs;
+                        let low_delay = !within_cvs && low_delay;
+                        let cpb_cnt = if low_delay { 1 } else { nal_params.len() };
+                        let take = |params: Vec<_>| params.into_iter().take(cpb_cnt).collect();
+                        SubLayerHrdParametersContainer {
+                            fixed_pic_rate_general_flag: general,
+                            fixed_pic_rate_within_cvs_flag: within_cvs,
+                            elemental_duration_in_tc_minus1: if within_cvs {
+                                elemental_duration
+                            } else {
+                                0
+                            },
+                            low_delay_hrd_flag: low_delay,
+                            cpb_cnt_minus1: cpb_cnt as u32 - 1,
+                            nal_hrd_parameters: nal.then(|| take(nal_params)),
+                            vcl_hrd_parameters: vcl.then(|| take(vcl_params)),
+                        }
+                    },
+                )
+        }
+
+        fn hrd_parameters(max_sub_layers_minus1: u8) -> impl Strategy<Value = HrdParameters> {
+            common_inf().prop_flat_map(move |common| {
+                let sub_layers = vec(sub_layer(&common), usize::from(max_sub_layers_minus1) + 1);
+                sub_layers.prop_map(move |sub_layers| HrdParameters {
+                    common: Some(common.clone()),
+                    sub_layers: sub_layers.into_iter().collect(),
+                })
+            })
+        }
+
+        fn timing_info(max_sub_layers_minus1: u8) -> impl Strategy<Value = TimingInfo> {
+            (
+                any::<u32>(),
+                any::<u32>(),
+                option::of(ue()),
+                option::of(hrd_parameters(max_sub_layers_minus1)),
+            )
+                .prop_map(|(num_units_in_tick, time_scale, num_ticks, hrd)| {
+                    TimingInfo {
+                        num_units_in_tick,
+                        time_scale,
+                        num_ticks_poc_diff_one_minus1: num_ticks,
+                        hrd_parameters: hrd,
+                    }
+                })
+        }
+
+        fn bitstream_restrictions() -> impl Strategy<Value = BitstreamRestrictions> {
+            (
+                any::<[bool; 3]>(),
+                0..4096u32,
+                0..=16u32,
+                0..=16u32,
+                0..=15u32,
+                0..=15u32,
+            )
+                .prop_map(|([tiles, mvs, restricted], min_seg, bytes, bits, h, v)| {
+                    BitstreamRestrictions {
+                        tiles_fixed_structure_flag: tiles,
+                        motion_vectors_over_pic_boundaries_flag: mvs,
+                        restricted_ref_pic_lists_flag: restricted,
+                        min_spatial_segmentation_idc: min_seg,
+                        max_bytes_per_pic_denom: bytes,
+                        max_bits_per_mb_denom: bits,
+                        log2_max_mv_length_horizontal: h,
+                        log2_max_mv_length_vertical: v,
+                    }
+                })
+        }
+
+        /// A VUI and the `sps_max_sub_layers_minus1` its HRD parameters are sized for.
+        fn vui_parameters() -> impl Strategy<Value = (VuiParameters, u8)> {
+            (0..MAX_SUB_LAYERS as u8).prop_flat_map(|max_sub_layers_minus1| {
+                (
+                    option::of(aspect_ratio_info()),
+                    overscan_appropriate(),
+                    option::of(video_signal_type()),
+                    option::of((0..6u32, 0..6u32)),
+                    any::<[bool; 3]>(),
+                    option::of(window()),
+                    option::of(timing_info(max_sub_layers_minus1)),
+                    option::of(bitstream_restrictions()),
+                )
+                    .prop_map(
+                        move |(
+                            aspect,
+                            overscan,
+                            signal,
+                            loc,
+                            flags,
+                            window,
+                            timing,
+                            restrictions,
+                        )| {
+                            let [neutral_chroma, field_seq, frame_field_info] = flags;
+                            let vui = VuiParameters {
+                                aspect_ratio_info: aspect,
+                                overscan_appropriate: overscan,
+                                video_signal_type: signal,
+                                chroma_loc_info: loc.map(|(top, bottom)| ChromaLocInfo {
+                                    chroma_sample_loc_type_top_field: top,
+                                    chroma_sample_loc_type_bottom_field: bottom,
+                                }),
+                                neutral_chroma_indication_flag: neutral_chroma,
+                                field_seq_flag: field_seq,
+                                frame_field_info_present_flag: frame_field_info,
+                                default_display_window: window,
+                                timing_info: timing,
+                                bitstream_restrictions: restrictions,
+                            };
+                            (vui, max_sub_layers_minus1)
+                        },
+                    )
+            })
+        }
+
+        proptest! {
+            #[test]
+            fn write_then_read(
+                (vui, max_sub_layers_minus1) in vui_parameters(),
+                present in any::<bool>(),
+            ) {
+                let vui = present.then_some(vui);
+                let mut w = BitWriter::new();
+                VuiParameters::write(vui.as_ref(), &mut w);
+                w.write_rbsp_trailing_bits();
+                let bytes = w.into_bytes();
+                let mut r = BitReader::new(&bytes[..]);
+                let read = VuiParameters::read(&mut r, true, max_sub_layers_minus1).unwrap();
+                prop_assert_eq!(read, vui);
+                prop_assert!(r.finish_rbsp().is_ok());
+            }
+        }
     }
 }
@@ -0,0 +1,286 @@
+//! Video Parameter Set parsing, as specified in clause 7.3.2.1.
+
+use crate::{
+    nal::{
+        pps::{ParamSetId, ParamSetIdError},
+        sps::{
+            HrdParameters, LayerInfo, ProfileTierLevel, SpsError, VideoParamSetId, MAX_SUB_LAYERS,
+        },
+    },
+    rbsp::{read_structure, BitRead, BitReaderError, RawBits},
+};
+use arrayvec::ArrayVec;
+
+#[derive(Debug)]
+pub enum VpsError {
+    RbspReaderError(BitReaderError),
+    BadVideoParamSetId(ParamSetIdError),
+    /// A field in the bitstream had a value too large for a subsequent calculation
+    FieldValueTooLarge {
+        name: &'static str,
+        value: u32,
+    },
+    /// Error in syntax shared with the SPS (e.g. `profile_tier_level`)
+    SpsSyntax(SpsError),
+}
+impl VpsError {
+    /// See [`crate::error_code`].
+    pub fn code(&self) -> u32 {
+        crate::error_code::VPS
+            + match self {
+                VpsError::RbspReaderError(..) => 1,
+                VpsError::BadVideoParamSetId(..) => 2,
+                VpsError::FieldValueTooLarge { .. } => 3,
+                VpsError::SpsSyntax(..) => 4,
+            }
+    }
+}
+
+impl From<BitReaderError> for VpsError {
+    fn from(e: BitReaderError) -> Self {
+        VpsError::RbspReaderError(e)
+    }
+}
+
+impl From<SpsError> for VpsError {
+    fn from(e: SpsError) -> Self {
+        VpsError::SpsSyntax(e)
+    }
+}
+
+/// One `hrd_parameters()` entry of the VPS timing info, together with the layer set it
+/// applies to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct VpsHrdParameters {
+    pub hrd_layer_set_idx: u32,
+    pub cprms_present_flag: bool,
+    pub hrd_parameters: HrdParameters,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct VpsTimingInfo {
+    pub num_units_in_tick: u32,
+    pub time_scale: u32,
+    pub num_ticks_poc_diff_one_minus1: Option<u32>,
+    pub hrd_parameters: Vec<VpsHrdParameters>,
+}
+impl VpsTimingInfo {
+    fn read<R: BitRead>(
+        r: &mut R,
+        vps_base_layer_internal_flag: bool,
+        vps_num_layer_sets_minus1: u32,
+        vps_max_sub_layers_minus1: u8,
+    ) -> Result<Option<Self>, VpsError> {
+        if !r.read_bool("vps_timing_info_present_flag")? {
+            return Ok(None);
+        }
+        let num_units_in_tick = r.read_u32(32, "vps_num_units_in_tick")?;
+        let time_scale = r.read_u32(32, "vps_time_scale")?;
+        let num_ticks_poc_diff_one_minus1 = if r.read_bool("vps_poc_proportional_to_timing_flag")? {
+            Some(r.read_ue("vps_num_ticks_poc_diff_one_minus1")?)
+        } else {
+            None
+        };
+        let vps_num_hrd_parameters = r.read_ue("vps_num_hrd_parameters")?;
+        if vps_num_hrd_parameters > vps_num_layer_sets_minus1 + 1 {
+            return Err(VpsError::FieldValueTooLarge {
+                name: "vps_num_hrd_parameters",
+                value: vps_num_hrd_parameters,
+            });
+        }
+        let min_layer_set_idx = if vps_base_layer_internal_flag { 0 } else { 1 };
+        let mut hrd_parameters = Vec::with_capacity(vps_num_hrd_parameters as usize);
+        for i in 0..vps_num_hrd_parameters {
+            let hrd_layer_set_idx = r.read_ue("hrd_layer_set_idx")?;
+            if hrd_layer_set_idx < min_layer_set_idx
+                || hrd_layer_set_idx > vps_num_layer_sets_minus1
+            {
+                return Err(VpsError::FieldValueTooLarge {
+                    name: "hrd_layer_set_idx",
+                    value: hrd_layer_set_idx,
+                });
+            }
+            // cprms_present_flag[0] is inferred to be 1
+            let cprms_present_flag = i == 0 || r.read_bool("cprms_present_flag")?;
+            let previous_common = hrd_parameters
+                .last()
+                .and_then(|p: &VpsHrdParameters| p.hrd_parameters.common.as_ref());
+            let params = read_structure(r, "hrd_parameters", |r| {
+                HrdParameters::read_body(
+                    r,
+                    cprms_present_flag,
+                    previous_common,
+                    vps_max_sub_layers_minus1,
+                )
+            })?;
+            hrd_parameters.push(VpsHrdParameters {
+                hrd_layer_set_idx,
+                cprms_present_flag,
+                hrd_parameters: params,
+            });
+        }
+        Ok(Some(VpsTimingInfo {
+            num_units_in_tick,
+            time_scale,
+            num_ticks_poc_diff_one_minus1,
+            hrd_parameters,
+        }))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct VideoParameterSet {
+    pub vps_video_parameter_set_id: VideoParamSetId,
+    pub vps_base_layer_internal_flag: bool,
+    pub vps_base_layer_available_flag: bool,
+    pub vps_max_layers_minus1: u8,
+    pub vps_max_sub_layers_minus1: u8,
+    pub vps_temporal_id_nesting_flag: bool,
+    pub profile_tier_level: ProfileTierLevel,
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = crate::fuzzing::array_vec))]
+    pub sub_layer_ordering_info: ArrayVec<LayerInfo, MAX_SUB_LAYERS>,
+    pub vps_max_layer_id: u8,
+    /// `layer_id_included_flag[i][j]` for each layer set `i`, as a bit mask where bit `j`
+    /// is set if `nuh_layer_id` `j` is included. Layer set 0 only contains layer 0.
+    pub layer_sets: Vec<u64>,
+    pub timing_info: Option<VpsTimingInfo>,
+    /// The syntax following `vps_extension_flag`, if set: `vps_extension()` for multi-layer
+    /// streams and any `vps_extension_data_flag` bits, unparsed.
+    pub vps_extension: Option<RawBits>,
+}
+impl VideoParameterSet {
+    pub fn from_bits<R: BitRead>(mut r: R) -> Result<VideoParameterSet, VpsError> {
+        let vps_video_parameter_set_id =
+            ParamSetId::from_u32(r.read_u8(4, "vps_video_parameter_set_id")?.into())
+                .map_err(VpsError::BadVideoParamSetId)?;
+        let vps_base_layer_internal_flag = r.read_bool("vps_base_layer_internal_flag")?;
+        let vps_base_layer_available_flag = r.read_bool("vps_base_layer_available_flag")?;
+        let vps_max_layers_minus1 = r.read_u8(6, "vps_max_layers_minus1")?;
+        let vps_max_sub_layers_minus1 = r.read_u8(3, "vps_max_sub_layers_minus1")?;
+        let vps_temporal_id_nesting_flag = r.read_bool("vps_temporal_id_nesting_flag")?;
+        let _reserved = r.read_u16(16, "vps_reserved_0xffff_16bits")?;
+        let profile_tier_level = read_structure(&mut r, "profile_tier_level", |r| {
+            ProfileTierLevel::read(r, true, vps_max_sub_layers_minus1)
+        })?;
+        let sub_layer_ordering_info = LayerInfo::read(&mut r, vps_max_sub_layers_minus1)?;
+        let vps_max_layer_id = r.read_u8(6, "vps_max_layer_id")?;
+        let vps_num_layer_sets_minus1 = r.read_ue("vps_num_layer_sets_minus1")?;
+        if vps_num_layer_sets_minus1 > 1023 {
+            return Err(VpsError::FieldValueTooLarge {
+                name: "vps_num_layer_sets_minus1",
+                value: vps_num_layer_sets_minus1,
+            });
+        }
+        let mut layer_sets = Vec::with_capacity(vps_num_layer_sets_minus1 as usize + 1);
+        layer_sets.push(1);
+        for _ in 1..=vps_num_layer_sets_minus1 {
+            let mut included = 0u64;
+            for j in 0..=vps_max_layer_id {
+                if r.read_bool("layer_id_included_flag")? {
+                    included |= 1 << j;
+                }
+            }
+            layer_sets.push(included);
+        }
+        let timing_info = VpsTimingInfo::read(
+            &mut r,
+            vps_base_layer_internal_flag,
+            vps_num_layer_sets_minus1,
+            vps_max_sub_layers_minus1,
+        )?;
+        let vps_extension = if r.read_bool("vps_extension_flag")? {
+            // TODO: vps_extension() for multi-layer streams
+            Some(RawBits::read_to_trailing_bits(&mut r, "vps_extension")?)
+        } else {
+            None
+        };
+        r.finish_rbsp()?;
+        Ok(VideoParameterSet {
+            vps_video_parameter_set_id,
+            vps_base_layer_internal_flag,
+            vps_base_layer_available_flag,
+            vps_max_layers_minus1,
+            vps_max_sub_layers_minus1,
+            vps_temporal_id_nesting_flag,
+            profile_tier_level,
+            sub_layer_ordering_info,
+            vps_max_layer_id,
+            layer_sets,
+            timing_info,
+            vps_extension,
+        })
+    }
+
+    pub fn id(&self) -> VideoParamSetId {
+        self.vps_video_parameter_set_id
+    }
+
+    /// Frame rate from the VPS timing info, if present.
+    pub fn fps(&self) -> Option<f64> {
+        let timing_info = self.timing_info.as_ref()?;
+        Some((timing_info.time_scale as f64) / (timing_info.num_units_in_tick as f64))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::nal::sps::{SubLayerHrdParameters, SubLayerSubPicHrdParams};
+    use crate::rbsp::{decode_nal, BitReader};
+    use hex_literal::hex;
+
+    #[test]
+    fn x265_vps() {
+        let data = hex!("40 01 0c 01 ff ff 01 60 00 00 03 00 90 00 00 03 00 00 03 00 5d 95 98 09");
+        let vps =
+            VideoParameterSet::from_bits(BitReader::new(&*decode_nal(&data).unwrap())).unwrap();
+        assert_eq!(vps.id().id(), 0);
+        assert!(vps.vps_base_layer_internal_flag);
+        assert_eq!(vps.vps_max_sub_layers_minus1, 0);
+        assert_eq!(vps.profile_tier_level.general_level_idc, 93);
+        assert_eq!(vps.layer_sets, vec![1]);
+        assert_eq!(vps.timing_info, None);
+        assert_eq!(vps.fps(), None);
+    }
+
+    #[test]
+    fn inferred_hrd_common_info() {
+        // Two layer sets, each with hrd_parameters(). The second has cprms_present_flag 0, so
+        // it inherits the sub-picture HRD parameters of the first one, which sizes its CPB
+        // specifications.
+        let data = hex!(
+            "40 01 0c 01 ff ff 01 60 00 00 03 00 90 00 00 03 00 00 03 00 5d 95 c0 58 00 00 03 00
+             08 00 00 03 00 c9 ea c1 e9 12 3b de fd 32 16 9c c7 10 24 80"
+        );
+        let vps =
+            VideoParameterSet::from_bits(BitReader::new(&*decode_nal(&data).unwrap())).unwrap();
+        assert_eq!(vps.layer_sets, vec![1, 1]);
+        let timing_info = vps.timing_info.unwrap();
+        assert_eq!(timing_info.hrd_parameters.len(), 2);
+        let first = &timing_info.hrd_parameters[0];
+        let second = &timing_info.hrd_parameters[1];
+        assert!(first.cprms_present_flag);
+        assert!(!second.cprms_present_flag);
+        assert_eq!(second.hrd_layer_set_idx, 1);
+        assert_eq!(second.hrd_parameters.common, first.hrd_parameters.common);
+        let nal = second.hrd_parameters.sub_layers[0]
+            .nal_hrd_parameters
+            .as_ref()
+            .unwrap();
+        assert_eq!(
+            nal[0],
+            SubLayerHrdParameters {
+                bit_rate_value_minus1: 5,
+                cpb_size_value_minus1: 6,
+                sub_pic_hrd_params: Some(SubLayerSubPicHrdParams {
+                    cpb_size_du_value_minus1: 7,
+                    bit_rate_du_value_minus1: 8,
+                }),
+                cbr_flag: false,
+            }
+        );
+    }
+}
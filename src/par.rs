@@ -0,0 +1,552 @@
+//! Parallel parsing of whole streams with rayon, for analysis of long recordings. Requires the
+//! `rayon` feature.
+//!
+//! Parameter sets are parsed in stream order, as each may depend on the ones before it. Slice
+//! segment headers and SEI NAL units only depend on the parameter sets preceding them, so they
+//! are parsed in parallel, and the results passed to a [`ParsedNalHandler`] in stream order.
+//!
+//! [`nal_units`] finds the NAL units of an in-memory Annex B byte stream in parallel, for
+//! indexing large files. [`ParParser::parse_reader`] instead reads and splits the stream on a
+//! thread of its own, while the parsing proceeds.
+
+use std::io::Read;
+use std::sync::mpsc::SyncSender;
+
+use rayon::prelude::*;
+
+use crate::annexb::{AnnexBReader, ReadAdapter};
+use crate::nal::pps::{PicParamSetId, PicParameterSet, PpsError};
+use crate::nal::sei::SeiReader;
+use crate::nal::slice::{SliceHeaderError, SliceSegmentHeader};
+use crate::nal::sps::{SeqParamSetId, SeqParameterSet, SpsError, VideoParamSetId};
+use crate::nal::vps::{VideoParameterSet, VpsError};
+use crate::nal::{Nal, NalHeader, RefNal, UnitType};
+use crate::push::parse::{FormatChange, ParseDepth, ParsedNal, ParsedNalHandler, VideoFormat};
+use crate::push::{AccumulatedNalHandler, NalInterest};
+use crate::rbsp::{decode_nal, BitReaderError};
+use crate::Context;
+
+/// The size of the chunks [`nal_units`] scans in parallel.
+const SCAN_CHUNK_SIZE: usize = 1 << 20;
+
+/// The number of NAL units parsed at a time, bounding the memory held for results not yet
+/// passed to the handler.
+const BATCH_SIZE: usize = 1024;
+
+/// The number of batches of NAL units [`ParParser::parse_reader`] reads ahead of the parsing.
+const READ_AHEAD_BATCHES: usize = 2;
+
+/// Parses NAL units to the given [`ParseDepth`] on the rayon thread pool, giving the same
+/// results in the same order as a [`NalParser`](crate::push::parse::NalParser).
+///
+/// ```
+/// use hevc_reader::nal::NalHeader;
+/// use hevc_reader::par::ParParser;
+/// use hevc_reader::push::parse::{ParseDepth, ParsedNal};
+/// use hevc_reader::Context;
+/// let mut types = vec![];
+/// let mut parser = ParParser::new(ParseDepth::Headers);
+/// parser.parse_annexb(
+///     b"\x00\x00\x01\x46\x01\x10\x00\x00\x01\x26\x01\xac\x9c",
+///     &mut |_: &Context, header: NalHeader, _: ParsedNal<'_>| {
+///         types.push(header.nal_unit_type().id())
+///     },
+/// );
+/// assert_eq!(types, [35, 19]);
+/// ```
+pub struct ParParser {
+    depth: ParseDepth,
+    ctx: Context,
+    retain_bytes: bool,
+    format: Option<VideoFormat>,
+}
+impl ParParser {
+    pub fn new(depth: ParseDepth) -> Self {
+        ParParser {
+            depth,
+            ctx: Context::new(),
+            retain_bytes: false,
+            format: None,
+        }
+    }
+
+    /// Passes the bytes of each parameter set and SEI NAL unit to
+    /// [`ParsedNalHandler::nal_with_bytes`], as
+    /// [`NalParser::with_retained_bytes`](crate::push::parse::NalParser::with_retained_bytes).
+    pub fn with_retained_bytes(mut self) -> Self {
+        self.retain_bytes = true;
+        self
+    }
+
+    pub fn depth(&self) -> ParseDepth {
+        self.depth
+    }
+
+    /// The parameter sets seen so far.
+    pub fn context(&self) -> &Context {
+        &self.ctx
+    }
+
+    /// Adds parameter sets from outside the stream, e.g. from an `hvcC` box.
+    pub fn context_mut(&mut self) -> &mut Context {
+        &mut self.ctx
+    }
+
+    /// The format of the last picture parsed, as
+    /// [`NalParser::format`](crate::push::parse::NalParser::format).
+    pub fn format(&self) -> Option<&VideoFormat> {
+        self.format.as_ref()
+    }
+
+    /// Parses the NAL units of an in-memory Annex B byte stream.
+    pub fn parse_annexb<H: ParsedNalHandler>(&mut self, data: &[u8], handler: &mut H) {
+        let nals: Vec<_> = nal_units(data).into_iter().map(|(_, nal)| nal).collect();
+        self.parse_nals(&nals, handler);
+    }
+
+    /// Parses an Annex B byte stream read from `reader`, returning the number of bytes read.
+    ///
+    /// A scoped thread reads the input and splits it into batches of NAL units, while the
+    /// calling thread parses them on the rayon thread pool and calls `handler`. The reading
+    /// thread blocks when it gets a couple of batches ahead, so memory use is bounded however
+    /// fast the input is. A read error is returned once the complete NAL units before it are parsed.
+    pub fn parse_reader<R: Read + Send, H: ParsedNalHandler>(
+        &mut self,
+        reader: R,
+        handler: &mut H,
+    ) -> std::io::Result<u64> {
+        let (tx, rx) = std::sync::mpsc::sync_channel(READ_AHEAD_BATCHES);
+        std::thread::scope(|s| {
+            let scanner = s.spawn(move || {
+                let batcher = Batcher {
+                    batch: Vec::with_capacity(BATCH_SIZE),
+                    tx,
+                    disconnected: false,
+                };
+                let mut adapter = ReadAdapter::new(reader, AnnexBReader::accumulate(batcher));
+                let mut total = 0;
+                loop {
+                    let result = adapter.read_chunk();
+                    let batcher = adapter.reader_mut().nal_handler_mut();
+                    match result {
+                        Ok(0) | Err(_) => {
+                            batcher.send();
+                            return result.map(|_| total);
+                        }
+                        Ok(_) if batcher.disconnected => return Ok(total),
+                        Ok(n) => total += n as u64,
+                    }
+                }
+            });
+            for batch in rx {
+                let nals: Vec<&[u8]> = batch.iter().map(Vec::as_slice).collect();
+                self.parse_nals(&nals, handler);
+            }
+            scanner
+                .join()
+                .unwrap_or_else(|e| std::panic::resume_unwind(e))
+        })
+    }
+
+    /// Parses NAL units, given without start codes or length prefixes, in stream order.
+    pub fn parse_nals<H: ParsedNalHandler>(&mut self, nals: &[&[u8]], handler: &mut H) {
+        for batch in nals.chunks(BATCH_SIZE) {
+            self.parse_batch(batch, handler);
+        }
+    }
+
+    fn parse_batch<H: ParsedNalHandler>(&mut self, nals: &[&[u8]], handler: &mut H) {
+        let mut snapshots = Snapshots {
+            all: vec![std::mem::take(&mut self.ctx)],
+            last_used: false,
+        };
+        let param_sets = self.depth >= ParseDepth::ParamSets;
+        let mut units = Vec::with_capacity(nals.len());
+        for &nal in nals {
+            let header = match nal {
+                [b0, b1, ..] => NalHeader::new(*b0, Some(*b1)).ok(),
+                _ => None,
+            };
+            let Some(header) = header else {
+                units.push((None, 0, Parsed::Unparsed));
+                continue;
+            };
+            let r = RefNal::new(nal, &[], true);
+            let parsed = match header.nal_unit_type() {
+                UnitType::VideoParameterSet if param_sets => {
+                    Parsed::Vps(VideoParameterSet::from_bits(r.rbsp_bits()).map(|vps| {
+                        let id = vps.vps_video_parameter_set_id;
+                        if snapshots.last().vps_by_id(id) != Some(&vps) {
+                            snapshots.modify().put_video_param_set(vps);
+                        }
+                        id
+                    }))
+                }
+                UnitType::SeqParameterSet if param_sets => {
+                    Parsed::Sps(SeqParameterSet::from_bits(r.rbsp_bits()).map(|sps| {
+                        let id = sps.id();
+                        if snapshots.last().sps_by_id(id) != Some(&sps) {
+                            snapshots.modify().put_seq_param_set(sps);
+                        }
+                        id
+                    }))
+                }
+                UnitType::PicParameterSet if param_sets => Parsed::Pps(
+                    PicParameterSet::from_bits(snapshots.last(), r.rbsp_bits()).map(|pps| {
+                        let id = pps.pic_parameter_set_id;
+                        if snapshots.last().pps_by_id(id) != Some(&pps) {
+                            snapshots.modify().put_pic_param_set(pps);
+                        }
+                        id
+                    }),
+                ),
+                UnitType::PrefixSEI | UnitType::SuffixSEI if param_sets => Parsed::Pending,
+                t if t.is_vcl() && self.depth >= ParseDepth::SliceHeaders => Parsed::Pending,
+                _ => Parsed::Unparsed,
+            };
+            units.push((Some(header), snapshots.use_last(), parsed));
+        }
+        let mut snapshots = snapshots.all;
+
+        let units: Vec<_> = units
+            .into_par_iter()
+            .zip(nals)
+            .map(|((header, snapshot, parsed), nal)| {
+                let parsed = match (parsed, header) {
+                    (Parsed::Pending, Some(header)) if header.nal_unit_type().is_vcl() => {
+                        let mut r = RefNal::new(nal, &[], true).rbsp_bits();
+                        Parsed::Slice(
+                            SliceSegmentHeader::from_bits(&snapshots[snapshot], &mut r, header)
+                                .map(|(header, _, _)| header),
+                        )
+                    }
+                    (Parsed::Pending, _) => Parsed::Sei(
+                        decode_nal(nal)
+                            .map(|rbsp| rbsp.into_owned())
+                            .map_err(BitReaderError::ReaderError),
+                    ),
+                    (parsed, _) => parsed,
+                };
+                (header, snapshot, parsed)
+            })
+            .collect();
+
+        for ((header, snapshot, parsed), nal) in units.into_iter().zip(nals) {
+            let Some(header) = header else {
+                continue;
+            };
+            let ctx = &snapshots[snapshot];
+            let rbsp;
+            let parsed = match parsed {
+                Parsed::Pending | Parsed::Unparsed => ParsedNal::Unparsed,
+                Parsed::Vps(r) => {
+                    ParsedNal::VideoParameterSet(r.map(|id| ctx.vps_by_id(id).unwrap()))
+                }
+                Parsed::Sps(r) => {
+                    ParsedNal::SeqParameterSet(r.map(|id| ctx.sps_by_id(id).unwrap()))
+                }
+                Parsed::Pps(r) => {
+                    ParsedNal::PicParameterSet(r.map(|id| ctx.pps_by_id(id).unwrap()))
+                }
+                Parsed::Sei(Ok(bytes)) => {
+                    rbsp = bytes;
+                    ParsedNal::Sei(Ok(SeiReader::from_rbsp_bytes(&rbsp)))
+                }
+                Parsed::Sei(Err(e)) => ParsedNal::Sei(Err(e)),
+                Parsed::Slice(r) => ParsedNal::SliceSegment(r),
+            };
+            let format = match &parsed {
+                ParsedNal::SeqParameterSet(Ok(sps)) if self.depth == ParseDepth::ParamSets => {
+                    Some(VideoFormat::new(ctx, sps))
+                }
+                ParsedNal::SliceSegment(Ok(h)) if h.first_slice_segment_in_pic_flag => ctx
+                    .pps_by_id(h.slice_pic_parameter_set_id)
+                    .and_then(|pps| ctx.sps_by_id(pps.seq_parameter_set_id))
+                    .map(|sps| VideoFormat::new(ctx, sps)),
+                _ => None,
+            };
+            if let Some(change) = format.and_then(|f| FormatChange::update(&mut self.format, f)) {
+                handler.format_change(ctx, &change);
+            }
+            match parsed {
+                ParsedNal::VideoParameterSet(_)
+                | ParsedNal::SeqParameterSet(_)
+                | ParsedNal::PicParameterSet(_)
+                | ParsedNal::Sei(_)
+                    if self.retain_bytes =>
+                {
+                    handler.nal_with_bytes(ctx, header, parsed, nal)
+                }
+                _ => handler.nal(ctx, header, parsed),
+            }
+        }
+        self.ctx = snapshots.pop().unwrap();
+    }
+}
+
+/// Splits a complete, in-memory Annex B byte stream into its NAL units, as
+/// [`annexb::nal_units`](crate::annexb::nal_units) does, but finding the start codes of chunks
+/// of the input in parallel.
+///
+/// ```
+/// let data = b"\x00\x00\x00\x01\x40\x01\x0c\x00\x00\x01\x42\x01\x01\x00";
+/// let nals = hevc_reader::par::nal_units(&data[..]);
+/// assert_eq!(nals, vec![(4, &b"\x40\x01\x0c"[..]), (10, &b"\x42\x01\x01"[..])]);
+/// ```
+pub fn nal_units(data: &[u8]) -> Vec<(usize, &[u8])> {
+    nal_units_in_chunks(data, SCAN_CHUNK_SIZE)
+}
+
+fn nal_units_in_chunks(data: &[u8], chunk_size: usize) -> Vec<(usize, &[u8])> {
+    // The index just past each `00 00 01` start code. Start codes can't overlap, so each is
+    // found in the chunk holding its last byte, looking back into the previous chunk for the
+    // zeros.
+    let starts: Vec<usize> = (0..data.len().div_ceil(chunk_size))
+        .into_par_iter()
+        .flat_map_iter(|i| {
+            let from = i * chunk_size;
+            let to = std::cmp::min((i + 1) * chunk_size, data.len());
+            memchr::memchr_iter(0x01, &data[from..to])
+                .map(move |pos| from + pos)
+                .filter(|&pos| pos >= 2 && data[pos - 2] == 0x00 && data[pos - 1] == 0x00)
+                .map(|pos| pos + 1)
+        })
+        .collect();
+    starts
+        .par_iter()
+        .enumerate()
+        .filter_map(|(i, &start)| {
+            let end = starts.get(i + 1).map_or(data.len(), |next| next - 3);
+            let mut nal = &data[start..end];
+            while let [rest @ .., 0x00] = nal {
+                nal = rest;
+            }
+            (!nal.is_empty()).then_some((start, nal))
+        })
+        .collect()
+}
+
+/// Collects the NAL units read by [`ParParser::parse_reader`] into batches for the parsing
+/// thread.
+struct Batcher {
+    batch: Vec<Vec<u8>>,
+    tx: SyncSender<Vec<Vec<u8>>>,
+    /// The parsing thread has stopped, having panicked.
+    disconnected: bool,
+}
+impl Batcher {
+    fn send(&mut self) {
+        if !self.batch.is_empty() {
+            let batch = std::mem::replace(&mut self.batch, Vec::with_capacity(BATCH_SIZE));
+            self.disconnected |= self.tx.send(batch).is_err();
+        }
+    }
+}
+impl AccumulatedNalHandler for Batcher {
+    fn nal(&mut self, nal: RefNal<'_>) -> NalInterest {
+        if nal.is_complete() {
+            let mut bytes = vec![];
+            if nal.reader().read_to_end(&mut bytes).is_ok() {
+                self.batch.push(bytes);
+            }
+            if self.batch.len() == BATCH_SIZE {
+                self.send();
+            }
+        }
+        NalInterest::Buffer
+    }
+}
+
+/// The context as of each NAL unit of a batch is one of these snapshots, a new one sharing the
+/// unchanged parameter sets being taken when a parameter set changes after NAL units which refer
+/// to the last.
+struct Snapshots {
+    all: Vec<Context>,
+    last_used: bool,
+}
+impl Snapshots {
+    fn last(&self) -> &Context {
+        self.all.last().unwrap()
+    }
+
+    fn modify(&mut self) -> &mut Context {
+        if self.last_used {
+            self.all.push(self.last().clone());
+            self.last_used = false;
+        }
+        self.all.last_mut().unwrap()
+    }
+
+    /// The index of the last snapshot, which is now referred to.
+    fn use_last(&mut self) -> usize {
+        self.last_used = true;
+        self.all.len() - 1
+    }
+}
+
+/// A [`ParsedNal`] before it borrows from its context snapshot.
+// Not boxed, like `ParsedNal`.
+#[allow(clippy::large_enum_variant)]
+enum Parsed {
+    Unparsed,
+    /// A slice segment or SEI NAL unit, to be parsed in parallel.
+    Pending,
+    Vps(Result<VideoParamSetId, VpsError>),
+    Sps(Result<SeqParamSetId, SpsError>),
+    Pps(Result<PicParamSetId, PpsError>),
+    Sei(Result<Vec<u8>, BitReaderError>),
+    Slice(Result<SliceSegmentHeader, SliceHeaderError>),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::annexb::AnnexBReader;
+    use crate::push::parse::NalParser;
+    use hex_literal::hex;
+
+    #[test]
+    fn chunked_scan() {
+        // Start codes and trailing zeros straddle the chunk boundaries every which way.
+        let data = hex!(
+            "01 00 00 01 00 00 00 01 40 01 00 00 00 00 01 42 01 00 00 03 01 00 00 01 00 00 01 44
+             01 00 00 01 00 00"
+        );
+        let expected: Vec<_> = crate::annexb::nal_units(&data).collect();
+        assert_eq!(expected.len(), 3);
+        for chunk_size in 1..data.len() + 2 {
+            assert_eq!(nal_units_in_chunks(&data, chunk_size), expected);
+        }
+    }
+
+    /// Reads a few bytes at a time, then fails if `fail` is set.
+    struct Trickle<'a> {
+        data: &'a [u8],
+        fail: bool,
+    }
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.data.is_empty() && self.fail {
+                return Err(std::io::Error::other("broken pipe"));
+            }
+            let n = buf.len().min(self.data.len()).min(3);
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn reader() {
+        let stream = hex!(
+            "00 00 00 01 46 01 10 00 00 01 26 01 ac 9c 00 00 01 46 01 10 00 00 01 26 01 af 3c"
+        );
+        let mut expected = vec![];
+        ParParser::new(ParseDepth::Headers).parse_annexb(
+            &stream,
+            &mut |_: &Context, header: NalHeader, _: ParsedNal<'_>| expected.push(header),
+        );
+        assert_eq!(expected.len(), 4);
+        for fail in [false, true] {
+            let mut actual = vec![];
+            let result = ParParser::new(ParseDepth::Headers).parse_reader(
+                Trickle {
+                    data: &stream,
+                    fail,
+                },
+                &mut |_: &Context, header: NalHeader, _: ParsedNal<'_>| actual.push(header),
+            );
+            if fail {
+                assert_eq!(result.unwrap_err().to_string(), "broken pipe");
+                // The last NAL unit is never completed.
+                assert_eq!(actual, expected[..3]);
+            } else {
+                assert_eq!(result.unwrap(), stream.len() as u64);
+                assert_eq!(actual, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn same_as_nal_parser() {
+        // "Intinor HW encode 720x576p" SPS, its PPS, an SEI and an IDR slice segment, then a
+        // copy of the PPS with id 1, a slice segment using it, a repeat of the first PPS and a
+        // slice segment which fails to parse. Then the "Haivision 1080i25" SPS, changing the
+        // format, the first PPS again and an IDR slice segment.
+        let stream = hex!(
+            "00 00 01 42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71
+             3e 87 ee 46 d1 2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00
+             e0 00 49 3e 00 0b b8 48
+             00 00 01 44 01 c1 72 b4 62 40
+             00 00 01 4e 01 05 01 80 80
+             00 00 01 26 01 ac 9c ff ff
+             00 00 01 44 01 50 5c ad 18 90
+             00 00 01 26 01 93 27 3f ff ff
+             00 00 01 44 01 c1 72 b4 62 40
+             00 00 01 26 01 a6 9c ff ff
+             00 00 01 42 01 01 01 40 00 00 03 00 40 00 00 03 00 00 03 00 7b a0 03 c0 80 22 1f
+             79 e9 6e 44 a1 7f f8 00 08 00 13 50 10 10 1e d0 00 00 03 00 10 00 00 03 03 25 08
+             ff de 10 00 16 e3 60 00 05 dd 77 df 08 04 10
+             00 00 01 44 01 c1 72 b4 62 40
+             00 00 01 26 01 ac 9c ff ff"
+        );
+        struct Describe(Vec<String>);
+        impl ParsedNalHandler for Describe {
+            fn nal(&mut self, ctx: &Context, header: NalHeader, parsed: ParsedNal<'_>) {
+                self.0.push(format!(
+                    "{:?} {:?} {}",
+                    header.nal_unit_type(),
+                    parsed,
+                    ctx.pps().count()
+                ))
+            }
+
+            fn nal_with_bytes(
+                &mut self,
+                ctx: &Context,
+                h: NalHeader,
+                p: ParsedNal<'_>,
+                nal: &[u8],
+            ) {
+                self.nal(ctx, h, p);
+                self.0.push(format!("{:02x?}", nal));
+            }
+
+            fn format_change(&mut self, _: &Context, change: &FormatChange) {
+                self.0.push(format!("{:?}", change));
+            }
+        }
+        for depth in [
+            ParseDepth::Headers,
+            ParseDepth::ParamSets,
+            ParseDepth::SliceHeaders,
+        ] {
+            for retain in [true, false] {
+                let mut nal_parser = NalParser::new(depth, Describe(vec![]));
+                let mut par_parser = ParParser::new(depth);
+                if retain {
+                    nal_parser = nal_parser.with_retained_bytes();
+                    par_parser = par_parser.with_retained_bytes();
+                }
+                let mut reader = AnnexBReader::accumulate(nal_parser);
+                reader.push(&stream);
+                reader.reset();
+                let expected = reader.into_nal_handler().into_handler().0;
+                let mut actual = Describe(vec![]);
+                par_parser.parse_annexb(&stream, &mut actual);
+                assert_eq!(actual.0, expected);
+            }
+            let mut actual = Describe(vec![]);
+            ParParser::new(depth).parse_annexb(&stream, &mut actual);
+            let actual = actual.0;
+            if depth == ParseDepth::SliceHeaders {
+                assert!(actual[3].starts_with("SliceSegmentLayerIdrWLp SliceSegment(Ok("));
+                assert!(actual[3].ends_with(" 1"));
+                assert!(actual[5].starts_with("SliceSegmentLayerIdrWLp SliceSegment(Ok("));
+                assert!(actual[5].ends_with(" 2"));
+                assert!(actual[7].contains("SliceSegment(Err("));
+                assert!(actual[10].starts_with("FormatChange"));
+            }
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! Reassembly of access units from the PES packets of an HEVC elementary stream in an MPEG-2
+//! Transport Stream (ISO/IEC 13818-1), for use behind a TS demuxer.
+
+use std::io::Read;
+
+use crate::annexb::AnnexBReader;
+use crate::nal::AccessUnitTracker;
+use crate::nal::{Nal, RefNal};
+use crate::push::{AccumulatedNalHandler, NalAccumulator, NalInterest};
+
+/// A complete access unit and the timestamps of the PES packet it started in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessUnit {
+    /// The 90 kHz presentation timestamp, if the access unit is the first to start in a PES
+    /// packet with one.
+    pub pts: Option<u64>,
+    /// The 90 kHz decoding timestamp, if given as above. A PES packet with a PTS but no DTS
+    /// means the two are equal.
+    pub dts: Option<u64>,
+    /// The NAL units, without start codes.
+    pub nal_units: Vec<Vec<u8>>,
+}
+
+/// Splits the Annex B byte stream carried in PES packet payloads into access units, which may
+/// span PES packets.
+///
+/// An access unit is only known to be complete when the next one starts, so each access unit
+/// is returned from the [`PesReassembler::push`] of a later PES packet, or from
+/// [`PesReassembler::flush`].
+///
+/// ```
+/// use hevc_reader::pes::PesReassembler;
+/// let mut reassembler = PesReassembler::new();
+/// // An AUD and IDR slice segment, split over two PES packets, then the next access unit.
+/// let pes = b"\x00\x00\x01\x46\x01\x10\x00\x00\x01\x26";
+/// assert!(reassembler.push(pes, Some(3600), None).is_empty());
+/// assert!(reassembler.push(b"\x01\xac\x9c", None, None).is_empty());
+/// let pes = b"\x00\x00\x01\x46\x01\x50\x00\x00\x01\x02\x01\xd0";
+/// let aus = reassembler.push(pes, Some(7200), None);
+/// assert_eq!(aus.len(), 1);
+/// assert_eq!(aus[0].pts, Some(3600));
+/// assert_eq!(aus[0].nal_units, [&b"\x46\x01\x10"[..], b"\x26\x01\xac\x9c"]);
+/// let aus = reassembler.flush();
+/// assert_eq!(aus[0].pts, Some(7200));
+/// assert_eq!(aus[0].nal_units.len(), 2);
+/// ```
+pub struct PesReassembler {
+    reader: AnnexBReader<NalAccumulator<Collector>>,
+}
+impl Default for PesReassembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl PesReassembler {
+    pub fn new() -> Self {
+        PesReassembler {
+            reader: AnnexBReader::accumulate(Collector::default()),
+        }
+    }
+
+    /// Pushes the payload of the next PES packet, with the timestamps from its header, and
+    /// returns the access units completed by it.
+    pub fn push(&mut self, payload: &[u8], pts: Option<u64>, dts: Option<u64>) -> Vec<AccessUnit> {
+        let collector = self.reader.nal_handler_mut();
+        collector.pes = Pes {
+            seq: collector.pes.seq + 1,
+            pts,
+            dts,
+        };
+        self.reader.push(payload);
+        std::mem::take(&mut self.reader.nal_handler_mut().done)
+    }
+
+    /// Ends the stream, e.g. at its end or a discontinuity, and returns the access units not yet
+    /// returned. Following data starts a new access unit.
+    pub fn flush(&mut self) -> Vec<AccessUnit> {
+        self.reader.reset();
+        let collector = self.reader.nal_handler_mut();
+        collector.tracker = AccessUnitTracker::default();
+        collector.done.extend(collector.au.take());
+        std::mem::take(&mut collector.done)
+    }
+}
+
+#[derive(Default, Clone, Copy)]
+struct Pes {
+    /// Counts the PES packets, so timestamps are only given to the first access unit starting
+    /// in one.
+    seq: u64,
+    pts: Option<u64>,
+    dts: Option<u64>,
+}
+
+#[derive(Default)]
+struct Collector {
+    /// The PES packet being pushed.
+    pes: Pes,
+    /// The PES packet the NAL unit being accumulated started in.
+    nal_start: Option<Pes>,
+    /// The PES packet whose timestamps were given to an access unit.
+    timestamped: Option<u64>,
+    tracker: AccessUnitTracker,
+    au: Option<AccessUnit>,
+    done: Vec<AccessUnit>,
+}
+impl AccumulatedNalHandler for Collector {
+    fn nal(&mut self, nal: RefNal<'_>) -> NalInterest {
+        let start = *self.nal_start.get_or_insert(self.pes);
+        if !nal.is_complete() {
+            return NalInterest::Buffer;
+        }
+        self.nal_start = None;
+        let Ok(header) = nal.header() else {
+            return NalInterest::Ignore;
+        };
+        let mut bytes = vec![];
+        if nal.reader().read_to_end(&mut bytes).is_err() {
+            return NalInterest::Ignore;
+        }
+        if self.tracker.push(header.nal_unit_type(), &bytes) {
+            self.done.extend(self.au.take());
+            let timestamped = self.timestamped != Some(start.seq);
+            if timestamped {
+                self.timestamped = Some(start.seq);
+            }
+            self.au = Some(AccessUnit {
+                pts: start.pts.filter(|_| timestamped),
+                dts: start.dts.filter(|_| timestamped),
+                nal_units: vec![],
+            });
+        }
+        if let Some(au) = &mut self.au {
+            au.nal_units.push(bytes);
+        }
+        NalInterest::Ignore
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn two_access_units_in_one_packet() {
+        let mut reassembler = PesReassembler::new();
+        // Two trailing pictures in one PES packet: only the first gets its timestamps.
+        let aus = reassembler.push(
+            b"\x00\x00\x01\x02\x01\xd0\x6c\x00\x00\x01\x02\x01\xd0\x6c\x00\x00\x01\x02\x01",
+            Some(100),
+            Some(90),
+        );
+        assert_eq!(
+            aus,
+            [AccessUnit {
+                pts: Some(100),
+                dts: Some(90),
+                nal_units: vec![b"\x02\x01\xd0\x6c".to_vec()],
+            }]
+        );
+        // The third picture continues into this packet, and a fourth starts in it.
+        let aus = reassembler.push(b"\xd0\x6c\x00\x00\x01\x02\x01\xd0\x6c", Some(300), None);
+        assert_eq!(aus.len(), 1);
+        assert_eq!(aus[0].pts, None);
+        let aus = reassembler.flush();
+        assert_eq!(aus.len(), 2);
+        assert_eq!((aus[0].pts, aus[1].pts), (None, Some(300)));
+        assert_eq!(aus[0].nal_units, [b"\x02\x01\xd0\x6c"]);
+        assert!(reassembler.flush().is_empty());
+    }
+}
@@ -1,5 +1,8 @@
 //! Push parsing of encoded NALs.
 
+#[cfg(all(feature = "sei", feature = "slice"))]
+pub mod parse;
+
 use crate::nal::RefNal;
 
 /// [`AccumulatedNalHandler`]'s interest in receiving additional callbacks on a NAL.
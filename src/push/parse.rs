@@ -0,0 +1,530 @@
+//! A push parser which parses each NAL unit as far as a [`ParseDepth`] asks, for scanning
+//! whole streams.
+
+use std::io::Read;
+
+use crate::nal::pps::{PicParameterSet, PpsError};
+use crate::nal::sei::SeiReader;
+use crate::nal::slice::{SliceHeaderError, SliceSegmentHeader};
+use crate::nal::sps::{ChromaFormat, SeqParameterSet, SpsError};
+use crate::nal::vps::{VideoParameterSet, VpsError};
+use crate::nal::{Nal, NalHeader, RefNal, UnitType};
+use crate::push::{AccumulatedNalHandler, NalInterest};
+use crate::rbsp::BitReaderError;
+use crate::{Context, FrameRate};
+
+/// How much of each NAL unit a [`NalParser`] parses. Each depth includes the ones before it.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ParseDepth {
+    /// Only the NAL unit headers. No NAL unit is buffered.
+    Headers,
+    /// Parameter sets and SEI NAL units, which are buffered until complete.
+    ParamSets,
+    /// Slice segment headers, which are buffered only until the header has been read.
+    #[default]
+    SliceHeaders,
+}
+
+/// What a [`NalParser`] parsed of a NAL unit.
+#[derive(Debug)]
+// Not boxed, so parsing a slice segment header doesn't allocate.
+#[allow(clippy::large_enum_variant)]
+pub enum ParsedNal<'a> {
+    /// A NAL unit of a type which isn't parsed at the parser's depth.
+    Unparsed,
+    /// A parameter set, as now stored in the [`Context`].
+    VideoParameterSet(Result<&'a VideoParameterSet, VpsError>),
+    SeqParameterSet(Result<&'a SeqParameterSet, SpsError>),
+    PicParameterSet(Result<&'a PicParameterSet, PpsError>),
+    /// The messages of a prefix or suffix SEI NAL unit.
+    Sei(Result<SeiReader<'a>, BitReaderError>),
+    SliceSegment(Result<SliceSegmentHeader, SliceHeaderError>),
+}
+
+/// The properties of the coded video which consumers such as ABR ingest must reconfigure for
+/// when they change mid-stream, see [`ParsedNalHandler::format_change`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VideoFormat {
+    /// The cropped picture size, or `None` if the conformance window is invalid.
+    pub dimensions: Option<(u32, u32)>,
+    pub chroma_format: ChromaFormat,
+    pub bit_depth_luma: u32,
+    pub bit_depth_chroma: u32,
+    /// As [`Context::frame_rate`], without `pic_timing` SEI.
+    pub frame_rate: Option<FrameRate>,
+}
+impl VideoFormat {
+    pub fn new(ctx: &Context, sps: &SeqParameterSet) -> Self {
+        VideoFormat {
+            dimensions: sps.pixel_dimensions().ok(),
+            chroma_format: sps.chroma_info.chroma_format,
+            bit_depth_luma: sps.bit_depth_luma_minus8 + 8,
+            bit_depth_chroma: sps.bit_depth_chroma_minus8 + 8,
+            frame_rate: ctx.frame_rate(sps, None),
+        }
+    }
+}
+
+/// A change of [`VideoFormat`] from one SPS to the next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatChange {
+    pub previous: VideoFormat,
+    pub current: VideoFormat,
+}
+impl FormatChange {
+    pub fn dimensions_changed(&self) -> bool {
+        self.previous.dimensions != self.current.dimensions
+    }
+
+    pub fn bit_depth_changed(&self) -> bool {
+        (self.previous.bit_depth_luma, self.previous.bit_depth_chroma)
+            != (self.current.bit_depth_luma, self.current.bit_depth_chroma)
+    }
+
+    pub fn chroma_format_changed(&self) -> bool {
+        self.previous.chroma_format != self.current.chroma_format
+    }
+
+    pub fn frame_rate_changed(&self) -> bool {
+        self.previous.frame_rate != self.current.frame_rate
+    }
+
+    /// Replaces the `last` format with `current`, returning the change if it's one.
+    pub(crate) fn update(last: &mut Option<VideoFormat>, current: VideoFormat) -> Option<Self> {
+        match last.replace(current) {
+            Some(previous) if previous != current => Some(FormatChange { previous, current }),
+            _ => None,
+        }
+    }
+}
+
+/// [`NalParser`] callback, called once for each NAL unit with the parameter sets seen so far.
+pub trait ParsedNalHandler {
+    fn nal(&mut self, ctx: &Context, header: NalHeader, parsed: ParsedNal<'_>);
+
+    /// Called when the [`VideoFormat`] changes, just before the NAL unit which changes it is
+    /// passed on.
+    ///
+    /// When parsing to [`ParseDepth::SliceHeaders`], that's the first slice segment of a
+    /// picture whose SPS has a different format than the previous picture's; SPSs which no
+    /// picture uses are disregarded. When parsing to [`ParseDepth::ParamSets`], it's an SPS
+    /// with a different format than the SPS before it.
+    fn format_change(&mut self, _ctx: &Context, _change: &FormatChange) {}
+
+    /// Called instead of [`Self::nal`] for the parameter set and SEI NAL units parsed by a
+    /// parser [retaining their bytes](NalParser::with_retained_bytes), with the whole NAL unit as
+    /// found in the stream. Rewriting pipelines can pass through the units they don't modify
+    /// bit-exactly, rather than re-serializing them.
+    fn nal_with_bytes(
+        &mut self,
+        ctx: &Context,
+        header: NalHeader,
+        parsed: ParsedNal<'_>,
+        _nal: &[u8],
+    ) {
+        self.nal(ctx, header, parsed)
+    }
+}
+
+impl<F: FnMut(&Context, NalHeader, ParsedNal<'_>)> ParsedNalHandler for F {
+    fn nal(&mut self, ctx: &Context, header: NalHeader, parsed: ParsedNal<'_>) {
+        (self)(ctx, header, parsed)
+    }
+}
+
+/// An [`AccumulatedNalHandler`] which parses NAL units to the given [`ParseDepth`], buffering
+/// no more than that needs, and keeps the parameter sets to parse slice segment headers with.
+///
+/// ```
+/// use hevc_reader::annexb::AnnexBReader;
+/// use hevc_reader::nal::{NalHeader, UnitType};
+/// use hevc_reader::push::parse::{NalParser, ParseDepth, ParsedNal};
+/// use hevc_reader::Context;
+/// let mut types = vec![];
+/// let handler = |_: &Context, header: NalHeader, parsed: ParsedNal<'_>| {
+///     assert!(matches!(parsed, ParsedNal::Unparsed));
+///     types.push(header.nal_unit_type());
+/// };
+/// let mut reader = AnnexBReader::accumulate(NalParser::new(ParseDepth::Headers, handler));
+/// reader.push(b"\x00\x00\x01\x46\x01\x10\x00\x00\x01\x26\x01\xac\x9c");
+/// reader.reset();
+/// assert_eq!(types, [UnitType::AccessUnitDelimiter, UnitType::SliceSegmentLayerIdrWLp]);
+/// ```
+pub struct NalParser<H: ParsedNalHandler> {
+    depth: ParseDepth,
+    ctx: Context,
+    /// The RBSP of the last SEI NAL unit.
+    rbsp: Vec<u8>,
+    /// The bytes of the last parameter set or SEI NAL unit, if retained.
+    bytes: Option<Vec<u8>>,
+    /// The format of the last picture, or at [`ParseDepth::ParamSets`] the last SPS.
+    format: Option<VideoFormat>,
+    /// True while skipping slice segments up to the first IRAP picture, when resuming.
+    awaiting_irap: bool,
+    handler: H,
+}
+impl<H: ParsedNalHandler> NalParser<H> {
+    pub fn new(depth: ParseDepth, handler: H) -> Self {
+        NalParser {
+            depth,
+            ctx: Context::new(),
+            rbsp: vec![],
+            bytes: None,
+            format: None,
+            awaiting_irap: false,
+            handler,
+        }
+    }
+
+    /// Creates a parser resuming a stream from an arbitrary point, e.g. a byte offset of a
+    /// partially downloaded file, with the parameter sets captured earlier (see
+    /// [`Self::context`]). Slice segments are skipped, without calling the handler, up to the
+    /// first slice segment of an IRAP picture; other NAL units are passed on as usual.
+    ///
+    /// Pair it with an [`AnnexBReader`] [starting mid-stream] to also skip the partial NAL unit
+    /// at the start.
+    ///
+    /// [`AnnexBReader`]: crate::annexb::AnnexBReader
+    /// [starting mid-stream]: crate::annexb::AnnexBReader::starting_mid_stream
+    pub fn resume(depth: ParseDepth, handler: H, ctx: Context) -> Self {
+        NalParser {
+            ctx,
+            awaiting_irap: true,
+            ..Self::new(depth, handler)
+        }
+    }
+
+    /// Passes the bytes of each parameter set and SEI NAL unit to
+    /// [`ParsedNalHandler::nal_with_bytes`], copied into a buffer reused between NAL units.
+    pub fn with_retained_bytes(mut self) -> Self {
+        self.bytes = Some(vec![]);
+        self
+    }
+
+    pub fn depth(&self) -> ParseDepth {
+        self.depth
+    }
+
+    /// The parameter sets seen so far.
+    pub fn context(&self) -> &Context {
+        &self.ctx
+    }
+
+    /// The format of the last picture parsed, or at [`ParseDepth::ParamSets`] of the last SPS.
+    pub fn format(&self) -> Option<&VideoFormat> {
+        self.format.as_ref()
+    }
+
+    /// Adds parameter sets from outside the stream, e.g. from an `hvcC` box.
+    pub fn context_mut(&mut self) -> &mut Context {
+        &mut self.ctx
+    }
+
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    pub fn handler_mut(&mut self) -> &mut H {
+        &mut self.handler
+    }
+
+    pub fn into_handler(self) -> H {
+        self.handler
+    }
+}
+impl<H: ParsedNalHandler> AccumulatedNalHandler for NalParser<H> {
+    fn nal(&mut self, nal: RefNal<'_>) -> NalInterest {
+        let header = match nal.header() {
+            Ok(header) if header.is_complete() => header,
+            Ok(_) if !nal.is_complete() => return NalInterest::Buffer,
+            _ => return NalInterest::Ignore,
+        };
+        let unit_type = header.nal_unit_type();
+        if self.awaiting_irap && unit_type.is_vcl() {
+            // The NAL unit header, and the byte starting with first_slice_segment_in_pic_flag.
+            let mut head = [0; 3];
+            match nal.reader().read_exact(&mut head) {
+                Ok(()) if unit_type.is_irap() && head[2] & 0x80 != 0 => self.awaiting_irap = false,
+                Err(_) if !nal.is_complete() => return NalInterest::Buffer,
+                _ => return NalInterest::Ignore,
+            }
+        }
+        let param_sets = self.depth >= ParseDepth::ParamSets;
+        let mut format = None;
+        let parsed = match unit_type {
+            UnitType::VideoParameterSet
+            | UnitType::SeqParameterSet
+            | UnitType::PicParameterSet
+            | UnitType::PrefixSEI
+            | UnitType::SuffixSEI
+                if param_sets && !nal.is_complete() =>
+            {
+                return NalInterest::Buffer
+            }
+            UnitType::VideoParameterSet if param_sets => {
+                match VideoParameterSet::from_bits(nal.rbsp_bits()) {
+                    Ok(vps) => {
+                        let id = vps.vps_video_parameter_set_id;
+                        self.ctx.put_video_param_set(vps);
+                        ParsedNal::VideoParameterSet(Ok(self.ctx.vps_by_id(id).unwrap()))
+                    }
+                    Err(e) => ParsedNal::VideoParameterSet(Err(e)),
+                }
+            }
+            UnitType::SeqParameterSet if param_sets => {
+                match SeqParameterSet::from_bits(nal.rbsp_bits()) {
+                    Ok(sps) => {
+                        let id = sps.id();
+                        self.ctx.put_seq_param_set(sps);
+                        let sps = self.ctx.sps_by_id(id).unwrap();
+                        if self.depth == ParseDepth::ParamSets {
+                            format = Some(VideoFormat::new(&self.ctx, sps));
+                        }
+                        ParsedNal::SeqParameterSet(Ok(sps))
+                    }
+                    Err(e) => ParsedNal::SeqParameterSet(Err(e)),
+                }
+            }
+            UnitType::PicParameterSet if param_sets => {
+                match PicParameterSet::from_bits(&self.ctx, nal.rbsp_bits()) {
+                    Ok(pps) => {
+                        let id = pps.pic_parameter_set_id;
+                        self.ctx.put_pic_param_set(pps);
+                        ParsedNal::PicParameterSet(Ok(self.ctx.pps_by_id(id).unwrap()))
+                    }
+                    Err(e) => ParsedNal::PicParameterSet(Err(e)),
+                }
+            }
+            UnitType::PrefixSEI | UnitType::SuffixSEI if param_sets => {
+                self.rbsp.clear();
+                match nal.rbsp_bytes().read_to_end(&mut self.rbsp) {
+                    Ok(_) => ParsedNal::Sei(Ok(SeiReader::from_rbsp_bytes(&self.rbsp))),
+                    Err(e) => ParsedNal::Sei(Err(BitReaderError::ReaderError(e))),
+                }
+            }
+            t if t.is_vcl() && self.depth >= ParseDepth::SliceHeaders => {
+                let mut r = nal.rbsp_bits();
+                match SliceSegmentHeader::from_bits(&self.ctx, &mut r, header) {
+                    Err(e) if !nal.is_complete() && would_block(&e) => return NalInterest::Buffer,
+                    result => ParsedNal::SliceSegment(result.map(|(header, sps, _)| {
+                        if header.first_slice_segment_in_pic_flag {
+                            format = Some(VideoFormat::new(&self.ctx, sps));
+                        }
+                        header
+                    })),
+                }
+            }
+            _ => ParsedNal::Unparsed,
+        };
+        if let Some(change) = format.and_then(|f| FormatChange::update(&mut self.format, f)) {
+            self.handler.format_change(&self.ctx, &change);
+        }
+        let retained = matches!(
+            parsed,
+            ParsedNal::VideoParameterSet(_)
+                | ParsedNal::SeqParameterSet(_)
+                | ParsedNal::PicParameterSet(_)
+                | ParsedNal::Sei(_)
+        );
+        match &mut self.bytes {
+            Some(bytes) if retained => {
+                bytes.clear();
+                // Reading a complete, buffered NAL unit can't fail.
+                let _ = nal.reader().read_to_end(bytes);
+                self.handler
+                    .nal_with_bytes(&self.ctx, header, parsed, bytes);
+            }
+            _ => self.handler.nal(&self.ctx, header, parsed),
+        }
+        NalInterest::Ignore
+    }
+}
+
+/// True if reading failed at the end of a partially buffered NAL unit.
+fn would_block(e: &SliceHeaderError) -> bool {
+    matches!(
+        e,
+        SliceHeaderError::RbspError(
+            BitReaderError::ReaderError(e) | BitReaderError::ReaderErrorFor(_, e)
+        ) if e.kind() == std::io::ErrorKind::WouldBlock
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::annexb::AnnexBReader;
+    use crate::push::{NalAccumulator, NalFragmentHandler};
+    use hex_literal::hex;
+
+    // "Intinor HW encode 720x576p" SPS, its PPS and the start of an IDR slice segment.
+    const SPS: [u8; 59] = hex!(
+        "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46 d1
+         2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00 0b
+         b8 48"
+    );
+    const PPS: [u8; 7] = hex!("44 01 c1 72 b4 62 40");
+    const IDR: [u8; 4] = hex!("26 01 ac 9c");
+
+    fn parse(depth: ParseDepth, idr_fragments: &[&[u8]]) -> Vec<String> {
+        let mut parsed = vec![];
+        let mut acc = NalAccumulator::new(NalParser::new(
+            depth,
+            |_: &Context, _: NalHeader, nal: ParsedNal<'_>| {
+                parsed.push(match nal {
+                    ParsedNal::Unparsed => "unparsed".to_owned(),
+                    ParsedNal::SeqParameterSet(Ok(sps)) => format!("sps {}", sps.id().id()),
+                    ParsedNal::PicParameterSet(Ok(pps)) => {
+                        format!("pps {}", pps.pic_parameter_set_id.id())
+                    }
+                    ParsedNal::SliceSegment(Ok(header)) => {
+                        format!("slice {:?}", header.slice_header.unwrap().slice_type)
+                    }
+                    other => format!("{:?}", other),
+                })
+            },
+        ));
+        acc.nal_fragment(&[&SPS[..10], &SPS[10..]], false);
+        acc.nal_fragment(&[], true);
+        acc.nal_fragment(&[&PPS], true);
+        for fragment in idr_fragments {
+            acc.nal_fragment(&[fragment], false);
+        }
+        // The rest of the slice segment data.
+        acc.nal_fragment(&[&[0xff; 100]], true);
+        parsed
+    }
+
+    #[test]
+    fn retained_bytes() {
+        struct Retain(Vec<(UnitType, Option<Vec<u8>>)>);
+        impl ParsedNalHandler for Retain {
+            fn nal(&mut self, _: &Context, header: NalHeader, _: ParsedNal<'_>) {
+                self.0.push((header.nal_unit_type(), None));
+            }
+
+            fn nal_with_bytes(&mut self, _: &Context, h: NalHeader, _: ParsedNal<'_>, nal: &[u8]) {
+                self.0.push((h.nal_unit_type(), Some(nal.to_vec())));
+            }
+        }
+        let mut acc = NalAccumulator::new(NalParser::new(ParseDepth::SliceHeaders, Retain(vec![])));
+        acc.nal_fragment(&[&SPS[..10], &SPS[10..]], false);
+        acc.nal_fragment(&[], true);
+        acc.nal_fragment(&[&PPS], true);
+        acc.nal_fragment(&[&IDR], true);
+        let plain = acc.into_handler().into_handler().0;
+        assert!(plain.iter().all(|(_, bytes)| bytes.is_none()));
+
+        let mut acc = NalAccumulator::new(
+            NalParser::new(ParseDepth::SliceHeaders, Retain(vec![])).with_retained_bytes(),
+        );
+        acc.nal_fragment(&[&SPS[..10], &SPS[10..]], false);
+        acc.nal_fragment(&[], true);
+        acc.nal_fragment(&[&PPS], true);
+        acc.nal_fragment(&[&IDR], true);
+        assert_eq!(
+            acc.into_handler().into_handler().0,
+            [
+                (UnitType::SeqParameterSet, Some(SPS.to_vec())),
+                (UnitType::PicParameterSet, Some(PPS.to_vec())),
+                (UnitType::SliceSegmentLayerIdrWLp, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn format_change() {
+        // "Haivision 1080i25", also SPS id 0, coded as 1920x540 fields.
+        let sps_1080 = hex!(
+            "42 01 01 01 40 00 00 03 00 40 00 00 03 00 00 03 00 7b a0 03 c0 80 22 1f 79 e9 6e
+             44 a1 7f f8 00 08 00 13 50 10 10 1e d0 00 00 03 00 10 00 00 03 03 25 08 ff de 10
+             00 16 e3 60 00 05 dd 77 df 08 04 10"
+        );
+        #[derive(Default)]
+        struct Changes(Vec<FormatChange>);
+        impl ParsedNalHandler for Changes {
+            fn nal(&mut self, _: &Context, _: NalHeader, _: ParsedNal<'_>) {}
+            fn format_change(&mut self, _: &Context, change: &FormatChange) {
+                self.0.push(*change);
+            }
+        }
+        let changes = |depth, nals: &[&[u8]]| {
+            let mut acc = NalAccumulator::new(NalParser::new(depth, Changes::default()));
+            for nal in nals {
+                acc.nal_fragment(&[nal], true);
+            }
+            acc.into_handler().into_handler().0
+        };
+
+        let stream: [&[u8]; 6] = [&SPS, &PPS, &IDR, &sps_1080, &PPS, &IDR];
+        for depth in [ParseDepth::ParamSets, ParseDepth::SliceHeaders] {
+            let changes = changes(depth, &stream);
+            assert_eq!(changes.len(), 1);
+            assert_eq!(changes[0].previous.dimensions, Some((720, 576)));
+            assert_eq!(changes[0].current.dimensions, Some((1920, 540)));
+            assert!(changes[0].dimensions_changed() && changes[0].frame_rate_changed());
+            assert!(!changes[0].bit_depth_changed() && !changes[0].chroma_format_changed());
+        }
+
+        // An SPS replaced before any picture uses it changes nothing.
+        let stream: [&[u8]; 6] = [&SPS, &PPS, &IDR, &sps_1080, &SPS, &IDR];
+        assert!(changes(ParseDepth::SliceHeaders, &stream).is_empty());
+        assert_eq!(changes(ParseDepth::ParamSets, &stream).len(), 2);
+    }
+
+    #[test]
+    fn resume() {
+        let mut ctx = None;
+        let mut reader = AnnexBReader::accumulate(NalParser::new(
+            ParseDepth::ParamSets,
+            |c: &Context, _: NalHeader, _: ParsedNal<'_>| ctx = Some(c.clone()),
+        ));
+        reader.push(&[0, 0, 1]);
+        reader.push(&SPS);
+        reader.push(&[0, 0, 1]);
+        reader.push(&PPS);
+        reader.reset();
+        drop(reader);
+
+        let mut parsed = vec![];
+        let handler = |_: &Context, header: NalHeader, nal: ParsedNal<'_>| {
+            assert!(matches!(nal, ParsedNal::SliceSegment(Ok(_))));
+            parsed.push(header.nal_unit_type());
+        };
+        let mut reader = AnnexBReader::accumulate(NalParser::resume(
+            ParseDepth::SliceHeaders,
+            handler,
+            ctx.unwrap(),
+        ))
+        .starting_mid_stream();
+        // The end of a P slice segment, a P slice segment to skip, then an IDR and a P one.
+        reader.push(&hex!("6c 27 80 00 00 01 02 01 d0 6c 27 80 00 00 01 26"));
+        reader.push(&hex!("01 ac 9c 00 00 01 02 01 d0 6c 27 80"));
+        reader.reset();
+        drop(reader);
+        assert_eq!(
+            parsed,
+            [
+                UnitType::SliceSegmentLayerIdrWLp,
+                UnitType::SliceSegmentLayerTrailR
+            ]
+        );
+    }
+
+    #[test]
+    fn depths() {
+        let idr = [&IDR[..3], &IDR[3..]];
+        assert_eq!(
+            parse(ParseDepth::Headers, &idr),
+            ["unparsed", "unparsed", "unparsed"]
+        );
+        assert_eq!(
+            parse(ParseDepth::ParamSets, &idr),
+            ["sps 0", "pps 0", "unparsed"]
+        );
+        assert_eq!(
+            parse(ParseDepth::SliceHeaders, &idr),
+            ["sps 0", "pps 0", "slice I"]
+        );
+    }
+}
@@ -0,0 +1,291 @@
+//! A Python extension module, for QC scripts inspecting streams without shelling out to
+//! ffprobe. Requires the `python` feature; build a wheel with
+//! [maturin](https://www.maturin.rs/), as configured by `pyproject.toml`, which builds the
+//! cdylib the crate doesn't make by default:
+//!
+//! ```text
+//! $ maturin develop --release
+//! $ python -c 'import hevc_reader; print(hevc_reader.parse_file("in.h265").stats.frames)'
+//! ```
+//!
+//! [`parse_file`] and [`parse_bytes`] parse a whole Annex B stream into a [`Stream`] of plain
+//! values, gathered by a [`StreamScanner`].
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use pyo3::prelude::*;
+
+use crate::annexb;
+use crate::nal::sei::SeiReader;
+use crate::nal::sps::SeqParameterSet;
+use crate::nal::{NalHeader, UnitType};
+use crate::rbsp::decode_nal;
+use crate::validate::order::OrderAnomalyKind;
+use crate::validate::StreamScanner;
+
+/// The fields of an SPS a QC script usually checks.
+#[pyclass(frozen, get_all, module = "hevc_reader")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeqParamSet {
+    pub id: u8,
+    /// The cropped picture size, or 0 if the conformance window is invalid.
+    pub width: u32,
+    pub height: u32,
+    pub profile_idc: u8,
+    pub high_tier: bool,
+    pub level_idc: u8,
+    pub chroma_format_idc: u32,
+    pub bit_depth_luma: u32,
+    pub bit_depth_chroma: u32,
+    /// The RFC 6381 `codecs` parameter for an `hvc1` sample entry.
+    pub codecs: String,
+}
+impl SeqParamSet {
+    fn new(sps: &SeqParameterSet) -> Self {
+        let (width, height) = sps.pixel_dimensions().unwrap_or((0, 0));
+        let profile = sps.general_layer_profile();
+        SeqParamSet {
+            id: sps.id().id(),
+            width,
+            height,
+            profile_idc: profile.profile_idc,
+            high_tier: profile.tier_flag,
+            level_idc: sps.profile_tier_level.general_level_idc,
+            chroma_format_idc: sps.chroma_info.chroma_format.id(),
+            bit_depth_luma: sps.bit_depth_luma_minus8 + 8,
+            bit_depth_chroma: sps.bit_depth_chroma_minus8 + 8,
+            codecs: sps.codecs_string("hvc1"),
+        }
+    }
+}
+
+/// A coded picture, in decoding order.
+#[pyclass(frozen, get_all, module = "hevc_reader")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    /// The NAL unit type of its slice segments.
+    pub nal_unit_type: u8,
+    /// True for IRAP pictures, where decoding can start.
+    pub keyframe: bool,
+    pub temporal_id: u8,
+    pub slice_segments: u32,
+    /// The payload types of the SEI messages in its access unit.
+    pub sei_types: Vec<u32>,
+    /// True if its access unit carries ATSC A/53 closed captions.
+    pub captions: bool,
+    /// The average slice QP, unless no slice header could be parsed.
+    pub qp: Option<f64>,
+}
+
+/// An SEI message.
+#[pyclass(frozen, module = "hevc_reader")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sei {
+    /// The byte offset of its NAL unit in the stream, just past the start code.
+    #[pyo3(get)]
+    pub offset: usize,
+    #[pyo3(get)]
+    pub payload_type: u32,
+    pub payload: Vec<u8>,
+}
+#[pymethods]
+impl Sei {
+    /// The payload, as `bytes` with emulation prevention removed.
+    #[getter]
+    fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+}
+
+/// Whole-stream figures.
+#[pyclass(frozen, get_all, module = "hevc_reader")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    pub bytes: usize,
+    pub nal_units: usize,
+    pub frames: usize,
+    pub keyframes: usize,
+    /// The frame rate signalled for the first SPS.
+    pub fps: Option<f64>,
+    /// The largest number of VCL bits in any one second window, if the frame rate is known.
+    pub peak_bit_rate: Option<f64>,
+    /// The VCL bit rate over the whole stream, if the frame rate is known.
+    pub average_bit_rate: Option<f64>,
+    pub hdr10_plus: bool,
+    pub dolby_vision: bool,
+    /// Duplicated pictures and slice segments, and pictures repeating a POC.
+    pub duplicates: usize,
+    /// Pictures received after a picture predicted from them, or otherwise out of order.
+    pub reordered: usize,
+    /// The number of slices with each slice QP.
+    pub qp_histogram: BTreeMap<i32, u64>,
+}
+
+/// What was found in a stream.
+#[pyclass(frozen, get_all, module = "hevc_reader")]
+#[derive(Debug, Clone)]
+pub struct Stream {
+    /// The distinct SPSs, in order of appearance.
+    pub seq_param_sets: Vec<SeqParamSet>,
+    pub frames: Vec<Frame>,
+    pub sei: Vec<Sei>,
+    pub stats: Stats,
+}
+impl Stream {
+    pub fn from_annexb(data: &[u8]) -> Self {
+        let mut scanner = StreamScanner::new();
+        let mut sei = vec![];
+        let mut nal_units = 0;
+        for (offset, nal) in annexb::nal_units(data) {
+            nal_units += 1;
+            scanner.push_nal(nal);
+            let is_sei = nal
+                .first()
+                .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())
+                .is_some_and(|h| {
+                    matches!(h.nal_unit_type(), UnitType::PrefixSEI | UnitType::SuffixSEI)
+                });
+            let Some(rbsp) = is_sei.then(|| decode_nal(nal).ok()).flatten() else {
+                continue;
+            };
+            let mut reader = SeiReader::from_rbsp_bytes(&rbsp);
+            while let Ok(Some(msg)) = reader.next() {
+                sei.push(Sei {
+                    offset,
+                    payload_type: msg.payload_type.id(),
+                    payload: msg.payload.to_vec(),
+                });
+            }
+        }
+        let summary = scanner.finish();
+        let frames: Vec<_> = summary
+            .pictures
+            .iter()
+            .map(|p| Frame {
+                nal_unit_type: p.unit_type.id(),
+                keyframe: p.unit_type.is_irap(),
+                temporal_id: p.temporal_id,
+                slice_segments: p.slice_segments,
+                sei_types: p.sei.iter().map(|t| t.id()).collect(),
+                captions: p.captions,
+                qp: p.average_qp(),
+            })
+            .collect();
+        Stream {
+            seq_param_sets: summary
+                .seq_param_sets
+                .iter()
+                .map(SeqParamSet::new)
+                .collect(),
+            stats: Stats {
+                bytes: data.len(),
+                nal_units,
+                frames: frames.len(),
+                keyframes: frames.iter().filter(|f| f.keyframe).count(),
+                fps: summary.frame_rate.map(|r| r.fps()),
+                peak_bit_rate: summary.level.peak_bit_rate,
+                average_bit_rate: summary.level.average_bit_rate,
+                hdr10_plus: summary.hdr10_plus,
+                dolby_vision: summary.dolby_vision,
+                duplicates: summary
+                    .order
+                    .iter()
+                    .filter(|a| {
+                        matches!(
+                            a.kind,
+                            OrderAnomalyKind::DuplicateAccessUnit
+                                | OrderAnomalyKind::DuplicateNal
+                                | OrderAnomalyKind::DuplicatePoc
+                        )
+                    })
+                    .count(),
+                reordered: summary
+                    .order
+                    .iter()
+                    .filter(|a| {
+                        matches!(
+                            a.kind,
+                            OrderAnomalyKind::Reordered | OrderAnomalyKind::LeadingAfterTrailing
+                        )
+                    })
+                    .count(),
+                qp_histogram: summary.qp_histogram,
+            },
+            frames,
+            sei,
+        }
+    }
+}
+
+/// Parses an Annex B file.
+#[pyfunction]
+pub fn parse_file(py: Python<'_>, path: PathBuf) -> PyResult<Stream> {
+    py.allow_threads(|| Ok(Stream::from_annexb(&std::fs::read(path)?)))
+}
+
+/// Parses an Annex B stream held in memory.
+#[pyfunction]
+pub fn parse_bytes(py: Python<'_>, data: &[u8]) -> Stream {
+    py.allow_threads(|| Stream::from_annexb(data))
+}
+
+#[pymodule]
+fn hevc_reader(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(parse_file, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_bytes, m)?)?;
+    m.add_class::<Stream>()?;
+    m.add_class::<Stats>()?;
+    m.add_class::<SeqParamSet>()?;
+    m.add_class::<Frame>()?;
+    m.add_class::<Sei>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hex_literal::hex;
+
+    #[test]
+    fn stream() {
+        // "Intinor HW encode 720x576p" SPS and its PPS, a recovery point SEI, then an IDR and a
+        // trailing picture.
+        let data = hex!(
+            "00 00 00 01 42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71
+             3e 87 ee 46 d1 2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0
+             00 49 3e 00 0b b8 48 00 00 01 44 01 c1 72 b4 62 40 00 00 01 4e 01 06 01 c4 80
+             00 00 01 26 01 ac 9c ff ff 00 00 01 02 01 d0 9c ff ff"
+        );
+        let stream = Stream::from_annexb(&data);
+        assert_eq!(stream.seq_param_sets.len(), 1);
+        assert_eq!(stream.seq_param_sets[0].codecs, "hvc1.1.6.L93.B0");
+        assert_eq!(
+            stream
+                .frames
+                .iter()
+                .map(|f| (f.nal_unit_type, f.keyframe))
+                .collect::<Vec<_>>(),
+            [(19, true), (1, false)]
+        );
+        assert_eq!(stream.frames[0].sei_types, [6]);
+        assert_eq!(
+            stream.sei,
+            [Sei {
+                offset: 76,
+                payload_type: 6,
+                payload: vec![0xc4]
+            }]
+        );
+        assert_eq!(stream.stats.nal_units, 5);
+        assert_eq!((stream.stats.frames, stream.stats.keyframes), (2, 1));
+        assert_eq!(stream.stats.fps, Some(25.0));
+        assert_eq!((stream.stats.duplicates, stream.stats.reordered), (0, 0));
+        // The trailing picture's slice header is cut short.
+        assert_eq!(
+            stream.frames.iter().map(|f| f.qp).collect::<Vec<_>>(),
+            [Some(28.0), None]
+        );
+        assert_eq!(stream.stats.qp_histogram, BTreeMap::from([(28, 1)]));
+    }
+}
@@ -22,7 +22,6 @@
 //! yield byte sequences where the encoding is removed (i.e. the decoder will replace instances of
 //! the sequence `0x00 0x00 0x03` with `0x00 0x00`).
 
-use bitstream_io::read::BitRead as _;
 use std::borrow::Cow;
 use std::io::BufRead;
 use std::io::Read;
@@ -73,6 +72,13 @@ impl<R: BufRead> ByteReader<R> {
         }
     }
 
+    /// Borrows the underlying reader, which is positioned just past the NAL bytes consumed so
+    /// far (including any emulation prevention bytes among them).
+    #[cfg(feature = "slice")]
+    pub(crate) fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
     /// Called when self.i == 0 only; returns false at EOF.
     /// Doesn't return actual buffer contents due to borrow checker limitations;
     /// caller will need to call fill_buf again.
@@ -92,7 +98,7 @@ impl<R: BufRead> ByteReader<R> {
                         self.state = ParseState::OneZero;
                     }
                     None => {
-                        self.i = chunk.len();
+                        self.i = limit;
                         break;
                     }
                 },
@@ -191,7 +197,9 @@ impl<R: BufRead> BufRead for ByteReader<R> {
 ///     Cow::Owned(s) if s == &b"\x12\x34\x00\x00\x00\x86"[..]));
 ///
 /// let nal_without_escape = &b"\x68\x00\xE8\x43\x8F\x13\x21\x30"[..];
-/// assert_eq!(decode_nal(nal_without_escape).unwrap(), Cow::Borrowed(&nal_without_escape[2..]));
+/// assert!(matches!(
+///     decode_nal(nal_without_escape).unwrap(),
+///     Cow::Borrowed(s) if s == &nal_without_escape[2..]));
 ///
 /// let invalid_nal = &b"\x68\x00\x12\x34\x00\x00\x00\x86"[..];
 /// assert_eq!(decode_nal(invalid_nal).unwrap_err().kind(), ErrorKind::InvalidData);
@@ -204,21 +212,52 @@ pub fn decode_nal(nal_unit: &[u8]) -> Result<Cow<'_, [u8]>, std::io::Error> {
         max_fill: usize::MAX, // to borrow if at all possible.
     };
     let buf = reader.fill_buf()?;
-    if buf.len() + 1 == nal_unit.len() {
-        return Ok(Cow::Borrowed(&nal_unit[1..]));
+    if buf.len() + 2 == nal_unit.len() {
+        return Ok(Cow::Borrowed(&nal_unit[2..]));
     }
     // Upper bound estimate; skipping the NAL header and at least one emulation prevention byte.
-    let mut dst = Vec::with_capacity(nal_unit.len() - 2);
+    let mut dst = Vec::with_capacity(nal_unit.len().saturating_sub(3));
+    read_rbsp(reader, &mut dst)?;
+    Ok(Cow::Owned(dst))
+}
+
+/// Like [`decode_nal`], but writes the RBSP into `dst`, replacing its contents, so a buffer can
+/// be reused across NAL units without allocating. `dst` is cleared on error.
+///
+/// ```
+/// # use hevc_reader::rbsp::decode_nal_into;
+/// let mut rbsp = Vec::new();
+/// decode_nal_into(&b"\x68\x00\x12\x34\x00\x00\x03\x00\x86"[..], &mut rbsp).unwrap();
+/// assert_eq!(rbsp, b"\x12\x34\x00\x00\x00\x86");
+/// decode_nal_into(&b"\x68\x00\xE8\x43"[..], &mut rbsp).unwrap();
+/// assert_eq!(rbsp, b"\xE8\x43");
+/// ```
+pub fn decode_nal_into(nal_unit: &[u8], dst: &mut Vec<u8>) -> Result<(), std::io::Error> {
+    dst.clear();
+    let reader = ByteReader {
+        inner: nal_unit,
+        state: ParseState::HeaderByte1,
+        i: 0,
+        max_fill: usize::MAX,
+    };
+    let r = read_rbsp(reader, dst);
+    if r.is_err() {
+        dst.clear();
+    }
+    r
+}
+
+/// Appends the remaining RBSP bytes of `reader` to `dst`.
+fn read_rbsp(mut reader: ByteReader<&[u8]>, dst: &mut Vec<u8>) -> Result<(), std::io::Error> {
     loop {
         let buf = reader.fill_buf()?;
         if buf.is_empty() {
-            break;
+            return Ok(());
         }
         dst.extend_from_slice(buf);
         let len = buf.len();
         reader.consume(len);
     }
-    Ok(Cow::Owned(dst))
 }
 
 #[derive(Debug)]
@@ -234,6 +273,19 @@ pub enum BitReaderError {
 
     Unaligned,
 }
+impl BitReaderError {
+    /// See [`crate::error_code`].
+    pub fn code(&self) -> u32 {
+        crate::error_code::BIT_READER
+            + match self {
+                BitReaderError::ReaderError(..) => 1,
+                BitReaderError::ReaderErrorFor(..) => 2,
+                BitReaderError::ExpGolombTooLarge(..) => 3,
+                BitReaderError::RemainingData => 4,
+                BitReaderError::Unaligned => 5,
+            }
+    }
+}
 
 pub trait BitRead {
     fn read_ue(&mut self, name: &'static str) -> Result<u32, BitReaderError>;
@@ -244,12 +296,59 @@ pub trait BitRead {
     fn read_u32(&mut self, bit_count: u32, name: &'static str) -> Result<u32, BitReaderError>;
     fn read_i32(&mut self, bit_count: u32, name: &'static str) -> Result<i32, BitReaderError>;
 
+    /// Advances past `bit_count` bits without returning them, e.g. to step over syntax which
+    /// isn't needed.
+    fn skip_bits(&mut self, mut bit_count: u32, name: &'static str) -> Result<(), BitReaderError> {
+        while bit_count > 0 {
+            let n = std::cmp::min(bit_count, 32);
+            self.read_u32(n, name)?;
+            bit_count -= n;
+        }
+        Ok(())
+    }
+
+    /// Advances past a `ue(v)` syntax element without returning it.
+    fn skip_ue(&mut self, name: &'static str) -> Result<(), BitReaderError> {
+        self.read_ue(name).map(|_| ())
+    }
+
+    /// Advances past an `se(v)` syntax element without returning it.
+    fn skip_se(&mut self, name: &'static str) -> Result<(), BitReaderError> {
+        self.read_se(name).map(|_| ())
+    }
+
     /// Returns true if positioned before the RBSP trailing bits.
     ///
     /// This matches the definition of `more_rbsp_data()` in Rec. ITU-T H.264
     /// (03/2010) section 7.2.
     fn has_more_rbsp_data(&mut self, name: &'static str) -> Result<bool, BitReaderError>;
 
+    /// Returns true if positioned at a byte boundary, which [`BitRead::read_byte_alignment`]
+    /// reads up to.
+    fn byte_aligned(&self) -> bool;
+
+    /// Reads the `byte_alignment()` syntax: a one bit followed by zero bits up to the next
+    /// byte boundary.
+    fn read_byte_alignment(&mut self) -> Result<(), BitReaderError> {
+        if !self.read_bool("alignment_bit_equal_to_one")? {
+            return Err(BitReaderError::Unaligned);
+        }
+        while !self.byte_aligned() {
+            if self.read_bool("alignment_bit_equal_to_zero")? {
+                return Err(BitReaderError::Unaligned);
+            }
+        }
+        Ok(())
+    }
+
+    /// Marks the start of a syntax structure such as `vui_parameters()`, for readers which
+    /// report structure; others ignore it. Each call is followed by one to
+    /// [`BitRead::exit_structure`], even when reading the structure fails.
+    fn enter_structure(&mut self, _name: &'static str) {}
+
+    /// Marks the end of the innermost structure entered.
+    fn exit_structure(&mut self) {}
+
     /// Consumes the reader, returning error if it's not positioned at the RBSP trailing bits.
     fn finish_rbsp(self) -> Result<(), BitReaderError>;
 
@@ -260,28 +359,436 @@ pub trait BitRead {
     fn finish_sei_payload(self) -> Result<(), BitReaderError>;
 }
 
+/// Reads a syntax structure with `f`, bracketed by [`BitRead::enter_structure`] and
+/// [`BitRead::exit_structure`].
+pub(crate) fn read_structure<R: BitRead, T, E>(
+    r: &mut R,
+    name: &'static str,
+    f: impl FnOnce(&mut R) -> Result<T, E>,
+) -> Result<T, E> {
+    r.enter_structure(name);
+    let result = f(r);
+    r.exit_structure();
+    result
+}
+
+/// Syntax this crate doesn't parse, kept bit for bit so it can be written back unchanged.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct RawBits {
+    bytes: Vec<u8>,
+    len: usize,
+}
+impl RawBits {
+    /// Reads the bits up to the `rbsp_trailing_bits()`.
+    pub(crate) fn read_to_trailing_bits<R: BitRead>(
+        r: &mut R,
+        name: &'static str,
+    ) -> Result<Self, BitReaderError> {
+        let mut bits = RawBits::default();
+        while r.has_more_rbsp_data(name)? {
+            bits.push(r.read_bool(name)?);
+        }
+        Ok(bits)
+    }
+
+    pub fn push(&mut self, bit: bool) {
+        if self.len.is_multiple_of(8) {
+            self.bytes.push(0);
+        }
+        if bit {
+            *self.bytes.last_mut().expect("pushed above") |= 0x80 >> (self.len % 8);
+        }
+        self.len += 1;
+    }
+
+    /// The number of bits.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<bool> {
+        (index < self.len).then(|| self.bytes[index / 8] & (0x80 >> (index % 8)) != 0)
+    }
+
+    /// The bits packed into bytes, most significant first, with a final partial byte padded
+    /// with zero bits.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for RawBits {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let bits: Vec<bool> = u.arbitrary()?;
+        let mut raw = RawBits::default();
+        for bit in bits {
+            raw.push(bit);
+        }
+        Ok(raw)
+    }
+}
+
+/// How a syntax element is coded, as in the descriptor column of the syntax tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Descriptor {
+    /// `u(n)`, an unsigned integer of `n` bits. Flags are `u(1)`.
+    U(u32),
+    /// `i(n)`, a signed integer of `n` bits.
+    I(u32),
+    /// `ue(v)`, an unsigned Exp-Golomb-coded integer.
+    Ue,
+    /// `se(v)`, a signed Exp-Golomb-coded integer.
+    Se,
+}
+
+/// A syntax element as read, reported to a [`SyntaxVisitor`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxElement {
+    pub name: &'static str,
+    pub descriptor: Descriptor,
+    pub value: i64,
+    /// The bits it occupies, counted from where the [`VisitingReader`] started reading, see
+    /// [`VisitingReader::starting_at`].
+    pub bits: std::ops::Range<u64>,
+}
+
+/// Receives the syntax elements read through a [`VisitingReader`], for collecting whatever a
+/// tool needs without parsing code of its own.
+pub trait SyntaxVisitor {
+    fn element(&mut self, element: &SyntaxElement);
+
+    /// Called at the start of a syntax structure such as `profile_tier_level`, `position` bits
+    /// in.
+    fn enter(&mut self, _name: &'static str, _position: u64) {}
+
+    /// Called at the end of the innermost structure entered.
+    fn exit(&mut self, _position: u64) {}
+}
+impl<F: FnMut(&SyntaxElement)> SyntaxVisitor for F {
+    fn element(&mut self, element: &SyntaxElement) {
+        (self)(element)
+    }
+}
+
+/// A [`BitRead`] wrapper which reports each syntax element read to a [`SyntaxVisitor`].
+///
+/// Elements skipped over by the parser are reported too, in pieces of up to 32 bits. Bit
+/// positions are counted from the syntax elements read, which is exact as each value has a
+/// single `ue(v)` or `se(v)` coding. The parsers mark the structures within NAL units, such as
+/// `vui_parameters()`, which are reported through [`SyntaxVisitor::enter`] and
+/// [`SyntaxVisitor::exit`].
+///
+/// ```
+/// use hevc_reader::rbsp::{BitRead, BitReader, Descriptor, SyntaxElement, VisitingReader};
+/// let mut elements = vec![];
+/// let mut visitor = |e: &SyntaxElement| elements.push((e.name, e.descriptor, e.value, e.bits.clone()));
+/// let mut r = VisitingReader::new(BitReader::new(&[0b1010_0110][..]), &mut visitor);
+/// r.read_bool("flag").unwrap();
+/// r.read_ue("value").unwrap();
+/// r.read_u8(2, "bits").unwrap();
+/// assert_eq!(
+///     elements,
+///     [
+///         ("flag", Descriptor::U(1), 1, 0..1),
+///         ("value", Descriptor::Ue, 1, 1..4),
+///         ("bits", Descriptor::U(2), 1, 4..6),
+///     ]
+/// );
+/// ```
+pub struct VisitingReader<'a, R: BitRead, V: SyntaxVisitor + ?Sized> {
+    inner: R,
+    pos: u64,
+    visitor: &'a mut V,
+}
+impl<'a, R: BitRead, V: SyntaxVisitor + ?Sized> VisitingReader<'a, R, V> {
+    pub fn new(inner: R, visitor: &'a mut V) -> Self {
+        VisitingReader {
+            inner,
+            pos: 0,
+            visitor,
+        }
+    }
+
+    /// Counts positions from `position` rather than 0, e.g. to follow on from syntax read by
+    /// another reader.
+    pub fn starting_at(mut self, position: u64) -> Self {
+        self.pos = position;
+        self
+    }
+
+    /// The number of bits read so far.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn visit<T: Into<i64> + Copy>(
+        &mut self,
+        name: &'static str,
+        descriptor: Descriptor,
+        value: T,
+        bit_count: u32,
+    ) -> T {
+        let start = self.pos;
+        self.pos += u64::from(bit_count);
+        self.visitor.element(&SyntaxElement {
+            name,
+            descriptor,
+            value: value.into(),
+            bits: start..self.pos,
+        });
+        value
+    }
+}
+impl<R: BitRead, V: SyntaxVisitor + ?Sized> BitRead for VisitingReader<'_, R, V> {
+    fn read_ue(&mut self, name: &'static str) -> Result<u32, BitReaderError> {
+        let v = self.inner.read_ue(name)?;
+        Ok(self.visit(name, Descriptor::Ue, v, ue_len(v) as u32))
+    }
+    fn read_se(&mut self, name: &'static str) -> Result<i32, BitReaderError> {
+        let v = self.inner.read_se(name)?;
+        Ok(self.visit(name, Descriptor::Se, v, ue_len(signed_to_golomb(v)) as u32))
+    }
+    fn read_bool(&mut self, name: &'static str) -> Result<bool, BitReaderError> {
+        let v = self.inner.read_bool(name)?;
+        self.visit(name, Descriptor::U(1), u8::from(v), 1);
+        Ok(v)
+    }
+    fn read_u8(&mut self, bit_count: u32, name: &'static str) -> Result<u8, BitReaderError> {
+        let v = self.inner.read_u8(bit_count, name)?;
+        Ok(self.visit(name, Descriptor::U(bit_count), v, bit_count))
+    }
+    fn read_u16(&mut self, bit_count: u32, name: &'static str) -> Result<u16, BitReaderError> {
+        let v = self.inner.read_u16(bit_count, name)?;
+        Ok(self.visit(name, Descriptor::U(bit_count), v, bit_count))
+    }
+    fn read_u32(&mut self, bit_count: u32, name: &'static str) -> Result<u32, BitReaderError> {
+        let v = self.inner.read_u32(bit_count, name)?;
+        Ok(self.visit(name, Descriptor::U(bit_count), v, bit_count))
+    }
+    fn read_i32(&mut self, bit_count: u32, name: &'static str) -> Result<i32, BitReaderError> {
+        let v = self.inner.read_i32(bit_count, name)?;
+        Ok(self.visit(name, Descriptor::I(bit_count), v, bit_count))
+    }
+    fn has_more_rbsp_data(&mut self, name: &'static str) -> Result<bool, BitReaderError> {
+        self.inner.has_more_rbsp_data(name)
+    }
+    fn byte_aligned(&self) -> bool {
+        self.inner.byte_aligned()
+    }
+    fn enter_structure(&mut self, name: &'static str) {
+        self.inner.enter_structure(name);
+        self.visitor.enter(name, self.pos);
+    }
+    fn exit_structure(&mut self) {
+        self.inner.exit_structure();
+        self.visitor.exit(self.pos);
+    }
+    fn finish_rbsp(self) -> Result<(), BitReaderError> {
+        self.inner.finish_rbsp()
+    }
+    fn finish_sei_payload(self) -> Result<(), BitReaderError> {
+        self.inner.finish_sei_payload()
+    }
+}
+
+/// A [`BufRead`] over a sequence of byte slices, read as if they were concatenated, for NAL units
+/// held in several buffers such as ring buffer segments or transport stream packet payloads.
+///
+/// Wrap it in a [`ByteReader`] for NAL bytes, then in a [`BitReader`]:
+///
+/// ```
+/// # use hevc_reader::rbsp::{BitRead, BitReader, ByteReader, ChainedReader};
+/// // A NAL unit with an emulation prevention byte, split just after it.
+/// let chunks = [&[0x40, 0x01, 0x00, 0x00, 0x03][..], &[0x01, 0x80]];
+/// let mut r = BitReader::new(ByteReader::new(ChainedReader::new(&chunks)));
+/// assert_eq!(r.read_u32(24, "value").unwrap(), 0x000001);
+/// r.finish_rbsp().unwrap();
+/// ```
+#[derive(Clone)]
+pub struct ChainedReader<'a> {
+    chunks: &'a [&'a [u8]],
+    /// The position within `chunks[0]`.
+    pos: usize,
+}
+impl<'a> ChainedReader<'a> {
+    pub fn new(chunks: &'a [&'a [u8]]) -> Self {
+        let mut reader = ChainedReader { chunks, pos: 0 };
+        reader.skip_exhausted();
+        reader
+    }
+
+    /// Moves past any exhausted or empty chunks.
+    fn skip_exhausted(&mut self) {
+        while let [first, rest @ ..] = self.chunks {
+            if self.pos < first.len() {
+                break;
+            }
+            self.chunks = rest;
+            self.pos = 0;
+        }
+    }
+}
+impl Read for ChainedReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let chunk = self.fill_buf()?;
+        let amt = std::cmp::min(buf.len(), chunk.len());
+        buf[..amt].copy_from_slice(&chunk[..amt]);
+        self.consume(amt);
+        Ok(amt)
+    }
+}
+impl BufRead for ChainedReader<'_> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        Ok(self.chunks.first().map_or(&[][..], |c| &c[self.pos..]))
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+        self.skip_exhausted();
+    }
+}
+
 /// Reads H.264 bitstream syntax elements from an RBSP representation (no NAL
 /// header byte or emulation prevention three bytes).
+///
+/// Bits are read from a 64 bit cache of up to eight bytes of the underlying reader's buffer,
+/// which is refilled a word at a time. Bytes are only consumed from the underlying reader once
+/// all their bits are read.
+#[derive(Clone)]
 pub struct BitReader<R: std::io::BufRead + Clone> {
-    reader: bitstream_io::read::BitReader<R, bitstream_io::BigEndian>,
+    inner: R,
+    /// The unread bits of the cache, most significant first, followed by zeros.
+    cache: u64,
+    /// The number of unread bits in the cache.
+    avail: u32,
+    /// The number of bytes of `inner`'s buffer loaded into the cache, not yet consumed.
+    loaded: usize,
 }
 impl<R: std::io::BufRead + Clone> BitReader<R> {
     pub fn new(inner: R) -> Self {
         Self {
-            reader: bitstream_io::read::BitReader::new(inner),
+            inner,
+            cache: 0,
+            avail: 0,
+            loaded: 0,
         }
     }
 
     /// Borrows the underlying reader if byte-aligned.
     pub fn reader(&mut self) -> Option<&mut R> {
-        self.reader.reader()
+        if !self.avail.is_multiple_of(8) {
+            return None;
+        }
+        self.inner.consume(self.loaded - self.avail as usize / 8);
+        self.cache = 0;
+        self.avail = 0;
+        self.loaded = 0;
+        Some(&mut self.inner)
+    }
+
+    /// Consumes the fully read cache from the underlying reader and loads the next bytes.
+    fn refill(&mut self) -> std::io::Result<()> {
+        debug_assert_eq!(self.avail, 0);
+        self.inner.consume(self.loaded);
+        self.loaded = 0;
+        let buf = self.inner.fill_buf()?;
+        if buf.is_empty() {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        let n = std::cmp::min(buf.len(), 8);
+        let mut word = [0u8; 8];
+        word[..n].copy_from_slice(&buf[..n]);
+        self.cache = u64::from_be_bytes(word);
+        self.avail = n as u32 * 8;
+        self.loaded = n;
+        Ok(())
+    }
+
+    /// Reads up to 32 bits.
+    fn read_bits(&mut self, mut bit_count: u32) -> std::io::Result<u32> {
+        debug_assert!(bit_count <= 32);
+        let mut v = 0u64;
+        while bit_count > 0 {
+            if self.avail == 0 {
+                self.refill()?;
+            }
+            let n = std::cmp::min(bit_count, self.avail);
+            v = v << n | self.cache >> (64 - n);
+            self.cache <<= n;
+            self.avail -= n;
+            bit_count -= n;
+        }
+        Ok(v as u32)
+    }
+
+    /// Reads zero bits up to and including a one bit, returning the number of zero bits.
+    fn read_unary1(&mut self) -> std::io::Result<u32> {
+        let mut count = 0;
+        loop {
+            if self.avail == 0 {
+                self.refill()?;
+            }
+            // The bits after the unread ones are zero, so this may exceed `avail`.
+            let zeros = self.cache.leading_zeros();
+            if zeros < self.avail {
+                self.cache <<= zeros;
+                self.cache <<= 1;
+                self.avail -= zeros + 1;
+                return Ok(count + zeros);
+            }
+            count += self.avail;
+            self.cache = 0;
+            self.avail = 0;
+        }
+    }
+
+    /// Reads `bit_count` bits into a value of `max_bits` bits.
+    fn read_checked(
+        &mut self,
+        bit_count: u32,
+        max_bits: u32,
+        name: &'static str,
+    ) -> Result<u32, BitReaderError> {
+        if bit_count > max_bits {
+            return Err(BitReaderError::ReaderErrorFor(
+                name,
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "excessive bits for type read",
+                ),
+            ));
+        }
+        self.read_bits(bit_count)
+            .map_err(|e| BitReaderError::ReaderErrorFor(name, e))
+    }
+}
+impl<'a> BitReader<ByteReader<&'a [u8]>> {
+    /// Reads the RBSP of a complete NAL unit, skipping its header and removing emulation
+    /// prevention bytes as it goes, rather than copying the RBSP up front as [`decode_nal`] does.
+    ///
+    /// ```
+    /// # use hevc_reader::rbsp::{BitRead, BitReader};
+    /// let nal = [0x40, 0x01, 0x00, 0x00, 0x03, 0x01, 0x80];
+    /// let mut r = BitReader::from_nal(&nal);
+    /// assert_eq!(r.read_u32(24, "value").unwrap(), 0x000001);
+    /// r.finish_rbsp().unwrap();
+    /// ```
+    pub fn from_nal(nal: &'a [u8]) -> Self {
+        Self::new(ByteReader::new(nal))
     }
 }
 
 impl<R: std::io::BufRead + Clone> BitRead for BitReader<R> {
     fn read_ue(&mut self, name: &'static str) -> Result<u32, BitReaderError> {
         let count = self
-            .reader
             .read_unary1()
             .map_err(|e| BitReaderError::ReaderErrorFor(name, e))?;
         if count > 31 {
@@ -299,39 +806,81 @@ impl<R: std::io::BufRead + Clone> BitRead for BitReader<R> {
     }
 
     fn read_bool(&mut self, name: &'static str) -> Result<bool, BitReaderError> {
-        self.reader
-            .read_bit()
-            .map_err(|e| BitReaderError::ReaderErrorFor(name, e))
+        Ok(self.read_checked(1, 1, name)? == 1)
     }
 
     fn read_u8(&mut self, bit_count: u32, name: &'static str) -> Result<u8, BitReaderError> {
-        self.reader
-            .read(bit_count)
-            .map_err(|e| BitReaderError::ReaderErrorFor(name, e))
+        Ok(self.read_checked(bit_count, 8, name)? as u8)
     }
 
     fn read_u16(&mut self, bit_count: u32, name: &'static str) -> Result<u16, BitReaderError> {
-        self.reader
-            .read(bit_count)
-            .map_err(|e| BitReaderError::ReaderErrorFor(name, e))
+        Ok(self.read_checked(bit_count, 16, name)? as u16)
     }
 
     fn read_u32(&mut self, bit_count: u32, name: &'static str) -> Result<u32, BitReaderError> {
-        self.reader
-            .read(bit_count)
-            .map_err(|e| BitReaderError::ReaderErrorFor(name, e))
+        self.read_checked(bit_count, 32, name)
     }
 
     fn read_i32(&mut self, bit_count: u32, name: &'static str) -> Result<i32, BitReaderError> {
-        self.reader
-            .read(bit_count)
+        let v = self.read_checked(bit_count, 32, name)?;
+        if bit_count == 0 {
+            return Ok(0);
+        }
+        // Sign-extend from bit_count bits.
+        let shift = 32 - bit_count;
+        Ok(((v << shift) as i32) >> shift)
+    }
+
+    fn skip_bits(&mut self, mut bit_count: u32, name: &'static str) -> Result<(), BitReaderError> {
+        let n = std::cmp::min(bit_count, self.avail);
+        self.cache = self.cache.checked_shl(n).unwrap_or(0);
+        self.avail -= n;
+        bit_count -= n;
+        if bit_count == 0 {
+            return Ok(());
+        }
+        // The cache is fully read, so whole bytes can be skipped in the underlying reader.
+        self.inner.consume(self.loaded);
+        self.loaded = 0;
+        let mut bytes = bit_count as usize / 8;
+        while bytes > 0 {
+            let buf = self
+                .inner
+                .fill_buf()
+                .map_err(|e| BitReaderError::ReaderErrorFor(name, e))?;
+            if buf.is_empty() {
+                return Err(BitReaderError::ReaderErrorFor(
+                    name,
+                    std::io::ErrorKind::UnexpectedEof.into(),
+                ));
+            }
+            let n = std::cmp::min(buf.len(), bytes);
+            self.inner.consume(n);
+            bytes -= n;
+        }
+        self.read_bits(bit_count % 8)
+            .map(|_| ())
             .map_err(|e| BitReaderError::ReaderErrorFor(name, e))
     }
 
+    fn skip_ue(&mut self, name: &'static str) -> Result<(), BitReaderError> {
+        let count = self
+            .read_unary1()
+            .map_err(|e| BitReaderError::ReaderErrorFor(name, e))?;
+        if count > 31 {
+            return Err(BitReaderError::ExpGolombTooLarge(name));
+        }
+        self.skip_bits(count, name)
+    }
+
+    fn skip_se(&mut self, name: &'static str) -> Result<(), BitReaderError> {
+        self.skip_ue(name)
+    }
+
     fn has_more_rbsp_data(&mut self, name: &'static str) -> Result<bool, BitReaderError> {
-        let mut throwaway = self.reader.clone();
+        let mut throwaway = self.clone();
         let r = (move || {
-            throwaway.skip(1)?;
+            throwaway.read_bits(1)?;
             throwaway.read_unary1()?;
             Ok::<_, std::io::Error>(())
         })();
@@ -342,21 +891,26 @@ impl<R: std::io::BufRead + Clone> BitRead for BitReader<R> {
         }
     }
 
+    fn byte_aligned(&self) -> bool {
+        // The cache is loaded from byte boundaries.
+        self.avail.is_multiple_of(8)
+    }
+
     fn finish_rbsp(mut self) -> Result<(), BitReaderError> {
         // The next bit is expected to be the final one bit.
-        if !self
-            .reader
-            .read_bit()
+        if self
+            .read_bits(1)
             .map_err(|e| BitReaderError::ReaderErrorFor("finish", e))?
+            == 0
         {
             // It was a zero! Determine if we're past the end or haven't reached it yet.
-            match self.reader.read_unary1() {
+            match self.read_unary1() {
                 Err(e) => return Err(BitReaderError::ReaderErrorFor("finish", e)),
                 Ok(_) => return Err(BitReaderError::RemainingData),
             }
         }
         // All remaining bits in the stream must then be zeros.
-        match self.reader.read_unary1() {
+        match self.read_unary1() {
             Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(()),
             Err(e) => Err(BitReaderError::ReaderErrorFor("finish", e)),
             Ok(_) => Err(BitReaderError::RemainingData),
@@ -364,23 +918,416 @@ impl<R: std::io::BufRead + Clone> BitRead for BitReader<R> {
     }
 
     fn finish_sei_payload(mut self) -> Result<(), BitReaderError> {
-        match self.reader.read_bit() {
+        match self.read_bits(1) {
             Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
             Err(e) => return Err(BitReaderError::ReaderErrorFor("finish", e)),
-            Ok(false) => return Err(BitReaderError::RemainingData),
-            Ok(true) => {}
+            Ok(0) => return Err(BitReaderError::RemainingData),
+            Ok(_) => {}
         }
-        match self.reader.read_unary1() {
+        match self.read_unary1() {
             Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(()),
             Err(e) => Err(BitReaderError::ReaderErrorFor("finish", e)),
             Ok(_) => Err(BitReaderError::RemainingData),
         }
     }
 }
+/// A [`BitRead`] which pulls bytes from an [`io::Read`](std::io::Read) as needed, for parsing
+/// straight off a socket or file handle.
+///
+/// The source must end where the RBSP does, e.g. via [`Read::take`], so the trailing bits can be
+/// found. For NAL bytes, wrap the source in a [`ByteReader`] first:
+///
+/// ```
+/// # use hevc_reader::nal::sps::SeqParameterSet;
+/// # use hevc_reader::rbsp::{BitReader, ByteReader, ReadBitReader};
+/// # use std::io::{BufReader, Read};
+/// # let sps = [
+/// #     0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03, 0x00, 0x00,
+/// #     0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46, 0xd1, 0x2e,
+/// #     0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10, 0x00, 0x00, 0x03,
+/// #     0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00, 0x0b, 0xb8, 0x48,
+/// # ];
+/// # let mut file = &sps[..];
+/// // `file` is positioned at an SPS NAL unit of a known length.
+/// let nal = BufReader::new(file.by_ref().take(sps.len() as u64));
+/// let parsed = SeqParameterSet::from_bits(ReadBitReader::new(ByteReader::new(nal))).unwrap();
+/// assert_eq!(parsed.pixel_dimensions().unwrap(), (720, 576));
+/// ```
+pub struct ReadBitReader<R: Read> {
+    inner: R,
+    /// Bytes read from `inner` and not yet discarded.
+    buf: Vec<u8>,
+    /// The bit position in `buf`.
+    pos: usize,
+}
+impl<R: Read> ReadBitReader<R> {
+    pub fn new(inner: R) -> Self {
+        ReadBitReader {
+            inner,
+            buf: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// Returns the underlying reader. Bytes already pulled from it are lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Reads from `inner` until `bit_count` bits past the position are buffered, returning
+    /// false if it ends first.
+    fn fill(&mut self, bit_count: usize) -> std::io::Result<bool> {
+        if self.pos + bit_count <= self.buf.len() * 8 {
+            return Ok(true);
+        }
+        // Discard the bytes already read.
+        self.buf.drain(..self.pos / 8);
+        self.pos %= 8;
+        while self.buf.len() * 8 < self.pos + bit_count {
+            let len = self.buf.len();
+            self.buf.resize(len + 256, 0);
+            let n = loop {
+                match self.inner.read(&mut self.buf[len..]) {
+                    Ok(n) => break n,
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                    Err(e) => {
+                        self.buf.truncate(len);
+                        return Err(e);
+                    }
+                }
+            };
+            self.buf.truncate(len + n);
+            if n == 0 {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// The bit `offset` bits past the position, which must be buffered.
+    fn peek(&self, offset: usize) -> bool {
+        let i = self.pos + offset;
+        self.buf[i / 8] & (0x80 >> (i % 8)) != 0
+    }
+
+    /// Reads up to 32 bits.
+    fn read_bits(&mut self, bit_count: u32) -> std::io::Result<u32> {
+        if !self.fill(bit_count as usize)? {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        let mut v = 0;
+        for i in 0..bit_count as usize {
+            v = v << 1 | u32::from(self.peek(i));
+        }
+        self.pos += bit_count as usize;
+        Ok(v)
+    }
+
+    /// Reads zero bits up to and including a one bit, returning the number of zero bits.
+    fn read_unary1(&mut self) -> std::io::Result<u32> {
+        let mut count = 0;
+        while self.read_bits(1)? == 0 {
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn read_checked(
+        &mut self,
+        bit_count: u32,
+        max_bits: u32,
+        name: &'static str,
+    ) -> Result<u32, BitReaderError> {
+        if bit_count > max_bits {
+            return Err(BitReaderError::ReaderErrorFor(
+                name,
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "excessive bits for type read",
+                ),
+            ));
+        }
+        self.read_bits(bit_count)
+            .map_err(|e| BitReaderError::ReaderErrorFor(name, e))
+    }
+}
+impl<R: Read> BitRead for ReadBitReader<R> {
+    fn read_ue(&mut self, name: &'static str) -> Result<u32, BitReaderError> {
+        let count = self
+            .read_unary1()
+            .map_err(|e| BitReaderError::ReaderErrorFor(name, e))?;
+        if count > 31 {
+            Err(BitReaderError::ExpGolombTooLarge(name))
+        } else if count > 0 {
+            let val = self.read_u32(count, name)?;
+            Ok((1 << count) - 1 + val)
+        } else {
+            Ok(0)
+        }
+    }
+
+    fn read_se(&mut self, name: &'static str) -> Result<i32, BitReaderError> {
+        Ok(golomb_to_signed(self.read_ue(name)?))
+    }
+
+    fn read_bool(&mut self, name: &'static str) -> Result<bool, BitReaderError> {
+        Ok(self.read_checked(1, 1, name)? == 1)
+    }
+
+    fn read_u8(&mut self, bit_count: u32, name: &'static str) -> Result<u8, BitReaderError> {
+        Ok(self.read_checked(bit_count, 8, name)? as u8)
+    }
+
+    fn read_u16(&mut self, bit_count: u32, name: &'static str) -> Result<u16, BitReaderError> {
+        Ok(self.read_checked(bit_count, 16, name)? as u16)
+    }
+
+    fn read_u32(&mut self, bit_count: u32, name: &'static str) -> Result<u32, BitReaderError> {
+        self.read_checked(bit_count, 32, name)
+    }
+
+    fn read_i32(&mut self, bit_count: u32, name: &'static str) -> Result<i32, BitReaderError> {
+        let v = self.read_checked(bit_count, 32, name)?;
+        if bit_count == 0 {
+            return Ok(0);
+        }
+        let shift = 32 - bit_count;
+        Ok(((v << shift) as i32) >> shift)
+    }
+
+    fn has_more_rbsp_data(&mut self, name: &'static str) -> Result<bool, BitReaderError> {
+        // Look for a one bit after the next bit, buffering as far as needed.
+        let mut offset = 1;
+        loop {
+            match self.fill(offset + 1) {
+                Ok(true) if self.peek(offset) => return Ok(true),
+                Ok(true) => offset += 1,
+                Ok(false) => return Ok(false),
+                Err(e) => return Err(BitReaderError::ReaderErrorFor(name, e)),
+            }
+        }
+    }
+
+    fn byte_aligned(&self) -> bool {
+        self.pos.is_multiple_of(8)
+    }
+
+    fn finish_rbsp(mut self) -> Result<(), BitReaderError> {
+        // The next bit is expected to be the final one bit.
+        if self
+            .read_bits(1)
+            .map_err(|e| BitReaderError::ReaderErrorFor("finish", e))?
+            == 0
+        {
+            match self.read_unary1() {
+                Err(e) => return Err(BitReaderError::ReaderErrorFor("finish", e)),
+                Ok(_) => return Err(BitReaderError::RemainingData),
+            }
+        }
+        match self.read_unary1() {
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(()),
+            Err(e) => Err(BitReaderError::ReaderErrorFor("finish", e)),
+            Ok(_) => Err(BitReaderError::RemainingData),
+        }
+    }
+
+    fn finish_sei_payload(mut self) -> Result<(), BitReaderError> {
+        match self.read_bits(1) {
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(BitReaderError::ReaderErrorFor("finish", e)),
+            Ok(0) => return Err(BitReaderError::RemainingData),
+            Ok(_) => {}
+        }
+        match self.read_unary1() {
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(()),
+            Err(e) => Err(BitReaderError::ReaderErrorFor("finish", e)),
+            Ok(_) => Err(BitReaderError::RemainingData),
+        }
+    }
+}
+
+/// Writes H.265 bitstream syntax elements into an RBSP representation, the counterpart of
+/// [`BitReader`]. Use [`encode_nal`] to turn the result back into a NAL unit.
+///
+/// ```
+/// # use hevc_reader::rbsp::{BitRead, BitReader, BitWriter};
+/// let mut w = BitWriter::new();
+/// w.write_ue(7);
+/// w.write_se(-2);
+/// w.write_bool(true);
+/// w.write_rbsp_trailing_bits();
+/// let rbsp = w.into_bytes();
+/// let mut r = BitReader::new(&rbsp[..]);
+/// assert_eq!(r.read_ue("a").unwrap(), 7);
+/// assert_eq!(r.read_se("b").unwrap(), -2);
+/// assert!(r.read_bool("c").unwrap());
+/// r.finish_rbsp().unwrap();
+/// ```
+#[cfg(feature = "write")]
+#[derive(Clone, Debug, Default)]
+pub struct BitWriter {
+    buf: Vec<u8>,
+    /// Number of bits used in the last byte of `buf`, or 0 if byte-aligned.
+    partial_bits: u32,
+}
+#[cfg(feature = "write")]
+impl BitWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes the low `bit_count` bits of `value`, most significant first.
+    fn write_bits(&mut self, bit_count: u32, value: u64) {
+        debug_assert!(bit_count <= 64);
+        for i in (0..bit_count).rev() {
+            if self.partial_bits == 0 {
+                self.buf.push(0);
+            }
+            if (value >> i) & 1 != 0 {
+                *self.buf.last_mut().unwrap() |= 0x80 >> self.partial_bits;
+            }
+            self.partial_bits = (self.partial_bits + 1) % 8;
+        }
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_bits(1, value.into());
+    }
+
+    pub fn write_u8(&mut self, bit_count: u32, value: u8) {
+        debug_assert!(bit_count <= 8);
+        self.write_bits(bit_count, value.into());
+    }
+
+    pub fn write_u16(&mut self, bit_count: u32, value: u16) {
+        debug_assert!(bit_count <= 16);
+        self.write_bits(bit_count, value.into());
+    }
+
+    pub fn write_u32(&mut self, bit_count: u32, value: u32) {
+        debug_assert!(bit_count <= 32);
+        self.write_bits(bit_count, value.into());
+    }
+
+    pub fn write_i32(&mut self, bit_count: u32, value: i32) {
+        debug_assert!(bit_count <= 32);
+        self.write_bits(bit_count, value as u32 as u64 & ((1u64 << bit_count) - 1));
+    }
+
+    pub fn write_ue(&mut self, value: u32) {
+        let v = u64::from(value) + 1;
+        let len = 64 - v.leading_zeros();
+        self.write_bits(len - 1, 0);
+        self.write_bits(len, v);
+    }
+
+    pub fn write_se(&mut self, value: i32) {
+        self.write_ue(signed_to_golomb(value));
+    }
+
+    /// Copies the bits `start_bit..end_bit` of `src`, counted from the most significant bit
+    /// of the first byte.
+    pub fn write_bits_from(&mut self, src: &[u8], start_bit: usize, end_bit: usize) {
+        let mut i = start_bit;
+        while i < end_bit && !i.is_multiple_of(8) {
+            self.write_bool(src[i / 8] & (0x80 >> (i % 8)) != 0);
+            i += 1;
+        }
+        while i + 8 <= end_bit {
+            self.write_bits(8, src[i / 8].into());
+            i += 8;
+        }
+        while i < end_bit {
+            self.write_bool(src[i / 8] & (0x80 >> (i % 8)) != 0);
+            i += 1;
+        }
+    }
+
+    /// Writes bits kept by the parser, as they were read.
+    pub fn write_raw_bits(&mut self, bits: &RawBits) {
+        self.write_bits_from(&bits.bytes, 0, bits.len);
+    }
+
+    /// Returns true if positioned at a byte boundary.
+    pub fn byte_aligned(&self) -> bool {
+        self.partial_bits == 0
+    }
+
+    /// The number of bits written so far.
+    pub fn bit_len(&self) -> usize {
+        self.buf.len() * 8
+            - if self.partial_bits == 0 {
+                0
+            } else {
+                8 - self.partial_bits as usize
+            }
+    }
+
+    /// Writes the `byte_alignment()` syntax: a one bit followed by zero bits up to the next
+    /// byte boundary.
+    pub fn write_byte_alignment(&mut self) {
+        self.write_bool(true);
+        self.partial_bits = 0;
+    }
+
+    /// Writes `rbsp_trailing_bits()`, which has the same form as `byte_alignment()`.
+    pub fn write_rbsp_trailing_bits(&mut self) {
+        self.write_byte_alignment();
+    }
+
+    /// Returns the written bytes, padding a final partial byte with zero bits.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// Encodes a NAL unit from its header bytes and RBSP, inserting _emulation prevention_ bytes
+/// where needed. The inverse of [`decode_nal`].
+///
+/// ```
+/// # use hevc_reader::rbsp::{decode_nal, encode_nal};
+/// let rbsp = [0x12, 0x00, 0x00, 0x01, 0x80, 0x00, 0x00];
+/// let nal = encode_nal(&[0x44, 0x01], &rbsp);
+/// assert_eq!(nal, &[0x44, 0x01, 0x12, 0x00, 0x00, 0x03, 0x01, 0x80, 0x00, 0x00, 0x03]);
+/// assert_eq!(&*decode_nal(&nal).unwrap(), &rbsp);
+/// ```
+#[cfg(feature = "write")]
+pub fn encode_nal(header: &[u8], rbsp: &[u8]) -> Vec<u8> {
+    let mut nal = Vec::with_capacity(header.len() + rbsp.len() + rbsp.len() / 64 + 1);
+    nal.extend_from_slice(header);
+    let mut zeros = 0;
+    for &b in rbsp {
+        if zeros >= 2 && b <= 3 {
+            nal.push(3);
+            zeros = 0;
+        }
+        nal.push(b);
+        zeros = if b == 0 { zeros + 1 } else { 0 };
+    }
+    // The NAL unit may not end with a zero byte, as happens after cabac_zero_words.
+    if zeros >= 2 {
+        nal.push(3);
+    }
+    nal
+}
+
+/// The length in bits of `value` coded as ue(v).
+pub(crate) fn ue_len(value: u32) -> usize {
+    let v = u64::from(value) + 1;
+    2 * (63 - v.leading_zeros() as usize) + 1
+}
+
 fn golomb_to_signed(val: u32) -> i32 {
     let sign = (((val & 0x1) as i32) << 1) - 1;
     ((val >> 1) as i32 + (val & 0x1) as i32) * sign
 }
+fn signed_to_golomb(val: i32) -> u32 {
+    if val > 0 {
+        (val as u32) * 2 - 1
+    } else {
+        val.unsigned_abs() * 2
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -416,6 +1363,42 @@ mod tests {
     }
     */
 
+    #[test]
+    fn chained_reader() {
+        let nal = hex_literal::hex!(
+            "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46
+             d1 2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00
+             0b b8 48"
+        );
+        let expected = decode_nal(&nal).unwrap();
+        for i in 0..nal.len() {
+            for j in i..nal.len() {
+                let chunks = [&nal[..i], &nal[i..j], &[][..], &nal[j..]];
+                let mut rbsp = vec![];
+                ByteReader::new(ChainedReader::new(&chunks))
+                    .read_to_end(&mut rbsp)
+                    .unwrap();
+                assert_eq!(rbsp, &*expected, "split at {} and {}", i, j);
+            }
+        }
+    }
+
+    #[test]
+    fn emulation_prevention_past_max_fill() {
+        // An emulation prevention byte further into the NAL unit than one fill of the reader.
+        let mut nal = vec![0x40, 0x01];
+        nal.extend_from_slice(&[0xaa; 130]);
+        nal.extend_from_slice(&[0x00, 0x00, 0x03, 0x01, 0x80]);
+        let mut rbsp = vec![];
+        ByteReader::new(&nal[..]).read_to_end(&mut rbsp).unwrap();
+        assert_eq!(rbsp.len(), 134);
+        assert_eq!(rbsp, &*decode_nal(&nal).unwrap());
+        let mut r = BitReader::from_nal(&nal);
+        r.skip_bits(130 * 8, "data").unwrap();
+        assert_eq!(r.read_u32(24, "value").unwrap(), 0x000001);
+        r.finish_rbsp().unwrap();
+    }
+
     #[test]
     fn bitreader_has_more_data() {
         // Should work when the end bit is byte-aligned.
@@ -437,6 +1420,94 @@ mod tests {
             .unwrap());
     }
 
+    #[test]
+    #[cfg(feature = "write")]
+    fn bitwriter_copy_bits() {
+        let src = [0b1011_0110, 0b0101_1100, 0b1110_0001];
+        for start in 0..24 {
+            for end in start..=24 {
+                let mut w = BitWriter::new();
+                w.write_bits(start as u32 % 5, 0b10101);
+                w.write_bits_from(&src, start, end);
+                let len = w.bit_len();
+                assert_eq!(len, start % 5 + end - start);
+                let out = w.into_bytes();
+                let mut r = BitReader::new(&out[..]);
+                r.read_u8(start as u32 % 5, "prefix").unwrap();
+                for i in start..end {
+                    let expected = src[i / 8] & (0x80 >> (i % 8)) != 0;
+                    assert_eq!(r.read_bool("bit").unwrap(), expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bitreader_across_chunks() {
+        let data = [
+            0x00, 0x01, 0xff, 0xfe, 0xe5, 0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc,
+        ];
+        for i in 0..data.len() {
+            let chunks = [&data[..i], &data[i..]];
+            let mut r = BitReader::new(ChainedReader::new(&chunks));
+            // 15 zero bits, a one and a 15 bit suffix of ones.
+            assert_eq!(r.read_ue("ue").unwrap(), 65534, "split at {}", i);
+            assert!(!r.read_bool("bool").unwrap());
+            assert_eq!(r.read_i32(4, "i32").unwrap(), -2);
+            assert_eq!(r.read_u32(28, "u32").unwrap(), 0x5123456);
+            assert!(r.read_u8(9, "u8").is_err());
+            assert!(r.byte_aligned());
+            assert_eq!(r.reader().unwrap().fill_buf().unwrap()[0], 0x78);
+            assert_eq!(r.read_u16(16, "u16").unwrap(), 0x789a);
+        }
+    }
+
+    #[test]
+    fn skip() {
+        let data = [
+            0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0, 0x11, 0x22, 0x33, 0x44,
+        ];
+        for i in 0..data.len() {
+            let chunks = [&data[..i], &data[i..]];
+            let mut r = BitReader::new(ChainedReader::new(&chunks));
+            r.skip_bits(4, "a").unwrap();
+            r.skip_bits(60, "b").unwrap();
+            // 0x11: a ue(v) of three zero bits, a one and three bits of suffix, then a one bit se(v).
+            r.skip_ue("c").unwrap();
+            r.skip_se("d").unwrap();
+            assert_eq!(r.read_u8(7, "e").unwrap(), 0x11, "split at {}", i);
+            assert!(r.clone().skip_bits(18, "f").is_err());
+            r.skip_bits(17, "g").unwrap();
+            r.finish_sei_payload().unwrap();
+        }
+    }
+
+    #[test]
+    fn read_bit_reader() {
+        /// Returns one byte per read.
+        struct Trickle<'a>(&'a [u8]);
+        impl Read for Trickle<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let n = self.0.len().min(buf.len()).min(1);
+                self.0.read(&mut buf[..n])
+            }
+        }
+        let mut r = ReadBitReader::new(Trickle(&[0x12, 0x80, 0x00, 0x00]));
+        assert!(r.has_more_rbsp_data("call 1").unwrap());
+        assert_eq!(r.read_ue("ue").unwrap(), 8);
+        assert_eq!(r.read_u8(1, "u8").unwrap(), 0);
+        assert!(r.byte_aligned());
+        assert!(!r.has_more_rbsp_data("call 2").unwrap());
+        r.finish_rbsp().unwrap();
+
+        let mut r = ReadBitReader::new(Trickle(&[0xff]));
+        assert!(r.read_u16(9, "u16").is_err());
+        assert!(matches!(
+            r.finish_rbsp(),
+            Err(BitReaderError::RemainingData)
+        ));
+    }
+
     #[test]
     fn read_ue_overflow() {
         let mut reader = BitReader::new(&[0, 0, 0, 0, 255, 255, 255, 255, 255][..]);
@@ -445,4 +1516,38 @@ mod tests {
             Err(BitReaderError::ExpGolombTooLarge("test"))
         ));
     }
+
+    #[test]
+    fn visit_sps() {
+        // "Intinor HW encode 720x576p" SPS.
+        let nal = hex_literal::hex!(
+            "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46
+             d1 2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00
+             0b b8 48"
+        );
+        let mut elements = vec![];
+        let mut visitor = |e: &SyntaxElement| elements.push(e.clone());
+        let r = VisitingReader::new(BitReader::from_nal(&nal), &mut visitor);
+        crate::nal::sps::SeqParameterSet::from_bits(r).unwrap();
+        assert_eq!(
+            elements[0],
+            SyntaxElement {
+                name: "sps_video_parameter_set_id",
+                descriptor: Descriptor::U(4),
+                value: 0,
+                bits: 0..4,
+            }
+        );
+        let width = elements
+            .iter()
+            .find(|e| e.name == "pic_width_in_luma_samples")
+            .unwrap();
+        assert_eq!((width.descriptor, width.value), (Descriptor::Ue, 736));
+        assert!(elements
+            .windows(2)
+            .all(|w| w[0].bits.end == w[1].bits.start));
+        // Everything up to the trailing bits, which start in the fifth bit of the last of the
+        // 51 bytes of RBSP.
+        assert_eq!(elements.last().unwrap().bits.end, 50 * 8 + 4);
+    }
 }
@@ -0,0 +1,121 @@
+//! Removal of identifying metadata, e.g. for sharing sample streams in bug reports.
+
+use std::borrow::Cow;
+
+use crate::nal::sei::{HeaderType, SeiMessage};
+use crate::nal::{NalHeader, UnitType};
+use crate::rbsp::BitReaderError;
+use crate::rewrite::sei_strip::SeiStripper;
+use crate::rewrite::{FilterError, NalFilter};
+
+/// Filter which removes metadata that may identify the encoder or the source of a stream,
+/// leaving everything needed for decoding untouched:
+///
+/// * `user_data_unregistered()` SEI messages, where encoders such as x265 put their version
+///   and settings.
+/// * `time_code()` SEI messages, which may tell when the content was recorded.
+/// * NAL units of the unspecified types 48 to 63, which are used for proprietary data.
+///
+/// ITU-T T.35 registered user data, which also carries closed captions and dynamic HDR
+/// metadata, is only removed if enabled with [`Anonymizer::strip_registered_user_data`].
+///
+/// ```
+/// use hevc_reader::rewrite::anonymize::Anonymizer;
+/// let mut anonymizer = Anonymizer::new();
+/// // A prefix SEI with only a user_data_unregistered() message, and an unspecified NAL unit.
+/// assert_eq!(anonymizer.process(&[0x4e, 0x01, 0x05, 0x01, 0x42, 0x80]).unwrap(), None);
+/// assert_eq!(anonymizer.process(&[0x60, 0x01, 0x12, 0x34]).unwrap(), None);
+/// let idr = [0x26, 0x01, 0xac, 0x9c];
+/// assert_eq!(&*anonymizer.process(&idr).unwrap().unwrap(), &idr);
+/// assert_eq!(anonymizer.removed_sei_count(), 1);
+/// assert_eq!(anonymizer.dropped_nal_count(), 1);
+/// ```
+pub struct Anonymizer {
+    stripper: SeiStripper,
+    dropped: u64,
+}
+impl Default for Anonymizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Anonymizer {
+    pub fn new() -> Self {
+        Anonymizer {
+            stripper: Self::stripper(false),
+            dropped: 0,
+        }
+    }
+
+    /// Also removes `user_data_registered_itu_t_t35()` SEI messages.
+    pub fn strip_registered_user_data(mut self) -> Self {
+        self.stripper = Self::stripper(true);
+        self
+    }
+
+    fn stripper(registered_user_data: bool) -> SeiStripper {
+        SeiStripper::new(move |msg: &SeiMessage<'_>| match msg.payload_type {
+            HeaderType::UserDataUnregistered | HeaderType::TimeCode => true,
+            HeaderType::UserDataRegisteredItuTT35 => registered_user_data,
+            _ => false,
+        })
+    }
+
+    /// The number of SEI messages removed so far.
+    pub fn removed_sei_count(&self) -> u64 {
+        self.stripper.removed_count()
+    }
+
+    /// The number of NAL units of unspecified types dropped so far. SEI NAL units left without
+    /// messages aren't counted.
+    pub fn dropped_nal_count(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Filters a NAL unit (without start code or length prefix), returning `None` if it should
+    /// be dropped.
+    pub fn process<'a>(&mut self, nal: &'a [u8]) -> Result<Option<Cow<'a, [u8]>>, BitReaderError> {
+        let unit_type = nal
+            .first()
+            .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())
+            .map(|h| h.nal_unit_type());
+        if let Some(UnitType::Unspecified(_)) = unit_type {
+            self.dropped += 1;
+            return Ok(None);
+        }
+        self.stripper.process(nal)
+    }
+}
+
+impl NalFilter for Anonymizer {
+    fn filter(&mut self, nal: &[u8], out: &mut dyn FnMut(&[u8])) -> Result<(), FilterError> {
+        if let Some(nal) = self.process(nal)? {
+            out(&nal);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn registered_user_data() {
+        // A suffix SEI with a T.35 message followed by a decoded_picture_hash() with a checksum.
+        let sei = [
+            0x50, 0x01, 0x04, 0x03, 0xb5, 0x00, 0x31, 0x84, 0x05, 0x02, 0x11, 0x22, 0x33, 0x44,
+            0x80,
+        ];
+        let mut anonymizer = Anonymizer::new();
+        assert!(matches!(
+            anonymizer.process(&sei).unwrap(),
+            Some(Cow::Borrowed(_))
+        ));
+        let mut anonymizer = Anonymizer::new().strip_registered_user_data();
+        assert_eq!(
+            &*anonymizer.process(&sei).unwrap().unwrap(),
+            &[0x50, 0x01, 0x84, 0x05, 0x02, 0x11, 0x22, 0x33, 0x44, 0x80]
+        );
+    }
+}
@@ -0,0 +1,321 @@
+//! Detection and removal of repeated parameter sets, e.g. to reduce overhead when storing a
+//! broadcast stream that repeats its VPS/SPS/PPS before every IRAP picture.
+
+use crate::nal::pps::PicParameterSet;
+use crate::nal::sps::SeqParameterSet;
+use crate::nal::vps::VideoParameterSet;
+use crate::nal::{NalHeader, UnitType};
+use crate::rbsp::{decode_nal, BitReader};
+use crate::rewrite::{FilterError, NalFilter};
+use crate::Context;
+
+/// How parameter sets are compared to decide whether one repeats an earlier one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    /// The RBSP bytes must be identical.
+    Bytes,
+    /// The parsed syntax structures must be equal, so e.g. differences in `*_extension_data_flag`
+    /// bits or redundant signalling of inferred values are ignored.
+    Semantic,
+}
+
+/// The outcome of [`ParamSetDedup::classify`] for one NAL unit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Repetition {
+    /// Not a parameter set.
+    NotParamSet,
+    /// The first parameter set with its id, or one with new content.
+    New,
+    /// A repeat of the current parameter set, kept as a periodic refresh.
+    Refresh,
+    /// A repeat of the current parameter set, which can be dropped.
+    Redundant,
+}
+impl Repetition {
+    /// True unless the NAL unit is a redundant parameter set.
+    pub fn keep(self) -> bool {
+        self != Repetition::Redundant
+    }
+}
+
+#[derive(Clone)]
+struct Emitted {
+    rbsp: Vec<u8>,
+    /// Value of `ParamSetDedup::iraps` when this parameter set was last kept.
+    irap: u64,
+}
+
+/// Filter which drops parameter sets that repeat the currently stored parameter set with the
+/// same id.
+///
+/// A parameter set with new content is always kept, and invalidates the stored parameter sets
+/// which refer to it (so e.g. the PPS following a changed SPS is kept too). Parameter sets
+/// which can't be parsed are always kept.
+///
+/// ```
+/// use hevc_reader::rewrite::dedup::{Comparison, ParamSetDedup, Repetition};
+/// // An x265 VPS.
+/// let vps = [
+///     0x40, 0x01, 0x0c, 0x01, 0xff, 0xff, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00,
+///     0x03, 0x00, 0x00, 0x03, 0x00, 0x5d, 0x95, 0x98, 0x09,
+/// ];
+/// let mut dedup = ParamSetDedup::new(Comparison::Bytes);
+/// assert_eq!(dedup.classify(&vps), Repetition::New);
+/// assert_eq!(dedup.classify(&vps), Repetition::Redundant);
+/// ```
+pub struct ParamSetDedup {
+    comparison: Comparison,
+    refresh_interval: Option<u64>,
+    ctx: Context,
+    vps: Vec<Option<Emitted>>,
+    sps: Vec<Option<Emitted>>,
+    pps: Vec<Option<Emitted>>,
+    /// The number of IRAP pictures seen so far.
+    iraps: u64,
+    redundant: u64,
+}
+impl ParamSetDedup {
+    pub fn new(comparison: Comparison) -> Self {
+        ParamSetDedup {
+            comparison,
+            refresh_interval: None,
+            ctx: Context::new(),
+            vps: vec![None; 16],
+            sps: vec![None; 16],
+            pps: vec![None; 64],
+            iraps: 0,
+            redundant: 0,
+        }
+    }
+
+    /// Keeps a repeated parameter set if at least `iraps` IRAP pictures have passed since it was
+    /// last kept, so that the output stream can still be joined at (some) random access points.
+    pub fn with_refresh_interval(mut self, iraps: u64) -> Self {
+        self.refresh_interval = Some(iraps);
+        self
+    }
+
+    /// The number of NAL units classified as [`Repetition::Redundant`] so far.
+    pub fn redundant_count(&self) -> u64 {
+        self.redundant
+    }
+
+    /// Classifies a NAL unit (without start code or length prefix), updating the stored
+    /// parameter sets and the IRAP count.
+    pub fn classify(&mut self, nal: &[u8]) -> Repetition {
+        let Some(header) = nal
+            .first()
+            .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())
+        else {
+            return Repetition::NotParamSet;
+        };
+        let unit_type = header.nal_unit_type();
+        if unit_type.is_irap() && nal.get(2).is_some_and(|b| b & 0x80 != 0) {
+            // first_slice_segment_in_pic_flag
+            self.iraps += 1;
+        }
+        if !matches!(
+            unit_type,
+            UnitType::VideoParameterSet | UnitType::SeqParameterSet | UnitType::PicParameterSet
+        ) {
+            return Repetition::NotParamSet;
+        }
+        let Ok(rbsp) = decode_nal(nal) else {
+            return Repetition::New;
+        };
+        let result = match unit_type {
+            UnitType::VideoParameterSet => self.classify_vps(&rbsp),
+            UnitType::SeqParameterSet => self.classify_sps(&rbsp),
+            _ => self.classify_pps(&rbsp),
+        };
+        if result == Repetition::Redundant {
+            self.redundant += 1;
+        }
+        result
+    }
+
+    fn classify_vps(&mut self, rbsp: &[u8]) -> Repetition {
+        let Ok(vps) = VideoParameterSet::from_bits(BitReader::new(rbsp)) else {
+            return Repetition::New;
+        };
+        let id = vps.id();
+        let same = self.ctx.vps_by_id(id) == Some(&vps);
+        let result = self.classify_slot(SlotKind::Vps, id.id().into(), rbsp, same);
+        if result == Repetition::New {
+            for (i, sps) in self
+                .ctx
+                .sps()
+                .map(|sps| (sps.id(), sps.sps_video_parameter_set_id))
+            {
+                if sps == id {
+                    self.sps[i.id() as usize] = None;
+                }
+            }
+            self.ctx.put_video_param_set(vps);
+        }
+        result
+    }
+
+    fn classify_sps(&mut self, rbsp: &[u8]) -> Repetition {
+        let Ok(mut sps) = SeqParameterSet::from_bits(BitReader::new(rbsp)) else {
+            return Repetition::New;
+        };
+        if let Some(ext) = sps.sps_extension.as_mut() {
+            if !ext.sps_multilayer_extension_flag && !ext.sps_3d_extension_flag {
+                // Decoders ignore sps_extension_data_flag.
+                ext.extension_data = None;
+            }
+        }
+        let id = sps.id();
+        let same = self.ctx.sps_by_id(id) == Some(&sps);
+        let result = self.classify_slot(SlotKind::Sps, id.id().into(), rbsp, same);
+        if result == Repetition::New {
+            for (i, pps) in self
+                .ctx
+                .pps()
+                .map(|pps| (pps.id(), pps.seq_parameter_set_id))
+            {
+                if pps == id {
+                    self.pps[i.id() as usize] = None;
+                }
+            }
+            self.ctx.put_seq_param_set(sps);
+        }
+        result
+    }
+
+    fn classify_pps(&mut self, rbsp: &[u8]) -> Repetition {
+        let Ok(mut pps) = PicParameterSet::from_bits(&self.ctx, BitReader::new(rbsp)) else {
+            return Repetition::New;
+        };
+        if let Some(ext) = pps.pps_extension.as_mut() {
+            if !ext.pps_multilayer_extension_flag && !ext.pps_3d_extension_flag {
+                // Decoders ignore pps_extension_data_flag.
+                ext.extension_data = None;
+            }
+        }
+        let id = pps.id();
+        let same = self.ctx.pps_by_id(id) == Some(&pps);
+        let result = self.classify_slot(SlotKind::Pps, id.id().into(), rbsp, same);
+        if result == Repetition::New {
+            self.ctx.put_pic_param_set(pps);
+        }
+        result
+    }
+
+    fn classify_slot(
+        &mut self,
+        kind: SlotKind,
+        id: usize,
+        rbsp: &[u8],
+        semantically_same: bool,
+    ) -> Repetition {
+        let iraps = self.iraps;
+        let slot = match kind {
+            SlotKind::Vps => &mut self.vps[id],
+            SlotKind::Sps => &mut self.sps[id],
+            SlotKind::Pps => &mut self.pps[id],
+        };
+        let repeat = slot.as_ref().is_some_and(|emitted| match self.comparison {
+            Comparison::Bytes => emitted.rbsp == rbsp,
+            Comparison::Semantic => semantically_same,
+        });
+        if !repeat {
+            *slot = Some(Emitted {
+                rbsp: rbsp.to_vec(),
+                irap: iraps,
+            });
+            return Repetition::New;
+        }
+        let emitted = slot.as_mut().unwrap();
+        match self.refresh_interval {
+            Some(interval) if iraps - emitted.irap >= interval => {
+                emitted.irap = iraps;
+                Repetition::Refresh
+            }
+            _ => Repetition::Redundant,
+        }
+    }
+}
+
+impl NalFilter for ParamSetDedup {
+    fn filter(&mut self, nal: &[u8], out: &mut dyn FnMut(&[u8])) -> Result<(), FilterError> {
+        if self.classify(nal).keep() {
+            out(nal);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
+enum SlotKind {
+    Vps,
+    Sps,
+    Pps,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hex_literal::hex;
+
+    // "Intinor HW encode 720x576p" SPS, x265 PPS and the start of an IDR slice segment.
+    const SPS: [u8; 59] = hex!(
+        "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46 d1
+         2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00 0b
+         b8 48"
+    );
+    const PPS: [u8; 7] = hex!("44 01 c1 72 b4 62 40");
+    const IDR: [u8; 4] = hex!("26 01 ac 9c");
+
+    #[test]
+    fn refresh_interval() {
+        let mut dedup = ParamSetDedup::new(Comparison::Bytes).with_refresh_interval(2);
+        let mut kept = vec![];
+        for _ in 0..5 {
+            kept.push((dedup.classify(&SPS), dedup.classify(&PPS)));
+            assert_eq!(dedup.classify(&IDR), Repetition::NotParamSet);
+        }
+        use Repetition::*;
+        assert_eq!(
+            kept,
+            [
+                (New, New),
+                (Redundant, Redundant),
+                (Refresh, Refresh),
+                (Redundant, Redundant),
+                (Refresh, Refresh),
+            ]
+        );
+        assert_eq!(dedup.redundant_count(), 4);
+    }
+
+    #[test]
+    fn semantic() {
+        // The x265 PPS with pps_extension_4bits set, followed by two different runs of
+        // pps_extension_data_flag, which decoders ignore.
+        let pps_a = hex!("44 01 c1 72 b4 62 80 d8");
+        let pps_b = hex!("44 01 c1 72 b4 62 80 f0");
+        let mut bytes = ParamSetDedup::new(Comparison::Bytes);
+        let mut semantic = ParamSetDedup::new(Comparison::Semantic);
+        for dedup in [&mut bytes, &mut semantic] {
+            assert_eq!(dedup.classify(&SPS), Repetition::New);
+            assert_eq!(dedup.classify(&pps_a), Repetition::New);
+        }
+        assert_eq!(bytes.classify(&pps_b), Repetition::New);
+        assert_eq!(semantic.classify(&pps_b), Repetition::Redundant);
+    }
+
+    #[test]
+    fn changed_sps_invalidates_pps() {
+        let mut dedup = ParamSetDedup::new(Comparison::Bytes);
+        let mut sps = SPS;
+        dedup.classify(&sps);
+        dedup.classify(&PPS);
+        // Change general_level_idc.
+        sps[17] = 0x5a;
+        assert_eq!(dedup.classify(&sps), Repetition::New);
+        assert_eq!(dedup.classify(&PPS), Repetition::New);
+        assert_eq!(dedup.classify(&PPS), Repetition::Redundant);
+    }
+}
@@ -0,0 +1,180 @@
+//! Repair of NAL units with broken emulation prevention, as some hardware encoders produce.
+//!
+//! Within a NAL unit, `0x000000`, `0x000001` and `0x000002` must be escaped with an emulation
+//! prevention byte as `0x00000300` and so on, and `0x000003` itself as `0x00000303`. Encoders
+//! get this wrong in two ways: by not escaping at all, leaving the sequences in the NAL unit,
+//! or by inserting a needless `0x03`, leaving `0x000003` followed by a byte above 3. Both make
+//! the NAL unit unreadable to a strict parser such as [`crate::rbsp::decode_nal`].
+//!
+//! [`repair`] reads such a NAL unit leniently and escapes its RBSP again with
+//! [`crate::rbsp::encode_nal`], which is also the way to escape an RBSP from scratch. In Annex B
+//! streams an unescaped `0x000001` looks like a start code and splits the NAL unit, so only NAL
+//! units framed otherwise (e.g. length-prefixed) can be repaired in full.
+
+use crate::rbsp::encode_nal;
+use crate::rewrite::{FilterError, NalFilter};
+
+/// The kinds of [`EscapeIssue`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EscapeIssueKind {
+    /// `0x000000`, `0x000001` or `0x000002`, which should have been escaped.
+    Unescaped,
+    /// `0x000003` followed by a byte above 3, which escaping never produces.
+    StrayThree,
+}
+
+/// A byte sequence which correct emulation prevention doesn't produce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EscapeIssue {
+    pub kind: EscapeIssueKind,
+    /// The offset of the sequence within the NAL unit, counting the header.
+    pub offset: usize,
+}
+
+/// How to read a [`EscapeIssueKind::StrayThree`] sequence.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StrayThree {
+    /// The `0x03` was inserted needlessly: drop it, as lenient decoders do.
+    #[default]
+    Drop,
+    /// The `0x03` is data which wasn't escaped: keep it, and escape it.
+    Keep,
+}
+
+/// Finds the sequences of a NAL unit (without start code or length prefix) which correct
+/// emulation prevention doesn't produce. A `0x000003` at the very end, protecting trailing
+/// `cabac_zero_words`, is fine.
+pub fn scan(nal: &[u8]) -> Vec<EscapeIssue> {
+    let mut issues = vec![];
+    for i in 2..nal.len() {
+        let kind = match nal[i..] {
+            [0, 0, 0..=2, ..] => EscapeIssueKind::Unescaped,
+            [0, 0, 3, b, ..] if b > 3 => EscapeIssueKind::StrayThree,
+            _ => continue,
+        };
+        issues.push(EscapeIssue { kind, offset: i });
+    }
+    issues
+}
+
+/// Reads the RBSP of a NAL unit, keeping unescaped sequences as they are and treating stray
+/// `0x03` bytes as `stray` says.
+pub fn lenient_rbsp(nal: &[u8], stray: StrayThree) -> Vec<u8> {
+    let payload = nal.get(2..).unwrap_or_default();
+    let mut rbsp = Vec::with_capacity(payload.len());
+    let mut zeros = 0;
+    for (i, &b) in payload.iter().enumerate() {
+        if zeros >= 2 && b == 3 {
+            let escape = match payload.get(i + 1) {
+                None | Some(0..=3) => true,
+                Some(_) => stray == StrayThree::Drop,
+            };
+            if escape {
+                zeros = 0;
+                continue;
+            }
+        }
+        rbsp.push(b);
+        zeros = if b == 0 { zeros + 1 } else { 0 };
+    }
+    rbsp
+}
+
+/// Returns the NAL unit escaped correctly, or `None` if it already is (or has no header).
+///
+/// ```
+/// use hevc_reader::rewrite::escape::{repair, StrayThree};
+/// // A PPS with `0x000001` left unescaped.
+/// let nal = [0x44, 0x01, 0xc1, 0x00, 0x00, 0x01, 0x40];
+/// assert_eq!(
+///     repair(&nal, StrayThree::Drop).unwrap(),
+///     [0x44, 0x01, 0xc1, 0x00, 0x00, 0x03, 0x01, 0x40]
+/// );
+/// assert_eq!(repair(&[0x44, 0x01, 0xc1, 0x40], StrayThree::Drop), None);
+/// ```
+pub fn repair(nal: &[u8], stray: StrayThree) -> Option<Vec<u8>> {
+    if nal.len() < 2 || scan(nal).is_empty() {
+        return None;
+    }
+    Some(encode_nal(&nal[..2], &lenient_rbsp(nal, stray)))
+}
+
+/// A [`NalFilter`] which [`repair`]s each NAL unit, counting those it changed.
+#[derive(Debug, Default)]
+pub struct EscapeRepair {
+    stray: StrayThree,
+    repaired: u64,
+}
+impl EscapeRepair {
+    pub fn new(stray: StrayThree) -> Self {
+        EscapeRepair { stray, repaired: 0 }
+    }
+
+    /// The number of NAL units repaired so far.
+    pub fn repaired(&self) -> u64 {
+        self.repaired
+    }
+}
+impl NalFilter for EscapeRepair {
+    fn filter(&mut self, nal: &[u8], out: &mut dyn FnMut(&[u8])) -> Result<(), FilterError> {
+        match repair(nal, self.stray) {
+            Some(repaired) => {
+                self.repaired += 1;
+                out(&repaired);
+            }
+            None => out(nal),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rbsp::decode_nal;
+
+    #[test]
+    fn stray_three() {
+        // An SEI NAL unit with `0x000000` unescaped, then `0x00000304`, and a correctly
+        // escaped `0x000003` at the end.
+        let nal = [
+            0x4e, 0x01, 0x05, 0x00, 0x00, 0x00, 0x42, 0x00, 0x00, 0x03, 0x04, 0x00, 0x00, 0x03,
+        ];
+        assert_eq!(
+            scan(&nal),
+            [
+                EscapeIssue {
+                    kind: EscapeIssueKind::Unescaped,
+                    offset: 3
+                },
+                EscapeIssue {
+                    kind: EscapeIssueKind::StrayThree,
+                    offset: 7
+                },
+            ]
+        );
+        assert!(decode_nal(&nal).is_err());
+        let dropped = repair(&nal, StrayThree::Drop).unwrap();
+        assert_eq!(
+            &*decode_nal(&dropped).unwrap(),
+            [0x05, 0x00, 0x00, 0x00, 0x42, 0x00, 0x00, 0x04, 0x00, 0x00]
+        );
+        let kept = repair(&nal, StrayThree::Keep).unwrap();
+        assert_eq!(
+            &*decode_nal(&kept).unwrap(),
+            [0x05, 0x00, 0x00, 0x00, 0x42, 0x00, 0x00, 0x03, 0x04, 0x00, 0x00]
+        );
+        assert!(scan(&kept).is_empty());
+
+        let mut filter = EscapeRepair::default();
+        let mut output = vec![];
+        filter
+            .filter(&nal, &mut |n| output.push(n.to_vec()))
+            .unwrap();
+        filter
+            .filter(&[0x26, 0x01, 0xac, 0x9c], &mut |n| output.push(n.to_vec()))
+            .unwrap();
+        assert_eq!(output, [dropped, vec![0x26, 0x01, 0xac, 0x9c]]);
+        assert_eq!(filter.repaired(), 1);
+    }
+}
@@ -0,0 +1,99 @@
+//! Writing of Annex B byte streams with a choice of start codes.
+//!
+//! A four byte start code is required before parameter sets and the first NAL unit of each
+//! access unit, and optional elsewhere (clause B.2.2). Some decoders are picky about it either
+//! way, and comparing a rewritten stream against its input is easier with the input's framing
+//! kept, so [`AnnexBWriter`] offers all three.
+
+use crate::annexb::Framing;
+use crate::nal::AccessUnitTracker;
+use crate::nal::{NalHeader, UnitType};
+
+/// Which start codes an [`AnnexBWriter`] writes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StartCodes {
+    /// Four byte start codes before every NAL unit.
+    #[default]
+    Four,
+    /// Four byte start codes where required, before parameter sets and the first NAL unit of
+    /// each access unit, and three byte ones elsewhere.
+    Minimal,
+    /// The start codes and trailing zero bytes the NAL units had in the input, or as
+    /// [`StartCodes::Minimal`] for NAL units without a [`Framing`].
+    Preserve,
+}
+
+/// Writes NAL units as an Annex B byte stream.
+///
+/// ```
+/// use hevc_reader::rewrite::framing::{AnnexBWriter, StartCodes};
+/// let mut writer = AnnexBWriter::new(StartCodes::Minimal);
+/// // A PPS and an IDR slice segment starting one access unit, and a trailing slice segment
+/// // starting the next; then a suffix SEI NAL unit.
+/// writer.push(&[0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40], None);
+/// writer.push(&[0x26, 0x01, 0xac, 0x9c], None);
+/// writer.push(&[0x02, 0x01, 0xd0, 0x6c, 0x27, 0x80], None);
+/// writer.push(&[0x50, 0x01, 0x05, 0x01, 0x42, 0x80], None);
+/// let stream = writer.into_inner();
+/// assert_eq!(&stream[..4], [0, 0, 0, 1]);
+/// assert_eq!(&stream[11..14], [0, 0, 1]);
+/// assert_eq!(&stream[18..22], [0, 0, 0, 1]);
+/// assert_eq!(&stream[28..31], [0, 0, 1]);
+/// ```
+#[derive(Default)]
+pub struct AnnexBWriter {
+    start_codes: StartCodes,
+    tracker: AccessUnitTracker,
+    out: Vec<u8>,
+}
+impl AnnexBWriter {
+    pub fn new(start_codes: StartCodes) -> Self {
+        AnnexBWriter {
+            start_codes,
+            ..Self::default()
+        }
+    }
+
+    /// Like [`Self::new`], reserving space for `capacity` bytes.
+    pub fn with_capacity(start_codes: StartCodes, capacity: usize) -> Self {
+        AnnexBWriter {
+            start_codes,
+            out: Vec::with_capacity(capacity),
+            ..Self::default()
+        }
+    }
+
+    /// Appends a NAL unit (without start code), with its framing in the input if any.
+    pub fn push(&mut self, nal: &[u8], framing: Option<Framing>) {
+        let unit_type = nal
+            .first()
+            .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())
+            .map(|h| h.nal_unit_type());
+        let first = unit_type.is_some_and(|t| self.tracker.push(t, nal));
+        let required = first
+            || matches!(
+                unit_type,
+                Some(
+                    UnitType::VideoParameterSet
+                        | UnitType::SeqParameterSet
+                        | UnitType::PicParameterSet
+                )
+            );
+        let (zero_byte, trailing_zeros) = match (self.start_codes, framing) {
+            (StartCodes::Four, _) => (true, 0),
+            (StartCodes::Preserve, Some(framing)) => (framing.zero_byte, framing.trailing_zeros),
+            (StartCodes::Minimal | StartCodes::Preserve, _) => (required, 0),
+        };
+        if zero_byte {
+            self.out.push(0);
+        }
+        self.out.extend_from_slice(&[0, 0, 1]);
+        self.out.extend_from_slice(nal);
+        self.out.resize(self.out.len() + trailing_zeros, 0);
+    }
+
+    /// The stream written so far.
+    pub fn into_inner(self) -> Vec<u8> {
+        self.out
+    }
+}
@@ -0,0 +1,151 @@
+//! Insertion of HDR static metadata SEI messages, for streams where it is missing or wrong.
+
+use crate::nal::sei::content_light_level::ContentLightLevelInfo;
+use crate::nal::sei::mastering_display_colour_volume::MasteringDisplayColourVolume;
+use crate::nal::sei::HeaderType;
+use crate::nal::{NalHeader, UnitType};
+use crate::rbsp::{encode_nal, BitReaderError};
+use crate::rewrite::sei_strip::SeiStripper;
+use crate::rewrite::{FilterError, NalFilter};
+
+/// Filter which puts the given `mastering_display_colour_volume()` and/or
+/// `content_light_level_info()` messages in a prefix SEI NAL unit before the first slice segment
+/// of every IRAP picture.
+///
+/// Existing messages of the same types are removed from the whole stream, so the given values
+/// replace them.
+///
+/// ```
+/// use hevc_reader::nal::sei::content_light_level::ContentLightLevelInfo;
+/// use hevc_reader::rewrite::hdr::HdrSeiInserter;
+/// let cll = ContentLightLevelInfo {
+///     max_content_light_level: 1000,
+///     max_pic_average_light_level: 400,
+/// };
+/// let mut inserter = HdrSeiInserter::new(None, Some(cll));
+/// let mut out = vec![];
+/// inserter.process(&[0x26, 0x01, 0xac, 0x9c], |nal| out.push(nal.to_vec())).unwrap();
+/// assert_eq!(
+///     out,
+///     [&[0x4e, 0x01, 0x90, 0x04, 0x03, 0xe8, 0x01, 0x90, 0x80][..], &[0x26, 0x01, 0xac, 0x9c]]
+/// );
+/// ```
+pub struct HdrSeiInserter {
+    stripper: SeiStripper,
+    /// The prefix SEI NAL unit to insert, or empty if there are no messages.
+    sei: Vec<u8>,
+    inserted: u64,
+}
+impl HdrSeiInserter {
+    pub fn new(
+        mastering_display: Option<&MasteringDisplayColourVolume>,
+        content_light_level: Option<ContentLightLevelInfo>,
+    ) -> Self {
+        let mut types = vec![];
+        let mut rbsp = vec![];
+        if let Some(mdcv) = mastering_display {
+            mdcv.to_message(&mdcv.to_payload()).write_to(&mut rbsp);
+            types.push(HeaderType::MasteringDisplayColourVolume);
+        }
+        if let Some(cll) = content_light_level {
+            cll.to_message(&cll.to_payload()).write_to(&mut rbsp);
+            types.push(HeaderType::ContentLightLevelInfo);
+        }
+        let sei = if rbsp.is_empty() {
+            vec![]
+        } else {
+            // rbsp_trailing_bits()
+            rbsp.push(0x80);
+            // A prefix SEI NAL unit with nuh_layer_id 0 and TemporalId 0.
+            encode_nal(&[0x4e, 0x01], &rbsp)
+        };
+        HdrSeiInserter {
+            stripper: SeiStripper::payload_types(&types),
+            sei,
+            inserted: 0,
+        }
+    }
+
+    /// The number of SEI NAL units inserted so far.
+    pub fn inserted_count(&self) -> u64 {
+        self.inserted
+    }
+
+    /// The number of existing messages removed so far.
+    pub fn replaced_count(&self) -> u64 {
+        self.stripper.removed_count()
+    }
+
+    /// Passes a NAL unit (without start code or length prefix) to `out`, preceded by the SEI if
+    /// it starts an IRAP picture, and with existing messages of the inserted types removed.
+    pub fn process(
+        &mut self,
+        nal: &[u8],
+        mut out: impl FnMut(&[u8]),
+    ) -> Result<(), BitReaderError> {
+        let unit_type = nal
+            .first()
+            .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())
+            .map(|h| h.nal_unit_type());
+        let first_slice_segment_in_pic_flag = nal.get(2).is_some_and(|b| b & 0x80 != 0);
+        if unit_type.is_some_and(UnitType::is_irap)
+            && first_slice_segment_in_pic_flag
+            && !self.sei.is_empty()
+        {
+            out(&self.sei);
+            self.inserted += 1;
+        }
+        if let Some(nal) = self.stripper.process(nal)? {
+            out(&nal);
+        }
+        Ok(())
+    }
+}
+
+impl NalFilter for HdrSeiInserter {
+    fn filter(&mut self, nal: &[u8], out: &mut dyn FnMut(&[u8])) -> Result<(), FilterError> {
+        self.process(nal, out)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::nal::sei::SeiReader;
+    use crate::rbsp::decode_nal;
+    use hex_literal::hex;
+
+    #[test]
+    fn replace() {
+        let mdcv = MasteringDisplayColourVolume {
+            display_primaries: [(13250, 34500), (7500, 3000), (34000, 16000)],
+            white_point: (15635, 16450),
+            max_display_mastering_luminance: 40_000_000,
+            min_display_mastering_luminance: 50,
+        };
+        let mut inserter = HdrSeiInserter::new(Some(&mdcv), None);
+        // A prefix SEI with a recovery_point() and an all-zero mastering_display_colour_volume(),
+        // followed by an IDR and a P slice segment.
+        let mut rbsp = vec![0x06, 0x01, 0xc4, 0x89, 0x18];
+        rbsp.extend_from_slice(&[0; 24]);
+        rbsp.push(0x80);
+        let old_sei = encode_nal(&[0x4e, 0x01], &rbsp);
+        let idr = hex!("26 01 ac 9c");
+        let trail = hex!("02 01 d0 6c 27 80");
+        let mut out = vec![];
+        for nal in [&old_sei[..], &idr, &trail] {
+            inserter.process(nal, |nal| out.push(nal.to_vec())).unwrap();
+        }
+        assert_eq!(out.len(), 4);
+        assert_eq!(out[0], [0x4e, 0x01, 0x06, 0x01, 0xc4, 0x80]);
+        let rbsp = decode_nal(&out[1]).unwrap();
+        let mut reader = SeiReader::from_rbsp_bytes(&rbsp);
+        let msg = reader.next().unwrap().unwrap();
+        assert_eq!(MasteringDisplayColourVolume::read(&msg).unwrap(), mdcv);
+        assert!(reader.next().unwrap().is_none());
+        assert_eq!(out[2..], [&idr[..], &trail]);
+        assert_eq!(inserter.inserted_count(), 1);
+        assert_eq!(inserter.replaced_count(), 1);
+    }
+}
@@ -0,0 +1,220 @@
+//! Conversion of MP4 samples between the `hev1` and `hvc1` sample entry types.
+//!
+//! With `hvc1`, parameter sets are carried only in the sample entry's `hvcC` box (as required
+//! by e.g. Apple players), while `hev1` samples may carry them in-band.
+
+use crate::hvcc::{nal_units, push_nal_unit, try_push_nal_unit, SampleError};
+use crate::nal::{NalHeader, UnitType};
+use crate::rewrite::inject::{param_set_id, ParamSetInjector};
+
+/// What [`SampleConverter`] changed in one sample.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SampleReport {
+    /// The parameter set NAL units removed from the sample.
+    pub stripped: Vec<UnitType>,
+    /// The number of parameter set NAL units inserted into the sample.
+    pub injected: usize,
+    /// True if a stripped parameter set differs from the one with the same id in
+    /// [`SampleConverter::param_sets`]. An `hvc1` stream needs a new sample entry from this
+    /// sample on.
+    pub param_set_changed: bool,
+}
+impl SampleReport {
+    pub fn is_unchanged(&self) -> bool {
+        self.stripped.is_empty() && self.injected == 0
+    }
+}
+
+/// Strips or injects parameter sets in length-prefixed samples.
+///
+/// ```
+/// use hevc_reader::rewrite::hvc1::SampleConverter;
+/// let mut converter = SampleConverter::new(4).unwrap();
+/// // An in-band PPS followed by an IDR slice segment.
+/// let sample = [
+///     0, 0, 0, 7, 0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40, 0, 0, 0, 4, 0x26, 0x01, 0xac, 0x9c,
+/// ];
+/// let (hvc1, report) = converter.to_hvc1(&sample).unwrap();
+/// assert_eq!(hvc1, &sample[11..]);
+/// assert_eq!(report.stripped.len(), 1);
+/// let (hev1, report) = converter.to_hev1(&hvc1).unwrap();
+/// assert_eq!(hev1, sample);
+/// assert_eq!(report.injected, 1);
+/// ```
+pub struct SampleConverter {
+    length_size: usize,
+    /// The sample entry's parameter sets, first seen per type and id.
+    param_sets: Vec<(UnitType, usize, Vec<u8>)>,
+    injector: ParamSetInjector,
+}
+impl SampleConverter {
+    /// Creates a converter for samples with `length_size` byte NAL unit lengths
+    /// (`lengthSizeMinusOne + 1`).
+    pub fn new(length_size: usize) -> Result<Self, SampleError> {
+        // Validates the length size.
+        nal_units(&[], length_size)?;
+        Ok(SampleConverter {
+            length_size,
+            param_sets: vec![],
+            injector: ParamSetInjector::new(),
+        })
+    }
+
+    /// Adds a parameter set NAL unit from the sample entry's `hvcC` arrays. Returns false if
+    /// the NAL unit isn't a parameter set.
+    pub fn add_param_set(&mut self, nal: &[u8]) -> bool {
+        let Some((unit_type, id)) = param_set_id(nal) else {
+            return false;
+        };
+        if !self
+            .param_sets
+            .iter()
+            .any(|(t, i, _)| (*t, *i) == (unit_type, id))
+        {
+            self.param_sets.push((unit_type, id, nal.to_vec()));
+        }
+        self.injector.store(nal);
+        true
+    }
+
+    /// The parameter sets for the `hvcC` box: those added by [`Self::add_param_set`], plus
+    /// those first seen in samples passed to [`Self::to_hvc1`].
+    pub fn param_sets(&self) -> impl Iterator<Item = &[u8]> {
+        self.param_sets.iter().map(|(_, _, nal)| &nal[..])
+    }
+
+    /// Converts a `hev1` sample to `hvc1` by removing its parameter sets.
+    pub fn to_hvc1(&mut self, sample: &[u8]) -> Result<(Vec<u8>, SampleReport), SampleError> {
+        let mut out = Vec::with_capacity(sample.len());
+        let mut report = SampleReport::default();
+        for nal in nal_units(sample, self.length_size)? {
+            let nal = nal?;
+            let Some((unit_type, id)) = param_set_id(nal) else {
+                push_nal_unit(&mut out, nal, self.length_size);
+                continue;
+            };
+            match self
+                .param_sets
+                .iter()
+                .find(|(t, i, _)| (*t, *i) == (unit_type, id))
+            {
+                Some((_, _, existing)) => report.param_set_changed |= existing[..] != nal[..],
+                None => self.param_sets.push((unit_type, id, nal.to_vec())),
+            }
+            self.injector.store(nal);
+            report.stripped.push(unit_type);
+        }
+        Ok((out, report))
+    }
+
+    /// Converts an `hvc1` sample to `hev1` by inserting the parameter sets before the first
+    /// slice segment of each IRAP picture which isn't already preceded by some.
+    ///
+    /// In-band parameter sets already in the sample replace the stored ones for later samples.
+    pub fn to_hev1(&mut self, sample: &[u8]) -> Result<(Vec<u8>, SampleReport), SampleError> {
+        let mut out = Vec::with_capacity(sample.len());
+        let mut report = SampleReport::default();
+        let length_size = self.length_size;
+        for nal in nal_units(sample, length_size)? {
+            let nal = nal?;
+            let mut outputs = 0;
+            let mut pushed = Ok(());
+            self.injector.process(nal, |nal| {
+                // Stored parameter sets may be too long for the sample's length size.
+                if pushed.is_ok() {
+                    pushed = try_push_nal_unit(&mut out, nal, length_size);
+                }
+                outputs += 1;
+            });
+            pushed?;
+            report.injected += outputs - 1;
+        }
+        Ok((out, report))
+    }
+}
+
+/// True if the sample contains the first slice segment of an IRAP picture, i.e. it is a sync
+/// sample.
+pub fn is_sync_sample(sample: &[u8], length_size: usize) -> Result<bool, SampleError> {
+    for nal in nal_units(sample, length_size)? {
+        let nal = nal?;
+        let Some(header) = nal
+            .first()
+            .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())
+        else {
+            continue;
+        };
+        if header.nal_unit_type().is_irap() && nal.get(2).is_some_and(|b| b & 0x80 != 0) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hex_literal::hex;
+
+    const PPS: [u8; 7] = hex!("44 01 c1 72 b4 62 40");
+    const IDR: [u8; 4] = hex!("26 01 ac 9c");
+    const TRAIL: [u8; 6] = hex!("02 01 d0 6c 27 80");
+
+    fn sample(nals: &[&[u8]]) -> Vec<u8> {
+        let mut sample = vec![];
+        for nal in nals {
+            push_nal_unit(&mut sample, nal, 2);
+        }
+        sample
+    }
+
+    #[test]
+    fn changed_param_set() {
+        let mut converter = SampleConverter::new(2).unwrap();
+        assert!(converter.add_param_set(&PPS));
+        let (out, report) = converter.to_hvc1(&sample(&[&PPS, &IDR])).unwrap();
+        assert_eq!(out, sample(&[&IDR]));
+        assert!(!report.param_set_changed);
+        let (out, report) = converter.to_hvc1(&sample(&[&TRAIL])).unwrap();
+        assert_eq!(out, sample(&[&TRAIL]));
+        assert!(report.is_unchanged());
+        // A PPS with the same id but different content (init_qp_minus26 changed).
+        let pps2 = hex!("44 01 c1 72 b4 62 c0");
+        let (_, report) = converter.to_hvc1(&sample(&[&pps2, &IDR])).unwrap();
+        assert_eq!(report.stripped, [UnitType::PicParameterSet]);
+        assert!(report.param_set_changed);
+        assert_eq!(converter.param_sets().collect::<Vec<_>>(), [&PPS[..]]);
+    }
+
+    #[test]
+    fn hev1_only_sync_samples() {
+        let mut converter = SampleConverter::new(2).unwrap();
+        converter.add_param_set(&PPS);
+        let (out, report) = converter.to_hev1(&sample(&[&TRAIL])).unwrap();
+        assert_eq!(out, sample(&[&TRAIL]));
+        assert!(report.is_unchanged());
+        assert!(!is_sync_sample(&out, 2).unwrap());
+        let (out, report) = converter.to_hev1(&sample(&[&IDR])).unwrap();
+        assert_eq!(out, sample(&[&PPS, &IDR]));
+        assert_eq!(report.injected, 1);
+        assert!(is_sync_sample(&out, 2).unwrap());
+    }
+
+    #[test]
+    fn param_set_too_long() {
+        // A sample entry PPS too long for 1 byte lengths.
+        let mut pps = PPS.to_vec();
+        pps.resize(300, 0);
+        let mut converter = SampleConverter::new(1).unwrap();
+        converter.add_param_set(&pps);
+        let mut sample = vec![];
+        push_nal_unit(&mut sample, &IDR, 1);
+        assert_eq!(
+            converter.to_hev1(&sample),
+            Err(SampleError::NalUnitTooLong {
+                len: 300,
+                length_size: 1
+            })
+        );
+    }
+}
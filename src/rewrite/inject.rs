@@ -0,0 +1,219 @@
+//! Insertion of parameter sets before IRAP pictures, turning a stream which relies on
+//! out-of-band parameter sets into a self-contained one that can be joined at random access
+//! points.
+
+use crate::nal::{NalHeader, UnitType};
+use crate::rewrite::{FilterError, NalFilter};
+
+/// Filter which inserts the stored VPS, SPS and PPS NAL units before the first slice segment of
+/// every IRAP picture (or every Nth one).
+///
+/// Parameter sets are stored from [`ParamSetInjector::store`] (e.g. out of an `hvcC` box or SDP
+/// `sprop-*` parameters) as well as from the stream itself, so in-band updates are picked up. No
+/// parameter sets are inserted for an IRAP picture that is already preceded by some.
+///
+/// ```
+/// use hevc_reader::rewrite::inject::ParamSetInjector;
+/// let mut injector = ParamSetInjector::new();
+/// injector.store(&[0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40]);
+/// let mut out = vec![];
+/// injector.process(&[0x26, 0x01, 0xac, 0x9c], |nal| out.push(nal.to_vec()));
+/// assert_eq!(out, [&[0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40][..], &[0x26, 0x01, 0xac, 0x9c]]);
+/// ```
+pub struct ParamSetInjector {
+    interval: u64,
+    vps: Vec<Option<Vec<u8>>>,
+    sps: Vec<Option<Vec<u8>>>,
+    pps: Vec<Option<Vec<u8>>>,
+    /// The number of IRAP pictures seen so far.
+    iraps: u64,
+    /// True if a parameter set was seen since the last VCL NAL unit.
+    param_sets_since_vcl: bool,
+}
+impl Default for ParamSetInjector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl ParamSetInjector {
+    pub fn new() -> Self {
+        ParamSetInjector {
+            interval: 1,
+            vps: vec![None; 16],
+            sps: vec![None; 16],
+            pps: vec![None; 64],
+            iraps: 0,
+            param_sets_since_vcl: false,
+        }
+    }
+
+    /// Only inserts parameter sets before every `n`th IRAP picture, starting with the first.
+    pub fn with_interval(mut self, n: u64) -> Self {
+        self.interval = n.max(1);
+        self
+    }
+
+    /// Stores a parameter set NAL unit (without start code or length prefix), replacing any
+    /// earlier one with the same id. Returns false if the NAL unit isn't a parameter set.
+    pub fn store(&mut self, nal: &[u8]) -> bool {
+        let Some((unit_type, id)) = param_set_id(nal) else {
+            return false;
+        };
+        let slot = match unit_type {
+            UnitType::VideoParameterSet => &mut self.vps[id],
+            UnitType::SeqParameterSet => &mut self.sps[id],
+            _ => &mut self.pps[id],
+        };
+        *slot = Some(nal.to_vec());
+        true
+    }
+
+    /// The stored parameter sets: VPS, SPS then PPS NAL units, each in order of id.
+    pub fn stored(&self) -> impl Iterator<Item = &[u8]> {
+        self.vps
+            .iter()
+            .chain(&self.sps)
+            .chain(&self.pps)
+            .flatten()
+            .map(|nal| &nal[..])
+    }
+
+    /// Passes a NAL unit (without start code or length prefix) to `out`, preceded by the stored
+    /// parameter sets if it starts an IRAP picture which should have them.
+    pub fn process(&mut self, nal: &[u8], mut out: impl FnMut(&[u8])) {
+        if let Some(unit_type) = unit_type(nal) {
+            if self.store(nal) {
+                self.param_sets_since_vcl = true;
+            } else if unit_type.is_vcl() {
+                let first_slice_segment_in_pic_flag = nal.get(2).is_some_and(|b| b & 0x80 != 0);
+                if unit_type.is_irap() && first_slice_segment_in_pic_flag {
+                    if self.iraps.is_multiple_of(self.interval) && !self.param_sets_since_vcl {
+                        for nal in self.stored() {
+                            out(nal);
+                        }
+                    }
+                    self.iraps += 1;
+                }
+                self.param_sets_since_vcl = false;
+            }
+        }
+        out(nal);
+    }
+}
+
+impl NalFilter for ParamSetInjector {
+    fn filter(&mut self, nal: &[u8], out: &mut dyn FnMut(&[u8])) -> Result<(), FilterError> {
+        self.process(nal, out);
+        Ok(())
+    }
+}
+
+fn unit_type(nal: &[u8]) -> Option<UnitType> {
+    let header = NalHeader::new(*nal.first()?, nal.get(1).copied()).ok()?;
+    Some(header.nal_unit_type())
+}
+
+/// Returns the type and id of a VPS, SPS or PPS NAL unit.
+pub(crate) fn param_set_id(nal: &[u8]) -> Option<(UnitType, usize)> {
+    let unit_type = unit_type(nal)?;
+    let payload = nal.get(2..)?;
+    // The VPS and PPS ids are at the start of the RBSP, where there can be no emulation
+    // prevention bytes.
+    let id = match unit_type {
+        UnitType::VideoParameterSet => usize::from(payload.first()? >> 4),
+        UnitType::SeqParameterSet => sps_id(nal)?,
+        UnitType::PicParameterSet => leading_ue(payload).filter(|&id| id < 64)? as usize,
+        _ => return None,
+    };
+    Some((unit_type, id))
+}
+
+/// Reads `sps_seq_parameter_set_id`, which follows the `profile_tier_level()`.
+fn sps_id(nal: &[u8]) -> Option<usize> {
+    let rbsp = crate::rbsp::decode_nal(nal).ok()?;
+    let max_sub_layers_minus1 = (rbsp.first()? >> 1) & 0x7;
+    // 12 bytes of general profile and level, plus 2 bytes of sub-layer flags if there are
+    // sub-layers, plus the sub-layer profiles and levels which are present.
+    let mut offset = 1 + 12;
+    if max_sub_layers_minus1 > 0 {
+        let flags = u16::from_be_bytes([*rbsp.get(offset)?, *rbsp.get(offset + 1)?]);
+        offset += 2;
+        for i in 0..max_sub_layers_minus1 {
+            let sub_layer_profile_present_flag = flags & (0x8000 >> (2 * i)) != 0;
+            let sub_layer_level_present_flag = flags & (0x4000 >> (2 * i)) != 0;
+            offset += 11 * usize::from(sub_layer_profile_present_flag);
+            offset += usize::from(sub_layer_level_present_flag);
+        }
+    }
+    leading_ue(rbsp.get(offset..)?)
+        .filter(|&id| id < 16)
+        .map(|id| id as usize)
+}
+
+/// Reads a ue(v) from the start of `data`, if it fits in a byte.
+fn leading_ue(data: &[u8]) -> Option<u32> {
+    let mut bits = u16::from_be_bytes([*data.first()?, data.get(1).copied().unwrap_or(0)]);
+    let zeros = bits.leading_zeros();
+    if zeros > 7 {
+        return None;
+    }
+    bits <<= zeros;
+    Some(u32::from(bits >> (15 - zeros)) - 1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hex_literal::hex;
+
+    // "Intinor HW encode 720x576p" SPS, x265 PPS, and the starts of an IDR and a P slice segment.
+    const SPS: [u8; 59] = hex!(
+        "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46 d1
+         2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00 0b
+         b8 48"
+    );
+    const PPS: [u8; 7] = hex!("44 01 c1 72 b4 62 40");
+    const IDR: [u8; 4] = hex!("26 01 ac 9c");
+    const TRAIL: [u8; 6] = hex!("02 01 d0 6c 27 80");
+
+    fn run(injector: &mut ParamSetInjector, input: &[&[u8]]) -> Vec<Vec<u8>> {
+        let mut out = vec![];
+        for nal in input {
+            injector.process(nal, |nal| out.push(nal.to_vec()));
+        }
+        out
+    }
+
+    #[test]
+    fn every_irap() {
+        let mut injector = ParamSetInjector::new();
+        assert!(injector.store(&SPS));
+        assert!(injector.store(&PPS));
+        assert!(!injector.store(&IDR));
+        let out = run(&mut injector, &[&IDR, &TRAIL, &IDR, &SPS, &PPS, &IDR]);
+        assert_eq!(
+            out,
+            [
+                &SPS[..],
+                &PPS,
+                &IDR,
+                &TRAIL,
+                &SPS,
+                &PPS,
+                &IDR,
+                &SPS,
+                &PPS,
+                &IDR
+            ]
+        );
+    }
+
+    #[test]
+    fn every_other_irap() {
+        let mut injector = ParamSetInjector::new().with_interval(2);
+        let out = run(&mut injector, &[&SPS, &PPS, &IDR, &IDR, &IDR]);
+        assert_eq!(out, [&SPS[..], &PPS, &IDR, &IDR, &SPS, &PPS, &IDR]);
+        let out = run(&mut injector, &[&IDR, &TRAIL, &IDR]);
+        assert_eq!(out, [&IDR[..], &TRAIL, &SPS, &PPS, &IDR]);
+    }
+}
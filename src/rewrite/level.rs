@@ -0,0 +1,430 @@
+//! Correction of the signalled level: measuring what a stream actually needs, and patching
+//! `general_level_idc` and `general_tier_flag` in the VPS and SPS to match.
+//!
+//! This is a two pass operation; the whole stream goes through a [`LevelAnalyzer`] first, then
+//! through a [`LevelPatcher`] with the level it found.
+
+use std::borrow::Cow;
+
+use crate::bitrate::BitRateMeter;
+use crate::nal::pps::{PicParamSetId, PicParameterSet};
+use crate::nal::sps::{Level, SeqParameterSet, Tier};
+use crate::nal::vps::VideoParameterSet;
+use crate::nal::{NalHeader, UnitType};
+use crate::rbsp::{decode_nal, encode_nal, BitRead, BitReader, BitReaderError};
+use crate::rewrite::{FilterError, NalFilter};
+use crate::Context;
+
+/// The properties of a stream which are constrained by its level, see [`LevelAnalyzer`].
+///
+/// Bit rates and CPB sizes count VCL NAL units only, and are compared with the limits using the
+/// factors of the Main and Main 10 profiles, which is conservative for the other profiles.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LevelMeasurement {
+    pub pictures: u64,
+    /// The largest picture size in luma samples.
+    pub max_luma_ps: u32,
+    pub max_width: u32,
+    pub max_height: u32,
+    pub max_slice_segments_per_picture: u32,
+    pub max_tile_cols: u32,
+    pub max_tile_rows: u32,
+    /// The picture rate, if signalled in the stream or set with
+    /// [`LevelAnalyzer::with_picture_rate`].
+    pub pictures_per_second: Option<f64>,
+    /// The largest luma sample rate, in samples per second.
+    pub max_luma_sr: Option<f64>,
+    /// The largest number of VCL bits in any one window (of one second, unless set with
+    /// [`LevelAnalyzer::with_bit_rate_window`]), in bits per second.
+    pub peak_bit_rate: Option<f64>,
+    /// The VCL bit rate over the whole stream, in bits per second.
+    pub average_bit_rate: Option<f64>,
+    /// The largest bit rate signalled in the HRD parameters, in bits per second.
+    pub hrd_bit_rate: Option<f64>,
+    /// The largest CPB size signalled in the HRD parameters, in bits.
+    pub hrd_cpb_size: Option<f64>,
+}
+impl LevelMeasurement {
+    /// True if the stream fits the limits of `level` in `tier`. Quantities which are unknown
+    /// (e.g. the bit rate of a stream without timing info) aren't checked.
+    pub fn fits(&self, tier: Tier, level: Level) -> bool {
+        let Some(limits) = level.limits() else {
+            return level == Level::L8_5;
+        };
+        let (Some(max_br), Some(max_cpb)) = (limits.max_br(tier), limits.max_cpb(tier)) else {
+            return false;
+        };
+        let max_br = f64::from(max_br) * 1000.0;
+        self.max_luma_ps <= limits.max_luma_ps
+            && self.max_width <= limits.max_dimension()
+            && self.max_height <= limits.max_dimension()
+            && self.max_slice_segments_per_picture <= limits.max_slice_segments_per_picture
+            && self.max_tile_cols <= limits.max_tile_cols
+            && self.max_tile_rows <= limits.max_tile_rows
+            && self
+                .max_luma_sr
+                .is_none_or(|sr| sr <= limits.max_luma_sr as f64)
+            && self.peak_bit_rate.is_none_or(|br| br <= max_br)
+            && self.hrd_bit_rate.is_none_or(|br| br <= max_br)
+            && self
+                .hrd_cpb_size
+                .is_none_or(|cpb| cpb <= f64::from(max_cpb) * 1000.0)
+    }
+
+    /// The lowest tier and level which the stream fits, preferring the main tier at the same
+    /// level. Level 8.5 is never chosen.
+    pub fn minimum_level(&self, allow_high_tier: bool) -> Option<(Tier, Level)> {
+        Level::ALL
+            .into_iter()
+            .filter(|&level| level != Level::L8_5)
+            .find_map(|level| {
+                if self.fits(Tier::Main, level) {
+                    Some((Tier::Main, level))
+                } else if allow_high_tier && self.fits(Tier::High, level) {
+                    Some((Tier::High, level))
+                } else {
+                    None
+                }
+            })
+    }
+}
+
+/// Measures the level-constrained properties of a stream, fed one NAL unit at a time.
+///
+/// ```
+/// use hevc_reader::nal::sps::{Level, Tier};
+/// use hevc_reader::rewrite::level::LevelAnalyzer;
+/// # let sps = [
+/// #     0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03, 0x00, 0x00,
+/// #     0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46, 0xd1, 0x2e,
+/// #     0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10, 0x00, 0x00, 0x03,
+/// #     0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00, 0x0b, 0xb8, 0x48,
+/// # ];
+/// let mut analyzer = LevelAnalyzer::new();
+/// // An SPS signalling level 3.1 for 736x576 pictures at 25 Hz, a PPS and an IDR slice segment.
+/// analyzer.push_nal(&sps);
+/// analyzer.push_nal(&[0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40]);
+/// analyzer.push_nal(&[0x26, 0x01, 0xac, 0x9c]);
+/// let measurement = analyzer.measurement();
+/// assert_eq!(measurement.max_luma_ps, 736 * 576);
+/// assert_eq!(measurement.minimum_level(false), Some((Tier::Main, Level::L3)));
+/// ```
+pub struct LevelAnalyzer {
+    ctx: Context,
+    picture_rate: Option<f64>,
+    /// The length of the bit rate windows, in seconds.
+    window: f64,
+    measurement: LevelMeasurement,
+    /// The VCL bits of each picture.
+    picture_bits: Vec<u64>,
+    slice_segments: u32,
+}
+impl Default for LevelAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl LevelAnalyzer {
+    pub fn new() -> Self {
+        LevelAnalyzer {
+            ctx: Context::new(),
+            picture_rate: None,
+            window: 1.0,
+            measurement: LevelMeasurement::default(),
+            picture_bits: vec![],
+            slice_segments: 0,
+        }
+    }
+
+    /// Uses the given picture rate instead of the one signalled in the stream, e.g. for streams
+    /// without timing info.
+    pub fn with_picture_rate(mut self, pictures_per_second: f64) -> Self {
+        self.picture_rate = Some(pictures_per_second);
+        self
+    }
+
+    /// Measures the peak bit rate over windows of the given length in seconds rather than one
+    /// second.
+    pub fn with_bit_rate_window(mut self, seconds: f64) -> Self {
+        self.window = seconds;
+        self
+    }
+
+    /// Adds a NAL unit (without start code or length prefix). NAL units which can't be parsed
+    /// are ignored.
+    pub fn push_nal(&mut self, nal: &[u8]) {
+        let Some(header) = nal
+            .first()
+            .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())
+        else {
+            return;
+        };
+        let unit_type = header.nal_unit_type();
+        let Ok(rbsp) = decode_nal(nal) else {
+            return;
+        };
+        match unit_type {
+            UnitType::VideoParameterSet => {
+                if let Ok(vps) = VideoParameterSet::from_bits(BitReader::new(&*rbsp)) {
+                    self.ctx.put_video_param_set(vps);
+                }
+            }
+            UnitType::SeqParameterSet => {
+                if let Ok(sps) = SeqParameterSet::from_bits(BitReader::new(&*rbsp)) {
+                    self.measure_hrd(&sps);
+                    self.ctx.put_seq_param_set(sps);
+                }
+            }
+            UnitType::PicParameterSet => {
+                if let Ok(pps) = PicParameterSet::from_bits(&self.ctx, BitReader::new(&*rbsp)) {
+                    self.ctx.put_pic_param_set(pps);
+                }
+            }
+            t if t.is_vcl() => self.push_slice_segment(nal, &rbsp, t),
+            _ => {}
+        }
+    }
+
+    fn push_slice_segment(&mut self, nal: &[u8], rbsp: &[u8], unit_type: UnitType) {
+        let mut r = BitReader::new(rbsp);
+        let Ok(first_slice_segment_in_pic_flag) = r.read_bool("first_slice_segment_in_pic_flag")
+        else {
+            return;
+        };
+        if first_slice_segment_in_pic_flag {
+            self.picture_bits.push(0);
+            self.slice_segments = 0;
+            self.measurement.pictures += 1;
+            if unit_type.is_irap() {
+                let _ = r.read_bool("no_output_of_prior_pics_flag");
+            }
+            if let Ok(pps_id) = r.read_ue("slice_pic_parameter_set_id") {
+                self.measure_picture(pps_id);
+            }
+        }
+        if let Some(bits) = self.picture_bits.last_mut() {
+            *bits += 8 * nal.len() as u64;
+        }
+        self.slice_segments += 1;
+        let m = &mut self.measurement;
+        m.max_slice_segments_per_picture =
+            m.max_slice_segments_per_picture.max(self.slice_segments);
+    }
+
+    fn measure_picture(&mut self, pps_id: u32) {
+        let Some(pps) = PicParamSetId::from_u32(pps_id)
+            .ok()
+            .and_then(|id| self.ctx.pps_by_id(id))
+        else {
+            return;
+        };
+        let Some(sps) = self.ctx.sps_by_id(pps.seq_parameter_set_id) else {
+            return;
+        };
+        let m = &mut self.measurement;
+        let width = sps.pic_width_in_luma_samples;
+        let height = sps.pic_height_in_luma_samples;
+        let luma_ps = width.saturating_mul(height);
+        m.max_luma_ps = m.max_luma_ps.max(luma_ps);
+        m.max_width = m.max_width.max(width);
+        m.max_height = m.max_height.max(height);
+        let (cols, rows) = pps.tiles.as_ref().map_or((1, 1), |t| {
+            (t.num_tile_columns_minus1 + 1, t.num_tile_rows_minus1 + 1)
+        });
+        m.max_tile_cols = m.max_tile_cols.max(cols);
+        m.max_tile_rows = m.max_tile_rows.max(rows);
+        let rate = self.picture_rate.or_else(|| {
+            self.ctx
+                .frame_rate(sps, None)
+                .map(|r| r.pictures_per_second())
+        });
+        if let Some(rate) = rate {
+            m.pictures_per_second = Some(rate);
+            let sr = f64::from(luma_ps) * rate;
+            m.max_luma_sr = Some(m.max_luma_sr.map_or(sr, |max| max.max(sr)));
+        }
+    }
+
+    /// Takes the largest bit rate and CPB size of the SPS HRD parameters, scaling NAL HRD
+    /// values to VCL ones.
+    fn measure_hrd(&mut self, sps: &SeqParameterSet) {
+        let Some(hrd) = sps
+            .vui_parameters
+            .as_ref()
+            .and_then(|v| v.timing_info.as_ref())
+            .and_then(|t| t.hrd_parameters.as_ref())
+        else {
+            return;
+        };
+        let Some(common) = hrd.common.as_ref().and_then(|c| c.parameters.as_ref()) else {
+            return;
+        };
+        let m = &mut self.measurement;
+        for sub_layer in &hrd.sub_layers {
+            let params = sub_layer
+                .nal_hrd_parameters
+                .iter()
+                .flatten()
+                .map(|p| (p, 1000.0 / 1100.0))
+                .chain(
+                    sub_layer
+                        .vcl_hrd_parameters
+                        .iter()
+                        .flatten()
+                        .map(|p| (p, 1.0)),
+                );
+            for (p, factor) in params {
+                let bit_rate = (f64::from(p.bit_rate_value_minus1) + 1.0)
+                    * 2f64.powi(6 + i32::from(common.bit_rate_scale))
+                    * factor;
+                let cpb_size = (f64::from(p.cpb_size_value_minus1) + 1.0)
+                    * 2f64.powi(4 + i32::from(common.cpb_size_scale))
+                    * factor;
+                m.hrd_bit_rate = Some(m.hrd_bit_rate.map_or(bit_rate, |br| br.max(bit_rate)));
+                m.hrd_cpb_size = Some(m.hrd_cpb_size.map_or(cpb_size, |c| c.max(cpb_size)));
+            }
+        }
+    }
+
+    /// The measurement of the NAL units added so far.
+    pub fn measurement(&self) -> LevelMeasurement {
+        let mut m = self.measurement.clone();
+        if let Some(rate) = m.pictures_per_second.filter(|&r| r > 0.0) {
+            let meter = BitRateMeter::with_picture_rate(
+                self.window,
+                rate,
+                self.picture_bits.iter().copied(),
+            );
+            if let Some(report) = meter.report() {
+                m.peak_bit_rate = Some(report.peak);
+                m.average_bit_rate = Some(report.average);
+            }
+        }
+        m
+    }
+}
+
+/// Sets `general_tier_flag` and `general_level_idc` of a VPS or SPS NAL unit (without start code
+/// or length prefix). Other NAL units are returned as-is, as are the sub-layer levels.
+///
+/// ```
+/// use hevc_reader::nal::sps::{Level, Tier};
+/// use hevc_reader::rewrite::level::patch_level;
+/// let vps = [
+///     0x40, 0x01, 0x0c, 0x01, 0xff, 0xff, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00,
+///     0x03, 0x00, 0x00, 0x03, 0x00, 0x5d, 0x95, 0x98, 0x09,
+/// ];
+/// let patched = patch_level(&vps, Tier::Main, Level::L4).unwrap();
+/// assert_eq!(patched[20], 120);
+/// ```
+pub fn patch_level(nal: &[u8], tier: Tier, level: Level) -> Result<Cow<'_, [u8]>, BitReaderError> {
+    let Some(header) = nal
+        .first()
+        .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())
+    else {
+        return Ok(Cow::Borrowed(nal));
+    };
+    // The byte offset of profile_tier_level() in the RBSP.
+    let ptl = match header.nal_unit_type() {
+        UnitType::VideoParameterSet => 4,
+        UnitType::SeqParameterSet => 1,
+        _ => return Ok(Cow::Borrowed(nal)),
+    };
+    let mut rbsp = decode_nal(nal)
+        .map_err(BitReaderError::ReaderError)?
+        .into_owned();
+    if rbsp.len() <= ptl + 11 {
+        return Err(BitReaderError::ReaderErrorFor(
+            "general_level_idc",
+            std::io::ErrorKind::UnexpectedEof.into(),
+        ));
+    }
+    let tier_flag = u8::from(tier == Tier::High) << 5;
+    let old = (rbsp[ptl], rbsp[ptl + 11]);
+    rbsp[ptl] = (rbsp[ptl] & !0x20) | tier_flag;
+    rbsp[ptl + 11] = level.level_idc();
+    if (rbsp[ptl], rbsp[ptl + 11]) == old {
+        return Ok(Cow::Borrowed(nal));
+    }
+    Ok(Cow::Owned(encode_nal(&nal[..2], &rbsp)))
+}
+
+/// Filter which sets the general tier and level of every VPS and SPS, see [`patch_level`].
+pub struct LevelPatcher {
+    tier: Tier,
+    level: Level,
+}
+impl LevelPatcher {
+    pub fn new(tier: Tier, level: Level) -> Self {
+        LevelPatcher { tier, level }
+    }
+}
+
+impl NalFilter for LevelPatcher {
+    fn filter(&mut self, nal: &[u8], out: &mut dyn FnMut(&[u8])) -> Result<(), FilterError> {
+        out(&patch_level(nal, self.tier, self.level)?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hex_literal::hex;
+
+    // "Intinor HW encode 720x576p" SPS, x265 PPS and an IDR and a P slice segment.
+    const SPS: [u8; 59] = hex!(
+        "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46 d1
+         2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00 0b
+         b8 48"
+    );
+    const PPS: [u8; 7] = hex!("44 01 c1 72 b4 62 40");
+    const IDR: [u8; 4] = hex!("26 01 ac 9c");
+
+    #[test]
+    fn bit_rate_raises_level() {
+        let mut analyzer = LevelAnalyzer::new().with_picture_rate(25.0);
+        analyzer.push_nal(&SPS);
+        analyzer.push_nal(&PPS);
+        // 25 pictures of 50 kB each are 10 Mbit/s.
+        let mut idr = IDR.to_vec();
+        idr.resize(50_000, 0x55);
+        for _ in 0..25 {
+            analyzer.push_nal(&idr);
+        }
+        let m = analyzer.measurement();
+        assert_eq!(m.pictures, 25);
+        assert_eq!(m.peak_bit_rate, Some(10_000_000.0));
+        assert_eq!(m.minimum_level(false), Some((Tier::Main, Level::L3_1)));
+
+        // Then 20 Mbit/s.
+        idr.resize(100_000, 0x55);
+        for _ in 0..25 {
+            analyzer.push_nal(&idr);
+        }
+        let m = analyzer.measurement();
+        assert_eq!(m.minimum_level(false), Some((Tier::Main, Level::L4_1)));
+        assert_eq!(m.minimum_level(true), Some((Tier::High, Level::L4)));
+        assert_eq!(m.average_bit_rate, Some(15_000_000.0));
+    }
+
+    #[test]
+    fn patch() {
+        let patched = patch_level(&SPS, Tier::High, Level::L4).unwrap();
+        let rbsp = decode_nal(&patched).unwrap();
+        let sps = SeqParameterSet::from_bits(BitReader::new(&*rbsp)).unwrap();
+        assert_eq!(sps.general_level(), Level::L4);
+        assert_eq!(sps.general_tier(), Tier::High);
+        let orig = SeqParameterSet::from_bits(BitReader::new(&*decode_nal(&SPS).unwrap())).unwrap();
+        assert_eq!(orig.diff(&sps).len(), 2);
+
+        assert!(matches!(
+            patch_level(&SPS, Tier::Main, Level::L3_1).unwrap(),
+            Cow::Borrowed(_)
+        ));
+        assert!(matches!(
+            patch_level(&IDR, Tier::Main, Level::L4).unwrap(),
+            Cow::Borrowed(_)
+        ));
+    }
+}
@@ -0,0 +1,285 @@
+//! Bitstream rewriting: filters which modify NAL units in place, without re-encoding the video.
+//!
+//! Filters implement [`NalFilter`] and can be chained in a [`Pipeline`], which runs over Annex B
+//! streams or length-prefixed samples:
+//!
+//! ```
+//! use hevc_reader::nal::sei::HeaderType;
+//! use hevc_reader::rewrite::{dedup, sei_strip::SeiStripper, Pipeline};
+//! let mut pipeline = Pipeline::new()
+//!     .with(SeiStripper::payload_types(&[HeaderType::UserDataUnregistered]))
+//!     .with(dedup::ParamSetDedup::new(dedup::Comparison::Bytes));
+//! // A user_data_unregistered() SEI and an IDR slice segment.
+//! let input = [0, 0, 1, 0x4e, 0x01, 0x05, 0x01, 0x42, 0x80, 0, 0, 1, 0x26, 0x01, 0xac, 0x9c];
+//! let output = pipeline.run_annexb(&input).unwrap();
+//! assert_eq!(output, [0, 0, 0, 1, 0x26, 0x01, 0xac, 0x9c]);
+//! ```
+
+#[cfg(feature = "sei")]
+pub mod anonymize;
+pub mod dedup;
+pub mod escape;
+pub mod framing;
+#[cfg(feature = "sei")]
+pub mod hdr;
+pub mod hvc1;
+pub mod inject;
+#[cfg(feature = "sei")]
+pub mod level;
+#[cfg(feature = "slice")]
+pub mod remap;
+#[cfg(feature = "sei")]
+pub mod sei_strip;
+pub mod split;
+pub mod trim;
+pub mod vui;
+
+use crate::hvcc::SampleError;
+use crate::rbsp::BitReaderError;
+use crate::rewrite::framing::{AnnexBWriter, StartCodes};
+
+#[derive(Debug)]
+pub enum FilterError {
+    RbspError(BitReaderError),
+    #[cfg(feature = "slice")]
+    Remap(remap::RemapError),
+    Sample(SampleError),
+    Vui(vui::VuiEditError),
+    /// An error from a filter outside this crate.
+    Custom(String),
+}
+impl FilterError {
+    /// See [`crate::error_code`].
+    pub fn code(&self) -> u32 {
+        crate::error_code::FILTER
+            + match self {
+                FilterError::RbspError(..) => 1,
+                #[cfg(feature = "slice")]
+                FilterError::Remap(..) => 2,
+                FilterError::Sample(..) => 3,
+                FilterError::Vui(..) => 4,
+                FilterError::Custom(..) => 5,
+            }
+    }
+}
+impl From<BitReaderError> for FilterError {
+    fn from(e: BitReaderError) -> Self {
+        FilterError::RbspError(e)
+    }
+}
+#[cfg(feature = "slice")]
+impl From<remap::RemapError> for FilterError {
+    fn from(e: remap::RemapError) -> Self {
+        FilterError::Remap(e)
+    }
+}
+impl From<vui::VuiEditError> for FilterError {
+    fn from(e: vui::VuiEditError) -> Self {
+        FilterError::Vui(e)
+    }
+}
+impl From<SampleError> for FilterError {
+    fn from(e: SampleError) -> Self {
+        FilterError::Sample(e)
+    }
+}
+
+/// A bitstream filter, which turns each input NAL unit into zero or more output NAL units.
+///
+/// NAL units are given without start code or length prefix, with emulation prevention bytes.
+pub trait NalFilter {
+    fn filter(&mut self, nal: &[u8], out: &mut dyn FnMut(&[u8])) -> Result<(), FilterError>;
+
+    /// Emits any NAL units still held by the filter at the end of the stream.
+    fn flush(&mut self, _out: &mut dyn FnMut(&[u8])) -> Result<(), FilterError> {
+        Ok(())
+    }
+}
+
+/// A chain of [`NalFilter`]s, where the output of each filter is the input of the next.
+#[derive(Default)]
+pub struct Pipeline {
+    filters: Vec<Box<dyn NalFilter>>,
+    start_codes: StartCodes,
+}
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a filter to the end of the chain.
+    pub fn with(mut self, filter: impl NalFilter + 'static) -> Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    /// Sets the start codes [`Self::run_annexb`] writes, four byte ones by default. With
+    /// [`StartCodes::Preserve`], the first NAL unit output for each input NAL unit gets its
+    /// framing.
+    pub fn with_start_codes(mut self, start_codes: StartCodes) -> Self {
+        self.start_codes = start_codes;
+        self
+    }
+
+    /// Filters an Annex B stream. Flushes the filters at the end of the input.
+    pub fn run_annexb(&mut self, input: &[u8]) -> Result<Vec<u8>, FilterError> {
+        let mut writer = AnnexBWriter::with_capacity(self.start_codes, input.len());
+        for (_, nal, framing) in crate::annexb::framed_nal_units(input) {
+            let mut framing = Some(framing);
+            self.filter(nal, &mut |nal: &[u8]| writer.push(nal, framing.take()))?;
+        }
+        self.flush(&mut |nal: &[u8]| writer.push(nal, None))?;
+        Ok(writer.into_inner())
+    }
+
+    /// Filters one length-prefixed sample, with `length_size` byte lengths. The filters are not
+    /// flushed, as their state usually carries over to the next sample.
+    ///
+    /// Fails with [`SampleError::NalUnitTooLong`] if a filter outputs a NAL unit too long for
+    /// `length_size`.
+    pub fn run_sample(
+        &mut self,
+        sample: &[u8],
+        length_size: usize,
+    ) -> Result<Vec<u8>, FilterError> {
+        let mut output = Vec::with_capacity(sample.len());
+        for nal in crate::hvcc::nal_units(sample, length_size)? {
+            let mut result = Ok(());
+            self.filter(nal?, &mut |nal: &[u8]| {
+                if result.is_ok() {
+                    result = crate::hvcc::try_push_nal_unit(&mut output, nal, length_size);
+                }
+            })?;
+            result?;
+        }
+        Ok(output)
+    }
+}
+impl NalFilter for Pipeline {
+    fn filter(&mut self, nal: &[u8], out: &mut dyn FnMut(&[u8])) -> Result<(), FilterError> {
+        run_chain(&mut self.filters, nal, out)
+    }
+
+    fn flush(&mut self, out: &mut dyn FnMut(&[u8])) -> Result<(), FilterError> {
+        for i in 0..self.filters.len() {
+            let (head, rest) = self.filters.split_at_mut(i + 1);
+            let mut result = Ok(());
+            head[i].flush(&mut |nal: &[u8]| {
+                if result.is_ok() {
+                    result = run_chain(rest, nal, out);
+                }
+            })?;
+            result?;
+        }
+        Ok(())
+    }
+}
+
+fn run_chain(
+    filters: &mut [Box<dyn NalFilter>],
+    nal: &[u8],
+    out: &mut dyn FnMut(&[u8]),
+) -> Result<(), FilterError> {
+    let Some((first, rest)) = filters.split_first_mut() else {
+        out(nal);
+        return Ok(());
+    };
+    let mut result = Ok(());
+    first.filter(nal, &mut |nal: &[u8]| {
+        if result.is_ok() {
+            result = run_chain(rest, nal, out);
+        }
+    })?;
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Duplicates every NAL unit, and emits one more when flushed.
+    struct Duplicate;
+    impl NalFilter for Duplicate {
+        fn filter(&mut self, nal: &[u8], out: &mut dyn FnMut(&[u8])) -> Result<(), FilterError> {
+            out(nal);
+            out(nal);
+            Ok(())
+        }
+
+        fn flush(&mut self, out: &mut dyn FnMut(&[u8])) -> Result<(), FilterError> {
+            out(&[0x4a, 0x01]);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn chain_and_flush() {
+        let mut pipeline = Pipeline::new().with(Duplicate).with(Duplicate);
+        let output = pipeline.run_sample(&[1, 0x46, 2, 0x46, 0x01], 1).unwrap();
+        // Both NAL units four times; flush output isn't produced for single samples.
+        assert_eq!(output.len(), 4 * 2 + 4 * 3);
+        let output = pipeline.run_annexb(&[0, 0, 1, 0x46, 0x01]).unwrap();
+        let nals: Vec<_> = crate::annexb::nal_units(&output).map(|(_, n)| n).collect();
+        // The first filter's flush output passes through the second filter.
+        assert_eq!(
+            nals,
+            [
+                &[0x46, 0x01][..],
+                &[0x46, 0x01],
+                &[0x46, 0x01],
+                &[0x46, 0x01]
+            ]
+            .into_iter()
+            .chain([&[0x4a, 0x01][..]; 3])
+            .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn grown_nal_unit() {
+        /// Pads every NAL unit to 300 bytes.
+        struct Pad;
+        impl NalFilter for Pad {
+            fn filter(
+                &mut self,
+                nal: &[u8],
+                out: &mut dyn FnMut(&[u8]),
+            ) -> Result<(), FilterError> {
+                let mut nal = nal.to_vec();
+                nal.resize(300, 0);
+                out(&nal);
+                Ok(())
+            }
+        }
+        let mut pipeline = Pipeline::new().with(Pad);
+        assert_eq!(
+            pipeline.run_sample(&[0, 2, 0x46, 0x01], 2).unwrap().len(),
+            302
+        );
+        assert!(matches!(
+            pipeline.run_sample(&[2, 0x46, 0x01], 1),
+            Err(FilterError::Sample(SampleError::NalUnitTooLong {
+                len: 300,
+                length_size: 1
+            }))
+        ));
+    }
+
+    #[test]
+    fn start_codes() {
+        // An access unit delimiter and an IDR slice segment with three byte start codes, and a
+        // trailing zero byte.
+        let input = [
+            0, 0, 1, 0x46, 0x01, 0x10, 0, 0, 1, 0x26, 0x01, 0xac, 0x9c, 0,
+        ];
+        let run = |start_codes| {
+            Pipeline::new()
+                .with_start_codes(start_codes)
+                .run_annexb(&input)
+                .unwrap()
+        };
+        assert_eq!(run(StartCodes::Preserve), input);
+        assert_eq!(run(StartCodes::Minimal), [&[0][..], &input[..13]].concat());
+        assert_eq!(run(StartCodes::Four).len(), input.len() + 1);
+    }
+}
@@ -0,0 +1,398 @@
+//! Renumbering of parameter set ids, so that streams from different sources can be spliced into
+//! one elementary stream without id collisions.
+
+use std::borrow::Cow;
+
+use crate::nal::pps::{PicParamSetId, PicParameterSet};
+use crate::nal::slice::{SliceHeaderError, SliceSegmentHeader};
+use crate::nal::sps::{
+    ProfileTierLevel, SeqParamSetId, SeqParameterSet, SpsError, VideoParamSetId,
+};
+use crate::nal::vps::VideoParameterSet;
+use crate::nal::{NalHeader, NalHeaderError, UnitType};
+use crate::rbsp::{decode_nal, encode_nal, ue_len, BitRead, BitReader, BitReaderError, BitWriter};
+use crate::rewrite::{FilterError, NalFilter};
+use crate::Context;
+
+#[derive(Debug)]
+pub enum RemapError {
+    RbspError(BitReaderError),
+    BadNalHeader(NalHeaderError),
+    /// The SPS `profile_tier_level()` preceding `sps_seq_parameter_set_id` was invalid.
+    BadSeqParamSet(SpsError),
+    /// The slice segment header had to be parsed to find where it ends, and that failed.
+    ///
+    /// This happens when the parameter sets it refers to haven't been seen by the remapper.
+    BadSliceHeader(SliceHeaderError),
+    /// The RBSP ended before its trailing bits.
+    MissingTrailingBits,
+}
+impl RemapError {
+    /// See [`crate::error_code`].
+    pub fn code(&self) -> u32 {
+        crate::error_code::REMAP
+            + match self {
+                RemapError::RbspError(..) => 1,
+                RemapError::BadNalHeader(..) => 2,
+                RemapError::BadSeqParamSet(..) => 3,
+                RemapError::BadSliceHeader(..) => 4,
+                RemapError::MissingTrailingBits => 5,
+            }
+    }
+}
+impl From<BitReaderError> for RemapError {
+    fn from(e: BitReaderError) -> Self {
+        RemapError::RbspError(e)
+    }
+}
+
+/// A mapping from input to output parameter set ids. Ids which aren't explicitly mapped are
+/// left unchanged.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParamSetIdMap {
+    vps: [u8; 16],
+    sps: [u8; 16],
+    pps: [u8; 64],
+}
+impl Default for ParamSetIdMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl ParamSetIdMap {
+    /// Creates the identity mapping.
+    pub fn new() -> Self {
+        let mut map = ParamSetIdMap {
+            vps: [0; 16],
+            sps: [0; 16],
+            pps: [0; 64],
+        };
+        for (i, id) in map.vps.iter_mut().enumerate() {
+            *id = i as u8;
+        }
+        for (i, id) in map.sps.iter_mut().enumerate() {
+            *id = i as u8;
+        }
+        for (i, id) in map.pps.iter_mut().enumerate() {
+            *id = i as u8;
+        }
+        map
+    }
+
+    pub fn map_vps(&mut self, from: VideoParamSetId, to: VideoParamSetId) -> &mut Self {
+        self.vps[from.id() as usize] = to.id();
+        self
+    }
+
+    pub fn map_sps(&mut self, from: SeqParamSetId, to: SeqParamSetId) -> &mut Self {
+        self.sps[from.id() as usize] = to.id();
+        self
+    }
+
+    pub fn map_pps(&mut self, from: PicParamSetId, to: PicParamSetId) -> &mut Self {
+        self.pps[from.id() as usize] = to.id();
+        self
+    }
+
+    pub fn vps(&self, id: VideoParamSetId) -> VideoParamSetId {
+        VideoParamSetId::from_u32(self.vps[id.id() as usize].into()).unwrap()
+    }
+
+    pub fn sps(&self, id: SeqParamSetId) -> SeqParamSetId {
+        SeqParamSetId::from_u32(self.sps[id.id() as usize].into()).unwrap()
+    }
+
+    pub fn pps(&self, id: PicParamSetId) -> PicParamSetId {
+        PicParamSetId::from_u32(self.pps[id.id() as usize].into()).unwrap()
+    }
+}
+
+/// Rewrite filter which renumbers VPS, SPS and PPS ids according to a [`ParamSetIdMap`], and
+/// patches the references to them in SPS, PPS and slice segment headers.
+///
+/// Only the id fields are touched; when the new id has a different Exp-Golomb length than the
+/// old one, the remainder of the syntax is shifted and realigned, leaving the slice segment data
+/// unchanged. The parameter sets are tracked (by their input ids) so that slice segment headers
+/// can be parsed in that case. Ids referenced from SEI messages such as
+/// `active_parameter_sets()` are not patched.
+///
+/// ```
+/// use hevc_reader::nal::pps::PicParamSetId;
+/// use hevc_reader::rewrite::remap::{IdRemapper, ParamSetIdMap};
+/// let mut map = ParamSetIdMap::new();
+/// map.map_pps(PicParamSetId::from_u32(0).unwrap(), PicParamSetId::from_u32(1).unwrap());
+/// let mut remapper = IdRemapper::new(map);
+/// // x265 PPS with pps_pic_parameter_set_id 0.
+/// let pps = remapper.rewrite(&[0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40]).unwrap();
+/// assert_eq!(&*pps, &[0x44, 0x01, 0x50, 0x5c, 0xad, 0x18, 0x90]);
+/// ```
+pub struct IdRemapper {
+    map: ParamSetIdMap,
+    ctx: Context,
+}
+impl IdRemapper {
+    pub fn new(map: ParamSetIdMap) -> Self {
+        IdRemapper {
+            map,
+            ctx: Context::new(),
+        }
+    }
+
+    pub fn map(&self) -> &ParamSetIdMap {
+        &self.map
+    }
+
+    /// Rewrites a single NAL unit, given with its header and emulation prevention bytes but
+    /// without start code or length prefix. NAL units which don't refer to parameter set ids,
+    /// or whose ids are unchanged, are returned as-is.
+    pub fn rewrite<'a>(&mut self, nal: &'a [u8]) -> Result<Cow<'a, [u8]>, RemapError> {
+        if nal.len() < 2 {
+            return Ok(Cow::Borrowed(nal));
+        }
+        let header = NalHeader::new(nal[0], Some(nal[1])).map_err(RemapError::BadNalHeader)?;
+        let unit_type = header.nal_unit_type();
+        if !unit_type.is_vcl()
+            && !matches!(
+                unit_type,
+                UnitType::VideoParameterSet | UnitType::SeqParameterSet | UnitType::PicParameterSet
+            )
+        {
+            return Ok(Cow::Borrowed(nal));
+        }
+        let rbsp = decode_nal(nal).map_err(BitReaderError::ReaderError)?;
+        self.track(unit_type, &rbsp);
+        let rewritten = match unit_type {
+            UnitType::VideoParameterSet => self.rewrite_vps(&rbsp)?,
+            UnitType::SeqParameterSet => self.rewrite_sps(&rbsp)?,
+            UnitType::PicParameterSet => self.rewrite_pps(&rbsp)?,
+            _ => self.rewrite_slice(header, &rbsp)?,
+        };
+        Ok(match rewritten {
+            Some(rbsp) => Cow::Owned(encode_nal(&nal[..2], &rbsp)),
+            None => Cow::Borrowed(nal),
+        })
+    }
+
+    /// Keeps the input parameter sets, which slice segment headers need to be parsed.
+    fn track(&mut self, unit_type: UnitType, rbsp: &[u8]) {
+        match unit_type {
+            UnitType::VideoParameterSet => {
+                if let Ok(vps) = VideoParameterSet::from_bits(BitReader::new(rbsp)) {
+                    self.ctx.put_video_param_set(vps);
+                }
+            }
+            UnitType::SeqParameterSet => {
+                if let Ok(sps) = SeqParameterSet::from_bits(BitReader::new(rbsp)) {
+                    self.ctx.put_seq_param_set(sps);
+                }
+            }
+            UnitType::PicParameterSet => {
+                if let Ok(pps) = PicParameterSet::from_bits(&self.ctx, BitReader::new(rbsp)) {
+                    self.ctx.put_pic_param_set(pps);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn rewrite_vps(&self, rbsp: &[u8]) -> Result<Option<Vec<u8>>, RemapError> {
+        let mut r = BitReader::new(rbsp);
+        let id = self.map_vps_id(r.read_u8(4, "vps_video_parameter_set_id")?);
+        let Some(new_id) = id else {
+            return Ok(None);
+        };
+        let mut rbsp = rbsp.to_vec();
+        rbsp[0] = (rbsp[0] & 0x0f) | (new_id << 4);
+        Ok(Some(rbsp))
+    }
+
+    fn rewrite_sps(&self, rbsp: &[u8]) -> Result<Option<Vec<u8>>, RemapError> {
+        let mut r = BitReader::new(rbsp);
+        let vps_id = r.read_u8(4, "sps_video_parameter_set_id")?;
+        let max_sub_layers_minus1 = r.read_u8(3, "sps_max_sub_layers_minus1")?;
+        r.read_bool("sps_temporal_id_nesting_flag")?;
+        ProfileTierLevel::read(&mut r, true, max_sub_layers_minus1)
+            .map_err(RemapError::BadSeqParamSet)?;
+        // profile_tier_level() always has a whole number of bytes, so this is aligned.
+        let ptl_end = (rbsp.len() - r.reader().map_or(0, |rest| rest.len())) * 8;
+        let sps_id = r.read_ue("sps_seq_parameter_set_id")?;
+        let new_vps_id = self.map_vps_id(vps_id);
+        let new_sps_id = self.map_sps_id(sps_id);
+        if new_vps_id.is_none() && new_sps_id.is_none() {
+            return Ok(None);
+        }
+        let mut w = BitWriter::new();
+        w.write_u8(4, new_vps_id.unwrap_or(vps_id));
+        w.write_bits_from(rbsp, 4, ptl_end);
+        w.write_ue(new_sps_id.unwrap_or(sps_id));
+        splice(w, rbsp, ptl_end + ue_len(sps_id), rbsp.len()).map(Some)
+    }
+
+    fn rewrite_pps(&self, rbsp: &[u8]) -> Result<Option<Vec<u8>>, RemapError> {
+        let mut r = BitReader::new(rbsp);
+        let pps_id = r.read_ue("pps_pic_parameter_set_id")?;
+        let sps_id = r.read_ue("pps_seq_parameter_set_id")?;
+        let new_pps_id = self.map_pps_id(pps_id);
+        let new_sps_id = self.map_sps_id(sps_id);
+        if new_pps_id.is_none() && new_sps_id.is_none() {
+            return Ok(None);
+        }
+        let mut w = BitWriter::new();
+        w.write_ue(new_pps_id.unwrap_or(pps_id));
+        w.write_ue(new_sps_id.unwrap_or(sps_id));
+        splice(w, rbsp, ue_len(pps_id) + ue_len(sps_id), rbsp.len()).map(Some)
+    }
+
+    fn rewrite_slice(&self, header: NalHeader, rbsp: &[u8]) -> Result<Option<Vec<u8>>, RemapError> {
+        let mut r = BitReader::new(rbsp);
+        let pos = if header.nal_unit_type().is_irap() {
+            r.read_u8(2, "first_slice_segment_in_pic_flag")?;
+            2
+        } else {
+            r.read_bool("first_slice_segment_in_pic_flag")?;
+            1
+        };
+        let pps_id = r.read_ue("slice_pic_parameter_set_id")?;
+        let Some(new_pps_id) = self.map_pps_id(pps_id) else {
+            return Ok(None);
+        };
+        let new_len = ue_len(new_pps_id);
+        // If the id changes length, the header is shifted up to its byte_alignment() and the
+        // slice segment data following it is copied as-is.
+        let header_end = if new_len == ue_len(pps_id) {
+            rbsp.len()
+        } else {
+            let mut r = BitReader::new(rbsp);
+            SliceSegmentHeader::from_bits(&self.ctx, &mut r, header)
+                .map_err(RemapError::BadSliceHeader)?;
+            rbsp.len() - r.reader().map_or(0, |rest| rest.len())
+        };
+        let mut w = BitWriter::new();
+        w.write_bits_from(rbsp, 0, pos);
+        w.write_ue(new_pps_id);
+        splice(w, rbsp, pos + ue_len(pps_id), header_end).map(Some)
+    }
+
+    fn map_vps_id(&self, id: u8) -> Option<u8> {
+        let new = self.map.vps[id as usize];
+        (new != id).then_some(new)
+    }
+
+    fn map_sps_id(&self, id: u32) -> Option<u32> {
+        let new = u32::from(*self.map.sps.get(id as usize)?);
+        (new != id).then_some(new)
+    }
+
+    fn map_pps_id(&self, id: u32) -> Option<u32> {
+        let new = u32::from(*self.map.pps.get(id as usize)?);
+        (new != id).then_some(new)
+    }
+}
+
+impl NalFilter for IdRemapper {
+    fn filter(&mut self, nal: &[u8], out: &mut dyn FnMut(&[u8])) -> Result<(), FilterError> {
+        out(&self.rewrite(nal)?);
+        Ok(())
+    }
+}
+
+/// Completes `w` with the bits of `rbsp` from `from_bit` up to the final one bit within
+/// `rbsp[..end]` (the `rbsp_trailing_bits()` or `byte_alignment()`), rewrites that alignment
+/// for the new position, and appends the bytes following it unchanged.
+fn splice(
+    mut w: BitWriter,
+    rbsp: &[u8],
+    from_bit: usize,
+    end: usize,
+) -> Result<Vec<u8>, RemapError> {
+    let last = rbsp[..end]
+        .iter()
+        .rposition(|&b| b != 0)
+        .ok_or(RemapError::MissingTrailingBits)?;
+    let stop_bit = last * 8 + 7 - rbsp[last].trailing_zeros() as usize;
+    if stop_bit < from_bit {
+        return Err(RemapError::MissingTrailingBits);
+    }
+    w.write_bits_from(rbsp, from_bit, stop_bit);
+    w.write_byte_alignment();
+    let mut out = w.into_bytes();
+    out.extend_from_slice(&rbsp[last + 1..]);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::annexb::nal_units;
+    use hex_literal::hex;
+
+    // "Intinor HW encode 720x576p" SPS, x265 PPS and an IDR slice segment from the slice tests,
+    // followed by slice segment data.
+    const SPS: [u8; 59] = hex!(
+        "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46 d1
+         2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00 0b
+         b8 48"
+    );
+    const PPS: [u8; 7] = hex!("44 01 c1 72 b4 62 40");
+    const IDR: [u8; 6] = hex!("26 01 ac 9c 12 34");
+
+    fn parse(nal: &[u8]) -> Vec<u8> {
+        decode_nal(nal).unwrap().into_owned()
+    }
+
+    #[test]
+    fn remap_all() {
+        let mut map = ParamSetIdMap::new();
+        map.map_sps(
+            SeqParamSetId::from_u32(0).unwrap(),
+            SeqParamSetId::from_u32(3).unwrap(),
+        )
+        .map_pps(
+            PicParamSetId::from_u32(0).unwrap(),
+            PicParamSetId::from_u32(5).unwrap(),
+        );
+        let mut remapper = IdRemapper::new(map);
+        let sps = remapper.rewrite(&SPS).unwrap().into_owned();
+        let pps = remapper.rewrite(&PPS).unwrap().into_owned();
+        let idr = remapper.rewrite(&IDR).unwrap().into_owned();
+
+        let mut ctx = Context::new();
+        let sps = SeqParameterSet::from_bits(BitReader::new(&*parse(&sps))).unwrap();
+        let orig_sps = SeqParameterSet::from_bits(BitReader::new(&*parse(&SPS))).unwrap();
+        assert_eq!(sps.id().id(), 3);
+        assert_eq!(
+            orig_sps
+                .diff(&sps)
+                .iter()
+                .map(|d| &d.path[..])
+                .collect::<Vec<_>>(),
+            ["sps_seq_parameter_set_id"]
+        );
+        ctx.put_seq_param_set(sps);
+        let pps = PicParameterSet::from_bits(&ctx, BitReader::new(&*parse(&pps))).unwrap();
+        assert_eq!(pps.id().id(), 5);
+        assert_eq!(pps.seq_parameter_set_id.id(), 3);
+        ctx.put_pic_param_set(pps);
+
+        let rbsp = parse(&idr);
+        let mut r = BitReader::new(&rbsp[..]);
+        let header = NalHeader::new(idr[0], Some(idr[1])).unwrap();
+        let (hdr, _, _) = SliceSegmentHeader::from_bits(&ctx, &mut r, header).unwrap();
+        assert_eq!(hdr.slice_pic_parameter_set_id.id(), 5);
+        // The slice segment data is unchanged.
+        assert_eq!(r.reader().unwrap(), &[0x12, 0x34]);
+    }
+
+    #[test]
+    fn unchanged() {
+        let mut remapper = IdRemapper::new(ParamSetIdMap::new());
+        let mut stream = vec![];
+        for nal in [&SPS[..], &PPS, &IDR] {
+            stream.extend_from_slice(&[0, 0, 1]);
+            stream.extend_from_slice(nal);
+        }
+        for (_, nal) in nal_units(&stream) {
+            assert!(matches!(remapper.rewrite(nal).unwrap(), Cow::Borrowed(_)));
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! Removal of selected SEI messages, e.g. user data or timecodes, for privacy or to reduce
+//! size.
+
+use std::borrow::Cow;
+
+use crate::nal::sei::{HeaderType, SeiMessage, SeiNalReader};
+use crate::nal::{NalHeader, UnitType};
+use crate::rbsp::{encode_nal, BitReaderError};
+use crate::rewrite::{FilterError, NalFilter};
+
+/// True for ITU-T T.35 registered user data from Dolby (country code `0xB5`, provider code
+/// `0x003B`), which carries e.g. Dolby Vision metadata.
+pub fn is_dolby_metadata(msg: &SeiMessage<'_>) -> bool {
+    msg.payload_type == HeaderType::UserDataRegisteredItuTT35
+        && msg.payload.starts_with(&[0xb5, 0x00, 0x3b])
+}
+
+/// Filter which removes the selected messages from prefix and suffix SEI NAL units, re-framing
+/// the remaining messages. SEI NAL units left without messages are dropped entirely.
+///
+/// Messages inside a `scalable_nesting()` message are not inspected.
+///
+/// ```
+/// use hevc_reader::nal::sei::HeaderType;
+/// use hevc_reader::rewrite::sei_strip::SeiStripper;
+/// let mut stripper = SeiStripper::payload_types(&[HeaderType::UserDataUnregistered]);
+/// // A recovery_point() message followed by a one byte user_data_unregistered() message.
+/// let sei = [0x4e, 0x01, 0x06, 0x01, 0xc4, 0x05, 0x01, 0x42, 0x80];
+/// assert_eq!(&*stripper.process(&sei).unwrap().unwrap(), &[0x4e, 0x01, 0x06, 0x01, 0xc4, 0x80]);
+/// assert_eq!(stripper.removed_count(), 1);
+/// ```
+pub struct SeiStripper {
+    remove: Box<dyn FnMut(&SeiMessage<'_>) -> bool>,
+    removed: u64,
+}
+impl SeiStripper {
+    /// Creates a filter which removes the messages for which `remove` returns true.
+    pub fn new(remove: impl FnMut(&SeiMessage<'_>) -> bool + 'static) -> Self {
+        SeiStripper {
+            remove: Box::new(remove),
+            removed: 0,
+        }
+    }
+
+    /// Creates a filter which removes all messages of the given payload types.
+    pub fn payload_types(types: &[HeaderType]) -> Self {
+        let types = types.to_vec();
+        Self::new(move |msg| types.contains(&msg.payload_type))
+    }
+
+    /// The number of SEI messages removed so far.
+    pub fn removed_count(&self) -> u64 {
+        self.removed
+    }
+
+    /// Filters a NAL unit (without start code or length prefix), returning `None` if it should
+    /// be dropped. NAL units other than SEI, and SEI NAL units without any removed messages, are
+    /// returned as-is.
+    pub fn process<'a>(&mut self, nal: &'a [u8]) -> Result<Option<Cow<'a, [u8]>>, BitReaderError> {
+        let is_sei = nal
+            .first()
+            .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())
+            .is_some_and(|h| {
+                matches!(h.nal_unit_type(), UnitType::PrefixSEI | UnitType::SuffixSEI)
+            });
+        if !is_sei || nal.len() < 2 {
+            return Ok(Some(Cow::Borrowed(nal)));
+        }
+        // Payloads are only copied if there is something to remove.
+        let mut reader = SeiNalReader::new(nal);
+        let mut kept = vec![];
+        let mut any_removed = false;
+        while let Some(msg) = reader.next()? {
+            if msg.with_message(|m| (self.remove)(m)) {
+                self.removed += 1;
+                any_removed = true;
+            } else {
+                kept.push(msg);
+            }
+        }
+        if !any_removed {
+            return Ok(Some(Cow::Borrowed(nal)));
+        }
+        if kept.is_empty() {
+            return Ok(None);
+        }
+        let mut rbsp = Vec::with_capacity(nal.len());
+        for msg in kept {
+            msg.with_message(|m| m.write_to(&mut rbsp));
+        }
+        // rbsp_trailing_bits()
+        rbsp.push(0x80);
+        Ok(Some(Cow::Owned(encode_nal(&nal[..2], &rbsp))))
+    }
+}
+
+impl NalFilter for SeiStripper {
+    fn filter(&mut self, nal: &[u8], out: &mut dyn FnMut(&[u8])) -> Result<(), FilterError> {
+        if let Some(nal) = self.process(nal)? {
+            out(&nal);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn drop_empty_nal() {
+        let mut stripper = SeiStripper::new(is_dolby_metadata);
+        // A suffix SEI with only a Dolby T.35 message.
+        let sei = [0x50, 0x01, 0x04, 0x05, 0xb5, 0x00, 0x3b, 0x00, 0x00, 0x80];
+        assert_eq!(stripper.process(&sei).unwrap(), None);
+        // Other T.35 user data is kept, without re-encoding.
+        let sei = [0x50, 0x01, 0x04, 0x05, 0xb5, 0x00, 0x31, 0x00, 0x00, 0x80];
+        assert!(matches!(
+            stripper.process(&sei).unwrap(),
+            Some(Cow::Borrowed(_))
+        ));
+        // Non-SEI NAL units pass through.
+        let pps = [0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40];
+        assert_eq!(&*stripper.process(&pps).unwrap().unwrap(), &pps);
+        assert_eq!(stripper.removed_count(), 1);
+
+        // A payload kept after a removed one is escaped again.
+        let mut stripper = SeiStripper::payload_types(&[HeaderType::RecoveryPoint]);
+        let sei = [
+            0x4e, 0x01, 0x06, 0x01, 0xc4, 0x05, 0x04, 0x00, 0x00, 0x03, 0x01, 0x02, 0x80,
+        ];
+        assert_eq!(
+            &*stripper.process(&sei).unwrap().unwrap(),
+            [0x4e, 0x01, 0x05, 0x04, 0x00, 0x00, 0x03, 0x01, 0x02, 0x80]
+        );
+    }
+}
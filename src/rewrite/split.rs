@@ -0,0 +1,141 @@
+//! Splitting of an Annex B stream into independently decodable chunks, e.g. for transcoding
+//! the chunks in parallel.
+
+use crate::annexb::nal_units;
+use crate::nal::{NalHeader, UnitType};
+use crate::rewrite::trim::{extract, scan, RandomAccessPoint};
+
+/// A chunk of the stream, see [`split`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    /// The random access point starting the chunk.
+    pub start: RandomAccessPoint,
+    /// The number of pictures in the chunk, including any RASL pictures removed from it.
+    pub pictures: u64,
+    /// The chunk as a standalone Annex B stream, starting with its parameter sets.
+    pub data: Vec<u8>,
+}
+
+/// Returns the random access points at which the stream can be cut without breaking references
+/// between pictures: IDR and BLA pictures, and CRA pictures without RASL pictures.
+pub fn closed_gop_boundaries(data: &[u8]) -> Vec<RandomAccessPoint> {
+    let mut raps = scan(data);
+    // Picture indices of RASL pictures.
+    let mut rasl = vec![];
+    let mut pictures = 0;
+    for (_, nal) in nal_units(data) {
+        let Some(header) = nal
+            .first()
+            .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())
+        else {
+            continue;
+        };
+        let unit_type = header.nal_unit_type();
+        if unit_type.is_vcl() && nal.get(2).is_some_and(|b| b & 0x80 != 0) {
+            if matches!(
+                unit_type,
+                UnitType::SliceSegmentLayerRaslN | UnitType::SliceSegmentLayerRaslR
+            ) {
+                rasl.push(pictures);
+            }
+            pictures += 1;
+        }
+    }
+    let mut rasl = rasl.into_iter().peekable();
+    let mut open = vec![false; raps.len()];
+    for (i, rap) in raps.iter().enumerate() {
+        let next = raps.get(i + 1).map_or(u64::MAX, |r| r.picture);
+        while rasl.next_if(|&p| p < next).is_some() {
+            open[i] |= rap.unit_type == UnitType::SliceSegmentLayerCraNut;
+        }
+    }
+    let mut open = open.into_iter();
+    raps.retain(|_| !open.next().unwrap());
+    raps
+}
+
+/// Splits the stream at closed GOP boundaries into chunks of at least `min_pictures` pictures
+/// (except possibly the last one). Each chunk decodes on its own.
+///
+/// Any pictures before the first random access point are left out.
+///
+/// ```
+/// use hevc_reader::rewrite::split::split;
+/// let pps = [0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40];
+/// let idr = [0x26, 0x01, 0xac, 0x9c];
+/// let trail = [0x02, 0x01, 0xd0, 0x6c, 0x27, 0x80];
+/// let mut stream = vec![];
+/// for nal in [&pps[..], &idr, &trail, &idr, &trail, &idr] {
+///     stream.extend_from_slice(&[0, 0, 1]);
+///     stream.extend_from_slice(nal);
+/// }
+/// let chunks = split(&stream, 3);
+/// assert_eq!(chunks.iter().map(|c| c.pictures).collect::<Vec<_>>(), [4, 1]);
+/// ```
+pub fn split(data: &[u8], min_pictures: u64) -> Vec<Chunk> {
+    let boundaries = closed_gop_boundaries(data);
+    let total_pictures = nal_units(data)
+        .filter(|(_, nal)| {
+            nal.first()
+                .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())
+                .is_some_and(|h| h.nal_unit_type().is_vcl())
+                && nal.get(2).is_some_and(|b| b & 0x80 != 0)
+        })
+        .count() as u64;
+    let mut chunks = vec![];
+    let mut chunk_start = None;
+    for (i, &rap) in boundaries.iter().enumerate() {
+        let start = *chunk_start.get_or_insert(rap);
+        let next = boundaries.get(i + 1);
+        let end_picture = next.map_or(total_pictures, |r| r.picture);
+        if next.is_some() && end_picture - start.picture < min_pictures {
+            continue;
+        }
+        chunks.push(Chunk {
+            start,
+            pictures: end_picture - start.picture,
+            data: extract(data, start.offset, next.map(|r| r.offset))
+                .expect("chunks start with an IRAP picture"),
+        });
+        chunk_start = None;
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hex_literal::hex;
+
+    const PPS: [u8; 7] = hex!("44 01 c1 72 b4 62 40");
+    const IDR: [u8; 4] = hex!("26 01 ac 9c");
+    const TRAIL: [u8; 6] = hex!("02 01 d0 6c 27 80");
+    const CRA: [u8; 4] = hex!("2a 01 ac 9c");
+    const RASL: [u8; 4] = hex!("10 01 d0 6c");
+
+    fn annexb(nals: &[&[u8]]) -> Vec<u8> {
+        let mut stream = vec![];
+        for nal in nals {
+            stream.extend_from_slice(&[0, 0, 0, 1]);
+            stream.extend_from_slice(nal);
+        }
+        stream
+    }
+
+    #[test]
+    fn open_gop_cra() {
+        // A CRA picture with a RASL picture isn't a boundary; one without is.
+        let stream = annexb(&[
+            &PPS, &IDR, &TRAIL, &CRA, &RASL, &TRAIL, &CRA, &TRAIL, &TRAIL,
+        ]);
+        let boundaries = closed_gop_boundaries(&stream);
+        assert_eq!(
+            boundaries.iter().map(|r| r.picture).collect::<Vec<_>>(),
+            [0, 5]
+        );
+        let chunks = split(&stream, 1);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].pictures, 5);
+        assert_eq!(chunks[1].data, annexb(&[&PPS, &CRA, &TRAIL, &TRAIL]));
+    }
+}
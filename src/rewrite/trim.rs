@@ -0,0 +1,221 @@
+//! Extraction of a standalone sub-stream between random access points of an Annex B stream.
+
+use crate::annexb::nal_units;
+use crate::nal::{first_slice_segment_in_pic_flag, AccessUnitTracker, NalHeader, UnitType};
+use crate::rewrite::inject::ParamSetInjector;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TrimError {
+    /// The access unit at the given offset doesn't start with an IRAP picture.
+    NotRandomAccessPoint { offset: usize },
+}
+impl TrimError {
+    /// See [`crate::error_code`].
+    pub fn code(&self) -> u32 {
+        crate::error_code::TRIM
+            + match self {
+                TrimError::NotRandomAccessPoint { .. } => 1,
+            }
+    }
+}
+
+/// An IRAP access unit, as found by [`scan`] or taken from an index such as an MP4 sync sample
+/// table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RandomAccessPoint {
+    /// The offset of the first NAL unit of the access unit, just past its start code.
+    pub offset: usize,
+    /// The number of pictures preceding this one in decoding order.
+    pub picture: u64,
+    pub unit_type: UnitType,
+}
+
+/// Finds the IRAP access units of an Annex B stream.
+pub fn scan(data: &[u8]) -> Vec<RandomAccessPoint> {
+    let mut raps = vec![];
+    let mut tracker = AccessUnitTracker::default();
+    let mut au_start = 0;
+    let mut pictures = 0;
+    for (offset, nal) in nal_units(data) {
+        let Some(unit_type) = unit_type(nal) else {
+            continue;
+        };
+        if tracker.push(unit_type, nal) {
+            au_start = offset;
+        }
+        if unit_type.is_vcl() && first_slice_segment_in_pic_flag(nal) {
+            if unit_type.is_irap() {
+                raps.push(RandomAccessPoint {
+                    offset: au_start,
+                    picture: pictures,
+                    unit_type,
+                });
+            }
+            pictures += 1;
+        }
+    }
+    raps
+}
+
+/// Extracts the access units from offset `start` up to (but excluding) offset `end`, both
+/// [`RandomAccessPoint::offset`]s, as a standalone Annex B stream with four byte start codes.
+///
+/// The stream is prefixed with the latest VPS, SPS and PPS of each id preceding `start`. If the
+/// first picture is a CRA or BLA picture, its RASL pictures are removed, as they refer to
+/// pictures before it; any parameter sets in their access units are kept.
+///
+/// ```
+/// use hevc_reader::rewrite::trim::{extract, scan};
+/// let pps = [0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40];
+/// let idr = [0x26, 0x01, 0xac, 0x9c];
+/// let trail = [0x02, 0x01, 0xd0, 0x6c, 0x27, 0x80];
+/// let mut stream = vec![];
+/// for nal in [&pps[..], &idr, &trail, &idr, &trail, &idr] {
+///     stream.extend_from_slice(&[0, 0, 1]);
+///     stream.extend_from_slice(nal);
+/// }
+/// let raps = scan(&stream);
+/// assert_eq!(raps.len(), 3);
+/// let trimmed = extract(&stream, raps[1].offset, Some(raps[2].offset)).unwrap();
+/// assert_eq!(trimmed.len(), 3 * 4 + pps.len() + idr.len() + trail.len());
+/// ```
+pub fn extract(data: &[u8], start: usize, end: Option<usize>) -> Result<Vec<u8>, TrimError> {
+    let mut param_sets = ParamSetInjector::new();
+    let mut out = vec![];
+    let mut push = |nal: &[u8]| {
+        out.extend_from_slice(&[0, 0, 0, 1]);
+        out.extend_from_slice(nal);
+    };
+    let mut tracker = AccessUnitTracker::default();
+    let mut au: Vec<&[u8]> = vec![];
+    let mut found_irap = false;
+    // True while in the leading pictures of a CRA or BLA picture.
+    let mut skip_rasl = false;
+    for (offset, nal) in nal_units(data) {
+        if end.is_some_and(|end| offset >= end) {
+            break;
+        }
+        let Some(unit_type) = unit_type(nal) else {
+            continue;
+        };
+        if offset < start {
+            param_sets.store(nal);
+            continue;
+        }
+        if tracker.push(unit_type, nal) && !au.is_empty() {
+            emit_access_unit(&au, skip_rasl, &mut push);
+            au.clear();
+        }
+        if unit_type.is_vcl() && first_slice_segment_in_pic_flag(nal) {
+            if !found_irap {
+                if !unit_type.is_irap() {
+                    return Err(TrimError::NotRandomAccessPoint { offset: start });
+                }
+                found_irap = true;
+                skip_rasl = !unit_type.is_idr();
+                for nal in param_sets.stored() {
+                    push(nal);
+                }
+            } else if unit_type.is_irap() {
+                skip_rasl = false;
+            }
+        }
+        au.push(nal);
+    }
+    if !found_irap {
+        return Err(TrimError::NotRandomAccessPoint { offset: start });
+    }
+    emit_access_unit(&au, skip_rasl, &mut push);
+    Ok(out)
+}
+
+/// Emits the NAL units of an access unit, or only its parameter sets if it is a RASL picture
+/// to skip.
+fn emit_access_unit(au: &[&[u8]], skip_rasl: bool, push: &mut impl FnMut(&[u8])) {
+    let is_rasl = au.iter().filter_map(|nal| unit_type(nal)).any(|t| {
+        matches!(
+            t,
+            UnitType::SliceSegmentLayerRaslN | UnitType::SliceSegmentLayerRaslR
+        )
+    });
+    for nal in au {
+        let keep = !(skip_rasl && is_rasl)
+            || unit_type(nal).is_some_and(|t| {
+                matches!(
+                    t,
+                    UnitType::VideoParameterSet
+                        | UnitType::SeqParameterSet
+                        | UnitType::PicParameterSet
+                )
+            });
+        if keep {
+            push(nal);
+        }
+    }
+}
+
+fn unit_type(nal: &[u8]) -> Option<UnitType> {
+    let header = NalHeader::new(*nal.first()?, nal.get(1).copied()).ok()?;
+    Some(header.nal_unit_type())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hex_literal::hex;
+
+    const SPS: [u8; 59] = hex!(
+        "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46 d1
+         2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00 0b
+         b8 48"
+    );
+    const PPS: [u8; 7] = hex!("44 01 c1 72 b4 62 40");
+    const IDR: [u8; 4] = hex!("26 01 ac 9c");
+    const TRAIL: [u8; 6] = hex!("02 01 d0 6c 27 80");
+    const CRA: [u8; 4] = hex!("2a 01 ac 9c");
+    const RASL: [u8; 4] = hex!("10 01 d0 6c");
+
+    fn annexb(nals: &[&[u8]]) -> Vec<u8> {
+        let mut stream = vec![];
+        for nal in nals {
+            stream.extend_from_slice(&[0, 0, 0, 1]);
+            stream.extend_from_slice(nal);
+        }
+        stream
+    }
+
+    #[test]
+    fn cra_drops_rasl() {
+        let stream = annexb(&[
+            &SPS, &PPS, &IDR, &TRAIL, &CRA, &RASL, &PPS, &RASL, &TRAIL, &IDR,
+        ]);
+        let raps = scan(&stream);
+        assert_eq!(
+            raps.iter()
+                .map(|r| (r.picture, r.unit_type))
+                .collect::<Vec<_>>(),
+            [
+                (0, UnitType::SliceSegmentLayerIdrWLp),
+                (2, UnitType::SliceSegmentLayerCraNut),
+                (6, UnitType::SliceSegmentLayerIdrWLp),
+            ]
+        );
+        // The first access unit starts with the SPS.
+        assert_eq!(raps[0].offset, 4);
+        let trimmed = extract(&stream, raps[1].offset, Some(raps[2].offset)).unwrap();
+        assert_eq!(trimmed, annexb(&[&SPS, &PPS, &CRA, &PPS, &TRAIL]));
+        // Up to the end of the stream.
+        let trimmed = extract(&stream, raps[2].offset, None).unwrap();
+        assert_eq!(trimmed, annexb(&[&SPS, &PPS, &IDR]));
+    }
+
+    #[test]
+    fn not_random_access_point() {
+        let stream = annexb(&[&PPS, &IDR, &TRAIL]);
+        let offset = 4 + PPS.len() + 4 + IDR.len() + 4;
+        assert_eq!(
+            extract(&stream, offset, None),
+            Err(TrimError::NotRandomAccessPoint { offset })
+        );
+    }
+}
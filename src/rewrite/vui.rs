@@ -0,0 +1,284 @@
+//! Editing of the SPS `vui_parameters()`, e.g. to fix mis-signalled colorimetry, aspect ratio or
+//! frame rate without re-encoding.
+
+use crate::nal::sps::{
+    AspectRatioInfo, ColourDescription, SeqParameterSet, SpsError, TimingInfo, VideoFormat,
+    VideoSignalType, VuiParameters,
+};
+use crate::nal::{NalHeader, NalHeaderError, UnitType};
+use crate::rbsp::{
+    decode_nal, encode_nal, BitReader, BitReaderError, BitWriter, SyntaxElement, VisitingReader,
+};
+use crate::rewrite::{FilterError, NalFilter};
+
+#[derive(Debug)]
+pub enum VuiEditError {
+    RbspError(BitReaderError),
+    BadNalHeader(NalHeaderError),
+    /// The NAL unit wasn't an SPS.
+    NotSeqParamSet(UnitType),
+    BadSeqParamSet(SpsError),
+}
+impl VuiEditError {
+    /// See [`crate::error_code`].
+    pub fn code(&self) -> u32 {
+        crate::error_code::VUI_EDIT
+            + match self {
+                VuiEditError::RbspError(..) => 1,
+                VuiEditError::BadNalHeader(..) => 2,
+                VuiEditError::NotSeqParamSet(..) => 3,
+                VuiEditError::BadSeqParamSet(..) => 4,
+            }
+    }
+}
+impl From<BitReaderError> for VuiEditError {
+    fn from(e: BitReaderError) -> Self {
+        VuiEditError::RbspError(e)
+    }
+}
+
+/// A parsed SPS NAL unit whose VUI can be changed and written back, leaving the other syntax
+/// elements bit-exact.
+///
+/// ```
+/// use hevc_reader::nal::sps::ColourDescription;
+/// use hevc_reader::rewrite::vui::SpsVuiEditor;
+/// # let sps = [
+/// #     0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03, 0x00, 0x00,
+/// #     0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46, 0xd1, 0x2e,
+/// #     0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10, 0x00, 0x00, 0x03,
+/// #     0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00, 0x0b, 0xb8, 0x48,
+/// # ];
+/// let mut editor = SpsVuiEditor::new(&sps).unwrap();
+/// assert_eq!(editor.to_nal(), sps);
+/// editor
+///     .set_colour_description(Some(ColourDescription {
+///         colour_primaries: 9,
+///         transfer_characteristics: 16,
+///         matrix_coeffs: 9,
+///     }))
+///     .set_full_range(false);
+/// let edited = SpsVuiEditor::new(&editor.to_nal()).unwrap();
+/// let signal = edited.vui().unwrap().video_signal_type.as_ref().unwrap();
+/// assert_eq!(signal.colour_description.as_ref().unwrap().transfer_characteristics, 16);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SpsVuiEditor {
+    header: [u8; 2],
+    rbsp: Vec<u8>,
+    /// The bit positions of `vui_parameters_present_flag` and `sps_extension_present_flag`.
+    vui_start: usize,
+    vui_end: usize,
+    sps: SeqParameterSet,
+}
+impl SpsVuiEditor {
+    /// Parses an SPS NAL unit, given without start code or length prefix.
+    pub fn new(nal: &[u8]) -> Result<Self, VuiEditError> {
+        let [b0, b1, ..] = *nal else {
+            return Err(VuiEditError::BadNalHeader(NalHeaderError::IncompleteHeader));
+        };
+        let header = NalHeader::new(b0, Some(b1)).map_err(VuiEditError::BadNalHeader)?;
+        if header.nal_unit_type() != UnitType::SeqParameterSet {
+            return Err(VuiEditError::NotSeqParamSet(header.nal_unit_type()));
+        }
+        let rbsp = decode_nal(nal)
+            .map_err(BitReaderError::ReaderError)?
+            .into_owned();
+        // The bit positions of the flags before and after the VUI.
+        let (mut vui_start, mut vui_end) = (None, None);
+        let mut visitor = |e: &SyntaxElement| match e.name {
+            "vui_parameters_present_flag" => vui_start = vui_start.or(Some(e.bits.start)),
+            "sps_extension_present_flag" => vui_end = vui_end.or(Some(e.bits.start)),
+            _ => {}
+        };
+        let sps = SeqParameterSet::from_bits(VisitingReader::new(
+            BitReader::new(&rbsp[..]),
+            &mut visitor,
+        ))
+        .map_err(VuiEditError::BadSeqParamSet)?;
+        let (Some(vui_start), Some(vui_end)) = (vui_start, vui_end) else {
+            unreachable!("both flags are read by a successful parse");
+        };
+        let (vui_start, vui_end) = (vui_start as usize, vui_end as usize);
+        Ok(SpsVuiEditor {
+            header: [b0, b1],
+            rbsp,
+            vui_start,
+            vui_end,
+            sps,
+        })
+    }
+
+    /// The SPS, including any changes made to the VUI.
+    pub fn sps(&self) -> &SeqParameterSet {
+        &self.sps
+    }
+
+    pub fn vui(&self) -> Option<&VuiParameters> {
+        self.sps.vui_parameters.as_ref()
+    }
+
+    /// Gives access to all VUI fields, adding an empty VUI if the SPS has none.
+    pub fn vui_mut(&mut self) -> &mut VuiParameters {
+        self.sps.vui_parameters.get_or_insert_with(Default::default)
+    }
+
+    /// Removes the VUI entirely.
+    pub fn clear_vui(&mut self) -> &mut Self {
+        self.sps.vui_parameters = None;
+        self
+    }
+
+    /// Sets the sample aspect ratio, or removes it with `None`.
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: Option<AspectRatioInfo>) -> &mut Self {
+        self.vui_mut().aspect_ratio_info = aspect_ratio;
+        self
+    }
+
+    /// Sets the timing info, keeping any existing POC proportional timing and HRD parameters.
+    pub fn set_timing(&mut self, num_units_in_tick: u32, time_scale: u32) -> &mut Self {
+        let timing = self
+            .vui_mut()
+            .timing_info
+            .get_or_insert_with(TimingInfo::default);
+        timing.num_units_in_tick = num_units_in_tick;
+        timing.time_scale = time_scale;
+        self
+    }
+
+    /// Sets the colour primaries, transfer characteristics and matrix coefficients, or removes
+    /// them with `None`.
+    pub fn set_colour_description(&mut self, desc: Option<ColourDescription>) -> &mut Self {
+        if desc.is_some() || self.vui().is_some_and(|v| v.video_signal_type.is_some()) {
+            self.video_signal_type().colour_description = desc;
+        }
+        self
+    }
+
+    pub fn set_full_range(&mut self, video_full_range_flag: bool) -> &mut Self {
+        self.video_signal_type().video_full_range_flag = video_full_range_flag;
+        self
+    }
+
+    /// The video signal type, added with the values inferred when absent if needed.
+    fn video_signal_type(&mut self) -> &mut VideoSignalType {
+        self.vui_mut()
+            .video_signal_type
+            .get_or_insert(VideoSignalType {
+                video_format: VideoFormat::Unspecified,
+                video_full_range_flag: false,
+                colour_description: None,
+            })
+    }
+
+    /// Writes the SPS NAL unit with the current VUI.
+    pub fn to_nal(&self) -> Vec<u8> {
+        let mut w = BitWriter::new();
+        w.write_bits_from(&self.rbsp, 0, self.vui_start);
+        VuiParameters::write(self.sps.vui_parameters.as_ref(), &mut w);
+        // The parse succeeded, so there are rbsp_trailing_bits() ending in a one bit.
+        let last = self.rbsp.iter().rposition(|&b| b != 0).unwrap();
+        let stop_bit = last * 8 + 7 - self.rbsp[last].trailing_zeros() as usize;
+        w.write_bits_from(&self.rbsp, self.vui_end, stop_bit);
+        w.write_rbsp_trailing_bits();
+        encode_nal(&self.header, &w.into_bytes())
+    }
+}
+
+/// Filter which applies the same VUI edit to every SPS in a stream.
+pub struct VuiRewriter {
+    edit: Box<dyn FnMut(&mut SpsVuiEditor)>,
+}
+impl VuiRewriter {
+    pub fn new(edit: impl FnMut(&mut SpsVuiEditor) + 'static) -> Self {
+        VuiRewriter {
+            edit: Box::new(edit),
+        }
+    }
+}
+
+impl NalFilter for VuiRewriter {
+    fn filter(&mut self, nal: &[u8], out: &mut dyn FnMut(&[u8])) -> Result<(), FilterError> {
+        let is_sps = nal
+            .first()
+            .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())
+            .is_some_and(|h| h.nal_unit_type() == UnitType::SeqParameterSet);
+        if !is_sps {
+            out(nal);
+            return Ok(());
+        }
+        let mut editor = SpsVuiEditor::new(nal)?;
+        (self.edit)(&mut editor);
+        out(&editor.to_nal());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hex_literal::hex;
+
+    // "Intinor HW encode 720x576p" SPS, which has timing info.
+    const SPS: [u8; 59] = hex!(
+        "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46 d1
+         2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00 0b
+         b8 48"
+    );
+
+    #[test]
+    fn round_trip() {
+        let mut editor = SpsVuiEditor::new(&SPS).unwrap();
+        assert_eq!(editor.to_nal(), SPS);
+        let orig = editor.sps().clone();
+        editor
+            .set_aspect_ratio(Some(AspectRatioInfo::Extended(64, 45)))
+            .set_timing(1001, 60000)
+            .set_full_range(true);
+        let edited = SpsVuiEditor::new(&editor.to_nal()).unwrap();
+        assert_eq!(edited.sps(), editor.sps());
+        let vui = edited.vui().unwrap();
+        assert_eq!(
+            vui.aspect_ratio_info.as_ref().unwrap().get(),
+            Some((64, 45))
+        );
+        assert_eq!(vui.timing_info.as_ref().unwrap().time_scale, 60000);
+        // Only the VUI changed.
+        assert!(orig
+            .diff(edited.sps())
+            .iter()
+            .all(|d| d.path.starts_with("vui_parameters")));
+
+        editor.clear_vui();
+        let edited = SpsVuiEditor::new(&editor.to_nal()).unwrap();
+        assert_eq!(edited.vui(), None);
+        assert_eq!(edited.sps().pic_width_in_luma_samples, 736);
+    }
+
+    #[test]
+    fn hrd_round_trip() {
+        // "Haivision 1080i25" SPS, with HRD parameters in the VUI.
+        let sps = hex!(
+            "42 01 01 01 40 00 00 03 00 40 00 00 03 00 00 03 00 7b a0 03 c0 80 22 1f 79 e9 6e
+             44 a1 7f f8 00 08 00 13 50 10 10 1e d0 00 00 03 00 10 00 00 03 03 25 08 ff de 10
+             00 16 e3 60 00 05 dd 77 df 08 04 10"
+        );
+        let mut editor = SpsVuiEditor::new(&sps).unwrap();
+        assert_eq!(editor.to_nal(), sps);
+        editor.set_colour_description(Some(ColourDescription {
+            colour_primaries: 1,
+            transfer_characteristics: 1,
+            matrix_coeffs: 1,
+        }));
+        let edited = SpsVuiEditor::new(&editor.to_nal()).unwrap();
+        assert_eq!(edited.sps(), editor.sps());
+    }
+
+    #[test]
+    fn not_sps() {
+        assert!(matches!(
+            SpsVuiEditor::new(&hex!("44 01 c1 72 b4 62 40")),
+            Err(VuiEditError::NotSeqParamSet(UnitType::PicParameterSet))
+        ));
+    }
+}
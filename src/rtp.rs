@@ -0,0 +1,231 @@
+//! Packetization of NAL units into RTP payloads as specified by
+//! [RFC 7798](https://www.rfc-editor.org/rfc/rfc7798).
+//!
+//! Only the non-interleaved mode is supported: payloads carry no decoding order numbers, as when
+//! `sprop-max-don-diff` is 0.
+
+/// Payload header type of an aggregation packet.
+const AP: u8 = 48;
+/// Payload header type of a fragmentation unit.
+const FU: u8 = 49;
+/// Payload header type of a payload content information packet.
+const PACI: u8 = 50;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PacketizeError {
+    /// The maximum payload size can't fit a fragmentation unit with at least one byte of NAL
+    /// unit payload.
+    PayloadSizeTooSmall(usize),
+    /// A NAL unit was shorter than its two byte header.
+    NalTooShort,
+    /// A NAL unit's type is one RFC 7798 uses for its own packet types.
+    ReservedUnitType(u8),
+}
+impl PacketizeError {
+    /// See [`crate::error_code`].
+    pub fn code(&self) -> u32 {
+        crate::error_code::PACKETIZE
+            + match self {
+                PacketizeError::PayloadSizeTooSmall(..) => 1,
+                PacketizeError::NalTooShort => 2,
+                PacketizeError::ReservedUnitType(..) => 3,
+            }
+    }
+}
+
+/// Splits the NAL units of an access unit into RTP payloads of at most a given size.
+///
+/// NAL units which fit are sent in single NAL unit packets, or combined into aggregation
+/// packets if enabled. Larger ones are split into fragmentation units.
+///
+/// ```
+/// use hevc_reader::rtp::Packetizer;
+/// let packetizer = Packetizer::new(8).unwrap();
+/// let vps = [0x40, 0x01, 0x0c];
+/// let sps = [0x42, 0x01, 0x01];
+/// let idr = [0x26, 0x01, 0xaf, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07];
+/// let payloads = packetizer.packetize([&vps[..], &sps, &idr]).unwrap();
+/// assert_eq!(
+///     payloads,
+///     [
+///         // An aggregation packet isn't smaller here, so the VPS and SPS go on their own.
+///         &vps[..],
+///         &sps,
+///         // Fragmentation units with start and end bits set.
+///         &[0x62, 0x01, 0x93, 0xaf, 0x01, 0x02, 0x03, 0x04],
+///         &[0x62, 0x01, 0x53, 0x05, 0x06, 0x07],
+///     ]
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct Packetizer {
+    max_payload_size: usize,
+    aggregate: bool,
+}
+impl Packetizer {
+    /// Creates a packetizer for payloads of at most `max_payload_size` bytes: the path MTU
+    /// minus the IP, UDP and RTP headers. Aggregation is enabled.
+    pub fn new(max_payload_size: usize) -> Result<Self, PacketizeError> {
+        // The payload header, the FU header and one byte.
+        if max_payload_size < 4 {
+            return Err(PacketizeError::PayloadSizeTooSmall(max_payload_size));
+        }
+        Ok(Packetizer {
+            max_payload_size,
+            aggregate: true,
+        })
+    }
+
+    /// Sets whether small NAL units are combined into aggregation packets.
+    pub fn aggregate(mut self, aggregate: bool) -> Self {
+        self.aggregate = aggregate;
+        self
+    }
+
+    /// Returns the payloads for the NAL units of one access unit, in order. The RTP marker bit
+    /// belongs on the packet of the last payload.
+    pub fn packetize<'a>(
+        &self,
+        nals: impl IntoIterator<Item = &'a [u8]>,
+    ) -> Result<Vec<Vec<u8>>, PacketizeError> {
+        let mut payloads = vec![];
+        // NAL units waiting to be sent in an aggregation packet.
+        let mut pending: Vec<&[u8]> = vec![];
+        for nal in nals {
+            if nal.len() < 2 {
+                return Err(PacketizeError::NalTooShort);
+            }
+            let unit_type = (nal[0] >> 1) & 0x3f;
+            if matches!(unit_type, AP | FU | PACI) {
+                return Err(PacketizeError::ReservedUnitType(unit_type));
+            }
+            if nal.len() > self.max_payload_size {
+                self.flush(&mut pending, &mut payloads);
+                self.fragment(nal, &mut payloads);
+                continue;
+            }
+            if !self.aggregate {
+                payloads.push(nal.to_vec());
+                continue;
+            }
+            // Aggregation units have a 16 bit NAL unit size.
+            if nal.len() > usize::from(u16::MAX) {
+                self.flush(&mut pending, &mut payloads);
+                payloads.push(nal.to_vec());
+                continue;
+            }
+            if ap_size(&pending) + 2 + nal.len() > self.max_payload_size {
+                self.flush(&mut pending, &mut payloads);
+            }
+            pending.push(nal);
+        }
+        self.flush(&mut pending, &mut payloads);
+        Ok(payloads)
+    }
+
+    /// Sends the pending NAL units in an aggregation packet, or a single NAL unit packet if
+    /// there is only one.
+    fn flush(&self, pending: &mut Vec<&[u8]>, payloads: &mut Vec<Vec<u8>>) {
+        match pending[..] {
+            [] => {}
+            [nal] => payloads.push(nal.to_vec()),
+            _ => {
+                // F is set if any aggregated NAL unit's is, and LayerId and TID are the lowest
+                // of the aggregated NAL units'.
+                let f = pending.iter().fold(0, |f, nal| f | (nal[0] & 0x80));
+                let layer_id = pending.iter().map(|nal| layer_id(nal)).min().unwrap();
+                let tid = pending.iter().map(|nal| nal[1] & 0x7).min().unwrap();
+                let mut payload = Vec::with_capacity(ap_size(pending));
+                payload.push(f | AP << 1 | layer_id >> 5);
+                payload.push((layer_id & 0x1f) << 3 | tid);
+                for nal in pending.iter() {
+                    payload.extend_from_slice(&(nal.len() as u16).to_be_bytes());
+                    payload.extend_from_slice(nal);
+                }
+                payloads.push(payload);
+            }
+        }
+        pending.clear();
+    }
+
+    /// Splits `nal` into fragmentation units, which carry its header fields in their payload
+    /// header and FU header.
+    fn fragment(&self, nal: &[u8], payloads: &mut Vec<Vec<u8>>) {
+        let unit_type = (nal[0] >> 1) & 0x3f;
+        let header = [nal[0] & 0x81 | FU << 1, nal[1]];
+        let chunks = nal[2..].chunks(self.max_payload_size - 3);
+        let last = chunks.len() - 1;
+        for (i, chunk) in chunks.enumerate() {
+            let mut fu_header = unit_type;
+            if i == 0 {
+                fu_header |= 0x80;
+            }
+            if i == last {
+                fu_header |= 0x40;
+            }
+            let mut payload = Vec::with_capacity(3 + chunk.len());
+            payload.extend_from_slice(&header);
+            payload.push(fu_header);
+            payload.extend_from_slice(chunk);
+            payloads.push(payload);
+        }
+    }
+}
+
+fn layer_id(nal: &[u8]) -> u8 {
+    (nal[0] & 0x1) << 5 | nal[1] >> 3
+}
+
+/// The size of an aggregation packet of `nals`.
+fn ap_size(nals: &[&[u8]]) -> usize {
+    2 + nals.iter().map(|nal| 2 + nal.len()).sum::<usize>()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hex_literal::hex;
+
+    #[test]
+    fn aggregation() {
+        let packetizer = Packetizer::new(16).unwrap();
+        // A prefix SEI with TID 2, a PPS in layer 1 and a slice segment which doesn't fit.
+        let sei = hex!("4e 03 05 00 80");
+        let pps = hex!("44 09 c1 72");
+        let trail = hex!("02 01 d0 6c 27 80 00 00 00 01 02 03 04 05 06 07 08");
+        let payloads = packetizer.packetize([&sei[..], &pps, &trail]).unwrap();
+        assert_eq!(
+            payloads,
+            [
+                &hex!("60 01 00 05 4e 03 05 00 80 00 04 44 09 c1 72")[..],
+                &hex!("62 01 81 d0 6c 27 80 00 00 00 01 02 03 04 05 06")[..],
+                &hex!("62 01 41 07 08")[..],
+            ]
+        );
+        assert_eq!(
+            packetizer
+                .clone()
+                .aggregate(false)
+                .packetize([&sei[..], &pps])
+                .unwrap(),
+            [&sei[..], &pps]
+        );
+        assert_eq!(
+            packetizer.packetize([&hex!("60 01")[..]]),
+            Err(PacketizeError::ReservedUnitType(AP))
+        );
+        // With payloads past 64 KiB, NAL units too long for an aggregation unit go on their own.
+        let long = [&trail[..], &[0; 65530]].concat();
+        assert_eq!(
+            Packetizer::new(1 << 17)
+                .unwrap()
+                .packetize([&pps[..], &long, &sei])
+                .unwrap(),
+            [&pps[..], &long, &sei]
+        );
+        assert_eq!(
+            Packetizer::new(3).err(),
+            Some(PacketizeError::PayloadSizeTooSmall(3))
+        );
+    }
+}
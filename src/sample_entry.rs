@@ -0,0 +1,489 @@
+//! Construction of the `hvc1` or `hev1` sample entry of an MP4 (ISO/IEC 14496-15) track: the
+//! fields and box payloads a muxer needs, derived from the parameter sets.
+
+use crate::duration::DurationScanner;
+use crate::hvcc::{check_length_size, SampleError};
+use crate::nal::pps::{ParallelismType, PicParameterSet};
+use crate::nal::sei::content_light_level::ContentLightLevelInfo;
+use crate::nal::sei::mastering_display_colour_volume::MasteringDisplayColourVolume;
+use crate::nal::sps::{SeqParameterSet, SpsError};
+use crate::nal::{NalHeader, UnitType};
+use crate::rbsp::BitReader;
+use crate::Context;
+
+#[derive(Debug)]
+pub enum SampleEntryError {
+    /// The context has no SPS.
+    NoSeqParamSet,
+    BadSeqParamSet(SpsError),
+    BadLengthSize(SampleError),
+    /// A NAL unit given for the `hvcC` arrays isn't a parameter set or SEI, or has a bad header.
+    UnexpectedNal,
+    /// A NAL unit is too long for the `hvcC` box's 16 bit lengths.
+    NalTooLong(usize),
+    /// The cropped picture size doesn't fit the sample entry's 16 bit fields.
+    TooLarge {
+        width: u32,
+        height: u32,
+    },
+}
+impl SampleEntryError {
+    /// See [`crate::error_code`].
+    pub fn code(&self) -> u32 {
+        crate::error_code::SAMPLE_ENTRY
+            + match self {
+                SampleEntryError::NoSeqParamSet => 1,
+                SampleEntryError::BadSeqParamSet(..) => 2,
+                SampleEntryError::BadLengthSize(..) => 3,
+                SampleEntryError::UnexpectedNal => 4,
+                SampleEntryError::NalTooLong(..) => 5,
+                SampleEntryError::TooLarge { .. } => 6,
+            }
+    }
+}
+impl From<SpsError> for SampleEntryError {
+    fn from(e: SpsError) -> Self {
+        SampleEntryError::BadSeqParamSet(e)
+    }
+}
+
+/// The choices a muxer makes about the sample entry.
+#[derive(Debug, Clone)]
+pub struct SampleEntryConfig {
+    /// `hvc1`, where every parameter set is in the `hvcC` box, or `hev1`, where samples may
+    /// carry them too.
+    pub sample_entry_type: [u8; 4],
+    /// The size of the NAL unit lengths in samples.
+    pub length_size: usize,
+    /// HDR10 metadata for the `mdcv` and `clli` boxes, e.g. from the first IRAP access unit.
+    pub mastering_display: Option<MasteringDisplayColourVolume>,
+    pub content_light_level: Option<ContentLightLevelInfo>,
+    /// The `hvcC` fields found by an [`HvccAnalyzer`]. Without them, the fields are taken from
+    /// the first SPS and the PPSs of the context, and `constantFrameRate` is 0.
+    pub fields: Option<HvccFields>,
+}
+impl Default for SampleEntryConfig {
+    /// `hvc1` with 4 byte lengths, no HDR metadata and no stream analysis.
+    fn default() -> Self {
+        SampleEntryConfig {
+            sample_entry_type: *b"hvc1",
+            length_size: 4,
+            mastering_display: None,
+            content_light_level: None,
+            fields: None,
+        }
+    }
+}
+
+/// The fields of an `HEVCDecoderConfigurationRecord` which describe the whole stream rather than
+/// one SPS, as found by an [`HvccAnalyzer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HvccFields {
+    /// The lowest `min_spatial_segmentation_idc` of the SPSs, 0 if any has none.
+    pub min_spatial_segmentation_idc: u16,
+    /// The parallelism all PPSs allow, or [`ParallelismType::Mixed`] (unknown) if
+    /// `min_spatial_segmentation_idc` is 0.
+    pub parallelism_type: ParallelismType,
+    /// `avgFrameRate`, in frames per 256 seconds, or 0 if unknown.
+    pub avg_frame_rate: u16,
+    /// `constantFrameRate`: 1 if every picture is displayed for the same time, else 0.
+    pub constant_frame_rate: u8,
+    /// The number of temporal layers the pictures use.
+    pub num_temporal_layers: u8,
+    /// True if every SPS has `sps_temporal_id_nesting_flag`, or the pictures use one temporal
+    /// layer only.
+    pub temporal_id_nested: bool,
+}
+
+/// Finds the [`HvccFields`] of a stream, fed one NAL unit at a time in decoding order.
+///
+/// ```
+/// use hevc_reader::nal::pps::ParallelismType;
+/// use hevc_reader::sample_entry::HvccAnalyzer;
+/// let mut analyzer = HvccAnalyzer::new();
+/// analyzer.push_annexb(&[
+///     // "Intinor HW encode 720x576p" SPS and its PPS.
+///     0, 0, 1, 0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03,
+///     0x00, 0x00, 0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46,
+///     0xd1, 0x2e, 0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10, 0x00,
+///     0x00, 0x03, 0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00, 0x0b, 0xb8,
+///     0x48,
+///     0, 0, 1, 0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40,
+///     // Two IDR pictures.
+///     0, 0, 1, 0x26, 0x01, 0xac, 0x9c,
+///     0, 0, 1, 0x26, 0x01, 0xac, 0x9c,
+/// ]);
+/// let fields = analyzer.finish();
+/// assert_eq!(fields.min_spatial_segmentation_idc, 0);
+/// assert_eq!(fields.parallelism_type, ParallelismType::Mixed);
+/// // 25 frames per second.
+/// assert_eq!((fields.avg_frame_rate, fields.constant_frame_rate), (6400, 1));
+/// assert_eq!(fields.num_temporal_layers, 1);
+/// assert!(fields.temporal_id_nested);
+/// ```
+#[derive(Default)]
+pub struct HvccAnalyzer {
+    ctx: Context,
+    duration: DurationScanner,
+    /// The lowest `min_spatial_segmentation_idc` of the SPSs so far.
+    min_spatial_segmentation_idc: Option<u32>,
+    parallelism_type: Option<ParallelismType>,
+    all_nested: bool,
+    /// The highest `nuh_temporal_id_plus1` of the pictures so far.
+    max_temporal_id_plus1: u8,
+}
+impl HvccAnalyzer {
+    pub fn new() -> Self {
+        HvccAnalyzer {
+            duration: DurationScanner::new(),
+            all_nested: true,
+            ..Self::default()
+        }
+    }
+
+    /// Adds a NAL unit (without start code or length prefix). NAL units which can't be parsed
+    /// are ignored.
+    pub fn push_nal(&mut self, nal: &[u8]) {
+        self.duration.push_nal(nal);
+        let Some(header) = nal
+            .first()
+            .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())
+        else {
+            return;
+        };
+        if !matches!(header.nuh_layer_id(), Ok(0)) {
+            return;
+        }
+        let r = BitReader::from_nal(nal);
+        match header.nal_unit_type() {
+            UnitType::VideoParameterSet => {
+                self.ctx.put_param_set_nal(nal);
+            }
+            UnitType::SeqParameterSet => {
+                let Ok(sps) = SeqParameterSet::from_bits(r) else {
+                    return;
+                };
+                let idc = sps
+                    .vui_parameters
+                    .as_ref()
+                    .and_then(|v| v.bitstream_restrictions.as_ref())
+                    .map_or(0, |b| b.min_spatial_segmentation_idc);
+                let min = self.min_spatial_segmentation_idc.get_or_insert(idc);
+                *min = (*min).min(idc);
+                self.all_nested &= sps.sps_temporal_id_nesting;
+                self.ctx.put_seq_param_set(sps);
+            }
+            UnitType::PicParameterSet => {
+                let Ok(pps) = PicParameterSet::from_bits(&self.ctx, r) else {
+                    return;
+                };
+                let t = ParallelismType::for_pps([&pps]);
+                self.parallelism_type = match self.parallelism_type {
+                    Some(prev) if prev != t => Some(ParallelismType::Mixed),
+                    _ => Some(t),
+                };
+                self.ctx.put_pic_param_set(pps);
+            }
+            t if t.is_vcl() => {
+                let temporal_id_plus1 = header.temporal_id() + 1;
+                self.max_temporal_id_plus1 = self.max_temporal_id_plus1.max(temporal_id_plus1);
+            }
+            _ => {}
+        }
+    }
+
+    /// Adds the NAL units of an Annex B stream.
+    pub fn push_annexb(&mut self, data: &[u8]) {
+        for (_, nal) in crate::annexb::nal_units(data) {
+            self.push_nal(nal);
+        }
+    }
+
+    pub fn finish(self) -> HvccFields {
+        let min_spatial_segmentation_idc = self.min_spatial_segmentation_idc.unwrap_or(0);
+        let parallelism_type = match self.parallelism_type {
+            Some(t) if min_spatial_segmentation_idc > 0 => t,
+            _ => ParallelismType::Mixed,
+        };
+        let report = self.duration.finish();
+        let avg_frame_rate = report.duration.filter(|&d| d > 0.0).map_or(0, |d| {
+            (report.frames as f64 / d * 256.0).round().min(65535.0) as u16
+        });
+        let num_temporal_layers = self.max_temporal_id_plus1.max(1);
+        HvccFields {
+            min_spatial_segmentation_idc: min_spatial_segmentation_idc.min(0xfff) as u16,
+            parallelism_type,
+            avg_frame_rate,
+            constant_frame_rate: u8::from(report.constant_frame_rate),
+            num_temporal_layers,
+            temporal_id_nested: self.all_nested || num_temporal_layers == 1,
+        }
+    }
+}
+
+/// The fields of an `HEVCSampleEntry` which depend on the stream, and the payloads of its boxes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SampleEntry {
+    pub sample_entry_type: [u8; 4],
+    /// The cropped picture size.
+    pub width: u16,
+    pub height: u16,
+    /// The `HEVCDecoderConfigurationRecord` for the `hvcC` box.
+    pub hvcc: Vec<u8>,
+    /// An `nclx` `colr` box payload, if the VUI has a colour description.
+    pub colr: Option<Vec<u8>>,
+    pub mdcv: Option<Vec<u8>>,
+    pub clli: Option<Vec<u8>>,
+}
+impl SampleEntry {
+    /// Builds the sample entry for the first SPS of `ctx`. `nals` are the NAL units for the
+    /// `hvcC` arrays: the parameter sets as found in the stream (the context doesn't keep their
+    /// bytes), and optionally declarative SEI.
+    ///
+    /// ```
+    /// use hevc_reader::nal::pps::PicParameterSet;
+    /// use hevc_reader::nal::sps::SeqParameterSet;
+    /// use hevc_reader::rbsp::{decode_nal, BitReader};
+    /// use hevc_reader::sample_entry::SampleEntry;
+    /// use hevc_reader::Context;
+    /// # let sps = [
+    /// #     0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03, 0x00,
+    /// #     0x00, 0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46,
+    /// #     0xd1, 0x2e, 0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10,
+    /// #     0x00, 0x00, 0x03, 0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00,
+    /// #     0x0b, 0xb8, 0x48,
+    /// # ];
+    /// let pps = [0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40];
+    /// let mut ctx = Context::new();
+    /// let rbsp = decode_nal(&sps).unwrap();
+    /// ctx.put_seq_param_set(SeqParameterSet::from_bits(BitReader::new(&*rbsp)).unwrap());
+    /// let rbsp = decode_nal(&pps).unwrap();
+    /// ctx.put_pic_param_set(PicParameterSet::from_bits(&ctx, BitReader::new(&*rbsp)).unwrap());
+    /// let entry = SampleEntry::new(&ctx, &[&sps, &pps], &Default::default()).unwrap();
+    /// assert_eq!((entry.width, entry.height), (720, 576));
+    /// // Main profile, level 3.1, 25 frames per second and 4 byte lengths.
+    /// assert_eq!(entry.hvcc[..2], [1, 1]);
+    /// assert_eq!(entry.hvcc[12], 93);
+    /// assert_eq!(entry.hvcc[19..22], [0x19, 0x00, 0x0f]);
+    /// assert_eq!(entry.colr, None);
+    /// ```
+    pub fn new(
+        ctx: &Context,
+        nals: &[&[u8]],
+        config: &SampleEntryConfig,
+    ) -> Result<SampleEntry, SampleEntryError> {
+        check_length_size(config.length_size).map_err(SampleEntryError::BadLengthSize)?;
+        let sps = ctx.sps().next().ok_or(SampleEntryError::NoSeqParamSet)?;
+        let (width, height) = sps.pixel_dimensions()?;
+        let too_large = || SampleEntryError::TooLarge { width, height };
+        let profile = sps.general_layer_profile();
+        let fields = config.fields.unwrap_or_else(|| HvccFields {
+            min_spatial_segmentation_idc: sps
+                .vui_parameters
+                .as_ref()
+                .and_then(|v| v.bitstream_restrictions.as_ref())
+                .map_or(0, |b| b.min_spatial_segmentation_idc.min(0xfff) as u16),
+            parallelism_type: ParallelismType::for_pps(ctx.pps()),
+            avg_frame_rate: ctx.frame_rate(sps, None).map_or(0, |r| {
+                (r.pictures_per_second() * 256.0).round().min(65535.0) as u16
+            }),
+            constant_frame_rate: 0,
+            num_temporal_layers: sps.sps_max_sub_layers_minus1 + 1,
+            temporal_id_nested: sps.sps_temporal_id_nesting,
+        });
+
+        let mut hvcc = vec![
+            1,
+            profile.profile_space << 6 | u8::from(profile.tier_flag) << 5 | profile.profile_idc,
+        ];
+        hvcc.extend_from_slice(&profile.compatibility_flags().to_be_bytes());
+        hvcc.extend_from_slice(&profile.constraint_indicator_flags());
+        hvcc.push(sps.profile_tier_level.general_level_idc);
+        hvcc.extend_from_slice(&(0xf000 | fields.min_spatial_segmentation_idc).to_be_bytes());
+        hvcc.push(0xfc | fields.parallelism_type.id());
+        hvcc.push(0xfc | sps.chroma_info.chroma_format.id() as u8);
+        hvcc.push(0xf8 | sps.bit_depth_luma_minus8 as u8);
+        hvcc.push(0xf8 | sps.bit_depth_chroma_minus8 as u8);
+        hvcc.extend_from_slice(&fields.avg_frame_rate.to_be_bytes());
+        hvcc.push(
+            fields.constant_frame_rate << 6
+                | (fields.num_temporal_layers & 7) << 3
+                | u8::from(fields.temporal_id_nested) << 2
+                | (config.length_size - 1) as u8,
+        );
+        push_arrays(&mut hvcc, nals, &config.sample_entry_type == b"hvc1")?;
+
+        let signal = sps
+            .vui_parameters
+            .as_ref()
+            .and_then(|v| v.video_signal_type.as_ref());
+        let colr = signal.and_then(|s| {
+            let c = s.colour_description.as_ref()?;
+            let mut colr = b"nclx".to_vec();
+            for v in [
+                c.colour_primaries,
+                c.transfer_characteristics,
+                c.matrix_coeffs,
+            ] {
+                colr.extend_from_slice(&u16::from(v).to_be_bytes());
+            }
+            colr.push(u8::from(s.video_full_range_flag) << 7);
+            Some(colr)
+        });
+        Ok(SampleEntry {
+            sample_entry_type: config.sample_entry_type,
+            width: width.try_into().map_err(|_| too_large())?,
+            height: height.try_into().map_err(|_| too_large())?,
+            hvcc,
+            colr,
+            // Both boxes have the layout of the SEI message payloads.
+            mdcv: config.mastering_display.as_ref().map(|m| m.to_payload()),
+            clli: config.content_light_level.map(|c| c.to_payload()),
+        })
+    }
+}
+
+/// Appends `numOfArrays` and the arrays, one per NAL unit type in VPS, SPS, PPS, SEI order.
+/// With `complete`, `array_completeness` is set for the parameter set arrays.
+fn push_arrays(hvcc: &mut Vec<u8>, nals: &[&[u8]], complete: bool) -> Result<(), SampleEntryError> {
+    let mut arrays: Vec<(UnitType, Vec<&[u8]>)> = [
+        UnitType::VideoParameterSet,
+        UnitType::SeqParameterSet,
+        UnitType::PicParameterSet,
+        UnitType::PrefixSEI,
+        UnitType::SuffixSEI,
+    ]
+    .into_iter()
+    .map(|t| (t, vec![]))
+    .collect();
+    for &nal in nals {
+        let unit_type = nal
+            .first()
+            .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())
+            .ok_or(SampleEntryError::UnexpectedNal)?
+            .nal_unit_type();
+        let (_, array) = arrays
+            .iter_mut()
+            .find(|(t, _)| *t == unit_type)
+            .ok_or(SampleEntryError::UnexpectedNal)?;
+        if nal.len() > usize::from(u16::MAX) {
+            return Err(SampleEntryError::NalTooLong(nal.len()));
+        }
+        array.push(nal);
+    }
+    arrays.retain(|(_, array)| !array.is_empty());
+    hvcc.push(arrays.len() as u8);
+    for (unit_type, array) in arrays {
+        let is_param_set = !matches!(unit_type, UnitType::PrefixSEI | UnitType::SuffixSEI);
+        hvcc.push(u8::from(complete && is_param_set) << 7 | unit_type.id());
+        hvcc.extend_from_slice(&(array.len() as u16).to_be_bytes());
+        for nal in array {
+            hvcc.extend_from_slice(&(nal.len() as u16).to_be_bytes());
+            hvcc.extend_from_slice(nal);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hvcc::DecoderConfigRecord;
+    use crate::nal::sps::{BitstreamRestrictions, ColourDescription, SeqParameterSet};
+    use crate::rbsp::{decode_nal, BitReader};
+    use crate::rewrite::vui::SpsVuiEditor;
+    use hex_literal::hex;
+
+    const SPS: [u8; 59] = hex!(
+        "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46 d1
+         2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00 0b
+         b8 48"
+    );
+    const PPS: [u8; 7] = hex!("44 01 c1 72 b4 62 40");
+
+    #[test]
+    fn hev1_with_hdr() {
+        let mut editor = SpsVuiEditor::new(&SPS).unwrap();
+        editor.set_colour_description(Some(ColourDescription {
+            colour_primaries: 9,
+            transfer_characteristics: 16,
+            matrix_coeffs: 9,
+        }));
+        let sps = editor.to_nal();
+        let mut ctx = Context::new();
+        let rbsp = decode_nal(&sps).unwrap();
+        ctx.put_seq_param_set(SeqParameterSet::from_bits(BitReader::new(&*rbsp)).unwrap());
+        let config = SampleEntryConfig {
+            sample_entry_type: *b"hev1",
+            length_size: 2,
+            content_light_level: Some(ContentLightLevelInfo {
+                max_content_light_level: 1000,
+                max_pic_average_light_level: 400,
+            }),
+            ..Default::default()
+        };
+        let entry = SampleEntry::new(&ctx, &[&PPS, &sps], &config).unwrap();
+        assert_eq!(
+            entry.hvcc[..23],
+            hex!("01 01 60 00 00 00 b0 00 00 00 00 00 5d f0 00 fc fd f8 f8 19 00 0d 02")
+        );
+        // The SPS array comes first, neither marked complete.
+        assert_eq!(entry.hvcc[23], 33);
+        let record = DecoderConfigRecord::parse(&entry.hvcc).unwrap();
+        assert_eq!(record.length_size, 2);
+        assert_eq!(record.nal_units, [&sps[..], &PPS]);
+        assert_eq!(entry.colr.unwrap(), b"nclx\x00\x09\x00\x10\x00\x09\x00");
+        assert_eq!(entry.clli.unwrap(), hex!("03 e8 01 90"));
+        assert_eq!(entry.mdcv, None);
+
+        assert!(matches!(
+            SampleEntry::new(&ctx, &[&hex!("26 01 ac 9c")], &config),
+            Err(SampleEntryError::UnexpectedNal)
+        ));
+        assert!(matches!(
+            SampleEntry::new(&Context::new(), &[], &config),
+            Err(SampleEntryError::NoSeqParamSet)
+        ));
+    }
+
+    #[test]
+    fn analyzed_fields() {
+        let mut ctx = Context::new();
+        let rbsp = decode_nal(&SPS).unwrap();
+        ctx.put_seq_param_set(SeqParameterSet::from_bits(BitReader::new(&*rbsp)).unwrap());
+        let mut analyzer = HvccAnalyzer::new();
+        analyzer.push_nal(&SPS);
+        analyzer.push_nal(&PPS);
+        // An IDR picture, and a trailing picture with TemporalId 1.
+        analyzer.push_nal(&hex!("26 01 ac 9c"));
+        analyzer.push_nal(&hex!("02 02 d0 6c 27 80"));
+        let fields = analyzer.finish();
+        assert_eq!(fields.num_temporal_layers, 2);
+        // The SPS has sps_temporal_id_nesting_flag.
+        assert!(fields.temporal_id_nested);
+        let config = SampleEntryConfig {
+            fields: Some(fields),
+            ..Default::default()
+        };
+        let entry = SampleEntry::new(&ctx, &[&SPS, &PPS], &config).unwrap();
+        // avgFrameRate 6400, constantFrameRate 1, numTemporalLayers 2, temporalIdNested and 4
+        // byte lengths.
+        assert_eq!(entry.hvcc[19..22], [0x19, 0x00, 0x57]);
+    }
+
+    #[test]
+    fn min_spatial_segmentation_idc() {
+        // Past the 12 bits of the record, which clamps it rather than spilling into the
+        // reserved bits.
+        let mut sps =
+            SeqParameterSet::from_bits(BitReader::new(&*decode_nal(&SPS).unwrap())).unwrap();
+        sps.vui_parameters.as_mut().unwrap().bitstream_restrictions = Some(BitstreamRestrictions {
+            min_spatial_segmentation_idc: 0x1_1000,
+            ..Default::default()
+        });
+        let mut ctx = Context::new();
+        ctx.put_seq_param_set(sps);
+        let entry = SampleEntry::new(&ctx, &[&SPS, &PPS], &SampleEntryConfig::default()).unwrap();
+        assert_eq!(entry.hvcc[13..15], [0xff, 0xff]);
+    }
+}
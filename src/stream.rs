@@ -0,0 +1,214 @@
+//! Asynchronous sources of NAL units, for ingest servers built on tokio. Requires the `async`
+//! feature.
+//!
+//! [`NalStream`] reads an Annex B byte stream from a [`tokio::io::AsyncRead`], and
+//! [`BytesNalStream`] from a [`Stream`] of [`Bytes`] chunks, such as the body of an HTTP
+//! request. Both are [`Stream`]s of complete NAL units, without start codes.
+
+use std::collections::VecDeque;
+use std::io::Read;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures_core::Stream;
+use tokio::io::{AsyncRead, ReadBuf};
+
+use crate::annexb::AnnexBReader;
+use crate::nal::{Nal, RefNal};
+use crate::push::{AccumulatedNalHandler, NalAccumulator, NalInterest};
+
+/// Queues the complete NAL units.
+#[derive(Default)]
+struct Queue(VecDeque<Vec<u8>>);
+impl AccumulatedNalHandler for Queue {
+    fn nal(&mut self, nal: RefNal<'_>) -> NalInterest {
+        if nal.is_complete() {
+            let mut bytes = vec![];
+            if nal.reader().read_to_end(&mut bytes).is_ok() {
+                self.0.push_back(bytes);
+            }
+        }
+        NalInterest::Buffer
+    }
+}
+
+/// The parser state shared by the streams.
+struct Parser {
+    reader: AnnexBReader<NalAccumulator<Queue>>,
+    ended: bool,
+}
+impl Parser {
+    fn new() -> Self {
+        Parser {
+            reader: AnnexBReader::accumulate(Queue::default()),
+            ended: false,
+        }
+    }
+
+    fn pop(&mut self) -> Option<Vec<u8>> {
+        self.reader.nal_handler_mut().0.pop_front()
+    }
+
+    /// Pushes a chunk of input, or ends the stream if it's empty.
+    fn push(&mut self, data: &[u8]) {
+        if data.is_empty() {
+            self.reader.reset();
+            self.ended = true;
+        } else {
+            self.reader.push(data);
+        }
+    }
+}
+
+/// The NAL units of an Annex B byte stream read from an [`AsyncRead`].
+///
+/// ```
+/// use futures_core::Stream;
+/// use hevc_reader::stream::NalStream;
+/// use std::future::poll_fn;
+/// use std::pin::Pin;
+/// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+/// let data = &b"\x00\x00\x00\x01\x40\x01\x0c\x00\x00\x01\x42\x01\x01"[..];
+/// let mut nals = NalStream::new(data);
+/// let mut out = vec![];
+/// while let Some(nal) = poll_fn(|cx| Pin::new(&mut nals).poll_next(cx)).await {
+///     out.push(nal.unwrap());
+/// }
+/// assert_eq!(out, [&b"\x40\x01\x0c"[..], b"\x42\x01\x01"]);
+/// # });
+/// ```
+pub struct NalStream<R: AsyncRead + Unpin> {
+    inner: R,
+    buf: Box<[u8]>,
+    parser: Parser,
+}
+impl<R: AsyncRead + Unpin> NalStream<R> {
+    /// Creates a stream reading 64 KiB at a time.
+    pub fn new(inner: R) -> Self {
+        Self::with_capacity(inner, 64 * 1024)
+    }
+
+    /// Creates a stream reading up to `capacity` bytes at a time.
+    pub fn with_capacity(inner: R, capacity: usize) -> Self {
+        assert!(capacity > 0);
+        NalStream {
+            inner,
+            buf: vec![0; capacity].into_boxed_slice(),
+            parser: Parser::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+impl<R: AsyncRead + Unpin> Stream for NalStream<R> {
+    type Item = std::io::Result<Vec<u8>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(nal) = this.parser.pop() {
+                return Poll::Ready(Some(Ok(nal)));
+            }
+            if this.parser.ended {
+                return Poll::Ready(None);
+            }
+            let mut buf = ReadBuf::new(&mut this.buf);
+            match Pin::new(&mut this.inner).poll_read(cx, &mut buf) {
+                Poll::Ready(Ok(())) => this.parser.push(buf.filled()),
+                Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(e))),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// The NAL units of an Annex B byte stream arriving as a [`Stream`] of [`Bytes`].
+pub struct BytesNalStream<S: Stream<Item = Bytes> + Unpin> {
+    inner: S,
+    parser: Parser,
+}
+impl<S: Stream<Item = Bytes> + Unpin> BytesNalStream<S> {
+    pub fn new(inner: S) -> Self {
+        BytesNalStream {
+            inner,
+            parser: Parser::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+impl<S: Stream<Item = Bytes> + Unpin> Stream for BytesNalStream<S> {
+    type Item = Vec<u8>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(nal) = this.parser.pop() {
+                return Poll::Ready(Some(nal));
+            }
+            if this.parser.ended {
+                return Poll::Ready(None);
+            }
+            match Pin::new(&mut this.inner).poll_next(cx) {
+                // Empty chunks aren't the end of the stream.
+                Poll::Ready(Some(chunk)) if chunk.is_empty() => {}
+                Poll::Ready(Some(chunk)) => this.parser.push(&chunk),
+                Poll::Ready(None) => this.parser.push(&[]),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Yields its chunks, returning `Pending` before each.
+    struct Chunks(VecDeque<Bytes>, bool);
+    impl Stream for Chunks {
+        type Item = Bytes;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Bytes>> {
+            self.1 = !self.1;
+            if self.1 {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            Poll::Ready(self.0.pop_front())
+        }
+    }
+
+    async fn collect<S: Stream + Unpin>(mut stream: S) -> Vec<S::Item> {
+        let mut items = vec![];
+        while let Some(item) = std::future::poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await
+        {
+            items.push(item);
+        }
+        items
+    }
+
+    #[tokio::test]
+    async fn bytes_chunks() {
+        let chunks = [
+            &b"\x00\x00"[..],
+            b"",
+            b"\x01\x40\x01\x0c\x00",
+            b"\x00\x01\x42\x01",
+            b"\x01",
+        ];
+        let stream = BytesNalStream::new(Chunks(
+            chunks.iter().map(|c| Bytes::from_static(c)).collect(),
+            false,
+        ));
+        assert_eq!(
+            collect(stream).await,
+            [&b"\x40\x01\x0c"[..], b"\x42\x01\x01"]
+        );
+    }
+}
@@ -0,0 +1,525 @@
+//! Tracking of which parameter sets the slices of each coded video sequence (CVS) activate.
+//!
+//! A stream may carry parameter sets which no picture refers to, or replace the content of an
+//! active one where the spec forbids it. An [`ActivationTracker`] follows activation as a
+//! decoder would (clause 7.4.2.4.2), and reports the sequences found, the parameter sets never
+//! activated and the [`Violation`]s of the activation rules.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::hvcc::SampleError;
+use crate::nal::pps::{PicParamSetId, PicParameterSet};
+use crate::nal::sps::{SeqParamSetId, SeqParameterSet, VideoParamSetId};
+use crate::nal::{CvsTracker, NalHeader, UnitType};
+use crate::rbsp::{decode_nal, BitRead, BitReader};
+use crate::validate::Violation;
+use crate::Context;
+
+/// One content of a parameter set: from the NAL unit first carrying it until one with the same
+/// id and different content replaces it. Repeats of the same content are counted, not recorded
+/// separately.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParamSetRecord {
+    /// `VideoParameterSet`, `SeqParameterSet` or `PicParameterSet`.
+    pub unit_type: UnitType,
+    pub id: u8,
+    /// The index, counting from 0, of the NAL unit which first carried this content.
+    pub nal_index: u64,
+    /// The number of NAL units carrying it, including the first.
+    pub count: u32,
+    /// The indices into [`ActivationReport::sequences`] of the sequences activating it.
+    pub activated_by: Vec<usize>,
+}
+
+/// A coded video sequence: an IRAP picture starting a sequence and the pictures up to the next.
+#[derive(Debug, Clone)]
+pub struct CodedVideoSequence {
+    /// The index of its first picture, in decoding order.
+    pub first_picture: u64,
+    pub pictures: u64,
+    /// The VPS referred to by its SPS.
+    pub vps: VideoParamSetId,
+    /// The active SPS.
+    pub sps: Arc<SeqParameterSet>,
+    /// The PPSs activated by its pictures, in order of first activation. A PPS id appears more
+    /// than once if its content was replaced between pictures.
+    pub pps: Vec<Arc<PicParameterSet>>,
+}
+impl CodedVideoSequence {
+    pub fn sps_id(&self) -> SeqParamSetId {
+        self.sps.id()
+    }
+
+    /// True if a picture of this sequence activated a PPS with the given id.
+    pub fn activates_pps(&self, id: PicParamSetId) -> bool {
+        self.pps.iter().any(|p| p.pic_parameter_set_id == id)
+    }
+
+    /// The last content of the PPS with the given id activated in this sequence.
+    pub fn pps_by_id(&self, id: PicParamSetId) -> Option<&PicParameterSet> {
+        self.pps
+            .iter()
+            .rev()
+            .find(|p| p.pic_parameter_set_id == id)
+            .map(|p| &**p)
+    }
+}
+
+/// What an [`ActivationTracker`] found.
+#[derive(Debug, Clone, Default)]
+pub struct ActivationReport {
+    pub sequences: Vec<CodedVideoSequence>,
+    /// Every parameter set content seen, in order of appearance.
+    pub param_sets: Vec<ParamSetRecord>,
+    /// Failed activation rules, with rules `activation.*`.
+    pub violations: Vec<Violation>,
+}
+impl ActivationReport {
+    /// The parameter sets no sequence activated.
+    pub fn unused(&self) -> impl Iterator<Item = &ParamSetRecord> {
+        self.param_sets.iter().filter(|p| p.activated_by.is_empty())
+    }
+}
+
+/// The content of a parameter set as last received.
+struct Current {
+    record: usize,
+    nal: Vec<u8>,
+    sps: Option<Arc<SeqParameterSet>>,
+}
+
+/// The picture whose slice segments were last seen.
+struct Picture {
+    pps_id: PicParamSetId,
+    pps_record: usize,
+}
+
+/// Follows parameter set activation, fed one NAL unit at a time in decoding order.
+///
+/// ```
+/// use hevc_reader::nal::pps::PicParamSetId;
+/// use hevc_reader::validate::activation::ActivationTracker;
+/// let mut tracker = ActivationTracker::new();
+/// tracker.push_annexb(&[
+///     // "Intinor HW encode 720x576p" SPS.
+///     0, 0, 1, 0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03,
+///     0x00, 0x00, 0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46,
+///     0xd1, 0x2e, 0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10, 0x00,
+///     0x00, 0x03, 0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00, 0x0b, 0xb8,
+///     0x48,
+///     // Its PPS, and an IDR slice segment.
+///     0, 0, 1, 0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40,
+///     0, 0, 1, 0x26, 0x01, 0xac, 0x9c, 0xff, 0xff,
+/// ]);
+/// let report = tracker.finish();
+/// assert_eq!(report.sequences.len(), 1);
+/// assert!(report.sequences[0].activates_pps(PicParamSetId::from_u32(0).unwrap()));
+/// assert!(report.violations.is_empty() && report.unused().next().is_none());
+/// ```
+#[derive(Default)]
+pub struct ActivationTracker {
+    ctx: Context,
+    nal_index: u64,
+    pictures: u64,
+    /// The parameter sets by NAL unit type and id.
+    current: HashMap<(u8, u8), Current>,
+    picture: Option<Picture>,
+    /// The indices of the parameter sets activated by the current sequence.
+    active: Vec<usize>,
+    cvs: CvsTracker,
+    /// The index of an SPS NAL unit which replaced the active SPS, until the next picture.
+    sps_replaced: Option<u64>,
+    /// The index of a PPS NAL unit which replaced the PPS of the current picture, until the next
+    /// slice segment.
+    pps_replaced: Option<u64>,
+    /// The number of pictures before the first IRAP picture.
+    undecodable: u64,
+    report: ActivationReport,
+}
+impl ActivationTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a NAL unit (without start code or length prefix). NAL units of layers other than
+    /// the base layer are ignored.
+    pub fn push_nal(&mut self, nal: &[u8]) {
+        let index = self.nal_index;
+        self.nal_index += 1;
+        let Some(header) = nal
+            .first()
+            .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())
+        else {
+            return;
+        };
+        if header.nuh_layer_id().map_or(true, |id| id != 0) {
+            return;
+        }
+        match header.nal_unit_type() {
+            t @ (UnitType::VideoParameterSet
+            | UnitType::SeqParameterSet
+            | UnitType::PicParameterSet) => self.push_param_set(t, nal, index),
+            UnitType::EndOfSeq | UnitType::EndOfStream => self.cvs.end_sequence(),
+            t if t.is_vcl() => self.push_slice_segment(t, nal),
+            _ => {}
+        }
+    }
+
+    /// Adds the NAL units of an Annex B stream.
+    pub fn push_annexb(&mut self, data: &[u8]) {
+        for (_, nal) in crate::annexb::nal_units(data) {
+            self.push_nal(nal);
+        }
+    }
+
+    /// Adds the NAL units of a length-prefixed sample.
+    pub fn push_sample(&mut self, sample: &[u8], length_size: usize) -> Result<(), SampleError> {
+        for nal in crate::hvcc::nal_units(sample, length_size)? {
+            self.push_nal(nal?);
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> ActivationReport {
+        if self.undecodable > 0 {
+            self.report.violations.insert(
+                0,
+                Violation::at(
+                    "activation.before-irap",
+                    0,
+                    format!(
+                        "{} pictures before the first IRAP picture activate no parameter sets",
+                        self.undecodable
+                    ),
+                ),
+            );
+        }
+        self.report
+    }
+
+    fn push_param_set(&mut self, unit_type: UnitType, nal: &[u8], index: u64) {
+        let Ok(rbsp) = decode_nal(nal) else {
+            return;
+        };
+        let mut r = BitReader::from_nal(nal);
+        let id = match unit_type {
+            UnitType::VideoParameterSet => r.read_u8(4, "vps_video_parameter_set_id").ok(),
+            UnitType::SeqParameterSet => None,
+            _ => r
+                .read_ue("pps_pic_parameter_set_id")
+                .ok()
+                .and_then(|id| PicParamSetId::from_u32(id).ok())
+                .map(|id| id.id()),
+        };
+        let sps = (unit_type == UnitType::SeqParameterSet)
+            .then(|| SeqParameterSet::from_bits(BitReader::from_nal(nal)).ok())
+            .flatten()
+            .map(Arc::new);
+        let Some(id) = id.or_else(|| sps.as_ref().map(|s| s.id().id())) else {
+            return;
+        };
+        let key = (unit_type.id(), id);
+        if let Some(current) = self.current.get(&key) {
+            if decode_nal(&current.nal).is_ok_and(|c| c == rbsp) {
+                self.report.param_sets[current.record].count += 1;
+                return;
+            }
+            if self.active.contains(&current.record) {
+                match unit_type {
+                    UnitType::SeqParameterSet => self.sps_replaced = Some(index),
+                    UnitType::PicParameterSet
+                        if self.picture.as_ref().map(|p| p.pps_record) == Some(current.record) =>
+                    {
+                        self.pps_replaced = Some(index)
+                    }
+                    _ => {}
+                }
+            }
+        }
+        if let Some(sps) = &sps {
+            self.ctx.put_shared_seq_param_set(sps.clone());
+        }
+        self.report.param_sets.push(ParamSetRecord {
+            unit_type,
+            id,
+            nal_index: index,
+            count: 1,
+            activated_by: vec![],
+        });
+        self.current.insert(
+            key,
+            Current {
+                record: self.report.param_sets.len() - 1,
+                nal: nal.to_vec(),
+                sps,
+            },
+        );
+    }
+
+    fn push_slice_segment(&mut self, unit_type: UnitType, nal: &[u8]) {
+        let mut r = BitReader::from_nal(nal);
+        let Ok(first_slice_segment_in_pic_flag) = r.read_bool("first_slice_segment_in_pic_flag")
+        else {
+            return;
+        };
+        if unit_type.is_irap() && r.read_bool("no_output_of_prior_pics_flag").is_err() {
+            return;
+        }
+        let Some(pps_id) = r
+            .read_ue("slice_pic_parameter_set_id")
+            .ok()
+            .and_then(|id| PicParamSetId::from_u32(id).ok())
+        else {
+            return;
+        };
+        let replaced = self.pps_replaced.take();
+        if !first_slice_segment_in_pic_flag {
+            let picture_index = self.pictures.saturating_sub(1);
+            let Some(picture) = &self.picture else {
+                return;
+            };
+            if picture.pps_id != pps_id {
+                self.report.violations.push(Violation::at(
+                    "activation.pps-within-picture",
+                    picture_index,
+                    format!(
+                        "slice segment refers to PPS {}, the picture's first to PPS {}",
+                        pps_id.id(),
+                        picture.pps_id.id()
+                    ),
+                ));
+            } else if let Some(index) = replaced {
+                self.report.violations.push(Violation::at(
+                    "activation.active-pps-replaced",
+                    picture_index,
+                    format!(
+                        "NAL unit {} changes the content of active PPS {} within a picture",
+                        index,
+                        pps_id.id()
+                    ),
+                ));
+            }
+            return;
+        }
+        let picture_index = self.pictures;
+        self.pictures += 1;
+        self.picture = None;
+        let starts_sequence = self.cvs.push_picture(unit_type);
+        if let Some(index) = self.sps_replaced.take() {
+            if !starts_sequence {
+                let sps_id = self.report.sequences.last().map_or(0, |s| s.sps_id().id());
+                self.report.violations.push(Violation::at(
+                    "activation.active-sps-replaced",
+                    picture_index,
+                    format!(
+                        "NAL unit {} changes the content of active SPS {} within a sequence",
+                        index, sps_id
+                    ),
+                ));
+            }
+        }
+        if !starts_sequence && self.report.sequences.is_empty() {
+            self.undecodable += 1;
+            return;
+        }
+        let Some(pps) = self
+            .current
+            .get(&(UnitType::PicParameterSet.id(), pps_id.id()))
+        else {
+            self.report.violations.push(Violation::at(
+                "activation.undefined-pps",
+                picture_index,
+                format!("no PPS {} was received", pps_id.id()),
+            ));
+            return;
+        };
+        let pps_record = pps.record;
+        let mut r = BitReader::from_nal(&pps.nal);
+        let Some(sps_id) = r
+            .read_ue("pps_pic_parameter_set_id")
+            .and_then(|_| r.read_ue("pps_seq_parameter_set_id"))
+            .ok()
+            .and_then(|id| SeqParamSetId::from_u32(id).ok())
+        else {
+            return;
+        };
+        let Some(sps) = self
+            .current
+            .get(&(UnitType::SeqParameterSet.id(), sps_id.id()))
+        else {
+            self.report.violations.push(Violation::at(
+                "activation.undefined-sps",
+                picture_index,
+                format!(
+                    "PPS {} refers to SPS {}, which was not received",
+                    pps_id.id(),
+                    sps_id.id()
+                ),
+            ));
+            return;
+        };
+        let Some(sps_content) = sps.sps.clone() else {
+            self.report.violations.push(Violation::at(
+                "activation.bad-param-set",
+                picture_index,
+                format!("SPS {} could not be parsed", sps_id.id()),
+            ));
+            return;
+        };
+        let sps_record = sps.record;
+        if starts_sequence {
+            self.active.clear();
+            let vps = sps_content.sps_video_parameter_set_id;
+            if let Some(vps) = self
+                .current
+                .get(&(UnitType::VideoParameterSet.id(), vps.id()))
+            {
+                self.active.push(vps.record);
+            }
+            self.active.push(sps_record);
+            self.report.sequences.push(CodedVideoSequence {
+                first_picture: picture_index,
+                pictures: 0,
+                vps,
+                sps: sps_content,
+                pps: vec![],
+            });
+            let sequence = self.report.sequences.len() - 1;
+            for &record in &self.active {
+                self.report.param_sets[record].activated_by.push(sequence);
+            }
+        } else if self.report.sequences.last().map(|s| s.sps_id()) != Some(sps_id) {
+            let active = self.report.sequences.last().map_or(0, |s| s.sps_id().id());
+            self.report.violations.push(Violation::at(
+                "activation.sps-changed",
+                picture_index,
+                format!(
+                    "PPS {} activates SPS {} within a sequence using SPS {}",
+                    pps_id.id(),
+                    sps_id.id(),
+                    active
+                ),
+            ));
+            return;
+        }
+        let sequence = self.report.sequences.len() - 1;
+        self.report.sequences[sequence].pictures += 1;
+        self.picture = Some(Picture { pps_id, pps_record });
+        if self.active.contains(&pps_record) {
+            return;
+        }
+        let parsed = PicParameterSet::from_bits(&self.ctx, BitReader::from_nal(&pps.nal));
+        let Ok(parsed) = parsed else {
+            self.report.violations.push(Violation::at(
+                "activation.bad-param-set",
+                picture_index,
+                format!("PPS {} could not be parsed", pps_id.id()),
+            ));
+            return;
+        };
+        self.active.push(pps_record);
+        self.report.param_sets[pps_record]
+            .activated_by
+            .push(sequence);
+        self.report.sequences[sequence].pps.push(Arc::new(parsed));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hex_literal::hex;
+
+    // "Intinor HW encode 720x576p" SPS, with id 0.
+    const SPS: [u8; 59] = hex!(
+        "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46
+         d1 2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00
+         0b b8 48"
+    );
+    // Its PPS, with id 0.
+    const PPS: [u8; 7] = hex!("44 01 c1 72 b4 62 40");
+    const IDR: [u8; 4] = hex!("26 01 ac 9c");
+    const TRAIL: [u8; 4] = hex!("02 01 d0 9c");
+
+    fn pps_id(id: u32) -> PicParamSetId {
+        PicParamSetId::from_u32(id).unwrap()
+    }
+
+    #[test]
+    fn activation() {
+        // "Haivision 1080i25", also SPS id 0.
+        let sps_1080 = hex!(
+            "42 01 01 01 40 00 00 03 00 40 00 00 03 00 00 03 00 7b a0 03 c0 80 22 1f 79 e9 6e
+             44 a1 7f f8 00 08 00 13 50 10 10 1e d0 00 00 03 00 10 00 00 03 03 25 08 ff de 10
+             00 16 e3 60 00 05 dd 77 df 08 04 10"
+        );
+        // The PPS with id 1, and a trailing picture referring to PPS 3.
+        let pps_1 = hex!("44 01 50 5c ad 18 90");
+        let trail_pps_3 = hex!("02 01 90 9c");
+        let mut tracker = ActivationTracker::new();
+        for nal in [
+            &TRAIL[..],
+            &SPS,
+            &PPS,
+            &pps_1,
+            &IDR,
+            &TRAIL,
+            &trail_pps_3,
+            &sps_1080,
+            &TRAIL,
+            &PPS,
+            &IDR,
+        ] {
+            tracker.push_nal(nal);
+        }
+        let report = tracker.finish();
+        assert_eq!(
+            report
+                .violations
+                .iter()
+                .map(|v| (v.rule, v.picture))
+                .collect::<Vec<_>>(),
+            [
+                ("activation.before-irap", Some(0)),
+                ("activation.undefined-pps", Some(3)),
+                ("activation.active-sps-replaced", Some(4)),
+            ]
+        );
+        let sequences: Vec<_> = report
+            .sequences
+            .iter()
+            .map(|s| (s.first_picture, s.pictures, s.sps.pic_width_in_luma_samples))
+            .collect();
+        // Coded widths, before cropping.
+        assert_eq!(sequences, [(1, 3, 736), (5, 1, 1920)]);
+        assert!(report.sequences[1].activates_pps(pps_id(0)));
+        assert!(!report.sequences[0].activates_pps(pps_id(1)));
+        let unused: Vec<_> = report.unused().map(|p| (p.id, p.nal_index)).collect();
+        assert_eq!(unused, [(1, 3)]);
+        let pps: Vec<_> = report
+            .param_sets
+            .iter()
+            .filter(|p| p.unit_type == UnitType::PicParameterSet && p.id == 0)
+            .collect();
+        assert_eq!((pps.len(), pps[0].count), (1, 2));
+        assert_eq!(pps[0].activated_by, [0, 1]);
+    }
+
+    #[test]
+    fn cra_after_end_of_sequence() {
+        let cra = hex!("2a 01 ac 9c");
+        let mut tracker = ActivationTracker::new();
+        for nal in [&SPS[..], &PPS, &cra, &TRAIL, &cra, &hex!("48 01"), &cra] {
+            tracker.push_nal(nal);
+        }
+        let report = tracker.finish();
+        assert!(report.violations.is_empty());
+        let sequences: Vec<_> = report
+            .sequences
+            .iter()
+            .map(|s| (s.first_picture, s.pictures))
+            .collect();
+        assert_eq!(sequences, [(0, 3), (3, 1)]);
+    }
+}
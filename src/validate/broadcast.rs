@@ -0,0 +1,256 @@
+//! Conformance presets for broadcast delivery: DVB UHD-1 (ETSI TS 101 154) and ATSC 3.0
+//! (A/341), configurable for the variations between services.
+
+use super::{
+    check_every_picture, check_tier_level, StreamSummary, Violation, BT2020, TRANSFER_HLG,
+    TRANSFER_PQ,
+};
+use crate::nal::sei::HeaderType;
+use crate::nal::sps::{Level, Profile, Tier};
+
+/// The constraints checked by [`check`].
+#[derive(Debug, Clone)]
+pub struct BroadcastConfig {
+    pub profiles: Vec<Profile>,
+    pub max_tier: Tier,
+    pub max_level: Level,
+    pub min_bit_depth: u32,
+    /// Every access unit must start with an access unit delimiter.
+    pub require_aud: bool,
+    /// The allowed `(colour_primaries, transfer_characteristics, matrix_coeffs)`; the VUI must
+    /// signal one of them.
+    pub colour: Vec<(u8, u8, u8)>,
+    /// The VUI must carry timing info.
+    pub require_timing: bool,
+    /// The VUI must carry HRD parameters.
+    pub require_hrd: bool,
+    /// PQ video must carry mastering display colour volume and content light level SEI.
+    pub require_pq_metadata: bool,
+    /// Every access unit must carry a `time_code()` SEI message.
+    pub require_timecode: bool,
+    /// Every access unit must carry ATSC A/53 closed caption data.
+    pub require_captions: bool,
+}
+impl BroadcastConfig {
+    /// DVB UHD-1 Phase 2: Main 10 up to Level 5.1 Main tier, with access unit delimiters, and
+    /// BT.709, BT.2020, HLG or PQ colour.
+    pub fn dvb_uhd1() -> Self {
+        BroadcastConfig {
+            profiles: vec![Profile::Main10],
+            max_tier: Tier::Main,
+            max_level: Level::L5_1,
+            min_bit_depth: 10,
+            require_aud: true,
+            colour: vec![
+                (1, 1, 1),
+                (BT2020, 14, BT2020),
+                (BT2020, TRANSFER_HLG, BT2020),
+                (BT2020, TRANSFER_PQ, BT2020),
+            ],
+            require_timing: false,
+            require_hrd: false,
+            require_pq_metadata: false,
+            require_timecode: false,
+            require_captions: false,
+        }
+    }
+
+    /// ATSC 3.0 (A/341) single layer video: Main 10 up to Level 5.2 Main tier, with VUI
+    /// timing, BT.709, BT.2020, HLG or PQ colour, and HDR10 metadata for PQ.
+    pub fn atsc3() -> Self {
+        BroadcastConfig {
+            profiles: vec![Profile::Main10],
+            max_tier: Tier::Main,
+            max_level: Level::L5_2,
+            min_bit_depth: 10,
+            require_aud: false,
+            colour: vec![
+                (1, 1, 1),
+                (BT2020, 14, BT2020),
+                (BT2020, TRANSFER_HLG, BT2020),
+                (BT2020, TRANSFER_PQ, BT2020),
+            ],
+            require_timing: true,
+            require_hrd: false,
+            require_pq_metadata: true,
+            require_timecode: false,
+            require_captions: false,
+        }
+    }
+}
+
+/// Checks the stream against `config`. Rules are named `broadcast.*`.
+///
+/// ```
+/// use hevc_reader::validate::{broadcast, StreamScanner};
+/// # let sps = [
+/// #     0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03, 0x00, 0x00,
+/// #     0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46, 0xd1, 0x2e,
+/// #     0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10, 0x00, 0x00, 0x03,
+/// #     0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00, 0x0b, 0xb8, 0x48,
+/// # ];
+/// let mut scanner = StreamScanner::new();
+/// // An 8 bit Main profile SPS without colour description, a PPS and an IDR slice segment.
+/// scanner.push_nal(&sps);
+/// scanner.push_nal(&[0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40]);
+/// scanner.push_nal(&[0x26, 0x01, 0xac, 0x9c]);
+/// let summary = scanner.finish();
+/// let rules: Vec<_> = broadcast::check(&summary, &broadcast::BroadcastConfig::dvb_uhd1())
+///     .iter()
+///     .map(|v| v.rule)
+///     .collect();
+/// assert_eq!(
+///     rules,
+///     ["broadcast.profile", "broadcast.bit-depth", "broadcast.colour", "broadcast.aud"]
+/// );
+/// ```
+pub fn check(summary: &StreamSummary, config: &BroadcastConfig) -> Vec<Violation> {
+    let mut violations = vec![];
+    for sps in &summary.seq_param_sets {
+        let profile = sps.general_profile();
+        if !config.profiles.contains(&profile) {
+            violations.push(Violation::new(
+                "broadcast.profile",
+                format!("profile {:?} isn't one of {:?}", profile, config.profiles),
+            ));
+        }
+        let bit_depth = sps.bit_depth_luma_minus8 + 8;
+        if bit_depth < config.min_bit_depth {
+            violations.push(Violation::new(
+                "broadcast.bit-depth",
+                format!(
+                    "bit depth {} is less than {}",
+                    bit_depth, config.min_bit_depth
+                ),
+            ));
+        }
+        let vui = sps.vui_parameters.as_ref();
+        let colour = vui
+            .and_then(|v| v.video_signal_type.as_ref())
+            .and_then(|s| s.colour_description.as_ref())
+            .map(|c| {
+                (
+                    c.colour_primaries,
+                    c.transfer_characteristics,
+                    c.matrix_coeffs,
+                )
+            });
+        match colour {
+            None => violations.push(Violation::new(
+                "broadcast.colour",
+                "the VUI has no colour description".to_owned(),
+            )),
+            Some(c) if !config.colour.contains(&c) => violations.push(Violation::new(
+                "broadcast.colour",
+                format!(
+                    "colour primaries, transfer characteristics and matrix coefficients {:?} \
+                     aren't allowed",
+                    c
+                ),
+            )),
+            Some(_) => {}
+        }
+        let timing = vui.and_then(|v| v.timing_info.as_ref());
+        if config.require_timing && timing.is_none() {
+            violations.push(Violation::new(
+                "broadcast.timing",
+                "the VUI has no timing info".to_owned(),
+            ));
+        }
+        if config.require_hrd && timing.and_then(|t| t.hrd_parameters.as_ref()).is_none() {
+            violations.push(Violation::new(
+                "broadcast.hrd",
+                "the VUI has no HRD parameters".to_owned(),
+            ));
+        }
+        let is_pq = matches!(
+            colour,
+            Some((_, transfer, _)) if transfer == TRANSFER_PQ
+        );
+        if config.require_pq_metadata
+            && is_pq
+            && (summary.mastering_display.is_empty() || summary.content_light_level.is_empty())
+        {
+            violations.push(Violation::new(
+                "broadcast.hdr",
+                "PQ video without mastering display colour volume and content light level SEI"
+                    .to_owned(),
+            ));
+        }
+    }
+    check_tier_level(
+        summary,
+        &mut violations,
+        "broadcast.tier-level",
+        config.max_tier,
+        config.max_level,
+    );
+    if config.require_aud {
+        check_every_picture(summary, &mut violations, "broadcast.aud", |p| p.has_aud);
+    }
+    if config.require_timecode {
+        check_every_picture(summary, &mut violations, "broadcast.timecode", |p| {
+            p.sei.contains(&HeaderType::TimeCode)
+        });
+    }
+    if config.require_captions {
+        check_every_picture(summary, &mut violations, "broadcast.captions", |p| {
+            p.captions
+        });
+    }
+    violations
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::nal::sps::ColourDescription;
+    use crate::rbsp::encode_nal;
+    use crate::rewrite::vui::SpsVuiEditor;
+    use crate::validate::StreamScanner;
+    use hex_literal::hex;
+
+    const SPS: [u8; 59] = hex!(
+        "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46 d1
+         2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00 0b
+         b8 48"
+    );
+    const PPS: [u8; 7] = hex!("44 01 c1 72 b4 62 40");
+    const IDR: [u8; 4] = hex!("26 01 ac 9c");
+    const TRAIL: [u8; 6] = hex!("02 01 d0 6c 27 80");
+
+    #[test]
+    fn captions_and_colour() {
+        let mut editor = SpsVuiEditor::new(&SPS).unwrap();
+        editor.set_colour_description(Some(ColourDescription {
+            colour_primaries: BT2020,
+            transfer_characteristics: TRANSFER_PQ,
+            matrix_coeffs: BT2020,
+        }));
+        // A/53 cc_data() in registered user data, and a trailing picture without.
+        let mut rbsp = vec![0x04, 0x0b];
+        rbsp.extend_from_slice(&[0xb5, 0x00, 0x31, b'G', b'A', b'9', b'4', 0x03, 0xc1, 0xff]);
+        rbsp.extend_from_slice(&[0xfc, 0x80]);
+        let sei = encode_nal(&[0x4e, 0x01], &rbsp);
+        let mut scanner = StreamScanner::new();
+        for nal in [&editor.to_nal()[..], &PPS, &sei, &IDR, &sei, &TRAIL, &TRAIL] {
+            scanner.push_nal(nal);
+        }
+        let summary = scanner.finish();
+        assert!(summary.pictures[0].captions);
+        let config = BroadcastConfig {
+            profiles: vec![Profile::Main],
+            min_bit_depth: 8,
+            require_captions: true,
+            ..BroadcastConfig::atsc3()
+        };
+        let violations = check(&summary, &config);
+        assert_eq!(
+            violations
+                .iter()
+                .map(|v| (v.rule, v.picture))
+                .collect::<Vec<_>>(),
+            [("broadcast.hdr", None), ("broadcast.captions", Some(2)),]
+        );
+    }
+}
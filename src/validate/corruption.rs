@@ -0,0 +1,410 @@
+//! Heuristics for whether a recording is damaged.
+//!
+//! A [`CorruptionScanner`] looks for what a clean encoder never produces: NAL unit headers with
+//! forbidden or reserved values, byte sequences which emulation prevention should have escaped,
+//! syntax ending early, and field values the spec doesn't allow. Slice segment data isn't
+//! decoded, so damage past the slice segment headers goes unnoticed.
+//!
+//! It also checks the `TemporalId` of each NAL unit against its type and access unit, which is
+//! rather an encoder bug than damage, but as strictly a conformance requirement.
+
+use std::io::ErrorKind;
+
+use crate::nal::pps::{PicParameterSet, PpsError};
+use crate::nal::sei::SeiReader;
+use crate::nal::slice::{SliceHeaderError, SliceSegmentHeader};
+use crate::nal::sps::{SeqParameterSet, SpsError};
+use crate::nal::vps::{VideoParameterSet, VpsError};
+use crate::nal::AccessUnitTracker;
+use crate::nal::{NalHeader, UnitType};
+use crate::rbsp::{decode_nal, BitReader, BitReaderError};
+use crate::Context;
+
+/// The kinds of [`Anomaly`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum AnomalyKind {
+    /// `forbidden_zero_bit` set in a NAL unit header.
+    ForbiddenBit,
+    /// A reserved NAL unit type, or a `nuh_temporal_id_plus1` of 0.
+    BadHeader,
+    /// A sequence emulation prevention should have escaped: `0x000000`, `0x000001` (in NAL
+    /// units not framed by start codes), `0x000002`, or `0x000003` followed by a byte above 3.
+    /// See [`crate::rewrite::escape`] for repairing them.
+    StartCodeEmulation,
+    /// A NAL unit ending before its syntax does.
+    Truncated,
+    /// A field value the spec doesn't allow, or data after the end of the syntax.
+    BadValue,
+    /// A `TemporalId` which the NAL unit type or access unit doesn't allow (clause 7.4.2.2).
+    /// Sub-layer extraction and switching rely on these.
+    TemporalId,
+}
+
+/// Something a clean encoder wouldn't produce.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Anomaly {
+    pub kind: AnomalyKind,
+    /// The byte offset in the stream: of the escaped sequence for
+    /// [`AnomalyKind::StartCodeEmulation`], otherwise of the NAL unit.
+    pub offset: usize,
+    /// The type of the NAL unit, unless its header is incomplete.
+    pub unit_type: Option<UnitType>,
+    pub message: String,
+}
+
+/// What a [`CorruptionScanner`] found.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CorruptionReport {
+    pub nal_units: u64,
+    pub anomalies: Vec<Anomaly>,
+}
+impl CorruptionReport {
+    /// The number of anomalies of the given kind.
+    pub fn count(&self, kind: AnomalyKind) -> usize {
+        self.anomalies.iter().filter(|a| a.kind == kind).count()
+    }
+
+    /// True if any anomaly was found.
+    pub fn is_damaged(&self) -> bool {
+        !self.anomalies.is_empty()
+    }
+}
+
+/// Checks NAL units one at a time in decoding order, parsing parameter sets, SEI messages and
+/// slice segment headers.
+///
+/// Slice segments whose parameter sets weren't seen, and syntax this crate doesn't implement,
+/// aren't counted as anomalies.
+///
+/// ```
+/// use hevc_reader::validate::corruption::{AnomalyKind, CorruptionScanner};
+/// let mut scanner = CorruptionScanner::new();
+/// // An access unit delimiter with `forbidden_zero_bit` set, and an SEI NAL unit whose
+/// // message claims more payload than there is.
+/// scanner.push_annexb(&[0, 0, 1, 0xc6, 0x01, 0x10, 0, 0, 1, 0x4e, 0x01, 0x06, 0x05, 0xc4]);
+/// let report = scanner.finish();
+/// assert_eq!(report.nal_units, 2);
+/// assert_eq!(report.anomalies[0].kind, AnomalyKind::ForbiddenBit);
+/// assert_eq!(report.anomalies[1].kind, AnomalyKind::Truncated);
+/// assert_eq!(report.anomalies[1].offset, 9);
+/// ```
+#[derive(Default)]
+pub struct CorruptionScanner {
+    ctx: Context,
+    tracker: AccessUnitTracker,
+    /// The `TemporalId` of the VCL NAL units of the current access unit, once one is seen.
+    access_unit_temporal_id: Option<u8>,
+    /// The offset, type and `TemporalId` of NAL units preceding the first VCL NAL unit of the
+    /// current access unit, whose `TemporalId` depends on it.
+    pending: Vec<(usize, UnitType, u8)>,
+    report: CorruptionReport,
+}
+impl CorruptionScanner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the NAL units of an Annex B stream, with offsets within `data`.
+    pub fn push_annexb(&mut self, data: &[u8]) {
+        for (offset, nal) in crate::annexb::nal_units(data) {
+            self.push_nal(offset, nal);
+        }
+    }
+
+    /// Adds a NAL unit (without start code or length prefix) found at byte `offset` of the
+    /// stream.
+    pub fn push_nal(&mut self, offset: usize, nal: &[u8]) {
+        self.report.nal_units += 1;
+        let [b0, b1, ..] = *nal else {
+            self.add(
+                AnomalyKind::Truncated,
+                offset,
+                None,
+                "incomplete NAL unit header",
+            );
+            return;
+        };
+        let Ok(header) = NalHeader::new(b0, Some(b1)) else {
+            self.add(
+                AnomalyKind::ForbiddenBit,
+                offset,
+                None,
+                "forbidden_zero_bit is 1",
+            );
+            return;
+        };
+        let unit_type = Some(header.nal_unit_type());
+        if let UnitType::Reserved(id) = header.nal_unit_type() {
+            let message = format!("reserved nal_unit_type {}", id);
+            self.add(AnomalyKind::BadHeader, offset, unit_type, message);
+        }
+        if b1 & 0b111 == 0 {
+            self.add(
+                AnomalyKind::BadHeader,
+                offset,
+                unit_type,
+                "nuh_temporal_id_plus1 is 0",
+            );
+        } else {
+            self.check_temporal_id(offset, header, nal);
+        }
+        let issues = crate::rewrite::escape::scan(nal);
+        for issue in &issues {
+            let i = issue.offset;
+            let message = format!("unescaped {:02x?}", &nal[i..nal.len().min(i + 4)]);
+            self.add(
+                AnomalyKind::StartCodeEmulation,
+                offset + i,
+                unit_type,
+                message,
+            );
+        }
+        // The RBSP of a NAL unit with unescaped sequences can't be read.
+        if !issues.is_empty() || !matches!(header.nuh_layer_id(), Ok(0)) {
+            return;
+        }
+        let error = match header.nal_unit_type() {
+            UnitType::VideoParameterSet => {
+                match VideoParameterSet::from_bits(BitReader::from_nal(nal)) {
+                    Ok(vps) => {
+                        self.ctx.put_video_param_set(vps);
+                        None
+                    }
+                    Err(VpsError::RbspReaderError(e)) => Some(Err(e)),
+                    Err(e) => Some(Ok(format!("{:?}", e))),
+                }
+            }
+            UnitType::SeqParameterSet => match SeqParameterSet::from_bits(BitReader::from_nal(nal))
+            {
+                Ok(sps) => {
+                    self.ctx.put_seq_param_set(sps);
+                    None
+                }
+                Err(SpsError::Unimplemented(_)) => None,
+                Err(SpsError::RbspReaderError(e)) => Some(Err(e)),
+                Err(e) => Some(Ok(format!("{:?}", e))),
+            },
+            UnitType::PicParameterSet => {
+                match PicParameterSet::from_bits(&self.ctx, BitReader::from_nal(nal)) {
+                    Ok(pps) => {
+                        self.ctx.put_pic_param_set(pps);
+                        None
+                    }
+                    Err(PpsError::Unimplemented(_) | PpsError::UnknownSeqParamSetId(_)) => None,
+                    Err(PpsError::RbspReaderError(e)) => Some(Err(e)),
+                    Err(e) => Some(Ok(format!("{:?}", e))),
+                }
+            }
+            UnitType::PrefixSEI | UnitType::SuffixSEI => {
+                let rbsp = decode_nal(nal).unwrap_or_default();
+                let mut reader = SeiReader::from_rbsp_bytes(&rbsp);
+                loop {
+                    match reader.next() {
+                        Ok(Some(_)) => continue,
+                        Ok(None) => break None,
+                        Err(e) => break Some(Err(e)),
+                    }
+                }
+            }
+            t if t.is_vcl() => {
+                let mut r = BitReader::from_nal(nal);
+                match SliceSegmentHeader::from_bits(&self.ctx, &mut r, header) {
+                    Ok(_)
+                    | Err(
+                        SliceHeaderError::UndefinedPicParamSetId(_)
+                        | SliceHeaderError::UndefinedSeqParamSetId(_),
+                    ) => None,
+                    Err(SliceHeaderError::RbspError(e)) => Some(Err(e)),
+                    Err(e) => Some(Ok(format!("{:?}", e))),
+                }
+            }
+            _ => None,
+        };
+        match error {
+            None => {}
+            Some(Err(BitReaderError::ReaderError(e) | BitReaderError::ReaderErrorFor(_, e)))
+                if e.kind() == ErrorKind::UnexpectedEof =>
+            {
+                self.add(
+                    AnomalyKind::Truncated,
+                    offset,
+                    unit_type,
+                    format!("{:?}", e),
+                )
+            }
+            Some(Err(e)) => self.add(AnomalyKind::BadValue, offset, unit_type, format!("{:?}", e)),
+            Some(Ok(message)) => self.add(AnomalyKind::BadValue, offset, unit_type, message),
+        }
+    }
+
+    pub fn finish(self) -> CorruptionReport {
+        self.report
+    }
+
+    /// Checks the `TemporalId` of a NAL unit of the base layer against clause 7.4.2.2.
+    fn check_temporal_id(&mut self, offset: usize, header: NalHeader, nal: &[u8]) {
+        let unit_type = header.nal_unit_type();
+        if self.tracker.push(unit_type, nal) {
+            self.access_unit_temporal_id = None;
+            self.pending.clear();
+        }
+        let Ok(0) = header.nuh_layer_id() else {
+            return;
+        };
+        let temporal_id = header.temporal_id();
+        let must_be_zero = unit_type.is_irap()
+            || matches!(
+                unit_type,
+                UnitType::VideoParameterSet
+                    | UnitType::SeqParameterSet
+                    | UnitType::EndOfSeq
+                    | UnitType::EndOfStream
+            );
+        let switching = matches!(
+            unit_type,
+            UnitType::SliceSegmentLayerTsaN
+                | UnitType::SliceSegmentLayerTsaR
+                | UnitType::SliceSegmentLayerStsaN
+                | UnitType::SliceSegmentLayerStsaR
+        );
+        if must_be_zero && temporal_id != 0 {
+            let message = format!("TemporalId {}, which must be 0", temporal_id);
+            self.add(AnomalyKind::TemporalId, offset, Some(unit_type), message);
+        } else if switching && temporal_id == 0 {
+            let message = "TemporalId 0 in a sub-layer switching picture";
+            self.add(AnomalyKind::TemporalId, offset, Some(unit_type), message);
+        }
+        if unit_type.is_vcl() {
+            match self.access_unit_temporal_id {
+                Some(t) if t != temporal_id => {
+                    let message = format!(
+                        "TemporalId {} in an access unit with TemporalId {}",
+                        temporal_id, t
+                    );
+                    self.add(AnomalyKind::TemporalId, offset, Some(unit_type), message);
+                }
+                Some(_) => {}
+                None => {
+                    self.access_unit_temporal_id = Some(temporal_id);
+                    for (offset, unit_type, t) in std::mem::take(&mut self.pending) {
+                        self.check_access_unit_temporal_id(offset, unit_type, t, temporal_id);
+                    }
+                }
+            }
+        } else if matches!(
+            unit_type,
+            UnitType::AccessUnitDelimiter
+                | UnitType::FillerData
+                | UnitType::PicParameterSet
+                | UnitType::PrefixSEI
+                | UnitType::SuffixSEI
+        ) {
+            match self.access_unit_temporal_id {
+                Some(t) => self.check_access_unit_temporal_id(offset, unit_type, temporal_id, t),
+                None => self.pending.push((offset, unit_type, temporal_id)),
+            }
+        }
+    }
+
+    /// Access unit delimiters and filler data have the `TemporalId` of their access unit;
+    /// PPSs and SEI NAL units have one at least as high.
+    fn check_access_unit_temporal_id(
+        &mut self,
+        offset: usize,
+        unit_type: UnitType,
+        temporal_id: u8,
+        access_unit: u8,
+    ) {
+        let ok = match unit_type {
+            UnitType::AccessUnitDelimiter | UnitType::FillerData => temporal_id == access_unit,
+            _ => temporal_id >= access_unit,
+        };
+        if !ok {
+            let message = format!(
+                "TemporalId {} in an access unit with TemporalId {}",
+                temporal_id, access_unit
+            );
+            self.add(AnomalyKind::TemporalId, offset, Some(unit_type), message);
+        }
+    }
+
+    fn add(
+        &mut self,
+        kind: AnomalyKind,
+        offset: usize,
+        unit_type: Option<UnitType>,
+        message: impl Into<String>,
+    ) {
+        self.report.anomalies.push(Anomaly {
+            kind,
+            offset,
+            unit_type,
+            message: message.into(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hex_literal::hex;
+
+    #[test]
+    fn anomalies() {
+        // "Intinor HW encode 720x576p" SPS, cut short; its PPS with `0x000000` in place of an
+        // escaped `0x000003`; and an IDR slice segment without parameter sets to parse it.
+        let data = hex!(
+            "00 00 01 42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2
+             00 00 01 44 01 c1 72 00 00 00 62 40
+             00 00 01 26 01 ac 9c"
+        );
+        let mut scanner = CorruptionScanner::new();
+        scanner.push_annexb(&data);
+        let report = scanner.finish();
+        assert_eq!(report.nal_units, 3);
+        assert_eq!(
+            report
+                .anomalies
+                .iter()
+                .map(|a| (a.kind, a.offset))
+                .collect::<Vec<_>>(),
+            [
+                (AnomalyKind::Truncated, 3),
+                (AnomalyKind::StartCodeEmulation, 31),
+            ]
+        );
+        assert!(report.is_damaged());
+        assert_eq!(report.count(AnomalyKind::Truncated), 1);
+    }
+
+    #[test]
+    fn temporal_id() {
+        // An access unit delimiter and an IDR slice segment with TemporalId 1; an access unit
+        // delimiter and a TSA slice segment with TemporalId 0; and a prefix SEI NAL unit with
+        // TemporalId 0 before a trailing slice segment with TemporalId 1.
+        let data = hex!(
+            "00 00 01 46 02 10
+             00 00 01 26 02 ac 9c
+             00 00 01 46 01 10
+             00 00 01 04 01 ac
+             00 00 01 4e 01 01 01 00 80
+             00 00 01 02 02 ac"
+        );
+        let mut scanner = CorruptionScanner::new();
+        scanner.push_annexb(&data);
+        let report = scanner.finish();
+        assert_eq!(
+            report
+                .anomalies
+                .iter()
+                .filter(|a| a.kind == AnomalyKind::TemporalId)
+                .map(|a| (a.unit_type.unwrap(), a.offset))
+                .collect::<Vec<_>>(),
+            [
+                (UnitType::SliceSegmentLayerIdrWLp, 9),
+                (UnitType::SliceSegmentLayerTsaN, 22),
+                (UnitType::PrefixSEI, 28),
+            ]
+        );
+    }
+}
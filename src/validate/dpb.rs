@@ -0,0 +1,529 @@
+//! The output order DPB model of clause C.5.2, without pixel data.
+//!
+//! A decoder outputs ("bumps") pictures from its decoded picture buffer (DPB) in ascending POC
+//! order, but only once it has to: when more pictures wait for output than
+//! `sps_max_num_reorder_pics` allows, when one has waited longer than `SpsMaxLatencyPictures`,
+//! or when the DPB is full. Following the model gives the order and the moment at which a
+//! conforming decoder outputs each picture, which in turn shows whether the signalled values
+//! suffice for the stream.
+//!
+//! At an IRAP picture which starts a coded video sequence, the pictures still waiting are all
+//! output, or with `NoOutputOfPriorPicsFlag` discarded without output. That flag is set for CRA
+//! pictures, and for IDR and BLA pictures with `no_output_of_prior_pics_flag`. The model never
+//! sets it for a change of picture size or DPB size, as decoders may but should not.
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::nal::slice::{SliceSegmentHeader, SliceType};
+use crate::nal::sps::LayerInfo;
+use crate::nal::{CvsTracker, NalHeader, UnitType};
+use crate::rbsp::BitReader;
+use crate::validate::references::ReferenceTracker;
+use crate::Context;
+
+/// A picture leaving the set of pictures waiting for output, as returned by
+/// [`DpbModel::push_nal`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputPicture {
+    /// The index of the picture, in decoding order.
+    pub picture: u64,
+    /// `PicOrderCntVal`.
+    pub poc: i32,
+    /// False if the picture was discarded without output, at an IRAP picture with
+    /// `NoOutputOfPriorPicsFlag`.
+    pub output: bool,
+}
+
+/// A picture in the DPB.
+struct Picture {
+    picture: u64,
+    poc: i32,
+    needed_for_output: bool,
+    used_for_reference: bool,
+    /// `PicLatencyCount`.
+    latency: u32,
+}
+
+/// Follows the DPB of a stream, fed one NAL unit at a time in decoding order, returning the
+/// pictures in output order.
+///
+/// RASL pictures skipped at the start of decoding (see [`ReferenceTracker`]) and pictures with
+/// `pic_output_flag` unset are never output. Call [`DpbModel::flush`] at the end of the stream
+/// for the pictures still waiting.
+///
+/// ```
+/// use hevc_reader::validate::dpb::DpbModel;
+/// let mut dpb = DpbModel::new();
+/// let output = dpb.push_annexb(&[
+///     // "Intinor HW encode 720x576p" SPS, with sps_max_num_reorder_pics 0.
+///     0, 0, 1, 0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03,
+///     0x00, 0x00, 0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46,
+///     0xd1, 0x2e, 0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10, 0x00,
+///     0x00, 0x03, 0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00, 0x0b, 0xb8,
+///     0x48,
+///     // Its PPS.
+///     0, 0, 1, 0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40,
+///     // An IDR slice, then P slices with POC 2 and 1.
+///     0, 0, 1, 0x26, 0x01, 0xac, 0x9c,
+///     0, 0, 1, 0x02, 0x01, 0xd0, 0xac, 0x27, 0x80,
+///     0, 0, 1, 0x02, 0x01, 0xd0, 0x6c, 0x27, 0x80,
+/// ]);
+/// // Without reordering, each picture is output as soon as it's decoded.
+/// assert_eq!(output.iter().map(|p| p.poc).collect::<Vec<_>>(), [0, 2, 1]);
+/// assert!(dpb.flush().is_empty());
+/// ```
+#[derive(Default)]
+pub struct DpbModel {
+    ctx: Context,
+    references: ReferenceTracker,
+    cvs: CvsTracker,
+    /// Whether a picture has been decoded.
+    started: bool,
+    /// The sub-layer ordering info of the highest sub-layer of the active SPS.
+    limits: Option<LayerInfo>,
+    pictures: Vec<Picture>,
+    /// The index of the last picture decoded.
+    decoded: Option<u64>,
+}
+impl DpbModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a NAL unit (without start code or length prefix), returning the pictures it causes
+    /// to be output or discarded. NAL units which can't be parsed are ignored.
+    pub fn push_nal(&mut self, nal: &[u8]) -> Vec<OutputPicture> {
+        let mut out = vec![];
+        let picture = self.references.push_nal(nal);
+        let Some(header) = nal
+            .first()
+            .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())
+        else {
+            return out;
+        };
+        if !matches!(header.nuh_layer_id(), Ok(0)) {
+            return out;
+        }
+        let unit_type = header.nal_unit_type();
+        match unit_type {
+            UnitType::VideoParameterSet | UnitType::SeqParameterSet | UnitType::PicParameterSet => {
+                self.ctx.put_param_set_nal(nal);
+            }
+            UnitType::EndOfSeq | UnitType::EndOfStream => self.cvs.end_sequence(),
+            t if t.is_vcl() => {
+                let Some(picture) = picture else {
+                    return out;
+                };
+                let mut r = BitReader::from_nal(nal);
+                let Ok((slice, sps, _)) = SliceSegmentHeader::from_bits(&self.ctx, &mut r, header)
+                else {
+                    return out;
+                };
+                let Some(slice_header) = &slice.slice_header else {
+                    return out;
+                };
+                let pic_output_flag = slice_header.pic_output_flag;
+                self.limits = sps.sub_layering_ordering_info.last().cloned();
+
+                // Clause C.5.2.2: removal of pictures before decoding the current one.
+                let starts = self.cvs.push_picture(unit_type);
+                if starts && self.started {
+                    let no_output_of_prior_pics = unit_type == UnitType::SliceSegmentLayerCraNut
+                        || slice.no_output_of_prior_pics_flag;
+                    if no_output_of_prior_pics {
+                        self.pictures.sort_by_key(|p| p.poc);
+                        out.extend(self.pictures.drain(..).filter(|p| p.needed_for_output).map(
+                            |p| OutputPicture {
+                                picture: p.picture,
+                                poc: p.poc,
+                                output: false,
+                            },
+                        ));
+                    } else {
+                        while self.bump(&mut out) {}
+                        self.pictures.clear();
+                    }
+                } else {
+                    let references: Vec<i32> = self.references.reference_pocs().collect();
+                    for p in &mut self.pictures {
+                        p.used_for_reference = references.contains(&p.poc);
+                    }
+                    self.pictures
+                        .retain(|p| p.needed_for_output || p.used_for_reference);
+                    while self.must_bump(true) && self.bump(&mut out) {}
+                }
+                self.started = true;
+                self.decoded = Some(picture.picture);
+
+                // Clause C.5.2.3: marking of the current picture, and additional bumping.
+                for p in &mut self.pictures {
+                    if p.needed_for_output {
+                        p.latency += 1;
+                    }
+                }
+                self.pictures.push(Picture {
+                    picture: picture.picture,
+                    poc: picture.poc,
+                    needed_for_output: pic_output_flag,
+                    used_for_reference: true,
+                    latency: 0,
+                });
+                while self.must_bump(false) && self.bump(&mut out) {}
+            }
+            _ => {}
+        }
+        out
+    }
+
+    /// Adds the NAL units of an Annex B stream, returning the pictures they cause to be output
+    /// or discarded.
+    pub fn push_annexb(&mut self, data: &[u8]) -> Vec<OutputPicture> {
+        let mut out = vec![];
+        for (_, nal) in crate::annexb::nal_units(data) {
+            out.extend(self.push_nal(nal));
+        }
+        out
+    }
+
+    /// Outputs all pictures still waiting, as at the end of the stream.
+    pub fn flush(&mut self) -> Vec<OutputPicture> {
+        let mut out = vec![];
+        while self.bump(&mut out) {}
+        self.pictures.clear();
+        self.cvs.end_sequence();
+        out
+    }
+
+    /// Whether a picture must be bumped: for too many pictures waiting for output, for too
+    /// long a wait, or (before decoding a picture) for a full DPB.
+    fn must_bump(&self, before_decoding: bool) -> bool {
+        let Some(limits) = &self.limits else {
+            return false;
+        };
+        let waiting = self.pictures.iter().filter(|p| p.needed_for_output);
+        if waiting.clone().count() > limits.sps_max_num_reorder_pics as usize {
+            return true;
+        }
+        if limits.sps_max_latency_increase_plus1 != 0 {
+            // SpsMaxLatencyPictures, which the unbounded fields may take past u32.
+            let max_latency = u64::from(limits.sps_max_num_reorder_pics)
+                + u64::from(limits.sps_max_latency_increase_plus1)
+                - 1;
+            if waiting.clone().any(|p| u64::from(p.latency) >= max_latency) {
+                return true;
+            }
+        }
+        before_decoding && self.pictures.len() > limits.sps_max_dec_pic_buffering_minus1 as usize
+    }
+
+    /// Outputs the waiting picture with the lowest POC, returning false if none is waiting.
+    fn bump(&mut self, out: &mut Vec<OutputPicture>) -> bool {
+        let Some(i) = (0..self.pictures.len())
+            .filter(|&i| self.pictures[i].needed_for_output)
+            .min_by_key(|&i| self.pictures[i].poc)
+        else {
+            return false;
+        };
+        let p = &mut self.pictures[i];
+        p.needed_for_output = false;
+        out.push(OutputPicture {
+            picture: p.picture,
+            poc: p.poc,
+            output: true,
+        });
+        if !p.used_for_reference {
+            self.pictures.remove(i);
+        }
+        true
+    }
+}
+
+/// A picture in output order, as returned by [`DisplayOrder`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisplayPicture<T> {
+    /// The index of the picture, in decoding order.
+    pub picture: u64,
+    /// `PicOrderCntVal`.
+    pub poc: i32,
+    /// The NAL unit type of the slice segments of the picture.
+    pub unit_type: UnitType,
+    /// B if any slice of the picture is a B slice, else P if any is a P slice, else I.
+    pub slice_type: SliceType,
+    /// The timestamp given with the access unit of the picture.
+    pub timestamp: T,
+}
+
+/// What's known of a decoded picture until its output.
+struct PictureInfo<T> {
+    unit_type: UnitType,
+    slice_type: SliceType,
+    timestamp: T,
+}
+
+/// An iterator adapter which takes access units in decoding order, each as a timestamp of the
+/// caller's choice and its NAL units (without start codes), and yields their pictures in output
+/// order, following a [`DpbModel`].
+///
+/// Pictures are yielded as soon as the model outputs them, and the rest once the access units
+/// run out. Pictures which are discarded or not output at all are skipped, as are access units
+/// without a picture of layer 0.
+///
+/// ```
+/// use hevc_reader::nal::slice::SliceType;
+/// use hevc_reader::validate::dpb::DisplayOrder;
+/// // "Intinor HW encode 720x576p" SPS, with sps_max_num_reorder_pics 0.
+/// let sps = vec![
+///     0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03, 0x00, 0x00,
+///     0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46, 0xd1, 0x2e,
+///     0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10, 0x00, 0x00, 0x03,
+///     0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00, 0x0b, 0xb8, 0x48,
+/// ];
+/// let pps = vec![0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40];
+/// let access_units = vec![
+///     // An IDR picture with its parameter sets, at a PTS of 3600, then a P picture.
+///     (3600u64, vec![sps, pps, vec![0x26, 0x01, 0xac, 0x9c]]),
+///     (7200, vec![vec![0x02, 0x01, 0xd0, 0x6c, 0x27, 0x80]]),
+/// ];
+/// let pictures: Vec<_> = DisplayOrder::new(access_units.into_iter()).collect();
+/// assert_eq!(pictures[0].timestamp, 3600);
+/// assert_eq!(pictures[1].poc, 1);
+/// assert_eq!(pictures[1].slice_type, SliceType::P);
+/// ```
+pub struct DisplayOrder<I, T> {
+    inner: I,
+    dpb: DpbModel,
+    /// The decoded pictures not output yet, by index.
+    pending: HashMap<u64, PictureInfo<T>>,
+    ready: VecDeque<DisplayPicture<T>>,
+    flushed: bool,
+}
+impl<I, T, A, N> DisplayOrder<I, T>
+where
+    I: Iterator<Item = (T, A)>,
+    A: IntoIterator<Item = N>,
+    N: AsRef<[u8]>,
+{
+    pub fn new(inner: I) -> Self {
+        DisplayOrder {
+            inner,
+            dpb: DpbModel::new(),
+            pending: HashMap::new(),
+            ready: VecDeque::new(),
+            flushed: false,
+        }
+    }
+
+    /// Decodes an access unit, queueing the pictures it causes to be output.
+    fn push_access_unit(&mut self, timestamp: T, nal_units: A) {
+        let decoded = self.dpb.decoded;
+        let mut out = vec![];
+        let mut info: Option<(UnitType, SliceType)> = None;
+        for nal in nal_units {
+            let nal = nal.as_ref();
+            out.extend(self.dpb.push_nal(nal));
+            let Some(header) = nal
+                .first()
+                .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())
+            else {
+                continue;
+            };
+            if !header.nal_unit_type().is_vcl() || !matches!(header.nuh_layer_id(), Ok(0)) {
+                continue;
+            }
+            let mut r = BitReader::from_nal(nal);
+            let Ok((slice, _, _)) = SliceSegmentHeader::from_bits(&self.dpb.ctx, &mut r, header)
+            else {
+                continue;
+            };
+            let Some(slice_header) = slice.slice_header else {
+                continue;
+            };
+            let slice_type = match (info.map(|(_, t)| t), slice_header.slice_type) {
+                (Some(SliceType::B), _) | (_, SliceType::B) => SliceType::B,
+                (Some(SliceType::P), _) | (_, SliceType::P) => SliceType::P,
+                _ => SliceType::I,
+            };
+            info = Some((header.nal_unit_type(), slice_type));
+        }
+        if let (Some(picture), Some((unit_type, slice_type))) = (self.dpb.decoded, info) {
+            if Some(picture) != decoded {
+                self.pending.insert(
+                    picture,
+                    PictureInfo {
+                        unit_type,
+                        slice_type,
+                        timestamp,
+                    },
+                );
+            }
+        }
+        self.queue(out);
+    }
+
+    /// Queues the output pictures, forgetting the discarded ones.
+    fn queue(&mut self, out: Vec<OutputPicture>) {
+        for p in out {
+            let Some(info) = self.pending.remove(&p.picture) else {
+                continue;
+            };
+            if p.output {
+                self.ready.push_back(DisplayPicture {
+                    picture: p.picture,
+                    poc: p.poc,
+                    unit_type: info.unit_type,
+                    slice_type: info.slice_type,
+                    timestamp: info.timestamp,
+                });
+            }
+        }
+    }
+}
+impl<I, T, A, N> Iterator for DisplayOrder<I, T>
+where
+    I: Iterator<Item = (T, A)>,
+    A: IntoIterator<Item = N>,
+    N: AsRef<[u8]>,
+{
+    type Item = DisplayPicture<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(p) = self.ready.pop_front() {
+                return Some(p);
+            }
+            if self.flushed {
+                return None;
+            }
+            match self.inner.next() {
+                Some((timestamp, nal_units)) => self.push_access_unit(timestamp, nal_units),
+                None => {
+                    let out = self.dpb.flush();
+                    self.queue(out);
+                    self.pending.clear();
+                    self.flushed = true;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::nal::sps::SeqParameterSet;
+    use crate::rbsp::{decode_nal, encode_nal, BitWriter, SyntaxElement, VisitingReader};
+    use hex_literal::hex;
+
+    /// The "Intinor HW encode 720x576p" SPS, with the given `sps_max_dec_pic_buffering_minus1`,
+    /// `sps_max_num_reorder_pics` and `sps_max_latency_increase_plus1`.
+    fn sps(dec_pic_buffering_minus1: u32, num_reorder_pics: u32, latency_plus1: u32) -> Vec<u8> {
+        let nal = hex!(
+            "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46
+             d1 2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00
+             0b b8 48"
+        );
+        let rbsp = decode_nal(&nal).unwrap();
+        let (mut start, mut end) = (0, 0);
+        let mut visitor = |e: &SyntaxElement| match e.name {
+            "sps_max_dec_pic_buffering_minus1" => start = e.bits.start as usize,
+            "sps_max_latency_increase_plus1" => end = e.bits.end as usize,
+            _ => {}
+        };
+        SeqParameterSet::from_bits(VisitingReader::new(BitReader::new(&*rbsp), &mut visitor))
+            .unwrap();
+        let mut w = BitWriter::new();
+        w.write_bits_from(&rbsp, 0, start);
+        w.write_ue(dec_pic_buffering_minus1);
+        w.write_ue(num_reorder_pics);
+        w.write_ue(latency_plus1);
+        w.write_bits_from(&rbsp, end, rbsp.len() * 8);
+        encode_nal(&nal[..2], &w.into_bytes())
+    }
+
+    #[test]
+    fn bumping() {
+        let pps = hex!("44 01 c1 72 b4 62 40");
+        let idr = hex!("26 01 ac 9c");
+        // With no_output_of_prior_pics_flag.
+        let idr_no_output = hex!("26 01 ec 9c");
+        // P slices with POC LSB 1 and 2.
+        let p1 = hex!("02 01 d0 6c 27 80");
+        let p2 = hex!("02 01 d0 ac 27 80");
+        let mut dpb = DpbModel::new();
+        let mut out = vec![];
+        for nal in [&sps(2, 1, 0)[..], &pps, &idr, &p2, &p1, &idr, &p2, &p1] {
+            out.extend(dpb.push_nal(nal));
+        }
+        out.extend(dpb.flush());
+        assert_eq!(
+            out.iter()
+                .map(|p| (p.picture, p.poc, p.output))
+                .collect::<Vec<_>>(),
+            [
+                (0, 0, true),
+                (2, 1, true),
+                (1, 2, true),
+                (3, 0, true),
+                (5, 1, true),
+                (4, 2, true),
+            ]
+        );
+
+        // An IDR picture with no_output_of_prior_pics_flag discards the waiting POC 2.
+        let mut dpb = DpbModel::new();
+        let mut out = vec![];
+        for nal in [&sps(2, 1, 0)[..], &pps, &idr, &p2, &p1, &idr_no_output] {
+            out.extend(dpb.push_nal(nal));
+        }
+        out.extend(dpb.flush());
+        assert_eq!(
+            out.iter().map(|p| (p.poc, p.output)).collect::<Vec<_>>(),
+            [(0, true), (1, true), (2, false), (0, true)]
+        );
+
+        // With a DPB of one picture, each must be output before the next is decoded.
+        let mut dpb = DpbModel::new();
+        let mut out = vec![];
+        for nal in [&sps(0, 1, 0)[..], &pps, &idr, &p2, &p1] {
+            out.extend(dpb.push_nal(nal));
+        }
+        assert_eq!(out.iter().map(|p| p.poc).collect::<Vec<_>>(), [0, 2]);
+
+        // SpsMaxLatencyPictures past u32 never forces output.
+        let mut dpb = DpbModel::new();
+        let mut out = vec![];
+        for nal in [&sps(2, 0x20, 0xffff_fff0)[..], &pps, &idr, &p2, &p1] {
+            out.extend(dpb.push_nal(nal));
+        }
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn display_order() {
+        let pps = hex!("44 01 c1 72 b4 62 40");
+        let idr = hex!("26 01 ac 9c");
+        let p1 = hex!("02 01 d0 6c 27 80");
+        let p2 = hex!("02 01 d0 ac 27 80");
+        let sps = sps(2, 1, 0);
+        let access_units: Vec<(u32, Vec<&[u8]>)> = vec![
+            (0, vec![&sps, &pps, &idr]),
+            (2, vec![&p2]),
+            // An access unit without a picture.
+            (9, vec![&pps]),
+            (1, vec![&p1]),
+            (3, vec![&idr]),
+        ];
+        let pictures: Vec<_> = DisplayOrder::new(access_units.into_iter())
+            .map(|p| (p.picture, p.poc, p.unit_type, p.timestamp))
+            .collect();
+        assert_eq!(
+            pictures,
+            [
+                (0, 0, UnitType::SliceSegmentLayerIdrWLp, 0),
+                (2, 1, UnitType::SliceSegmentLayerTrailR, 1),
+                (1, 2, UnitType::SliceSegmentLayerTrailR, 2),
+                (3, 0, UnitType::SliceSegmentLayerIdrWLp, 3),
+            ]
+        );
+    }
+}
@@ -0,0 +1,247 @@
+//! The requirements of Apple's HLS Authoring Specification for HEVC streams.
+
+use super::{
+    check_every_picture, check_tier_level, StreamSummary, Violation, BT2020, TRANSFER_HLG,
+    TRANSFER_PQ,
+};
+use crate::nal::sps::{Level, Profile, Tier};
+
+#[derive(Debug, Clone)]
+pub struct HlsConfig {
+    /// The sample entry type (`hvc1` or `hev1`) for fragmented MP4 segments, or `None` for
+    /// MPEG-TS segments.
+    pub sample_entry_type: Option<[u8; 4]>,
+    /// The longest allowed time from one IDR picture to the next, in seconds.
+    pub max_idr_interval: f64,
+    pub max_tier: Tier,
+    pub max_level: Level,
+}
+impl Default for HlsConfig {
+    /// Fragmented MP4 with `hvc1`, IDR pictures every 2 seconds and at most Main 10, Level 5.1,
+    /// High tier.
+    fn default() -> Self {
+        HlsConfig {
+            sample_entry_type: Some(*b"hvc1"),
+            max_idr_interval: 2.0,
+            max_tier: Tier::High,
+            max_level: Level::L5_1,
+        }
+    }
+}
+
+/// Checks the stream against the HLS requirements.
+///
+/// ```
+/// use hevc_reader::validate::{hls, StreamScanner};
+/// let mut scanner = StreamScanner::new();
+/// // An in-band PPS, which `hvc1` doesn't allow, and a trailing picture.
+/// scanner.push_nal(&[0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40]);
+/// scanner.push_nal(&[0x02, 0x01, 0xd0, 0x6c, 0x27, 0x80]);
+/// let violations = hls::check(&scanner.finish(), &hls::HlsConfig::default());
+/// let rules: Vec<_> = violations.iter().map(|v| v.rule).collect();
+/// assert_eq!(rules, ["hls.hvc1", "hls.idr-interval"]);
+/// ```
+pub fn check(summary: &StreamSummary, config: &HlsConfig) -> Vec<Violation> {
+    let mut violations = vec![];
+    check_packaging(summary, config, &mut violations);
+    check_idr_interval(summary, config, &mut violations);
+    check_profile_level(summary, config, &mut violations);
+    check_hdr(summary, &mut violations);
+    violations
+}
+
+fn check_packaging(summary: &StreamSummary, config: &HlsConfig, violations: &mut Vec<Violation>) {
+    let Some(sample_entry_type) = config.sample_entry_type else {
+        return;
+    };
+    if &sample_entry_type != b"hvc1" {
+        violations.push(Violation::new(
+            "hls.hvc1",
+            format!(
+                "sample entry type is {:?}, not hvc1",
+                String::from_utf8_lossy(&sample_entry_type)
+            ),
+        ));
+        return;
+    }
+    check_every_picture(summary, violations, "hls.hvc1", |p| !p.has_param_sets);
+}
+
+fn check_idr_interval(
+    summary: &StreamSummary,
+    config: &HlsConfig,
+    violations: &mut Vec<Violation>,
+) {
+    let Some(first) = summary.pictures.first() else {
+        return;
+    };
+    if !first.unit_type.is_idr() {
+        violations.push(Violation::at(
+            "hls.idr-interval",
+            0,
+            "the stream doesn't start with an IDR picture".to_owned(),
+        ));
+    }
+    let Some(rate) = summary.frame_rate else {
+        return;
+    };
+    let max_pictures = (config.max_idr_interval * rate.pictures_per_second()).round() as u64;
+    let mut last_idr = 0;
+    for (i, picture) in summary.pictures.iter().enumerate() {
+        let i = i as u64;
+        if picture.unit_type.is_idr() || i + 1 == summary.pictures.len() as u64 {
+            if i - last_idr > max_pictures {
+                violations.push(Violation::at(
+                    "hls.idr-interval",
+                    last_idr,
+                    format!(
+                        "{} pictures from this IDR picture to the next, more than {} seconds",
+                        i - last_idr,
+                        config.max_idr_interval
+                    ),
+                ));
+            }
+            last_idr = i;
+        }
+    }
+}
+
+fn check_profile_level(
+    summary: &StreamSummary,
+    config: &HlsConfig,
+    violations: &mut Vec<Violation>,
+) {
+    for sps in &summary.seq_param_sets {
+        let profile = sps.general_profile();
+        if !matches!(profile, Profile::Main | Profile::Main10) {
+            violations.push(Violation::new(
+                "hls.profile",
+                format!("profile {:?} isn't Main or Main 10", profile),
+            ));
+        }
+    }
+    check_tier_level(
+        summary,
+        violations,
+        "hls.tier-level",
+        config.max_tier,
+        config.max_level,
+    );
+}
+
+fn check_hdr(summary: &StreamSummary, violations: &mut Vec<Violation>) {
+    let mut transfers = vec![];
+    for sps in &summary.seq_param_sets {
+        let colour = sps
+            .vui_parameters
+            .as_ref()
+            .and_then(|v| v.video_signal_type.as_ref())
+            .and_then(|s| s.colour_description.as_ref());
+        let transfer = colour.map(|c| c.transfer_characteristics);
+        if !transfers.contains(&transfer) {
+            transfers.push(transfer);
+        }
+        let Some(c) =
+            colour.filter(|c| matches!(c.transfer_characteristics, TRANSFER_PQ | TRANSFER_HLG))
+        else {
+            continue;
+        };
+        if c.colour_primaries != BT2020 || c.matrix_coeffs != BT2020 {
+            violations.push(Violation::new(
+                "hls.hdr",
+                format!(
+                    "HDR transfer characteristics {} with colour primaries {} and matrix \
+                     coefficients {} rather than BT.2020",
+                    c.transfer_characteristics, c.colour_primaries, c.matrix_coeffs
+                ),
+            ));
+        }
+        if sps.bit_depth_luma_minus8 < 2 {
+            violations.push(Violation::new(
+                "hls.hdr",
+                format!("HDR with a bit depth of {}", sps.bit_depth_luma_minus8 + 8),
+            ));
+        }
+    }
+    if transfers.len() > 1 {
+        violations.push(Violation::new(
+            "hls.hdr",
+            format!("transfer characteristics change: {:?}", transfers),
+        ));
+    }
+    let has_metadata =
+        !summary.mastering_display.is_empty() || !summary.content_light_level.is_empty();
+    if transfers.contains(&Some(TRANSFER_PQ)) && summary.mastering_display.is_empty() {
+        violations.push(Violation::new(
+            "hls.hdr",
+            "PQ video without mastering display colour volume SEI".to_owned(),
+        ));
+    } else if has_metadata && !transfers.contains(&Some(TRANSFER_PQ)) {
+        violations.push(Violation::new(
+            "hls.hdr",
+            "HDR10 metadata SEI in video which doesn't signal PQ".to_owned(),
+        ));
+    }
+    if summary.mastering_display.len() > 1 || summary.content_light_level.len() > 1 {
+        violations.push(Violation::new(
+            "hls.hdr",
+            "HDR10 metadata SEI changes within the stream".to_owned(),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::nal::sei::content_light_level::ContentLightLevelInfo;
+    use crate::rbsp::encode_nal;
+    use crate::validate::StreamScanner;
+    use hex_literal::hex;
+
+    // "Intinor HW encode 720x576p" SPS: Main profile, level 3.1, 25 Hz.
+    const SPS: [u8; 59] = hex!(
+        "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46 d1
+         2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00 0b
+         b8 48"
+    );
+    const PPS: [u8; 7] = hex!("44 01 c1 72 b4 62 40");
+    const IDR: [u8; 4] = hex!("26 01 ac 9c");
+    const TRAIL: [u8; 6] = hex!("02 01 d0 6c 27 80");
+
+    #[test]
+    fn idr_interval_and_hdr() {
+        let mut scanner = StreamScanner::new();
+        scanner.add_param_set(&SPS);
+        scanner.add_param_set(&PPS);
+        let cll = ContentLightLevelInfo {
+            max_content_light_level: 1000,
+            max_pic_average_light_level: 400,
+        };
+        let payload = cll.to_payload();
+        let mut rbsp = vec![];
+        cll.to_message(&payload).write_to(&mut rbsp);
+        rbsp.push(0x80);
+        scanner.push_nal(&encode_nal(&[0x4e, 0x01], &rbsp));
+        // IDR pictures 2 seconds apart, then 3 seconds.
+        for gop in [50, 75] {
+            scanner.push_nal(&IDR);
+            for _ in 1..gop {
+                scanner.push_nal(&TRAIL);
+            }
+        }
+        scanner.push_nal(&IDR);
+        let summary = scanner.finish();
+        assert_eq!(summary.pictures.len(), 126);
+        assert!(!summary.pictures[0].has_param_sets);
+        assert_eq!(summary.pictures[0].average_qp(), Some(28.0));
+        assert_eq!(summary.qp_histogram.values().sum::<u64>(), 126);
+        let violations = check(&summary, &HlsConfig::default());
+        assert_eq!(
+            violations
+                .iter()
+                .map(|v| (v.rule, v.picture))
+                .collect::<Vec<_>>(),
+            [("hls.idr-interval", Some(50)), ("hls.hdr", None)]
+        );
+    }
+}
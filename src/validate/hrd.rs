@@ -0,0 +1,1073 @@
+//! Verification of a stream against the coded picture buffer (CPB) of its HRD parameters.
+//!
+//! A [`CpbSimulator`] follows the CPB of the hypothetical reference decoder (Annex C): bits
+//! arrive at the signalled bit rate, and each access unit is removed at once when it is
+//! decoded. Rather than just pass or fail, it reports the fullness of the CPB at every removal
+//! and each underflow (an access unit not completely arrived when due) or overflow (more bits
+//! than the CPB holds), with the offending access unit.
+//!
+//! The initial removal delay and the removal times come from the buffering period and picture
+//! timing SEI messages. Streams without them fall back to the initial removal delay of
+//! [`CpbConfig`] and removal at a constant picture rate. A [`SeiTimingChecker`] checks the
+//! messages themselves, against each other and the HRD parameters.
+
+use crate::nal::sei::buffering_period::{BufferingPeriod, InitialCpbRemoval};
+use crate::nal::sei::pic_timing::PicTiming;
+use crate::nal::sei::{HeaderType, SeiMessage, SeiReader};
+use crate::nal::slice::SliceSegmentHeader;
+use crate::nal::sps::{
+    HrdParametersCommonInfParameters, SeqParameterSet, SubLayerHrdParameters,
+    SubLayerHrdParametersContainer,
+};
+use crate::nal::{
+    first_slice_segment_in_pic_flag, AccessUnitTracker, CvsTracker, NalHeader, UnitType,
+};
+use crate::rbsp::{decode_nal, BitReader};
+use crate::validate::references::ReferenceTracker;
+use crate::validate::Violation;
+use crate::Context;
+
+/// The fraction of the largest initial removal delay which [`CpbConfig::from_sps`] assumes,
+/// as many encoders use, for streams without buffering period SEI messages.
+const INITIAL_FULLNESS: f64 = 0.9;
+
+/// One CPB specification.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CpbConfig {
+    /// In bits per second.
+    pub bit_rate: f64,
+    /// In bits.
+    pub cpb_size: f64,
+    /// Constant bit rate: bits keep arriving, rather than waiting until `initial_delay` before
+    /// the removal of the access unit they belong to.
+    pub cbr: bool,
+    /// The time from the arrival of the first bit to the removal of the first access unit, in
+    /// seconds. A buffering period SEI message in the first access unit overrides it.
+    pub initial_delay: f64,
+    /// True to count VCL NAL units only (a Type I bitstream), false to count all NAL units
+    /// with a three byte start code each (Type II).
+    pub vcl_only: bool,
+}
+impl CpbConfig {
+    /// The first CPB specification of the highest sub-layer of the SPS, preferring the VCL
+    /// HRD parameters. The initial removal delay is 90% of the time to fill the CPB, for want
+    /// of a buffering period SEI message.
+    pub fn from_sps(sps: &SeqParameterSet) -> Option<CpbConfig> {
+        let hrd = sps
+            .vui_parameters
+            .as_ref()?
+            .timing_info
+            .as_ref()?
+            .hrd_parameters
+            .as_ref()?;
+        let common = hrd.common.as_ref()?.parameters.as_ref()?;
+        let sub_layer = hrd.sub_layers.last()?;
+        let (params, vcl_only): (&SubLayerHrdParameters, bool) =
+            match (&sub_layer.vcl_hrd_parameters, &sub_layer.nal_hrd_parameters) {
+                (Some(vcl), _) if !vcl.is_empty() => (&vcl[0], true),
+                (_, Some(nal)) if !nal.is_empty() => (&nal[0], false),
+                _ => return None,
+            };
+        let bit_rate = (f64::from(params.bit_rate_value_minus1) + 1.0)
+            * 2f64.powi(6 + i32::from(common.bit_rate_scale));
+        let cpb_size = (f64::from(params.cpb_size_value_minus1) + 1.0)
+            * 2f64.powi(4 + i32::from(common.cpb_size_scale));
+        Some(CpbConfig {
+            bit_rate,
+            cpb_size,
+            cbr: params.cbr_flag,
+            initial_delay: INITIAL_FULLNESS * cpb_size / bit_rate,
+            vcl_only,
+        })
+    }
+}
+
+/// The state of the CPB at the removal of one access unit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CpbSample {
+    /// The index of the access unit, in decoding order.
+    pub picture: u64,
+    /// In seconds from the arrival of the first bit.
+    pub removal_time: f64,
+    /// When the last bit of the access unit arrived, in seconds.
+    pub final_arrival_time: f64,
+    pub bits: u64,
+    /// The bits in the CPB just before the removal, which is negative if the access unit
+    /// hasn't completely arrived.
+    pub fullness: f64,
+}
+
+/// The kinds of [`HrdEvent`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HrdEventKind {
+    /// An access unit hadn't completely arrived when due for removal.
+    Underflow,
+    /// The CPB held more bits than its size.
+    Overflow,
+}
+
+/// A failure of the CPB to behave as the HRD requires.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HrdEvent {
+    pub kind: HrdEventKind,
+    /// In seconds from the arrival of the first bit: the removal time of the access unit.
+    pub time: f64,
+    /// The index of the offending access unit, in decoding order.
+    pub picture: u64,
+    /// The index of the first NAL unit of the access unit, counting from 0.
+    pub nal_index: u64,
+    /// The bits in the CPB just before the removal.
+    pub fullness: f64,
+}
+
+/// What a [`CpbSimulator`] found.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HrdReport {
+    /// The CPB specification simulated, with the initial removal delay used, or `None` if the
+    /// stream has no HRD parameters or removal times.
+    pub config: Option<CpbConfig>,
+    pub pictures_per_second: Option<f64>,
+    /// True if the removal times came from picture timing SEI messages, false if from the
+    /// picture rate.
+    pub sei_timing: bool,
+    /// One sample per access unit, in decoding order.
+    pub timeline: Vec<CpbSample>,
+    pub events: Vec<HrdEvent>,
+}
+impl HrdReport {
+    /// True if the stream was simulated without underflow or overflow.
+    pub fn passed(&self) -> bool {
+        self.config.is_some() && self.events.is_empty()
+    }
+}
+
+/// An access unit, as counted towards the CPB.
+struct AccessUnit {
+    nal_index: u64,
+    /// The bits of all its NAL units, with start codes.
+    nal_bits: u64,
+    vcl_bits: u64,
+    /// The removal time from the SEI messages, in clock ticks from the first buffering period.
+    removal: Option<i64>,
+}
+
+/// Derives CPB removal times in clock ticks from buffering period and picture timing SEI
+/// messages (C.3.2), counting from the removal of the first access unit with a buffering
+/// period.
+///
+/// A removal delay below that of the previous access unit is taken to have wrapped around if
+/// the previous one was in the upper half of its range.
+#[derive(Default)]
+struct RemovalClock {
+    /// The removal time of the last access unit with a buffering period.
+    anchor: Option<i64>,
+    /// The removal time and `au_cpb_removal_delay_minus1` of the previous access unit.
+    previous: Option<(i64, u32)>,
+    /// The number of times the removal delay wrapped around since the anchor.
+    wraps: i64,
+}
+impl RemovalClock {
+    /// The removal time of the previous access unit.
+    fn previous(&self) -> Option<i64> {
+        self.previous.map(|(removal, _)| removal)
+    }
+
+    /// Returns the removal time of the next access unit, or `None` before the first buffering
+    /// period.
+    fn push(
+        &mut self,
+        buffering_period: Option<&BufferingPeriod>,
+        delay_minus1: u32,
+        params: &HrdParametersCommonInfParameters,
+    ) -> Option<i64> {
+        let removal = match (self.anchor, buffering_period, self.previous) {
+            (None, None, _) => return None,
+            (None, Some(_), _) => 0,
+            (Some(_), Some(bp), Some((previous, _))) if bp.concatenation_flag => {
+                previous + i64::from(bp.au_cpb_removal_delay_delta_minus1) + 1
+            }
+            (Some(anchor), _, previous) => {
+                let half = 1i64 << params.au_cpb_removal_delay_length_minus1;
+                let wrapped = previous.is_some_and(|(_, p)| {
+                    delay_minus1 < p && i64::from(p) >= half && i64::from(delay_minus1) < half
+                });
+                if wrapped {
+                    self.wraps += 1;
+                }
+                anchor + 2 * half * self.wraps + i64::from(delay_minus1) + 1
+            }
+        };
+        if buffering_period.is_some() {
+            self.anchor = Some(removal);
+            self.wraps = 0;
+        }
+        self.previous = Some((removal, delay_minus1));
+        Some(removal)
+    }
+}
+
+/// The HRD parameters common to the sub-layers of the SPS, and those of the highest sub-layer.
+fn hrd_parameters(
+    sps: &SeqParameterSet,
+) -> Option<(
+    &HrdParametersCommonInfParameters,
+    &SubLayerHrdParametersContainer,
+)> {
+    let hrd = sps
+        .vui_parameters
+        .as_ref()?
+        .timing_info
+        .as_ref()?
+        .hrd_parameters
+        .as_ref()?;
+    Some((
+        hrd.common.as_ref()?.parameters.as_ref()?,
+        hrd.sub_layers.last()?,
+    ))
+}
+
+/// Simulates the CPB, fed one NAL unit at a time in decoding order.
+///
+/// The CPB specification and picture rate come from the first SPS with HRD parameters and
+/// timing info unless given with [`CpbSimulator::with_config`] and
+/// [`CpbSimulator::with_picture_rate`]. The picture rate is only used if some access unit
+/// lacks a picture timing SEI message, or the first a buffering period.
+///
+/// ```
+/// use hevc_reader::validate::hrd::{CpbConfig, CpbSimulator, HrdEventKind};
+/// // A 1 Mbit/s CBR CPB of 500 kbit, half full when decoding starts, at 25 pictures/s.
+/// let mut simulator = CpbSimulator::new()
+///     .with_config(CpbConfig {
+///         bit_rate: 1_000_000.0,
+///         cpb_size: 500_000.0,
+///         cbr: true,
+///         initial_delay: 0.25,
+///         vcl_only: true,
+///     })
+///     .with_picture_rate(25.0);
+/// // IDR slice segments of 80 kbit, twice what arrives in the time of a picture.
+/// let mut idr = vec![0x26, 0x01, 0xac, 0x9c];
+/// idr.resize(10_000, 0x55);
+/// for _ in 0..10 {
+///     simulator.push_nal(&idr);
+/// }
+/// let report = simulator.finish();
+/// assert_eq!(report.timeline.len(), 10);
+/// assert_eq!(report.events[0].kind, HrdEventKind::Underflow);
+/// assert_eq!(report.events[0].picture, 5);
+/// ```
+#[derive(Default)]
+pub struct CpbSimulator {
+    ctx: Context,
+    tracker: AccessUnitTracker,
+    config: Option<CpbConfig>,
+    picture_rate: Option<f64>,
+    /// The payloads of the messages of the current access unit.
+    buffering_period: Option<Vec<u8>>,
+    pic_timing: Option<Vec<u8>>,
+    clock: RemovalClock,
+    /// In seconds, from the SPS of the first picture.
+    clock_tick: Option<f64>,
+    /// The initial removal delays of the first buffering period, in seconds, for the NAL and
+    /// VCL HRD parameters.
+    initial_delays: Option<(Option<f64>, Option<f64>)>,
+    access_units: Vec<AccessUnit>,
+    nal_index: u64,
+}
+impl CpbSimulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Simulates the given CPB specification rather than the one signalled in the stream.
+    pub fn with_config(mut self, config: CpbConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Uses the given picture rate instead of the one signalled in the stream.
+    pub fn with_picture_rate(mut self, pictures_per_second: f64) -> Self {
+        self.picture_rate = Some(pictures_per_second);
+        self
+    }
+
+    /// Adds a NAL unit (without start code or length prefix).
+    pub fn push_nal(&mut self, nal: &[u8]) {
+        let nal_index = self.nal_index;
+        self.nal_index += 1;
+        let Some(header) = nal
+            .first()
+            .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())
+        else {
+            return;
+        };
+        let unit_type = header.nal_unit_type();
+        if self.tracker.push(unit_type, nal) {
+            self.access_units.push(AccessUnit {
+                nal_index,
+                nal_bits: 0,
+                vcl_bits: 0,
+                removal: None,
+            });
+            self.buffering_period = None;
+            self.pic_timing = None;
+        }
+        match unit_type {
+            UnitType::SeqParameterSet => self.push_sps(nal),
+            UnitType::PrefixSEI if matches!(header.nuh_layer_id(), Ok(0)) => {
+                if let Ok(rbsp) = decode_nal(nal) {
+                    let mut reader = SeiReader::from_rbsp_bytes(&rbsp);
+                    while let Ok(Some(msg)) = reader.next() {
+                        match msg.payload_type {
+                            HeaderType::BufferingPeriod => {
+                                self.buffering_period = Some(msg.payload.to_vec());
+                            }
+                            HeaderType::PicTiming => self.pic_timing = Some(msg.payload.to_vec()),
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            t if t.is_vcl() && first_slice_segment_in_pic_flag(nal) => {
+                self.push_picture(header, nal);
+            }
+            _ => crate::cenc::track_param_set(&mut self.ctx, nal),
+        }
+        if let Some(au) = self.access_units.last_mut() {
+            au.nal_bits += 8 * (nal.len() as u64 + 3);
+            if unit_type.is_vcl() {
+                au.vcl_bits += 8 * nal.len() as u64;
+            }
+        }
+    }
+
+    /// Adds the NAL units of an Annex B stream.
+    pub fn push_annexb(&mut self, data: &[u8]) {
+        for (_, nal) in crate::annexb::nal_units(data) {
+            self.push_nal(nal);
+        }
+    }
+
+    fn push_sps(&mut self, nal: &[u8]) {
+        let Ok(sps) = SeqParameterSet::from_bits(BitReader::from_nal(nal)) else {
+            return;
+        };
+        if self.config.is_none() {
+            self.config = CpbConfig::from_sps(&sps);
+        }
+        if self.picture_rate.is_none() {
+            self.picture_rate = self
+                .ctx
+                .frame_rate(&sps, None)
+                .map(|r| r.pictures_per_second());
+        }
+        self.ctx.put_seq_param_set(sps);
+    }
+
+    /// Takes the removal time of the current access unit from its SEI messages.
+    fn push_picture(&mut self, header: NalHeader, nal: &[u8]) {
+        let buffering_period = self.buffering_period.take();
+        let pic_timing = self.pic_timing.take();
+        let mut r = BitReader::from_nal(nal);
+        let Ok((_, sps, _)) = SliceSegmentHeader::from_bits(&self.ctx, &mut r, header) else {
+            return;
+        };
+        let Some((params, _)) = hrd_parameters(sps) else {
+            return;
+        };
+        let timing = sps
+            .vui_parameters
+            .as_ref()
+            .and_then(|v| v.timing_info.as_ref());
+        if let Some(timing) = timing.filter(|t| t.time_scale > 0) {
+            self.clock_tick
+                .get_or_insert(f64::from(timing.num_units_in_tick) / f64::from(timing.time_scale));
+        }
+        let buffering_period = buffering_period.and_then(|payload| {
+            let msg = SeiMessage {
+                payload_type: HeaderType::BufferingPeriod,
+                payload: &payload,
+            };
+            BufferingPeriod::read(&self.ctx, &msg).ok()
+        });
+        if let Some(bp) = &buffering_period {
+            // In units of a 90 kHz clock.
+            let first = |initial: &[InitialCpbRemoval]| {
+                initial
+                    .first()
+                    .map(|i| f64::from(i.initial_cpb_removal_delay) / 90000.0)
+            };
+            self.initial_delays.get_or_insert((
+                first(&bp.nal_initial_cpb_removal),
+                first(&bp.vcl_initial_cpb_removal),
+            ));
+        }
+        let delays = pic_timing.and_then(|payload| {
+            let msg = SeiMessage {
+                payload_type: HeaderType::PicTiming,
+                payload: &payload,
+            };
+            PicTiming::read(sps, &msg).ok()?.delays
+        });
+        let removal = delays.and_then(|d| {
+            self.clock.push(
+                buffering_period.as_ref(),
+                d.au_cpb_removal_delay_minus1,
+                params,
+            )
+        });
+        if let Some(au) = self.access_units.last_mut() {
+            au.removal = removal;
+        }
+    }
+
+    pub fn finish(self) -> HrdReport {
+        let mut report = HrdReport {
+            pictures_per_second: self.picture_rate,
+            ..HrdReport::default()
+        };
+        let Some(mut config) = self.config.filter(|c| c.bit_rate > 0.0) else {
+            return report;
+        };
+        let initial_delay = self
+            .initial_delays
+            .and_then(|(nal, vcl)| if config.vcl_only { vcl } else { nal });
+        if let Some(delay) = initial_delay {
+            config.initial_delay = delay;
+        }
+        // The removal times from the SEI messages if every access unit has one, else at the
+        // picture rate.
+        let sei_removals = self.clock_tick.and_then(|tick| {
+            self.access_units
+                .iter()
+                .map(|au| au.removal.map(|r| config.initial_delay + r as f64 * tick))
+                .collect::<Option<Vec<f64>>>()
+        });
+        let mut removal_times = match (sei_removals, self.picture_rate) {
+            (Some(removals), _) => {
+                report.sei_timing = true;
+                removals
+            }
+            (None, Some(rate)) if rate > 0.0 => (0..self.access_units.len())
+                .map(|n| config.initial_delay + n as f64 / rate)
+                .collect(),
+            (None, _) => return report,
+        };
+        // An access unit removed before the previous one, which `SeiTimingChecker` reports, is
+        // taken to be removed with it.
+        for n in 1..removal_times.len() {
+            removal_times[n] = f64::max(removal_times[n], removal_times[n - 1]);
+        }
+        report.config = Some(config);
+        let bits: Vec<u64> = self
+            .access_units
+            .iter()
+            .map(|au| {
+                if config.vcl_only {
+                    au.vcl_bits
+                } else {
+                    au.nal_bits
+                }
+            })
+            .collect();
+        // The initial and final arrival times of each access unit (C.2.2 and C.3.2).
+        let mut arrivals: Vec<(f64, f64)> = Vec::with_capacity(bits.len());
+        let mut final_arrival = 0.0;
+        for (&b, &removal) in bits.iter().zip(&removal_times) {
+            let initial = if config.cbr {
+                final_arrival
+            } else {
+                f64::max(final_arrival, removal - config.initial_delay)
+            };
+            final_arrival = initial + b as f64 / config.bit_rate;
+            arrivals.push((initial, final_arrival));
+        }
+        let mut removed = 0.0;
+        // The leading access units which have wholly arrived by the removal time, and the sum of
+        // their bits. Removal times only increase, so only the access units after them need
+        // rescanning for each removal.
+        let (mut complete, mut complete_bits) = (0, 0.0);
+        for (n, au) in self.access_units.iter().enumerate() {
+            let removal_time = removal_times[n];
+            let arrived_by = |k: usize| {
+                let initial = arrivals[k].0;
+                f64::min(bits[k] as f64, (removal_time - initial) * config.bit_rate)
+            };
+            while complete < arrivals.len()
+                && arrivals[complete].0 < removal_time
+                && arrived_by(complete) == bits[complete] as f64
+            {
+                complete_bits += bits[complete] as f64;
+                complete += 1;
+            }
+            let arrived = (complete..arrivals.len())
+                .take_while(|&k| arrivals[k].0 < removal_time)
+                .fold(complete_bits, |sum, k| sum + arrived_by(k));
+            let fullness = arrived - removed;
+            let sample = CpbSample {
+                picture: n as u64,
+                removal_time,
+                final_arrival_time: arrivals[n].1,
+                bits: bits[n],
+                fullness,
+            };
+            // Allow for rounding in the arrival times.
+            let kind = if sample.final_arrival_time > removal_time + 1e-9 {
+                Some(HrdEventKind::Underflow)
+            } else if fullness > config.cpb_size + 0.5 {
+                Some(HrdEventKind::Overflow)
+            } else {
+                None
+            };
+            if let Some(kind) = kind {
+                report.events.push(HrdEvent {
+                    kind,
+                    time: removal_time,
+                    picture: n as u64,
+                    nal_index: au.nal_index,
+                    fullness,
+                });
+            }
+            report.timeline.push(sample);
+            removed += bits[n] as f64;
+        }
+        report
+    }
+}
+
+/// A picture awaiting the output order check.
+struct Output {
+    picture: u64,
+    poc: i32,
+    /// The DPB output time, in clock ticks.
+    time: i64,
+    /// The output interval which times must be a multiple of, with
+    /// `fixed_pic_rate_within_cvs_flag`.
+    interval: Option<i64>,
+}
+
+/// Cross-checks buffering period and picture timing SEI messages against each other and the
+/// HRD parameters of the SPS, fed one NAL unit at a time in decoding order.
+///
+/// CPB removal times are derived in clock ticks from the buffering period anchors (C.3.2), and
+/// DPB output times from them. The rules are:
+///
+/// * `hrd.missing-buffering-period`: the first access unit has none.
+/// * `hrd.missing-pic-timing`: an access unit has no picture timing message.
+/// * `hrd.bad-sei`: a message doesn't parse with the field lengths of the HRD parameters.
+/// * `hrd.buffering-period-sps`: a buffering period names an SPS other than the active one.
+/// * `hrd.initial-removal-delay`: an initial CPB removal delay of 0, or more than it takes to
+///   fill the CPB at its bit rate.
+/// * `hrd.initial-removal-sum`: initial removal delay plus offset changes within a coded video
+///   sequence.
+/// * `hrd.removal-order`: an access unit isn't removed after the previous one.
+/// * `hrd.output-order`: output times aren't in the order of the POCs.
+/// * `hrd.output-interval`: with `fixed_pic_rate_within_cvs_flag`, consecutive pictures aren't
+///   output a multiple of `elemental_duration_in_tc_minus1 + 1` ticks apart.
+///
+/// A removal delay below that of the previous access unit is taken to have wrapped around if
+/// the previous one was in the upper half of its range.
+///
+/// ```
+/// use hevc_reader::validate::hrd::SeiTimingChecker;
+/// let mut checker = SeiTimingChecker::new();
+/// checker.push_annexb(&[
+///     // "Intinor HW encode 720x576p" SPS, which has HRD parameters.
+///     0, 0, 1, 0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03,
+///     0x00, 0x00, 0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46,
+///     0xd1, 0x2e, 0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10, 0x00,
+///     0x00, 0x03, 0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00, 0x0b, 0xb8,
+///     0x48,
+///     // Its PPS, and an IDR slice without picture timing SEI.
+///     0, 0, 1, 0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40,
+///     0, 0, 1, 0x26, 0x01, 0xac, 0x9c,
+/// ]);
+/// let violations = checker.finish();
+/// assert_eq!(violations[0].rule, "hrd.missing-pic-timing");
+/// ```
+#[derive(Default)]
+pub struct SeiTimingChecker {
+    ctx: Context,
+    references: ReferenceTracker,
+    tracker: AccessUnitTracker,
+    cvs: CvsTracker,
+    /// The payloads of the messages of the current access unit.
+    buffering_period: Option<Vec<u8>>,
+    pic_timing: Option<Vec<u8>>,
+    pictures: u64,
+    clock: RemovalClock,
+    /// Initial removal delay plus offset of each CPB specification, in the coded video
+    /// sequence.
+    initial_sums: Vec<u64>,
+    /// The pictures since the last IRAP picture, in decoding order.
+    outputs: Vec<Output>,
+    last_output: Option<Output>,
+    violations: Vec<Violation>,
+}
+impl SeiTimingChecker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a NAL unit (without start code or length prefix). NAL units which can't be parsed
+    /// are ignored.
+    pub fn push_nal(&mut self, nal: &[u8]) {
+        let Some(header) = nal
+            .first()
+            .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())
+        else {
+            return;
+        };
+        if !matches!(header.nuh_layer_id(), Ok(0)) {
+            return;
+        }
+        let unit_type = header.nal_unit_type();
+        if self.tracker.push(unit_type, nal) {
+            self.buffering_period = None;
+            self.pic_timing = None;
+        }
+        match unit_type {
+            UnitType::VideoParameterSet | UnitType::SeqParameterSet | UnitType::PicParameterSet => {
+                crate::cenc::track_param_set(&mut self.ctx, nal);
+                self.references.push_nal(nal);
+            }
+            UnitType::EndOfSeq | UnitType::EndOfStream => {
+                self.cvs.end_sequence();
+                self.references.push_nal(nal);
+            }
+            UnitType::PrefixSEI => {
+                let Ok(rbsp) = decode_nal(nal) else {
+                    return;
+                };
+                let mut reader = SeiReader::from_rbsp_bytes(&rbsp);
+                while let Ok(Some(msg)) = reader.next() {
+                    match msg.payload_type {
+                        HeaderType::BufferingPeriod => {
+                            self.buffering_period = Some(msg.payload.to_vec());
+                        }
+                        HeaderType::PicTiming => self.pic_timing = Some(msg.payload.to_vec()),
+                        _ => {}
+                    }
+                }
+            }
+            t if t.is_vcl() => {
+                let poc = self.references.push_nal(nal).map(|p| p.poc);
+                let mut r = BitReader::from_nal(nal);
+                let sps = match SliceSegmentHeader::from_bits(&self.ctx, &mut r, header) {
+                    Ok((slice, sps, _)) if slice.first_slice_segment_in_pic_flag => sps.clone(),
+                    _ => return,
+                };
+                let starts_cvs = self.cvs.push_picture(unit_type);
+                self.push_picture(unit_type, starts_cvs, &sps, poc);
+            }
+            _ => {
+                self.references.push_nal(nal);
+            }
+        }
+    }
+
+    /// Adds the NAL units of an Annex B stream.
+    pub fn push_annexb(&mut self, data: &[u8]) {
+        for (_, nal) in crate::annexb::nal_units(data) {
+            self.push_nal(nal);
+        }
+    }
+
+    pub fn finish(mut self) -> Vec<Violation> {
+        self.flush();
+        self.violations
+    }
+
+    fn push_picture(
+        &mut self,
+        unit_type: UnitType,
+        starts_cvs: bool,
+        sps: &SeqParameterSet,
+        poc: Option<i32>,
+    ) {
+        let picture = self.pictures;
+        self.pictures += 1;
+        let Some((params, sub_layer)) = hrd_parameters(sps) else {
+            return;
+        };
+        if unit_type.is_irap() {
+            self.flush();
+        }
+        if starts_cvs {
+            self.initial_sums.clear();
+            self.last_output = None;
+        }
+        let buffering_period = self.buffering_period.take().map(|payload| {
+            let msg = SeiMessage {
+                payload_type: HeaderType::BufferingPeriod,
+                payload: &payload,
+            };
+            BufferingPeriod::read(&self.ctx, &msg)
+        });
+        let buffering_period = match buffering_period {
+            Some(Ok(bp)) => Some(bp),
+            Some(Err(e)) => {
+                self.add("hrd.bad-sei", picture, format!("{:?}", e));
+                None
+            }
+            None => None,
+        };
+        let Some(payload) = self.pic_timing.take() else {
+            self.add(
+                "hrd.missing-pic-timing",
+                picture,
+                "no picture timing SEI message".to_owned(),
+            );
+            return;
+        };
+        let msg = SeiMessage {
+            payload_type: HeaderType::PicTiming,
+            payload: &payload,
+        };
+        let delays = match PicTiming::read(sps, &msg) {
+            Ok(pt) => pt.delays,
+            Err(e) => {
+                self.add("hrd.bad-sei", picture, format!("{:?}", e));
+                return;
+            }
+        };
+        if let Some(bp) = &buffering_period {
+            self.check_buffering_period(picture, bp, sps, params, sub_layer);
+        }
+        let Some(delays) = delays else {
+            return;
+        };
+        let previous = self.clock.previous();
+        let Some(removal) = self.clock.push(
+            buffering_period.as_ref(),
+            delays.au_cpb_removal_delay_minus1,
+            params,
+        ) else {
+            self.add(
+                "hrd.missing-buffering-period",
+                picture,
+                "the first access unit has no buffering period SEI message".to_owned(),
+            );
+            return;
+        };
+        if let Some(previous) = previous.filter(|&p| removal <= p) {
+            self.add(
+                "hrd.removal-order",
+                picture,
+                format!(
+                    "removed at tick {}, not after the previous access unit at {}",
+                    removal, previous
+                ),
+            );
+        }
+        if let Some(poc) = poc {
+            self.outputs.push(Output {
+                picture,
+                poc,
+                time: removal + i64::from(delays.pic_dpb_output_delay),
+                interval: sub_layer
+                    .fixed_pic_rate_within_cvs_flag
+                    .then(|| i64::from(sub_layer.elemental_duration_in_tc_minus1) + 1),
+            });
+        }
+    }
+
+    fn check_buffering_period(
+        &mut self,
+        picture: u64,
+        bp: &BufferingPeriod,
+        sps: &SeqParameterSet,
+        params: &HrdParametersCommonInfParameters,
+        sub_layer: &SubLayerHrdParametersContainer,
+    ) {
+        if bp.bp_seq_parameter_set_id != sps.id() {
+            self.add(
+                "hrd.buffering-period-sps",
+                picture,
+                format!(
+                    "buffering period for SPS {}, in a picture using SPS {}",
+                    bp.bp_seq_parameter_set_id.id(),
+                    sps.id().id()
+                ),
+            );
+        }
+        let specs = bp
+            .nal_initial_cpb_removal
+            .iter()
+            .zip(sub_layer.nal_hrd_parameters.iter().flatten())
+            .chain(
+                bp.vcl_initial_cpb_removal
+                    .iter()
+                    .zip(sub_layer.vcl_hrd_parameters.iter().flatten()),
+            );
+        let mut sums = vec![];
+        for (i, (initial, hrd)) in specs.enumerate() {
+            let bit_rate = (f64::from(hrd.bit_rate_value_minus1) + 1.0)
+                * 2f64.powi(6 + i32::from(params.bit_rate_scale));
+            let cpb_size = (f64::from(hrd.cpb_size_value_minus1) + 1.0)
+                * 2f64.powi(4 + i32::from(params.cpb_size_scale));
+            let max = (90000.0 * cpb_size / bit_rate) as u64;
+            let delay = initial.initial_cpb_removal_delay;
+            if delay == 0 || u64::from(delay) > max {
+                self.add(
+                    "hrd.initial-removal-delay",
+                    picture,
+                    format!(
+                        "initial CPB removal delay {} of CPB {} is not in 1..={}",
+                        delay, i, max
+                    ),
+                );
+            }
+            sums.push(u64::from(delay) + u64::from(initial.initial_cpb_removal_offset));
+        }
+        if self.initial_sums.is_empty() {
+            self.initial_sums = sums;
+        } else if self.initial_sums != sums {
+            self.add(
+                "hrd.initial-removal-sum",
+                picture,
+                format!(
+                    "initial CPB removal delay plus offset changed from {:?} to {:?}",
+                    self.initial_sums, sums
+                ),
+            );
+        }
+    }
+
+    /// Checks the output times of the buffered pictures, in output order.
+    fn flush(&mut self) {
+        let mut outputs = std::mem::take(&mut self.outputs);
+        outputs.sort_by_key(|o| o.poc);
+        for output in outputs {
+            if let Some(last) = &self.last_output {
+                let (poc, time) = (last.poc, last.time);
+                if output.time <= time {
+                    self.add(
+                        "hrd.output-order",
+                        output.picture,
+                        format!(
+                            "POC {} is output at tick {}, not after POC {} at {}",
+                            output.poc, output.time, poc, time
+                        ),
+                    );
+                } else if let Some(interval) =
+                    output.interval.filter(|i| (output.time - time) % i != 0)
+                {
+                    self.add(
+                        "hrd.output-interval",
+                        output.picture,
+                        format!(
+                            "POC {} is output {} ticks after POC {}, not a multiple of {}",
+                            output.poc,
+                            output.time - time,
+                            poc,
+                            interval
+                        ),
+                    );
+                }
+            }
+            self.last_output = Some(output);
+        }
+    }
+
+    fn add(&mut self, rule: &'static str, picture: u64, message: String) {
+        self.violations.push(Violation::at(rule, picture, message));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hex_literal::hex;
+
+    #[test]
+    fn overflow() {
+        // "Intinor HW encode 720x576p" SPS, signalling 25 Hz, and its PPS.
+        let sps = hex!(
+            "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46
+             d1 2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00
+             0b b8 48"
+        );
+        let pps = hex!("44 01 c1 72 b4 62 40");
+        // A 1 Mbit/s CBR CPB of 50 kbit, into which pictures of 20 kbit arrive twice as fast as
+        // they are removed.
+        let mut simulator = CpbSimulator::new().with_config(CpbConfig {
+            bit_rate: 1_000_000.0,
+            cpb_size: 50_000.0,
+            cbr: true,
+            initial_delay: 0.04,
+            vcl_only: true,
+        });
+        simulator.push_nal(&sps);
+        simulator.push_nal(&pps);
+        let mut idr = hex!("26 01 ac 9c").to_vec();
+        idr.resize(2500, 0x55);
+        for _ in 0..10 {
+            simulator.push_nal(&idr);
+        }
+        let report = simulator.finish();
+        assert_eq!(report.pictures_per_second, Some(25.0));
+        assert!(!report.sei_timing);
+        assert_eq!(
+            report
+                .timeline
+                .iter()
+                .map(|s| (s.fullness / 1000.0).round())
+                .collect::<Vec<_>>(),
+            [40.0, 60.0, 80.0, 100.0, 120.0, 100.0, 80.0, 60.0, 40.0, 20.0]
+        );
+        assert_eq!(
+            report
+                .events
+                .iter()
+                .map(|e| (e.kind, e.picture, e.nal_index))
+                .collect::<Vec<_>>(),
+            (1..8)
+                .map(|n| (HrdEventKind::Overflow, n, n + 2))
+                .collect::<Vec<_>>()
+        );
+        assert!(!report.passed());
+    }
+
+    #[test]
+    fn sei_timing() {
+        use crate::nal::sei::buffering_period::InitialCpbRemoval;
+        use crate::rbsp::{encode_nal, BitWriter};
+        use crate::rewrite::vui::SpsVuiEditor;
+
+        // The "Intinor HW encode 720x576p" SPS at 50 Hz, with the VCL HRD parameters of the
+        // "Haivision 1080i25" one and an output interval of two ticks; its PPS; an IDR slice;
+        // and P slices with POC LSB 1 to 3.
+        let haivision = hex!(
+            "42 01 01 01 40 00 00 03 00 40 00 00 03 00 00 03 00 7b a0 03 c0 80 22 1f 79 e9 6e
+             44 a1 7f f8 00 08 00 13 50 10 10 1e d0 00 00 03 00 10 00 00 03 03 25 08 ff de 10
+             00 16 e3 60 00 05 dd 77 df 08 04 10"
+        );
+        let mut hrd = SpsVuiEditor::new(&haivision)
+            .unwrap()
+            .vui()
+            .unwrap()
+            .timing_info
+            .clone()
+            .unwrap()
+            .hrd_parameters
+            .unwrap();
+        hrd.sub_layers[0].fixed_pic_rate_within_cvs_flag = true;
+        hrd.sub_layers[0].elemental_duration_in_tc_minus1 = 1;
+        let mut editor = SpsVuiEditor::new(&hex!(
+            "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46
+             d1 2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00
+             0b b8 48"
+        ))
+        .unwrap();
+        editor.set_timing(1, 50);
+        editor.vui_mut().frame_field_info_present_flag = false;
+        editor
+            .vui_mut()
+            .timing_info
+            .as_mut()
+            .unwrap()
+            .hrd_parameters = Some(hrd);
+        let sps = editor.to_nal();
+        let pictures = [
+            &hex!("26 01 ac 9c")[..],
+            &hex!("02 01 d0 6c 27 80"),
+            &hex!("02 01 d0 ac 27 80"),
+            &hex!("02 01 d0 ec 27 80"),
+        ];
+        let bp = BufferingPeriod {
+            bp_seq_parameter_set_id: editor.sps().id(),
+            irap_cpb_params: None,
+            concatenation_flag: false,
+            au_cpb_removal_delay_delta_minus1: 0,
+            nal_initial_cpb_removal: vec![],
+            vcl_initial_cpb_removal: vec![InitialCpbRemoval {
+                initial_cpb_removal_delay: 90000,
+                initial_cpb_removal_offset: 0,
+                alt: None,
+            }],
+        }
+        .to_payload(editor.sps())
+        .unwrap();
+        let sei = |messages: &[(HeaderType, &[u8])]| {
+            let mut rbsp = vec![];
+            for &(payload_type, payload) in messages {
+                SeiMessage {
+                    payload_type,
+                    payload,
+                }
+                .write_to(&mut rbsp);
+            }
+            rbsp.push(0x80);
+            encode_nal(&[0x4e, 0x01], &rbsp)
+        };
+        let pic_timing = |delay_minus1: u32, output_delay: u32| {
+            let mut w = BitWriter::new();
+            w.write_u32(31, delay_minus1);
+            w.write_u32(31, output_delay);
+            w.write_byte_alignment();
+            w.into_bytes()
+        };
+
+        let mut nals = vec![sps.clone(), hex!("44 01 c1 72 b4 62 40").to_vec()];
+        // Removal at ticks 0, 2, 4 and 3; output at ticks 0, 4, 7 and 5.
+        for (i, (picture, (delay_minus1, output_delay))) in pictures
+            .iter()
+            .zip([(0, 0), (1, 2), (3, 3), (2, 2)])
+            .enumerate()
+        {
+            let pt = pic_timing(delay_minus1, output_delay);
+            if i == 0 {
+                nals.push(sei(&[
+                    (HeaderType::BufferingPeriod, &bp),
+                    (HeaderType::PicTiming, &pt),
+                ]));
+            } else {
+                nals.push(sei(&[(HeaderType::PicTiming, &pt)]));
+            }
+            nals.push(picture.to_vec());
+        }
+        let mut checker = SeiTimingChecker::new();
+        for nal in &nals {
+            checker.push_nal(nal);
+        }
+        assert_eq!(
+            checker
+                .finish()
+                .into_iter()
+                .map(|v| (v.rule, v.picture))
+                .collect::<Vec<_>>(),
+            [
+                ("hrd.removal-order", Some(3)),
+                ("hrd.output-interval", Some(2)),
+                ("hrd.output-order", Some(3)),
+            ]
+        );
+
+        // The simulator takes the initial delay of one second and the removal times, 20 ms
+        // ticks apart, from the same messages.
+        let mut simulator = CpbSimulator::new();
+        for nal in &nals {
+            simulator.push_nal(nal);
+        }
+        let report = simulator.finish();
+        assert!(report.sei_timing);
+        assert_eq!(report.config.unwrap().initial_delay, 1.0);
+        assert_eq!(
+            report
+                .timeline
+                .iter()
+                .map(|s| (s.removal_time * 1000.0).round())
+                .collect::<Vec<_>>(),
+            [1000.0, 1040.0, 1080.0, 1080.0]
+        );
+
+        // Without a buffering period, nothing can be checked.
+        let mut checker = SeiTimingChecker::new();
+        checker.push_nal(&sps);
+        checker.push_nal(&hex!("44 01 c1 72 b4 62 40"));
+        checker.push_nal(&sei(&[(HeaderType::PicTiming, &pic_timing(0, 0))]));
+        checker.push_nal(pictures[0]);
+        assert_eq!(checker.finish()[0].rule, "hrd.missing-buffering-period");
+    }
+}
@@ -0,0 +1,453 @@
+//! Checks of streams against the constraints of delivery specifications.
+//!
+//! A [`StreamScanner`] gathers what the checks need in one pass over the stream; each preset
+//! then checks the resulting [`StreamSummary`] and reports [`Violation`]s by rule.
+
+pub mod activation;
+pub mod broadcast;
+pub mod corruption;
+pub mod dpb;
+pub mod hls;
+pub mod hrd;
+pub mod nesting;
+pub mod order;
+pub mod param_sets;
+pub mod references;
+pub mod reorder;
+pub mod timecode;
+pub mod uhd_bd;
+
+use std::collections::BTreeMap;
+
+use crate::hvcc::SampleError;
+use crate::nal::pps::PicParameterSet;
+use crate::nal::sei::content_light_level::ContentLightLevelInfo;
+use crate::nal::sei::mastering_display_colour_volume::MasteringDisplayColourVolume;
+use crate::nal::sei::{HeaderType, SeiMessage, SeiReader};
+use crate::nal::slice::SliceSegmentHeader;
+use crate::nal::sps::{Level, SeqParameterSet, Tier};
+use crate::nal::vps::VideoParameterSet;
+use crate::nal::AccessUnitTracker;
+use crate::nal::{NalHeader, UnitType};
+use crate::rbsp::{decode_nal, BitReader};
+use crate::rewrite::level::{LevelAnalyzer, LevelMeasurement};
+use crate::rewrite::sei_strip::is_dolby_metadata;
+use crate::validate::order::{OrderAnomaly, OrderChecker};
+use crate::{Context, FrameRate};
+
+/// Transfer characteristics of SMPTE ST 2084 (PQ) and ARIB STD-B67 (HLG).
+pub(crate) const TRANSFER_PQ: u8 = 16;
+pub(crate) const TRANSFER_HLG: u8 = 18;
+/// Colour primaries and matrix coefficients of ITU-R BT.2020 (non-constant luminance).
+pub(crate) const BT2020: u8 = 9;
+
+/// A failed check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// The identifier of the rule, e.g. `hls.idr-interval`.
+    pub rule: &'static str,
+    /// The picture (in decoding order) at which the violation was found, if specific to one.
+    pub picture: Option<u64>,
+    pub message: String,
+}
+impl Violation {
+    pub(crate) fn new(rule: &'static str, message: String) -> Self {
+        Violation {
+            rule,
+            picture: None,
+            message,
+        }
+    }
+
+    pub(crate) fn at(rule: &'static str, picture: u64, message: String) -> Self {
+        Violation {
+            rule,
+            picture: Some(picture),
+            message,
+        }
+    }
+}
+
+/// Checks the signalled tier and level of each SPS, and the measured properties of the stream,
+/// against a cap. Streams without pictures have no measured properties to check.
+pub(crate) fn check_tier_level(
+    summary: &StreamSummary,
+    violations: &mut Vec<Violation>,
+    rule: &'static str,
+    max_tier: Tier,
+    max_level: Level,
+) {
+    for sps in &summary.seq_param_sets {
+        let (tier, level) = (sps.general_tier(), sps.general_level());
+        if tier > max_tier || !level.at_most(max_level) {
+            violations.push(Violation::new(
+                rule,
+                format!(
+                    "signalled {:?} tier level {:?} exceeds {:?} tier level {:?}",
+                    tier, level, max_tier, max_level
+                ),
+            ));
+        }
+    }
+    if summary.level.pictures > 0 && !summary.level.fits(max_tier, max_level) {
+        violations.push(Violation::new(
+            rule,
+            format!(
+                "the stream needs more than {:?} tier level {:?}",
+                max_tier, max_level
+            ),
+        ));
+    }
+}
+
+/// Reports the first picture failing `ok`, with the number of such pictures.
+pub(crate) fn check_every_picture(
+    summary: &StreamSummary,
+    violations: &mut Vec<Violation>,
+    rule: &'static str,
+    ok: impl Fn(&PictureInfo) -> bool,
+) {
+    let mut failing = summary.pictures.iter().enumerate().filter(|(_, p)| !ok(p));
+    if let Some((first, _)) = failing.next() {
+        violations.push(Violation::at(
+            rule,
+            first as u64,
+            format!(
+                "{} of {} pictures",
+                1 + failing.count(),
+                summary.pictures.len()
+            ),
+        ));
+    }
+}
+
+/// One coded picture of a [`StreamSummary`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PictureInfo {
+    /// The NAL unit type of its slice segments.
+    pub unit_type: UnitType,
+    pub temporal_id: u8,
+    pub slice_segments: u32,
+    /// True if its access unit starts with an access unit delimiter.
+    pub has_aud: bool,
+    /// True if its access unit contains a VPS, SPS or PPS.
+    pub has_param_sets: bool,
+    /// The payload types of the SEI messages in its access unit.
+    pub sei: Vec<HeaderType>,
+    /// True if its access unit carries ATSC A/53 closed caption data in registered user data.
+    pub captions: bool,
+    /// The sum of `SliceQpY` over its slices whose headers could be parsed.
+    pub qp_sum: i64,
+    /// The number of slices in `qp_sum`.
+    pub qp_slices: u32,
+}
+
+impl PictureInfo {
+    fn new(unit_type: UnitType) -> Self {
+        PictureInfo {
+            unit_type,
+            temporal_id: 0,
+            slice_segments: 0,
+            has_aud: false,
+            has_param_sets: false,
+            sei: vec![],
+            captions: false,
+            qp_sum: 0,
+            qp_slices: 0,
+        }
+    }
+
+    /// The average `SliceQpY` of its slices, unless none could be parsed.
+    pub fn average_qp(&self) -> Option<f64> {
+        (self.qp_slices > 0).then(|| self.qp_sum as f64 / f64::from(self.qp_slices))
+    }
+}
+
+/// True for ITU-T T.35 registered user data with HDR10+ metadata: country code `0xB5`,
+/// provider code `0x003C` and provider oriented code `0x0001`.
+fn is_hdr10_plus(msg: &SeiMessage<'_>) -> bool {
+    msg.payload_type == HeaderType::UserDataRegisteredItuTT35
+        && msg.payload.starts_with(&[0xb5, 0x00, 0x3c, 0x00, 0x01])
+}
+
+/// True for ITU-T T.35 registered user data carrying ATSC A/53 `cc_data()`: country code
+/// `0xB5`, provider code `0x0031`, user identifier `GA94` and user data type code `0x03`.
+fn is_a53_captions(msg: &SeiMessage<'_>) -> bool {
+    msg.payload_type == HeaderType::UserDataRegisteredItuTT35
+        && msg
+            .payload
+            .starts_with(&[0xb5, 0x00, 0x31, b'G', b'A', b'9', b'4', 0x03])
+}
+
+/// The properties of a stream which the presets check, see [`StreamScanner`].
+#[derive(Debug, Clone, Default)]
+pub struct StreamSummary {
+    /// The distinct SPSs, in order of appearance.
+    pub seq_param_sets: Vec<SeqParameterSet>,
+    pub pictures: Vec<PictureInfo>,
+    pub level: LevelMeasurement,
+    /// The frame rate signalled for the first SPS.
+    pub frame_rate: Option<FrameRate>,
+    /// The distinct mastering display colour volume SEI messages.
+    pub mastering_display: Vec<MasteringDisplayColourVolume>,
+    /// The distinct content light level SEI messages.
+    pub content_light_level: Vec<ContentLightLevelInfo>,
+    /// True if Dolby Vision metadata was found, as RPU NAL units (type 62) or in SEI.
+    pub dolby_vision: bool,
+    /// True if HDR10+ dynamic metadata (SMPTE ST 2094-40) was found in SEI.
+    pub hdr10_plus: bool,
+    /// Duplicated and reordered NAL units and pictures, see [`OrderChecker`].
+    pub order: Vec<OrderAnomaly>,
+    /// The number of slices with each `SliceQpY`, over the slices whose headers could be
+    /// parsed.
+    pub qp_histogram: BTreeMap<i32, u64>,
+}
+
+/// Gathers a [`StreamSummary`], fed one NAL unit at a time in decoding order.
+///
+/// ```
+/// use hevc_reader::validate::StreamScanner;
+/// let mut scanner = StreamScanner::new();
+/// // An access unit delimiter, a PPS and an IDR slice segment.
+/// scanner.push_nal(&[0x46, 0x01, 0x10]);
+/// scanner.push_nal(&[0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40]);
+/// scanner.push_nal(&[0x26, 0x01, 0xac, 0x9c]);
+/// let summary = scanner.finish();
+/// assert_eq!(summary.pictures.len(), 1);
+/// assert!(summary.pictures[0].has_aud && summary.pictures[0].has_param_sets);
+/// ```
+#[derive(Default)]
+pub struct StreamScanner {
+    ctx: Context,
+    level: LevelAnalyzer,
+    tracker: AccessUnitTracker,
+    order: OrderChecker,
+    /// What was seen of the current access unit before its first slice segment.
+    pending: Option<PictureInfo>,
+    summary: StreamSummary,
+}
+impl StreamScanner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a NAL unit (without start code or length prefix). NAL units which can't be parsed
+    /// are ignored.
+    pub fn push_nal(&mut self, nal: &[u8]) {
+        let Some(header) = nal
+            .first()
+            .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())
+        else {
+            return;
+        };
+        self.level.push_nal(nal);
+        self.order.push_nal(nal);
+        let unit_type = header.nal_unit_type();
+        if self.tracker.push(unit_type, nal) {
+            let mut picture = PictureInfo::new(unit_type);
+            picture.has_aud = unit_type == UnitType::AccessUnitDelimiter;
+            self.pending = Some(picture);
+        }
+        match unit_type {
+            UnitType::VideoParameterSet | UnitType::SeqParameterSet | UnitType::PicParameterSet => {
+                if let Some(picture) = self.current() {
+                    picture.has_param_sets = true;
+                }
+                self.push_param_set(unit_type, nal);
+            }
+            UnitType::PrefixSEI | UnitType::SuffixSEI => self.push_sei(nal),
+            UnitType::Unspecified(62) => self.summary.dolby_vision = true,
+            t if t.is_vcl() => {
+                if nal.get(2).is_some_and(|b| b & 0x80 != 0) {
+                    let mut picture = self
+                        .pending
+                        .take()
+                        .unwrap_or_else(|| PictureInfo::new(unit_type));
+                    picture.unit_type = unit_type;
+                    picture.temporal_id = header.nuh_temporal_id().unwrap_or(0);
+                    self.summary.pictures.push(picture);
+                }
+                if let Some(picture) = self.summary.pictures.last_mut() {
+                    picture.slice_segments += 1;
+                }
+                self.push_slice_segment(header, nal);
+            }
+            _ => {}
+        }
+    }
+
+    /// The picture whose access unit the last NAL unit belongs to.
+    fn current(&mut self) -> Option<&mut PictureInfo> {
+        self.pending
+            .as_mut()
+            .or_else(|| self.summary.pictures.last_mut())
+    }
+
+    /// Adds a parameter set from outside the stream, e.g. from the `hvcC` box of an MP4 sample
+    /// entry, without counting it as in-band.
+    pub fn add_param_set(&mut self, nal: &[u8]) {
+        let Some(header) = nal
+            .first()
+            .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())
+        else {
+            return;
+        };
+        self.level.push_nal(nal);
+        self.order.add_param_set(nal);
+        self.push_param_set(header.nal_unit_type(), nal);
+    }
+
+    /// Adds the NAL units of an Annex B stream.
+    pub fn push_annexb(&mut self, data: &[u8]) {
+        for (_, nal) in crate::annexb::nal_units(data) {
+            self.push_nal(nal);
+        }
+    }
+
+    /// Adds the NAL units of a length-prefixed sample.
+    pub fn push_sample(&mut self, sample: &[u8], length_size: usize) -> Result<(), SampleError> {
+        for nal in crate::hvcc::nal_units(sample, length_size)? {
+            self.push_nal(nal?);
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> StreamSummary {
+        self.summary.level = self.level.measurement();
+        self.summary.order = self.order.finish();
+        self.summary
+    }
+
+    fn push_param_set(&mut self, unit_type: UnitType, nal: &[u8]) {
+        let r = BitReader::from_nal(nal);
+        match unit_type {
+            UnitType::VideoParameterSet => {
+                if let Ok(vps) = VideoParameterSet::from_bits(r) {
+                    self.ctx.put_video_param_set(vps);
+                }
+            }
+            UnitType::SeqParameterSet => {
+                let Ok(sps) = SeqParameterSet::from_bits(r) else {
+                    return;
+                };
+                if self.summary.seq_param_sets.is_empty() {
+                    self.summary.frame_rate = self.ctx.frame_rate(&sps, None);
+                }
+                if !self.summary.seq_param_sets.contains(&sps) {
+                    self.summary.seq_param_sets.push(sps.clone());
+                }
+                self.ctx.put_seq_param_set(sps);
+            }
+            UnitType::PicParameterSet => {
+                if let Ok(pps) = PicParameterSet::from_bits(&self.ctx, r) {
+                    self.ctx.put_pic_param_set(pps);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Counts the QP of a slice; dependent slice segments share the QP of their slice. Slices
+    /// whose `SliceQpY` is outside `-QpBdOffsetY..=51` are malformed, and aren't counted.
+    fn push_slice_segment(&mut self, header: NalHeader, nal: &[u8]) {
+        let mut r = BitReader::from_nal(nal);
+        let Ok((slice, sps, pps)) = SliceSegmentHeader::from_bits(&self.ctx, &mut r, header) else {
+            return;
+        };
+        let (Some(slice_header), Some(picture), Ok(derived)) = (
+            &slice.slice_header,
+            self.summary.pictures.last_mut(),
+            sps.derived(),
+        ) else {
+            return;
+        };
+        let Some(qp) = pps
+            .slice_qp_y(slice_header.slice_qp_delta)
+            .filter(|qp| (-(derived.qp_bd_offset_y as i32)..=51).contains(qp))
+        else {
+            return;
+        };
+        picture.qp_sum += i64::from(qp);
+        picture.qp_slices += 1;
+        *self.summary.qp_histogram.entry(qp).or_default() += 1;
+    }
+
+    fn push_sei(&mut self, nal: &[u8]) {
+        let Ok(rbsp) = decode_nal(nal) else {
+            return;
+        };
+        let mut reader = SeiReader::from_rbsp_bytes(&rbsp);
+        let mut types = vec![];
+        let mut captions = false;
+        while let Ok(Some(msg)) = reader.next() {
+            types.push(msg.payload_type);
+            captions |= is_a53_captions(&msg);
+            self.summary.dolby_vision |= is_dolby_metadata(&msg);
+            self.summary.hdr10_plus |= is_hdr10_plus(&msg);
+            match msg.payload_type {
+                HeaderType::MasteringDisplayColourVolume => {
+                    if let Ok(mdcv) = MasteringDisplayColourVolume::read(&msg) {
+                        if !self.summary.mastering_display.contains(&mdcv) {
+                            self.summary.mastering_display.push(mdcv);
+                        }
+                    }
+                }
+                HeaderType::ContentLightLevelInfo => {
+                    if let Ok(cll) = ContentLightLevelInfo::read(&msg) {
+                        if !self.summary.content_light_level.contains(&cll) {
+                            self.summary.content_light_level.push(cll);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        if let Some(picture) = self.current() {
+            picture.sei.extend(types);
+            picture.captions |= captions;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rbsp::{encode_nal, BitWriter};
+    use hex_literal::hex;
+
+    #[test]
+    fn malformed_qp() {
+        // "Intinor HW encode 720x576p" SPS, its PPS and an IDR slice segment.
+        let sps = hex!(
+            "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46
+             d1 2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00
+             0b b8 48"
+        );
+        let pps = hex!("44 01 c1 72 b4 62 40");
+        let idr = hex!("26 01 ac 9c");
+        // A P slice segment, whose slice_qp_delta is bits 21..22 of the RBSP.
+        let p = hex!("02 01 d0 6c 27 80");
+        let with_qp_delta = |delta| {
+            let rbsp = decode_nal(&p).unwrap();
+            let mut w = BitWriter::new();
+            w.write_bits_from(&rbsp, 0, 21);
+            w.write_se(delta);
+            w.write_bits_from(&rbsp, 22, rbsp.len() * 8);
+            encode_nal(&p[..2], &w.into_bytes())
+        };
+        let mut scanner = StreamScanner::new();
+        for nal in [
+            &sps[..],
+            &pps,
+            &idr,
+            &p,
+            &with_qp_delta(30),
+            &with_qp_delta(i32::MAX),
+        ] {
+            scanner.push_nal(nal);
+        }
+        let summary = scanner.finish();
+        assert_eq!(summary.pictures.len(), 4);
+        assert_eq!(summary.qp_histogram, BTreeMap::from([(26, 1), (28, 1)]));
+    }
+}
@@ -0,0 +1,257 @@
+//! Checking of the temporal sub-layer nesting signalled by `sps_temporal_id_nesting_flag`.
+//!
+//! With the flag set, a decoder may switch up to any higher sub-layer at any picture: no
+//! picture predicts from a picture of its own or a lower sub-layer which precedes a picture of
+//! an even lower sub-layer in decoding order (clause 7.4.3.2.1). Every picture with
+//! `TemporalId` greater than 0 is then effectively a TSA picture whatever its NAL unit type, so
+//! it is the references of each picture which are checked. Streams breaking this decode
+//! correctly in full, but not after dropping or switching sub-layers.
+
+use std::collections::HashMap;
+
+use crate::nal::slice::SliceSegmentHeader;
+use crate::nal::sps::SeqParameterSet;
+use crate::nal::{CvsTracker, NalHeader, UnitType};
+use crate::rbsp::BitReader;
+use crate::validate::references::{PictureReferences, ReferenceTracker};
+use crate::validate::Violation;
+use crate::Context;
+
+/// A picture which may be used for reference.
+struct Picture {
+    /// The index of the picture, in decoding order.
+    index: u64,
+    temporal_id: u8,
+    /// The lowest `TemporalId` of the pictures following it in decoding order.
+    lowest_following: Option<u8>,
+}
+
+/// Checks the temporal sub-layer nesting of a stream, fed one NAL unit at a time in decoding
+/// order. The rules are:
+///
+/// * `nesting.single-layer`: an SPS with one sub-layer doesn't set
+///   `sps_temporal_id_nesting_flag`, which it must.
+/// * `nesting.vps`: the VPS sets `vps_temporal_id_nesting_flag` but the SPS doesn't set
+///   `sps_temporal_id_nesting_flag`.
+/// * `nesting.reference`: with the flag set, a picture predicts from a picture with a
+///   `TemporalId` no higher than its own, which precedes a picture of a lower sub-layer.
+///
+/// ```
+/// use hevc_reader::validate::nesting::NestingChecker;
+/// let mut checker = NestingChecker::new();
+/// checker.push_annexb(&[
+///     // "Intinor HW encode 720x576p" SPS, with sps_temporal_id_nesting_flag set.
+///     0, 0, 1, 0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03,
+///     0x00, 0x00, 0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46,
+///     0xd1, 0x2e, 0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10, 0x00,
+///     0x00, 0x03, 0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00, 0x0b, 0xb8,
+///     0x48,
+///     // Its PPS.
+///     0, 0, 1, 0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40,
+///     // An IDR slice, and P slices predicting from the previous POC: POC 1 in sub-layer 1,
+///     // POC 3 in sub-layer 0, and POC 2 in sub-layer 1, predicting from POC 1 across POC 3.
+///     0, 0, 1, 0x26, 0x01, 0xac, 0x9c,
+///     0, 0, 1, 0x02, 0x02, 0xd0, 0x6c, 0x27, 0x80,
+///     0, 0, 1, 0x02, 0x01, 0xd0, 0xec, 0x27, 0x80,
+///     0, 0, 1, 0x02, 0x02, 0xd0, 0xac, 0x27, 0x80,
+/// ]);
+/// let violations = checker.finish();
+/// assert_eq!(violations[0].rule, "nesting.reference");
+/// assert_eq!(violations[0].picture, Some(3));
+/// ```
+#[derive(Default)]
+pub struct NestingChecker {
+    ctx: Context,
+    references: ReferenceTracker,
+    cvs: CvsTracker,
+    /// Whether the SPS of the current coded video sequence sets the flag.
+    nesting: bool,
+    /// The pictures of the current and previous IRAP periods, by POC.
+    pictures: HashMap<i32, Picture>,
+    /// The index of the last IRAP picture.
+    last_irap: u64,
+    violations: Vec<Violation>,
+}
+impl NestingChecker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a NAL unit (without start code or length prefix). NAL units which can't be parsed
+    /// are ignored.
+    pub fn push_nal(&mut self, nal: &[u8]) {
+        let Some(header) = nal
+            .first()
+            .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())
+        else {
+            return;
+        };
+        if !matches!(header.nuh_layer_id(), Ok(0)) {
+            return;
+        }
+        let unit_type = header.nal_unit_type();
+        let picture = self.references.push_nal(nal);
+        match unit_type {
+            UnitType::VideoParameterSet | UnitType::SeqParameterSet | UnitType::PicParameterSet => {
+                crate::cenc::track_param_set(&mut self.ctx, nal);
+            }
+            UnitType::EndOfSeq | UnitType::EndOfStream => self.cvs.end_sequence(),
+            t if t.is_vcl() => {
+                let Some(picture) = picture else {
+                    return;
+                };
+                let mut r = BitReader::from_nal(nal);
+                let Ok((_, sps, _)) = SliceSegmentHeader::from_bits(&self.ctx, &mut r, header)
+                else {
+                    return;
+                };
+                let sps = sps.clone();
+                self.push_picture(unit_type, header.temporal_id(), &sps, picture);
+            }
+            _ => {}
+        }
+    }
+
+    /// Adds the NAL units of an Annex B stream.
+    pub fn push_annexb(&mut self, data: &[u8]) {
+        for (_, nal) in crate::annexb::nal_units(data) {
+            self.push_nal(nal);
+        }
+    }
+
+    pub fn finish(self) -> Vec<Violation> {
+        self.violations
+    }
+
+    fn push_picture(
+        &mut self,
+        unit_type: UnitType,
+        temporal_id: u8,
+        sps: &SeqParameterSet,
+        picture: PictureReferences,
+    ) {
+        let index = picture.picture;
+        let starts = self.cvs.push_picture(unit_type);
+        if unit_type.is_irap() {
+            // Leading pictures may predict from the previous IRAP period, but no further.
+            let last_irap = self.last_irap;
+            self.pictures.retain(|_, p| p.index >= last_irap);
+            self.last_irap = index;
+        }
+        if starts || index == 0 {
+            self.pictures.clear();
+            self.check_sps(index, sps);
+            self.nesting = sps.sps_temporal_id_nesting;
+        }
+        if self.nesting {
+            for poc in &picture.references {
+                let Some(reference) = self.pictures.get(poc) else {
+                    continue;
+                };
+                let crossed = reference
+                    .lowest_following
+                    .filter(|&t| t < reference.temporal_id);
+                if let Some(lowest) = crossed.filter(|_| reference.temporal_id <= temporal_id) {
+                    self.violations.push(Violation::at(
+                        "nesting.reference",
+                        index,
+                        format!(
+                            "POC {} with TemporalId {} predicts from POC {} with TemporalId {}, \
+                             which precedes a picture with TemporalId {}",
+                            picture.poc, temporal_id, poc, reference.temporal_id, lowest
+                        ),
+                    ));
+                }
+            }
+        }
+        for p in self.pictures.values_mut() {
+            p.lowest_following = Some(
+                p.lowest_following
+                    .map_or(temporal_id, |t| t.min(temporal_id)),
+            );
+        }
+        self.pictures.insert(
+            picture.poc,
+            Picture {
+                index,
+                temporal_id,
+                lowest_following: None,
+            },
+        );
+    }
+
+    fn check_sps(&mut self, index: u64, sps: &SeqParameterSet) {
+        if sps.sps_temporal_id_nesting {
+            return;
+        }
+        if sps.sps_max_sub_layers_minus1 == 0 {
+            self.violations.push(Violation::at(
+                "nesting.single-layer",
+                index,
+                format!(
+                    "SPS {} has one sub-layer, but sps_temporal_id_nesting_flag is 0",
+                    sps.id().id()
+                ),
+            ));
+        }
+        if let Some(vps) = self
+            .ctx
+            .vps_by_id(sps.sps_video_parameter_set_id)
+            .filter(|vps| vps.vps_temporal_id_nesting_flag)
+        {
+            self.violations.push(Violation::at(
+                "nesting.vps",
+                index,
+                format!(
+                    "VPS {} sets vps_temporal_id_nesting_flag, but SPS {} doesn't set \
+                     sps_temporal_id_nesting_flag",
+                    vps.vps_video_parameter_set_id.id(),
+                    sps.id().id()
+                ),
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hex_literal::hex;
+
+    #[test]
+    fn references() {
+        // "Intinor HW encode 720x576p" SPS and PPS; an IDR slice; and P slices predicting from
+        // the previous POC, with TemporalId given.
+        let sps = hex!(
+            "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46
+             d1 2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00
+             0b b8 48"
+        );
+        let pps = hex!("44 01 c1 72 b4 62 40");
+        let check = |pictures: &[(u8, u8)]| {
+            let mut checker = NestingChecker::new();
+            checker.push_nal(&sps);
+            checker.push_nal(&pps);
+            checker.push_nal(&hex!("26 01 ac 9c"));
+            for &(lsb, temporal_id) in pictures {
+                let b2 = 0xd0 | (lsb >> 2);
+                let b3 = ((lsb & 3) << 6) | 0x2c;
+                checker.push_nal(&[0x02, temporal_id + 1, b2, b3, 0x27, 0x80]);
+            }
+            checker
+                .finish()
+                .into_iter()
+                .map(|v| (v.rule, v.picture))
+                .collect::<Vec<_>>()
+        };
+
+        // POC 2 predicts from POC 1 across POC 3 of a lower sub-layer, but not in the same one.
+        assert_eq!(
+            check(&[(1, 1), (3, 0), (2, 1)]),
+            [("nesting.reference", Some(3))]
+        );
+        assert_eq!(check(&[(1, 1), (3, 1), (2, 1)]), []);
+        // Predicting from sub-layer 0 across sub-layer 1 is fine too.
+        assert_eq!(check(&[(1, 0), (3, 1), (2, 1)]), []);
+    }
+}
@@ -0,0 +1,242 @@
+//! Detection of duplicated and reordered access units, as left behind by faulty UDP reordering.
+//!
+//! An [`OrderChecker`] remembers the slice segments of recent pictures to find repeats, and
+//! uses a [`ReferenceTracker`] for the picture order count (POC) of each picture and the
+//! references it uses.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+
+use crate::nal::{NalHeader, UnitType};
+use crate::validate::references::ReferenceTracker;
+
+/// The number of slice segments remembered to find duplicates.
+const WINDOW: usize = 256;
+
+/// The kinds of [`OrderAnomaly`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OrderAnomalyKind {
+    /// A picture whose first slice segment repeats that of a recent picture. Its other slice
+    /// segments aren't reported separately.
+    DuplicateAccessUnit,
+    /// A slice segment repeating a recent one, within a picture which isn't itself a duplicate.
+    DuplicateNal,
+    /// A picture with the POC of an earlier picture since the last IRAP picture, but different
+    /// content.
+    DuplicatePoc,
+    /// A picture received after a picture which uses it as a reference.
+    Reordered,
+    /// A leading (RASL or RADL) picture received after a trailing picture of the same IRAP
+    /// picture.
+    LeadingAfterTrailing,
+}
+
+/// A duplicated or reordered NAL unit or picture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderAnomaly {
+    pub kind: OrderAnomalyKind,
+    /// The index of the NAL unit, counting from 0.
+    pub nal_index: u64,
+    /// The index of the picture, in order of reception, not counting duplicated access units.
+    pub picture: u64,
+    /// The POC of the picture, for the kinds found from it.
+    pub poc: Option<i32>,
+}
+
+/// Checks NAL units one at a time, in order of reception.
+///
+/// ```
+/// use hevc_reader::validate::order::{OrderAnomalyKind, OrderChecker};
+/// let mut checker = OrderChecker::new();
+/// // An IDR slice segment, received twice.
+/// checker.push_nal(&[0x26, 0x01, 0xac, 0x9c]);
+/// checker.push_nal(&[0x26, 0x01, 0xac, 0x9c]);
+/// let anomalies = checker.finish();
+/// assert_eq!(anomalies[0].kind, OrderAnomalyKind::DuplicateAccessUnit);
+/// assert_eq!(anomalies[0].nal_index, 1);
+/// ```
+pub struct OrderChecker {
+    references: ReferenceTracker,
+    nal_index: u64,
+    pictures: u64,
+    /// Hashes of recent slice segments.
+    recent: VecDeque<u64>,
+    /// True while the slice segments of a duplicated access unit are arriving.
+    in_duplicate: bool,
+    /// The POCs received since the last IRAP picture.
+    pocs: HashSet<i32>,
+    /// The references found missing since the last IRAP picture, with the picture needing each.
+    missing: HashMap<i32, u64>,
+    trailing: bool,
+    anomalies: Vec<OrderAnomaly>,
+}
+impl Default for OrderChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl OrderChecker {
+    pub fn new() -> Self {
+        OrderChecker {
+            references: ReferenceTracker::new(),
+            nal_index: 0,
+            pictures: 0,
+            recent: VecDeque::with_capacity(WINDOW),
+            in_duplicate: false,
+            pocs: HashSet::new(),
+            missing: HashMap::new(),
+            trailing: false,
+            anomalies: vec![],
+        }
+    }
+
+    /// Adds a NAL unit (without start code or length prefix).
+    pub fn push_nal(&mut self, nal: &[u8]) {
+        let nal_index = self.nal_index;
+        self.nal_index += 1;
+        let Some(header) = nal
+            .first()
+            .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())
+        else {
+            return;
+        };
+        let unit_type = header.nal_unit_type();
+        if !unit_type.is_vcl() {
+            self.references.push_nal(nal);
+            return;
+        }
+        let first_slice_segment = nal.get(2).is_some_and(|b| b & 0x80 != 0);
+        let mut hasher = DefaultHasher::new();
+        nal.hash(&mut hasher);
+        let hash = hasher.finish();
+        let duplicate = self.recent.contains(&hash);
+        if self.recent.len() == WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(hash);
+        if first_slice_segment {
+            self.in_duplicate = duplicate;
+        }
+        if duplicate {
+            if first_slice_segment {
+                self.add(OrderAnomalyKind::DuplicateAccessUnit, nal_index, None);
+            } else if !self.in_duplicate {
+                self.add(OrderAnomalyKind::DuplicateNal, nal_index, None);
+            }
+            return;
+        }
+        if self.in_duplicate {
+            return;
+        }
+        if first_slice_segment {
+            self.pictures += 1;
+        }
+        let Some(picture) = self.references.push_nal(nal) else {
+            return;
+        };
+        let poc = Some(picture.poc);
+        if unit_type.is_irap() {
+            self.pocs.clear();
+            self.missing.clear();
+            self.trailing = false;
+        } else if matches!(
+            unit_type,
+            UnitType::SliceSegmentLayerTrailN
+                | UnitType::SliceSegmentLayerTrailR
+                | UnitType::SliceSegmentLayerTsaN
+                | UnitType::SliceSegmentLayerTsaR
+                | UnitType::SliceSegmentLayerStsaN
+                | UnitType::SliceSegmentLayerStsaR
+        ) {
+            self.trailing = true;
+        } else if self.trailing
+            && matches!(
+                unit_type,
+                UnitType::SliceSegmentLayerRadlN
+                    | UnitType::SliceSegmentLayerRadlR
+                    | UnitType::SliceSegmentLayerRaslN
+                    | UnitType::SliceSegmentLayerRaslR
+            )
+        {
+            self.add(OrderAnomalyKind::LeadingAfterTrailing, nal_index, poc);
+        }
+        if !self.pocs.insert(picture.poc) {
+            self.add(OrderAnomalyKind::DuplicatePoc, nal_index, poc);
+        } else if self.missing.remove(&picture.poc).is_some() {
+            self.add(OrderAnomalyKind::Reordered, nal_index, poc);
+        }
+        // A reference received earlier but dropped by a reordered picture isn't missing.
+        for &missing in picture.missing.iter().filter(|p| !self.pocs.contains(p)) {
+            self.missing.entry(missing).or_insert(picture.picture);
+        }
+    }
+
+    /// Adds a parameter set from outside the stream, e.g. from the `hvcC` box of an MP4 sample
+    /// entry, without counting it as a NAL unit.
+    pub fn add_param_set(&mut self, nal: &[u8]) {
+        self.references.push_nal(nal);
+    }
+
+    /// Adds the NAL units of an Annex B stream.
+    pub fn push_annexb(&mut self, data: &[u8]) {
+        for (_, nal) in crate::annexb::nal_units(data) {
+            self.push_nal(nal);
+        }
+    }
+
+    pub fn finish(self) -> Vec<OrderAnomaly> {
+        self.anomalies
+    }
+
+    fn add(&mut self, kind: OrderAnomalyKind, nal_index: u64, poc: Option<i32>) {
+        self.anomalies.push(OrderAnomaly {
+            kind,
+            nal_index,
+            picture: self.pictures.saturating_sub(1),
+            poc,
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hex_literal::hex;
+
+    #[test]
+    fn anomalies() {
+        // "Intinor HW encode 720x576p" SPS, whose one short-term RPS predicts from the previous
+        // picture, and its PPS.
+        let sps = hex!(
+            "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46
+             d1 2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00
+             0b b8 48"
+        );
+        let pps = hex!("44 01 c1 72 b4 62 40");
+        let idr = hex!("26 01 ac 9c");
+        // P slices with POC LSB 1, 2 and 3.
+        let p1 = hex!("02 01 d0 6c 27 80");
+        let p2 = hex!("02 01 d0 ac 27 80");
+        let p3 = hex!("02 01 d0 ec 27 80");
+        // POC 1 again, with different slice data.
+        let p1b = hex!("02 01 d0 6c 27 80 ff");
+        let mut checker = OrderChecker::new();
+        for nal in [&sps[..], &pps, &idr, &p1, &p1, &p3, &p2, &p1b] {
+            checker.push_nal(nal);
+        }
+        let anomalies: Vec<_> = checker
+            .finish()
+            .iter()
+            .map(|a| (a.kind, a.nal_index, a.picture, a.poc))
+            .collect();
+        assert_eq!(
+            anomalies,
+            [
+                (OrderAnomalyKind::DuplicateAccessUnit, 4, 1, None),
+                (OrderAnomalyKind::Reordered, 6, 3, Some(2)),
+                (OrderAnomalyKind::DuplicatePoc, 7, 4, Some(1)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,299 @@
+//! Cross-checks of the VPSs, SPSs and PPSs of a [`Context`] against each other.
+//!
+//! Each parameter set may parse on its own and still be unusable with the ones it references:
+//! these can be missing, or the PPS can use values which are out of range for the chroma format
+//! or bit depth of its SPS. Decoders tend to fail on such streams late, at the first slice.
+
+use crate::nal::pps::PicParameterSet;
+use crate::nal::sps::SeqParameterSet;
+use crate::validate::Violation;
+use crate::Context;
+
+/// Checks the parameter sets of `ctx` against those they reference. The rules are:
+///
+/// * `param-sets.undefined-vps`: an SPS references a VPS which isn't in the context.
+/// * `param-sets.undefined-sps`: a PPS references an SPS which isn't in the context.
+/// * `param-sets.sub-layers`: an SPS has more sub-layers than its VPS.
+/// * `param-sets.qp-range`: `init_qp_minus26` is out of range for the luma bit depth.
+/// * `param-sets.block-size`: a PPS depth or block size exceeds what the SPS coding and
+///   transform block sizes allow.
+/// * `param-sets.chroma-format`: a PPS enables a tool which the chroma format doesn't allow.
+/// * `param-sets.bit-depth`: a PPS value is out of range for the bit depths of the SPS.
+///
+/// ```
+/// use hevc_reader::nal::pps::PicParameterSet;
+/// use hevc_reader::nal::sps::SeqParameterSet;
+/// use hevc_reader::rbsp::BitReader;
+/// use hevc_reader::validate::param_sets;
+/// use hevc_reader::Context;
+/// let mut ctx = Context::new();
+/// // "Intinor HW encode 720x576p" SPS, whose VPS is missing, and its PPS.
+/// ctx.put_seq_param_set(
+///     SeqParameterSet::from_bits(BitReader::from_nal(&[
+///         0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03, 0x00,
+///         0x00, 0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46,
+///         0xd1, 0x2e, 0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10,
+///         0x00, 0x00, 0x03, 0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00,
+///         0x0b, 0xb8, 0x48,
+///     ]))
+///     .unwrap(),
+/// );
+/// let pps = PicParameterSet::from_bits(
+///     &ctx,
+///     BitReader::from_nal(&[0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40]),
+/// )
+/// .unwrap();
+/// ctx.put_pic_param_set(pps);
+/// let violations = param_sets::check(&ctx);
+/// assert_eq!(violations.len(), 1);
+/// assert_eq!(violations[0].rule, "param-sets.undefined-vps");
+/// ```
+pub fn check(ctx: &Context) -> Vec<Violation> {
+    let mut violations = vec![];
+    for sps in ctx.sps() {
+        match ctx.get(sps.sps_video_parameter_set_id) {
+            None => violations.push(Violation::new(
+                "param-sets.undefined-vps",
+                format!(
+                    "SPS {} references VPS {}, which isn't defined",
+                    sps.id(),
+                    sps.sps_video_parameter_set_id
+                ),
+            )),
+            Some(vps) if sps.sps_max_sub_layers_minus1 > vps.vps_max_sub_layers_minus1 => {
+                violations.push(Violation::new(
+                    "param-sets.sub-layers",
+                    format!(
+                        "SPS {} has {} sub-layers, but VPS {} has {}",
+                        sps.id(),
+                        sps.sps_max_sub_layers_minus1 + 1,
+                        vps.vps_video_parameter_set_id,
+                        vps.vps_max_sub_layers_minus1 + 1
+                    ),
+                ))
+            }
+            Some(_) => {}
+        }
+    }
+    for pps in ctx.pps() {
+        match ctx.get(pps.seq_parameter_set_id) {
+            None => violations.push(Violation::new(
+                "param-sets.undefined-sps",
+                format!(
+                    "PPS {} references SPS {}, which isn't defined",
+                    pps.id(),
+                    pps.seq_parameter_set_id
+                ),
+            )),
+            Some(sps) => check_pps(pps, sps, &mut violations),
+        }
+    }
+    violations
+}
+
+/// Checks the fields of a PPS whose ranges depend on its SPS.
+fn check_pps(pps: &PicParameterSet, sps: &SeqParameterSet, violations: &mut Vec<Violation>) {
+    let Ok(derived) = sps.derived() else {
+        return;
+    };
+    let mut violation = |rule, message: String| {
+        violations.push(Violation::new(
+            rule,
+            format!("PPS {} with SPS {}: {}", pps.id(), sps.id(), message),
+        ));
+    };
+    let min_init_qp = -26 - derived.qp_bd_offset_y as i32;
+    if !(min_init_qp..=25).contains(&pps.init_qp_minus26) {
+        violation(
+            "param-sets.qp-range",
+            format!(
+                "init_qp_minus26 is {}, outside {}..=25 for a luma bit depth of {}",
+                pps.init_qp_minus26, min_init_qp, derived.bit_depth_y
+            ),
+        );
+    }
+    let max_depth = sps.log2_diff_max_min_luma_coding_block_size;
+    if let Some(depth) = pps.diff_cu_qp_delta_depth.filter(|&d| d > max_depth) {
+        violation(
+            "param-sets.block-size",
+            format!(
+                "diff_cu_qp_delta_depth is {}, above log2_diff_max_min_luma_coding_block_size \
+                 {}",
+                depth, max_depth
+            ),
+        );
+    }
+
+    let chroma_array_type = sps.chroma_array_type();
+    let extension = pps.pps_extension.as_ref();
+    if let Some(range) = extension.and_then(|e| e.pps_range_extension.as_ref()) {
+        let max_size = derived.max_tb_log2_size_y.saturating_sub(2);
+        if let Some(size) = range
+            .log2_max_transform_skip_block_size_minus2
+            .filter(|&s| s > max_size)
+        {
+            violation(
+                "param-sets.block-size",
+                format!(
+                    "log2_max_transform_skip_block_size_minus2 is {}, above MaxTbLog2SizeY - 2 \
+                     = {}",
+                    size, max_size
+                ),
+            );
+        }
+        if range.cross_component_prediction_enabled_flag && chroma_array_type != 3 {
+            violation(
+                "param-sets.chroma-format",
+                format!(
+                    "cross_component_prediction_enabled_flag is set with ChromaArrayType {}",
+                    chroma_array_type
+                ),
+            );
+        }
+        if let Some(list) = &range.chroma_qp_offset_list {
+            if chroma_array_type == 0 {
+                violation(
+                    "param-sets.chroma-format",
+                    "chroma_qp_offset_list_enabled_flag is set with ChromaArrayType 0".to_owned(),
+                );
+            }
+            if list.diff_cu_chroma_qp_offset_depth > max_depth {
+                violation(
+                    "param-sets.block-size",
+                    format!(
+                        "diff_cu_chroma_qp_offset_depth is {}, above \
+                         log2_diff_max_min_luma_coding_block_size {}",
+                        list.diff_cu_chroma_qp_offset_depth, max_depth
+                    ),
+                );
+            }
+        }
+        for (name, scale, bit_depth) in [
+            (
+                "log2_sao_offset_scale_luma",
+                range.log2_sao_offset_scale_luma,
+                derived.bit_depth_y,
+            ),
+            (
+                "log2_sao_offset_scale_chroma",
+                range.log2_sao_offset_scale_chroma,
+                derived.bit_depth_c,
+            ),
+        ] {
+            let max_scale = bit_depth.saturating_sub(10);
+            if scale > max_scale {
+                violation(
+                    "param-sets.bit-depth",
+                    format!(
+                        "{} is {}, above {} for a bit depth of {}",
+                        name, scale, max_scale, bit_depth
+                    ),
+                );
+            }
+        }
+    }
+    if let Some(scc) = extension.and_then(|e| e.pps_scc_extension.as_ref()) {
+        if scc.residual_adaptive_colour_transform.is_some() && chroma_array_type != 3 {
+            violation(
+                "param-sets.chroma-format",
+                format!(
+                    "residual_adaptive_colour_transform_enabled_flag is set with \
+                     ChromaArrayType {}",
+                    chroma_array_type
+                ),
+            );
+        }
+        if let Some(init) = &scc.pps_palette_predictor_initializers {
+            if init.monochrome_palette_flag != (chroma_array_type == 0) {
+                violation(
+                    "param-sets.chroma-format",
+                    format!(
+                        "monochrome_palette_flag is {} with ChromaArrayType {}",
+                        u8::from(init.monochrome_palette_flag),
+                        chroma_array_type
+                    ),
+                );
+            }
+            if init.luma_bit_depth_entry_minus8 != sps.bit_depth_luma_minus8
+                || (!init.monochrome_palette_flag
+                    && init.chroma_bit_depth_entry_minus8 != sps.bit_depth_chroma_minus8)
+            {
+                violation(
+                    "param-sets.bit-depth",
+                    format!(
+                        "the palette predictor initializers have bit depths {} and {}, but the \
+                         SPS {} and {}",
+                        init.luma_bit_depth_entry_minus8 + 8,
+                        init.chroma_bit_depth_entry_minus8 + 8,
+                        derived.bit_depth_y,
+                        derived.bit_depth_c
+                    ),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::nal::pps::{PpsExtension, PpsRangeExtension};
+    use crate::rbsp::BitReader;
+    use hex_literal::hex;
+
+    #[test]
+    fn pps_ranges() {
+        // "Intinor HW encode 720x576p" SPS, 8 bit 4:2:0, and its PPS.
+        let sps = hex!(
+            "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46
+             d1 2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00
+             0b b8 48"
+        );
+        let mut ctx = Context::new();
+        ctx.put_seq_param_set(SeqParameterSet::from_bits(BitReader::from_nal(&sps)).unwrap());
+        let pps =
+            PicParameterSet::from_bits(&ctx, BitReader::from_nal(&hex!("44 01 c1 72 b4 62 40")))
+                .unwrap();
+        let rules = |pps: &PicParameterSet| {
+            let mut ctx = ctx.clone();
+            ctx.put_pic_param_set(pps.clone());
+            check(&ctx)
+                .into_iter()
+                .map(|v| v.rule)
+                .filter(|&r| r != "param-sets.undefined-vps")
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(rules(&pps), Vec::<&str>::new());
+
+        let mut bad = pps.clone();
+        bad.init_qp_minus26 = -27;
+        bad.pps_extension = Some(PpsExtension {
+            pps_range_extension: Some(PpsRangeExtension {
+                log2_max_transform_skip_block_size_minus2: None,
+                cross_component_prediction_enabled_flag: true,
+                chroma_qp_offset_list: None,
+                log2_sao_offset_scale_luma: 1,
+                log2_sao_offset_scale_chroma: 0,
+            }),
+            pps_multilayer_extension_flag: false,
+            pps_3d_extension_flag: false,
+            pps_scc_extension_flag: false,
+            pps_scc_extension: None,
+            pps_extension_4bits: 0,
+            extension_data: None,
+        });
+        assert_eq!(
+            rules(&bad),
+            [
+                "param-sets.qp-range",
+                "param-sets.chroma-format",
+                "param-sets.bit-depth"
+            ]
+        );
+
+        // A PPS whose SPS is gone.
+        let mut orphan = pps;
+        orphan.seq_parameter_set_id = 3.try_into().unwrap();
+        assert_eq!(rules(&orphan), ["param-sets.undefined-sps"]);
+    }
+}
@@ -0,0 +1,526 @@
+//! Detection of lost reference pictures, for monitoring RTP or SRT ingest.
+//!
+//! Each slice names the pictures it may predict from in its reference picture set (RPS), as
+//! differences of picture order count (POC). A [`ReferenceTracker`] derives the POC of every
+//! picture received (clause 8.3.1) and the RPS of every picture (clause 8.3.2), keeping the
+//! reference pictures as a decoder would. A picture whose RPS names a reference it uses which
+//! was never received has lost a reference, and can't be decoded; neither can the pictures
+//! predicted from it.
+//!
+//! Entries of the RPS which only later pictures use (`RefPicSetStFoll` and `RefPicSetLtFoll`)
+//! aren't checked, as they may be missing by design after sub-layer switching.
+
+use crate::hvcc::SampleError;
+use crate::nal::slice::{SliceHeader, SliceSegmentHeader};
+use crate::nal::sps::ShortTermRefPicSet;
+use crate::nal::{CvsTracker, NalHeader, UnitType};
+use crate::rbsp::BitReader;
+use crate::Context;
+
+/// The references of one picture, as returned by [`ReferenceTracker::push_nal`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PictureReferences {
+    /// The index of the picture, in decoding order.
+    pub picture: u64,
+    /// `PicOrderCntVal`.
+    pub poc: i32,
+    /// The POCs of the references used by the picture (`RefPicSetStCurrBefore`,
+    /// `RefPicSetStCurrAfter` and `RefPicSetLtCurr`), including missing ones.
+    pub references: Vec<i32>,
+    /// The POCs of references used by the picture which were never received.
+    pub missing: Vec<i32>,
+    /// False if a reference is missing, or is itself undecodable.
+    pub decodable: bool,
+}
+
+/// A reference picture which was never received.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LostPicture {
+    pub poc: i32,
+    /// The index of the first picture found to need it.
+    pub needed_by: u64,
+}
+
+/// What a [`ReferenceTracker`] found.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReferenceReport {
+    /// The number of pictures whose slice headers could be parsed.
+    pub pictures: u64,
+    pub lost: Vec<LostPicture>,
+    /// The pictures which can't be decoded, in decoding order.
+    pub undecodable: Vec<PictureReferences>,
+}
+
+/// A picture marked as used for reference.
+struct RefPicture {
+    poc: i32,
+    decodable: bool,
+    /// False for a placeholder standing in for a lost picture.
+    received: bool,
+}
+
+/// An entry of an RPS.
+#[derive(Clone, Copy)]
+enum RpsEntry {
+    Poc(i32),
+    /// A long-term entry given by the least significant bits of its POC only.
+    PocLsb(u32),
+}
+
+/// Follows the reference pictures of a stream, fed one NAL unit at a time in decoding order.
+///
+/// RASL pictures associated with a CRA picture which starts decoding, e.g. the first picture
+/// of the stream, are skipped as a decoder skips them: their references are missing by design.
+///
+/// ```
+/// use hevc_reader::validate::references::ReferenceTracker;
+/// let mut tracker = ReferenceTracker::new();
+/// tracker.push_annexb(&[
+///     // "Intinor HW encode 720x576p" SPS.
+///     0, 0, 1, 0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03,
+///     0x00, 0x00, 0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46,
+///     0xd1, 0x2e, 0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10, 0x00,
+///     0x00, 0x03, 0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00, 0x0b, 0xb8,
+///     0x48,
+///     // Its PPS.
+///     0, 0, 1, 0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40,
+///     // An IDR slice, and a P slice with POC 2, predicted from POC 1, which is missing.
+///     0, 0, 1, 0x26, 0x01, 0xac, 0x9c,
+///     0, 0, 1, 0x02, 0x01, 0xd0, 0xac, 0x27, 0x80,
+/// ]);
+/// let report = tracker.finish();
+/// assert_eq!(report.lost[0].poc, 1);
+/// assert_eq!(report.undecodable[0].picture, 1);
+/// ```
+pub struct ReferenceTracker {
+    ctx: Context,
+    pictures: u64,
+    dpb: Vec<RefPicture>,
+    /// The POC of the previous picture with `TemporalId` 0 which is not a RASL, RADL or
+    /// sub-layer non-reference picture.
+    prev_tid0_poc: i32,
+    /// A picture starting a coded video sequence starts decoding, and its RASL pictures are
+    /// skipped.
+    cvs: CvsTracker,
+    skip_rasl: bool,
+    /// Whether a picture starting decoding has been seen; until then nothing can be decoded.
+    started: bool,
+    report: ReferenceReport,
+}
+impl Default for ReferenceTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl ReferenceTracker {
+    pub fn new() -> Self {
+        ReferenceTracker {
+            ctx: Context::new(),
+            pictures: 0,
+            dpb: vec![],
+            prev_tid0_poc: 0,
+            cvs: CvsTracker::default(),
+            skip_rasl: false,
+            started: false,
+            report: ReferenceReport::default(),
+        }
+    }
+
+    /// Adds a NAL unit (without start code or length prefix), returning the references of the
+    /// picture it starts, if any. Slice segments which can't be parsed, e.g. for lack of their
+    /// parameter sets, are ignored.
+    pub fn push_nal(&mut self, nal: &[u8]) -> Option<PictureReferences> {
+        let header = nal
+            .first()
+            .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())?;
+        if header.nuh_layer_id().ok()? != 0 {
+            return None;
+        }
+        let unit_type = header.nal_unit_type();
+        match unit_type {
+            UnitType::VideoParameterSet | UnitType::SeqParameterSet | UnitType::PicParameterSet => {
+                crate::cenc::track_param_set(&mut self.ctx, nal);
+                None
+            }
+            UnitType::EndOfSeq | UnitType::EndOfStream => {
+                self.cvs.end_sequence();
+                None
+            }
+            t if t.is_vcl() => {
+                let mut r = BitReader::from_nal(nal);
+                let (slice, sps, _) =
+                    SliceSegmentHeader::from_bits(&self.ctx, &mut r, header).ok()?;
+                if !slice.first_slice_segment_in_pic_flag {
+                    return None;
+                }
+                let temporal_id = header.temporal_id();
+                let header = slice.slice_header.as_ref()?;
+                let max_lsb = 1i32 << sps.log2_max_pic_order_cnt_lsb();
+                let st = header
+                    .short_term_ref_pic_set
+                    .as_ref()
+                    .and_then(|s| s.get(sps))
+                    .cloned();
+                let picture =
+                    self.push_picture(unit_type, temporal_id, header, max_lsb, st.as_ref())?;
+                if !picture.decodable {
+                    self.report.undecodable.push(picture.clone());
+                }
+                Some(picture)
+            }
+            _ => None,
+        }
+    }
+
+    /// Adds the NAL units of an Annex B stream.
+    pub fn push_annexb(&mut self, data: &[u8]) {
+        for (_, nal) in crate::annexb::nal_units(data) {
+            self.push_nal(nal);
+        }
+    }
+
+    /// Adds the NAL units of a length-prefixed sample.
+    pub fn push_sample(&mut self, sample: &[u8], length_size: usize) -> Result<(), SampleError> {
+        for nal in crate::hvcc::nal_units(sample, length_size)? {
+            self.push_nal(nal?);
+        }
+        Ok(())
+    }
+
+    /// The POCs of the pictures marked as used for reference after the last picture pushed,
+    /// including that picture.
+    pub fn reference_pocs(&self) -> impl Iterator<Item = i32> + '_ {
+        self.dpb.iter().map(|p| p.poc)
+    }
+
+    pub fn finish(self) -> ReferenceReport {
+        self.report
+    }
+
+    fn push_picture(
+        &mut self,
+        unit_type: UnitType,
+        temporal_id: u8,
+        header: &SliceHeader,
+        max_lsb: i32,
+        st: Option<&ShortTermRefPicSet>,
+    ) -> Option<PictureReferences> {
+        let index = self.pictures;
+        self.pictures += 1;
+        self.report.pictures += 1;
+        let id = unit_type.id();
+        let rasl = matches!(
+            unit_type,
+            UnitType::SliceSegmentLayerRaslN | UnitType::SliceSegmentLayerRaslR
+        );
+        let starts_decoding = self.cvs.push_picture(unit_type);
+        if unit_type.is_irap() {
+            self.skip_rasl = starts_decoding;
+            self.started |= starts_decoding;
+        }
+        if rasl && self.skip_rasl {
+            return None;
+        }
+
+        // Clause 8.3.1.
+        let lsb = header.slice_pic_order_cnt_lsb as i32;
+        let msb = if starts_decoding {
+            0
+        } else {
+            let prev_lsb = self.prev_tid0_poc & (max_lsb - 1);
+            let prev_msb = self.prev_tid0_poc - prev_lsb;
+            if lsb < prev_lsb && prev_lsb - lsb >= max_lsb / 2 {
+                prev_msb + max_lsb
+            } else if lsb > prev_lsb && lsb - prev_lsb > max_lsb / 2 {
+                prev_msb - max_lsb
+            } else {
+                prev_msb
+            }
+        };
+        let poc = msb + lsb;
+        let sub_layer_non_reference = id <= 14 && id.is_multiple_of(2);
+        if temporal_id == 0 && !rasl && !(6..=7).contains(&id) && !sub_layer_non_reference {
+            self.prev_tid0_poc = poc;
+        }
+
+        // Clause 8.3.2: the RPS entries this picture uses, and those kept for later pictures.
+        let mut curr = vec![];
+        let mut foll = vec![];
+        if let Some(st) = st {
+            for r in st.negative_pics_s0.iter().chain(&st.positive_pics_s1) {
+                let list = if r.used_by_curr_pic_flag {
+                    &mut curr
+                } else {
+                    &mut foll
+                };
+                list.push(RpsEntry::Poc(poc + r.delta_poc));
+            }
+        }
+        if let Some(lt) = &header.long_term_ref_pics {
+            let mut delta_poc_msb_cycle_lt = 0i32;
+            for (i, p) in lt.pics.iter().enumerate() {
+                if i == 0 || i == lt.num_long_term_sps as usize {
+                    delta_poc_msb_cycle_lt = 0;
+                }
+                // A POC outside i32 can't be that of a picture; skip such a picture as one
+                // whose slice header can't be parsed.
+                let delta = i32::try_from(p.delta_poc_msb_cycle_lt.unwrap_or(0)).ok()?;
+                delta_poc_msb_cycle_lt = delta_poc_msb_cycle_lt.checked_add(delta)?;
+                let entry = if p.delta_poc_msb_cycle_lt.is_some() {
+                    RpsEntry::Poc(
+                        delta_poc_msb_cycle_lt
+                            .checked_mul(max_lsb)
+                            .and_then(|d| poc.checked_sub(d))
+                            .and_then(|d| d.checked_sub(lsb - p.poc_lsb_lt as i32))?,
+                    )
+                } else {
+                    RpsEntry::PocLsb(p.poc_lsb_lt)
+                };
+                let list = if p.used_by_curr_pic_lt {
+                    &mut curr
+                } else {
+                    &mut foll
+                };
+                list.push(entry);
+            }
+        }
+
+        let mut picture = PictureReferences {
+            picture: index,
+            poc,
+            references: vec![],
+            missing: vec![],
+            decodable: self.started,
+        };
+        if starts_decoding || unit_type.is_idr() {
+            // References of a CRA or BLA picture starting decoding are generated as
+            // unavailable, not lost.
+            self.dpb.clear();
+        } else {
+            let dpb = std::mem::take(&mut self.dpb);
+            let (mut kept, mut rest): (Vec<_>, Vec<_>) = dpb
+                .into_iter()
+                .partition(|p| curr.iter().any(|&e| matches(e, p.poc, max_lsb)));
+            for &entry in &curr {
+                if let Some(r) = kept.iter().find(|p| matches(entry, p.poc, max_lsb)) {
+                    picture.references.push(r.poc);
+                    picture.decodable &= r.decodable;
+                    if !r.received {
+                        picture.missing.push(r.poc);
+                    }
+                    continue;
+                }
+                let lost = match entry {
+                    RpsEntry::Poc(p) => p,
+                    RpsEntry::PocLsb(p_lsb) => {
+                        let p = poc - lsb + p_lsb as i32;
+                        if p > poc {
+                            p - max_lsb
+                        } else {
+                            p
+                        }
+                    }
+                };
+                picture.decodable = false;
+                picture.references.push(lost);
+                picture.missing.push(lost);
+                if self.started {
+                    self.report.lost.push(LostPicture {
+                        poc: lost,
+                        needed_by: index,
+                    });
+                }
+                kept.push(RefPicture {
+                    poc: lost,
+                    decodable: false,
+                    received: false,
+                });
+            }
+            rest.retain(|p| foll.iter().any(|&e| matches(e, p.poc, max_lsb)));
+            kept.append(&mut rest);
+            self.dpb = kept;
+        }
+        self.dpb.push(RefPicture {
+            poc,
+            decodable: picture.decodable,
+            received: true,
+        });
+        Some(picture)
+    }
+}
+
+fn matches(entry: RpsEntry, poc: i32, max_lsb: i32) -> bool {
+    match entry {
+        RpsEntry::Poc(p) => p == poc,
+        RpsEntry::PocLsb(lsb) => poc & (max_lsb - 1) == lsb as i32,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::nal::sps::SeqParameterSet;
+    use crate::rbsp::{
+        decode_nal, encode_nal, BitWriter, SyntaxElement, SyntaxVisitor, VisitingReader,
+    };
+    use hex_literal::hex;
+
+    /// Replaces the bits of `nal` from the start of the syntax element `from` to the end of
+    /// `to`, as found by `parse`, with those written by `write`.
+    fn rewrite(
+        nal: &[u8],
+        parse: impl FnOnce(VisitingReader<'_, BitReader<&[u8]>, dyn SyntaxVisitor + '_>),
+        from: &str,
+        to: &str,
+        write: impl FnOnce(&mut BitWriter),
+    ) -> Vec<u8> {
+        let rbsp = decode_nal(nal).unwrap();
+        let (mut start, mut end) = (0, 0);
+        let mut visitor = |e: &SyntaxElement| {
+            if e.name == from {
+                start = e.bits.start as usize;
+            }
+            if e.name == to {
+                end = e.bits.end as usize;
+            }
+        };
+        parse(VisitingReader::new(
+            BitReader::new(&*rbsp),
+            &mut visitor as &mut dyn SyntaxVisitor,
+        ));
+        let mut w = BitWriter::new();
+        w.write_bits_from(&rbsp, 0, start);
+        write(&mut w);
+        w.write_bits_from(&rbsp, end, rbsp.len() * 8);
+        encode_nal(&nal[..2], &w.into_bytes())
+    }
+
+    #[test]
+    fn lost_reference() {
+        // "Intinor HW encode 720x576p" SPS, whose one short-term RPS predicts from the previous
+        // picture, and its PPS.
+        let sps = hex!(
+            "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46
+             d1 2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00
+             0b b8 48"
+        );
+        let pps = hex!("44 01 c1 72 b4 62 40");
+        let idr = hex!("26 01 ac 9c");
+        // P slices with POC LSB 1, 2, 4 and 5; the one with 3 is lost.
+        let p: Vec<[u8; 6]> = [0x6c, 0xac, 0x2c, 0x6c]
+            .iter()
+            .zip([0xd0, 0xd0, 0xd1, 0xd1])
+            .map(|(&b1, b0)| [0x02, 0x01, b0, b1, 0x27, 0x80])
+            .collect();
+        let mut tracker = ReferenceTracker::new();
+        let mut pictures = vec![];
+        for nal in [
+            &sps[..],
+            &pps,
+            &idr,
+            &p[0],
+            &p[1],
+            &p[2],
+            &p[3],
+            &idr,
+            &p[0],
+        ] {
+            pictures.extend(tracker.push_nal(nal));
+        }
+        assert_eq!(
+            pictures
+                .iter()
+                .map(|p| (p.poc, p.decodable))
+                .collect::<Vec<_>>(),
+            [
+                (0, true),
+                (1, true),
+                (2, true),
+                (4, false),
+                (5, false),
+                (0, true),
+                (1, true)
+            ]
+        );
+        let report = tracker.finish();
+        assert_eq!(report.pictures, 7);
+        assert_eq!(
+            report.lost,
+            [LostPicture {
+                poc: 3,
+                needed_by: 3
+            }]
+        );
+        let undecodable: Vec<_> = report
+            .undecodable
+            .iter()
+            .map(|p| (p.picture, p.missing.clone()))
+            .collect();
+        assert_eq!(undecodable, [(3, vec![3]), (4, vec![])]);
+    }
+
+    #[test]
+    fn long_term_overflow() {
+        let sps = hex!(
+            "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46
+             d1 2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00
+             0b b8 48"
+        );
+        let pps = hex!("44 01 c1 72 b4 62 40");
+        let idr = hex!("26 01 ac 9c");
+        // A P slice with POC LSB 1.
+        let p = hex!("02 01 d0 6c 27 80");
+
+        // Long-term references allowed, with none in the SPS.
+        let flag = "long_term_ref_pics_present_flag";
+        let lt_sps = rewrite(
+            &sps,
+            |r| {
+                SeqParameterSet::from_bits(r).unwrap();
+            },
+            flag,
+            flag,
+            |w| {
+                w.write_bool(true);
+                w.write_ue(0);
+            },
+        );
+        // The P slice with one long-term reference used, whose POC LSB is 0, found by parsing
+        // with the original SPS.
+        let mut ctx = Context::new();
+        ctx.put_param_set_nal(&sps);
+        ctx.put_param_set_nal(&pps);
+        let long_term = |delta_poc_msb_cycle_lt| {
+            let flag = "short_term_ref_pic_set_sps_flag";
+            rewrite(
+                &p,
+                |mut r| {
+                    let header = NalHeader::new(p[0], Some(p[1])).unwrap();
+                    SliceSegmentHeader::from_bits(&ctx, &mut r, header).unwrap();
+                },
+                flag,
+                flag,
+                |w| {
+                    w.write_bool(true);
+                    // num_long_term_pics, poc_lsb_lt, used_by_curr_pic_lt_flag and
+                    // delta_poc_msb_present_flag.
+                    w.write_ue(1);
+                    w.write_u32(5, 0);
+                    w.write_bool(true);
+                    w.write_bool(true);
+                    w.write_ue(delta_poc_msb_cycle_lt);
+                },
+            )
+        };
+
+        let mut tracker = ReferenceTracker::new();
+        for nal in [&lt_sps[..], &pps, &idr] {
+            tracker.push_nal(nal);
+        }
+        let picture = tracker.push_nal(&long_term(1)).unwrap();
+        assert_eq!(picture.references, [0, -32]);
+        // POCs past i32 don't panic, and the picture is skipped.
+        assert_eq!(tracker.push_nal(&long_term(1 << 26)), None);
+        assert_eq!(tracker.push_nal(&long_term(u32::MAX - 1)), None);
+    }
+}
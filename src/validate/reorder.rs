@@ -0,0 +1,234 @@
+//! Comparison of the signalled picture reordering with the reordering actually used.
+//!
+//! `sps_max_num_reorder_pics` tells a player how many pictures it may have to hold back before
+//! output. Signalled too small, a player outputting early has to stall or drop pictures when a
+//! picture turns up which should have been output before them; signalled larger than needed,
+//! every picture waits longer than it has to, adding latency.
+//!
+//! The reorder depth of a picture is the number of pictures which precede it in decoding order
+//! and follow it in output order, within its coded video sequence; the signalled value must be
+//! at least the largest of these (clause 7.4.3.2.1).
+
+use crate::nal::slice::SliceSegmentHeader;
+use crate::nal::{CvsTracker, NalHeader, UnitType};
+use crate::rbsp::BitReader;
+use crate::validate::references::ReferenceTracker;
+use crate::validate::Violation;
+use crate::Context;
+
+/// The reordering of one coded video sequence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReorderDepth {
+    /// The index of its first picture, in decoding order.
+    pub first_picture: u64,
+    pub pictures: u64,
+    /// `sps_max_num_reorder_pics` of the highest sub-layer, if the SPS could be parsed.
+    pub signalled: Option<u32>,
+    /// The largest reorder depth of any picture.
+    pub observed: u32,
+    /// The first picture with the largest reorder depth, if any is reordered.
+    pub deepest_picture: Option<u64>,
+}
+impl ReorderDepth {
+    /// `reorder.too-small` if the signalled value is less than the observed one, or
+    /// `reorder.too-large` if more.
+    pub fn violation(&self) -> Option<Violation> {
+        let signalled = self.signalled?;
+        if signalled < self.observed {
+            Some(Violation::at(
+                "reorder.too-small",
+                self.deepest_picture.unwrap_or(self.first_picture),
+                format!(
+                    "{} pictures precede this one in decoding order and follow it in output \
+                     order, but sps_max_num_reorder_pics is {}",
+                    self.observed, signalled
+                ),
+            ))
+        } else if signalled > self.observed {
+            Some(Violation::at(
+                "reorder.too-large",
+                self.first_picture,
+                format!(
+                    "sps_max_num_reorder_pics is {}, but at most {} pictures are reordered in \
+                     {} pictures",
+                    signalled, self.observed, self.pictures
+                ),
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+/// Measures the reorder depth of each coded video sequence, fed one NAL unit at a time in
+/// decoding order.
+///
+/// RASL pictures skipped at the start of decoding (see [`ReferenceTracker`]) aren't counted.
+///
+/// ```
+/// use hevc_reader::validate::reorder::ReorderChecker;
+/// let mut checker = ReorderChecker::new();
+/// checker.push_annexb(&[
+///     // "Intinor HW encode 720x576p" SPS, with sps_max_num_reorder_pics 0.
+///     0, 0, 1, 0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03,
+///     0x00, 0x00, 0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46,
+///     0xd1, 0x2e, 0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10, 0x00,
+///     0x00, 0x03, 0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00, 0x0b, 0xb8,
+///     0x48,
+///     // Its PPS.
+///     0, 0, 1, 0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40,
+///     // An IDR slice, then P slices with POC 2 and 1.
+///     0, 0, 1, 0x26, 0x01, 0xac, 0x9c,
+///     0, 0, 1, 0x02, 0x01, 0xd0, 0xac, 0x27, 0x80,
+///     0, 0, 1, 0x02, 0x01, 0xd0, 0x6c, 0x27, 0x80,
+/// ]);
+/// let depths = checker.finish();
+/// assert_eq!(depths[0].observed, 1);
+/// assert_eq!(depths[0].violation().unwrap().rule, "reorder.too-small");
+/// ```
+#[derive(Default)]
+pub struct ReorderChecker {
+    ctx: Context,
+    references: ReferenceTracker,
+    cvs: CvsTracker,
+    /// The POCs of the pictures of the current coded video sequence, in ascending order.
+    pocs: Vec<i32>,
+    sequences: Vec<ReorderDepth>,
+}
+impl ReorderChecker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a NAL unit (without start code or length prefix). NAL units which can't be parsed
+    /// are ignored.
+    pub fn push_nal(&mut self, nal: &[u8]) {
+        let Some(header) = nal
+            .first()
+            .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())
+        else {
+            return;
+        };
+        if !matches!(header.nuh_layer_id(), Ok(0)) {
+            return;
+        }
+        let unit_type = header.nal_unit_type();
+        let picture = self.references.push_nal(nal);
+        match unit_type {
+            UnitType::VideoParameterSet | UnitType::SeqParameterSet | UnitType::PicParameterSet => {
+                crate::cenc::track_param_set(&mut self.ctx, nal);
+            }
+            UnitType::EndOfSeq | UnitType::EndOfStream => self.cvs.end_sequence(),
+            t if t.is_vcl() => {
+                let Some(picture) = picture else {
+                    return;
+                };
+                let mut r = BitReader::from_nal(nal);
+                let Ok((_, sps, _)) = SliceSegmentHeader::from_bits(&self.ctx, &mut r, header)
+                else {
+                    return;
+                };
+                let starts = self.cvs.push_picture(unit_type);
+                if starts || self.sequences.is_empty() {
+                    self.pocs.clear();
+                    self.sequences.push(ReorderDepth {
+                        first_picture: picture.picture,
+                        pictures: 0,
+                        signalled: sps
+                            .sub_layering_ordering_info
+                            .last()
+                            .map(|l| l.sps_max_num_reorder_pics),
+                        observed: 0,
+                        deepest_picture: None,
+                    });
+                }
+                let sequence = self.sequences.last_mut().expect("pushed above");
+                sequence.pictures += 1;
+                let at = self.pocs.partition_point(|&p| p <= picture.poc);
+                let depth = (self.pocs.len() - at) as u32;
+                if depth > sequence.observed {
+                    sequence.observed = depth;
+                    sequence.deepest_picture = Some(picture.picture);
+                }
+                self.pocs.insert(at, picture.poc);
+            }
+            _ => {}
+        }
+    }
+
+    /// Adds the NAL units of an Annex B stream.
+    pub fn push_annexb(&mut self, data: &[u8]) {
+        for (_, nal) in crate::annexb::nal_units(data) {
+            self.push_nal(nal);
+        }
+    }
+
+    /// The reordering of each coded video sequence, in decoding order.
+    pub fn finish(self) -> Vec<ReorderDepth> {
+        self.sequences
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hex_literal::hex;
+
+    #[test]
+    fn depth() {
+        // "Intinor HW encode 720x576p" SPS and PPS; an IDR slice; and P slices with POC LSB 1
+        // to 4.
+        let sps = hex!(
+            "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46
+             d1 2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00
+             0b b8 48"
+        );
+        let pps = hex!("44 01 c1 72 b4 62 40");
+        let idr = hex!("26 01 ac 9c");
+        let p = [
+            hex!("02 01 d0 6c 27 80"),
+            hex!("02 01 d0 ac 27 80"),
+            hex!("02 01 d0 ec 27 80"),
+            hex!("02 01 d1 2c 27 80"),
+        ];
+        let check = |pocs: &[usize]| {
+            let mut checker = ReorderChecker::new();
+            checker.push_nal(&sps);
+            checker.push_nal(&pps);
+            checker.push_nal(&idr);
+            for &poc in pocs {
+                checker.push_nal(&p[poc - 1]);
+            }
+            checker.finish()
+        };
+
+        // In output order.
+        let depths = check(&[1, 2, 3, 4]);
+        assert_eq!(depths.len(), 1);
+        assert_eq!((depths[0].signalled, depths[0].observed), (Some(0), 0));
+        assert_eq!(depths[0].violation(), None);
+
+        // POC 1 follows 3 and 4 in decoding order, as a hierarchical B structure would.
+        let depths = check(&[4, 2, 1, 3]);
+        assert_eq!(depths[0].pictures, 5);
+        assert_eq!(depths[0].observed, 2);
+        assert_eq!(depths[0].deepest_picture, Some(3));
+        let violation = depths[0].violation().unwrap();
+        assert_eq!(violation.rule, "reorder.too-small");
+        assert_eq!(violation.picture, Some(3));
+
+        // A second IDR picture starts over.
+        let mut checker = ReorderChecker::new();
+        for nal in [&sps[..], &pps, &idr, &p[1], &p[0], &idr, &p[0]] {
+            checker.push_nal(nal);
+        }
+        let depths = checker.finish();
+        assert_eq!(
+            depths
+                .iter()
+                .map(|d| (d.first_picture, d.pictures, d.observed))
+                .collect::<Vec<_>>(),
+            [(0, 3, 1), (3, 2, 0)]
+        );
+    }
+}
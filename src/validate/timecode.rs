@@ -0,0 +1,407 @@
+//! Continuity checking of the `time_code()` SEI message, for broadcast ingest.
+//!
+//! A [`TimecodeChecker`] puts the pictures of each IRAP period in output order, and checks that
+//! the time code advances by one picture's worth of frame counts from each picture to the next,
+//! given the frame rate. Drop-frame counting (`counting_type` 4) skips frame numbers 0 and 1
+//! (0 to 3 at twice the rate) at the start of each minute not divisible by ten, and is taken
+//! into account.
+
+use crate::nal::sei::time_code::{ClockTimestamp, TimeCode, COUNTING_TYPE_DROP_FRAME};
+use crate::nal::sei::{HeaderType, SeiReader};
+use crate::nal::slice::SliceSegmentHeader;
+use crate::nal::{NalHeader, UnitType};
+use crate::rbsp::{decode_nal, BitReader};
+use crate::validate::references::ReferenceTracker;
+use crate::validate::Violation;
+use crate::{Context, FrameRate};
+
+/// A picture awaiting output.
+struct Picture {
+    /// The index of the picture, in decoding order.
+    index: u64,
+    poc: i32,
+    rate: Option<FrameRate>,
+    time_code: Option<ClockTimestamp>,
+}
+
+/// A time code against which the following ones are checked.
+struct Anchor {
+    /// The time code as a number of frame counts since midnight.
+    count: i64,
+    /// The pictures output since, counting the anchor itself.
+    pictures: i64,
+    nominal: i64,
+    counting_type: u8,
+    units_field_based_flag: bool,
+    field_pictures: bool,
+}
+
+/// The last time code seen in output order.
+struct Previous {
+    hours: u8,
+    minutes: u8,
+    seconds: u8,
+    label: String,
+}
+
+/// Checks time codes, fed one NAL unit at a time in decoding order.
+///
+/// The frame rate is taken from the SPS VUI or VPS timing info (see [`Context::frame_rate`])
+/// unless given with [`TimecodeChecker::with_frame_rate`]; time codes of pictures without a
+/// frame rate aren't checked. A time code with `discontinuity_flag` set starts over.
+///
+/// ```
+/// use hevc_reader::validate::timecode::TimecodeChecker;
+/// use hevc_reader::{FrameRate, FrameRateSource};
+/// let mut checker = TimecodeChecker::with_frame_rate(FrameRate {
+///     num_units_in_tick: 1001,
+///     time_scale: 30000,
+///     source: FrameRateSource::Vui,
+///     field_pictures: false,
+/// });
+/// checker.push_annexb(&[
+///     // "Intinor HW encode 720x576p" SPS.
+///     0, 0, 1, 0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03,
+///     0x00, 0x00, 0x03, 0x00, 0x5d, 0xa0, 0x05, 0xc2, 0x00, 0x90, 0x71, 0x3e, 0x87, 0xee, 0x46,
+///     0xd1, 0x2e, 0x3f, 0xf0, 0x04, 0x00, 0x02, 0xd0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10, 0x00,
+///     0x00, 0x03, 0x01, 0x96, 0x00, 0x00, 0x03, 0x00, 0xe0, 0x00, 0x49, 0x3e, 0x00, 0x0b, 0xb8,
+///     0x48,
+///     // Its PPS.
+///     0, 0, 1, 0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40,
+///     // A time code of 01:02:00;00 with drop-frame counting, which has no frame 0.
+///     0, 0, 1, 0x4e, 0x01, 0x88, 0x06, 0x62, 0x40, 0x00, 0x01, 0x04, 0x10, 0x80,
+///     // An IDR slice.
+///     0, 0, 1, 0x26, 0x01, 0xac, 0x9c,
+/// ]);
+/// let violations = checker.finish();
+/// assert_eq!(violations[0].rule, "timecode.dropped-frame-number");
+/// assert_eq!(violations[0].message, "01:02:00;00 is dropped");
+/// ```
+#[derive(Default)]
+pub struct TimecodeChecker {
+    ctx: Context,
+    references: ReferenceTracker,
+    frame_rate: Option<FrameRate>,
+    /// A time code from a prefix SEI NAL unit, for the next picture.
+    pending: Option<ClockTimestamp>,
+    /// The pictures since the last IRAP picture, in decoding order.
+    pictures: Vec<Picture>,
+    anchor: Option<Anchor>,
+    previous: Option<Previous>,
+    violations: Vec<Violation>,
+}
+impl TimecodeChecker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks against the given frame rate rather than the one signalled in the stream.
+    pub fn with_frame_rate(frame_rate: FrameRate) -> Self {
+        TimecodeChecker {
+            frame_rate: Some(frame_rate),
+            ..Self::default()
+        }
+    }
+
+    /// Adds a NAL unit (without start code or length prefix). NAL units which can't be parsed
+    /// are ignored.
+    pub fn push_nal(&mut self, nal: &[u8]) {
+        let Some(header) = nal
+            .first()
+            .and_then(|&b| NalHeader::new(b, nal.get(1).copied()).ok())
+        else {
+            return;
+        };
+        if !matches!(header.nuh_layer_id(), Ok(0)) {
+            return;
+        }
+        let unit_type = header.nal_unit_type();
+        match unit_type {
+            UnitType::VideoParameterSet | UnitType::SeqParameterSet | UnitType::PicParameterSet => {
+                crate::cenc::track_param_set(&mut self.ctx, nal);
+                self.references.push_nal(nal);
+            }
+            UnitType::PrefixSEI => {
+                let Ok(rbsp) = decode_nal(nal) else {
+                    return;
+                };
+                let mut reader = SeiReader::from_rbsp_bytes(&rbsp);
+                while let Ok(Some(msg)) = reader.next() {
+                    if msg.payload_type == HeaderType::TimeCode {
+                        if let Ok(time_code) = TimeCode::read(&msg) {
+                            self.pending = time_code.first().copied();
+                        }
+                    }
+                }
+            }
+            t if t.is_vcl() => {
+                let mut r = BitReader::from_nal(nal);
+                let rate = match SliceSegmentHeader::from_bits(&self.ctx, &mut r, header) {
+                    Ok((slice, sps, _)) if slice.first_slice_segment_in_pic_flag => {
+                        self.frame_rate.or_else(|| self.ctx.frame_rate(sps, None))
+                    }
+                    _ => return,
+                };
+                let time_code = self.pending.take();
+                // Pictures skipped as undecodable, e.g. RASL pictures of a CRA picture starting
+                // decoding, aren't output.
+                let Some(picture) = self.references.push_nal(nal) else {
+                    return;
+                };
+                if unit_type.is_irap() {
+                    self.flush();
+                }
+                self.pictures.push(Picture {
+                    index: picture.picture,
+                    poc: picture.poc,
+                    rate,
+                    time_code,
+                });
+            }
+            _ => {
+                self.references.push_nal(nal);
+            }
+        }
+    }
+
+    /// Adds the NAL units of an Annex B stream.
+    pub fn push_annexb(&mut self, data: &[u8]) {
+        for (_, nal) in crate::annexb::nal_units(data) {
+            self.push_nal(nal);
+        }
+    }
+
+    pub fn finish(mut self) -> Vec<Violation> {
+        self.flush();
+        self.violations
+    }
+
+    /// Checks the buffered pictures in output order.
+    fn flush(&mut self) {
+        let mut pictures = std::mem::take(&mut self.pictures);
+        pictures.sort_by_key(|p| p.poc);
+        for picture in pictures {
+            self.check(picture);
+        }
+    }
+
+    fn check(&mut self, picture: Picture) {
+        if let Some(anchor) = &mut self.anchor {
+            anchor.pictures += 1;
+        }
+        let (Some(ts), Some(rate)) = (picture.time_code, picture.rate) else {
+            return;
+        };
+        let units_per_second = if ts.units_field_based_flag {
+            rate.fps() * 2.0
+        } else {
+            rate.fps()
+        };
+        let nominal = (units_per_second - 1e-3).ceil() as i64;
+        if nominal <= 0 {
+            return;
+        }
+        // Absent values are those of the previous time code in output order.
+        let previous = self.previous.take();
+        let hours = ts.hours_value.or(previous.as_ref().map(|p| p.hours));
+        let minutes = ts.minutes_value.or(previous.as_ref().map(|p| p.minutes));
+        let seconds = ts.seconds_value.or(previous.as_ref().map(|p| p.seconds));
+        let (hours, minutes, seconds) = (
+            hours.unwrap_or(0),
+            minutes.unwrap_or(0),
+            seconds.unwrap_or(0),
+        );
+        let drop_frame = ts.counting_type == COUNTING_TYPE_DROP_FRAME;
+        let label = format!(
+            "{:02}:{:02}:{:02}{}{:02}",
+            hours,
+            minutes,
+            seconds,
+            if drop_frame { ';' } else { ':' },
+            ts.n_frames
+        );
+        self.previous = Some(Previous {
+            hours,
+            minutes,
+            seconds,
+            label: label.clone(),
+        });
+        if i64::from(ts.n_frames) >= nominal || seconds > 59 || minutes > 59 || hours > 23 {
+            self.violations.push(Violation::at(
+                "timecode.frames-out-of-range",
+                picture.index,
+                format!("{} is out of range at {} frames per second", label, nominal),
+            ));
+            self.anchor = None;
+            return;
+        }
+        let drop = if drop_frame { 2 * nominal / 30 } else { 0 };
+        if drop_frame && seconds == 0 && minutes % 10 != 0 && i64::from(ts.n_frames) < drop {
+            self.violations.push(Violation::at(
+                "timecode.dropped-frame-number",
+                picture.index,
+                format!("{} is dropped", label),
+            ));
+        }
+        let total_minutes = i64::from(hours) * 60 + i64::from(minutes);
+        let count = (total_minutes * 60 + i64::from(seconds)) * nominal + i64::from(ts.n_frames)
+            - drop * (total_minutes - total_minutes / 10);
+        let anchor = Anchor {
+            count,
+            pictures: 0,
+            nominal,
+            counting_type: ts.counting_type,
+            units_field_based_flag: ts.units_field_based_flag,
+            field_pictures: rate.field_pictures,
+        };
+        let Some(previous_anchor) = self.anchor.as_ref().filter(|a| {
+            !ts.discontinuity_flag
+                && a.nominal == anchor.nominal
+                && a.counting_type == anchor.counting_type
+                && a.units_field_based_flag == anchor.units_field_based_flag
+                && a.field_pictures == anchor.field_pictures
+        }) else {
+            self.anchor = Some(anchor);
+            return;
+        };
+        let day = 24 * 60 * 60 * nominal - drop * (24 * 60 - 24 * 6);
+        let actual = (count - previous_anchor.count).rem_euclid(day);
+        // A frame count may span two field pictures, so allow either neighbour of a fraction.
+        let num = previous_anchor.pictures * if ts.units_field_based_flag { 2 } else { 1 };
+        let den = if rate.field_pictures { 2 } else { 1 };
+        let (floor, ceil) = (num / den, (num + den - 1) / den);
+        let skipped = ts.cnt_dropped_flag && actual > ceil;
+        if actual != floor && actual != ceil && !skipped {
+            let previous = previous.map_or_else(String::new, |p| p.label);
+            self.violations.push(Violation::at(
+                "timecode.discontinuity",
+                picture.index,
+                format!("{} follows {}", label, previous),
+            ));
+        } else if floor != ceil {
+            // Measure from the start of the count.
+            return;
+        }
+        self.anchor = Some(anchor);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rbsp::encode_nal;
+    use crate::FrameRateSource;
+    use hex_literal::hex;
+
+    fn sei(counting_type: u8, minutes: u8, seconds: u8, n_frames: u16) -> Vec<u8> {
+        let time_code = TimeCode {
+            clock_timestamps: vec![Some(ClockTimestamp {
+                units_field_based_flag: false,
+                counting_type,
+                full_timestamp_flag: true,
+                discontinuity_flag: false,
+                cnt_dropped_flag: false,
+                n_frames,
+                seconds_value: Some(seconds),
+                minutes_value: Some(minutes),
+                hours_value: Some(10),
+                time_offset_length: 0,
+                time_offset_value: 0,
+            })],
+        };
+        let payload = time_code.to_payload();
+        let mut rbsp = vec![];
+        time_code.to_message(&payload).write_to(&mut rbsp);
+        rbsp.push(0x80);
+        encode_nal(&[0x4e, 0x01], &rbsp)
+    }
+
+    #[test]
+    fn continuity() {
+        // "Intinor HW encode 720x576p" SPS and PPS; an IDR slice; and P slices with POC LSB 1
+        // to 4.
+        let sps = hex!(
+            "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46
+             d1 2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00
+             0b b8 48"
+        );
+        let pps = hex!("44 01 c1 72 b4 62 40");
+        let pictures = [
+            &hex!("26 01 ac 9c")[..],
+            &hex!("02 01 d0 6c 27 80"),
+            &hex!("02 01 d0 ac 27 80"),
+            &hex!("02 01 d0 ec 27 80"),
+            &hex!("02 01 d1 2c 27 80"),
+        ];
+        let check = |rate: (u32, u32), time_codes: [(u8, u8, u8, u16); 5]| {
+            let mut checker = TimecodeChecker::with_frame_rate(FrameRate {
+                num_units_in_tick: rate.0,
+                time_scale: rate.1,
+                source: FrameRateSource::Vui,
+                field_pictures: false,
+            });
+            checker.push_nal(&sps);
+            checker.push_nal(&pps);
+            for (picture, (counting_type, minutes, seconds, n_frames)) in
+                pictures.iter().zip(time_codes)
+            {
+                checker.push_nal(&sei(counting_type, minutes, seconds, n_frames));
+                checker.push_nal(picture);
+            }
+            checker
+                .finish()
+                .into_iter()
+                .map(|v| (v.rule, v.picture, v.message))
+                .collect::<Vec<_>>()
+        };
+
+        // 25 frames per second, with two frames missing.
+        assert_eq!(
+            check(
+                (1, 25),
+                [
+                    (0, 0, 0, 23),
+                    (0, 0, 0, 24),
+                    (0, 0, 1, 0),
+                    (0, 0, 1, 3),
+                    (0, 0, 1, 4)
+                ]
+            ),
+            [(
+                "timecode.discontinuity",
+                Some(3),
+                "10:00:01:03 follows 10:00:01:00".to_owned()
+            )]
+        );
+
+        // 29.97 frames per second with drop-frame counting, across the start of a minute.
+        let df = COUNTING_TYPE_DROP_FRAME;
+        assert_eq!(
+            check(
+                (1001, 30000),
+                [
+                    (df, 0, 59, 28),
+                    (df, 0, 59, 29),
+                    (df, 1, 0, 2),
+                    (df, 1, 0, 3),
+                    (df, 1, 0, 4)
+                ]
+            ),
+            []
+        );
+        assert_eq!(
+            check(
+                (1001, 30000),
+                [
+                    (df, 9, 59, 29),
+                    (df, 10, 0, 0),
+                    (df, 10, 0, 1),
+                    (df, 10, 0, 2),
+                    (df, 10, 0, 4)
+                ]
+            )
+            .len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,252 @@
+//! Constraints of the Blu-ray Disc Association's Ultra HD Blu-ray format on the primary HEVC
+//! video stream.
+
+use super::{
+    check_every_picture, check_tier_level, StreamSummary, Violation, BT2020, TRANSFER_HLG,
+    TRANSFER_PQ,
+};
+use crate::nal::sei::HeaderType;
+use crate::nal::sps::{Level, Profile, Tier};
+
+/// The constraints checked by [`check`]. The [`Default`] is the UHD Blu-ray format itself.
+#[derive(Debug, Clone)]
+pub struct UhdBdConfig {
+    pub max_tier: Tier,
+    pub max_level: Level,
+    /// The allowed cropped picture sizes.
+    pub resolutions: Vec<(u32, u32)>,
+    /// The allowed frame rates, in frames per second.
+    pub frame_rates: Vec<f64>,
+    /// The longest allowed time from one IRAP picture to the next, in seconds.
+    pub max_gop_duration: f64,
+    /// The most slice segments allowed in one picture.
+    pub max_slice_segments: u32,
+    /// The allowed `(colour_primaries, transfer_characteristics, matrix_coeffs)`.
+    pub colour: Vec<(u8, u8, u8)>,
+}
+impl Default for UhdBdConfig {
+    /// Main 10 at up to Level 5.1 High tier, 3840x2160 or 1920x1080 at film, 25, 30, 50 and
+    /// 60 Hz rates, GOPs of up to a second, and BT.709, BT.2020, HDR10 or HLG colour.
+    fn default() -> Self {
+        UhdBdConfig {
+            max_tier: Tier::High,
+            max_level: Level::L5_1,
+            resolutions: vec![(3840, 2160), (1920, 1080)],
+            frame_rates: vec![
+                24000.0 / 1001.0,
+                24.0,
+                25.0,
+                30000.0 / 1001.0,
+                50.0,
+                60000.0 / 1001.0,
+                60.0,
+            ],
+            max_gop_duration: 1.0,
+            max_slice_segments: 200,
+            colour: vec![
+                (1, 1, 1),
+                (BT2020, 14, BT2020),
+                (BT2020, TRANSFER_PQ, BT2020),
+                (BT2020, TRANSFER_HLG, BT2020),
+            ],
+        }
+    }
+}
+
+/// Checks the stream against `config`. Rules are named `uhdbd.*`.
+///
+/// HDR10 streams must carry mastering display colour volume and content light level SEI in
+/// every IRAP access unit, and Dolby Vision or HDR10+ metadata is only allowed on an HDR10 base.
+///
+/// ```
+/// use hevc_reader::validate::{uhd_bd, StreamScanner};
+/// let mut scanner = StreamScanner::new();
+/// // A PPS, an IDR slice segment and a Dolby Vision RPU NAL unit.
+/// scanner.push_nal(&[0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40]);
+/// scanner.push_nal(&[0x26, 0x01, 0xac, 0x9c]);
+/// scanner.push_nal(&[0x7c, 0x01, 0x19, 0x08]);
+/// let violations = uhd_bd::check(&scanner.finish(), &Default::default());
+/// assert!(violations.iter().any(|v| v.rule == "uhdbd.dolby-vision"));
+/// ```
+pub fn check(summary: &StreamSummary, config: &UhdBdConfig) -> Vec<Violation> {
+    let mut violations = vec![];
+    let mut pq = false;
+    for sps in &summary.seq_param_sets {
+        let profile = sps.general_profile();
+        if profile != Profile::Main10 {
+            violations.push(Violation::new(
+                "uhdbd.profile",
+                format!("profile {:?} isn't Main 10", profile),
+            ));
+        }
+        match sps.pixel_dimensions() {
+            Ok(size) if config.resolutions.contains(&size) => {}
+            size => violations.push(Violation::new(
+                "uhdbd.resolution",
+                format!("picture size {:?} isn't allowed", size),
+            )),
+        }
+        let colour = sps
+            .vui_parameters
+            .as_ref()
+            .and_then(|v| v.video_signal_type.as_ref())
+            .and_then(|s| s.colour_description.as_ref())
+            .map(|c| {
+                (
+                    c.colour_primaries,
+                    c.transfer_characteristics,
+                    c.matrix_coeffs,
+                )
+            });
+        match colour {
+            Some(c) if config.colour.contains(&c) => pq |= c.1 == TRANSFER_PQ,
+            c => violations.push(Violation::new(
+                "uhdbd.colour",
+                format!(
+                    "colour primaries, transfer characteristics and matrix coefficients {:?} \
+                     aren't allowed",
+                    c
+                ),
+            )),
+        }
+    }
+    check_tier_level(
+        summary,
+        &mut violations,
+        "uhdbd.tier-level",
+        config.max_tier,
+        config.max_level,
+    );
+    match summary.frame_rate {
+        Some(rate)
+            if config
+                .frame_rates
+                .iter()
+                .any(|&r| (r - rate.fps()).abs() < 0.001) => {}
+        rate => violations.push(Violation::new(
+            "uhdbd.frame-rate",
+            format!("frame rate {:?} isn't allowed", rate.map(|r| r.fps())),
+        )),
+    }
+    check_gops(summary, config, &mut violations);
+    check_every_picture(summary, &mut violations, "uhdbd.slices", |p| {
+        p.slice_segments <= config.max_slice_segments
+    });
+    if pq {
+        check_every_picture(summary, &mut violations, "uhdbd.hdr10", |p| {
+            !p.unit_type.is_irap()
+                || (p.sei.contains(&HeaderType::MasteringDisplayColourVolume)
+                    && p.sei.contains(&HeaderType::ContentLightLevelInfo))
+        });
+    }
+    if summary.dolby_vision && !pq {
+        violations.push(Violation::new(
+            "uhdbd.dolby-vision",
+            "Dolby Vision metadata on a base layer which isn't HDR10".to_owned(),
+        ));
+    }
+    if summary.hdr10_plus && !pq {
+        violations.push(Violation::new(
+            "uhdbd.hdr10-plus",
+            "HDR10+ metadata in a stream which isn't HDR10".to_owned(),
+        ));
+    }
+    violations
+}
+
+fn check_gops(summary: &StreamSummary, config: &UhdBdConfig, violations: &mut Vec<Violation>) {
+    let Some(first) = summary.pictures.first() else {
+        return;
+    };
+    if !first.unit_type.is_irap() {
+        violations.push(Violation::at(
+            "uhdbd.gop",
+            0,
+            "the stream doesn't start with an IRAP picture".to_owned(),
+        ));
+    }
+    let Some(rate) = summary.frame_rate else {
+        return;
+    };
+    let max_pictures = (config.max_gop_duration * rate.pictures_per_second()).round() as usize;
+    let iraps: Vec<_> = summary
+        .pictures
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| p.unit_type.is_irap())
+        .map(|(i, _)| i)
+        .chain([summary.pictures.len()])
+        .collect();
+    for gop in iraps.windows(2) {
+        if gop[1] - gop[0] > max_pictures {
+            violations.push(Violation::at(
+                "uhdbd.gop",
+                gop[0] as u64,
+                format!(
+                    "GOP of {} pictures is longer than {} seconds",
+                    gop[1] - gop[0],
+                    config.max_gop_duration
+                ),
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::validate::StreamScanner;
+    use hex_literal::hex;
+
+    // "Intinor HW encode 720x576p" SPS: Main profile, 25 Hz.
+    const SPS: [u8; 59] = hex!(
+        "42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71 3e 87 ee 46 d1
+         2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0 00 49 3e 00 0b
+         b8 48"
+    );
+    const PPS: [u8; 7] = hex!("44 01 c1 72 b4 62 40");
+    const CRA: [u8; 4] = hex!("2a 01 ac 9c");
+    const TRAIL: [u8; 6] = hex!("02 01 d0 6c 27 80");
+
+    #[test]
+    fn gops() {
+        let mut scanner = StreamScanner::new();
+        scanner.add_param_set(&SPS);
+        scanner.add_param_set(&PPS);
+        // GOPs of 1 and 1.2 seconds.
+        for gop in [25, 30] {
+            scanner.push_nal(&CRA);
+            for _ in 1..gop {
+                scanner.push_nal(&TRAIL);
+            }
+        }
+        let summary = scanner.finish();
+        let violations = check(&summary, &UhdBdConfig::default());
+        assert_eq!(
+            violations
+                .iter()
+                .map(|v| (v.rule, v.picture))
+                .collect::<Vec<_>>(),
+            [
+                ("uhdbd.profile", None),
+                ("uhdbd.resolution", None),
+                ("uhdbd.colour", None),
+                ("uhdbd.gop", Some(25)),
+            ]
+        );
+    }
+    #[test]
+    fn no_pictures() {
+        let mut scanner = StreamScanner::new();
+        scanner.add_param_set(&SPS);
+        scanner.add_param_set(&PPS);
+        // Levels below 4 have no high tier, so nothing would fit.
+        let config = UhdBdConfig {
+            max_tier: Tier::High,
+            max_level: Level::L3_1,
+            ..UhdBdConfig::default()
+        };
+        let violations = check(&scanner.finish(), &config);
+        assert!(violations.iter().all(|v| v.rule != "uhdbd.tier-level"));
+    }
+}
@@ -0,0 +1,212 @@
+//! JavaScript bindings built with [wasm-bindgen](https://rustwasm.github.io/wasm-bindgen/), for
+//! stream inspection tools running in the browser. Requires the `wasm` feature, and a cdylib
+//! build, which the crate doesn't make by default:
+//!
+//! ```text
+//! $ cargo rustc --lib --release --target wasm32-unknown-unknown --features wasm \
+//!     --crate-type cdylib
+//! $ wasm-bindgen --target web --out-dir pkg \
+//!     target/wasm32-unknown-unknown/release/hevc_reader.wasm
+//! ```
+//!
+//! The functions take the bytes of an Annex B stream or a NAL unit as a `Uint8Array`, and report
+//! failures by throwing an `Error`.
+
+use wasm_bindgen::prelude::*;
+
+use crate::annexb;
+use crate::nal::pps::PicParameterSet;
+use crate::nal::sei::SeiReader;
+use crate::nal::sps::SeqParameterSet;
+use crate::nal::vps::VideoParameterSet;
+use crate::nal::{Nal, RefNal, UnitType};
+use crate::rbsp::decode_nal;
+use crate::sample_entry::{SampleEntry, SampleEntryConfig};
+use crate::Context;
+
+fn error<E: std::fmt::Debug>(e: E) -> JsError {
+    JsError::new(&format!("{:?}", e))
+}
+
+/// The position and type of a NAL unit within an Annex B stream.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NalUnit {
+    /// The byte offset just past the start code.
+    pub offset: usize,
+    /// The length, excluding trailing zero bytes.
+    pub length: usize,
+    #[wasm_bindgen(js_name = nalUnitType)]
+    pub nal_unit_type: u8,
+}
+
+/// Splits an Annex B stream into its NAL units, as [`annexb::nal_units`]. NAL units with a
+/// bad header have type 255.
+#[wasm_bindgen(js_name = nalUnits)]
+pub fn nal_units(data: &[u8]) -> Vec<NalUnit> {
+    annexb::nal_units(data)
+        .map(|(offset, nal)| NalUnit {
+            offset,
+            length: nal.len(),
+            nal_unit_type: RefNal::new(nal, &[], true)
+                .header()
+                .map_or(255, |h| h.nal_unit_type().id()),
+        })
+        .collect()
+}
+
+/// The fields of an SPS an inspection tool usually shows.
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpsInfo {
+    /// The cropped picture size.
+    pub width: u32,
+    pub height: u32,
+    #[wasm_bindgen(js_name = profileIdc)]
+    pub profile_idc: u8,
+    #[wasm_bindgen(js_name = highTier)]
+    pub high_tier: bool,
+    #[wasm_bindgen(js_name = levelIdc)]
+    pub level_idc: u8,
+    #[wasm_bindgen(js_name = chromaFormatIdc)]
+    pub chroma_format_idc: u32,
+    #[wasm_bindgen(js_name = bitDepthLuma)]
+    pub bit_depth_luma: u32,
+    #[wasm_bindgen(js_name = bitDepthChroma)]
+    pub bit_depth_chroma: u32,
+    /// The VUI timing, if present.
+    pub fps: Option<f64>,
+    /// The RFC 6381 `codecs` parameter for an `hvc1` sample entry.
+    pub codecs: String,
+}
+
+/// Parses an SPS NAL unit, including its two byte header.
+#[wasm_bindgen(js_name = parseSps)]
+pub fn parse_sps(nal: &[u8]) -> Result<SpsInfo, JsError> {
+    let sps = SeqParameterSet::from_bits(RefNal::new(nal, &[], true).rbsp_bits()).map_err(error)?;
+    let (width, height) = sps.pixel_dimensions().map_err(error)?;
+    let profile = sps.general_layer_profile();
+    Ok(SpsInfo {
+        width,
+        height,
+        profile_idc: profile.profile_idc,
+        high_tier: profile.tier_flag,
+        level_idc: sps.profile_tier_level.general_level_idc,
+        chroma_format_idc: sps.chroma_info.chroma_format.id(),
+        bit_depth_luma: sps.bit_depth_luma_minus8 + 8,
+        bit_depth_chroma: sps.bit_depth_chroma_minus8 + 8,
+        fps: sps.fps(),
+        codecs: sps.codecs_string("hvc1"),
+    })
+}
+
+/// One message of an SEI NAL unit.
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeiPayload {
+    #[wasm_bindgen(js_name = payloadType)]
+    pub payload_type: u32,
+    /// The payload, with emulation prevention removed.
+    pub payload: Vec<u8>,
+}
+
+/// Parses the messages of a prefix or suffix SEI NAL unit, including its two byte header.
+#[wasm_bindgen(js_name = parseSei)]
+pub fn parse_sei(nal: &[u8]) -> Result<Vec<SeiPayload>, JsError> {
+    let rbsp = decode_nal(nal).map_err(error)?;
+    let mut reader = SeiReader::from_rbsp_bytes(&rbsp);
+    let mut messages = vec![];
+    while let Some(msg) = reader.next().map_err(error)? {
+        messages.push(SeiPayload {
+            payload_type: msg.payload_type.id(),
+            payload: msg.payload.to_vec(),
+        });
+    }
+    Ok(messages)
+}
+
+/// Builds the `HEVCDecoderConfigurationRecord` of an `hvcC` box from the first of each
+/// parameter set found in an Annex B stream, for NAL unit lengths of `length_size` bytes.
+#[wasm_bindgen(js_name = hvccFromAnnexB)]
+pub fn hvcc_from_annexb(data: &[u8], length_size: usize) -> Result<Vec<u8>, JsError> {
+    let mut ctx = Context::new();
+    let mut nals = vec![];
+    for (_, nal) in annexb::nal_units(data) {
+        let r = RefNal::new(nal, &[], true);
+        let Ok(header) = r.header() else {
+            continue;
+        };
+        match header.nal_unit_type() {
+            UnitType::VideoParameterSet => {
+                let vps = VideoParameterSet::from_bits(r.rbsp_bits()).map_err(error)?;
+                if ctx.vps_by_id(vps.vps_video_parameter_set_id).is_some() {
+                    continue;
+                }
+                ctx.put_video_param_set(vps);
+            }
+            UnitType::SeqParameterSet => {
+                let sps = SeqParameterSet::from_bits(r.rbsp_bits()).map_err(error)?;
+                if ctx.sps_by_id(sps.id()).is_some() {
+                    continue;
+                }
+                ctx.put_seq_param_set(sps);
+            }
+            UnitType::PicParameterSet => {
+                let pps = PicParameterSet::from_bits(&ctx, r.rbsp_bits()).map_err(error)?;
+                if ctx.pps_by_id(pps.pic_parameter_set_id).is_some() {
+                    continue;
+                }
+                ctx.put_pic_param_set(pps);
+            }
+            _ => continue,
+        }
+        nals.push(nal);
+    }
+    let config = SampleEntryConfig {
+        length_size,
+        ..Default::default()
+    };
+    let entry = SampleEntry::new(&ctx, &nals, &config).map_err(error)?;
+    Ok(entry.hvcc)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hex_literal::hex;
+
+    // "Intinor HW encode 720x576p" SPS and its PPS, then an IDR slice segment.
+    const STREAM: [u8; 84] = hex!(
+        "00 00 00 01 42 01 01 01 60 00 00 03 00 b0 00 00 03 00 00 03 00 5d a0 05 c2 00 90 71
+         3e 87 ee 46 d1 2e 3f f0 04 00 02 d0 10 00 00 03 00 10 00 00 03 01 96 00 00 03 00 e0
+         00 49 3e 00 0b b8 48 00 00 01 44 01 c1 72 b4 62 40 00 00 01 26 01 ac 9c ff ff 00 00"
+    );
+
+    #[test]
+    fn inspect() {
+        let nals = nal_units(&STREAM);
+        assert_eq!(
+            nals.iter().map(|n| n.nal_unit_type).collect::<Vec<_>>(),
+            [33, 34, 19]
+        );
+        assert_eq!((nals[2].offset, nals[2].length), (76, 6));
+        let sps = parse_sps(&STREAM[nals[0].offset..][..nals[0].length]).unwrap();
+        assert_eq!((sps.width, sps.height), (720, 576));
+        assert_eq!(sps.codecs, "hvc1.1.6.L93.B0");
+        assert_eq!(sps.fps, Some(25.0));
+
+        let hvcc = hvcc_from_annexb(&STREAM, 4).unwrap();
+        assert_eq!(hvcc[..2], [1, 1]);
+        // Two arrays, the SPS and the PPS.
+        assert_eq!(hvcc[22], 2);
+
+        let sei = parse_sei(&hex!("4e 01 06 01 c4 80")).unwrap();
+        assert_eq!(
+            sei,
+            [SeiPayload {
+                payload_type: 6,
+                payload: vec![0xc4]
+            }]
+        );
+    }
+}
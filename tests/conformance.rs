@@ -0,0 +1,152 @@
+//! Parses the JCT-VC conformance bitstreams (ITU-T H.265.1) to find syntax the parser gets
+//! wrong. The bitstreams aren't distributed with the crate, so this is ignored by default; run
+//! it against a directory of the unzipped conformance packages with:
+//!
+//! ```text
+//! HEVC_CONFORMANCE_DIR=/path/to/bitstreams cargo test --test conformance -- --ignored
+//! ```
+//!
+//! Every `.bit` and `.bin` file below the directory is parsed down to the slice segment headers.
+
+#![cfg(all(feature = "sei", feature = "slice"))]
+
+use std::path::{Path, PathBuf};
+
+use hevc_reader::annexb::AnnexBReader;
+use hevc_reader::nal::NalHeader;
+use hevc_reader::push::parse::{NalParser, ParseDepth, ParsedNal};
+use hevc_reader::Context;
+
+/// What was seen while parsing one bitstream.
+#[derive(Default)]
+struct Summary {
+    nal_units: usize,
+    failures: Vec<String>,
+    bit_depth_luma: Option<u32>,
+    tiles_enabled: bool,
+    entropy_coding_sync_enabled: bool,
+}
+
+fn parse(data: &[u8]) -> Summary {
+    let mut summary = Summary::default();
+    let handler = |_: &Context, header: NalHeader, parsed: ParsedNal<'_>| {
+        let i = summary.nal_units;
+        summary.nal_units += 1;
+        // Only the base layer of multi-layer streams is supported.
+        if header.nuh_layer_id().unwrap() > 0 {
+            return;
+        }
+        let unit_type = header.nal_unit_type();
+        let failure = match parsed {
+            ParsedNal::Unparsed => None,
+            ParsedNal::VideoParameterSet(r) => r.err().map(|e| format!("{:?}", e)),
+            ParsedNal::SeqParameterSet(Ok(sps)) => {
+                summary.bit_depth_luma = Some(sps.bit_depth_luma_minus8 + 8);
+                None
+            }
+            ParsedNal::SeqParameterSet(Err(e)) => Some(format!("{:?}", e)),
+            ParsedNal::PicParameterSet(Ok(pps)) => {
+                summary.tiles_enabled |= pps.tiles_enabled_flag();
+                summary.entropy_coding_sync_enabled |= pps.entropy_coding_sync_enabled_flag;
+                None
+            }
+            ParsedNal::PicParameterSet(Err(e)) => Some(format!("{:?}", e)),
+            ParsedNal::Sei(Ok(mut reader)) => loop {
+                match reader.next() {
+                    Ok(Some(_)) => {}
+                    Ok(None) => break None,
+                    Err(e) => break Some(format!("{:?}", e)),
+                }
+            },
+            ParsedNal::Sei(Err(e)) => Some(format!("{:?}", e)),
+            ParsedNal::SliceSegment(r) => r.err().map(|e| format!("{:?}", e)),
+        };
+        if let Some(failure) = failure {
+            summary
+                .failures
+                .push(format!("NAL unit {} ({:?}): {}", i, unit_type, failure));
+        }
+    };
+    let mut reader = AnnexBReader::accumulate(NalParser::new(ParseDepth::SliceHeaders, handler));
+    reader.push(data);
+    reader.reset();
+    drop(reader);
+    summary
+}
+
+fn bitstreams(dir: &Path, out: &mut Vec<PathBuf>) {
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            bitstreams(&path, out);
+        } else if path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("bit") || e.eq_ignore_ascii_case("bin"))
+        {
+            out.push(path);
+        }
+    }
+}
+
+/// Checks values the conformance bitstream names promise, e.g. `TILES_A_Cisco_3` uses tiles.
+fn check_known_values(name: &str, summary: &Summary) -> Option<String> {
+    if name.starts_with("TILES_") && !summary.tiles_enabled {
+        return Some("no PPS with tiles_enabled_flag".to_owned());
+    }
+    if name.starts_with("WPP_") && !summary.entropy_coding_sync_enabled {
+        return Some("no PPS with entropy_coding_sync_enabled_flag".to_owned());
+    }
+    if name.contains("MAIN10") && summary.bit_depth_luma != Some(10) {
+        return Some(format!("bit depth {:?}, not 10", summary.bit_depth_luma));
+    }
+    None
+}
+
+#[test]
+#[ignore = "needs HEVC_CONFORMANCE_DIR"]
+fn conformance_bitstreams() {
+    let dir = std::env::var_os("HEVC_CONFORMANCE_DIR")
+        .expect("HEVC_CONFORMANCE_DIR should name a directory of conformance bitstreams");
+    let mut paths = vec![];
+    bitstreams(Path::new(&dir), &mut paths);
+    paths.sort();
+    assert!(!paths.is_empty(), "no .bit or .bin files found");
+    let mut report = String::new();
+    let mut failed = 0;
+    for path in &paths {
+        let summary = parse(&std::fs::read(path).unwrap());
+        let name = path.file_name().unwrap().to_string_lossy();
+        let mut failures = summary.failures.clone();
+        if summary.nal_units == 0 {
+            failures.push("no NAL units".to_owned());
+        }
+        failures.extend(check_known_values(&name, &summary));
+        if !failures.is_empty() {
+            failed += 1;
+            report += &format!("\n{}: {} failures", path.display(), failures.len());
+            for failure in failures.iter().take(5) {
+                report += &format!("\n    {}", failure);
+            }
+        }
+    }
+    assert_eq!(
+        failed,
+        0,
+        "{} of {} bitstreams failed:{}",
+        failed,
+        paths.len(),
+        report
+    );
+}
+
+#[test]
+fn known_values() {
+    let summary = Summary {
+        bit_depth_luma: Some(8),
+        entropy_coding_sync_enabled: true,
+        ..Default::default()
+    };
+    assert_eq!(check_known_values("WPP_A_ericsson_MAIN_2", &summary), None);
+    assert!(check_known_values("WPP_A_ericsson_MAIN10_2", &summary).is_some());
+    assert!(check_known_values("TILES_A_Cisco_3", &summary).is_some());
+}